@@ -11,10 +11,15 @@ extern crate toml;
 
 extern crate which;
 
+use std::collections::BTreeMap;
 use std::env;
+#[cfg(windows)]
+use std::path::Path;
 use std::path::PathBuf;
 
 use std::fs::copy;
+#[cfg(windows)]
+use std::fs::write;
 use std::fs::File;
 
 use std::io::Read;
@@ -27,21 +32,188 @@ use std::env::consts::OS;
 pub struct BaseAttributes {
     pub name: String,
     pub target_url: String,
+    #[serde(default)]
+    pub version_info: VersionInfo,
 }
 
+/// What is stamped into the Windows executable's version resource, as shown in the
+/// file's properties and in SmartScreen dialogs.
+#[derive(Debug, Deserialize, Default)]
+pub struct VersionInfo {
+    /// Version of the product being installed (e.g. "1.2.3"), instead of the installer's
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub company: Option<String>,
+    #[serde(default)]
+    pub copyright: Option<String>,
+    /// Name the installer is published under, instead of "<name>_installer.exe"
+    #[serde(default)]
+    pub file_name: Option<String>,
+    /// Icon file, relative to the repository root
+    #[serde(default)]
+    pub icon: Option<String>,
+    /// File descriptions by language (e.g. "de"). English is used for anything else.
+    #[serde(default)]
+    pub descriptions: BTreeMap<String, String>,
+}
+
+/// Windows language identifiers for languages descriptions can be given in.
 #[cfg(windows)]
-fn handle_binary(config: &BaseAttributes) {
-    let mut res = winres::WindowsResource::new();
-    res.set_icon("ui/public/favicon.ico");
-    res.set(
-        "FileDescription",
-        &format!("Interactive installer for {}", config.name),
+const LANGUAGE_IDS: &[(&str, u16)] = &[
+    ("en", 0x0409),
+    ("de", 0x0407),
+    ("es", 0x0C0A),
+    ("fr", 0x040C),
+    ("it", 0x0410),
+    ("ja", 0x0411),
+    ("ko", 0x0412),
+    ("nl", 0x0413),
+    ("pl", 0x0415),
+    ("pt", 0x0416),
+    ("ru", 0x0419),
+    ("zh", 0x0804),
+];
+
+/// The UTF-16 code page, which string tables are given in.
+#[cfg(windows)]
+const UNICODE_CODE_PAGE: u16 = 1200;
+
+/// Escapes a string for use within a resource script.
+#[cfg(windows)]
+fn escape_rc(value: &str) -> String {
+    value.replace('"', "\"\"")
+}
+
+/// Turns a version such as "v1.2.3-beta" into the four numbers Windows expects.
+#[cfg(windows)]
+fn version_numbers(version: &str) -> [u16; 4] {
+    let mut numbers = [0; 4];
+    let version = version.trim_start_matches('v');
+    let version = version.split(|x| x == '-' || x == '+').next().unwrap_or("");
+
+    for (i, part) in version.split('.').take(4).enumerate() {
+        numbers[i] = part.parse().unwrap_or(0);
+    }
+
+    numbers
+}
+
+/// Writes a resource script containing the icon, and a version resource with a string
+/// table for each language a description is given in.
+#[cfg(windows)]
+fn version_resource(config: &BaseAttributes, root: &Path) -> String {
+    let info = &config.version_info;
+
+    let version = info
+        .version
+        .clone()
+        .unwrap_or_else(|| env::var("CARGO_PKG_VERSION").unwrap());
+    let numbers = version_numbers(&version);
+    let numbers = format!(
+        "{},{},{},{}",
+        numbers[0], numbers[1], numbers[2], numbers[3]
     );
-    res.set("ProductName", &format!("{} installer", config.name));
-    res.set(
-        "OriginalFilename",
-        &format!("{}_installer.exe", config.name),
+
+    let file_name = info
+        .file_name
+        .clone()
+        .unwrap_or_else(|| format!("{}_installer.exe", config.name));
+    let icon = root.join(
+        info.icon
+            .as_ref()
+            .map_or("ui/public/favicon.ico", |x| x.as_str()),
     );
+
+    let mut descriptions = vec![(
+        0x0409,
+        info.descriptions
+            .get("en")
+            .cloned()
+            .unwrap_or_else(|| format!("Interactive installer for {}", config.name)),
+    )];
+    for (language, description) in &info.descriptions {
+        if language == "en" {
+            continue;
+        }
+
+        match LANGUAGE_IDS.iter().find(|x| x.0 == language.as_str()) {
+            Some(&(_, id)) => descriptions.push((id, description.clone())),
+            None => panic!("Unknown language for file description: {:?}", language),
+        }
+    }
+
+    let mut tables = String::new();
+    let mut translations = Vec::new();
+    for (id, description) in descriptions {
+        let mut values = vec![
+            ("FileDescription", description),
+            ("FileVersion", version.clone()),
+            ("InternalName", config.name.clone()),
+            ("OriginalFilename", file_name.clone()),
+            ("ProductName", format!("{} installer", config.name)),
+            ("ProductVersion", version.clone()),
+        ];
+        if let Some(ref company) = info.company {
+            values.push(("CompanyName", company.clone()));
+        }
+        if let Some(ref copyright) = info.copyright {
+            values.push(("LegalCopyright", copyright.clone()));
+        }
+
+        tables.push_str(&format!(
+            "        BLOCK \"{:04X}{:04X}\"\n        BEGIN\n",
+            id, UNICODE_CODE_PAGE
+        ));
+        for (name, value) in values {
+            tables.push_str(&format!(
+                "            VALUE \"{}\", \"{}\"\n",
+                name,
+                escape_rc(&value)
+            ));
+        }
+        tables.push_str("        END\n");
+
+        translations.push(format!("0x{:04X}, {}", id, UNICODE_CODE_PAGE));
+    }
+
+    format!(
+        r#"#pragma code_page(65001)
+1 ICON "{icon}"
+
+1 VERSIONINFO
+FILEVERSION {numbers}
+PRODUCTVERSION {numbers}
+FILEOS 0x40004
+FILETYPE 0x1
+BEGIN
+    BLOCK "StringFileInfo"
+    BEGIN
+{tables}    END
+    BLOCK "VarFileInfo"
+    BEGIN
+        VALUE "Translation", {translations}
+    END
+END
+"#,
+        icon = escape_rc(&icon.to_string_lossy()).replace('\\', "\\\\"),
+        numbers = numbers,
+        tables = tables,
+        translations = translations.join(", "),
+    )
+}
+
+#[cfg(windows)]
+fn handle_binary(config: &BaseAttributes) {
+    let output_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let current_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+
+    let resource_file = output_dir.join("installer.rc");
+    write(&resource_file, version_resource(config, &current_dir))
+        .expect("Unable to write resource file");
+
+    let mut res = winres::WindowsResource::new();
+    res.set_resource_file(resource_file.to_str().expect("Unable to convert path"));
     res.compile().expect("Failed to generate metadata");
 
     cc::Build::new()
@@ -0,0 +1,84 @@
+//! announcements.rs
+//!
+//! Turns the stream of progress messages from an operation into a few announcements which
+//! are meant to be read out by screen readers: every quarter of the way through, whenever
+//! the operation moves on to another phase (downloading, extracting, ...), and when it
+//! finishes or fails. Reading out every progress message would be far too chatty.
+//!
+//! Announcements are published as events, which the frontend shows in a live region, and
+//! mirrors as native notifications if its window doesn't have focus.
+
+use events::{self, Event};
+
+use installer::InstallMessage;
+
+use strings;
+
+/// Progress (out of 100) at which milestones are announced. Reaching 100% is announced
+/// as the operation finishing instead.
+const MILESTONES: [u32; 3] = [25, 50, 75];
+
+/// Tracks a single operation's progress, announcing milestones as they are reached.
+#[derive(Default)]
+pub struct Announcer {
+    /// Index of the next milestone in `MILESTONES`
+    next_milestone: usize,
+    /// Id of the messages of the current phase
+    phase: Option<&'static str>,
+    failed: bool,
+}
+
+fn announce(message: String, urgent: bool) {
+    events::publish(Event::Announcement { message, urgent });
+}
+
+impl Announcer {
+    fn progress(&mut self, id: &'static str, message: &str, progress: f64) {
+        // Each message of a phase has the same id, e.g. for each file being deleted
+        if self.phase != Some(id) {
+            self.phase = Some(id);
+            announce(message.to_string(), false);
+        }
+
+        let percent = (progress * 100.0) as u32;
+        let mut reached = None;
+        while self.next_milestone < MILESTONES.len() && percent >= MILESTONES[self.next_milestone] {
+            reached = Some(MILESTONES[self.next_milestone]);
+            self.next_milestone += 1;
+        }
+
+        // Only the latest milestone is worth hearing if several were passed at once
+        if let Some(milestone) = reached {
+            announce(
+                strings::format(
+                    "announcements.milestone",
+                    &[("percent", &milestone.to_string())],
+                ),
+                false,
+            );
+        }
+    }
+
+    /// Announces anything of note in a message sent by the operation.
+    pub fn observe(&mut self, message: &InstallMessage) {
+        match *message {
+            InstallMessage::Status(ref message, progress, _, id) => {
+                self.progress(id, message, progress)
+            }
+            InstallMessage::Prompt(ref question, _)
+            | InstallMessage::DirectoryPrompt(ref question, _)
+            | InstallMessage::TextPrompt(ref question, _) => announce(question.clone(), true),
+            InstallMessage::Error(ref error) => {
+                self.failed = true;
+                announce(
+                    strings::format("announcements.failed", &[("error", error)]),
+                    true,
+                );
+            }
+            InstallMessage::EOF if !self.failed => {
+                announce(strings::tr("announcements.finished"), false)
+            }
+            _ => {}
+        }
+    }
+}
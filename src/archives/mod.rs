@@ -1,23 +1,149 @@
 //! Provides interfaces to various archives.
 
+pub mod multipart;
+pub mod nupkg;
+
+use zip::read::ZipFile;
 use zip::ZipArchive as UpstreamZipArchive;
 
 use tar::Archive as UpstreamTarArchive;
-use tar::EntryType;
+use tar::{Entry, EntryType};
 
+use std::borrow::Cow;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
 use std::io::Cursor;
 use std::io::Read;
 use std::iter::Iterator;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 
 use xz2::read::XzDecoder;
 
+use flate2::read::GzDecoder;
+
+/// Describes an entry being extracted, beyond its name and contents.
+pub struct EntryMetadata {
+    /// Unix permission bits, if the archive records them
+    pub mode: Option<u32>,
+    /// Where a symbolic link points. Links have no contents.
+    pub link_target: Option<PathBuf>,
+    /// Uncompressed size of the contents, if known
+    pub size: Option<u64>,
+    /// Size of the contents as stored, if the archive compresses entries individually
+    pub compressed_size: Option<u64>,
+}
+
 pub trait Archive<'a> {
-    /// func: iterator value, max size, file name, file contents
+    /// Calls func for each file and symbolic link in the archive.
+    ///
+    /// func: iterator value, max size, file name, metadata, file contents
     fn for_each(
         &mut self,
-        func: &mut dyn FnMut(usize, Option<usize>, PathBuf, &mut dyn Read) -> Result<(), String>,
+        func: &mut dyn FnMut(
+            usize,
+            Option<usize>,
+            PathBuf,
+            &EntryMetadata,
+            &mut dyn Read,
+        ) -> Result<(), String>,
     ) -> Result<(), String>;
+
+    /// Lists the names of all files in the archive, without extracting them.
+    fn list(&mut self) -> Result<Vec<PathBuf>, String>;
+}
+
+/// Unix file type bits for symbolic links, as stored in .zip files.
+const S_IFMT: u32 = 0o170_000;
+const S_IFLNK: u32 = 0o120_000;
+/// Permission bits which are kept from archives. setuid and friends are dropped.
+const PERMISSION_BITS: u32 = 0o777;
+
+fn split_path(name: &str) -> impl Iterator<Item = &str> {
+    name.split(|x| x == '/' || x == '\\')
+        .filter(|x| !x.is_empty() && *x != ".")
+}
+
+fn is_absolute(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    let drive = bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':';
+
+    drive
+        || name.starts_with('/')
+        || name.starts_with('\\')
+        || Path::new(name).components().any(|x| match x {
+            Component::Normal(_) | Component::CurDir | Component::ParentDir => false,
+            _ => true,
+        })
+}
+
+/// Checks that an entry's name is relative and doesn't traverse upwards, regardless of
+/// which separators the archive uses.
+fn check_path(name: &str) -> Result<PathBuf, String> {
+    if is_absolute(name) || split_path(name).any(|x| x == "..") {
+        return Err(format!(
+            "Refusing to extract archive, as {:?} would be written outside of the install \
+             directory",
+            name
+        ));
+    }
+
+    Ok(PathBuf::from(name))
+}
+
+/// Checks that a link entry doesn't point outside of the directory being extracted to.
+/// Targets are relative to the directory containing the link.
+fn check_link(name: &str, target: &str) -> Result<(), String> {
+    check_path(name)?;
+
+    let mut depth = split_path(name).count() as isize - 1;
+    let mut escapes = is_absolute(target);
+
+    for part in split_path(target) {
+        depth += if part == ".." { -1 } else { 1 };
+        escapes |= depth < 0;
+    }
+
+    if escapes {
+        return Err(format!(
+            "Refusing to extract archive, as the link {:?} points outside of the install \
+             directory ({:?})",
+            name, target
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks a .zip entry, returning its name if it is a file or link to extract.
+fn check_zip_entry(entry: &mut ZipFile) -> Result<Option<(PathBuf, EntryMetadata)>, String> {
+    let name = entry.name().to_string();
+
+    if name.ends_with('/') || name.ends_with('\\') {
+        return Ok(None);
+    }
+
+    let mut metadata = EntryMetadata {
+        mode: entry.unix_mode().map(|x| x & PERMISSION_BITS),
+        link_target: None,
+        size: Some(entry.size()),
+        compressed_size: Some(entry.compressed_size()),
+    };
+
+    // Links are stored as files containing their target
+    if entry.unix_mode().map_or(false, |x| x & S_IFMT == S_IFLNK) {
+        let mut target = String::new();
+        entry
+            .read_to_string(&mut target)
+            .map_err(|v| format!("Error while reading from .zip file: {:?}", v))?;
+
+        check_link(&name, &target)?;
+        metadata.link_target = Some(PathBuf::from(target));
+    } else {
+        check_path(&name)?;
+    }
+
+    Ok(Some((entry.sanitized_name(), metadata)))
 }
 
 struct ZipArchive<'a> {
@@ -27,7 +153,13 @@ struct ZipArchive<'a> {
 impl<'a> Archive<'a> for ZipArchive<'a> {
     fn for_each(
         &mut self,
-        func: &mut dyn FnMut(usize, Option<usize>, PathBuf, &mut dyn Read) -> Result<(), String>,
+        func: &mut dyn FnMut(
+            usize,
+            Option<usize>,
+            PathBuf,
+            &EntryMetadata,
+            &mut dyn Read,
+        ) -> Result<(), String>,
     ) -> Result<(), String> {
         let max = self.archive.len();
 
@@ -37,28 +169,104 @@ impl<'a> Archive<'a> for ZipArchive<'a> {
                 .by_index(i)
                 .map_err(|v| format!("Error while reading from .zip file: {:?}", v))?;
 
-            if archive.name().ends_with('/') || archive.name().ends_with('\\') {
-                continue;
+            if let Some((path, metadata)) = check_zip_entry(&mut archive)? {
+                func(i, Some(max), path, &metadata, &mut archive)?;
             }
-
-            func(i, Some(max), archive.sanitized_name(), &mut archive)?;
         }
 
         Ok(())
     }
+
+    fn list(&mut self) -> Result<Vec<PathBuf>, String> {
+        let mut names = Vec::new();
+
+        for i in 0..self.archive.len() {
+            let mut archive = self
+                .archive
+                .by_index(i)
+                .map_err(|v| format!("Error while reading from .zip file: {:?}", v))?;
+
+            names.extend(check_zip_entry(&mut archive)?.map(|x| x.0));
+        }
+
+        Ok(names)
+    }
+}
+
+/// Checks a .tar entry, returning its name if it is a file or symbolic link to extract.
+fn check_tar_entry<'a, R: 'a + Read>(
+    entry: &Entry<'a, R>,
+) -> Result<Option<(PathBuf, EntryMetadata)>, String> {
+    let path = entry
+        .path()
+        .map_err(|v| format!("Failed to read entry from .tar file: {:?}", v))?;
+    let name = path.to_str().ok_or("Unable to get str from file name")?;
+
+    let target = entry
+        .link_name()
+        .map_err(|v| format!("Failed to read entry from .tar file: {:?}", v))?;
+    let target = match target {
+        Some(ref v) => Some(v.to_str().ok_or("Unable to get str from link target")?),
+        None => None,
+    };
+
+    let mode = entry.header().mode().ok().map(|x| x & PERMISSION_BITS);
+
+    // Hard links aren't extracted, but still shouldn't point anywhere unexpected
+    match (entry.header().entry_type(), target) {
+        (EntryType::Regular, _) => Ok(Some((
+            check_path(name)?,
+            EntryMetadata {
+                mode,
+                link_target: None,
+                size: entry.header().size().ok(),
+                compressed_size: None,
+            },
+        ))),
+        (EntryType::Symlink, Some(target)) => {
+            check_link(name, target)?;
+            Ok(Some((
+                check_path(name)?,
+                EntryMetadata {
+                    mode,
+                    link_target: Some(PathBuf::from(target)),
+                    size: None,
+                    compressed_size: None,
+                },
+            )))
+        }
+        (EntryType::Link, Some(target)) => {
+            check_path(name)?;
+            check_path(target).map(|_| None)
+        }
+        _ => Ok(None),
+    }
 }
 
+/// Tar archives are held uncompressed, so that they can be read more than once.
 struct TarArchive<'a> {
-    archive: UpstreamTarArchive<Box<dyn Read + 'a>>,
+    data: Cow<'a, [u8]>,
+}
+
+impl<'a> TarArchive<'a> {
+    fn archive(&self) -> UpstreamTarArchive<Cursor<&[u8]>> {
+        UpstreamTarArchive::new(Cursor::new(&self.data[..]))
+    }
 }
 
 impl<'a> Archive<'a> for TarArchive<'a> {
     fn for_each(
         &mut self,
-        func: &mut dyn FnMut(usize, Option<usize>, PathBuf, &mut dyn Read) -> Result<(), String>,
+        func: &mut dyn FnMut(
+            usize,
+            Option<usize>,
+            PathBuf,
+            &EntryMetadata,
+            &mut dyn Read,
+        ) -> Result<(), String>,
     ) -> Result<(), String> {
-        let entries = self
-            .archive
+        let mut archive = self.archive();
+        let entries = archive
             .entries()
             .map_err(|x| format!("Error while reading .tar file: {:?}", x))?;
 
@@ -66,44 +274,152 @@ impl<'a> Archive<'a> for TarArchive<'a> {
             let mut entry =
                 entry.map_err(|v| format!("Failed to read entry from .tar file: {:?}", v))?;
 
-            if entry.header().entry_type() != EntryType::Regular {
-                continue;
+            if let Some((path, metadata)) = check_tar_entry(&entry)? {
+                func(i, None, path, &metadata, &mut entry)?;
             }
+        }
 
-            let path = entry
-                .path()
-                .map(PathBuf::from)
-                .map_err(|v| format!("Failed to read entry from .tar file: {:?}", v))?;
+        Ok(())
+    }
 
-            func(i, None, path, &mut entry)?;
+    fn list(&mut self) -> Result<Vec<PathBuf>, String> {
+        let mut names = Vec::new();
+
+        let mut archive = self.archive();
+        let entries = archive
+            .entries()
+            .map_err(|x| format!("Error while reading .tar file: {:?}", x))?;
+
+        for entry in entries {
+            let entry =
+                entry.map_err(|v| format!("Failed to read entry from .tar file: {:?}", v))?;
+
+            names.extend(check_tar_entry(&entry)?.map(|x| x.0));
         }
 
-        Ok(())
+        Ok(names)
+    }
+}
+
+/// The formats archives can be read from.
+#[derive(Debug, PartialEq)]
+enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+    TarXz,
+}
+
+/// Works out an archive's format from its contents, falling back to its file name.
+fn detect_format(name: &str, data: &[u8]) -> Option<ArchiveFormat> {
+    if data.starts_with(b"PK\x03\x04") || data.starts_with(b"PK\x05\x06") {
+        return Some(ArchiveFormat::Zip);
+    } else if data.starts_with(&[0x1F, 0x8B]) {
+        return Some(ArchiveFormat::TarGz);
+    } else if data.starts_with(&[0xFD, b'7', b'z', b'X', b'Z', 0x00]) {
+        return Some(ArchiveFormat::TarXz);
+    } else if data.len() > 262 && &data[257..262] == b"ustar" {
+        return Some(ArchiveFormat::Tar);
+    }
+
+    // Old-style .tar files have no magic number
+    let name = name.to_lowercase();
+    if name.ends_with(".zip") {
+        Some(ArchiveFormat::Zip)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(ArchiveFormat::TarGz)
+    } else if name.ends_with(".tar.xz") || name.ends_with(".txz") {
+        Some(ArchiveFormat::TarXz)
+    } else if name.ends_with(".tar") {
+        Some(ArchiveFormat::Tar)
+    } else {
+        None
     }
 }
 
+/// Decompresses a downloaded .tar.gz or .tar.xz file in place, so that only the .tar file
+/// itself needs reading once installed. Returns if there was anything to decompress.
+pub fn decompress_file(name: &str, path: &Path) -> Result<bool, String> {
+    let mut header = Vec::new();
+    fs::File::open(path)
+        .and_then(|x| x.take(512).read_to_end(&mut header))
+        .map_err(|x| format!("Unable to read {:?}: {:?}", path, x))?;
+
+    let format = detect_format(name, &header);
+    if format != Some(ArchiveFormat::TarGz) && format != Some(ArchiveFormat::TarXz) {
+        return Ok(false);
+    }
+
+    let source = fs::File::open(path).map_err(|x| format!("Unable to read {:?}: {:?}", path, x))?;
+    let mut decompresser: Box<dyn Read> = if format == Some(ArchiveFormat::TarGz) {
+        Box::new(GzDecoder::new(source))
+    } else {
+        Box::new(XzDecoder::new(source))
+    };
+
+    let mut temp = OsString::from(path.as_os_str());
+    temp.push(".tar");
+    let temp = PathBuf::from(temp);
+
+    let result = fs::File::create(&temp)
+        .and_then(|mut x| io::copy(&mut decompresser, &mut x))
+        .map_err(|x| format!("Failed to decompress {:?}: {:?}", path, x))
+        .and_then(|_| {
+            fs::rename(&temp, path).map_err(|x| format!("Unable to replace {:?}: {:?}", path, x))
+        });
+
+    // The compressed file is still there to be read as normal
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+
+    result.map(|_| true)
+}
+
 /// Reads the named archive with an archive implementation.
 pub fn read_archive<'a>(name: &str, data: &'a [u8]) -> Result<Box<dyn Archive<'a> + 'a>, String> {
-    if name.ends_with(".zip") {
-        // Decompress a .zip file
-        let archive = UpstreamZipArchive::new(Cursor::new(data))
-            .map_err(|x| format!("Error while reading .zip file: {:?}", x))?;
+    if name.to_lowercase().ends_with(".nupkg") {
+        return nupkg::read(data);
+    }
 
-        Ok(Box::new(ZipArchive { archive }))
-    } else if name.ends_with(".tar.xz") {
-        // Decompress a .tar.xz file
-        let mut decompresser = XzDecoder::new(data);
-        let mut decompressed_data = Vec::new();
-        decompresser
-            .read_to_end(&mut decompressed_data)
-            .map_err(|x| format!("Failed to decompress data: {:?}", x))?;
+    match detect_format(name, data) {
+        Some(ArchiveFormat::Zip) => {
+            // Decompress a .zip file
+            let archive = UpstreamZipArchive::new(Cursor::new(data))
+                .map_err(|x| format!("Error while reading .zip file: {:?}", x))?;
 
-        let decompressed_contents: Box<dyn Read> = Box::new(Cursor::new(decompressed_data));
+            Ok(Box::new(ZipArchive { archive }))
+        }
+        Some(ArchiveFormat::TarGz) => {
+            // Decompress a .tar.gz file
+            let mut decompresser = GzDecoder::new(data);
+            let mut decompressed_data = Vec::new();
+            decompresser
+                .read_to_end(&mut decompressed_data)
+                .map_err(|x| format!("Failed to decompress data: {:?}", x))?;
 
-        let tar = UpstreamTarArchive::new(decompressed_contents);
+            Ok(Box::new(TarArchive {
+                data: Cow::Owned(decompressed_data),
+            }))
+        }
+        Some(ArchiveFormat::TarXz) => {
+            // Decompress a .tar.xz file
+            let mut decompresser = XzDecoder::new(data);
+            let mut decompressed_data = Vec::new();
+            decompresser
+                .read_to_end(&mut decompressed_data)
+                .map_err(|x| format!("Failed to decompress data: {:?}", x))?;
 
-        Ok(Box::new(TarArchive { archive: tar }))
-    } else {
-        Err(format!("No decompression handler for {:?}.", name))
+            Ok(Box::new(TarArchive {
+                data: Cow::Owned(decompressed_data),
+            }))
+        }
+        Some(ArchiveFormat::Tar) => {
+            // Uncompressed .tar files, e.g. reassembled differential updates
+            Ok(Box::new(TarArchive {
+                data: Cow::Borrowed(data),
+            }))
+        }
+        None => Err(format!("No decompression handler for {:?}.", name)),
     }
 }
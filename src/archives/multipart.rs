@@ -0,0 +1,113 @@
+//! Reassembles archives which have been published as multiple parts
+//! (e.g. `app.zip.001`, `app.zip.002` or `app.zip.part1`, `app.zip.part2`).
+//!
+//! Parts are published without hashes of their own, so the reassembled archive is
+//! checked against a checksum published alongside as `app.zip.sha256` (in the style of
+//! `sha256sum`) before it is extracted, if there is one.
+
+use regex::Regex;
+
+use sources::types::File;
+
+lazy_static! {
+    /// `app.zip.001` style. The base is restricted to archive types as to not confuse
+    /// versioned file names (`app-1.2.3`) with parts.
+    static ref SUFFIX_PART: Regex =
+        Regex::new(r"(?i)^(.+\.(?:zip|tar|tar\.gz|tar\.xz|tgz|txz))\.(?:part)?([0-9]+)$")
+            .unwrap();
+    /// `app.part1.zip` style
+    static ref INFIX_PART: Regex = Regex::new(r"(?i)^(.+)\.part([0-9]+)(\.[a-z0-9.]+)$").unwrap();
+}
+
+/// Splits a file name into the name of the logical archive and the part index, if this
+/// is a part of a multi-part archive.
+pub fn parse_part_name(name: &str) -> Option<(String, u32)> {
+    if let Some(captures) = SUFFIX_PART.captures(name) {
+        return Some((captures[1].to_string(), captures[2].parse().ok()?));
+    }
+
+    if let Some(captures) = INFIX_PART.captures(name) {
+        return Some((
+            format!("{}{}", &captures[1], &captures[3]),
+            captures[2].parse().ok()?,
+        ));
+    }
+
+    None
+}
+
+/// Given a file matched in a release, finds all sibling parts of the same archive and merges
+/// them into a single logical file. Non-multi-part files are returned as-is.
+pub fn assemble(file: &File, release_files: &[File]) -> Result<File, String> {
+    let base_name = match parse_part_name(&file.name) {
+        Some((base_name, _)) => base_name,
+        None => return Ok(file.clone()),
+    };
+
+    let mut parts: Vec<(u32, &File)> = release_files
+        .iter()
+        .filter_map(|x| match parse_part_name(&x.name) {
+            Some((name, index)) => {
+                if name == base_name {
+                    Some((index, x))
+                } else {
+                    None
+                }
+            }
+            None => None,
+        })
+        .collect();
+
+    if parts.is_empty() {
+        return Ok(file.clone());
+    }
+
+    parts.sort_by_key(|x| x.0);
+
+    // Part numbers have to start at 0 or 1, and be contiguous
+    let first_index = parts[0].0;
+    if first_index > 1 {
+        return Err(format!(
+            "Multi-part archive {:?} is missing its first part",
+            base_name
+        ));
+    }
+
+    for (i, (index, _)) in parts.iter().enumerate() {
+        if *index != first_index + i as u32 {
+            return Err(format!(
+                "Multi-part archive {:?} is missing part {}",
+                base_name,
+                first_index + i as u32
+            ));
+        }
+    }
+
+    info!("Reassembling {:?} from {} parts", base_name, parts.len());
+
+    let manifest_name = format!("{}.sha256", base_name).to_lowercase();
+    let checksums = release_files
+        .iter()
+        .find(|x| x.name.to_lowercase() == manifest_name)
+        .map(|x| x.url.clone());
+    if checksums.is_none() {
+        warn!(
+            "No checksums were published for {:?}, so it can't be checked once reassembled",
+            base_name
+        );
+    }
+
+    Ok(File {
+        name: base_name,
+        url: parts[0].1.url.clone(),
+        requires_authorization: parts.iter().any(|x| x.1.requires_authorization),
+        parts: parts[1..].iter().map(|x| x.1.url.clone()).collect(),
+        chunk_index: None,
+        checksums,
+        sha1: None,
+        signature: None,
+        size: parts.iter().map(|x| x.1.size).sum(),
+        content_type: None,
+        torrent: None,
+    })
+}
@@ -0,0 +1,71 @@
+//! Reads Squirrel packages (`.nupkg`), which are .zip files holding the application
+//! within `lib/net45`, alongside NuGet's own metadata.
+
+use std::io::Cursor;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use zip::ZipArchive as UpstreamZipArchive;
+
+use archives::{Archive, EntryMetadata, ZipArchive};
+
+/// Where the application is kept within a package.
+const APPLICATION_DIR: &str = "lib/net45/";
+
+/// Returns where an entry goes, if it is part of the application rather than metadata.
+fn application_path(path: &Path) -> Option<PathBuf> {
+    let name = path.to_string_lossy().replace('\\', "/");
+
+    let is_application = name.len() > APPLICATION_DIR.len()
+        && name
+            .get(..APPLICATION_DIR.len())
+            .map_or(false, |x| x.eq_ignore_ascii_case(APPLICATION_DIR));
+    if is_application {
+        Some(PathBuf::from(&name[APPLICATION_DIR.len()..]))
+    } else {
+        None
+    }
+}
+
+pub struct NupkgArchive<'a> {
+    archive: ZipArchive<'a>,
+}
+
+impl<'a> Archive<'a> for NupkgArchive<'a> {
+    fn for_each(
+        &mut self,
+        func: &mut dyn FnMut(
+            usize,
+            Option<usize>,
+            PathBuf,
+            &EntryMetadata,
+            &mut dyn Read,
+        ) -> Result<(), String>,
+    ) -> Result<(), String> {
+        self.archive.for_each(
+            &mut |i, max, path, metadata, file| match application_path(&path) {
+                Some(path) => func(i, max, path, metadata, file),
+                None => Ok(()),
+            },
+        )
+    }
+
+    fn list(&mut self) -> Result<Vec<PathBuf>, String> {
+        Ok(self
+            .archive
+            .list()?
+            .into_iter()
+            .filter_map(|x| application_path(&x))
+            .collect())
+    }
+}
+
+/// Reads a Squirrel package.
+pub fn read<'a>(data: &'a [u8]) -> Result<Box<dyn Archive<'a> + 'a>, String> {
+    let archive = UpstreamZipArchive::new(Cursor::new(data))
+        .map_err(|x| format!("Error while reading .nupkg file: {:?}", x))?;
+
+    Ok(Box::new(NupkgArchive {
+        archive: ZipArchive { archive },
+    }))
+}
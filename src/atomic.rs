@@ -0,0 +1,111 @@
+//! atomic.rs
+//!
+//! Replaces files such that a crash or power loss part way through leaves either the
+//! previous or the new contents behind, never a truncated file. The previous contents
+//! are kept as a backup, for recovering from files which were corrupted some other way.
+
+use std::ffi::OsString;
+use std::fs::{copy, metadata, remove_file, rename, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a lock is waited for, before giving up.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+/// Locks older than this were left behind by a process which didn't get to release them.
+const STALE_LOCK_AGE: Duration = Duration::from_secs(60);
+
+/// Appends an extension to a path, e.g. `metadata.json` to `metadata.json.bak`.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name: OsString = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Where the previous contents of a file are kept.
+pub fn backup_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".bak")
+}
+
+/// A lock on a file, shared with other processes, which is released when dropped.
+pub struct FileLock(PathBuf);
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        if let Err(v) = remove_file(&self.0) {
+            warn!("Unable to release lock {:?}: {:?}", self.0, v);
+        }
+    }
+}
+
+/// Waits to lock a file, so that it can be read, changed and written again without
+/// losing what other processes write to it at the same time. This works by creating
+/// `<file>.lock`, so only keeps out processes which lock it too.
+pub fn lock(path: &Path) -> io::Result<FileLock> {
+    let lock_path = with_suffix(path, ".lock");
+    let start = Instant::now();
+
+    loop {
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+        {
+            Ok(_) => return Ok(FileLock(lock_path)),
+            Err(ref v) if v.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(v) => return Err(v),
+        }
+
+        let is_stale = metadata(&lock_path)
+            .and_then(|x| x.modified())
+            .ok()
+            .and_then(|x| x.elapsed().ok())
+            .map_or(false, |x| x > STALE_LOCK_AGE);
+        if is_stale {
+            warn!("Removing stale lock {:?}", lock_path);
+            remove_file(&lock_path)?;
+            continue;
+        }
+
+        if start.elapsed() > LOCK_TIMEOUT {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("{:?} is locked by another process", path),
+            ));
+        }
+
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Writes a file via a temporary file, which is flushed to disk and then renamed over
+/// the original.
+pub fn write(path: &Path, data: &[u8]) -> io::Result<()> {
+    let temp_path = with_suffix(path, ".tmp");
+
+    {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+    }
+
+    // Copied rather than moved, so that the original stays in place until replaced
+    if path.exists() {
+        copy(path, backup_path(path))?;
+    }
+
+    rename(&temp_path, path)?;
+
+    // Renames aren't durable until the directory itself has been flushed
+    #[cfg(unix)]
+    {
+        if let Some(parent) = path.parent() {
+            if let Err(v) = File::open(parent).and_then(|x| x.sync_all()) {
+                warn!("Unable to flush {:?}: {:?}", parent, v);
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,347 @@
+//! audit.rs
+//!
+//! An append-only log of privileged actions taken by the installer (registry changes,
+//! system accounts, files written outside the install directory, ...), for enterprise
+//! compliance reviews. Recording is enabled through the configuration's `audit` section.
+//!
+//! The log holds one JSON entry per line. Each entry contains the hash of the entry before
+//! it, and is hashed along with it, so that entries which are edited, removed or reordered
+//! break the chain from there on. This only makes tampering evident - anyone able to write
+//! the log can still rebuild the whole chain.
+//!
+//! Failures to record are logged, but never interrupt the installation.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::Local;
+
+use dirs::data_local_dir;
+
+use hashing::sha256_hex;
+
+use installer::{InstallationDatabase, InstallerFramework};
+
+use locations;
+
+use logging::LoggingErrors;
+
+use native;
+
+/// The hash before the first entry.
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Where the end of the log was when last appended to.
+struct LastEntry {
+    path: PathBuf,
+    /// Length of the log after the entry was written
+    length: u64,
+    sequence: u64,
+    hash: String,
+}
+
+lazy_static! {
+    /// Saves reading the whole log for every entry appended
+    static ref LAST_ENTRY: Mutex<Option<LastEntry>> = Mutex::new(None);
+}
+
+/// Returns where the log is kept by default: in the machine-wide data directory, unless
+/// this is a per-user install by someone who can't write there.
+pub fn default_path(product: &str, all_users: bool) -> Result<PathBuf, String> {
+    let dir = if all_users || native::is_elevated() {
+        locations::machine_data_dir()
+    } else {
+        data_local_dir()
+    }
+    .ok_or("Unable to find a directory to keep the audit log in")?;
+
+    let name: String = product
+        .chars()
+        .map(|x| {
+            if x.is_alphanumeric() || x == '-' {
+                x
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    Ok(dir
+        .join("liftinstall")
+        .join("audit")
+        .join(format!("{}.log", name)))
+}
+
+/// A privileged action taken by the installer.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum AuditAction {
+    /// Files written outside the install directory, as absolute paths
+    FilesWritten {
+        package: Option<String>,
+        files: Vec<String>,
+    },
+    /// Files removed from outside the install directory, as absolute paths
+    FilesRemoved {
+        package: Option<String>,
+        files: Vec<String>,
+    },
+    /// Values written to a registry key
+    RegistryWritten {
+        key: String,
+        values: Vec<String>,
+    },
+    RegistryDeleted {
+        key: String,
+    },
+    AccountCreated {
+        package: String,
+        name: String,
+        is_group: bool,
+    },
+    AccountRemoved {
+        name: String,
+        is_group: bool,
+    },
+    LinkCreated {
+        package: String,
+        path: String,
+        target: String,
+    },
+    LinkRemoved {
+        path: String,
+        target: String,
+    },
+    OwnershipChanged {
+        path: String,
+        owner: String,
+    },
+    /// A command run on behalf of a package, such as a hook
+    CommandRun {
+        package: String,
+        command: String,
+    },
+    /// Changes made by a failed install were undone
+    RolledBack {
+        error: String,
+    },
+}
+
+/// A single entry in the audit log.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AuditEntry {
+    /// Position in the log, starting from 0
+    pub sequence: u64,
+    pub timestamp: String,
+    /// Who the installer was running as
+    pub user: String,
+    pub elevated: bool,
+    pub action: AuditAction,
+    /// Hash of the entry before this one
+    pub previous_hash: String,
+    /// Hash of this entry (without this field) along with `previous_hash`
+    #[serde(default)]
+    pub hash: String,
+}
+
+impl AuditEntry {
+    /// Computes what this entry's hash should be.
+    fn compute_hash(&self) -> Result<String, String> {
+        let mut unhashed = self.clone();
+        unhashed.hash = String::new();
+
+        let json = serde_json::to_string(&unhashed)
+            .map_err(|x| format!("Unable to render audit entry: {:?}", x))?;
+
+        Ok(sha256_hex(
+            format!("{}{}", self.previous_hash, json).as_bytes(),
+        ))
+    }
+}
+
+/// Reads every entry of an audit log, checking that the chain of hashes is intact.
+/// Returns the entries which could be read, along with the first problem found.
+pub fn verify(path: &Path) -> (Vec<AuditEntry>, Option<String>) {
+    let contents = match fs::read_to_string(path) {
+        Ok(v) => v,
+        Err(v) => {
+            return (
+                Vec::new(),
+                Some(format!("Unable to read audit log {:?}: {:?}", path, v)),
+            )
+        }
+    };
+
+    let mut entries: Vec<AuditEntry> = Vec::new();
+    let mut previous_hash = GENESIS_HASH.to_string();
+
+    for (i, line) in contents
+        .lines()
+        .filter(|x| !x.trim().is_empty())
+        .enumerate()
+    {
+        let entry: AuditEntry = match serde_json::from_str(line) {
+            Ok(v) => v,
+            Err(v) => return (entries, Some(format!("Entry {} is invalid: {:?}", i, v))),
+        };
+
+        if entry.sequence != i as u64 || entry.previous_hash != previous_hash {
+            return (
+                entries,
+                Some(format!(
+                    "Entry {} doesn't follow on from the entry before it",
+                    i
+                )),
+            );
+        }
+
+        match entry.compute_hash() {
+            Ok(ref v) if *v == entry.hash => {}
+            Ok(_) => return (entries, Some(format!("Entry {} has been modified", i))),
+            Err(v) => return (entries, Some(v)),
+        }
+
+        previous_hash = entry.hash.clone();
+        entries.push(entry);
+    }
+
+    (entries, None)
+}
+
+/// Returns the last entry of an audit log, if it has any.
+fn last_entry(path: &Path) -> Result<Option<AuditEntry>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let contents = fs::read_to_string(path)
+        .map_err(|x| format!("Unable to read audit log {:?}: {:?}", path, x))?;
+
+    match contents.lines().rev().find(|x| !x.trim().is_empty()) {
+        Some(line) => serde_json::from_str(line)
+            .map(Some)
+            .map_err(|x| format!("Last entry of audit log {:?} is invalid: {:?}", path, x)),
+        None => Ok(None),
+    }
+}
+
+/// Appends an action to the audit log at the specified path.
+fn append(path: &Path, action: AuditAction) -> Result<(), String> {
+    let mut cached = LAST_ENTRY
+        .lock()
+        .log_expect("Audit log state has been dirtied");
+
+    // The log may have been appended to by another copy of the installer since
+    let length = fs::metadata(path).map(|x| x.len()).ok();
+    let cached_entry = cached
+        .as_ref()
+        .filter(|x| x.path == path && Some(x.length) == length)
+        .map(|x| (x.sequence, x.hash.clone()));
+
+    let last = match cached_entry {
+        Some(v) => Some(v),
+        None => last_entry(path)?.map(|x| (x.sequence, x.hash)),
+    };
+    let (sequence, previous_hash) = match last {
+        Some((sequence, hash)) => (sequence + 1, hash),
+        None => (0, GENESIS_HASH.to_string()),
+    };
+
+    let mut entry = AuditEntry {
+        sequence,
+        timestamp: Local::now().to_rfc3339(),
+        user: native::get_current_user(),
+        elevated: native::is_elevated(),
+        action,
+        previous_hash,
+        hash: String::new(),
+    };
+    entry.hash = entry.compute_hash()?;
+
+    let mut line = serde_json::to_string(&entry)
+        .map_err(|x| format!("Unable to render audit entry: {:?}", x))?;
+    line.push('\n');
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|x| format!("Unable to create directory for audit log: {:?}", x))?;
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|x| format!("Unable to open audit log {:?}: {:?}", path, x))?;
+
+    file.write_all(line.as_bytes())
+        .and_then(|_| file.sync_all())
+        .map_err(|x| format!("Unable to write to audit log {:?}: {:?}", path, x))?;
+
+    *cached = fs::metadata(path).ok().map(|x| LastEntry {
+        path: path.to_owned(),
+        length: x.len(),
+        sequence: entry.sequence,
+        hash: entry.hash,
+    });
+
+    Ok(())
+}
+
+/// Records a privileged action, if this installation keeps an audit log.
+pub fn record(context: &InstallerFramework, action: AuditAction) {
+    let path = match context.database.audit_log {
+        Some(ref v) => Path::new(v).to_owned(),
+        None => return,
+    };
+
+    if let Err(v) = append(&path, action) {
+        error!("Unable to record privileged action: {}", v);
+    }
+}
+
+/// An audit log as exported for review.
+#[derive(Serialize)]
+struct AuditExport {
+    product: String,
+    exported: String,
+    path: String,
+    /// If the chain of hashes is intact
+    verified: bool,
+    /// The first problem found with the chain, if any
+    problem: Option<String>,
+    entries: Vec<AuditEntry>,
+}
+
+/// Writes an installation's audit log to `output` as a single JSON document, after
+/// checking the chain of hashes. Entries are exported up to the first problem found,
+/// which is returned as an error once the export has been written.
+pub fn export(product: &str, database: &InstallationDatabase, output: &Path) -> Result<(), String> {
+    let path = database
+        .audit_log
+        .as_ref()
+        .ok_or("This installation doesn't keep an audit log")?;
+
+    let (entries, problem) = verify(Path::new(path));
+
+    let export = AuditExport {
+        product: product.to_string(),
+        exported: Local::now().to_rfc3339(),
+        path: path.clone(),
+        verified: problem.is_none(),
+        problem: problem.clone(),
+        entries,
+    };
+
+    let json = serde_json::to_string_pretty(&export)
+        .map_err(|x| format!("Unable to render audit log: {:?}", x))?;
+    fs::write(output, json).map_err(|x| format!("Unable to write {:?}: {:?}", output, x))?;
+
+    match problem {
+        Some(v) => Err(format!(
+            "Audit log {:?} has been tampered with: {}",
+            path, v
+        )),
+        None => Ok(()),
+    }
+}
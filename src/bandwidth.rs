@@ -0,0 +1,333 @@
+//! bandwidth.rs
+//!
+//! Keeps track of how much has been downloaded, both by this session and by every
+//! session this month, so that users on capped connections can see what installing and
+//! updating costs them. A monthly data budget can be set, in which case downloads which
+//! would use up a large part of what is left are warned about before they start.
+//!
+//! Usage and the budget are kept per user, outside of any one installation, as a cap
+//! applies to everything the user downloads. Several installers may be running at once,
+//! so what each has downloaded is added to the saved usage under a lock. Elevated copies
+//! record into the usage of the user who started them, when handed it (see
+//! `elevation_args`), and otherwise only keep track of their own session, rather than
+//! recording into the administrator's.
+//!
+//! Downloads the budget warns about are confirmed before they start (see `confirm`),
+//! whichever way the operation was started.
+
+use std::collections::BTreeMap;
+use std::fs::{create_dir_all, read};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+
+use chrono::Local;
+
+use dirs::data_local_dir;
+
+use atomic;
+
+use disk_space::pretty_size;
+
+use installer::InstallerFramework;
+
+use logging::LoggingErrors;
+
+use native;
+
+use prompts;
+
+use strings;
+
+use tasks::TaskMessage;
+
+/// How much can be downloaded before usage is saved again, in bytes. Usage is also saved
+/// once each operation finishes, so this only bounds what is lost to a crash.
+const SAVE_INTERVAL: u64 = 16 * 1024 * 1024;
+/// How many months of usage are kept.
+const MONTHS_KEPT: usize = 12;
+/// Downloads which use more than this part of what is left of the budget are warned
+/// about, even if they fit within it.
+const LARGE_DOWNLOAD_RATIO: f64 = 0.25;
+
+/// Usage as saved between sessions.
+#[derive(Serialize, Deserialize, Default)]
+struct Usage {
+    /// Bytes downloaded each month, by "YYYY-MM"
+    #[serde(default)]
+    months: BTreeMap<String, u64>,
+    /// Bytes which may be downloaded each month, if the user has set a budget
+    #[serde(default)]
+    monthly_budget: Option<u64>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Usage as last read, along with what has been recorded since
+    usage: Option<Usage>,
+    session: u64,
+    /// Bytes recorded since usage was last saved, by "YYYY-MM"
+    unsaved_months: BTreeMap<String, u64>,
+    /// Bytes recorded since saving was last tried
+    unsaved: u64,
+    /// The budget set since usage was last saved, if it has been
+    new_budget: Option<Option<u64>>,
+}
+
+lazy_static! {
+    static ref STATE: Mutex<State> = Mutex::new(State::default());
+    /// Where usage is kept, if handed over from the copy which started this one
+    static ref HANDED_OVER_PATH: RwLock<Option<PathBuf>> = RwLock::new(None);
+}
+
+/// What has been downloaded, compared to the budget.
+#[derive(Serialize)]
+pub struct BandwidthSummary {
+    pub session: u64,
+    pub session_text: String,
+    pub this_month: u64,
+    pub this_month_text: String,
+    /// Usage of previous months (as well as this one), by "YYYY-MM"
+    pub months: BTreeMap<String, u64>,
+    pub monthly_budget: Option<u64>,
+    pub monthly_budget_text: Option<String>,
+    /// What is left of this month's budget, if a budget is set
+    pub remaining: Option<u64>,
+    pub remaining_text: Option<String>,
+}
+
+/// Records into the usage at `path`, as handed over by the copy which started this one.
+pub fn set_usage_path(path: &Path) {
+    *HANDED_OVER_PATH
+        .write()
+        .log_expect("Bandwidth state has been dirtied") = Some(path.to_owned());
+}
+
+/// Where usage is saved. Elevated copies which weren't handed this may be running as
+/// someone else entirely, so don't save any.
+pub fn usage_path() -> Option<PathBuf> {
+    let handed_over = HANDED_OVER_PATH
+        .read()
+        .log_expect("Bandwidth state has been dirtied")
+        .clone();
+
+    if handed_over.is_some() {
+        handed_over
+    } else if native::is_elevated() {
+        None
+    } else {
+        data_local_dir().map(|x| x.join("liftinstall").join("bandwidth.json"))
+    }
+}
+
+/// Returns the arguments which have an elevated copy record into this one's usage. The
+/// files a Unix elevated copy would leave behind (e.g. backups) would belong to root,
+/// locking the user out of their usage, so they are only handed over on Windows.
+pub fn elevation_args() -> Vec<String> {
+    match usage_path() {
+        Some(ref path) if cfg!(windows) => vec![
+            "--bandwidth-file".to_string(),
+            path.to_string_lossy().to_string(),
+        ],
+        _ => Vec::new(),
+    }
+}
+
+/// Reads the saved usage at `path`.
+fn read_usage(path: &Path) -> Usage {
+    read(path)
+        .ok()
+        .and_then(|x| match serde_json::from_slice(&x) {
+            Ok(v) => Some(v),
+            Err(v) => {
+                warn!("Ignoring unreadable bandwidth usage: {:?}", v);
+                None
+            }
+        })
+        .unwrap_or_default()
+}
+
+fn current_month() -> String {
+    Local::now().format("%Y-%m").to_string()
+}
+
+/// Returns the saved usage, reading it the first time it is needed.
+fn usage(state: &mut State) -> &mut Usage {
+    if state.usage.is_none() {
+        state.usage = Some(usage_path().map(|x| read_usage(&x)).unwrap_or_default());
+    }
+
+    state
+        .usage
+        .as_mut()
+        .log_expect("Bandwidth usage wasn't loaded")
+}
+
+/// Adds what has been recorded to the saved usage, which other sessions may have added to
+/// since it was read. Failures only mean that usage is under-reported until saving is
+/// next tried, so are only logged.
+fn save(state: &mut State) {
+    state.unsaved = 0;
+
+    let path = match usage_path() {
+        Some(v) => v,
+        None => return,
+    };
+
+    let result = path
+        .parent()
+        .map_or(Ok(()), create_dir_all)
+        .and_then(|_| atomic::lock(&path))
+        .map_err(|x| format!("{:?}", x))
+        .and_then(|_lock| {
+            let mut usage = read_usage(&path);
+            for (month, bytes) in &state.unsaved_months {
+                *usage.months.entry(month.clone()).or_insert(0) += bytes;
+            }
+            if let Some(budget) = state.new_budget {
+                usage.monthly_budget = budget;
+            }
+
+            // Old months are dropped, keeping the most recent
+            while usage.months.len() > MONTHS_KEPT {
+                let oldest = usage.months.keys().next().cloned();
+                if let Some(oldest) = oldest {
+                    usage.months.remove(&oldest);
+                }
+            }
+
+            serde_json::to_vec(&usage)
+                .map_err(|x| format!("{:?}", x))
+                .and_then(|x| atomic::write(&path, &x).map_err(|x| format!("{:?}", x)))
+                .map(|_| usage)
+        });
+
+    match result {
+        Ok(usage) => {
+            state.usage = Some(usage);
+            state.unsaved_months.clear();
+            state.new_budget = None;
+        }
+        Err(v) => warn!("Unable to save bandwidth usage to {:?}: {}", path, v),
+    }
+}
+
+/// Records that data was downloaded.
+pub fn record(bytes: u64) {
+    if bytes == 0 {
+        return;
+    }
+
+    let mut state = STATE.lock().log_expect("Bandwidth state has been dirtied");
+    state.session += bytes;
+    state.unsaved += bytes;
+
+    let month = current_month();
+    *state.unsaved_months.entry(month.clone()).or_insert(0) += bytes;
+    *usage(&mut state).months.entry(month).or_insert(0) += bytes;
+
+    if state.unsaved >= SAVE_INTERVAL {
+        save(&mut state);
+    }
+}
+
+/// Saves usage which hasn't been saved yet, as an operation has finished.
+pub fn flush() {
+    let mut state = STATE.lock().log_expect("Bandwidth state has been dirtied");
+    if !state.unsaved_months.is_empty() {
+        info!("Downloaded {} this session", pretty_size(state.session));
+        save(&mut state);
+    }
+}
+
+/// Sets (or with `None`, removes) the monthly data budget.
+pub fn set_budget(budget: Option<u64>) {
+    let mut state = STATE.lock().log_expect("Bandwidth state has been dirtied");
+    match budget {
+        Some(v) => info!("Setting monthly data budget to {}", pretty_size(v)),
+        None => info!("Removing monthly data budget"),
+    }
+
+    usage(&mut state).monthly_budget = budget;
+    state.new_budget = Some(budget);
+    save(&mut state);
+}
+
+/// Returns what has been downloaded.
+pub fn summary() -> BandwidthSummary {
+    let mut state = STATE.lock().log_expect("Bandwidth state has been dirtied");
+    let session = state.session;
+    let usage = usage(&mut state);
+
+    let this_month = usage.months.get(&current_month()).cloned().unwrap_or(0);
+    let remaining = usage.monthly_budget.map(|x| x.saturating_sub(this_month));
+
+    BandwidthSummary {
+        session,
+        session_text: pretty_size(session),
+        this_month,
+        this_month_text: pretty_size(this_month),
+        months: usage.months.clone(),
+        monthly_budget: usage.monthly_budget,
+        monthly_budget_text: usage.monthly_budget.map(pretty_size),
+        remaining,
+        remaining_text: remaining.map(pretty_size),
+    }
+}
+
+/// Returns a warning about a download, if it would go over (or use up a large part of)
+/// what is left of the monthly budget.
+pub fn check(download_size: u64) -> Option<String> {
+    let summary = summary();
+    let remaining = summary.remaining?;
+    if download_size == 0 {
+        return None;
+    }
+
+    let size = pretty_size(download_size);
+    let remaining_text = pretty_size(remaining);
+
+    if download_size > remaining {
+        Some(strings::format(
+            "errors.data_budget_exceeded",
+            &[("size", &size), ("remaining", &remaining_text)],
+        ))
+    } else if download_size as f64 > remaining as f64 * LARGE_DOWNLOAD_RATIO {
+        Some(strings::format(
+            "errors.data_budget_large",
+            &[("size", &size), ("remaining", &remaining_text)],
+        ))
+    } else {
+        None
+    }
+}
+
+/// Asks before starting a download which `check` warns about, unless it is covered by what
+/// has already been confirmed for the running operation (e.g. before it was started, by
+/// the UI). If there is no-one to ask, downloads which fit within the budget go ahead, and
+/// those which don't are refused.
+pub fn confirm(
+    context: &mut InstallerFramework,
+    download_size: u64,
+    messenger: &dyn Fn(&TaskMessage),
+) -> Result<(), String> {
+    if download_size <= context.confirmed_download {
+        context.confirmed_download -= download_size;
+        return Ok(());
+    }
+
+    let warning = match check(download_size) {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+    let exceeded = summary().remaining.map_or(false, |x| download_size > x);
+
+    let download = strings::tr("ui.download_anyway");
+    let cancel = strings::tr("prompts.cancel");
+    let default = if exceeded { &cancel } else { &download };
+
+    if prompts::ask(messenger, &warning, &[&download, &cancel], default) != download {
+        return Err(strings::tr("errors.download_declined"));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,438 @@
+//! bundle.rs
+//!
+//! Offline bundles hold a configuration along with the package assets it resolves to, so
+//! that machines without internet access can be installed from a single file. A bundle is
+//! a .zip archive containing:
+//!
+//! - `bundle.json`, describing what was bundled (see `BundleManifest`)
+//! - `config.toml`, the configuration to install with
+//! - `packages/<package>/`, the asset each package installs, along with any published
+//!   checksum manifest and signature it is verified with
+//!
+//! While a bundle is open, its configuration is used instead of the one at `target_url`,
+//! and every package is resolved from the bundle by the `bundle` source. Checksum
+//! manifests and signatures are found within the bundle too, even where the
+//! configuration gives their URL.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::RwLock;
+
+use chrono::Local;
+
+use regex;
+
+use zip::result::ZipError;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use config::{BaseAttributes, Config, PackageSource};
+
+use http;
+
+use installer::InstallerFramework;
+
+use source_auth;
+
+use sources::types::{TomlValue, Version};
+
+use tasks::resolver::find_latest;
+use tasks::TaskMessage;
+
+use logging::LoggingErrors;
+
+const MANIFEST_NAME: &str = "bundle.json";
+const CONFIG_NAME: &str = "config.toml";
+const PACKAGES_DIR: &str = "packages";
+/// Where bundles are extracted to, within the staging directory.
+const EXTRACT_DIR: &str = ".bundle";
+
+/// A package within a bundle.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BundledPackage {
+    pub name: String,
+    pub version: Version,
+    /// Name of the asset which is installed
+    pub file: String,
+    /// Checksum manifests and signatures published alongside the asset
+    #[serde(default)]
+    pub extra_files: Vec<String>,
+}
+
+/// Describes what a bundle contains.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BundleManifest {
+    /// Name of the application the bundle is for, which has to match the installer's
+    pub name: String,
+    /// When the bundle was built, as RFC 3339
+    pub created: String,
+    pub packages: Vec<BundledPackage>,
+}
+
+/// A directory a bundle was extracted into, which is removed once it is no longer used
+/// (including when opening the bundle fails part way through).
+struct ExtractedDir(PathBuf);
+
+impl Drop for ExtractedDir {
+    fn drop(&mut self) {
+        if !self.0.exists() {
+            return;
+        }
+
+        if let Err(v) = fs::remove_dir_all(&self.0) {
+            warn!("Unable to remove extracted bundle {:?}: {:?}", self.0, v);
+        }
+    }
+}
+
+/// A bundle which is being installed from.
+struct OpenBundle {
+    /// The bundle, as it was given
+    source: PathBuf,
+    /// Where the bundle's contents are
+    dir: PathBuf,
+    /// Set if `dir` was extracted by this session
+    _extracted: Option<ExtractedDir>,
+    manifest: BundleManifest,
+    config: String,
+}
+
+lazy_static! {
+    static ref ACTIVE: RwLock<Option<OpenBundle>> = RwLock::new(None);
+}
+
+/// Returns the directory within a bundle which holds a package's files.
+fn package_dir(name: &str) -> String {
+    format!(
+        "{}/{}",
+        PACKAGES_DIR,
+        name.replace(|x| x == '/' || x == '\\', "_")
+    )
+}
+
+/// Streams a resource into a writer.
+fn copy_url<W: Write>(url: &str, writer: &mut W) -> Result<(), String> {
+    let mut result = Ok(());
+    http::stream_file(url, None, |data, _| {
+        if result.is_ok() {
+            result = writer
+                .write_all(&data)
+                .map_err(|x| format!("Unable to write {:?} to the bundle: {:?}", url, x));
+        }
+    })?;
+
+    result
+}
+
+/// Publisher usage - downloads the latest release of every package in a configuration
+/// (from the default channel, if any) into a bundle at `output`.
+pub fn build(attributes: &BaseAttributes, config_text: &str, output: &Path) -> Result<(), String> {
+    let mut framework = InstallerFramework::new(attributes.clone());
+    framework.load_config(config_text)?;
+    let config = framework
+        .config
+        .as_ref()
+        .ok_or("Configuration hasn't been loaded")?;
+
+    let file =
+        fs::File::create(output).map_err(|x| format!("Unable to create {:?}: {:?}", output, x))?;
+    let mut zip = ZipWriter::new(file);
+
+    // Package assets are almost always compressed already
+    let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let zip_error = |x: ZipError| format!("Unable to write to the bundle: {:?}", x);
+
+    zip.start_file(CONFIG_NAME, options).map_err(zip_error)?;
+    zip.write_all(config_text.as_bytes())
+        .map_err(|x| format!("Unable to write to the bundle: {:?}", x))?;
+
+    let mut packages = Vec::new();
+    for package in &config.packages {
+        source_auth::authorize_silently(&package.name, &package.source, Some(&framework))?;
+        let (version, file) = find_latest(package, &framework, &|_: &TaskMessage| {})
+            .map_err(|x| format!("Unable to resolve {:?}: {}", package.name, x))?;
+
+        if file.requires_authorization {
+            return Err(format!(
+                "{:?} can only be downloaded by authorised users, so can't be bundled",
+                package.name
+            ));
+        }
+
+        info!("Bundling {:?} {} ({})", package.name, version, file.name);
+        let dir = package_dir(&package.name);
+
+        zip.start_file(format!("{}/{}", dir, file.name), options)
+            .map_err(zip_error)?;
+        // Multi-part archives are reassembled
+        for url in Some(&file.url).into_iter().chain(file.parts.iter()) {
+            copy_url(url, &mut zip)?;
+        }
+
+        let mut extra_files = Vec::new();
+        for url in file.checksums.iter().chain(file.signature.iter()) {
+            let name = url
+                .rsplit('/')
+                .next()
+                .filter(|x| !x.is_empty())
+                .ok_or_else(|| format!("Unable to get file name of {:?}", url))?
+                .to_string();
+
+            zip.start_file(format!("{}/{}", dir, name), options)
+                .map_err(zip_error)?;
+            copy_url(url, &mut zip)?;
+            extra_files.push(name);
+        }
+
+        packages.push(BundledPackage {
+            name: package.name.clone(),
+            version,
+            file: file.name.clone(),
+            extra_files,
+        });
+    }
+
+    let manifest = BundleManifest {
+        name: attributes.name.clone(),
+        created: Local::now().to_rfc3339(),
+        packages,
+    };
+
+    zip.start_file(MANIFEST_NAME, options).map_err(zip_error)?;
+    serde_json::to_writer_pretty(&mut zip, &manifest)
+        .map_err(|x| format!("Unable to write bundle manifest: {:?}", x))?;
+    zip.finish().map_err(zip_error)?;
+
+    Ok(())
+}
+
+/// Extracts a bundle into `dir`, replacing anything left there by an earlier run.
+fn extract(path: &Path, dir: PathBuf) -> Result<ExtractedDir, String> {
+    let file = fs::File::open(path).map_err(|x| format!("Unable to open {:?}: {:?}", path, x))?;
+    let mut archive =
+        ZipArchive::new(file).map_err(|x| format!("Unable to read {:?}: {:?}", path, x))?;
+
+    info!("Extracting offline bundle {:?} into {:?}", path, dir);
+    let dir = ExtractedDir(dir);
+    if dir.0.exists() {
+        fs::remove_dir_all(&dir.0).map_err(|x| format!("Unable to remove {:?}: {:?}", dir.0, x))?;
+    }
+
+    for i in 0..archive.len() {
+        let mut entry = archive
+            .by_index(i)
+            .map_err(|x| format!("Unable to read {:?}: {:?}", path, x))?;
+        if entry.name().ends_with('/') {
+            continue;
+        }
+
+        let target = dir.0.join(entry.sanitized_name());
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|x| format!("Unable to create {:?}: {:?}", parent, x))?;
+        }
+
+        let mut output = fs::File::create(&target)
+            .map_err(|x| format!("Unable to create {:?}: {:?}", target, x))?;
+        io::copy(&mut entry, &mut output)
+            .map_err(|x| format!("Unable to extract {:?}: {:?}", target, x))?;
+    }
+
+    Ok(dir)
+}
+
+/// Installs from a bundle for the rest of the session. Bundles can also be given as a
+/// directory they have already been extracted into, such as on a network share. Others
+/// are extracted into the staging directory, if one was given, as they can be large.
+pub fn open(
+    path: &Path,
+    attributes: &BaseAttributes,
+    staging_dir: Option<&Path>,
+) -> Result<(), String> {
+    let (dir, extracted) = if path.is_dir() {
+        (path.to_owned(), None)
+    } else {
+        let dir = match staging_dir {
+            Some(v) => v.join(&attributes.name).join(EXTRACT_DIR),
+            None => env::temp_dir().join(format!("{}_bundle_{}", attributes.name, process::id())),
+        };
+        let extracted = extract(path, dir)?;
+        (extracted.0.clone(), Some(extracted))
+    };
+
+    let read = |name: &str| {
+        fs::read_to_string(dir.join(name))
+            .map_err(|x| format!("Unable to read {} from the offline bundle: {:?}", name, x))
+    };
+
+    let manifest: BundleManifest = serde_json::from_str(&read(MANIFEST_NAME)?)
+        .map_err(|x| format!("Invalid offline bundle manifest: {:?}", x))?;
+    let config = read(CONFIG_NAME)?;
+
+    if manifest.name != attributes.name {
+        return Err(format!(
+            "The offline bundle is for {:?}, not {:?}",
+            manifest.name, attributes.name
+        ));
+    }
+
+    info!(
+        "Using offline bundle built {} ({} packages)",
+        manifest.created,
+        manifest.packages.len()
+    );
+
+    *ACTIVE.write().log_expect("Offline bundle has been dirtied") = Some(OpenBundle {
+        source: path.to_owned(),
+        dir,
+        _extracted: extracted,
+        manifest,
+        config,
+    });
+
+    Ok(())
+}
+
+/// Removes anything extracted from the open bundle, which is no longer used.
+pub fn close() {
+    ACTIVE
+        .write()
+        .log_expect("Offline bundle has been dirtied")
+        .take();
+}
+
+/// Returns the open bundle as it was given, if there is one.
+pub fn source_path() -> Option<PathBuf> {
+    ACTIVE
+        .read()
+        .log_expect("Offline bundle has been dirtied")
+        .as_ref()
+        .map(|x| x.source.clone())
+}
+
+/// Returns the configuration of the open bundle, if there is one.
+pub fn config_text() -> Option<String> {
+    ACTIVE
+        .read()
+        .log_expect("Offline bundle has been dirtied")
+        .as_ref()
+        .map(|x| x.config.clone())
+}
+
+/// Finds the version of a package within the open bundle, along with the paths to its
+/// files.
+pub fn find_package(name: &str) -> Result<(Version, Vec<PathBuf>), String> {
+    let active = ACTIVE.read().log_expect("Offline bundle has been dirtied");
+    let bundle = active
+        .as_ref()
+        .ok_or("No offline bundle is being installed from")?;
+
+    let package = bundle
+        .manifest
+        .packages
+        .iter()
+        .find(|x| x.name == name)
+        .ok_or_else(|| format!("{:?} isn't in the offline bundle", name))?;
+
+    let dir = bundle.dir.join(package_dir(name));
+    let files = Some(&package.file)
+        .into_iter()
+        .chain(package.extra_files.iter())
+        .map(|x| dir.join(x))
+        .collect();
+
+    Ok((package.version.clone(), files))
+}
+
+/// Points every package of a configuration at the open bundle (if any), after checking
+/// that each bundled version is still allowed by the package's (chosen) channel.
+pub fn apply(config: &mut Config, channels: &HashMap<String, String>) -> Result<(), String> {
+    let active = ACTIVE.read().log_expect("Offline bundle has been dirtied");
+    let bundle = match *active {
+        Some(ref v) => v,
+        None => return Ok(()),
+    };
+
+    let mut problems = Vec::new();
+    for package in &mut config.packages {
+        let bundled = bundle
+            .manifest
+            .packages
+            .iter()
+            .find(|x| x.name == package.name);
+
+        // Left pointing at the bundle, so that it fails to resolve if selected
+        let match_regex = match bundled {
+            Some(bundled) => {
+                let channel = package.get_channel(channels.get(&package.name).map(|x| x.as_str()));
+                if let Some(channel) = channel {
+                    if !channel.accepts(&bundled.version)? {
+                        problems.push(format!(
+                            "{:?} {} isn't allowed by the {:?} channel",
+                            package.name, bundled.version, channel.name
+                        ));
+                    }
+                }
+
+                format!("^{}$", regex::escape(&bundled.file))
+            }
+            None => {
+                warn!("{:?} isn't in the offline bundle", package.name);
+                package.source.match_regex.clone()
+            }
+        };
+
+        let mut source_config = toml::value::Table::new();
+        source_config.insert(
+            "package".to_string(),
+            TomlValue::String(package.name.clone()),
+        );
+
+        package.source = PackageSource {
+            name: "bundle".to_string(),
+            match_regex,
+            config: TomlValue::Table(source_config),
+            mirrors: Vec::new(),
+            probe_mirrors: false,
+            select: Default::default(),
+            auth: None,
+            locale_match: Default::default(),
+        };
+
+        // The bundled file has already been chosen
+        for channel in &mut package.channels {
+            channel.match_regex = None;
+        }
+
+        // Manifests given by URL were bundled under their file name, as they are when
+        // published in the same release
+        if let Some(ref mut verification) = package.verification {
+            let bundled_name = verification
+                .checksums
+                .as_ref()
+                .filter(|x| x.contains("://"))
+                .and_then(|x| x.rsplit('/').next())
+                .map(|x| x.to_string());
+            if bundled_name.is_some() {
+                verification.checksums = bundled_name;
+            }
+        }
+    }
+
+    if !problems.is_empty() {
+        return Err(format!(
+            "The offline bundle doesn't match the configuration: {}",
+            problems.join(", ")
+        ));
+    }
+
+    // There is nothing to update the installer from
+    config.self_update = None;
+    config.new_tool = None;
+
+    Ok(())
+}
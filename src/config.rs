@@ -7,8 +7,18 @@ use toml::de::Error as TomlError;
 
 use serde_json::{self, Error as SerdeError};
 
+use regex::{self, Regex};
+
+use chrono::{NaiveTime, Timelike};
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
 use sources::get_by_name;
-use sources::types::Release;
+use sources::resolver::RESOLVER;
+use sources::types::{Release, SemverVersion, Version};
+
+use semver::VersionReq;
 
 /// Description of the source of a package.
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -17,6 +27,80 @@ pub struct PackageSource {
     #[serde(rename = "match")]
     pub match_regex: String,
     pub config: toml::Value,
+    /// Hosts serving the same paths as the source's assets, tried if the source's own
+    /// host is unavailable
+    #[serde(default)]
+    pub mirrors: Vec<String>,
+    /// If the source's host and its mirrors are probed, and tried fastest first, rather
+    /// than in the order they are listed (see `mirrors`)
+    #[serde(default = "default_true")]
+    pub probe_mirrors: bool,
+    /// Further narrows down which of a release's files matching `match` is installed
+    #[serde(default)]
+    pub select: AssetSelection,
+    /// How to authenticate with the source, if it is private
+    #[serde(default)]
+    pub auth: Option<SourceAuth>,
+    /// Replaces `match` for installations in these locales (e.g. `de` or `pt-BR`), so
+    /// that a localized build is installed. See `localization`.
+    #[serde(default)]
+    pub locale_match: BTreeMap<String, String>,
+}
+
+/// Where the access token for a private source comes from. The first of these which is
+/// available is used.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct SourceAuth {
+    /// Environment variable holding the token
+    #[serde(default)]
+    pub env: Option<String>,
+    /// The token itself. Anyone with the installer can read this, so it should only
+    /// grant read access. Never sent to the frontend or cached with the configuration.
+    #[serde(default, skip_serializing)]
+    pub token: Option<String>,
+    /// If the user is asked for a token when none is available otherwise
+    #[serde(default)]
+    pub prompt: bool,
+    /// If tokens the user entered are kept in metadata.json (encrypted with a key kept
+    /// outside of the install directory) for later updates
+    #[serde(default)]
+    pub remember: bool,
+    /// Prefixes of further URLs which are sent the token as a bearer token, for sources
+    /// which don't know where their files are hosted
+    #[serde(default)]
+    pub hosts: Vec<String>,
+}
+
+/// Criteria for choosing a file from a release, beyond its name matching a regex.
+/// Sizes and types are only checked against files which the source reports them for.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct AssetSelection {
+    /// Regexes in order of preference. Files matching an earlier pattern are chosen
+    /// over those matching a later one, and files matching none of them come last.
+    #[serde(default)]
+    pub prefer: Vec<String>,
+    /// Smallest acceptable file size, in bytes
+    #[serde(default)]
+    pub min_size: Option<u64>,
+    /// Largest acceptable file size, in bytes
+    #[serde(default)]
+    pub max_size: Option<u64>,
+    /// Acceptable MIME types, e.g. `application/zip`
+    #[serde(default)]
+    pub content_types: Vec<String>,
+}
+
+/// Rewrites asset URLs returned by package sources, e.g. to send traffic through an
+/// internal caching proxy.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct UrlRewrite {
+    /// Only rewrite URLs from this kind of source (e.g. `github`)
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Regex matched against each URL
+    pub pattern: String,
+    /// Replaces the matched part of the URL, and may refer to groups as `$1`
+    pub replacement: String,
 }
 
 /// Describes if/how a shortcut should be built for a package.
@@ -27,6 +111,16 @@ pub struct PackageShortcut {
     pub description: String,
     #[serde(default)]
     pub has_desktop_shortcut: bool,
+    /// Icon shown for the shortcut, relative to the package's root. Only used on Linux, as
+    /// Windows shows the executable's own icon.
+    #[serde(default)]
+    pub icon: Option<String>,
+    /// MIME types the application can open, e.g. `text/markdown` (Linux only)
+    #[serde(default)]
+    pub mime_types: Vec<String>,
+    /// Makes the application the installing user's default for opening `mime_types`
+    #[serde(default)]
+    pub default_handler: bool,
 }
 
 /// Extra description for authentication and authorization state for a package
@@ -44,6 +138,149 @@ pub struct PackageExtendedDescription {
     pub need_reward_tier_description: Option<String>,
 }
 
+/// Describes a dedicated system account to be created for a package (e.g. for daemons).
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct PackageSystemUser {
+    pub name: String,
+    /// Defaults to a group with the same name as the user
+    #[serde(default)]
+    pub group: Option<String>,
+    /// Paths, relative to the install directory, which should be owned by this account
+    #[serde(default)]
+    pub owned_paths: Vec<String>,
+    /// If the account should be deleted when the package is uninstalled
+    #[serde(default)]
+    pub remove_on_uninstall: bool,
+}
+
+/// How directory links are made on Windows. Other platforms always use symbolic links.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkKind {
+    /// Junctions need no special permissions, but can only point to local drives
+    Junction,
+    /// Symbolic links can also point to network shares, but need administrator
+    /// permission (or Developer Mode) to be created
+    Symlink,
+}
+
+impl Default for LinkKind {
+    fn default() -> Self {
+        LinkKind::Junction
+    }
+}
+
+/// A directory link created for a package, such as to keep a large data directory on
+/// another drive. Links are removed on uninstall, but whatever they point to is kept.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct PackageLink {
+    /// Where the link is created. See `locations::resolve` for the supported syntax.
+    pub path: String,
+    /// Directory the link points to, which is created if it doesn't exist yet. Uses the
+    /// same syntax as `path`.
+    pub target: String,
+    #[serde(default)]
+    pub kind: LinkKind,
+    /// If the user is asked where the link should point to, with `target` as the default
+    #[serde(default)]
+    pub user_selectable: bool,
+    /// Describes what the link is for, when asking the user where it should point to
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// Finds out if something (such as a runtime) is already on the system, by reading its
+/// version from the first of `registry`, `file` or `command` which is given. See `detect`.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Detection {
+    /// Registry value holding the version, as `<HKLM|HKCU>\<key>\<value>`
+    #[serde(default)]
+    pub registry: Option<String>,
+    /// File whose version resource holds the version. macOS app bundles use the version
+    /// in their Info.plist instead. See `locations::resolve` for the supported syntax.
+    #[serde(default)]
+    pub file: Option<String>,
+    /// Program which prints its version, looked up on the PATH
+    #[serde(default)]
+    pub command: Option<String>,
+    /// Arguments which make `command` print its version
+    #[serde(default = "default_version_args")]
+    pub args: Vec<String>,
+    /// Regex finding the version in the command's output, as its first group
+    #[serde(default = "default_version_pattern")]
+    pub pattern: String,
+    /// Semver requirement the version has to meet, e.g. `>= 14.30`. Anything found
+    /// meets it if this isn't given.
+    #[serde(default)]
+    pub version: Option<String>,
+}
+
+fn default_version_args() -> Vec<String> {
+    vec!["--version".to_string()]
+}
+
+fn default_version_pattern() -> String {
+    r"(\d+(?:\.\d+)*)".to_string()
+}
+
+/// A command run at a point in a package's lifecycle, such as to register a driver.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct HookCommand {
+    /// Program to run. Paths are relative to the install directory, and anything else is
+    /// looked up on the PATH.
+    pub command: String,
+    /// Arguments, in which `{install_dir}` is replaced with the install directory
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Directory to run in, relative to the install directory. Defaults to the package's
+    /// root.
+    #[serde(default)]
+    pub working_dir: Option<String>,
+    /// How long the command may run for, in seconds, instead of `limits.command_timeout`
+    #[serde(default)]
+    pub timeout: Option<u64>,
+    /// If the command failing fails the whole operation (rolling back an install), rather
+    /// than only being logged. Uninstalls ask the user if they should continue anyway,
+    /// and headless ones do.
+    #[serde(default = "default_true")]
+    pub required: bool,
+    /// Skips the command if this detects what it would install is already there
+    #[serde(default)]
+    pub skip_if: Option<Detection>,
+    /// If the command can ask the user questions (see `sandbox`). Its stdin is only
+    /// connected to answer them if this is set, and is empty otherwise.
+    #[serde(default)]
+    pub dialogs: bool,
+}
+
+/// Runs a package's program once it has been installed, to catch installs which were
+/// damaged (e.g. by antivirus software) or cut short.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SmokeTest {
+    /// Program to run, relative to the install directory
+    pub command: String,
+    /// Arguments, in which `{install_dir}` is replaced with the install directory
+    #[serde(default = "default_version_args")]
+    pub args: Vec<String>,
+    /// Regex which the program's output must match, in addition to it exiting successfully
+    #[serde(default)]
+    pub pattern: Option<String>,
+    /// How long the program may run for, in seconds, instead of `limits.command_timeout`
+    #[serde(default)]
+    pub timeout: Option<u64>,
+}
+
+/// Commands run as a package is installed and removed.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct PackageHooks {
+    /// Run after the package has been installed or updated
+    #[serde(default)]
+    pub post_install: Vec<HookCommand>,
+    /// Run before the package is uninstalled. Updates don't run these.
+    #[serde(default)]
+    pub pre_uninstall: Vec<HookCommand>,
+}
+
 /// Describes a overview of a individual package.
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct PackageDescription {
@@ -61,6 +298,384 @@ pub struct PackageDescription {
     pub is_new: Option<bool>,
     #[serde(default)]
     pub extended_description: Option<PackageExtendedDescription>,
+    #[serde(default)]
+    pub system_user: Option<PackageSystemUser>,
+    /// Packages with lower priorities are installed (and listed) first.
+    #[serde(default)]
+    pub priority: i32,
+    /// Named group this package is listed under.
+    #[serde(default)]
+    pub group: Option<String>,
+    /// Other packages which must be installed alongside (and before) this one. Also
+    /// accepted as `depends`.
+    #[serde(default, alias = "depends")]
+    pub requires: Vec<String>,
+    /// Directory to install this package into, instead of the main install directory.
+    /// See `locations::resolve` for the supported syntax.
+    #[serde(default)]
+    pub install_root: Option<String>,
+    /// Overrides the global handling of files which already exist
+    #[serde(default)]
+    pub existing_files: ExistingFileRules,
+    /// Checks downloads against published checksums and signatures before extraction
+    #[serde(default)]
+    pub verification: Option<PackageVerification>,
+    /// Streams of releases which users can choose between. The first is used unless
+    /// another has been chosen.
+    #[serde(default)]
+    pub channels: Vec<ReleaseChannel>,
+    /// Space taken by the package once installed, in bytes, if estimating this from the
+    /// size of its archive isn't accurate enough
+    #[serde(default)]
+    pub installed_size: Option<u64>,
+    /// Commands run after installing and before uninstalling
+    #[serde(default)]
+    pub hooks: PackageHooks,
+    /// Marks this package as a language pack for these locales. It is selected by
+    /// default (instead of following `default`) if the installation is in one of them.
+    #[serde(default)]
+    pub locales: Vec<String>,
+    /// Directory links created once the package has been installed
+    #[serde(default)]
+    pub links: Vec<PackageLink>,
+    /// Finds out if this package is already on the system. Packages which are present
+    /// aren't installed when only pulled in as a requirement of another.
+    #[serde(default)]
+    pub detect: Option<Detection>,
+    /// CPU features (e.g. `avx2` or `sse4.2`) which this package's build needs. Packages
+    /// can't be selected on processors without them.
+    #[serde(default)]
+    pub cpu_features: Vec<String>,
+    /// Package to use instead on processors missing any of `cpu_features`
+    #[serde(default)]
+    pub cpu_fallback: Option<String>,
+    /// Checks that the package works once it has been installed
+    #[serde(default)]
+    pub smoke_test: Option<SmokeTest>,
+    /// Why this package can't be installed on this system. Set when the config is loaded.
+    #[serde(default, skip_deserializing)]
+    pub unsupported: Option<String>,
+}
+
+impl PackageDescription {
+    /// Finds a channel by name, or the default channel, if there are any.
+    pub fn get_channel(&self, name: Option<&str>) -> Option<&ReleaseChannel> {
+        name.and_then(|name| self.channels.iter().find(|x| x.name == name))
+            .or_else(|| self.channels.first())
+    }
+}
+
+/// A stream of releases of a package, e.g. stable, beta or nightly builds.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ReleaseChannel {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Only installs files matching this regex, instead of the source's `match`
+    #[serde(default, rename = "match")]
+    pub match_regex: Option<String>,
+    /// Only installs versions meeting this semver requirement, e.g. `>= 2.0, < 3.0`
+    #[serde(default)]
+    pub version: Option<String>,
+    /// If pre-release versions (e.g. `2.1.0-beta.1`) are installed
+    #[serde(default)]
+    pub prerelease: bool,
+}
+
+impl ReleaseChannel {
+    /// Returns if a version belongs to this channel.
+    pub fn accepts(&self, version: &Version) -> Result<bool, String> {
+        let version = match *version {
+            Version::Semver(ref v) => v.clone(),
+            // Plain numbers can't be pre-releases
+            Version::Integer(v) => SemverVersion::from((v, 0, 0)),
+        };
+
+        if version.is_prerelease() && !self.prerelease {
+            return Ok(false);
+        }
+
+        match self.version {
+            Some(ref requirement) => {
+                let requirement = VersionReq::parse(requirement).map_err(|x| {
+                    format!("Invalid version requirement {:?}: {:?}", requirement, x)
+                })?;
+
+                // Requirements otherwise only match pre-releases of the exact version named
+                let mut version = version;
+                version.pre.clear();
+
+                Ok(requirement.matches(&version))
+            }
+            None => Ok(true),
+        }
+    }
+}
+
+/// How downloaded packages are verified before being extracted.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct PackageVerification {
+    /// A `sha256sum` style checksum manifest, as either the name of a file in the same
+    /// release or a URL. `{{file}}` is replaced with the name of the downloaded file.
+    #[serde(default)]
+    pub checksums: Option<String>,
+    /// A minisign public key. The checksum manifest (or if there isn't one, the download
+    /// itself) must have a valid signature alongside it, named `<file>.minisig`.
+    #[serde(default)]
+    pub minisign_key: Option<String>,
+}
+
+/// What to do when a package contains a file which already exists.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// Fail the installation
+    Error,
+    /// Replace the file, backing up the original to be restored on uninstall
+    Overwrite,
+}
+
+impl Default for ConflictPolicy {
+    fn default() -> Self {
+        ConflictPolicy::Error
+    }
+}
+
+/// What to do with archive entries whose names only differ by case, when extracting onto
+/// a case-insensitive file system (as is the default on Windows and macOS).
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CaseCollisionPolicy {
+    /// Fail the installation
+    Error,
+    /// Extract the first of the colliding entries
+    KeepFirst,
+    /// Extract the last of the colliding entries
+    KeepLast,
+}
+
+impl Default for CaseCollisionPolicy {
+    fn default() -> Self {
+        CaseCollisionPolicy::Error
+    }
+}
+
+/// What to do with archive entries whose names can't be used on Windows, such as those
+/// using reserved device names (e.g. `CON` or `aux.txt`), characters like `:` or `?`, or
+/// ending with a dot or space. Archives built on other systems often contain these.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum InvalidNamePolicy {
+    /// Fail the installation, before anything is extracted
+    Error,
+    /// Extract the entry under a usable name, replacing what isn't allowed with `_`
+    Rename,
+    /// Leave the entry out
+    Skip,
+}
+
+impl Default for InvalidNamePolicy {
+    fn default() -> Self {
+        InvalidNamePolicy::Rename
+    }
+}
+
+/// What to do with a file which already exists, but wasn't installed by any package.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExistingFilePolicy {
+    /// Fail the installation
+    Error,
+    /// Replace the file
+    Overwrite,
+    /// Leave the existing file alone
+    Skip,
+    /// Replace the file, backing up the original to be restored on uninstall
+    Backup,
+    /// Ask the user which of the above to do
+    Prompt,
+}
+
+impl Default for ExistingFilePolicy {
+    fn default() -> Self {
+        ExistingFilePolicy::Error
+    }
+}
+
+/// Applies a policy to existing files matching a pattern. Patterns are relative to the
+/// package's root, where `*` matches within a directory and `**` matches across them.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ExistingFileRule {
+    pub pattern: String,
+    pub policy: ExistingFilePolicy,
+}
+
+/// How to handle files that already exist. The first matching rule wins, else the default.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct ExistingFileRules {
+    #[serde(default)]
+    pub default: Option<ExistingFilePolicy>,
+    #[serde(default)]
+    pub rules: Vec<ExistingFileRule>,
+}
+
+impl ExistingFileRules {
+    /// Returns the policy of the first rule which matches the specified path.
+    fn find_rule(&self, path: &str) -> Option<ExistingFilePolicy> {
+        self.rules
+            .iter()
+            .find(|x| matches_pattern(&x.pattern, path))
+            .map(|x| x.policy.clone())
+    }
+}
+
+/// Checks a relative path against a glob-style pattern.
+fn matches_pattern(pattern: &str, path: &str) -> bool {
+    let mut expression = String::from("^");
+    let mut chars = pattern.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                expression.push_str(".*");
+            }
+            '*' => expression.push_str("[^/]*"),
+            '?' => expression.push_str("[^/]"),
+            c => expression.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    expression.push('$');
+
+    // Archives always use forward slashes
+    let path = path.replace("\\", "/");
+
+    match Regex::new(&expression) {
+        Ok(v) => v.is_match(&path),
+        Err(v) => {
+            warn!("Invalid file pattern {:?}: {:?}", pattern, v);
+            false
+        }
+    }
+}
+
+/// Other places applications commonly leave data behind, checked after uninstalling.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct LeftoverLocations {
+    /// Paths to check (see `locations::resolve`), such as user data directories
+    #[serde(default)]
+    pub paths: Vec<String>,
+    /// Registry keys to check, as `<HKLM|HKCU>\<key>`
+    #[serde(default)]
+    pub registry_keys: Vec<String>,
+}
+
+/// What to do when installed files are held open by running applications, as found by
+/// the Windows Restart Manager.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LockedFilePolicy {
+    /// Fail the installation
+    Error,
+    /// Ask the user if the applications should be closed
+    Prompt,
+    /// Close the applications without asking
+    Close,
+}
+
+impl Default for LockedFilePolicy {
+    fn default() -> Self {
+        LockedFilePolicy::Prompt
+    }
+}
+
+/// A proxy to send all requests through, instead of the one the system would use.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ProxyConfig {
+    /// e.g. `http://proxy:8080`, or `socks5://proxy:1080` for SOCKS5
+    pub url: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub password: Option<String>,
+    /// Hosts (along with their subdomains) which are connected to directly, with `*`
+    /// matching everything
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+}
+
+/// Whether user data follows the user between machines, which is only distinguished on
+/// Windows (roaming vs local application data).
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DataScope {
+    Roaming,
+    Local,
+}
+
+/// A location the installed product keeps user data in.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DataLocation {
+    /// Name to substitute this location with, as `{data:<name>}`
+    pub name: String,
+    pub scope: DataScope,
+    /// Path within the scope's application data directory (see `locations::resolve`)
+    pub path: String,
+}
+
+/// Something which happened to an installation, which webhooks can be notified of.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    InstallStarted,
+    InstallSucceeded,
+    InstallFailed,
+    UpdateApplied,
+}
+
+/// An endpoint which install events are POSTed to, as JSON.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Webhook {
+    pub url: String,
+    /// Events to send - all events are sent if empty
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
+    /// Key to sign payloads with, sent as `X-Liftinstall-Signature: sha256=<HMAC>`
+    #[serde(default, skip_serializing)]
+    pub secret: Option<String>,
+}
+
+/// A named set of release feeds (e.g. staging), selected at runtime (see `environments`).
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Environment {
+    pub name: String,
+    /// Values replacing those of packages' source configuration, by package name
+    #[serde(default)]
+    pub sources: BTreeMap<String, toml::Value>,
+    /// Release channel used for packages which have it, instead of the chosen one
+    #[serde(default)]
+    pub channel: Option<String>,
+    /// Rewrites applied to asset URLs ahead of the configuration's own
+    #[serde(default)]
+    pub url_rewrites: Vec<UrlRewrite>,
+    /// Endpoints to notify of install events, instead of the configuration's
+    #[serde(default)]
+    pub webhooks: Option<Vec<Webhook>>,
+}
+
+/// Describes an existing application which packages are installed into, for products which
+/// are plugins or mods for another application.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct HostApplication {
+    /// Locations to check in order (see `locations::resolve`) - the first that exists is used
+    #[serde(default)]
+    pub locations: Vec<String>,
+    /// Executable name of the host, used to find it while it is running
+    #[serde(default)]
+    pub process_name: Option<String>,
+    /// Directory within the host application to install into
+    #[serde(default)]
+    pub subdirectory: Option<String>,
 }
 
 /// Configuration for validating the JWT token
@@ -79,11 +694,63 @@ pub struct AuthenticationConfig {
     pub validation: Option<JWTValidation>,
 }
 
+/// Who an installation is for, which decides where it goes by default.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum InstallScope {
+    /// Installed into the user's profile, without needing any privileges
+    User,
+    /// Installed for all users of the machine (into e.g. Program Files or /opt), which
+    /// needs elevation to install and maintain
+    Machine,
+}
+
+impl Default for InstallScope {
+    fn default() -> Self {
+        InstallScope::User
+    }
+}
+
+/// Overrides for the accessibility settings detected from the OS, which are otherwise
+/// followed by the frontend.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct AccessibilityOverrides {
+    /// Always (or never) use high contrast colours
+    #[serde(default)]
+    pub high_contrast: Option<bool>,
+    /// Always (or never) avoid animations
+    #[serde(default)]
+    pub reduced_motion: Option<bool>,
+}
+
 /// Describes the application itself.
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct BaseAttributes {
     pub name: String,
     pub target_url: String,
+    /// Fixed port for the embedded server, instead of a random one
+    #[serde(default)]
+    pub server_port: Option<u16>,
+    /// Unix domain socket the embedded server is also served on, for integrations which
+    /// don't use the built-in web view. Only supported on Unix.
+    #[serde(default)]
+    pub server_socket: Option<String>,
+    /// File name of an offline bundle (see `bundle`) which is installed from instead of
+    /// `target_url`, if it is found next to the installer
+    #[serde(default)]
+    pub offline_bundle: Option<String>,
+    /// Locale to show text in (e.g. "de"), instead of the user's
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// Who installations are for, unless the user (or `--all-users`) chooses otherwise
+    #[serde(default)]
+    pub install_scope: InstallScope,
+    #[serde(default)]
+    pub accessibility: AccessibilityOverrides,
+    /// Pages explaining errors, used until the configuration has been fetched (e.g. for
+    /// the errors which stop it from being fetched)
+    #[serde(default)]
+    pub error_help: Vec<ErrorHelp>,
 }
 
 impl BaseAttributes {
@@ -98,17 +765,502 @@ impl BaseAttributes {
     }
 }
 
+/// Tuning knobs for the resources an installation may use.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ResourceLimits {
+    /// Maximum number of packages downloaded at once
+    #[serde(default = "default_worker_count")]
+    pub download_parallelism: usize,
+    /// Maximum number of packages extracted at once. Above 1, packages downloaded ahead of
+    /// time are decompressed alongside each other, taking up their uncompressed size on disk
+    /// until installed; their files are still placed one package at a time.
+    #[serde(default = "default_worker_count")]
+    pub extraction_workers: usize,
+    /// If installation I/O should run with background/idle priority
+    #[serde(default)]
+    pub low_priority_io: bool,
+    /// What happens to downloads while the machine is running on battery power
+    #[serde(default)]
+    pub on_battery: BatteryPolicy,
+    /// Download rate while throttled on battery power, in bytes per second
+    #[serde(default = "default_battery_rate")]
+    pub battery_download_rate: u64,
+    /// Local time window non-interactive installs and updates may download within
+    #[serde(default)]
+    pub download_window: Option<TimeWindow>,
+    /// How long external commands may run for, in seconds
+    #[serde(default = "default_command_timeout")]
+    pub command_timeout: u64,
+    /// How much of each output stream of an external command is kept, in bytes
+    #[serde(default = "default_command_output_limit")]
+    pub command_output_limit: usize,
+    /// How many times an interrupted download is retried before moving on to a mirror
+    #[serde(default = "default_download_retries")]
+    pub download_retries: u32,
+    /// Where files replaced during installation are kept until it completes, instead of
+    /// within the install directory. On the same volume as the install directory, these
+    /// are moved rather than copied.
+    #[serde(default)]
+    pub staging_dir: Option<String>,
+    /// Space which is left free on the install volume, in bytes. Installation pauses
+    /// until the user frees up space, rather than using this.
+    #[serde(default = "default_min_free_space")]
+    pub min_free_space: u64,
+    /// How long a non-interactive operation may run for, in seconds, before it is aborted
+    /// and rolled back
+    #[serde(default)]
+    pub install_timeout: Option<u64>,
+    /// How long a task may go without showing any progress, in seconds, before it is
+    /// flagged as stalled (see `heartbeat`). 0 never flags tasks.
+    #[serde(default = "default_stall_timeout")]
+    pub stall_timeout: u64,
+}
+
+fn default_worker_count() -> usize {
+    1
+}
+
+fn default_battery_rate() -> u64 {
+    1_000_000
+}
+
+fn default_command_timeout() -> u64 {
+    600
+}
+
+fn default_command_output_limit() -> usize {
+    1024 * 1024
+}
+
+fn default_download_retries() -> u32 {
+    3
+}
+
+fn default_min_free_space() -> u64 {
+    50 * 1024 * 1024
+}
+
+fn default_stall_timeout() -> u64 {
+    120
+}
+
+/// What to do with downloads while the machine is running on battery power.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BatteryPolicy {
+    /// Download as normal
+    Ignore,
+    /// Wait until the machine is plugged in again
+    Pause,
+    /// Limit the download rate
+    Throttle,
+}
+
+impl Default for BatteryPolicy {
+    fn default() -> Self {
+        BatteryPolicy::Ignore
+    }
+}
+
+impl Default for ResourceLimits {
+    fn default() -> Self {
+        ResourceLimits {
+            download_parallelism: default_worker_count(),
+            extraction_workers: default_worker_count(),
+            low_priority_io: false,
+            on_battery: BatteryPolicy::default(),
+            battery_download_rate: default_battery_rate(),
+            download_window: None,
+            command_timeout: default_command_timeout(),
+            command_output_limit: default_command_output_limit(),
+            download_retries: default_download_retries(),
+            staging_dir: None,
+            min_free_space: default_min_free_space(),
+            install_timeout: None,
+            stall_timeout: default_stall_timeout(),
+        }
+    }
+}
+
+/// A daily window of local time, written as `HH:MM`. Windows may wrap past midnight,
+/// e.g. from 22:00 to 06:00.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TimeWindow {
+    pub start: String,
+    pub end: String,
+}
+
+impl TimeWindow {
+    fn parse_time(time: &str) -> Result<NaiveTime, String> {
+        NaiveTime::parse_from_str(time, "%H:%M")
+            .map_err(|x| format!("Invalid time {:?} (expected HH:MM): {:?}", time, x))
+    }
+
+    /// Returns how long it is until this window opens, which is zero while it is open.
+    pub fn time_until_open(&self, now: NaiveTime) -> Result<Duration, String> {
+        let start = i64::from(Self::parse_time(&self.start)?.num_seconds_from_midnight());
+        let end = i64::from(Self::parse_time(&self.end)?.num_seconds_from_midnight());
+        let now = i64::from(now.num_seconds_from_midnight());
+
+        let open = if start <= end {
+            start <= now && now < end
+        } else {
+            now >= start || now < end
+        };
+
+        if open {
+            return Ok(Duration::from_secs(0));
+        }
+
+        let day = 24 * 60 * 60;
+        Ok(Duration::from_secs((((start - now) % day + day) % day) as u64))
+    }
+}
+
+/// Where newer builds of the installer (which also acts as the maintenance tool) are
+/// released, so that existing installations can update their copy.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SelfUpdate {
+    pub source: PackageSource,
+    /// How downloaded builds are verified before replacing the maintenance tool
+    #[serde(default)]
+    pub verification: Option<PackageVerification>,
+}
+
+/// Which shortcuts are created for packages' `shortcuts`.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ShortcutSettings {
+    /// Adds entries to the Start Menu (or the application menu on Linux)
+    #[serde(default = "default_true")]
+    pub start_menu: bool,
+    /// Offers to create shortcuts on the desktop
+    #[serde(default = "default_true")]
+    pub desktop: bool,
+    /// If the desktop shortcut option starts off checked
+    #[serde(default = "default_true")]
+    pub desktop_by_default: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for ShortcutSettings {
+    fn default() -> Self {
+        ShortcutSettings {
+            start_menu: true,
+            desktop: true,
+            desktop_by_default: true,
+        }
+    }
+}
+
+/// How files kept for undoing changes are stored.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct BackupSettings {
+    /// Compresses files kept for rolling back a failed install, as well as the backups of
+    /// files replaced by packages
+    #[serde(default)]
+    pub compress: bool,
+    /// zstd compression level, from 1 (fastest) to 19 (smallest)
+    #[serde(default = "default_compression_level")]
+    pub compression_level: i32,
+    /// Most space (in bytes) files kept for rolling back an install may take up. Once this
+    /// is exceeded, the oldest are discarded, and can't be restored if the install fails.
+    #[serde(default)]
+    pub max_rollback_size: Option<u64>,
+}
+
+fn default_compression_level() -> i32 {
+    3
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        BackupSettings {
+            compress: false,
+            compression_level: default_compression_level(),
+            max_rollback_size: None,
+        }
+    }
+}
+
+/// How large archive entries are written out.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct LargeFileSettings {
+    /// Entries at least this large (in bytes) are preallocated or written as sparse files
+    #[serde(default = "default_large_file_threshold")]
+    pub threshold: u64,
+    /// Writes entries which are mostly zeros (e.g. placeholder data files) as sparse files,
+    /// where the file system supports them. These can only be told apart in archives
+    /// which compress entries individually, such as .zip files.
+    #[serde(default = "default_true")]
+    pub sparse: bool,
+    /// Reserves space for other entries before writing them, to avoid fragmentation
+    #[serde(default = "default_true")]
+    pub preallocate: bool,
+}
+
+fn default_large_file_threshold() -> u64 {
+    64 * 1024 * 1024
+}
+
+impl Default for LargeFileSettings {
+    fn default() -> Self {
+        LargeFileSettings {
+            threshold: default_large_file_threshold(),
+            sparse: true,
+            preallocate: true,
+        }
+    }
+}
+
+/// Hard-links identical files instead of keeping copies of them (see `dedup`).
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DeduplicationSettings {
+    /// Links files identical to ones from packages installed side-by-side, or to backups.
+    /// Changing a linked file in place changes every copy of it.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Files smaller than this (in bytes) aren't worth linking
+    #[serde(default = "default_deduplication_min_size")]
+    pub min_size: u64,
+}
+
+fn default_deduplication_min_size() -> u64 {
+    64 * 1024
+}
+
+impl Default for DeduplicationSettings {
+    fn default() -> Self {
+        DeduplicationSettings {
+            enabled: false,
+            min_size: default_deduplication_min_size(),
+        }
+    }
+}
+
+/// Graphics drivers which are checked for before installing (see `gpu`). Vendors number
+/// their drivers differently, so each has its own minimum version.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct GpuRequirements {
+    /// Oldest supported NVIDIA driver, as NVIDIA numbers them (e.g. "531.18")
+    #[serde(default)]
+    pub nvidia: Option<String>,
+    /// Oldest supported AMD driver on Windows, as Radeon Software numbers them (e.g. "23.4.1")
+    #[serde(default)]
+    pub amd: Option<String>,
+    /// Oldest supported Intel driver on Windows (e.g. "31.0.101.4255")
+    #[serde(default)]
+    pub intel: Option<String>,
+    /// Oldest supported version of Mesa, which provides AMD and Intel drivers elsewhere
+    #[serde(default)]
+    pub mesa: Option<String>,
+    /// Also treats finding no NVIDIA, AMD or Intel GPU at all as a problem
+    #[serde(default)]
+    pub require_gpu: bool,
+    /// Refuses to install when the requirements aren't met, rather than only warning
+    #[serde(default)]
+    pub block: bool,
+    /// Shown along with any problems, e.g. where newer drivers can be downloaded from
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+/// Restricts who may modify or uninstall an installation, for managed machines (see
+/// `policy`). On Windows, this can also be set by group policy.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct MaintenancePolicy {
+    /// If the maintenance tool needs elevation, or a token, to modify or uninstall
+    #[serde(default)]
+    pub restricted: bool,
+    /// SHA-256 hash (as hex) of a token administrators can give out to allow this
+    /// without elevation
+    #[serde(default)]
+    pub token_sha256: Option<String>,
+}
+
+/// Installer frameworks whose installations can be taken over (see `migration`).
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ForeignFramework {
+    Nsis,
+    Inno,
+    Squirrel,
+}
+
+/// An installation made by another installer framework, which fresh installs take over
+/// (see `migration`).
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ForeignInstall {
+    pub framework: ForeignFramework,
+    /// Name of its uninstall entry: the name given in the NSIS script, `<AppId>_is1` for
+    /// Inno Setup, or the application's ID for Squirrel
+    pub uninstall_key: String,
+    /// The package its files are adopted into, which is then installed over them
+    pub package: String,
+    /// Regexes of paths (relative, with forward slashes) which are left alone, such as
+    /// user data
+    #[serde(default)]
+    pub keep: Vec<String>,
+}
+
+/// A product installed as part of a suite, as an installation of its own (see `suite`).
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SuiteProduct {
+    /// Name of the product, which its maintenance tool and "Apps & Features" entry use
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Where the product's own configuration is downloaded from
+    pub config_url: String,
+    /// Directory within the suite's install location to install into. Defaults to the
+    /// product's name.
+    #[serde(default)]
+    pub directory: Option<String>,
+    /// If the product is selected to begin with
+    #[serde(default)]
+    pub default: bool,
+}
+
+impl SuiteProduct {
+    /// Returns the directory within the suite's install location this is installed into.
+    pub fn get_directory(&self) -> &str {
+        self.directory.as_ref().unwrap_or(&self.name)
+    }
+}
+
+/// A page explaining an error, shown with it as "Learn more about this error" (see
+/// `error_help`).
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ErrorHelp {
+    /// One of the built-in error codes, or any code along with `match`
+    pub code: String,
+    /// Regex matched against error messages, instead of recognising the code's errors
+    #[serde(default, rename = "match")]
+    pub match_regex: Option<String>,
+    /// The page, which may contain `{locale}` for the language being shown
+    pub url: String,
+}
+
+/// Where privileged actions are recorded for compliance reviews (see `audit`).
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct AuditSettings {
+    /// Records privileged actions, such as registry changes and files written outside the
+    /// install directory
+    #[serde(default)]
+    pub enabled: bool,
+    /// Where the log is kept, in any form accepted by `locations::resolve`. Defaults to a
+    /// log named after the product in the machine-wide data directory (see
+    /// `audit::default_path`), which outlives the installation and isn't writable by
+    /// whoever uses it.
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Config {
     pub installing_message: String,
-    /// URL to a new updater, if required
+    /// URL to a new updater, if required. Filled in from `self_update` if a newer
+    /// build has been released.
     #[serde(default)]
     pub new_tool: Option<String>,
+    /// Where newer builds of the installer are released
+    #[serde(default)]
+    pub self_update: Option<SelfUpdate>,
+    #[serde(default)]
     pub packages: Vec<PackageDescription>,
     #[serde(default)]
     pub hide_advanced: bool,
     #[serde(default)]
     pub authentication: Option<AuthenticationConfig>,
+    #[serde(default)]
+    pub limits: ResourceLimits,
+    /// Local (e.g. LAN) cache servers to prefer for asset downloads
+    #[serde(default)]
+    pub cache_servers: Vec<String>,
+    /// Rewrites the URLs of assets returned by package sources
+    #[serde(default)]
+    pub url_rewrites: Vec<UrlRewrite>,
+    /// Discovers the install directory within another application
+    #[serde(default)]
+    pub host_application: Option<HostApplication>,
+    /// How files provided by more than one package are handled
+    #[serde(default)]
+    pub conflict_policy: ConflictPolicy,
+    /// How files which already exist, but weren't installed by any package, are handled
+    #[serde(default)]
+    pub existing_files: ExistingFileRules,
+    /// How archive entries which would overwrite each other on case-insensitive file
+    /// systems are handled
+    #[serde(default)]
+    pub case_collisions: CaseCollisionPolicy,
+    /// How archive entries whose names can't be used on Windows are handled
+    #[serde(default)]
+    pub invalid_names: InvalidNamePolicy,
+    /// Where to look for data left behind after uninstalling
+    #[serde(default)]
+    pub leftovers: LeftoverLocations,
+    /// Where the installed product keeps user data
+    #[serde(default)]
+    pub data_locations: Vec<DataLocation>,
+    /// Endpoints to notify of install events
+    #[serde(default)]
+    pub webhooks: Vec<Webhook>,
+    /// If installing onto network shares is permitted (with a warning), rather than refused
+    #[serde(default)]
+    pub allow_network_install: bool,
+    /// Proxy auto-config (PAC) script to select proxies with, instead of the system's
+    #[serde(default)]
+    pub proxy_pac_url: Option<String>,
+    /// Proxy to use instead of the system's, unless the user chooses another
+    #[serde(default)]
+    pub proxy: Option<ProxyConfig>,
+    /// DNS JSON API endpoint to resolve hosts with when the system's resolver fails
+    #[serde(default)]
+    pub dns_over_https: Option<String>,
+    /// Which shortcuts are created
+    #[serde(default)]
+    pub shortcuts: ShortcutSettings,
+    /// How applications holding installed files open are handled. Applications which
+    /// support it are restarted once the install has finished.
+    #[serde(default)]
+    pub locked_files: LockedFilePolicy,
+    /// How large archive entries are written out
+    #[serde(default)]
+    pub large_files: LargeFileSettings,
+    /// How files kept for undoing changes are stored
+    #[serde(default)]
+    pub backups: BackupSettings,
+    /// Hard-links identical files between packages and backups
+    #[serde(default)]
+    pub deduplication: DeduplicationSettings,
+    /// Audit log of privileged actions taken by the installer
+    #[serde(default)]
+    pub audit: AuditSettings,
+    /// Graphics driver versions needed by the application
+    #[serde(default)]
+    pub gpu: Option<GpuRequirements>,
+    /// Who may modify or uninstall the application
+    #[serde(default)]
+    pub maintenance_policy: MaintenancePolicy,
+    /// Pages explaining errors, by error code
+    #[serde(default)]
+    pub error_help: Vec<ErrorHelp>,
+    /// Installations made by other installer frameworks, which are taken over
+    #[serde(default)]
+    pub migrate_from: Vec<ForeignInstall>,
+    /// Products installed together, each as an installation of its own, instead of this
+    /// configuration having packages (see `suite`)
+    #[serde(default)]
+    pub suite: Vec<SuiteProduct>,
+    /// Alternative release feeds, such as for testing against staging
+    #[serde(default)]
+    pub environments: Vec<Environment>,
+    /// Starts downloading the first package selected by default while the welcome screen
+    /// is shown (see `prefetch`)
+    #[serde(default)]
+    pub prefetch: bool,
 }
 
 impl Config {
@@ -118,19 +1270,127 @@ impl Config {
     }
 
     /// Builds a configuration from a specified TOML string.
+    /// Packages are sorted by priority, keeping their declared order otherwise.
     pub fn from_toml_str(contents: &str) -> Result<Self, TomlError> {
-        toml::from_str(contents)
+        let mut config: Config = toml::from_str(contents)?;
+        config.packages.sort_by_key(|x| x.priority);
+        Ok(config)
+    }
+
+    /// Adds any packages required by the specified packages, and sorts them into the
+    /// order they should be installed in: requirements first, then by priority.
+    pub fn resolve_packages(&self, names: &[String]) -> Result<Vec<String>, String> {
+        let mut resolved = Vec::new();
+        let mut visiting = Vec::new();
+
+        // Packages are already sorted by priority, and requirements are pulled in
+        // ahead of whatever needs them
+        for package in &self.packages {
+            if names.contains(&package.name) {
+                self.visit_package(&package.name, &mut visiting, &mut resolved)?;
+            }
+        }
+
+        for name in names {
+            if !resolved.contains(name) {
+                return Err(format!("Package {:?} could not be found.", name));
+            }
+        }
+
+        Ok(resolved)
+    }
+
+    /// Returns how an existing file at the specified path within a package should be handled.
+    /// Package rules take precedence over global ones.
+    pub fn get_existing_file_policy(
+        &self,
+        package: &PackageDescription,
+        path: &str,
+    ) -> ExistingFilePolicy {
+        package
+            .existing_files
+            .find_rule(path)
+            .or_else(|| self.existing_files.find_rule(path))
+            .or_else(|| package.existing_files.default.clone())
+            .or_else(|| self.existing_files.default.clone())
+            .unwrap_or_default()
+    }
+
+    fn visit_package(
+        &self,
+        name: &str,
+        visiting: &mut Vec<String>,
+        resolved: &mut Vec<String>,
+    ) -> Result<(), String> {
+        if resolved.iter().any(|x| x == name) {
+            return Ok(());
+        }
+
+        if visiting.iter().any(|x| x == name) {
+            return Err(format!(
+                "Packages have circular requirements: {} -> {}",
+                visiting.join(" -> "),
+                name
+            ));
+        }
+
+        let package = self
+            .packages
+            .iter()
+            .find(|x| x.name == name)
+            .ok_or_else(|| format!("Required package {:?} could not be found.", name))?;
+
+        visiting.push(name.to_string());
+        for requirement in &package.requires {
+            self.visit_package(requirement, visiting, resolved)?;
+        }
+        visiting.pop();
+
+        resolved.push(name.to_string());
+        Ok(())
     }
 }
 
 impl PackageSource {
-    /// Fetches releases for a given package
-    pub fn get_current_releases(&self) -> Result<Vec<Release>, String> {
+    /// Fetches releases for a given package, rewriting their URLs with the first matching
+    /// rule for this source (if any).
+    pub fn get_current_releases(&self, rewrites: &[UrlRewrite]) -> Result<Vec<Release>, String> {
         let package_handler = match get_by_name(&self.name) {
             Some(v) => v,
             _ => return Err(format!("Handler {} not found", self.name)),
         };
 
-        package_handler.get_current_releases(&self.config)
+        let mut releases =
+            RESOLVER.get_releases(&self.name, package_handler.as_ref(), &self.config)?;
+
+        let mut rules = Vec::new();
+        for rewrite in rewrites {
+            if rewrite.source.as_ref().map_or(false, |x| *x != self.name) {
+                continue;
+            }
+
+            let regex = Regex::new(&rewrite.pattern).map_err(|x| {
+                format!("Invalid URL rewrite pattern {:?}: {:?}", rewrite.pattern, x)
+            })?;
+            rules.push((regex, rewrite.replacement.as_str()));
+        }
+
+        let rewrite = |url: &mut String| {
+            if let Some((regex, replacement)) = rules.iter().find(|x| x.0.is_match(url)) {
+                let rewritten = regex.replace(url, *replacement).to_string();
+                debug!("Rewrote {:?} to {:?}", url, rewritten);
+                *url = rewritten;
+            }
+        };
+
+        for file in releases.iter_mut().flat_map(|x| x.files.iter_mut()) {
+            rewrite(&mut file.url);
+            file.parts.iter_mut().for_each(&rewrite);
+            if let Some(ref mut url) = file.chunk_index {
+                rewrite(url);
+            }
+        }
+
+        Ok(releases)
     }
 }
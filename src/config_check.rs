@@ -0,0 +1,607 @@
+//! config_check.rs
+//!
+//! Validates an installer configuration before it ships, so that mistakes such as invalid
+//! regexes, broken URLs or missing icons are caught by the publisher instead of by users.
+//! Each problem is reported with the line and key it was found at.
+
+use std::collections::HashSet;
+
+use chrono::NaiveTime;
+
+use regex::Regex;
+
+use semver::VersionReq;
+
+use url::Url;
+
+use config::{Config, Detection};
+
+use cpu;
+
+use error_help;
+
+use frontend::rest::assets;
+
+use gpu;
+
+use http;
+
+use sources::get_by_name;
+
+/// Finds the first line containing a value, for pointing users at problems.
+fn line_of(contents: &str, value: &str) -> Option<usize> {
+    if value.is_empty() {
+        return None;
+    }
+
+    contents
+        .lines()
+        .position(|x| x.contains(value))
+        .map(|x| x + 1)
+}
+
+/// Collects problems, along with where they were found.
+struct Problems<'a> {
+    file_name: &'a str,
+    contents: &'a str,
+    found: Vec<String>,
+}
+
+impl<'a> Problems<'a> {
+    /// Records a problem with the value at the specified key.
+    fn add(&mut self, key: &str, value: &str, message: String) {
+        let location = match line_of(self.contents, value) {
+            Some(line) => format!("{}:{}", self.file_name, line),
+            None => self.file_name.to_string(),
+        };
+
+        self.found
+            .push(format!("{}: {}: {}", location, key, message));
+    }
+
+    fn check_url(&mut self, key: &str, url: &str) {
+        if let Err(v) = Url::parse(url) {
+            self.add(key, url, format!("Invalid URL {:?}: {}", url, v));
+        }
+    }
+
+    fn check_detection(&mut self, key: &str, detection: &Detection) {
+        let sources = [&detection.registry, &detection.file, &detection.command];
+        if sources.iter().filter(|x| x.is_some()).count() != 1 {
+            self.add(
+                key,
+                "",
+                "Needs exactly one of registry, file or command".to_string(),
+            );
+        }
+
+        if let Err(v) = Regex::new(&detection.pattern) {
+            self.add(
+                &format!("{}.pattern", key),
+                &detection.pattern,
+                format!("Invalid regex: {}", v),
+            );
+        }
+
+        if let Some(ref requirement) = detection.version {
+            if let Err(v) = VersionReq::parse(requirement) {
+                self.add(
+                    &format!("{}.version", key),
+                    requirement,
+                    format!("Invalid version requirement: {:?}", v),
+                );
+            }
+        }
+    }
+}
+
+/// Checks a configuration file, returning a description of each problem found.
+pub fn check_config(file_name: &str, contents: &str) -> Vec<String> {
+    // Packages are kept in their declared order, so that indexes match the file
+    let config: Config = match toml::from_str(contents) {
+        Ok(v) => v,
+        Err(v) => return vec![format!("{}: {}", file_name, v)],
+    };
+
+    let mut problems = Problems {
+        file_name,
+        contents,
+        found: Vec::new(),
+    };
+
+    if let Some(ref url) = config.new_tool {
+        problems.check_url("new_tool", url);
+    }
+
+    if let Some(ref update) = config.self_update {
+        if get_by_name(&update.source.name).is_none() {
+            problems.add(
+                "self_update.source.name",
+                &update.source.name,
+                format!("Unknown source {:?}", update.source.name),
+            );
+        }
+
+        if let Err(v) = Regex::new(&update.source.match_regex) {
+            problems.add(
+                "self_update.source.match",
+                &update.source.match_regex,
+                format!("Invalid regex: {}", v),
+            );
+        }
+    }
+
+    for (i, url) in config.cache_servers.iter().enumerate() {
+        problems.check_url(&format!("cache_servers[{}]", i), url);
+    }
+
+    for (i, rewrite) in config.url_rewrites.iter().enumerate() {
+        if let Err(v) = Regex::new(&rewrite.pattern) {
+            problems.add(
+                &format!("url_rewrites[{}].pattern", i),
+                &rewrite.pattern,
+                format!("Invalid regex: {}", v),
+            );
+        }
+    }
+
+    if let Some(ref url) = config.proxy_pac_url {
+        problems.check_url("proxy_pac_url", url);
+    }
+
+    if let Some(ref proxy) = config.proxy {
+        if let Err(v) = http::check_proxy(proxy) {
+            problems.add("proxy.url", &proxy.url, v);
+        }
+    }
+
+    if let Some(ref url) = config.dns_over_https {
+        problems.check_url("dns_over_https", url);
+    }
+
+    for (i, webhook) in config.webhooks.iter().enumerate() {
+        problems.check_url(&format!("webhooks[{}].url", i), &webhook.url);
+    }
+
+    if let Some(ref authentication) = config.authentication {
+        problems.check_url("authentication.auth_url", &authentication.auth_url);
+
+        if let Err(v) = base64::decode(&authentication.pub_key_base64) {
+            problems.add(
+                "authentication.pub_key_base64",
+                &authentication.pub_key_base64,
+                format!("Invalid base64: {:?}", v),
+            );
+        }
+    }
+
+    if let Some(ref window) = config.limits.download_window {
+        if let Err(v) = window.time_until_open(NaiveTime::from_hms(0, 0, 0)) {
+            problems.add("limits.download_window", &window.start, v);
+        }
+    }
+
+    if let Some(ref requirements) = config.gpu {
+        let versions = [
+            ("nvidia", &requirements.nvidia),
+            ("amd", &requirements.amd),
+            ("intel", &requirements.intel),
+            ("mesa", &requirements.mesa),
+        ];
+        for &(vendor, version) in &versions {
+            if let Some(ref version) = *version {
+                if !gpu::is_valid_version(version) {
+                    problems.add(
+                        &format!("gpu.{}", vendor),
+                        version,
+                        format!("Invalid driver version {:?}", version),
+                    );
+                }
+            }
+        }
+    }
+
+    if let Some(ref hash) = config.maintenance_policy.token_sha256 {
+        let hash = hash.trim();
+        if hash.len() != 64 || !hash.chars().all(|x| x.is_ascii_hexdigit()) {
+            problems.add(
+                "maintenance_policy.token_sha256",
+                hash,
+                "Expected a SHA-256 hash, as 64 hex digits".to_string(),
+            );
+        }
+    }
+
+    for (i, help) in config.error_help.iter().enumerate() {
+        let key = format!("error_help[{}]", i);
+        problems.check_url(&format!("{}.url", key), &help.url.replace("{locale}", "en"));
+
+        match help.match_regex {
+            Some(ref pattern) => {
+                if let Err(v) = Regex::new(pattern) {
+                    problems.add(
+                        &format!("{}.match", key),
+                        pattern,
+                        format!("Invalid regex: {}", v),
+                    );
+                }
+            }
+            None if !error_help::is_built_in(&help.code) => {
+                problems.add(
+                    &format!("{}.code", key),
+                    &help.code,
+                    "Not a built-in error code, so needs a match regex".to_string(),
+                );
+            }
+            None => {}
+        }
+    }
+
+    for (i, migration) in config.migrate_from.iter().enumerate() {
+        let key = format!("migrate_from[{}]", i);
+        if !config.packages.iter().any(|x| x.name == migration.package) {
+            problems.add(
+                &format!("{}.package", key),
+                &migration.package,
+                format!("Unknown package {:?}", migration.package),
+            );
+        }
+
+        for (j, pattern) in migration.keep.iter().enumerate() {
+            if let Err(v) = Regex::new(pattern) {
+                problems.add(
+                    &format!("{}.keep[{}]", key, j),
+                    pattern,
+                    format!("Invalid regex: {}", v),
+                );
+            }
+        }
+    }
+
+    if !config.suite.is_empty() && !config.packages.is_empty() {
+        problems.add(
+            "packages",
+            "",
+            "Suites install their products' packages, so can't have their own".to_string(),
+        );
+    }
+
+    let mut product_names = HashSet::new();
+    let mut product_directories = HashSet::new();
+    for (i, product) in config.suite.iter().enumerate() {
+        let key = format!("suite[{}]", i);
+        if !product_names.insert(product.name.as_str()) {
+            problems.add(
+                &format!("{}.name", key),
+                &product.name,
+                format!("Duplicate product {:?}", product.name),
+            );
+        }
+
+        problems.check_url(&format!("{}.config_url", key), &product.config_url);
+
+        // Products are maintained separately, so can't share a directory
+        let directory = product.get_directory();
+        let is_relative = !directory.is_empty()
+            && !directory.starts_with('/')
+            && !directory.starts_with('\\')
+            && !directory.contains(':')
+            && !directory
+                .split(|x| x == '/' || x == '\\')
+                .any(|x| x == "..");
+        if !is_relative {
+            problems.add(
+                &format!("{}.directory", key),
+                directory,
+                "Needs to be a directory within the suite's install location".to_string(),
+            );
+        } else if !product_directories.insert(directory.to_lowercase()) {
+            problems.add(
+                &format!("{}.directory", key),
+                directory,
+                "Installed to the same directory as another product".to_string(),
+            );
+        }
+    }
+
+    let mut environment_names = HashSet::new();
+    for (i, environment) in config.environments.iter().enumerate() {
+        let key = format!("environments[{}]", i);
+        if !environment_names.insert(environment.name.as_str()) {
+            problems.add(
+                &format!("{}.name", key),
+                &environment.name,
+                format!("Duplicate environment {:?}", environment.name),
+            );
+        }
+
+        for (name, values) in &environment.sources {
+            let source_key = format!("{}.sources.{}", key, name);
+            if !config.packages.iter().any(|x| &x.name == name) {
+                problems.add(&source_key, name, format!("Unknown package {:?}", name));
+            } else if !values.is_table() {
+                problems.add(&source_key, name, "Needs to be a table".to_string());
+            }
+        }
+
+        if let Some(ref channel) = environment.channel {
+            let exists = config
+                .packages
+                .iter()
+                .any(|x| x.channels.iter().any(|x| &x.name == channel));
+            if !exists {
+                problems.add(
+                    &format!("{}.channel", key),
+                    channel,
+                    format!("No package has a {:?} channel", channel),
+                );
+            }
+        }
+
+        for (j, rewrite) in environment.url_rewrites.iter().enumerate() {
+            if let Err(v) = Regex::new(&rewrite.pattern) {
+                problems.add(
+                    &format!("{}.url_rewrites[{}].pattern", key, j),
+                    &rewrite.pattern,
+                    format!("Invalid regex: {}", v),
+                );
+            }
+        }
+
+        for (j, webhook) in environment.webhooks.iter().flatten().enumerate() {
+            problems.check_url(&format!("{}.webhooks[{}].url", key, j), &webhook.url);
+        }
+    }
+
+    let mut data_names = HashSet::new();
+    for (i, location) in config.data_locations.iter().enumerate() {
+        let key = format!("data_locations[{}].name", i);
+        if !data_names.insert(location.name.as_str()) {
+            problems.add(&key, &location.name, "Duplicate data location".to_string());
+        }
+
+        let valid_name = location
+            .name
+            .chars()
+            .all(|x| x.is_ascii_alphanumeric() || x == '_' || x == '-');
+        if location.name.is_empty() || !valid_name {
+            problems.add(
+                &key,
+                &location.name,
+                "Names may only contain letters, digits, '_' and '-'".to_string(),
+            );
+        }
+    }
+
+    let names: Vec<String> = config.packages.iter().map(|x| x.name.clone()).collect();
+    let mut seen_names = HashSet::new();
+    let mut missing_requirements = false;
+
+    for (i, package) in config.packages.iter().enumerate() {
+        let key = format!("packages[{}]", i);
+
+        if !seen_names.insert(package.name.as_str()) {
+            problems.add(
+                &format!("{}.name", key),
+                &package.name,
+                format!("Duplicate package {:?}", package.name),
+            );
+        }
+
+        if get_by_name(&package.source.name).is_none() {
+            problems.add(
+                &format!("{}.source.name", key),
+                &package.source.name,
+                format!("Unknown source {:?}", package.source.name),
+            );
+        }
+
+        if let Err(v) = Regex::new(&package.source.match_regex) {
+            problems.add(
+                &format!("{}.source.match", key),
+                &package.source.match_regex,
+                format!("Invalid regex: {}", v),
+            );
+        }
+
+        for (locale, pattern) in &package.source.locale_match {
+            if let Err(v) = Regex::new(pattern) {
+                problems.add(
+                    &format!("{}.source.locale_match.{}", key, locale),
+                    pattern,
+                    format!("Invalid regex: {}", v),
+                );
+            }
+        }
+
+        let select = &package.source.select;
+        for (i, pattern) in select.prefer.iter().enumerate() {
+            if let Err(v) = Regex::new(pattern) {
+                problems.add(
+                    &format!("{}.source.select.prefer[{}]", key, i),
+                    pattern,
+                    format!("Invalid regex: {}", v),
+                );
+            }
+        }
+
+        if let (Some(min), Some(max)) = (select.min_size, select.max_size) {
+            if min > max {
+                problems.add(
+                    &format!("{}.source.select.min_size", key),
+                    &min.to_string(),
+                    format!("Larger than max_size ({})", max),
+                );
+            }
+        }
+
+        if let Some(ref auth) = package.source.auth {
+            if auth.env.is_none() && auth.token.is_none() && !auth.prompt {
+                problems.add(
+                    &format!("{}.source.auth", key),
+                    "",
+                    "Needs at least one of env, token or prompt".to_string(),
+                );
+            }
+        }
+
+        let mut seen_channels = HashSet::new();
+        for (j, channel) in package.channels.iter().enumerate() {
+            let channel_key = format!("{}.channels[{}]", key, j);
+
+            if !seen_channels.insert(channel.name.as_str()) {
+                problems.add(
+                    &format!("{}.name", channel_key),
+                    &channel.name,
+                    format!("Duplicate channel {:?}", channel.name),
+                );
+            }
+
+            if let Some(ref regex) = channel.match_regex {
+                if let Err(v) = Regex::new(regex) {
+                    problems.add(
+                        &format!("{}.match", channel_key),
+                        regex,
+                        format!("Invalid regex: {}", v),
+                    );
+                }
+            }
+
+            if let Some(ref requirement) = channel.version {
+                if let Err(v) = VersionReq::parse(requirement) {
+                    problems.add(
+                        &format!("{}.version", channel_key),
+                        requirement,
+                        format!("Invalid version requirement: {:?}", v),
+                    );
+                }
+            }
+        }
+
+        for requirement in &package.requires {
+            if !names.contains(requirement) {
+                missing_requirements = true;
+                problems.add(
+                    &format!("{}.requires", key),
+                    requirement,
+                    format!("Unknown package {:?}", requirement),
+                );
+            }
+        }
+
+        if let Some(ref icon) = package.icon {
+            if assets::file_from_string(&format!("/{}", icon)).is_none() {
+                problems.add(
+                    &format!("{}.icon", key),
+                    icon,
+                    format!("{:?} isn't bundled with the installer", icon),
+                );
+            }
+        }
+
+        for (j, shortcut) in package.shortcuts.iter().enumerate() {
+            if shortcut.relative_path.is_empty() {
+                problems.add(
+                    &format!("{}.shortcuts[{}].relative_path", key, j),
+                    &shortcut.name,
+                    "Shortcuts need a path to point at".to_string(),
+                );
+            }
+        }
+
+        if let Some(ref detection) = package.detect {
+            problems.check_detection(&format!("{}.detect", key), detection);
+        }
+
+        if let Some(ref test) = package.smoke_test {
+            if test.command.is_empty() {
+                problems.add(
+                    &format!("{}.smoke_test.command", key),
+                    &package.name,
+                    "Smoke tests need a program to run".to_string(),
+                );
+            }
+
+            if let Some(ref pattern) = test.pattern {
+                if let Err(v) = Regex::new(pattern) {
+                    problems.add(
+                        &format!("{}.smoke_test.pattern", key),
+                        pattern,
+                        format!("Invalid regex: {}", v),
+                    );
+                }
+            }
+        }
+
+        let hooks = [
+            ("post_install", &package.hooks.post_install),
+            ("pre_uninstall", &package.hooks.pre_uninstall),
+        ];
+        for &(point, commands) in &hooks {
+            for (j, hook) in commands.iter().enumerate() {
+                if let Some(ref detection) = hook.skip_if {
+                    problems.check_detection(
+                        &format!("{}.hooks.{}[{}].skip_if", key, point, j),
+                        detection,
+                    );
+                }
+            }
+        }
+
+        for feature in &package.cpu_features {
+            if !cpu::KNOWN_FEATURES.contains(&feature.to_lowercase().as_str()) {
+                problems.add(
+                    &format!("{}.cpu_features", key),
+                    feature,
+                    format!("Unknown CPU feature {:?}", feature),
+                );
+            }
+        }
+
+        if let Some(ref fallback) = package.cpu_fallback {
+            if !names.contains(fallback) {
+                problems.add(
+                    &format!("{}.cpu_fallback", key),
+                    fallback,
+                    format!("Unknown package {:?}", fallback),
+                );
+            } else if fallback == &package.name {
+                problems.add(
+                    &format!("{}.cpu_fallback", key),
+                    fallback,
+                    "Packages can't fall back to themselves".to_string(),
+                );
+            }
+        }
+
+        let mut seen_links = HashSet::new();
+        for (j, link) in package.links.iter().enumerate() {
+            let link_key = format!("{}.links[{}]", key, j);
+
+            if link.path.is_empty() || link.target.is_empty() {
+                problems.add(
+                    &link_key,
+                    &link.path,
+                    "Links need both a path and a target".to_string(),
+                );
+            }
+
+            if !seen_links.insert(link.path.as_str()) {
+                problems.add(
+                    &format!("{}.path", link_key),
+                    &link.path,
+                    format!("Duplicate link {:?}", link.path),
+                );
+            }
+        }
+    }
+
+    // Catches requirement cycles
+    if !missing_requirements {
+        if let Err(v) = config.resolve_packages(&names) {
+            problems.add("packages", "", v);
+        }
+    }
+
+    problems.found
+}
@@ -0,0 +1,177 @@
+//! config_diff.rs
+//!
+//! Publisher usage - compares a configuration against an earlier version of it (or against
+//! what an installation recorded in its metadata.json), describing what an update would
+//! add, remove or change. This lets config edits be reviewed before they are published to
+//! `target_url`, where every existing installation picks them up.
+//!
+//! Each difference is described on its own line, starting with `+` for additions, `-` for
+//! removals and `~` for changes.
+
+use std::collections::BTreeSet;
+
+use serde_json::Value;
+
+use config::{Config, PackageDescription};
+
+use installer::InstallationDatabase;
+
+/// Renders a value compactly, for showing what it was changed from and to.
+fn describe(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => "(unset)".to_string(),
+        Some(v) => serde_json::to_string(v).unwrap_or_else(|_| format!("{:?}", v)),
+    }
+}
+
+/// Compares two serialized objects field by field, skipping any fields listed in `skip`.
+fn diff_fields(prefix: &str, old: &Value, new: &Value, skip: &[&str], changes: &mut Vec<String>) {
+    let empty = Default::default();
+    let old = old.as_object().unwrap_or(&empty);
+    let new = new.as_object().unwrap_or(&empty);
+
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+
+    for key in keys {
+        if skip.contains(&key.as_str()) || old.get(key) == new.get(key) {
+            continue;
+        }
+
+        changes.push(format!(
+            "~ {}{} changed from {} to {}",
+            prefix,
+            key,
+            describe(old.get(key)),
+            describe(new.get(key))
+        ));
+    }
+}
+
+fn to_value<T: ::serde::Serialize>(value: &T) -> Result<Value, String> {
+    serde_json::to_value(value).map_err(|x| format!("Unable to render configuration: {:?}", x))
+}
+
+fn find_package<'a>(config: &'a Config, name: &str) -> Option<&'a PackageDescription> {
+    config.packages.iter().find(|x| x.name == name)
+}
+
+/// Describes what changes between two versions of a configuration.
+pub fn diff_configs(old: &Config, new: &Config) -> Result<Vec<String>, String> {
+    let mut changes = Vec::new();
+
+    diff_fields(
+        "",
+        &to_value(old)?,
+        &to_value(new)?,
+        &["packages"],
+        &mut changes,
+    );
+
+    for package in &new.packages {
+        match find_package(old, &package.name) {
+            Some(previous) => diff_fields(
+                &format!("{}: ", package.name),
+                &to_value(previous)?,
+                &to_value(package)?,
+                &[],
+                &mut changes,
+            ),
+            None => changes.push(format!(
+                "+ package {:?} added{}",
+                package.name,
+                if package.default.unwrap_or(false) {
+                    " (installed by default)"
+                } else {
+                    ""
+                }
+            )),
+        }
+    }
+
+    for package in &old.packages {
+        if find_package(new, &package.name).is_none() {
+            changes.push(format!("- package {:?} removed", package.name));
+        }
+    }
+
+    Ok(changes)
+}
+
+/// Describes what updating an installation to a configuration would do.
+pub fn diff_installation(
+    database: &InstallationDatabase,
+    new: &Config,
+) -> Result<Vec<String>, String> {
+    let mut changes = Vec::new();
+    let mut kept = Vec::new();
+
+    for installed in &database.packages {
+        let package = match find_package(new, &installed.name) {
+            Some(v) => v,
+            None => {
+                changes.push(format!(
+                    "- package {:?} ({}) is installed, but no longer in the configuration. \
+                     Updates will fail until it is uninstalled.",
+                    installed.name, installed.version
+                ));
+                continue;
+            }
+        };
+
+        kept.push(installed.name.clone());
+
+        // Roots are recorded once resolved, so can only be told apart from the main
+        // install directory
+        if package.install_root.is_some() != installed.root.is_some() {
+            changes.push(format!(
+                "~ {}: install_root changed from {} to {}",
+                package.name,
+                installed.root.as_ref().map_or("(unset)", |x| x.as_str()),
+                package
+                    .install_root
+                    .as_ref()
+                    .map_or("(unset)", |x| x.as_str())
+            ));
+        }
+
+        let chosen = database.channels.get(&installed.name).map(|x| x.as_str());
+        if let Some(name) = chosen {
+            if !package.channels.iter().any(|x| x.name == name) {
+                changes.push(format!(
+                    "~ {}: chosen channel {:?} no longer exists, so {} will be used",
+                    package.name,
+                    name,
+                    package
+                        .get_channel(None)
+                        .map_or("no channel".to_string(), |x| format!("{:?}", x.name))
+                ));
+            }
+        }
+
+        if let Some(channel) = package.get_channel(chosen) {
+            if !channel.accepts(&installed.version)? {
+                changes.push(format!(
+                    "~ {}: installed version {} is outside of channel {:?}, and will be \
+                     replaced",
+                    package.name, installed.version, channel.name
+                ));
+            }
+        }
+
+        for required in &package.requires {
+            if !installed.requires.contains(required) {
+                changes.push(format!("~ {}: now requires {:?}", package.name, required));
+            }
+        }
+    }
+
+    let resolved = new.resolve_packages(&kept)?;
+    for name in resolved.iter().filter(|x| !kept.contains(x)) {
+        changes.push(format!(
+            "+ package {:?} will be installed, as it is now required",
+            name
+        ));
+    }
+
+    Ok(changes)
+}
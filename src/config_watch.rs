@@ -0,0 +1,108 @@
+//! config_watch.rs
+//!
+//! In debug builds, a `config.toml` in the working directory is used instead of the
+//! configuration at `target_url`, and is reloaded whenever it changes. This lets installer
+//! authors iterate on packages and branding without restarting the installer.
+
+use std::env;
+use std::fs::{metadata, read_to_string};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use events::{self, Event};
+
+use installer::InstallerFramework;
+
+use sources::resolver::RESOLVER;
+
+use logging::LoggingErrors;
+
+/// Name of the local configuration file.
+const CONFIG_FILE: &str = "config.toml";
+/// How often the configuration is checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+lazy_static! {
+    /// Frontends waiting to hear about reloads.
+    static ref LISTENERS: Mutex<Vec<Sender<()>>> = Mutex::new(Vec::new());
+}
+
+/// Returns the local configuration to use instead of `target_url`, if this is a debug build
+/// and one exists.
+pub fn local_config_path() -> Option<PathBuf> {
+    if !cfg!(debug_assertions) {
+        return None;
+    }
+
+    let path = env::current_dir().ok()?.join(CONFIG_FILE);
+    if path.is_file() {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+/// Returns a channel which receives a message every time the configuration is reloaded.
+pub fn subscribe() -> Receiver<()> {
+    let (sender, receiver) = channel();
+    LISTENERS
+        .lock()
+        .log_expect("Config listeners have been dirtied")
+        .push(sender);
+    receiver
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    metadata(path).and_then(|x| x.modified()).ok()
+}
+
+/// Starts watching the local configuration, if there is one.
+pub fn spawn(framework: Arc<RwLock<InstallerFramework>>) {
+    let path = match local_config_path() {
+        Some(v) => v,
+        None => return,
+    };
+
+    info!("Watching {:?} for changes", path);
+
+    thread::spawn(move || {
+        let mut last_modified = modified_time(&path);
+
+        loop {
+            thread::sleep(POLL_INTERVAL);
+
+            let modified = modified_time(&path);
+            if modified == last_modified {
+                continue;
+            }
+            last_modified = modified;
+
+            let result = read_to_string(&path)
+                .map_err(|x| format!("Failed to read configuration: {:?}", x))
+                .and_then(|text| {
+                    framework
+                        .write()
+                        .log_expect("InstallerFramework has been dirtied")
+                        .load_config(&text)
+                });
+
+            // Keep the previous configuration until the file is fixed
+            if let Err(v) = result {
+                error!("Unable to reload {:?}: {}", path, v);
+                continue;
+            }
+
+            info!("Reloaded {:?}", path);
+            RESOLVER.clear();
+            events::publish(Event::ConfigReloaded);
+
+            LISTENERS
+                .lock()
+                .log_expect("Config listeners have been dirtied")
+                .retain(|x| x.send(()).is_ok());
+        }
+    });
+}
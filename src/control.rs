@@ -0,0 +1,103 @@
+//! control.rs
+//!
+//! Lets the frontend pause or cancel the operation in progress. Operations hold the
+//! framework locked while they run, so this state is kept separately.
+//!
+//! Only operations running within a transaction can be cancelled, as only they can undo
+//! what they had done so far. Stopping anything else (e.g. an uninstall) part of the way
+//! through would just leave it half done.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// Returned by tasks which stopped as the operation was cancelled.
+pub const CANCELLED: &str = "The operation was cancelled";
+
+/// How often a paused operation checks if it has been resumed.
+const PAUSE_INTERVAL: Duration = Duration::from_millis(250);
+
+static CANCEL_REQUESTED: AtomicBool = AtomicBool::new(false);
+static PAUSE_REQUESTED: AtomicBool = AtomicBool::new(false);
+static CANCELLABLE: AtomicBool = AtomicBool::new(false);
+
+/// Clears any previous requests, as a new operation starts.
+pub fn reset() {
+    CANCEL_REQUESTED.store(false, Ordering::SeqCst);
+    PAUSE_REQUESTED.store(false, Ordering::SeqCst);
+}
+
+/// Sets if the running operation can be undone, and so be cancelled by the user.
+pub fn set_cancellable(cancellable: bool) {
+    CANCELLABLE.store(cancellable, Ordering::SeqCst);
+}
+
+/// Returns if the running operation can be cancelled by the user.
+pub fn is_cancellable() -> bool {
+    CANCELLABLE.load(Ordering::SeqCst)
+}
+
+/// Asks the running operation to stop at the next opportunity.
+pub fn cancel() {
+    info!("Cancelling the current operation");
+    CANCEL_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Cancels the running operation on behalf of the user, if it can be undone. Returns if
+/// it was cancelled.
+pub fn cancel_if_cancellable() -> bool {
+    if !is_cancellable() {
+        info!("Not cancelling the current operation, as it can't be undone");
+        return false;
+    }
+
+    cancel();
+    true
+}
+
+/// Pauses or resumes downloads within the running operation.
+pub fn set_paused(paused: bool) {
+    info!(
+        "{} the current operation",
+        if paused { "Pausing" } else { "Resuming" }
+    );
+    PAUSE_REQUESTED.store(paused, Ordering::SeqCst);
+}
+
+/// Returns if the running operation has been paused.
+pub fn is_paused() -> bool {
+    PAUSE_REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Returns if the running operation has been asked to stop.
+pub fn is_cancelled() -> bool {
+    CANCEL_REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Returns an error if the running operation has been cancelled, for tasks to stop with.
+pub fn check() -> Result<(), String> {
+    if is_cancelled() {
+        Err(CANCELLED.to_string())
+    } else {
+        Ok(())
+    }
+}
+
+/// Blocks while the running operation is paused, calling `notify` as it waits. Returns
+/// if it was paused at all. Cancelling also ends the pause.
+pub fn wait_while_paused<F>(notify: F) -> bool
+where
+    F: Fn(),
+{
+    let mut paused = false;
+
+    while PAUSE_REQUESTED.load(Ordering::SeqCst) && !is_cancelled() {
+        if !paused {
+            notify();
+            paused = true;
+        }
+        thread::sleep(PAUSE_INTERVAL);
+    }
+
+    paused
+}
@@ -0,0 +1,124 @@
+//! cpu.rs
+//!
+//! Keeps builds which need CPU features (such as AVX2) off processors without them, which
+//! would otherwise crash with illegal instructions once installed. Packages declare what
+//! they need with `cpu_features`. On processors missing any of them, the package can't be
+//! selected, and its `cpu_fallback` (e.g. a build without AVX2) is used in its place.
+//! Installations of it are moved over to the fallback the next time they are updated.
+
+use config::Config;
+
+use error_help;
+
+use strings;
+
+/// Feature names which can be required, as used by `is_x86_feature_detected`.
+pub const KNOWN_FEATURES: [&str; 16] = [
+    "sse2",
+    "sse3",
+    "ssse3",
+    "sse4.1",
+    "sse4.2",
+    "popcnt",
+    "aes",
+    "pclmulqdq",
+    "avx",
+    "avx2",
+    "fma",
+    "f16c",
+    "bmi1",
+    "bmi2",
+    "lzcnt",
+    "avx512f",
+];
+
+/// Returns if this processor supports a feature. Features of other architectures aren't
+/// supported.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub fn has_feature(name: &str) -> bool {
+    match name {
+        "sse2" => is_x86_feature_detected!("sse2"),
+        "sse3" => is_x86_feature_detected!("sse3"),
+        "ssse3" => is_x86_feature_detected!("ssse3"),
+        "sse4.1" => is_x86_feature_detected!("sse4.1"),
+        "sse4.2" => is_x86_feature_detected!("sse4.2"),
+        "popcnt" => is_x86_feature_detected!("popcnt"),
+        "aes" => is_x86_feature_detected!("aes"),
+        "pclmulqdq" => is_x86_feature_detected!("pclmulqdq"),
+        "avx" => is_x86_feature_detected!("avx"),
+        "avx2" => is_x86_feature_detected!("avx2"),
+        "fma" => is_x86_feature_detected!("fma"),
+        "f16c" => is_x86_feature_detected!("f16c"),
+        "bmi1" => is_x86_feature_detected!("bmi1"),
+        "bmi2" => is_x86_feature_detected!("bmi2"),
+        "lzcnt" => is_x86_feature_detected!("lzcnt"),
+        "avx512f" => is_x86_feature_detected!("avx512f"),
+        _ => false,
+    }
+}
+
+/// Returns if this processor supports a feature. Features of other architectures aren't
+/// supported.
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+pub fn has_feature(_name: &str) -> bool {
+    false
+}
+
+/// Returns which of the specified features this processor is missing.
+pub fn missing_features(required: &[String]) -> Vec<String> {
+    required
+        .iter()
+        .filter(|x| !has_feature(&x.to_lowercase()))
+        .cloned()
+        .collect()
+}
+
+/// Marks packages this processor can't run as unsupported, putting their fallbacks in
+/// their place.
+pub fn apply(config: &mut Config) {
+    let mut replaced = Vec::new();
+
+    for package in &mut config.packages {
+        let missing = missing_features(&package.cpu_features);
+        if missing.is_empty() {
+            continue;
+        }
+
+        let reason = error_help::coded(
+            "unsupported_system",
+            strings::format(
+                "errors.cpu_unsupported",
+                &[
+                    ("package", &package.name),
+                    ("features", &missing.join(", ")),
+                ],
+            ),
+        );
+        warn!("{}", reason);
+
+        package.unsupported = Some(reason);
+
+        let was_default = package.default.unwrap_or(false);
+        package.default = Some(false);
+
+        if let Some(ref fallback) = package.cpu_fallback {
+            replaced.push((package.name.clone(), fallback.clone(), was_default));
+        }
+    }
+
+    for (name, fallback, was_default) in replaced {
+        info!("Using {:?} in place of {:?}", fallback, name);
+
+        for package in &mut config.packages {
+            if package.name == fallback && was_default && package.unsupported.is_none() {
+                package.default = Some(true);
+            }
+
+            for requirement in &mut package.requires {
+                if *requirement == name {
+                    *requirement = fallback.clone();
+                }
+            }
+        }
+    }
+}
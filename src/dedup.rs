@@ -0,0 +1,136 @@
+//! dedup.rs
+//!
+//! Hard-links files identical to ones already installed, instead of keeping separate copies
+//! of them. Releases installed side-by-side (e.g. several versions of an SDK, each as its
+//! own package with its own `install_root`) and the backups of files replaced by other
+//! packages are often mostly unchanged, so this can save a lot of space.
+//!
+//! Candidates are found through the hashes recorded in the installation database, but are
+//! hashed again before being linked to, as they may have been changed since. Links can't
+//! cross volumes, so files on other volumes are left as copies.
+//!
+//! Installs always write files afresh rather than in place, so updating a package never
+//! changes the files linked to its own.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use config::DeduplicationSettings;
+
+use hashing::sha256_file;
+
+use installer::InstallationDatabase;
+
+pub struct Deduplicator {
+    min_size: u64,
+    /// Files which may have the contents with a hash
+    candidates: HashMap<String, Vec<PathBuf>>,
+    /// Bytes which didn't have to be stored again
+    saved: u64,
+}
+
+impl Deduplicator {
+    /// Finds every installed file (and uncompressed backup) which could be linked to.
+    pub fn new(
+        database: &InstallationDatabase,
+        install_path: &Path,
+        settings: &DeduplicationSettings,
+    ) -> Deduplicator {
+        let mut deduplicator = Deduplicator {
+            min_size: settings.min_size,
+            candidates: HashMap::new(),
+            saved: 0,
+        };
+
+        for package in &database.packages {
+            let root = package.get_root(install_path);
+            for (file, hash) in &package.hashes {
+                deduplicator.add(hash, root.join(file));
+            }
+        }
+
+        // Backups hold the contents their owner had installed
+        for file in database.packages.iter().flat_map(|x| x.overwritten.iter()) {
+            if file.compressed {
+                continue;
+            }
+
+            let hash = database
+                .packages
+                .iter()
+                .find(|x| Some(&x.name) == file.owner.as_ref())
+                .and_then(|x| x.hashes.get(&file.path));
+
+            if let Some(hash) = hash {
+                deduplicator.add(hash, PathBuf::from(&file.backup));
+            }
+        }
+
+        deduplicator
+    }
+
+    /// Records a file which later files with the same hash can be linked to.
+    pub fn add(&mut self, hash: &str, path: PathBuf) {
+        let paths = self
+            .candidates
+            .entry(hash.to_string())
+            .or_insert_with(Vec::new);
+
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+
+    /// Replaces a newly written file with a link to an identical one, if there is one.
+    /// Either way, the file can be linked to from then on.
+    pub fn link(&mut self, path: &Path, hash: &str) {
+        let size = match fs::metadata(path) {
+            Ok(v) => v.len(),
+            Err(_) => return,
+        };
+
+        if size >= self.min_size {
+            let candidates = self.candidates.get(hash).cloned().unwrap_or_default();
+
+            for candidate in candidates.iter().filter(|x| x.as_path() != path) {
+                let is_identical = fs::metadata(candidate).map_or(false, |x| x.len() == size)
+                    && sha256_file(candidate).ok().as_ref().map(|x| x.as_str()) == Some(hash);
+                if !is_identical {
+                    continue;
+                }
+
+                match replace_with_link(path, candidate) {
+                    Ok(_) => {
+                        info!("Linked {:?} to identical file {:?}", path, candidate);
+                        self.saved += size;
+                        break;
+                    }
+                    Err(v) => info!("Keeping {:?} as a copy: {}", path, v),
+                }
+            }
+        }
+
+        self.add(hash, path.to_owned());
+    }
+
+    /// Returns how many bytes didn't have to be stored again.
+    pub fn saved(&self) -> u64 {
+        self.saved
+    }
+}
+
+/// Replaces a file with a hard link to another, without ever leaving it missing.
+fn replace_with_link(path: &Path, original: &Path) -> Result<(), String> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".link");
+    let temp = PathBuf::from(temp);
+
+    fs::hard_link(original, &temp)
+        .map_err(|x| format!("Unable to link to {:?}: {:?}", original, x))?;
+
+    fs::rename(&temp, path).map_err(|x| {
+        let _ = fs::remove_file(&temp);
+        format!("Unable to replace {:?}: {:?}", path, x)
+    })
+}
@@ -0,0 +1,159 @@
+//! detect.rs
+//!
+//! Finds out if a prerequisite (such as a runtime installed by its own setup program) is
+//! already on the system, so that whatever would install it can be skipped. Versions are
+//! read from a registry value, a file's version resource, or the output of a command, then
+//! compared against a semver requirement.
+//!
+//! Versions which aren't valid semver (e.g. `v14.30.30704.00`) are compared by their first
+//! three numbers.
+
+use std::env;
+use std::path::Path;
+
+use regex::Regex;
+
+use semver::VersionReq;
+
+use config::{Config, Detection};
+
+use sources::types::SemverVersion;
+
+use locations;
+
+use native;
+
+use sandbox;
+
+/// Reads a version out of free-form text, such as "v14.30.30704.00" or "1.8.0_292".
+pub fn parse_version(text: &str) -> Option<SemverVersion> {
+    let text = text.trim().trim_start_matches(|x| x == 'v' || x == 'V');
+    if let Ok(v) = SemverVersion::parse(text) {
+        return Some(v);
+    }
+
+    let mut numbers = Vec::new();
+    for part in text.split(|x: char| !x.is_ascii_digit()).take(3) {
+        match part.parse::<u64>() {
+            Ok(v) => numbers.push(v),
+            Err(_) => break,
+        }
+    }
+
+    match numbers.len() {
+        0 => None,
+        _ => Some(SemverVersion::from((
+            numbers[0],
+            numbers.get(1).cloned().unwrap_or(0),
+            numbers.get(2).cloned().unwrap_or(0),
+        ))),
+    }
+}
+
+/// Runs a detection's command, returning the version it printed.
+fn command_version(detection: &Detection, program: &str, config: &Config) -> Option<String> {
+    let pattern = match Regex::new(&detection.pattern) {
+        Ok(v) => v,
+        Err(v) => {
+            warn!("Invalid version pattern {:?}: {}", detection.pattern, v);
+            return None;
+        }
+    };
+
+    let args: Vec<&str> = detection.args.iter().map(|x| x.as_str()).collect();
+
+    // Commands which aren't installed can't be run at all
+    let output = match sandbox::run(program, &args, &env::temp_dir(), &config.limits) {
+        Ok(v) => v,
+        Err(v) => {
+            info!("Unable to run {:?} to detect its version: {}", program, v);
+            return None;
+        }
+    };
+
+    // Some programs (e.g. Java) print their version as an error
+    [&output.stdout, &output.stderr]
+        .iter()
+        .filter_map(|x| pattern.captures(x))
+        .filter_map(|x| {
+            x.get(1)
+                .or_else(|| x.get(0))
+                .map(|x| x.as_str().to_string())
+        })
+        .next()
+}
+
+/// Reads the version of whatever a detection looks for, if it is on the system.
+pub fn detect_version(
+    detection: &Detection,
+    install_path: &Path,
+    config: &Config,
+) -> Result<Option<String>, String> {
+    let result = if let Some(ref path) = detection.registry {
+        native::read_registry_string(path)
+    } else if let Some(ref file) = detection.file {
+        let path = locations::resolve(file, install_path, &config.data_locations)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        native::get_file_version(&path)
+    } else if let Some(ref program) = detection.command {
+        return Ok(command_version(detection, program, config));
+    } else {
+        return Err("Detections need one of registry, file or command".to_string());
+    };
+
+    match result {
+        Ok(v) => Ok(Some(v)),
+        Err(v) => {
+            info!("Nothing detected: {}", v);
+            Ok(None)
+        }
+    }
+}
+
+/// Returns if whatever a detection looks for is on the system, at a version meeting its
+/// requirement.
+pub fn is_satisfied(
+    detection: &Detection,
+    install_path: &Path,
+    config: &Config,
+) -> Result<bool, String> {
+    let found = match detect_version(detection, install_path, config)? {
+        Some(v) => v,
+        None => return Ok(false),
+    };
+
+    let requirement = match detection.version {
+        Some(ref v) => v,
+        None => {
+            info!("Detected version {:?}", found);
+            return Ok(true);
+        }
+    };
+
+    let requirement = VersionReq::parse(requirement)
+        .map_err(|x| format!("Invalid version requirement {:?}: {:?}", requirement, x))?;
+
+    // Requirements otherwise only match pre-releases of the exact version named
+    let satisfied = match parse_version(&found) {
+        Some(mut v) => {
+            v.pre.clear();
+            requirement.matches(&v)
+        }
+        None => {
+            warn!("Unable to understand detected version {:?}", found);
+            false
+        }
+    };
+
+    info!(
+        "Detected version {:?}, which {} {}",
+        found,
+        if satisfied { "meets" } else { "doesn't meet" },
+        requirement
+    );
+
+    Ok(satisfied)
+}
@@ -0,0 +1,162 @@
+//! diagnostics.rs
+//!
+//! Diagnostics bundles gather what is needed to look into a failed install into a single
+//! .zip archive, which users can attach to bug reports. A bundle contains:
+//!
+//! - `installer.log`, the log of this session
+//! - `metadata.json`, the installation's database, without any stored credentials
+//! - `config.toml`, the configuration in use
+//! - `system.json`, describing the OS, architecture and installer
+//! - `errors.json`, the most recent task errors, along with failed operations from the
+//!   installation's history
+//!
+//! Everything is passed through `logging::redact`, in case secrets ended up elsewhere.
+
+use std::env::consts::ARCH;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+
+use zip::result::ZipError;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+use installer::{HistoryEntry, InstallerFramework, TaskError};
+
+use logging;
+
+use native;
+
+/// Describes the system the installer is running on.
+#[derive(Serialize)]
+struct SystemInfo {
+    os: String,
+    arch: &'static str,
+    installer_version: &'static str,
+    app_name: String,
+    install_path: Option<String>,
+    preexisting_install: bool,
+    is_launcher: bool,
+}
+
+/// Errors which happened during this session, and in previous operations.
+#[derive(Serialize)]
+struct Errors<'a> {
+    tasks: Vec<&'a TaskError>,
+    history: Vec<&'a HistoryEntry>,
+}
+
+/// Renders the installation's database without anything which could be used to sign in.
+fn sanitized_metadata(framework: &InstallerFramework) -> Result<String, String> {
+    let mut database = framework.database.clone();
+    database.credentials.username.clear();
+    database.credentials.token.clear();
+    database.source_tokens.clear();
+
+    serde_json::to_string_pretty(&database)
+        .map_err(|x| format!("Unable to render installation metadata: {:?}", x))
+}
+
+/// Returns the configuration as it was written, or as it was parsed if the original text
+/// isn't around.
+fn config_snapshot(framework: &InstallerFramework) -> Result<Option<String>, String> {
+    if let Some(ref raw_config) = framework.raw_config {
+        return Ok(Some(raw_config.clone()));
+    }
+
+    match framework.config {
+        Some(ref config) => config
+            .to_json_str()
+            .map(Some)
+            .map_err(|x| format!("Unable to render configuration: {:?}", x)),
+        None => Ok(None),
+    }
+}
+
+/// Returns the name used for bundles written at this point in time.
+fn file_name(framework: &InstallerFramework) -> String {
+    format!(
+        "{}-diagnostics-{}.zip",
+        framework.base_attributes.name,
+        Local::now().format("%Y%m%d-%H%M%S")
+    )
+}
+
+/// Writes a diagnostics bundle for this session into the specified directory, returning
+/// where it was written.
+pub fn export(framework: &InstallerFramework, dir: &Path) -> Result<PathBuf, String> {
+    let output = dir.join(file_name(framework));
+
+    let mut files = Vec::new();
+
+    match logging::get_log_file() {
+        Some(path) => match fs::read(&path) {
+            Ok(log) => files.push(("installer.log", String::from_utf8_lossy(&log).to_string())),
+            Err(v) => warn!(
+                "Unable to read log file {:?} for diagnostics: {:?}",
+                path, v
+            ),
+        },
+        None => warn!("No log file to include in diagnostics"),
+    }
+
+    files.push(("metadata.json", sanitized_metadata(framework)?));
+
+    if let Some(config) = config_snapshot(framework)? {
+        files.push(("config.toml", config));
+    }
+
+    let system = SystemInfo {
+        os: native::get_os_version(),
+        arch: ARCH,
+        installer_version: env!("CARGO_PKG_VERSION"),
+        app_name: framework.base_attributes.name.clone(),
+        install_path: framework
+            .install_path
+            .as_ref()
+            .map(|x| x.to_string_lossy().to_string()),
+        preexisting_install: framework.preexisting_install,
+        is_launcher: framework.is_launcher,
+    };
+    files.push((
+        "system.json",
+        serde_json::to_string_pretty(&system)
+            .map_err(|x| format!("Unable to render system information: {:?}", x))?,
+    ));
+
+    let errors = Errors {
+        tasks: framework.task_errors.iter().collect(),
+        history: framework
+            .database
+            .history
+            .iter()
+            .filter(|x| !x.success)
+            .collect(),
+    };
+    files.push((
+        "errors.json",
+        serde_json::to_string_pretty(&errors)
+            .map_err(|x| format!("Unable to render errors: {:?}", x))?,
+    ));
+
+    let file =
+        File::create(&output).map_err(|x| format!("Unable to create {:?}: {:?}", output, x))?;
+    let mut zip = ZipWriter::new(file);
+
+    let options = FileOptions::default();
+    let zip_error = |x: ZipError| format!("Unable to write diagnostics bundle: {:?}", x);
+
+    for (name, contents) in files {
+        zip.start_file(name, options).map_err(zip_error)?;
+        zip.write_all(logging::redact(&contents).as_bytes())
+            .map_err(|x| format!("Unable to write diagnostics bundle: {:?}", x))?;
+    }
+
+    zip.finish().map_err(zip_error)?;
+
+    info!("Wrote diagnostics bundle to {:?}", output);
+
+    Ok(output)
+}
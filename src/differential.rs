@@ -0,0 +1,453 @@
+//! differential.rs
+//!
+//! Chunk-based differential downloads. Publishers split the payload of a release into
+//! content-defined chunks, stored once each in a "blob" alongside an index describing which
+//! chunks make up which file. Clients chunk their installed files in the same way, and only
+//! fetch the chunks they are missing using HTTP range requests. Repairs use the same index
+//! to fetch only the chunks of files which have been damaged.
+
+use std::collections::HashMap;
+use std::fs::{create_dir_all, metadata, read_dir, remove_file, rename, set_permissions, File};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use tar::{Builder, Header};
+
+use url::Url;
+
+use hashing::sha256_hex;
+use http::fetch_bytes;
+
+/// Chunks will never be smaller than this, unless they end a file.
+const MIN_CHUNK_SIZE: usize = 4 * 1024;
+/// Chunks will always be split at this size.
+const MAX_CHUNK_SIZE: usize = 128 * 1024;
+/// Boundaries occur when the top bits of the rolling hash are zero, averaging ~16KiB chunks.
+const BOUNDARY_SHIFT: u32 = 64 - 14;
+/// Ranges separated by less than this many bytes are fetched using a single request.
+const RANGE_MERGE_GAP: u64 = 16 * 1024;
+
+lazy_static! {
+    /// Per-byte values for the gear rolling hash. Generated deterministically, as both
+    /// publishers and clients need to agree on chunk boundaries.
+    static ref GEAR: Vec<u64> = {
+        let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+        (0..256)
+            .map(|_| {
+                // splitmix64
+                state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+                let mut z = state;
+                z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+                z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+                z ^ (z >> 31)
+            })
+            .collect()
+    };
+}
+
+/// A single chunk within the blob.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Chunk {
+    pub hash: String,
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// A file in the payload, described as a series of chunks.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ChunkedFile {
+    pub path: String,
+    pub chunks: Vec<Chunk>,
+}
+
+/// Describes a release's payload in terms of content-defined chunks.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ChunkIndex {
+    /// URL of the blob containing all chunks, relative to the index if not absolute
+    pub blob: String,
+    pub files: Vec<ChunkedFile>,
+}
+
+/// Splits data into content-defined chunks, returning (start, length) pairs.
+pub fn chunk_boundaries(data: &[u8]) -> Vec<(usize, usize)> {
+    let mut chunks = Vec::new();
+
+    let gear: &[u64] = &GEAR;
+
+    let mut start = 0;
+    let mut hash: u64 = 0;
+
+    for (i, byte) in data.iter().enumerate() {
+        hash = (hash << 1).wrapping_add(gear[*byte as usize]);
+
+        let length = i + 1 - start;
+        if (length >= MIN_CHUNK_SIZE && (hash >> BOUNDARY_SHIFT) == 0) || length >= MAX_CHUNK_SIZE
+        {
+            chunks.push((start, length));
+            start = i + 1;
+            hash = 0;
+        }
+    }
+
+    if start < data.len() {
+        chunks.push((start, data.len() - start));
+    }
+
+    chunks
+}
+
+/// Recursively lists files in a directory, relative to the specified root.
+fn list_files(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    let entries = read_dir(dir).map_err(|x| format!("Unable to read {:?}: {:?}", dir, x))?;
+
+    for entry in entries {
+        let path = entry
+            .map_err(|x| format!("Unable to read {:?}: {:?}", dir, x))?
+            .path();
+
+        if path.is_dir() {
+            list_files(root, &path, files)?;
+        } else {
+            files.push(
+                path.strip_prefix(root)
+                    .map_err(|x| format!("Unable to relativize {:?}: {:?}", path, x))?
+                    .to_owned(),
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Chunks every file in a payload directory, writing each unique chunk once to the blob
+/// file and returning the index describing it.
+pub fn build_index(payload_dir: &Path, blob_path: &Path, blob_url: &str) -> Result<ChunkIndex, String> {
+    let mut files = Vec::new();
+    list_files(payload_dir, payload_dir, &mut files)?;
+    files.sort();
+
+    let mut blob =
+        File::create(blob_path).map_err(|x| format!("Unable to create blob: {:?}", x))?;
+    let mut offset = 0;
+    let mut known_chunks: HashMap<String, Chunk> = HashMap::new();
+    let mut index = ChunkIndex {
+        blob: blob_url.to_string(),
+        files: Vec::new(),
+    };
+
+    for file in files {
+        let mut data = Vec::new();
+        File::open(payload_dir.join(&file))
+            .and_then(|mut x| x.read_to_end(&mut data))
+            .map_err(|x| format!("Unable to read {:?}: {:?}", file, x))?;
+
+        let mut chunks = Vec::new();
+        for (start, length) in chunk_boundaries(&data) {
+            let contents = &data[start..start + length];
+            let hash = sha256_hex(contents);
+
+            if !known_chunks.contains_key(&hash) {
+                blob.write_all(contents)
+                    .map_err(|x| format!("Unable to write blob: {:?}", x))?;
+
+                known_chunks.insert(
+                    hash.clone(),
+                    Chunk {
+                        hash: hash.clone(),
+                        offset,
+                        length: length as u64,
+                    },
+                );
+                offset += length as u64;
+            }
+
+            chunks.push(known_chunks[&hash].clone());
+        }
+
+        // Archives use forward slashes regardless of platform
+        let path = file
+            .to_str()
+            .ok_or("Unable to get str from file name")?
+            .replace("\\", "/");
+
+        index.files.push(ChunkedFile { path, chunks });
+    }
+
+    info!(
+        "Built chunk index with {} files, {} unique chunks ({} bytes)",
+        index.files.len(),
+        known_chunks.len(),
+        offset
+    );
+
+    Ok(index)
+}
+
+/// Chunks installed files, returning where each chunk hash can be found locally.
+fn index_local_files(
+    install_path: &Path,
+    files: &[String],
+) -> HashMap<String, (PathBuf, u64, usize)> {
+    let mut local_chunks = HashMap::new();
+
+    for file in files {
+        let path = install_path.join(file);
+        if !path.is_file() {
+            continue;
+        }
+
+        let mut data = Vec::new();
+        if let Err(v) = File::open(&path).and_then(|mut x| x.read_to_end(&mut data)) {
+            warn!("Unable to read {:?} for differential update: {:?}", path, v);
+            continue;
+        }
+
+        for (start, length) in chunk_boundaries(&data) {
+            let hash = sha256_hex(&data[start..start + length]);
+            local_chunks
+                .entry(hash)
+                .or_insert_with(|| (path.clone(), start as u64, length));
+        }
+    }
+
+    local_chunks
+}
+
+/// Reads a single chunk back out of a local file.
+fn read_local_chunk(path: &Path, offset: u64, length: usize) -> Result<Vec<u8>, String> {
+    let mut file = File::open(path).map_err(|x| format!("Unable to open {:?}: {:?}", path, x))?;
+    file.seek(SeekFrom::Start(offset))
+        .map_err(|x| format!("Unable to seek {:?}: {:?}", path, x))?;
+
+    let mut data = vec![0 as u8; length];
+    file.read_exact(&mut data)
+        .map_err(|x| format!("Unable to read {:?}: {:?}", path, x))?;
+
+    Ok(data)
+}
+
+/// Fetches and parses a chunk index, returning it along with the URL of its blob.
+fn fetch_index(
+    index_url: &str,
+    authorization: Option<String>,
+) -> Result<(ChunkIndex, String), String> {
+    let index = fetch_bytes(index_url, authorization, None)?;
+    let index: ChunkIndex = serde_json::from_slice(&index)
+        .map_err(|x| format!("Failed to parse chunk index: {:?}", x))?;
+
+    let blob_url = Url::parse(index_url)
+        .and_then(|x| x.join(&index.blob))
+        .map_err(|x| format!("Invalid blob URL in chunk index: {:?}", x))?
+        .to_string();
+
+    Ok((index, blob_url))
+}
+
+/// Fetches chunks from the blob, merging those close together into single range requests.
+/// Returns the ranges fetched, as (offset, data) pairs.
+///
+/// progress: called with (downloaded bytes, total bytes to download)
+fn fetch_chunks<'a, I, F>(
+    blob_url: &str,
+    authorization: Option<String>,
+    chunks: I,
+    progress: &mut F,
+) -> Result<Vec<(u64, Vec<u8>)>, String>
+where
+    I: Iterator<Item = &'a Chunk>,
+    F: FnMut(u64, u64) -> (),
+{
+    let mut missing: Vec<(u64, u64)> = chunks.map(|x| (x.offset, x.length)).collect();
+    missing.sort();
+    missing.dedup();
+
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+    for (offset, length) in missing {
+        if let Some(last) = ranges.last_mut() {
+            if offset <= last.0 + last.1 + RANGE_MERGE_GAP {
+                last.1 = (offset + length).max(last.0 + last.1) - last.0;
+                continue;
+            }
+        }
+
+        ranges.push((offset, length));
+    }
+
+    let total: u64 = ranges.iter().map(|x| x.1).sum();
+    info!(
+        "Differential update needs {} bytes in {} requests",
+        total,
+        ranges.len()
+    );
+
+    let mut downloaded = 0;
+    let mut remote_data: Vec<(u64, Vec<u8>)> = Vec::with_capacity(ranges.len());
+    progress(0, total);
+    for (offset, length) in ranges {
+        let data = fetch_bytes(
+            blob_url,
+            authorization.clone(),
+            Some((offset, offset + length - 1)),
+        )?;
+
+        if data.len() as u64 != length {
+            return Err(format!(
+                "Blob range at {} was truncated ({} of {} bytes)",
+                offset,
+                data.len(),
+                length
+            ));
+        }
+
+        downloaded += length;
+        progress(downloaded, total);
+        remote_data.push((offset, data));
+    }
+
+    Ok(remote_data)
+}
+
+/// Finds a chunk within the ranges fetched from the blob.
+fn remote_chunk(remote_data: &[(u64, Vec<u8>)], chunk: &Chunk) -> Result<Vec<u8>, String> {
+    let (range_offset, range) = remote_data
+        .iter()
+        .find(|x| x.0 <= chunk.offset && chunk.offset < x.0 + x.1.len() as u64)
+        .ok_or_else(|| format!("Chunk {} was not downloaded", chunk.hash))?;
+    let start = (chunk.offset - range_offset) as usize;
+
+    Ok(range[start..start + chunk.length as usize].to_vec())
+}
+
+/// Reconstructs a release payload from locally installed files plus any missing chunks,
+/// returning it as an uncompressed .tar archive ready for extraction.
+///
+/// progress: called with (downloaded bytes, total bytes to download)
+pub fn download_payload<F>(
+    index_url: &str,
+    authorization: Option<String>,
+    install_path: &Path,
+    installed_files: &[String],
+    mut progress: F,
+) -> Result<Vec<u8>, String>
+where
+    F: FnMut(u64, u64) -> (),
+{
+    let (index, blob_url) = fetch_index(index_url, authorization.clone())?;
+
+    let local_chunks = index_local_files(install_path, installed_files);
+
+    // Work out which chunks of the blob need to be fetched
+    let missing = index
+        .files
+        .iter()
+        .flat_map(|x| x.chunks.iter())
+        .filter(|x| !local_chunks.contains_key(&x.hash));
+    let remote_data = fetch_chunks(&blob_url, authorization, missing, &mut progress)?;
+
+    // Assemble each file, verifying every chunk along the way
+    let mut builder = Builder::new(Vec::new());
+    for file in &index.files {
+        let mut contents = Vec::new();
+
+        for chunk in &file.chunks {
+            let data = match local_chunks.get(&chunk.hash) {
+                Some((path, offset, length)) => read_local_chunk(path, *offset, *length)?,
+                None => remote_chunk(&remote_data, chunk)?,
+            };
+
+            if sha256_hex(&data) != chunk.hash {
+                return Err(format!("Chunk {} of {:?} is corrupt", chunk.hash, file.path));
+            }
+
+            contents.extend_from_slice(&data);
+        }
+
+        let mut header = Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o755);
+        header.set_cksum();
+
+        builder
+            .append_data(&mut header, &file.path, contents.as_slice())
+            .map_err(|x| format!("Unable to assemble {:?}: {:?}", file.path, x))?;
+    }
+
+    builder
+        .into_inner()
+        .map_err(|x| format!("Unable to assemble payload: {:?}", x))
+}
+
+/// Rewrites damaged installed files from the chunks which make them up, fetching only
+/// those chunks rather than the whole payload. Files are given as (path, expected SHA-256)
+/// pairs, and are only written once all of them have been assembled and match what was
+/// installed, so a release which has since changed leaves everything as it is.
+///
+/// progress: called with (downloaded bytes, total bytes to download)
+pub fn repair_files<F>(
+    index_url: &str,
+    authorization: Option<String>,
+    root: &Path,
+    damaged: &[(String, String)],
+    mut progress: F,
+) -> Result<(), String>
+where
+    F: FnMut(u64, u64) -> (),
+{
+    let (index, blob_url) = fetch_index(index_url, authorization.clone())?;
+
+    let mut files = Vec::with_capacity(damaged.len());
+    for (path, hash) in damaged {
+        match index.files.iter().find(|x| &x.path == path) {
+            Some(file) => files.push((file, hash)),
+            None => return Err(format!("{:?} is not in the chunk index", path)),
+        }
+    }
+
+    let chunks = files.iter().flat_map(|x| x.0.chunks.iter());
+    let remote_data = fetch_chunks(&blob_url, authorization, chunks, &mut progress)?;
+
+    let mut contents = Vec::with_capacity(files.len());
+    for (file, hash) in files {
+        let mut data = Vec::new();
+        for chunk in &file.chunks {
+            data.extend_from_slice(&remote_chunk(&remote_data, chunk)?);
+        }
+
+        if &sha256_hex(&data) != hash {
+            return Err(format!(
+                "{:?} in the chunk index doesn't match the installed version",
+                file.path
+            ));
+        }
+
+        contents.push((root.join(&file.path), data));
+    }
+
+    // Files are written alongside and renamed into place, so that a failure part way
+    // through never leaves a file worse off than it was. Existing permissions are kept.
+    for (path, data) in contents {
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)
+                .map_err(|x| format!("Unable to create {:?}: {:?}", parent, x))?;
+        }
+
+        let mut temp_name = path.as_os_str().to_owned();
+        temp_name.push(".repair");
+        let temp_path = PathBuf::from(temp_name);
+
+        let result = File::create(&temp_path)
+            .and_then(|mut x| x.write_all(&data).and_then(|_| x.sync_all()))
+            .and_then(|_| match metadata(&path) {
+                Ok(v) => set_permissions(&temp_path, v.permissions()),
+                Err(_) => Ok(()),
+            })
+            .and_then(|_| rename(&temp_path, &path));
+
+        if let Err(v) = result {
+            let _ = remove_file(&temp_path);
+            return Err(format!("Unable to write {:?}: {:?}", path, v));
+        }
+    }
+
+    Ok(())
+}
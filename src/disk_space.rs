@@ -0,0 +1,128 @@
+//! disk_space.rs
+//!
+//! Watches the free space on the volume being installed to, asking the user to free some
+//! up (and waiting for them to) rather than failing part way through writing a file.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use number_prefix::{NumberPrefix, Prefixed, Standalone};
+
+use config::ResourceLimits;
+
+use error_help;
+
+use tasks::TaskMessage;
+
+use native;
+
+use prompts;
+
+use strings;
+
+/// How often the free space is checked, while there appears to be enough.
+const CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Tracks the free space on a volume as files are written to it.
+pub struct SpaceMonitor {
+    path: PathBuf,
+    reserve: u64,
+    last_check: Option<Instant>,
+    /// Free space as of the last check, less what has been written since
+    free: u64,
+}
+
+/// Returns the closest parent of a path which exists, as directories to install into
+/// may not have been created yet.
+pub fn existing_ancestor(path: &Path) -> &Path {
+    path.ancestors().find(|x| x.exists()).unwrap_or(path)
+}
+
+/// Formats a number of bytes for showing to the user.
+pub fn pretty_size(bytes: u64) -> String {
+    match NumberPrefix::decimal(bytes as f64) {
+        Standalone(bytes) => format!("{} bytes", bytes),
+        Prefixed(prefix, n) => format!("{:.0} {}B", n.ceil(), prefix),
+    }
+}
+
+impl SpaceMonitor {
+    /// Creates a new monitor for the volume containing the specified path.
+    pub fn new(path: &Path, limits: &ResourceLimits) -> SpaceMonitor {
+        SpaceMonitor {
+            path: path.to_owned(),
+            reserve: limits.min_free_space,
+            last_check: None,
+            free: 0,
+        }
+    }
+
+    /// Reads the free space of the volume. If this isn't possible, the space is assumed
+    /// to be sufficient so that unusual file systems can still be installed to.
+    fn check(&mut self) -> u64 {
+        let path = existing_ancestor(&self.path);
+
+        self.free = match native::get_free_space(path) {
+            Ok(v) => v,
+            Err(v) => {
+                warn!("Unable to monitor free space: {}", v);
+                u64::max_value()
+            }
+        };
+        self.last_check = Some(Instant::now());
+
+        self.free
+    }
+
+    /// Called before writing the specified amount of data. Blocks while there isn't
+    /// enough space to do so, asking the user to free some up. Fails if they give up.
+    pub fn wait(&mut self, bytes: u64, messenger: &dyn Fn(&TaskMessage)) -> Result<(), String> {
+        let required = bytes.saturating_add(self.reserve);
+
+        let due = self
+            .last_check
+            .map_or(true, |x| x.elapsed() >= CHECK_INTERVAL);
+        let mut free = if due || self.free < required {
+            self.check()
+        } else {
+            self.free
+        };
+
+        while free < required {
+            let needed = pretty_size(required - free);
+            warn!(
+                "Only {} free on {:?}, {} more is needed",
+                pretty_size(free),
+                self.path,
+                needed
+            );
+
+            let path = self.path.display().to_string();
+            let retry = strings::tr("prompts.retry");
+            let cancel = strings::tr("prompts.cancel");
+
+            let answer = prompts::ask(
+                messenger,
+                &strings::format("prompts.disk_full", &[("needed", &needed), ("path", &path)]),
+                &[&retry, &cancel],
+                &cancel,
+            );
+
+            if answer != retry {
+                return Err(error_help::coded(
+                    "not_enough_space",
+                    strings::format(
+                        "errors.not_enough_space",
+                        &[("needed", &needed), ("path", &path)],
+                    ),
+                ));
+            }
+
+            free = self.check();
+        }
+
+        self.free = free.saturating_sub(bytes);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,334 @@
+//! dns.rs
+//!
+//! Falls back to DNS-over-HTTPS when the system's resolver can't find a source's host,
+//! as happens on networks with captive portals or filtering resolvers. Requests to such
+//! hosts are routed through a local CONNECT tunnel which resolves the host using DoH, so
+//! TLS is still negotiated end-to-end with the real server. The tunnel races connections
+//! to the host's addresses (as with "Happy Eyeballs", RFC 8305), so that hosts whose IPv6
+//! or IPv4 addresses are unreachable are still connected to quickly.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::channel;
+use std::sync::{Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use reqwest::header::ACCEPT;
+
+use url::{Host, Url};
+
+use http::build_client;
+
+use logging::LoggingErrors;
+
+/// How long to wait when connecting to a resolved address.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(8);
+/// How long each connection attempt is given before the next is started alongside it, as
+/// recommended by RFC 8305.
+const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+/// Largest CONNECT request head that will be accepted.
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+
+/// A DNS JSON API response, as described at
+/// <https://developers.google.com/speed/public-dns/docs/doh/json>.
+#[derive(Deserialize)]
+struct DohResponse {
+    #[serde(rename = "Answer", default)]
+    answer: Vec<DohAnswer>,
+}
+
+#[derive(Deserialize)]
+struct DohAnswer {
+    #[serde(rename = "type")]
+    record_type: u16,
+    data: String,
+}
+
+lazy_static! {
+    /// DNS JSON API endpoint to fall back to, if any.
+    static ref DOH_URL: RwLock<Option<String>> = RwLock::new(None);
+
+    /// Whether the system's resolver could find each host.
+    static ref SYSTEM_RESOLVES: Mutex<HashMap<String, bool>> = Mutex::new(HashMap::new());
+
+    /// Address of the local tunnel, once started.
+    static ref TUNNEL: Mutex<Option<SocketAddr>> = Mutex::new(None);
+}
+
+/// Sets the DNS JSON API endpoint to use when the system's resolver fails. This should
+/// use an IP address (such as `https://1.1.1.1/dns-query`), as it can't resolve itself.
+pub fn set_doh_url(url: Option<String>) {
+    *DOH_URL.write().log_expect("DoH state has been dirtied") = url;
+    SYSTEM_RESOLVES
+        .lock()
+        .log_expect("DNS cache has been dirtied")
+        .clear();
+}
+
+/// Checks whether the system's resolver can find a host.
+fn system_resolves(host: &str) -> bool {
+    if let Some(result) = SYSTEM_RESOLVES
+        .lock()
+        .log_expect("DNS cache has been dirtied")
+        .get(host)
+    {
+        return *result;
+    }
+
+    let result = match (host, 443).to_socket_addrs() {
+        Ok(mut x) => x.next().is_some(),
+        Err(v) => {
+            warn!("System DNS failed to resolve {:?}: {:?}", host, v);
+            false
+        }
+    };
+
+    SYSTEM_RESOLVES
+        .lock()
+        .log_expect("DNS cache has been dirtied")
+        .insert(host.to_string(), result);
+
+    result
+}
+
+/// Resolves a host using DNS-over-HTTPS, alternating between IPv6 and IPv4 addresses so
+/// that a broken address family doesn't stall connecting.
+fn resolve_doh(host: &str) -> Result<Vec<IpAddr>, String> {
+    let endpoint = DOH_URL
+        .read()
+        .log_expect("DoH state has been dirtied")
+        .clone()
+        .ok_or("DNS-over-HTTPS isn't configured")?;
+
+    let mut found = Vec::new();
+
+    // AAAA records, then A records
+    for record_type in &[28, 1] {
+        let url = Url::parse_with_params(
+            &endpoint,
+            &[("name", host), ("type", &record_type.to_string())],
+        )
+        .map_err(|x| format!("Invalid DoH URL {:?}: {:?}", endpoint, x))?;
+
+        let mut response = build_client()?
+            .get(url)
+            .header(ACCEPT, "application/dns-json")
+            .send()
+            .map_err(|x| format!("DoH query for {:?} failed: {:?}", host, x))?;
+
+        let response: DohResponse = response
+            .json()
+            .map_err(|x| format!("Invalid DoH response for {:?}: {:?}", host, x))?;
+
+        found.push(
+            response
+                .answer
+                .iter()
+                .filter(|x| x.record_type == *record_type)
+                .filter_map(|x| x.data.parse::<IpAddr>().ok())
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    let (ipv6, ipv4) = (&found[0], &found[1]);
+    let mut addresses = Vec::with_capacity(ipv6.len() + ipv4.len());
+    for i in 0..ipv6.len().max(ipv4.len()) {
+        addresses.extend(ipv6.get(i));
+        addresses.extend(ipv4.get(i));
+    }
+
+    if addresses.is_empty() {
+        return Err(format!("DoH found no addresses for {:?}", host));
+    }
+
+    info!("Resolved {:?} using DoH: {:?}", host, addresses);
+
+    Ok(addresses)
+}
+
+/// Connects to whichever address answers first. Addresses are tried in order, each
+/// starting once the attempts before it have had `ATTEMPT_DELAY` to connect, or straight
+/// away when one of them fails. Connections which lose the race are closed.
+fn connect_first(addresses: &[IpAddr], port: u16) -> Option<TcpStream> {
+    let (sender, receiver) = channel();
+    let mut pending = 0;
+
+    for ip in addresses {
+        let address = SocketAddr::new(*ip, port);
+        let sender = sender.clone();
+        thread::spawn(move || {
+            let result = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT);
+            if let Err(ref v) = result {
+                info!("Unable to connect to {}: {:?}", address, v);
+            }
+
+            // Nothing is waiting once another attempt has won, so this is just closed
+            let _ = sender.send(result.ok());
+        });
+        pending += 1;
+
+        let deadline = Instant::now() + ATTEMPT_DELAY;
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+
+            match receiver.recv_timeout(deadline - now) {
+                Ok(Some(stream)) => return Some(stream),
+                Ok(None) => {
+                    pending -= 1;
+                    break;
+                }
+                Err(_) => break,
+            }
+        }
+    }
+
+    while pending > 0 {
+        match receiver.recv() {
+            Ok(Some(stream)) => return Some(stream),
+            Ok(None) => pending -= 1,
+            Err(_) => break,
+        }
+    }
+
+    None
+}
+
+/// Handles a single CONNECT request from the HTTP client.
+fn tunnel(mut client: TcpStream) -> Result<(), String> {
+    let mut head = Vec::new();
+    let mut byte = [0 as u8; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() > MAX_REQUEST_HEAD {
+            return Err("CONNECT request was too long".to_string());
+        }
+
+        client
+            .read_exact(&mut byte)
+            .map_err(|x| format!("Failed to read CONNECT request: {:?}", x))?;
+        head.push(byte[0]);
+    }
+
+    let head = String::from_utf8_lossy(&head);
+    let target = head
+        .lines()
+        .next()
+        .and_then(|line| {
+            let mut parts = line.split(' ');
+            match (parts.next(), parts.next()) {
+                (Some("CONNECT"), Some(target)) => Some(target.to_string()),
+                _ => None,
+            }
+        })
+        .ok_or_else(|| format!("Unexpected tunnel request: {:?}", head))?;
+
+    let split = target
+        .rfind(':')
+        .ok_or_else(|| format!("Tunnel target {:?} has no port", target))?;
+    let host = &target[..split];
+    let port: u16 = target[split + 1..]
+        .parse()
+        .map_err(|x| format!("Tunnel target {:?} has a bad port: {:?}", target, x))?;
+
+    // Only HTTPS passes through here, so don't act as an open proxy for anything else
+    if port != 443 {
+        client
+            .write_all(b"HTTP/1.1 403 Forbidden\r\n\r\n")
+            .map_err(|x| format!("Failed to reject tunnel: {:?}", x))?;
+        return Err(format!("Refusing to tunnel to {:?}", target));
+    }
+
+    let server = connect_first(&resolve_doh(host)?, port)
+        .ok_or_else(|| format!("Unable to connect to any address of {:?}", host))?;
+
+    client
+        .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+        .map_err(|x| format!("Failed to accept tunnel: {:?}", x))?;
+
+    let mut client_read = client
+        .try_clone()
+        .map_err(|x| format!("Failed to clone socket: {:?}", x))?;
+    let mut server_write = server
+        .try_clone()
+        .map_err(|x| format!("Failed to clone socket: {:?}", x))?;
+
+    let upload = thread::spawn(move || {
+        let _ = io::copy(&mut client_read, &mut server_write);
+        let _ = server_write.shutdown(Shutdown::Write);
+    });
+
+    let (mut server_read, mut client_write) = (server, client);
+    let _ = io::copy(&mut server_read, &mut client_write);
+    let _ = client_write.shutdown(Shutdown::Write);
+    let _ = upload.join();
+
+    Ok(())
+}
+
+/// Starts the local tunnel, if it isn't already running, returning its address.
+fn start_tunnel() -> Result<SocketAddr, String> {
+    let mut tunnel_addr = TUNNEL.lock().log_expect("Tunnel state has been dirtied");
+    if let Some(addr) = *tunnel_addr {
+        return Ok(addr);
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .or_else(|_| TcpListener::bind("[::1]:0"))
+        .map_err(|x| format!("Failed to bind DoH tunnel: {:?}", x))?;
+    let addr = listener
+        .local_addr()
+        .map_err(|x| format!("Failed to get DoH tunnel address: {:?}", x))?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    thread::spawn(move || {
+                        if let Err(v) = tunnel(stream) {
+                            warn!("DoH tunnel failed: {}", v);
+                        }
+                    });
+                }
+                Err(v) => warn!("Failed to accept DoH tunnel connection: {:?}", v),
+            }
+        }
+    });
+
+    info!("Started DoH tunnel on {}", addr);
+    *tunnel_addr = Some(addr);
+
+    Ok(addr)
+}
+
+/// Returns a proxy to route a request through, if its host can only be found using DoH.
+pub fn fallback_proxy(url: &Url) -> Option<Url> {
+    if DOH_URL
+        .read()
+        .log_expect("DoH state has been dirtied")
+        .is_none()
+    {
+        return None;
+    }
+
+    // IP addresses don't need resolving, which includes the DoH endpoint itself
+    let host = match url.host()? {
+        Host::Domain(host) => host,
+        _ => return None,
+    };
+
+    if url.scheme() != "https" || system_resolves(host) {
+        return None;
+    }
+
+    match start_tunnel() {
+        Ok(addr) => Url::parse(&format!("http://{}", addr)).ok(),
+        Err(v) => {
+            warn!("{}", v);
+            None
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//! environments.rs
+//!
+//! Named environments (e.g. `dev`, `staging` and `prod`) point the same installer at
+//! different release feeds, so that QA can test against staging without editing and
+//! re-embedding the configuration. Selecting one with `--environment` (or the
+//! `LIFTINSTALL_ENVIRONMENT` variable) replaces parts of the configuration:
+//!
+//! ```toml
+//! [[environments]]
+//! name = "staging"
+//! channel = "beta"
+//! webhooks = [{ url = "https://staging.example.com/install-events" }]
+//!
+//! [environments.sources.Game]
+//! repo = "example/game-staging"
+//! ```
+//!
+//! `sources` replaces values within the source configuration of packages, by package
+//! name. The maintenance tool keeps using the environment it was installed from.
+
+use config::{Config, Environment};
+
+/// Variable which selects an environment, if not given on the command line.
+pub const ENV_VAR: &str = "LIFTINSTALL_ENVIRONMENT";
+
+/// Finds an environment by name.
+pub fn find<'a>(config: &'a Config, name: &str) -> Result<&'a Environment, String> {
+    config
+        .environments
+        .iter()
+        .find(|x| x.name == name)
+        .ok_or_else(|| {
+            let names: Vec<&str> = config
+                .environments
+                .iter()
+                .map(|x| x.name.as_str())
+                .collect();
+            format!(
+                "Unknown environment {:?} (expected one of {:?})",
+                name, names
+            )
+        })
+}
+
+/// Replaces parts of the configuration with those of the selected environment, if any.
+/// Its channel is applied when channels are looked up, as it may not apply to every
+/// package.
+pub fn apply(config: &mut Config, name: Option<&str>) -> Result<(), String> {
+    let name = match name {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+
+    let environment = find(config, name)?.clone();
+    info!("Using the {:?} environment", name);
+
+    for (package_name, values) in &environment.sources {
+        let values = values.as_table().ok_or_else(|| {
+            format!(
+                "Sources of {:?} in environment {:?} should be a table",
+                package_name, name
+            )
+        })?;
+
+        let package = config
+            .packages
+            .iter_mut()
+            .find(|x| &x.name == package_name)
+            .ok_or_else(|| {
+                format!(
+                    "Environment {:?} configures unknown package {:?}",
+                    name, package_name
+                )
+            })?;
+
+        let table = package
+            .source
+            .config
+            .as_table_mut()
+            .ok_or_else(|| format!("Source config of {:?} is not a table", package_name))?;
+        for (key, value) in values {
+            table.insert(key.clone(), value.clone());
+        }
+    }
+
+    // The first matching rewrite is used, so the environment's take precedence
+    let mut url_rewrites = environment.url_rewrites;
+    url_rewrites.append(&mut config.url_rewrites);
+    config.url_rewrites = url_rewrites;
+
+    if let Some(webhooks) = environment.webhooks {
+        config.webhooks = webhooks;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,178 @@
+//! error_help.rs
+//!
+//! Points users at a page explaining what went wrong, shown with an error as "Learn
+//! more about this error". Publishers map error codes to pages of their own knowledge
+//! base with `error_help` in the configuration:
+//!
+//! ```toml
+//! [[error_help]]
+//! code = "not_enough_space"
+//! url = "https://support.example.com/{locale}/installer/disk-space"
+//!
+//! [[error_help]]
+//! code = "license_server"
+//! match = "(?i)licen[cs]e server"
+//! url = "https://support.example.com/{locale}/installer/licensing"
+//! ```
+//!
+//! Errors are plain messages, so the installer's own errors are given their code where
+//! they are raised (see `coded`), and recognised by that when they are shown, even if
+//! they have been wrapped in other messages since. Built-in codes (see `BUILT_IN`) are
+//! otherwise found by looking through the message for what the system reports for common
+//! failures. Entries with a `match` regex may use any code, and are checked against the
+//! message instead. Entries are tried in the order they are configured. The bootstrap
+//! configuration may also have `error_help`, for the errors shown before the
+//! configuration has been fetched.
+//!
+//! `{locale}` in a page's URL is replaced with the language being shown (e.g. `de`), for
+//! knowledge bases which are translated.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use regex::Regex;
+
+use config::ErrorHelp;
+
+use logging::LoggingErrors;
+
+use strings;
+
+/// Placeholder in help URLs which is replaced with the language being shown.
+const LOCALE_PLACEHOLDER: &str = "{locale}";
+
+/// How many errors raised with a code are remembered.
+const RAISED_KEPT: usize = 64;
+
+/// A built-in error code, along with how it is recognised.
+struct ErrorCode {
+    code: &'static str,
+    /// Lowercase text of messages from elsewhere, such as the system
+    patterns: &'static [&'static str],
+}
+
+lazy_static! {
+    /// Errors raised with a code, most recent last
+    static ref RAISED: Mutex<VecDeque<(String, &'static str)>> = Mutex::new(VecDeque::new());
+}
+
+/// The error codes which can be used without a `match` regex. Proxy failures come before
+/// other network failures, as they mention both.
+const BUILT_IN: &[ErrorCode] = &[
+    ErrorCode {
+        code: "not_enough_space",
+        patterns: &["no space left", "not enough space", "disk is full"],
+    },
+    ErrorCode {
+        code: "application_running",
+        patterns: &["being used by another process"],
+    },
+    ErrorCode {
+        code: "permission_denied",
+        patterns: &["permission denied", "permissiondenied", "access is denied"],
+    },
+    ErrorCode {
+        code: "proxy",
+        patterns: &["proxy"],
+    },
+    ErrorCode {
+        code: "network",
+        patterns: &[
+            "error trying to connect",
+            "connection refused",
+            "connection reset",
+            "failed to lookup address",
+            "dns error",
+            "network is unreachable",
+            "timed out",
+        ],
+    },
+    ErrorCode {
+        code: "verification_failed",
+        patterns: &[],
+    },
+    ErrorCode {
+        code: "unsupported_system",
+        patterns: &[],
+    },
+    ErrorCode {
+        code: "maintenance_restricted",
+        patterns: &[],
+    },
+];
+
+/// A page explaining an error.
+#[derive(Serialize)]
+pub struct ErrorHelpLink {
+    pub code: String,
+    pub url: String,
+}
+
+/// Returns if a code is one of the built-in ones.
+pub fn is_built_in(code: &str) -> bool {
+    BUILT_IN.iter().any(|x| x.code == code)
+}
+
+/// Records that an error being raised has one of the built-in codes, returning it.
+pub fn coded(code: &'static str, message: String) -> String {
+    debug_assert!(is_built_in(code), "Unknown error code {:?}", code);
+
+    let mut raised = RAISED.lock().log_expect("Error codes have been dirtied");
+    if raised.len() >= RAISED_KEPT {
+        raised.pop_front();
+    }
+    raised.push_back((message.clone(), code));
+
+    message
+}
+
+/// Returns the code of the most recent error raised with one that a message contains.
+fn raised_code(message: &str) -> Option<&'static str> {
+    RAISED
+        .lock()
+        .log_expect("Error codes have been dirtied")
+        .iter()
+        .rev()
+        .find(|x| message.contains(&x.0))
+        .map(|x| x.1)
+}
+
+/// Returns if a message is what a built-in code describes.
+fn matches_built_in(code: &str, message: &str) -> bool {
+    if let Some(raised) = raised_code(message) {
+        return raised == code;
+    }
+
+    let lowercase = message.to_lowercase();
+
+    BUILT_IN
+        .iter()
+        .filter(|x| x.code == code)
+        .any(|x| x.patterns.iter().any(|pattern| lowercase.contains(pattern)))
+}
+
+/// Returns if an entry applies to a message.
+fn matches(entry: &ErrorHelp, message: &str) -> bool {
+    match entry.match_regex {
+        Some(ref pattern) => match Regex::new(pattern) {
+            Ok(v) => v.is_match(message),
+            Err(v) => {
+                warn!("Invalid error help regex {:?}: {:?}", pattern, v);
+                false
+            }
+        },
+        None => matches_built_in(&entry.code, message),
+    }
+}
+
+/// Finds the page for an error message among configured entries, if any.
+pub fn find(entries: &[ErrorHelp], message: &str) -> Option<ErrorHelpLink> {
+    let entry = entries.iter().find(|x| matches(x, message))?;
+
+    Some(ErrorHelpLink {
+        code: entry.code.clone(),
+        url: entry
+            .url
+            .replace(LOCALE_PLACEHOLDER, &strings::get_language()),
+    })
+}
@@ -0,0 +1,126 @@
+//! eta.rs
+//!
+//! Estimates how long an operation has left. The rate of progress is smoothed using an
+//! exponentially weighted moving average for each phase (downloading, extracting, ...),
+//! seeded with the rates seen during previous runs, so that early estimates don't swing
+//! wildly.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Weight given to each new sample of the progress rate.
+const SMOOTHING: f64 = 0.1;
+/// Minimum time between samples, in seconds.
+const SAMPLE_INTERVAL: f64 = 0.5;
+/// How long a phase without any history must run before it is estimated, in seconds.
+const WARMUP: f64 = 3.0;
+/// Phases shorter than this, in seconds, are too noisy to remember.
+const MIN_RECORDED_DURATION: f64 = 1.0;
+
+/// The phase of an operation currently being measured.
+struct Phase {
+    name: String,
+    start: Instant,
+    start_progress: f64,
+    last_sample: Instant,
+    last_progress: f64,
+    /// Smoothed progress per second
+    rate: Option<f64>,
+}
+
+/// Tracks progress messages to estimate the time remaining.
+pub struct EtaEstimator {
+    /// Progress per second of each phase, from previous runs
+    history: HashMap<String, f64>,
+    phase: Option<Phase>,
+}
+
+fn duration_secs(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1_000_000_000.0
+}
+
+impl EtaEstimator {
+    /// Creates a new estimator, seeded with the progress rates from previous runs.
+    pub fn new(history: HashMap<String, f64>) -> EtaEstimator {
+        EtaEstimator {
+            history,
+            phase: None,
+        }
+    }
+
+    /// Remembers how quickly the current phase progressed, for future runs.
+    fn finish_phase(&mut self, now: Instant) {
+        let phase = match self.phase.take() {
+            Some(v) => v,
+            None => return,
+        };
+
+        let duration = duration_secs(now - phase.start);
+        let progressed = phase.last_progress - phase.start_progress;
+        if duration < MIN_RECORDED_DURATION || progressed <= 0.0 {
+            return;
+        }
+
+        let rate = progressed / duration;
+        let rate = match self.history.get(&phase.name) {
+            Some(previous) => (previous + rate) / 2.0,
+            None => rate,
+        };
+
+        self.history.insert(phase.name, rate);
+    }
+
+    /// Records a progress message, returning the estimated seconds remaining if known.
+    ///
+    /// Phases are named after the id of their messages, e.g. "progress.downloading", so
+    /// that rates are remembered the same whichever language is shown.
+    pub fn update(&mut self, id: &str, progress: f64) -> Option<u64> {
+        let name = id.to_string();
+        let now = Instant::now();
+
+        if self.phase.as_ref().map_or(true, |x| x.name != name) {
+            self.finish_phase(now);
+
+            self.phase = Some(Phase {
+                rate: self.history.get(&name).cloned(),
+                name,
+                start: now,
+                start_progress: progress,
+                last_sample: now,
+                last_progress: progress,
+            });
+        }
+
+        let phase = self.phase.as_mut()?;
+
+        let elapsed = duration_secs(now - phase.last_sample);
+        if elapsed >= SAMPLE_INTERVAL {
+            // Progress can briefly move backwards as tasks hand over
+            let sample = (progress - phase.last_progress) / elapsed;
+            if sample >= 0.0 {
+                phase.rate = Some(match phase.rate {
+                    Some(rate) => rate + SMOOTHING * (sample - rate),
+                    None => sample,
+                });
+            }
+
+            phase.last_sample = now;
+            phase.last_progress = progress;
+        }
+
+        if !self.history.contains_key(&phase.name) && duration_secs(now - phase.start) < WARMUP {
+            return None;
+        }
+
+        match phase.rate {
+            Some(rate) if rate > 0.0 => Some(((1.0 - progress).max(0.0) / rate).round() as u64),
+            _ => None,
+        }
+    }
+
+    /// Finishes estimating, returning the updated progress rates to remember.
+    pub fn finish(mut self) -> HashMap<String, f64> {
+        self.finish_phase(Instant::now());
+        self.history
+    }
+}
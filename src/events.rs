@@ -0,0 +1,104 @@
+//! events.rs
+//!
+//! A stream of everything happening within the installer (tasks starting and finishing,
+//! log lines, questions for the user), which custom frontends can subscribe to instead of
+//! polling individual endpoints.
+
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+
+lazy_static! {
+    static ref SUBSCRIBERS: Mutex<Vec<Sender<Event>>> = Mutex::new(Vec::new());
+}
+
+/// Something which happened within the installer.
+#[derive(Serialize, Clone, Debug)]
+pub enum Event {
+    TaskStarted {
+        task: String,
+    },
+    TaskFinished {
+        task: String,
+        error: Option<String>,
+    },
+    /// A task hasn't shown any progress for `secs` seconds (see `heartbeat`)
+    TaskStalled {
+        task: String,
+        secs: u64,
+    },
+    /// A task which had stalled is making progress again
+    TaskResumed {
+        task: String,
+    },
+    /// The status of the running operation, as shown by the progress bar
+    Progress {
+        message: String,
+        /// Between 0 and 1
+        progress: f64,
+        /// Estimated seconds until the operation finishes, if known
+        remaining: Option<u64>,
+    },
+    /// A package download has received more data
+    Download {
+        package: String,
+        downloaded: u64,
+        /// Zero if the server didn't say how large the file is
+        total: u64,
+    },
+    /// A log line, at info level or above
+    Log {
+        level: String,
+        target: String,
+        message: String,
+    },
+    /// The user has been asked to choose between options (see `prompts`)
+    Prompt {
+        question: String,
+        choices: Vec<String>,
+    },
+    /// The user has been asked to choose a directory, with `default` suggested
+    DirectoryPrompt {
+        question: String,
+        default: String,
+    },
+    /// The user has been asked to type in some text, with `default` suggested
+    TextPrompt {
+        question: String,
+        default: String,
+    },
+    /// A line printed by an external command (see `sandbox`)
+    CommandOutput {
+        program: String,
+        line: String,
+        error: bool,
+    },
+    ConfigReloaded,
+    /// Something to be read out by screen readers (see `announcements`). Urgent
+    /// announcements should interrupt whatever is being read.
+    Announcement {
+        message: String,
+        urgent: bool,
+    },
+}
+
+/// Returns a channel which receives every event from now on.
+pub fn subscribe() -> Receiver<Event> {
+    let (sender, receiver) = channel();
+
+    if let Ok(mut subscribers) = SUBSCRIBERS.lock() {
+        subscribers.push(sender);
+    }
+
+    receiver
+}
+
+/// Sends an event to all subscribers, forgetting any which have gone away.
+///
+/// This is called while logging, so must never log itself.
+pub fn publish(event: Event) {
+    if let Ok(mut subscribers) = SUBSCRIBERS.lock() {
+        if !subscribers.is_empty() {
+            subscribers.retain(|x| x.send(event.clone()).is_ok());
+        }
+    }
+}
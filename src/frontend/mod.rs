@@ -2,22 +2,55 @@
 //!
 //! Provides the frontend interface, including HTTP server.
 
+use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
 
+use config_watch;
+use control;
 use installer::InstallerFramework;
 use logging::LoggingErrors;
+use queue;
 
 pub mod rest;
 mod ui;
 
 /// Launches the main web server + UI. Returns when the framework has been consumed + web UI closed.
-pub fn launch(app_name: &str, is_launcher: bool, framework: InstallerFramework) {
+///
+/// The server uses a random port unless one is specified, and is also served on a Unix
+/// domain socket if one is specified.
+pub fn launch(
+    app_name: &str,
+    is_launcher: bool,
+    port: Option<u16>,
+    socket: Option<PathBuf>,
+    framework: InstallerFramework,
+) -> Result<(), String> {
+    // Jobs which were still queued when the installer last closed
+    if framework.preexisting_install {
+        if let Some(ref path) = framework.install_path {
+            queue::load(path);
+        }
+    }
+
     let framework = Arc::new(RwLock::new(framework));
 
-    let (servers, address) = rest::server::spawn_servers(framework.clone());
+    let (mut servers, address) = rest::server::spawn_servers(framework.clone(), port)?;
+    if let Some(ref socket) = socket {
+        let server = rest::server::spawn_socket_server(framework.clone(), socket)?;
+        servers.push(server);
+    }
+
+    config_watch::spawn(framework.clone());
 
     ui::start_ui(app_name, &address, is_launcher);
 
+    // Operations hold the framework while they run. Closing the window cancels them, so
+    // that whatever they had done is rolled back before exiting. Anything which can't be
+    // undone is left to finish instead.
+    if framework.try_write().is_err() {
+        control::cancel_if_cancellable();
+    }
+
     // Explicitly hint that we want the servers instance until here.
     drop(servers);
 
@@ -26,4 +59,6 @@ pub fn launch(app_name: &str, is_launcher: bool, framework: InstallerFramework)
         .log_expect("Failed to write to framework to finalize")
         .shutdown()
         .log_expect("Failed to finalize framework");
+
+    Ok(())
 }
@@ -2,6 +2,6 @@
 //!
 //! Contains the main web server used within the application.
 
-mod assets;
+pub mod assets;
 pub mod server;
 pub mod services;
@@ -1,6 +1,9 @@
 //! frontend/rest/server.rs
 //!
 //! Contains the over-arching server object + methods to manipulate it.
+//!
+//! The UI is served on a local port, and may also be served on a Unix domain socket
+//! (see `spawn_socket_server`) for integrations which don't use the built-in web view.
 
 use frontend::rest::services::WebService;
 
@@ -8,11 +11,19 @@ use installer::InstallerFramework;
 
 use logging::LoggingErrors;
 
+use hashing::to_hex;
+
+use strings;
+
 use hyper::server::Http;
 
+use ring::rand::{SecureRandom, SystemRandom};
+
+use std::sync::mpsc::channel;
 use std::sync::{Arc, RwLock};
 
 use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::path::Path;
 
 use std::thread;
 use std::thread::JoinHandle;
@@ -24,63 +35,227 @@ pub struct WebServer {
 }
 
 impl WebServer {
-    /// Creates a new web server with the specified address.
+    /// Creates a new web server with the specified address, serving beneath the
+    /// specified path prefix.
     pub fn with_addr(
         framework: Arc<RwLock<InstallerFramework>>,
         addr: SocketAddr,
-    ) -> Result<Self, hyper::Error> {
+        prefix: String,
+    ) -> Result<Self, String> {
+        // Servers can't leave the thread which runs them, so it reports how binding went
+        let (sender, receiver) = channel();
+
+        let handle = thread::spawn(move || {
+            let server = Http::new().bind(&addr, move || {
+                Ok(WebService::new(
+                    framework.clone(),
+                    Some(addr.port()),
+                    prefix.clone(),
+                ))
+            });
+
+            match server {
+                Ok(server) => {
+                    let _ = sender.send(Ok(()));
+                    server.run().log_expect("Failed to run HTTP server");
+                }
+                Err(v) => {
+                    let _ = sender.send(Err(format!("Unable to bind to {:?}: {:?}", addr, v)));
+                }
+            }
+        });
+
+        receiver
+            .recv()
+            .map_err(|_| format!("Server for {:?} stopped while starting", addr))??;
+
+        Ok(WebServer { _handle: handle })
+    }
+
+    /// Creates a new web server on a Unix domain socket at the specified path, which only
+    /// the current user can connect to. Sockets aren't reachable by other local software
+    /// scanning for the installer, so nothing is served beneath a path prefix.
+    #[cfg(not(windows))]
+    pub fn with_socket(
+        framework: Arc<RwLock<InstallerFramework>>,
+        path: &Path,
+    ) -> Result<Self, String> {
+        use futures::stream::Stream;
+        use std::fs::{remove_file, set_permissions, symlink_metadata, Permissions};
+        use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+        use std::os::unix::net::UnixListener as StdUnixListener;
+        use tokio_core::reactor::Core;
+        use tokio_uds::UnixListener;
+
+        // Sockets left behind by an earlier run would stop this one from being bound, but
+        // anything else there isn't ours to remove
+        if let Ok(metadata) = symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                return Err(format!("{:?} already exists and isn't a socket", path));
+            }
+
+            remove_file(path).map_err(|x| format!("Unable to remove {:?}: {:?}", path, x))?;
+        }
+
+        let listener = StdUnixListener::bind(path)
+            .map_err(|x| format!("Unable to bind to {:?}: {:?}", path, x))?;
+        set_permissions(path, Permissions::from_mode(0o600))
+            .map_err(|x| format!("Unable to restrict access to {:?}: {:?}", path, x))?;
+
+        let path = path.to_owned();
         let handle = thread::spawn(move || {
-            let server = Http::new()
-                .bind(&addr, move || Ok(WebService::new(framework.clone())))
-                .log_expect("Failed to bind to port");
+            let mut core = Core::new().log_expect("Failed to create event loop");
+            let handle = core.handle();
+
+            let listener = UnixListener::from_std(listener, &handle.new_tokio_handle())
+                .log_expect("Failed to register socket");
+
+            // Connections through a socket have no address of their own
+            let remote = SocketAddr::from(([127, 0, 0, 1], 0));
+            let http = Http::new();
 
-            server.run().log_expect("Failed to run HTTP server");
+            let server = listener.incoming().for_each(move |stream| {
+                http.bind_connection(
+                    &handle,
+                    stream,
+                    remote,
+                    WebService::new(framework.clone(), None, String::new()),
+                );
+                Ok(())
+            });
+
+            if let Err(v) = core.run(server) {
+                error!("Server on {:?} failed: {:?}", path, v);
+            }
         });
 
         Ok(WebServer { _handle: handle })
     }
 }
 
+/// Loopback addresses to fall back to, in case `localhost` doesn't resolve to a usable
+/// address (such as on IPv6-only hosts).
+const LOOPBACK_ADDRESSES: [&str; 2] = ["127.0.0.1:0", "[::1]:0"];
+
+/// Generates an unguessable path prefix for this session, so that other local software
+/// can't find or fingerprint the installer by scanning ports.
+fn generate_prefix() -> Result<String, String> {
+    let mut nonce = [0 as u8; 16];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| "Unable to generate a session prefix".to_string())?;
+
+    Ok(format!("/s/{}", to_hex(&nonce)))
+}
+
 /// Spawns a server instance on all local interfaces.
 ///
-/// Returns server instances + http address of service running.
-pub fn spawn_servers(framework: Arc<RwLock<InstallerFramework>>) -> (Vec<WebServer>, String) {
-    // Firstly, allocate us an epidermal port
-    let target_port = {
-        let listener = TcpListener::bind("127.0.0.1:0")
-            .log_expect("At least one local address should be free");
-        listener
-            .local_addr()
-            .log_expect("Should be able to pull address from listener")
-            .port()
+/// Uses the specified port if any, such as for firewalled deployments, else a random one.
+///
+/// Returns server instances + http address of service running, or an error if nothing
+/// could be served (e.g. as the specified port is already in use).
+pub fn spawn_servers(
+    framework: Arc<RwLock<InstallerFramework>>,
+    port: Option<u16>,
+) -> Result<(Vec<WebServer>, String), String> {
+    // Firstly, allocate us an epidermal port, on whichever address family is available
+    let target_port = match port {
+        Some(port) => port,
+        None => LOOPBACK_ADDRESSES
+            .iter()
+            .filter_map(|x| TcpListener::bind(x).ok())
+            .filter_map(|x| x.local_addr().ok())
+            .map(|x| x.port())
+            .next()
+            .ok_or("No local address is free to serve the user interface on")?,
+    };
+
+    // Now, iterate over all addresses localhost resolves to, plus the loopback addresses
+    let localhost: Vec<SocketAddr> = match "localhost:0".to_socket_addrs() {
+        Ok(x) => x.collect(),
+        Err(v) => {
+            warn!("Unable to resolve localhost: {:?}", v);
+            Vec::new()
+        }
     };
 
-    // Now, iterate over all ports
-    let addresses = "localhost:0"
-        .to_socket_addrs()
-        .log_expect("No localhost address found");
+    let mut addresses = localhost.clone();
+    for address in LOOPBACK_ADDRESSES.iter().filter_map(|x| x.parse().ok()) {
+        if !addresses.contains(&address) {
+            addresses.push(address);
+        }
+    }
+
+    let prefix = generate_prefix()?;
 
     let mut instances = Vec::with_capacity(addresses.len());
-    let mut http_address = None;
+    let mut bound = Vec::new();
 
     // Startup HTTP server for handling the web view
     for mut address in addresses {
         address.set_port(target_port);
 
-        let server = WebServer::with_addr(framework.clone(), address)
-            .log_expect("Failed to bind to address");
+        // Never expose the installer beyond this machine
+        if !address.ip().is_loopback() {
+            warn!("Skipping non-loopback address {:?} for localhost", address);
+            continue;
+        }
+
+        // Address families may be disabled entirely, or the port may be in use
+        let server = match WebServer::with_addr(framework.clone(), address, prefix.clone()) {
+            Ok(v) => v,
+            Err(v) => {
+                info!("Skipping unavailable address {:?}: {}", address, v);
+                continue;
+            }
+        };
 
         info!("Spawning server instance @ {:?}", address);
 
-        http_address = Some(address);
+        bound.push(address);
 
         instances.push(server);
     }
 
-    let http_address = http_address.log_expect("No HTTP address found");
+    if bound.is_empty() {
+        return Err(match port {
+            Some(port) => strings::format("errors.port_in_use", &[("port", &port.to_string())]),
+            None => "Unable to serve the user interface on any local address".to_string(),
+        });
+    }
+
+    // Prefer localhost, but only if it's actually served
+    let http_address = if localhost
+        .iter()
+        .any(|x| bound.iter().any(|y| x.ip() == y.ip()))
+    {
+        format!("http://localhost:{}{}/", target_port, prefix)
+    } else {
+        format!("http://{}{}/", bound[0], prefix)
+    };
+
+    Ok((instances, http_address))
+}
 
-    (
-        instances,
-        format!("http://localhost:{}", http_address.port()),
-    )
+/// Spawns a server instance on a Unix domain socket at the specified path, alongside
+/// those on local interfaces. Only supported on Unix.
+pub fn spawn_socket_server(
+    framework: Arc<RwLock<InstallerFramework>>,
+    path: &Path,
+) -> Result<WebServer, String> {
+    #[cfg(not(windows))]
+    {
+        let server = WebServer::with_socket(framework, path)?;
+        info!("Spawning server instance @ {:?}", path);
+        Ok(server)
+    }
+
+    #[cfg(windows)]
+    {
+        let _ = framework;
+        Err(format!(
+            "Unable to serve on {:?}, as sockets are only supported on Unix",
+            path
+        ))
+    }
 }
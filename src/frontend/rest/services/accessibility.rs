@@ -0,0 +1,49 @@
+//! frontend/rest/services/accessibility.rs
+//!
+//! The /api/accessibility call returns if high contrast colours should be used and
+//! animations avoided, as detected from the OS unless overridden by the bootstrap
+//! configuration's `accessibility` section.
+
+use frontend::rest::services::default_future;
+use frontend::rest::services::Future;
+use frontend::rest::services::Request;
+use frontend::rest::services::Response;
+use frontend::rest::services::WebService;
+
+use hyper::header::{ContentLength, ContentType};
+
+use logging::LoggingErrors;
+
+use native;
+
+/// Struct used by serde to send a JSON payload to the client.
+#[derive(Serialize)]
+struct AccessibilityResponse {
+    high_contrast: bool,
+    reduced_motion: bool,
+}
+
+pub fn handle(service: &WebService, _req: Request) -> Future {
+    let overrides = service
+        .get_framework_read()
+        .base_attributes
+        .accessibility
+        .clone();
+
+    let file = serde_json::to_string(&AccessibilityResponse {
+        high_contrast: overrides
+            .high_contrast
+            .unwrap_or_else(native::is_high_contrast_active),
+        reduced_motion: overrides
+            .reduced_motion
+            .unwrap_or_else(native::is_reduced_motion_preferred),
+    })
+    .log_expect("Failed to render JSON payload of accessibility settings");
+
+    default_future(
+        Response::new()
+            .with_header(ContentLength(file.len() as u64))
+            .with_header(ContentType::json())
+            .with_body(file),
+    )
+}
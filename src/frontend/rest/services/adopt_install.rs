@@ -0,0 +1,35 @@
+//! frontend/rest/services/adopt_install.rs
+//!
+//! The /api/adopt-install call updates an installation which the user has moved, so that its
+//! metadata and shortcuts point to where it now is.
+
+use frontend::rest::services::default_future;
+use frontend::rest::services::stream_progress;
+use frontend::rest::services::Future;
+use frontend::rest::services::Request;
+use frontend::rest::services::WebService;
+
+use logging::LoggingErrors;
+
+use installer::InstallMessage;
+
+pub fn handle(service: &WebService, _req: Request) -> Future {
+    let framework = service.framework.clone();
+
+    default_future(stream_progress(move |sender| {
+        let mut framework = framework
+            .write()
+            .log_expect("InstallerFramework has been dirtied");
+
+        if let Err(v) = framework.adopt_moved_install(&sender) {
+            error!("Adopting moved install failed: {:?}", v);
+            if let Err(v) = sender.send(InstallMessage::Error(v)) {
+                error!("Failed to send adoption error: {:?}", v);
+            };
+        }
+
+        if let Err(v) = sender.send(InstallMessage::EOF) {
+            error!("Failed to send EOF to client: {:?}", v);
+        }
+    }))
+}
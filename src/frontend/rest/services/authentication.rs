@@ -22,6 +22,7 @@ use http::{build_async_client, build_client};
 
 use config::JWTValidation;
 
+use logging;
 use logging::LoggingErrors;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -193,6 +194,7 @@ pub fn handle(service: &WebService, _req: Request) -> InternalFuture {
                 let (username, token) = {
                     let req_username = req.get("username").log_expect("No username in request");
                     let req_token = req.get("token").log_expect("No token in request");
+                    logging::register_secret(req_token);
 
                     // if the user didn't provide credentials, and theres nothing stored in the
                     // database, return an early error
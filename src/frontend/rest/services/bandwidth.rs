@@ -0,0 +1,74 @@
+//! frontend/rest/services/bandwidth.rs
+//!
+//! The /api/bandwidth call returns how much has been downloaded this session and each
+//! month, along with the monthly data budget (GET), or sets the budget from a
+//! `monthly_budget` form field, in bytes (POST). An empty `monthly_budget` removes it.
+
+use frontend::rest::services::default_future;
+use frontend::rest::services::Future;
+use frontend::rest::services::Request;
+use frontend::rest::services::Response;
+use frontend::rest::services::WebService;
+
+use hyper::header::{ContentLength, ContentType};
+use hyper::{Method, StatusCode};
+
+use futures::future::Future as _;
+use futures::stream::Stream;
+
+use url::form_urlencoded;
+
+use std::collections::HashMap;
+
+use logging::LoggingErrors;
+
+use bandwidth;
+
+use strings;
+
+fn summary_response() -> Response {
+    let file = serde_json::to_string(&bandwidth::summary())
+        .log_expect("Failed to render JSON representation of bandwidth usage");
+
+    Response::new()
+        .with_header(ContentLength(file.len() as u64))
+        .with_header(ContentType::json())
+        .with_body(file)
+}
+
+pub fn handle(_service: &WebService, req: Request) -> Future {
+    if req.method() == &Method::Post {
+        return set_budget(req);
+    }
+
+    default_future(summary_response())
+}
+
+fn set_budget(req: Request) -> Future {
+    Box::new(req.body().concat2().map(move |b| {
+        let results = form_urlencoded::parse(b.as_ref())
+            .into_owned()
+            .filter(|x| !x.1.trim().is_empty())
+            .collect::<HashMap<String, String>>();
+
+        let budget = match results.get("monthly_budget") {
+            Some(v) => match v.trim().parse::<u64>() {
+                Ok(v) if v > 0 => Some(v),
+                _ => {
+                    return Response::new()
+                        .with_status(StatusCode::BadRequest)
+                        .with_header(ContentType::plaintext())
+                        .with_body(strings::format(
+                            "errors.invalid_data_budget",
+                            &[("budget", v)],
+                        ));
+                }
+            },
+            None => None,
+        };
+
+        bandwidth::set_budget(budget);
+
+        summary_response()
+    }))
+}
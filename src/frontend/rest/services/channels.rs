@@ -0,0 +1,100 @@
+//! frontend/rest/services/channels.rs
+//!
+//! The /api/channels call lists the release channels of each package along with the one
+//! in use (GET), or moves a package onto the channel given by `package` and `channel`
+//! form fields (POST), reinstalling it as needed.
+
+use frontend::rest::services::default_future;
+use frontend::rest::services::stream_progress;
+use frontend::rest::services::Future;
+use frontend::rest::services::Request;
+use frontend::rest::services::Response;
+use frontend::rest::services::WebService;
+
+use hyper::header::{ContentLength, ContentType};
+use hyper::Method;
+
+use futures::future::Future as _;
+use futures::stream::Stream;
+
+use url::form_urlencoded;
+
+use logging::LoggingErrors;
+
+use config::ReleaseChannel;
+
+use installer::InstallMessage;
+
+/// The channels of a single package.
+#[derive(Serialize)]
+struct PackageChannels<'a> {
+    package: &'a str,
+    current: Option<&'a str>,
+    channels: &'a [ReleaseChannel],
+}
+
+pub fn handle(service: &WebService, req: Request) -> Future {
+    if req.method() == &Method::Post {
+        return switch_channel(service, req);
+    }
+
+    let framework = service.get_framework_read();
+
+    let packages: Vec<PackageChannels> = match framework.config {
+        Some(ref config) => config
+            .packages
+            .iter()
+            .filter(|x| !x.channels.is_empty())
+            .map(|x| PackageChannels {
+                package: &x.name,
+                current: framework.get_channel(x).map(|x| x.name.as_str()),
+                channels: &x.channels,
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let file = serde_json::to_string(&packages)
+        .log_expect("Failed to render JSON representation of channels");
+
+    default_future(
+        Response::new()
+            .with_header(ContentLength(file.len() as u64))
+            .with_header(ContentType::json())
+            .with_body(file),
+    )
+}
+
+fn switch_channel(service: &WebService, req: Request) -> Future {
+    let framework = service.framework.clone();
+
+    Box::new(req.body().concat2().map(move |b| {
+        let mut package = String::new();
+        let mut channel = String::new();
+
+        for (key, value) in form_urlencoded::parse(b.as_ref()).into_owned() {
+            match key.as_str() {
+                "package" => package = value,
+                "channel" => channel = value,
+                _ => {}
+            }
+        }
+
+        stream_progress(move |sender| {
+            let mut framework = framework
+                .write()
+                .log_expect("InstallerFramework has been dirtied");
+
+            if let Err(v) = framework.switch_channel(&package, &channel, &sender) {
+                error!("Channel switch error occurred: {:?}", v);
+                if let Err(v) = sender.send(InstallMessage::Error(v)) {
+                    error!("Failed to send channel switch error: {:?}", v);
+                }
+            }
+
+            if let Err(v) = sender.send(InstallMessage::EOF) {
+                error!("Failed to send EOF to client: {:?}", v);
+            }
+        })
+    }))
+}
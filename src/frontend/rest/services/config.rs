@@ -3,7 +3,14 @@
 //! The /api/config call returns the current installer framework configuration.
 //!
 //! This endpoint should be usable directly from a <script> tag during loading.
+//!
+//! Debug builds serve a local `config.toml` instead, if there is one (see `config_watch`).
+//! If the configuration can't be downloaded, the one cached by a previous run is served.
+//! While installing from an offline bundle, the bundle's configuration is served.
+//!
+//! Loading the configuration also starts prefetching, if it asks for that (see `prefetch`).
 
+use frontend::rest::services::Error;
 use frontend::rest::services::Future;
 use frontend::rest::services::Request;
 use frontend::rest::services::Response;
@@ -13,14 +20,72 @@ use hyper::header::{ContentLength, ContentType};
 
 use logging::LoggingErrors;
 
-use config::Config;
+use bundle;
+
+use config_watch;
+
+use prefetch;
+
+use installer::InstallerFramework;
 
 use http::build_async_client;
 
+use std::fs::read_to_string;
+use std::sync::{Arc, RwLock};
+
 use futures::stream::Stream;
 use futures::Future as _;
 
+/// Loads a configuration into the framework, returning it rendered as JSON.
+fn apply_config(
+    framework: &Arc<RwLock<InstallerFramework>>,
+    text: &str,
+) -> Result<Response, Error> {
+    let mut framework = framework
+        .write()
+        .log_expect("Failed to get write lock for framework");
+
+    framework.load_config(text).map_err(|x| {
+        error!("Error while parsing configuration file: {}", x);
+        hyper::Error::Incomplete
+    })?;
+
+    prefetch::start(&framework);
+
+    let file = framework
+        .get_config()
+        .log_expect("Config should be loaded by now")
+        .to_json_str()
+        .log_expect("Failed to render JSON representation of config");
+
+    Ok(Response::new()
+        .with_header(ContentLength(file.len() as u64))
+        .with_header(ContentType::json())
+        .with_body(file))
+}
+
 pub fn handle(service: &WebService, _req: Request) -> Future {
+    let framework = service.framework.clone();
+
+    // Debug builds can iterate on a local configuration instead
+    if let Some(path) = config_watch::local_config_path() {
+        info!("Using local configuration {:?}", path);
+
+        let result = read_to_string(&path)
+            .map_err(|x| {
+                error!("Failed to read local configuration: {:?}", x);
+                hyper::Error::Incomplete
+            })
+            .and_then(|x| apply_config(&framework, &x));
+
+        return Box::new(futures::future::result(result));
+    }
+
+    if let Some(text) = bundle::config_text() {
+        info!("Using the offline bundle's configuration");
+        return Box::new(futures::future::result(apply_config(&framework, &text)));
+    }
+
     let framework_url = {
         service
             .get_framework_read()
@@ -31,7 +96,7 @@ pub fn handle(service: &WebService, _req: Request) -> Future {
 
     info!("Downloading configuration from {:?}...", framework_url);
 
-    let framework = service.framework.clone();
+    let fallback = framework.clone();
 
     // Hyper doesn't allow for clients to do sync network operations in a async future.
     // This smallish pipeline joins the two together.
@@ -56,29 +121,25 @@ pub fn handle(service: &WebService, _req: Request) -> Future {
                     hyper::Error::Incomplete
                 })?;
 
-                let config = Config::from_toml_str(&x).map_err(|x| {
-                    error!("Serde error while parsing configuration file: {:?}", x);
-                    hyper::Error::Incomplete
-                })?;
-
-                let mut framework = framework
-                    .write()
-                    .log_expect("Failed to get write lock for framework");
-
-                framework.config = Some(config);
+                let response = apply_config(&framework, &x)?;
 
                 info!("Configuration file downloaded successfully.");
 
-                let file = framework
-                    .get_config()
-                    .log_expect("Config should be loaded by now")
-                    .to_json_str()
-                    .log_expect("Failed to render JSON representation of config");
-
-                Ok(Response::new()
-                    .with_header(ContentLength(file.len() as u64))
-                    .with_header(ContentType::json())
-                    .with_body(file))
+                Ok(response)
+            })
+            .or_else(move |x| {
+                let cached = fallback
+                    .read()
+                    .log_expect("InstallerFramework has been dirtied")
+                    .read_cached_config();
+
+                match cached {
+                    Some(text) => {
+                        warn!("Using the cached configuration instead");
+                        apply_config(&fallback, &text)
+                    }
+                    None => Err(x),
+                }
             }),
     )
 }
@@ -0,0 +1,43 @@
+//! frontend/rest/services/config_changes.rs
+//!
+//! The /api/config-changes call streams a line every time the local configuration is
+//! reloaded (see `config_watch`). Only available in debug builds.
+
+use frontend::rest::services::default_future;
+use frontend::rest::services::Future;
+use frontend::rest::services::Request;
+use frontend::rest::services::Response;
+use frontend::rest::services::WebService;
+
+use hyper::header::ContentType;
+
+use futures::future::Future as _;
+use futures::sink::Sink;
+
+use config_watch;
+
+use std::thread;
+
+pub fn handle(_service: &WebService, _req: Request) -> Future {
+    let receiver = config_watch::subscribe();
+    let (tx, rx) = hyper::Body::pair();
+
+    thread::spawn(move || {
+        let mut tx = tx;
+        while receiver.recv().is_ok() {
+            let message = b"{\"reloaded\":true}\n".to_vec();
+
+            // The frontend has gone away (or reloaded itself)
+            tx = match tx.send(Ok(message.into())).wait() {
+                Ok(v) => v,
+                Err(_) => break,
+            };
+        }
+    });
+
+    default_future(
+        Response::new()
+            .with_header(ContentType::plaintext())
+            .with_body(rx),
+    )
+}
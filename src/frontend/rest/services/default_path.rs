@@ -1,8 +1,9 @@
 //! frontend/rest/services/default_path.rs
 //!
 //! The /api/default-path returns the default path for the application to install into.
+//! POST requests give the scope to return it for with an `allUsers` form field, otherwise
+//! the path chosen so far (if any) is returned.
 
-use frontend::rest::services::default_future;
 use frontend::rest::services::Future;
 use frontend::rest::services::Request;
 use frontend::rest::services::Response;
@@ -10,26 +11,63 @@ use frontend::rest::services::WebService;
 
 use hyper::header::{ContentLength, ContentType};
 
+use futures::future::Future as _;
+use futures::stream::Stream;
+
+use url::form_urlencoded;
+
 use logging::LoggingErrors;
 
 /// Struct used by serde to send a JSON payload to the client containing an optional value.
 #[derive(Serialize)]
 struct FileSelection {
     path: Option<String>,
+    /// If a host application was expected, but couldn't be found
+    host_not_found: bool,
 }
 
-pub fn handle(service: &WebService, _req: Request) -> Future {
-    let path = { service.get_framework_read().get_default_path() };
+pub fn handle(service: &WebService, req: Request) -> Future {
+    let framework = service.framework.clone();
+
+    Box::new(req.body().concat2().map(move |b| {
+        let all_users = form_urlencoded::parse(b.as_ref())
+            .into_owned()
+            .find(|(key, _)| key == "allUsers")
+            .map(|(_, value)| value == "true");
+
+        let (path, host_not_found) = {
+            let framework = framework
+                .read()
+                .log_expect("InstallerFramework has been dirtied");
+
+            let host_not_found = match framework.find_host_path() {
+                Some(Err(_)) => true,
+                _ => false,
+            };
+
+            let path = match all_users {
+                Some(all_users) => framework.get_default_path_for(all_users),
+                // Set when handed over to an elevated copy
+                None => match framework.install_path {
+                    Some(ref v) => Some(v.display().to_string()),
+                    None => framework.get_default_path(),
+                },
+            };
+
+            (path, host_not_found)
+        };
 
-    let response = FileSelection { path };
+        let response = FileSelection {
+            path,
+            host_not_found,
+        };
 
-    let file = serde_json::to_string(&response)
-        .log_expect("Failed to render JSON payload of default path object");
+        let file = serde_json::to_string(&response)
+            .log_expect("Failed to render JSON payload of default path object");
 
-    default_future(
         Response::new()
             .with_header(ContentLength(file.len() as u64))
             .with_header(ContentType::json())
-            .with_body(file),
-    )
+            .with_body(file)
+    }))
 }
@@ -0,0 +1,75 @@
+//! frontend/rest/services/diagnostics.rs
+//!
+//! The /api/diagnostics call writes a diagnostics bundle (see `diagnostics`) into the
+//! directory given as `path`, returning where it was written.
+
+use frontend::rest::services::Future;
+use frontend::rest::services::Request;
+use frontend::rest::services::Response;
+use frontend::rest::services::WebService;
+
+use hyper::header::{ContentLength, ContentType};
+use hyper::StatusCode;
+
+use futures::future::Future as _;
+use futures::stream::Stream;
+
+use url::form_urlencoded;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use logging::LoggingErrors;
+
+use diagnostics;
+
+use strings;
+
+/// Struct used by serde to send a JSON payload to the client.
+#[derive(Serialize)]
+struct DiagnosticsResponse {
+    path: String,
+}
+
+pub fn handle(service: &WebService, req: Request) -> Future {
+    let framework = service.framework.clone();
+
+    Box::new(req.body().concat2().map(move |b| {
+        let results = form_urlencoded::parse(b.as_ref())
+            .into_owned()
+            .collect::<HashMap<String, String>>();
+
+        let result = match results.get("path").filter(|x| !x.is_empty()) {
+            Some(path) => {
+                let framework = framework
+                    .read()
+                    .log_expect("InstallerFramework has been dirtied");
+
+                diagnostics::export(&framework, Path::new(path))
+            }
+            None => Err(strings::tr("errors.no_diagnostics_dir")),
+        };
+
+        match result {
+            Ok(path) => {
+                let file = serde_json::to_string(&DiagnosticsResponse {
+                    path: path.to_string_lossy().to_string(),
+                })
+                .log_expect("Failed to render JSON payload of diagnostics path");
+
+                Response::new()
+                    .with_header(ContentLength(file.len() as u64))
+                    .with_header(ContentType::json())
+                    .with_body(file)
+            }
+            Err(v) => {
+                error!("{}", v);
+
+                Response::new()
+                    .with_status(StatusCode::InternalServerError)
+                    .with_header(ContentType::plaintext())
+                    .with_body(v)
+            }
+        }
+    }))
+}
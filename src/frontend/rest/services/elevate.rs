@@ -0,0 +1,76 @@
+//! frontend/rest/services/elevate.rs
+//!
+//! The /api/elevate call hands a fresh install over to an elevated copy of the installer,
+//! for installing somewhere that needs more privileges than this process has. The copy is
+//! started from what the framework has been told (see `elevation_args`) along with the
+//! `path`, `installForAllUsers`, `packages` and `desktopShortcut` chosen so far, and this
+//! process exits once it has been started.
+
+use frontend::rest::services::Future;
+use frontend::rest::services::Request;
+use frontend::rest::services::Response;
+use frontend::rest::services::WebService;
+
+use hyper::header::ContentType;
+use hyper::StatusCode;
+
+use futures::future::Future as _;
+use futures::stream::Stream;
+
+use url::form_urlencoded;
+
+use std::collections::HashMap;
+use std::process::exit;
+
+use logging::LoggingErrors;
+
+use native;
+
+pub fn handle(service: &WebService, req: Request) -> Future {
+    let framework = service.framework.clone();
+
+    Box::new(req.body().concat2().map(move |b| {
+        let results = form_urlencoded::parse(b.as_ref())
+            .into_owned()
+            .collect::<HashMap<String, String>>();
+
+        let mut args = framework
+            .read()
+            .log_expect("InstallerFramework has been dirtied")
+            .elevation_args();
+        if let Some(path) = results.get("path").filter(|x| !x.is_empty()) {
+            args.push("--install-dir".to_string());
+            args.push(path.to_owned());
+        }
+        if results.get("installForAllUsers").map(|x| x.as_str()) == Some("true") {
+            args.push("--all-users".to_string());
+        }
+        if let Some(packages) = results.get("packages") {
+            args.push("--packages".to_string());
+            args.push(packages.to_owned());
+        }
+        if let Some(desktop_shortcut) = results.get("desktopShortcut") {
+            args.push("--desktop-shortcut".to_string());
+            args.push((desktop_shortcut == "true").to_string());
+        }
+
+        let result = native::run_elevated(&args, false).and_then(|_| {
+            framework
+                .write()
+                .log_expect("InstallerFramework has been dirtied")
+                .shutdown()
+        });
+
+        match result {
+            Ok(_) => exit(0),
+            Err(v) => {
+                error!("Unable to hand over to an elevated installer: {}", v);
+
+                Response::new()
+                    .with_status(StatusCode::InternalServerError)
+                    .with_header(ContentType::plaintext())
+                    .with_body(v)
+            }
+        }
+    }))
+}
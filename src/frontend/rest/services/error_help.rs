@@ -0,0 +1,54 @@
+//! frontend/rest/services/error_help.rs
+//!
+//! The /api/error-help call returns the configured page explaining the error in a
+//! `message` form field, as `{"code": ..., "url": ...}`, or `null` if there is none.
+//! Until the configuration has been fetched, pages are looked up in the bootstrap
+//! configuration instead.
+
+use frontend::rest::services::Future;
+use frontend::rest::services::Request;
+use frontend::rest::services::Response;
+use frontend::rest::services::WebService;
+
+use hyper::header::{ContentLength, ContentType};
+
+use futures::future::Future as _;
+use futures::stream::Stream;
+
+use url::form_urlencoded;
+
+use std::collections::HashMap;
+
+use logging::LoggingErrors;
+
+use error_help;
+
+pub fn handle(service: &WebService, req: Request) -> Future {
+    let framework = service.framework.clone();
+
+    Box::new(req.body().concat2().map(move |b| {
+        let results = form_urlencoded::parse(b.as_ref())
+            .into_owned()
+            .collect::<HashMap<String, String>>();
+
+        let message = results.get("message").map(|x| x.as_str()).unwrap_or("");
+
+        let framework = framework
+            .read()
+            .log_expect("InstallerFramework has been dirtied");
+        let entries = match framework.config {
+            Some(ref v) => &v.error_help,
+            None => &framework.base_attributes.error_help,
+        };
+
+        let link = error_help::find(entries, message);
+
+        let file = serde_json::to_string(&link)
+            .log_expect("Failed to render JSON representation of error help");
+
+        Response::new()
+            .with_header(ContentLength(file.len() as u64))
+            .with_header(ContentType::json())
+            .with_body(file)
+    }))
+}
@@ -0,0 +1,77 @@
+//! frontend/rest/services/event_stream.rs
+//!
+//! The /api/events call streams every event within the installer (see `events`) as it
+//! happens, one JSON object per line. Clients which accept `text/event-stream` (or pass
+//! `?format=sse`) are sent Server-Sent Events instead, for use with `EventSource`.
+
+use frontend::rest::services::default_future;
+use frontend::rest::services::Future;
+use frontend::rest::services::Request;
+use frontend::rest::services::Response;
+use frontend::rest::services::WebService;
+
+use hyper::header::{Accept, ContentType};
+
+use url::form_urlencoded;
+
+use futures::future::Future as _;
+use futures::sink::Sink;
+
+use events;
+
+use logging::LoggingErrors;
+
+use std::thread;
+
+/// Whether the client has asked for Server-Sent Events rather than JSON lines.
+fn wants_sse(req: &Request) -> bool {
+    let by_query = req
+        .query()
+        .map(|x| form_urlencoded::parse(x.as_bytes()).any(|(k, v)| k == "format" && v == "sse"))
+        .unwrap_or(false);
+
+    let by_header = req
+        .headers()
+        .get::<Accept>()
+        .map(|x| x.iter().any(|x| x.item.as_ref() == "text/event-stream"))
+        .unwrap_or(false);
+
+    by_query || by_header
+}
+
+pub fn handle(_service: &WebService, req: Request) -> Future {
+    let sse = wants_sse(&req);
+    let receiver = events::subscribe();
+    let (tx, rx) = hyper::Body::pair();
+
+    thread::spawn(move || {
+        let mut tx = tx;
+        while let Ok(event) = receiver.recv() {
+            let payload =
+                serde_json::to_string(&event).log_expect("Failed to render JSON event payload");
+            let message = if sse {
+                format!("data: {}\n\n", payload)
+            } else {
+                format!("{}\n", payload)
+            };
+
+            // The frontend has gone away
+            tx = match tx.send(Ok(message.into_bytes().into())).wait() {
+                Ok(v) => v,
+                Err(_) => break,
+            };
+        }
+    });
+
+    let content_type = if sse {
+        ContentType(
+            "text/event-stream"
+                .parse()
+                .log_expect("Failed to parse content type into correct representation"),
+        )
+    } else {
+        ContentType::plaintext()
+    };
+
+    default_future(Response::new().with_header(content_type).with_body(rx))
+}
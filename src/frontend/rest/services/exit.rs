@@ -10,6 +10,8 @@ use hyper::StatusCode;
 
 use std::process::exit;
 
+use strings;
+
 pub fn handle(service: &WebService, _req: Request) -> InternalFuture {
     match service.get_framework_write().shutdown() {
         Ok(_) => {
@@ -22,7 +24,7 @@ pub fn handle(service: &WebService, _req: Request) -> InternalFuture {
                 Response::new()
                     .with_status(StatusCode::InternalServerError)
                     .with_header(ContentType::plaintext())
-                    .with_body(format!("Failed to complete framework shutdown - {}", e)),
+                    .with_body(strings::format("errors.shutdown_failed", &[("error", &e)])),
             )
         }
     }
@@ -0,0 +1,35 @@
+//! frontend/rest/services/gpu_check.rs
+//!
+//! The /api/gpu-check call checks the system's graphics drivers against the
+//! configuration's `gpu` requirements (see `gpu`), so that users can be told before
+//! installing that theirs are too old. Returns null if there are no requirements.
+
+use frontend::rest::services::default_future;
+use frontend::rest::services::Future;
+use frontend::rest::services::Request;
+use frontend::rest::services::Response;
+use frontend::rest::services::WebService;
+
+use hyper::header::{ContentLength, ContentType};
+
+use logging::LoggingErrors;
+
+use gpu;
+
+pub fn handle(service: &WebService, _req: Request) -> Future {
+    let requirements = service
+        .get_framework_read()
+        .config
+        .as_ref()
+        .and_then(|x| x.gpu.clone());
+
+    let file = serde_json::to_string(&requirements.map(|x| gpu::check(&x)))
+        .log_expect("Failed to render JSON payload of GPU check");
+
+    default_future(
+        Response::new()
+            .with_header(ContentLength(file.len() as u64))
+            .with_header(ContentType::json())
+            .with_body(file),
+    )
+}
@@ -0,0 +1,27 @@
+//! frontend/rest/services/history.rs
+//!
+//! The /api/history call returns the transcript of operations performed on this installation.
+
+use frontend::rest::services::default_future;
+use frontend::rest::services::Future;
+use frontend::rest::services::Request;
+use frontend::rest::services::Response;
+use frontend::rest::services::WebService;
+
+use hyper::header::{ContentLength, ContentType};
+
+use logging::LoggingErrors;
+
+pub fn handle(service: &WebService, _req: Request) -> Future {
+    let framework = service.get_framework_read();
+
+    let file = serde_json::to_string(&framework.database.history)
+        .log_expect("Failed to render JSON payload of installation history");
+
+    default_future(
+        Response::new()
+            .with_header(ContentLength(file.len() as u64))
+            .with_header(ContentType::json())
+            .with_body(file),
+    )
+}
@@ -11,6 +11,8 @@ use logging::LoggingErrors;
 
 use installer::InstallMessage;
 
+use policy;
+
 use futures::future::Future as _;
 use futures::stream::Stream;
 
@@ -29,6 +31,7 @@ pub fn handle(service: &WebService, req: Request) -> Future {
         let mut to_install = Vec::new();
         let mut path: Option<String> = None;
         let mut install_desktop_shortcut= false;
+        let mut install_for_all_users = false;
 
         // Transform results into just an array of stuff to install
         for (key, value) in &results {
@@ -39,6 +42,9 @@ pub fn handle(service: &WebService, req: Request) -> Future {
                 info!("Found installDesktopShortcut {:?}", value);
                 install_desktop_shortcut = value == "true";
                 continue;
+            } else if key == "installForAllUsers" {
+                install_for_all_users = value == "true";
+                continue;
             }
 
             if value == "true" {
@@ -57,7 +63,8 @@ pub fn handle(service: &WebService, req: Request) -> Future {
 
             let new_install = !framework.preexisting_install;
             if new_install {
-                framework.set_install_dir(&path);
+                framework.set_install_dir(&policy::install_dir(&path));
+                framework.database.all_users = install_for_all_users;
             }
 
             if let Err(v) = framework.install(to_install, &sender, new_install, install_desktop_shortcut) {
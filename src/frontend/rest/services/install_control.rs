@@ -0,0 +1,42 @@
+//! frontend/rest/services/install_control.rs
+//!
+//! The /api/install/cancel, /api/install/pause and /api/install/resume calls control the
+//! operation in progress. Cancelled operations stop at the next opportunity and undo
+//! their changes; operations which can't be undone aren't cancelled (see `control`).
+//! Pausing only holds up downloads.
+
+use frontend::rest::services::default_future;
+use frontend::rest::services::Future;
+use frontend::rest::services::Request;
+use frontend::rest::services::Response;
+use frontend::rest::services::WebService;
+
+use hyper::header::{ContentLength, ContentType};
+use hyper::StatusCode;
+
+use control;
+
+use prompts;
+
+pub fn handle(_service: &WebService, req: Request) -> Future {
+    match req.path() {
+        "/api/install/cancel" => {
+            // Anything waiting on the user gives up and takes its default
+            if control::cancel_if_cancellable() {
+                prompts::dismiss();
+            }
+        }
+        "/api/install/pause" => control::set_paused(true),
+        "/api/install/resume" => control::set_paused(false),
+        _ => return default_future(Response::new().with_status(StatusCode::NotFound)),
+    }
+
+    let file = "{}";
+
+    default_future(
+        Response::new()
+            .with_header(ContentLength(file.len() as u64))
+            .with_header(ContentType::json())
+            .with_body(file),
+    )
+}
@@ -0,0 +1,69 @@
+//! frontend/rest/services/install_report.rs
+//!
+//! The /api/install-report call returns the report of the last install, either from this
+//! session or as saved in the install directory. `?format=text` returns the human
+//! readable version instead of JSON.
+
+use frontend::rest::services::default_future;
+use frontend::rest::services::Future;
+use frontend::rest::services::Request;
+use frontend::rest::services::Response;
+use frontend::rest::services::WebService;
+
+use hyper::header::{ContentLength, ContentType};
+use hyper::StatusCode;
+
+use url::form_urlencoded;
+
+use logging::LoggingErrors;
+
+use report::InstallReport;
+
+pub fn handle(service: &WebService, req: Request) -> Future {
+    let framework = service.get_framework_read();
+
+    let as_text = req
+        .query()
+        .map(|x| form_urlencoded::parse(x.as_bytes()).any(|(k, v)| k == "format" && v == "text"))
+        .unwrap_or(false);
+
+    let report = match framework.report {
+        Some(ref v) => Ok(Some(v.clone())),
+        None => match framework.install_path {
+            Some(ref path) => InstallReport::load(path),
+            None => Ok(None),
+        },
+    };
+
+    let report = match report {
+        Ok(Some(v)) => v,
+        Ok(None) => return default_future(Response::new().with_status(StatusCode::NotFound)),
+        Err(v) => {
+            error!("{}", v);
+
+            return default_future(
+                Response::new()
+                    .with_status(StatusCode::InternalServerError)
+                    .with_header(ContentType::plaintext())
+                    .with_body(v),
+            );
+        }
+    };
+
+    let (file, content_type) = if as_text {
+        (report.to_text(), ContentType::plaintext())
+    } else {
+        (
+            serde_json::to_string(&report)
+                .log_expect("Failed to render JSON payload of install report"),
+            ContentType::json(),
+        )
+    };
+
+    default_future(
+        Response::new()
+            .with_header(ContentLength(file.len() as u64))
+            .with_header(content_type)
+            .with_body(file),
+    )
+}
@@ -0,0 +1,57 @@
+//! frontend/rest/services/leftovers.rs
+//!
+//! The /api/leftovers call lists data left behind after uninstalling (GET), or deletes the
+//! selected leftovers (POST), returning whatever remains.
+
+use frontend::rest::services::Future;
+use frontend::rest::services::Request;
+use frontend::rest::services::Response;
+use frontend::rest::services::WebService;
+
+use hyper::header::{ContentLength, ContentType};
+use hyper::{Method, StatusCode};
+
+use futures::future::Future as _;
+use futures::stream::Stream;
+
+use url::form_urlencoded;
+
+use logging::LoggingErrors;
+
+use leftovers;
+
+pub fn handle(service: &WebService, req: Request) -> Future {
+    let framework = service.framework.clone();
+    let is_delete = req.method() == &Method::Post;
+
+    Box::new(req.body().concat2().map(move |b| {
+        let framework = framework
+            .read()
+            .log_expect("InstallerFramework has been dirtied");
+
+        if is_delete {
+            let selected: Vec<String> = form_urlencoded::parse(b.as_ref())
+                .into_owned()
+                .filter(|(_, value)| value == "true")
+                .map(|(key, _)| key)
+                .collect();
+
+            if let Err(v) = leftovers::delete_leftovers(&framework, &selected) {
+                error!("{}", v);
+
+                return Response::new()
+                    .with_status(StatusCode::InternalServerError)
+                    .with_header(ContentType::plaintext())
+                    .with_body(v);
+            }
+        }
+
+        let file = serde_json::to_string(&leftovers::find_leftovers(&framework))
+            .log_expect("Failed to render JSON representation of leftovers");
+
+        Response::new()
+            .with_header(ContentLength(file.len() as u64))
+            .with_header(ContentType::json())
+            .with_body(file)
+    }))
+}
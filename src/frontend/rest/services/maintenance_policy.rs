@@ -0,0 +1,73 @@
+//! frontend/rest/services/maintenance_policy.rs
+//!
+//! The /api/maintenance-policy call returns the machine's policy, if modifying and
+//! uninstalling are restricted, and if this process is allowed to anyway (GET), or
+//! gives an administrator's maintenance token from a `token` form field to be allowed
+//! to (POST).
+
+use frontend::rest::services::default_future;
+use frontend::rest::services::Future;
+use frontend::rest::services::Request;
+use frontend::rest::services::Response;
+use frontend::rest::services::WebService;
+
+use hyper::header::{ContentLength, ContentType};
+use hyper::{Method, StatusCode};
+
+use futures::future::Future as _;
+use futures::stream::Stream;
+
+use url::form_urlencoded;
+
+use std::collections::HashMap;
+
+use logging::LoggingErrors;
+
+use policy;
+
+pub fn handle(service: &WebService, req: Request) -> Future {
+    if req.method() == &Method::Post {
+        return unlock(service, req);
+    }
+
+    let file = serde_json::to_string(&policy::status(&service.get_framework_read()))
+        .log_expect("Failed to render JSON representation of maintenance policy");
+
+    default_future(
+        Response::new()
+            .with_header(ContentLength(file.len() as u64))
+            .with_header(ContentType::json())
+            .with_body(file),
+    )
+}
+
+fn unlock(service: &WebService, req: Request) -> Future {
+    let framework = service.framework.clone();
+
+    Box::new(req.body().concat2().map(move |b| {
+        let results = form_urlencoded::parse(b.as_ref())
+            .into_owned()
+            .collect::<HashMap<String, String>>();
+
+        let token = results.get("token").map(|x| x.as_str()).unwrap_or("");
+
+        let mut framework = framework
+            .write()
+            .log_expect("InstallerFramework has been dirtied");
+
+        match policy::unlock(&mut framework, token) {
+            Ok(_) => {
+                let file = "{}";
+
+                Response::new()
+                    .with_header(ContentLength(file.len() as u64))
+                    .with_header(ContentType::json())
+                    .with_body(file)
+            }
+            Err(v) => Response::new()
+                .with_status(StatusCode::Forbidden)
+                .with_header(ContentType::plaintext())
+                .with_body(v),
+        }
+    }))
+}
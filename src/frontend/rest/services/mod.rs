@@ -4,6 +4,8 @@
 
 use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
+use announcements::Announcer;
+
 use installer::{InstallMessage, InstallerFramework};
 
 use hyper::server::Service;
@@ -15,23 +17,50 @@ use std::sync::mpsc::{channel, Sender};
 
 use std::thread;
 
-use hyper::header::ContentType;
+use hyper::header::{ContentType, Host};
+
+use url::Url;
 
 use futures::future::Future as _;
 use futures::sink::Sink;
 
+mod accessibility;
+mod adopt_install;
 mod attributes;
+mod bandwidth;
 pub mod authentication;
 mod browser;
+mod channels;
 mod config;
+mod config_changes;
 mod dark_mode;
 mod default_path;
+mod diagnostics;
+mod elevate;
+mod error_help;
+mod event_stream;
 mod exit;
+mod gpu_check;
+mod history;
 mod install;
+mod install_control;
+mod install_report;
 mod installation_status;
+mod leftovers;
+mod maintenance_policy;
 mod packages;
+mod prompt;
+mod proxy;
+mod queue;
+mod repair;
+mod space_estimate;
 mod static_files;
+mod strings;
+mod suite_install;
+mod system_summary;
+mod task_status;
 mod uninstall;
+mod uninstall_packages;
 mod update_updater;
 
 /// Expected incoming Request format from Hyper.
@@ -70,11 +99,14 @@ where
     // Spawn a thread for transforming messages to chunk messages
     thread::spawn(move || {
         let mut tx = tx;
+        let mut announcer = Announcer::default();
         loop {
             let response = receiver
                 .recv()
                 .log_expect("Failed to receive message from runner thread");
 
+            announcer.observe(&response);
+
             if let InstallMessage::EOF = response {
                 break;
             }
@@ -94,9 +126,16 @@ where
         .with_body(rx)
 }
 
+/// Hostnames the UI is served from. Anything else may be another site attempting to
+/// drive the installer through DNS rebinding.
+const ALLOWED_HOSTS: [&str; 4] = ["localhost", "127.0.0.1", "[::1]", "::1"];
+
 /// Holds internal state for a single Hyper instance. Multiple will exist.
 pub struct WebService {
     framework: Arc<RwLock<InstallerFramework>>,
+    /// The port being served, or None for a Unix domain socket
+    port: Option<u16>,
+    prefix: String,
 }
 
 impl WebService {
@@ -116,8 +155,53 @@ impl WebService {
 
     /// Creates a new WebService instance. Multiple are likely going to exist at once,
     /// so create a lock to hold this.
-    pub fn new(framework: Arc<RwLock<InstallerFramework>>) -> WebService {
-        WebService { framework }
+    ///
+    /// Only requests beneath the specified path prefix are served.
+    pub fn new(
+        framework: Arc<RwLock<InstallerFramework>>,
+        port: Option<u16>,
+        prefix: String,
+    ) -> WebService {
+        WebService {
+            framework,
+            port,
+            prefix,
+        }
+    }
+
+    /// Checks that a request came from the installer's own UI, rather than a web page
+    /// making cross-origin requests or rebinding its hostname to the loopback address.
+    /// Browsers can't connect to Unix domain sockets, which only their owner can connect
+    /// to, so anything arriving through one is trusted.
+    fn is_trusted(&self, req: &Request) -> bool {
+        let port = match self.port {
+            Some(v) => v,
+            None => return true,
+        };
+
+        let host_allowed = req.headers().get::<Host>().map_or(false, |host| {
+            ALLOWED_HOSTS.contains(&host.hostname()) && host.port() == Some(port)
+        });
+
+        if !host_allowed {
+            return false;
+        }
+
+        // Browsers always send an origin with cross-origin requests
+        match req.headers().get_raw("Origin").and_then(|x| x.one()) {
+            Some(origin) => {
+                Url::parse(&String::from_utf8_lossy(origin))
+                    .ok()
+                    .map_or(false, |origin| {
+                        origin.scheme() == "http"
+                            && origin
+                                .host_str()
+                                .map_or(false, |x| ALLOWED_HOSTS.contains(&x))
+                            && origin.port() == Some(port)
+                    })
+            }
+            None => true,
+        }
     }
 }
 
@@ -128,21 +212,91 @@ impl Service for WebService {
     type Future = Future;
 
     fn call(&self, req: Self::Request) -> Self::Future {
+        if !self.is_trusted(&req) {
+            warn!(
+                "Rejected untrusted request for {:?} (host: {:?}, origin: {:?})",
+                req.path(),
+                req.headers().get::<Host>(),
+                req.headers().get_raw("Origin")
+            );
+            return default_future(Response::new().with_status(StatusCode::Forbidden));
+        }
+
+        // Without the session's prefix, nothing (not even the UI) should be discoverable
+        let mut req = req;
+        let uri = if req.path().starts_with(&format!("{}/", self.prefix)) {
+            let mut uri = req.path()[self.prefix.len()..].to_string();
+            if let Some(query) = req.query() {
+                uri.push('?');
+                uri.push_str(query);
+            }
+
+            uri.parse().ok()
+        } else {
+            None
+        };
+
+        match uri {
+            Some(uri) => req.set_uri(uri),
+            None => {
+                info!("Returned 404 for unprefixed path {:?}", req.path());
+                return default_future(Response::new().with_status(StatusCode::NotFound));
+            }
+        }
+
         let method = req.method().clone();
         let path = req.path().to_string();
 
         match (method, path.as_str()) {
             (Method::Get, "/api/attrs") => attributes::handle(self, req),
+            (Method::Get, "/api/bandwidth") => bandwidth::handle(self, req),
+            (Method::Post, "/api/bandwidth") => bandwidth::handle(self, req),
+            (Method::Get, "/api/channels") => channels::handle(self, req),
+            (Method::Post, "/api/channels") => channels::handle(self, req),
             (Method::Get, "/api/config") => config::handle(self, req),
+            (Method::Get, "/api/config-changes") if cfg!(debug_assertions) => {
+                config_changes::handle(self, req)
+            }
             (Method::Get, "/api/dark-mode") => dark_mode::handle(self, req),
+            (Method::Get, "/api/accessibility") => accessibility::handle(self, req),
             (Method::Get, "/api/default-path") => default_path::handle(self, req),
+            (Method::Post, "/api/default-path") => default_path::handle(self, req),
+            (Method::Post, "/api/diagnostics") => diagnostics::handle(self, req),
+            (Method::Post, "/api/elevate") => elevate::handle(self, req),
+            (Method::Post, "/api/error-help") => error_help::handle(self, req),
+            (Method::Get, "/api/events") => event_stream::handle(self, req),
             (Method::Post, "/api/exit") => exit::handle(self, req),
+            (Method::Get, "/api/gpu-check") => gpu_check::handle(self, req),
             (Method::Get, "/api/packages") => packages::handle(self, req),
             (Method::Get, "/api/installation-status") => installation_status::handle(self, req),
+            (Method::Get, "/api/history") => history::handle(self, req),
+            (Method::Get, "/api/install-report") => install_report::handle(self, req),
+            (Method::Get, "/api/leftovers") => leftovers::handle(self, req),
+            (Method::Post, "/api/leftovers") => leftovers::handle(self, req),
+            (Method::Get, "/api/maintenance-policy") => maintenance_policy::handle(self, req),
+            (Method::Post, "/api/maintenance-policy") => maintenance_policy::handle(self, req),
+            (Method::Post, "/api/adopt-install") => adopt_install::handle(self, req),
             (Method::Post, "/api/check-auth") => authentication::handle(self, req),
             (Method::Post, "/api/start-install") => install::handle(self, req),
+            (Method::Post, "/api/start-suite-install") => suite_install::handle(self, req),
+            (Method::Post, "/api/install/cancel") => install_control::handle(self, req),
+            (Method::Post, "/api/install/pause") => install_control::handle(self, req),
+            (Method::Post, "/api/install/resume") => install_control::handle(self, req),
+            (Method::Post, "/api/space-estimate") => space_estimate::handle(self, req),
+            (Method::Get, "/api/strings") => strings::handle(self, req),
+            (Method::Post, "/api/system-summary") => system_summary::handle(self, req),
+            (Method::Get, "/api/task-status") => task_status::handle(self, req),
             (Method::Post, "/api/open-browser") => browser::handle(self, req),
+            (Method::Post, "/api/prompt") => prompt::handle(self, req),
+            (Method::Get, "/api/proxy") => proxy::handle(self, req),
+            (Method::Post, "/api/proxy") => proxy::handle(self, req),
+            (Method::Get, "/api/queue") => queue::handle(self, req),
+            (Method::Post, "/api/queue") => queue::handle(self, req),
+            (Method::Post, "/api/queue/remove") => queue::handle(self, req),
+            (Method::Post, "/api/queue/run") => queue::handle(self, req),
+            (Method::Post, "/api/repair") => repair::handle(self, req),
             (Method::Post, "/api/uninstall") => uninstall::handle(self, req),
+            (Method::Post, "/api/uninstall-packages") => uninstall_packages::handle(self, req),
             (Method::Post, "/api/update-updater") => update_updater::handle(self, req),
             (Method::Get, _) => static_files::handle(self, req),
             e => {
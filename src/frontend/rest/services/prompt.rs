@@ -0,0 +1,49 @@
+//! frontend/rest/services/prompt.rs
+//!
+//! The /api/prompt call answers a question asked by a running task.
+
+use frontend::rest::services::Future;
+use frontend::rest::services::Request;
+use frontend::rest::services::Response;
+use frontend::rest::services::WebService;
+
+use hyper::header::{ContentLength, ContentType};
+use hyper::StatusCode;
+
+use futures::future::Future as _;
+use futures::stream::Stream;
+
+use url::form_urlencoded;
+
+use std::collections::HashMap;
+
+use prompts;
+
+use strings;
+
+pub fn handle(_service: &WebService, req: Request) -> Future {
+    Box::new(req.body().concat2().map(move |b| {
+        let results = form_urlencoded::parse(b.as_ref())
+            .into_owned()
+            .collect::<HashMap<String, String>>();
+
+        let answered = match results.get("choice") {
+            Some(choice) => prompts::answer(choice.to_owned()),
+            None => false,
+        };
+
+        if answered {
+            let file = "{}";
+
+            Response::new()
+                .with_header(ContentLength(file.len() as u64))
+                .with_header(ContentType::json())
+                .with_body(file)
+        } else {
+            Response::new()
+                .with_status(StatusCode::BadRequest)
+                .with_header(ContentType::plaintext())
+                .with_body(strings::tr("errors.no_prompt"))
+        }
+    }))
+}
@@ -0,0 +1,83 @@
+//! frontend/rest/services/proxy.rs
+//!
+//! The /api/proxy call returns the manual proxy in use, if any (GET), or sets the proxy
+//! to use for the rest of the session from `url`, `username`, `password` and `no_proxy`
+//! (comma separated) form fields (POST). An empty `url` goes back to the configuration's
+//! proxy, or the system's.
+
+use frontend::rest::services::default_future;
+use frontend::rest::services::Future;
+use frontend::rest::services::Request;
+use frontend::rest::services::Response;
+use frontend::rest::services::WebService;
+
+use hyper::header::{ContentLength, ContentType};
+use hyper::{Method, StatusCode};
+
+use futures::future::Future as _;
+use futures::stream::Stream;
+
+use url::form_urlencoded;
+
+use std::collections::HashMap;
+
+use logging::LoggingErrors;
+
+use config::ProxyConfig;
+
+use http;
+
+pub fn handle(_service: &WebService, req: Request) -> Future {
+    if req.method() == &Method::Post {
+        return set_proxy(req);
+    }
+
+    let file = serde_json::to_string(&http::get_manual_proxy())
+        .log_expect("Failed to render JSON representation of proxy");
+
+    default_future(
+        Response::new()
+            .with_header(ContentLength(file.len() as u64))
+            .with_header(ContentType::json())
+            .with_body(file),
+    )
+}
+
+fn set_proxy(req: Request) -> Future {
+    Box::new(req.body().concat2().map(move |b| {
+        let mut results = form_urlencoded::parse(b.as_ref())
+            .into_owned()
+            .filter(|x| !x.1.is_empty())
+            .collect::<HashMap<String, String>>();
+
+        let proxy = results.remove("url").map(|url| ProxyConfig {
+            url,
+            username: results.remove("username"),
+            password: results.remove("password"),
+            no_proxy: results
+                .get("no_proxy")
+                .map(|x| {
+                    x.split(',')
+                        .map(|x| x.trim().to_string())
+                        .filter(|x| !x.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        });
+
+        match http::set_user_proxy(proxy) {
+            Ok(_) => {
+                let file = "{}";
+
+                Response::new()
+                    .with_header(ContentLength(file.len() as u64))
+                    .with_header(ContentType::json())
+                    .with_body(file)
+            }
+            Err(v) => Response::new()
+                .with_status(StatusCode::BadRequest)
+                .with_header(ContentType::plaintext())
+                .with_body(v),
+        }
+    }))
+}
@@ -0,0 +1,132 @@
+//! frontend/rest/services/queue.rs
+//!
+//! The /api/queue call returns the jobs waiting to be run (GET), or queues a job (POST)
+//! from an `action` form field of `install`, `update` or `remove`, along with the
+//! packages to act on set to `true`. /api/queue/remove removes the job with the given
+//! `id`, and /api/queue/run runs every queued job, streaming progress like
+//! /api/start-install.
+
+use frontend::rest::services::default_future;
+use frontend::rest::services::stream_progress;
+use frontend::rest::services::Future;
+use frontend::rest::services::Request;
+use frontend::rest::services::Response;
+use frontend::rest::services::WebService;
+
+use hyper::header::{ContentLength, ContentType};
+use hyper::{Method, StatusCode};
+
+use futures::future::Future as _;
+use futures::stream::Stream;
+
+use url::form_urlencoded;
+
+use logging::LoggingErrors;
+
+use installer::InstallMessage;
+
+use queue::{self, JobAction};
+
+use strings;
+
+pub fn handle(service: &WebService, req: Request) -> Future {
+    let method = req.method().clone();
+    let path = req.path().to_string();
+
+    match (method, path.as_str()) {
+        (Method::Post, "/api/queue/run") => run(service),
+        (Method::Post, "/api/queue/remove") => remove(req),
+        (Method::Post, _) => push(req),
+        _ => {
+            let file = serde_json::to_string(&queue::get())
+                .log_expect("Failed to render JSON representation of job queue");
+
+            default_future(json_response(file))
+        }
+    }
+}
+
+fn json_response(file: String) -> Response {
+    Response::new()
+        .with_header(ContentLength(file.len() as u64))
+        .with_header(ContentType::json())
+        .with_body(file)
+}
+
+fn error_response(error: String) -> Response {
+    Response::new()
+        .with_status(StatusCode::BadRequest)
+        .with_header(ContentType::plaintext())
+        .with_body(error)
+}
+
+fn push(req: Request) -> Future {
+    Box::new(req.body().concat2().map(move |b| {
+        let mut action = None;
+        let mut packages = Vec::new();
+
+        for (key, value) in form_urlencoded::parse(b.as_ref()).into_owned() {
+            match key.as_str() {
+                "action" => action = Some(value),
+                _ if value == "true" => packages.push(key),
+                _ => {}
+            }
+        }
+
+        let action = match action.as_ref().map(|x| x.as_str()) {
+            Some("install") => JobAction::Install,
+            Some("update") => JobAction::Update,
+            Some("remove") => JobAction::Remove,
+            _ => {
+                return error_response(strings::format(
+                    "errors.unknown_job_action",
+                    &[("action", action.as_ref().map_or("", |x| x.as_str()))],
+                ));
+            }
+        };
+
+        match queue::push(action, packages) {
+            Ok(job) => json_response(
+                serde_json::to_string(&job)
+                    .log_expect("Failed to render JSON representation of job"),
+            ),
+            Err(v) => error_response(v),
+        }
+    }))
+}
+
+fn remove(req: Request) -> Future {
+    Box::new(req.body().concat2().map(move |b| {
+        let id = form_urlencoded::parse(b.as_ref())
+            .into_owned()
+            .find(|x| x.0 == "id")
+            .and_then(|x| x.1.parse::<u64>().ok());
+
+        let result = match id {
+            Some(id) => queue::remove(id),
+            None => Err(strings::tr("errors.no_job_given")),
+        };
+
+        match result {
+            Ok(_) => json_response("{}".to_string()),
+            Err(v) => error_response(v),
+        }
+    }))
+}
+
+fn run(service: &WebService) -> Future {
+    let framework = service.framework.clone();
+
+    default_future(stream_progress(move |sender| {
+        if let Err(v) = queue::run(&framework, &sender) {
+            error!("Queued job failed: {:?}", v);
+            if let Err(v) = sender.send(InstallMessage::Error(v)) {
+                error!("Failed to send queue error: {:?}", v);
+            }
+        }
+
+        if let Err(v) = sender.send(InstallMessage::EOF) {
+            error!("Failed to send EOF to client: {:?}", v);
+        }
+    }))
+}
@@ -0,0 +1,100 @@
+//! frontend/rest/services/space_estimate.rs
+//!
+//! The /api/space-estimate call estimates the space needed to install the packages and
+//! path given in the same form as /api/start-install, and compares it with what is free
+//! (see `preflight`). Sources are queried to find sizes, so this is answered from a
+//! separate thread. The UI asks before installing anything the data budget is warned
+//! about, so the install which follows doesn't ask again.
+
+use frontend::rest::services::Future;
+use frontend::rest::services::Request;
+use frontend::rest::services::Response;
+use frontend::rest::services::WebService;
+
+use hyper::header::ContentType;
+
+use futures::future::Future as _;
+use futures::sink::Sink;
+use futures::stream::Stream;
+
+use url::form_urlencoded;
+
+use std::path::PathBuf;
+use std::thread;
+
+use logging::LoggingErrors;
+
+use preflight;
+
+#[derive(Serialize)]
+struct EstimateError {
+    error: String,
+}
+
+pub fn handle(service: &WebService, req: Request) -> Future {
+    let framework = service.framework.clone();
+
+    Box::new(req.body().concat2().map(move |b| {
+        let mut items = Vec::new();
+        let mut path = None;
+
+        for (key, value) in form_urlencoded::parse(b.as_ref()).into_owned() {
+            match key.as_str() {
+                "path" => path = Some(PathBuf::from(value)),
+                "installDesktopShortcut" | "installForAllUsers" => {}
+                _ if value == "true" => items.push(key),
+                _ => {}
+            }
+        }
+
+        let (tx, rx) = hyper::Body::pair();
+
+        thread::spawn(move || {
+            // Sources are queried without holding the framework, so installing isn't held up
+            let plan = {
+                let framework = framework
+                    .read()
+                    .log_expect("InstallerFramework has been dirtied");
+
+                let path = match path.or_else(|| framework.install_path.clone()) {
+                    Some(v) => v,
+                    None => {
+                        error!("No install path was given to estimate space for");
+                        return;
+                    }
+                };
+
+                preflight::prepare(&framework, &items, &path)
+            };
+            let estimate = plan.and_then(|x| preflight::estimate(&x));
+
+            if let Ok(ref estimate) = estimate {
+                framework
+                    .write()
+                    .log_expect("InstallerFramework has been dirtied")
+                    .confirmed_download = if estimate.data_warning.is_some() {
+                    estimate.download_size
+                } else {
+                    0
+                };
+            }
+
+            let file = match estimate {
+                Ok(v) => serde_json::to_string(&v),
+                Err(v) => {
+                    error!("Space estimation failed: {}", v);
+                    serde_json::to_string(&EstimateError { error: v })
+                }
+            }
+            .log_expect("Failed to render JSON representation of space estimate");
+
+            if let Err(v) = tx.send(Ok(file.into_bytes().into())).wait() {
+                error!("Failed to send space estimate to client: {:?}", v);
+            }
+        });
+
+        Response::new()
+            .with_header(ContentType::json())
+            .with_body(rx)
+    }))
+}
@@ -0,0 +1,31 @@
+//! frontend/rest/services/strings.rs
+//!
+//! The /api/strings call returns an executable script containing the active locale and
+//! its string table (see `strings`).
+
+use frontend::rest::services::default_future;
+use frontend::rest::services::encapsulate_json;
+use frontend::rest::services::Future;
+use frontend::rest::services::Request;
+use frontend::rest::services::Response;
+use frontend::rest::services::WebService;
+
+use hyper::header::{ContentLength, ContentType};
+
+use logging::LoggingErrors;
+
+use strings;
+
+pub fn handle(_service: &WebService, _req: Request) -> Future {
+    let file = encapsulate_json(
+        "strings",
+        &strings::to_json_str().log_expect("Failed to render JSON representation of strings"),
+    );
+
+    default_future(
+        Response::new()
+            .with_header(ContentLength(file.len() as u64))
+            .with_header(ContentType::json())
+            .with_body(file),
+    )
+}
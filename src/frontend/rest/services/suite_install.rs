@@ -0,0 +1,77 @@
+//! frontend/rest/services/suite_install.rs
+//!
+//! The /api/start-suite-install call installs a set of a suite's products, dictated by a
+//! POST request.
+
+use frontend::rest::services::stream_progress;
+use frontend::rest::services::Future;
+use frontend::rest::services::Request;
+use frontend::rest::services::WebService;
+
+use logging::LoggingErrors;
+
+use installer::InstallMessage;
+
+use policy;
+
+use suite;
+
+use futures::future::Future as _;
+use futures::stream::Stream;
+
+use url::form_urlencoded;
+
+use std::collections::HashMap;
+
+pub fn handle(service: &WebService, req: Request) -> Future {
+    let framework = service.framework.clone();
+
+    Box::new(req.body().concat2().map(move |b| {
+        let results = form_urlencoded::parse(b.as_ref())
+            .into_owned()
+            .collect::<HashMap<String, String>>();
+
+        let mut products = Vec::new();
+        let mut path: Option<String> = None;
+        let mut install_desktop_shortcut = false;
+        let mut install_for_all_users = false;
+
+        for (key, value) in &results {
+            if key == "path" {
+                path = Some(value.to_owned());
+            } else if key == "installDesktopShortcut" {
+                install_desktop_shortcut = value == "true";
+            } else if key == "installForAllUsers" {
+                install_for_all_users = value == "true";
+            } else if value == "true" {
+                products.push(key.to_owned());
+            }
+        }
+
+        // The frontend always provides this
+        let path =
+            path.log_expect("No path specified by frontend when one should have already existed");
+
+        stream_progress(move |sender| {
+            let mut framework = framework
+                .write()
+                .log_expect("InstallerFramework has been dirtied");
+
+            framework.set_install_dir(&policy::install_dir(&path));
+            framework.database.all_users = install_for_all_users;
+
+            if let Err(v) =
+                suite::install(&mut framework, &products, &sender, install_desktop_shortcut)
+            {
+                error!("Suite install error occurred: {:?}", v);
+                if let Err(v) = sender.send(InstallMessage::Error(v)) {
+                    error!("Failed to send install error: {:?}", v);
+                }
+            }
+
+            if let Err(v) = sender.send(InstallMessage::EOF) {
+                error!("Failed to send EOF to client: {:?}", v);
+            }
+        })
+    }))
+}
@@ -0,0 +1,117 @@
+//! frontend/rest/services/system_summary.rs
+//!
+//! The /api/system-summary call describes the system being installed to, so users can
+//! see what will happen before confirming.
+//!
+//! e.g. the OS version, and free space on the target volume
+
+use frontend::rest::services::Future;
+use frontend::rest::services::Request;
+use frontend::rest::services::Response;
+use frontend::rest::services::WebService;
+
+use hyper::header::{ContentLength, ContentType};
+
+use futures::future::Future as _;
+use futures::stream::Stream;
+
+use url::form_urlencoded;
+
+use std::collections::HashMap;
+use std::env::consts::ARCH;
+use std::path::Path;
+
+use logging::LoggingErrors;
+
+use locations;
+use native;
+
+/// Struct used by serde to send a JSON payload to the client describing the system.
+#[derive(Serialize)]
+struct SystemSummary {
+    os: String,
+    arch: &'static str,
+    install_path: Option<String>,
+    /// Space available on the target volume, in bytes
+    free_space: Option<u64>,
+    /// If the target directory already contains an installation
+    existing_install: bool,
+    /// If installing needs more privileges than the installer has, so has to be handed
+    /// over to an elevated copy (see /api/elevate)
+    needs_elevation: bool,
+}
+
+pub fn handle(service: &WebService, req: Request) -> Future {
+    let framework = service.framework.clone();
+
+    Box::new(req.body().concat2().map(move |b| {
+        let results = form_urlencoded::parse(b.as_ref())
+            .into_owned()
+            .collect::<HashMap<String, String>>();
+
+        let (install_path, preexisting_install, all_users) = {
+            let framework = framework
+                .read()
+                .log_expect("InstallerFramework has been dirtied");
+
+            let install_path = results
+                .get("path")
+                .filter(|x| !x.is_empty())
+                .cloned()
+                .or_else(|| framework.get_default_path());
+
+            let all_users = if framework.preexisting_install {
+                framework.database.all_users
+            } else {
+                results.get("installForAllUsers").map(|x| x.as_str()) == Some("true")
+            };
+
+            (install_path, framework.preexisting_install, all_users)
+        };
+
+        let needs_elevation = install_path.as_ref().map_or(false, |x| {
+            locations::needs_elevation(Path::new(x), all_users)
+        });
+
+        let (free_space, existing_install) = match install_path {
+            Some(ref path) => {
+                let path = Path::new(path);
+
+                // The target directory usually doesn't exist yet, so check the nearest parent
+                let free_space = path
+                    .ancestors()
+                    .find(|x| x.exists())
+                    .and_then(|x| match native::get_free_space(x) {
+                        Ok(v) => Some(v),
+                        Err(v) => {
+                            warn!("{}", v);
+                            None
+                        }
+                    });
+
+                (
+                    free_space,
+                    preexisting_install || path.join("metadata.json").exists(),
+                )
+            }
+            None => (None, preexisting_install),
+        };
+
+        let response = SystemSummary {
+            os: native::get_os_version(),
+            arch: ARCH,
+            install_path,
+            free_space,
+            existing_install,
+            needs_elevation,
+        };
+
+        let file = serde_json::to_string(&response)
+            .log_expect("Failed to render JSON payload of system summary object");
+
+        Response::new()
+            .with_header(ContentLength(file.len() as u64))
+            .with_header(ContentType::json())
+            .with_body(file)
+    }))
+}
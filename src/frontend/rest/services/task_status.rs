@@ -0,0 +1,29 @@
+//! frontend/rest/services/task_status.rs
+//!
+//! The /api/task-status call returns the state of the running task, including if it has
+//! stalled (see `heartbeat`).
+
+use frontend::rest::services::default_future;
+use frontend::rest::services::Future;
+use frontend::rest::services::Request;
+use frontend::rest::services::Response;
+use frontend::rest::services::WebService;
+
+use hyper::header::{ContentLength, ContentType};
+
+use heartbeat;
+
+use logging::LoggingErrors;
+
+pub fn handle(_service: &WebService, _req: Request) -> Future {
+    // Operations hold the framework locked while they run, so this doesn't touch it
+    let file = serde_json::to_string(&heartbeat::status())
+        .log_expect("Failed to render JSON payload of task status");
+
+    default_future(
+        Response::new()
+            .with_header(ContentLength(file.len() as u64))
+            .with_header(ContentType::json())
+            .with_body(file),
+    )
+}
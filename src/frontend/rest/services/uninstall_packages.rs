@@ -0,0 +1,47 @@
+//! frontend/rest/services/uninstall_packages.rs
+//!
+//! The /api/uninstall-packages call removes the packages dictated by a POST request,
+//! leaving the rest of the installation in place.
+
+use frontend::rest::services::stream_progress;
+use frontend::rest::services::Future;
+use frontend::rest::services::Request;
+use frontend::rest::services::WebService;
+
+use logging::LoggingErrors;
+
+use installer::InstallMessage;
+
+use futures::future::Future as _;
+use futures::stream::Stream;
+
+use url::form_urlencoded;
+
+pub fn handle(service: &WebService, req: Request) -> Future {
+    let framework = service.framework.clone();
+
+    Box::new(req.body().concat2().map(move |b| {
+        let to_remove = form_urlencoded::parse(b.as_ref())
+            .into_owned()
+            .filter(|(_, value)| value == "true")
+            .map(|(key, _)| key)
+            .collect::<Vec<String>>();
+
+        stream_progress(move |sender| {
+            let mut framework = framework
+                .write()
+                .log_expect("InstallerFramework has been dirtied");
+
+            if let Err(v) = framework.uninstall_packages(to_remove, &sender) {
+                error!("Uninstall error occurred: {:?}", v);
+                if let Err(v) = sender.send(InstallMessage::Error(v)) {
+                    error!("Failed to send uninstall error: {:?}", v);
+                }
+            }
+
+            if let Err(v) = sender.send(InstallMessage::EOF) {
+                error!("Failed to send EOF to client: {:?}", v);
+            }
+        })
+    }))
+}
@@ -4,14 +4,20 @@
 
 use web_view::Content;
 
+use locations;
 use logging::LoggingErrors;
+use native;
 
 use log::Level;
 
+use strings::{self, NativeString};
+
 #[derive(Deserialize, Debug)]
 enum CallbackType {
     SelectInstallDir { callback_name: String },
+    ValidateInstallDir { path: String, callback_name: String },
     Log { msg: String, kind: String },
+    Notify { title: String, message: String },
     Test {},
 }
 
@@ -22,7 +28,7 @@ pub fn start_ui(app_name: &str, http_address: &str, _is_launcher: bool) {
     info!("Spawning web view instance");
 
     web_view::builder()
-        .title(&format!("{} Installer", app_name))
+        .title(&strings::get(NativeString::WindowTitle, app_name))
         .content(Content::Url(http_address))
         .size(size.0, size.1)
         .resizable(false)
@@ -37,9 +43,10 @@ pub fn start_ui(app_name: &str, http_address: &str, _is_launcher: bool) {
 
             match command {
                 CallbackType::SelectInstallDir { callback_name } => {
-                    let result = wv
-                        .dialog()
-                        .choose_directory("Select a install directory...", "");
+                    let result = wv.dialog().choose_directory(
+                        &strings::get(NativeString::SelectInstallDir, app_name),
+                        "",
+                    );
 
                     if let Ok(Some(new_path)) = result {
                         if new_path.to_string_lossy().len() > 0 {
@@ -51,6 +58,19 @@ pub fn start_ui(app_name: &str, http_address: &str, _is_launcher: bool) {
                         }
                     }
                 }
+                CallbackType::ValidateInstallDir {
+                    path,
+                    callback_name,
+                } => {
+                    let validation = locations::validate_install_dir(&path);
+                    info!("Validated dropped path {:?}: {:?}", path, validation);
+
+                    let result = serde_json::to_string(&validation)
+                        .log_expect("Unable to serialize response");
+                    let command = format!("{}({});", callback_name, result);
+                    debug!("Injecting response: {}", command);
+                    cb_result = wv.eval(&command);
+                }
                 CallbackType::Log { msg, kind } => {
                     let kind = match kind.as_ref() {
                         "info" | "log" => Level::Info,
@@ -61,6 +81,11 @@ pub fn start_ui(app_name: &str, http_address: &str, _is_launcher: bool) {
 
                     log!(target: "liftinstall::frontend::js", kind, "{}", msg);
                 }
+                CallbackType::Notify { title, message } => {
+                    if let Err(v) = native::show_notification(&title, &message) {
+                        warn!("{}", v);
+                    }
+                }
                 CallbackType::Test {} => {}
             }
 
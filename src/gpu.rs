@@ -0,0 +1,162 @@
+//! gpu.rs
+//!
+//! Checks graphics drivers before installing, so that users of applications such as games
+//! are told up front that their driver is too old, instead of finding out through crashes
+//! or rendering problems afterwards. Drivers are found through the registry on Windows,
+//! and through sysfs, lspci and OpenGL elsewhere (see `native::get_gpus`).
+//!
+//! Systems often have more than one GPU (e.g. laptops with integrated and dedicated ones),
+//! so requirements are met as long as any recognised GPU meets them.
+
+use std::cmp::Ordering;
+
+use config::GpuRequirements;
+
+use error_help;
+
+use native;
+
+use strings;
+
+/// A GPU which was found, along with how it compares to the requirements.
+#[derive(Serialize)]
+pub struct GpuReport {
+    pub name: String,
+    /// "nvidia", "amd" or "intel", if the GPU is from one of them
+    pub vendor: Option<&'static str>,
+    pub driver_version: Option<String>,
+    /// The oldest driver version supported for this GPU, if any is set
+    pub required: Option<String>,
+    pub sufficient: bool,
+}
+
+/// The outcome of checking the system's GPUs.
+#[derive(Serialize)]
+pub struct GpuCheck {
+    pub gpus: Vec<GpuReport>,
+    /// Why the requirements aren't met, if they aren't
+    pub problems: Vec<String>,
+    /// If installing should be refused because of the problems
+    pub block: bool,
+    pub message: Option<String>,
+}
+
+/// Names the vendor with a PCI vendor ID.
+fn vendor_name(id: u16) -> Option<&'static str> {
+    match id {
+        0x10de => Some("nvidia"),
+        0x1002 => Some("amd"),
+        0x8086 => Some("intel"),
+        _ => None,
+    }
+}
+
+/// Splits a version (e.g. "31.0.101.4255" or "23.1.3-1ubuntu1") into its numbers.
+fn version_numbers(version: &str) -> Vec<u64> {
+    // Anything after the numbers (e.g. a distribution's revision) is ignored
+    let version = version.trim();
+    let end = version
+        .find(|x: char| !x.is_ascii_digit() && x != '.')
+        .unwrap_or_else(|| version.len());
+
+    version[..end]
+        .split('.')
+        .filter_map(|x| x.parse().ok())
+        .collect()
+}
+
+/// Returns if a version can be compared with drivers' versions.
+pub fn is_valid_version(version: &str) -> bool {
+    !version_numbers(version).is_empty()
+}
+
+/// Compares two versions number by number, with missing numbers being zero.
+fn compare_versions(a: &str, b: &str) -> Option<Ordering> {
+    let mut a = version_numbers(a);
+    let mut b = version_numbers(b);
+    if a.is_empty() || b.is_empty() {
+        return None;
+    }
+
+    let len = a.len().max(b.len());
+    a.resize(len, 0);
+    b.resize(len, 0);
+
+    Some(a.cmp(&b))
+}
+
+/// Finds the oldest supported driver for a GPU.
+fn required_version(requirements: &GpuRequirements, vendor: &str) -> Option<String> {
+    match vendor {
+        "nvidia" => requirements.nvidia.clone(),
+        // NVIDIA ships its own drivers everywhere, but other vendors' come with Mesa
+        _ if !cfg!(windows) => requirements.mesa.clone(),
+        "amd" => requirements.amd.clone(),
+        "intel" => requirements.intel.clone(),
+        _ => None,
+    }
+}
+
+/// Checks the system's GPUs against the requirements.
+pub fn check(requirements: &GpuRequirements) -> GpuCheck {
+    let mut gpus = Vec::new();
+    let mut problems = Vec::new();
+
+    for gpu in native::get_gpus() {
+        let vendor = gpu.vendor_id.and_then(vendor_name);
+        let required = vendor.and_then(|x| required_version(requirements, x));
+
+        info!(
+            "Found GPU {:?} (vendor {:?}, driver {:?}, requires {:?})",
+            gpu.name, vendor, gpu.driver_version, required
+        );
+
+        // Drivers which can't be read aren't held against the user
+        let mut sufficient = true;
+        if let (Some(found), Some(required)) = (&gpu.driver_version, &required) {
+            if compare_versions(found, required) == Some(Ordering::Less) {
+                sufficient = false;
+                problems.push(error_help::coded(
+                    "unsupported_system",
+                    strings::format(
+                        "errors.gpu_driver_outdated",
+                        &[
+                            ("gpu", &gpu.name),
+                            ("version", found),
+                            ("required", required),
+                        ],
+                    ),
+                ));
+            }
+        }
+
+        gpus.push(GpuReport {
+            name: gpu.name,
+            vendor,
+            driver_version: gpu.driver_version,
+            required,
+            sufficient,
+        });
+    }
+
+    let recognised: Vec<&GpuReport> = gpus.iter().filter(|x| x.vendor.is_some()).collect();
+    if recognised.iter().any(|x| x.sufficient) {
+        problems.clear();
+    } else if recognised.is_empty() && requirements.require_gpu {
+        problems.push(error_help::coded(
+            "unsupported_system",
+            strings::tr("errors.gpu_not_found"),
+        ));
+    }
+
+    for problem in &problems {
+        warn!("{}", problem);
+    }
+
+    GpuCheck {
+        gpus,
+        block: requirements.block && !problems.is_empty(),
+        problems,
+        message: requirements.message.clone(),
+    }
+}
@@ -0,0 +1,181 @@
+//! hashing.rs
+//!
+//! Helpers for computing content hashes of downloaded and installed data.
+
+use ring::digest;
+
+use sha2::{Digest, Sha256};
+
+use std::fs::{read_dir, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Default name for checksum manifests published alongside release assets.
+pub const MANIFEST_NAME: &str = "SHA256SUMS";
+
+/// Renders bytes as a lowercase hex string.
+pub fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|x| format!("{:02x}", x)).collect()
+}
+
+/// Returns the hex-encoded SHA-256 hash of a block of data.
+pub fn sha256_hex(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input(data);
+    to_hex(hasher.result().as_slice())
+}
+
+/// Returns the hex-encoded HMAC-SHA256 (RFC 2104) of a message.
+pub fn hmac_sha256_hex(key: &[u8], message: &[u8]) -> String {
+    to_hex(&hmac_sha256(key, message))
+}
+
+/// Returns the HMAC-SHA256 (RFC 2104) of a message.
+pub fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> {
+    const BLOCK_SIZE: usize = 64;
+
+    // Keys longer than a block are hashed first
+    let mut block = [0 as u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        let digest = Sha256::digest(key);
+        block[..digest.len()].copy_from_slice(digest.as_slice());
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = Sha256::new();
+    inner.input(block.iter().map(|x| x ^ 0x36).collect::<Vec<u8>>());
+    inner.input(message);
+
+    let mut outer = Sha256::new();
+    outer.input(block.iter().map(|x| x ^ 0x5c).collect::<Vec<u8>>());
+    outer.input(inner.result());
+
+    outer.result().to_vec()
+}
+
+/// Returns the hex-encoded SHA-256 hash of a file on disk, streaming it in blocks.
+pub fn sha256_file(path: &Path) -> Result<String, String> {
+    let mut file =
+        File::open(path).map_err(|x| format!("Unable to open {:?} for hashing: {:?}", path, x))?;
+
+    let mut hasher = Sha256::new();
+    let mut buf = [0 as u8; 65536];
+    loop {
+        let len = file
+            .read(&mut buf)
+            .map_err(|x| format!("Unable to read {:?} for hashing: {:?}", path, x))?;
+
+        if len == 0 {
+            break;
+        }
+
+        hasher.input(&buf[0..len]);
+    }
+
+    Ok(to_hex(hasher.result().as_slice()))
+}
+
+/// Returns the hex-encoded SHA-1 hash of a file on disk, streaming it in blocks. Only
+/// for sources which publish nothing better.
+pub fn sha1_file(path: &Path) -> Result<String, String> {
+    let mut file =
+        File::open(path).map_err(|x| format!("Unable to open {:?} for hashing: {:?}", path, x))?;
+
+    let mut context = digest::Context::new(&digest::SHA1);
+    let mut buf = [0 as u8; 65536];
+    loop {
+        let len = file
+            .read(&mut buf)
+            .map_err(|x| format!("Unable to read {:?} for hashing: {:?}", path, x))?;
+
+        if len == 0 {
+            break;
+        }
+
+        context.update(&buf[0..len]);
+    }
+
+    Ok(to_hex(context.finish().as_ref()))
+}
+
+/// Wraps a writer, hashing everything written through it.
+pub struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        HashingWriter {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Returns the hex-encoded SHA-256 hash of everything written so far.
+    pub fn finish(self) -> String {
+        to_hex(self.hasher.result().as_slice())
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.hasher.input(&buf[0..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Generates a checksum manifest in the same format as `sha256sum`
+/// (`<hex hash>  <file name>` per line) for the specified release assets.
+///
+/// Directories are expanded to the files they directly contain. Any existing manifest
+/// is skipped, so that it can be regenerated in place.
+pub fn build_manifest(paths: &[PathBuf]) -> Result<String, String> {
+    let mut files = Vec::new();
+
+    for path in paths {
+        if path.is_dir() {
+            let entries =
+                read_dir(path).map_err(|x| format!("Unable to read {:?}: {:?}", path, x))?;
+
+            for entry in entries {
+                let entry = entry
+                    .map_err(|x| format!("Unable to read {:?}: {:?}", path, x))?
+                    .path();
+
+                if entry.is_file() {
+                    files.push(entry);
+                }
+            }
+        } else {
+            files.push(path.to_owned());
+        }
+    }
+
+    files.sort();
+
+    let mut manifest = String::new();
+    for file in files {
+        let name = file
+            .file_name()
+            .and_then(|x| x.to_str())
+            .ok_or_else(|| format!("Unable to get file name of {:?}", file))?
+            .to_string();
+
+        if name == MANIFEST_NAME {
+            continue;
+        }
+
+        info!("Hashing {:?}", file);
+        let hash = sha256_file(&file)?;
+        manifest.push_str(&format!("{}  {}\n", hash, name));
+    }
+
+    Ok(manifest)
+}
@@ -0,0 +1,710 @@
+//! headless.rs
+//!
+//! Drives the installer framework directly from the command line, without a frontend.
+//!
+//! This also understands the switches which package managers such as winget and Chocolatey
+//! pass to NSIS and Inno Setup installers, so that LiftInstall-based installers can be
+//! repackaged without wrapper scripts.
+//!
+//! # Calling from scripts
+//!
+//! Installers are designed to be run from deployment scripts, such as a macOS .pkg's
+//! `postinstall` script, as:
+//!
+//! ```text
+//! installer install [--all-users | --console-user] [--install-dir DIR]
+//!     [--packages A,B] [--log-file FILE]
+//! ```
+//!
+//! - `install` (or `--silent`), `update` and `uninstall` never ask the user anything. Any
+//!   question is answered with its default, and authorisation which would need the user
+//!   fails instead.
+//! - `--all-users` installs shortcuts and registration for everyone, which needs root.
+//! - `--console-user` suits per-user installs from scripts which are run as root (as
+//!   `postinstall` scripts are). The installer runs itself again as the user logged in at
+//!   the console, so that the installation belongs to them and lands in their home
+//!   directory. If nobody is logged in, this exits with `EXIT_NO_CONSOLE_USER` without
+//!   doing anything, so that the install can be retried when someone logs in. Ignored
+//!   with `--all-users`, or when not running as root.
+//! - `--log-file` sets where the log is written. This defaults to `<name>_installer.log`
+//!   in the working directory, which isn't defined for `postinstall` scripts, so should
+//!   be given. With `--console-user`, the console user has to be able to write to it
+//!   (e.g. within `/tmp` or `/Users/Shared`).
+//!
+//! Progress is printed to stdout and errors to stderr, which `installer` (the macOS
+//! command) adds to `/var/log/install.log`.
+//!
+//! Deployment tools which can't capture stdout can give `--status-file FILE` instead.
+//! The file is kept up to date (at most once a second) with a JSON object of:
+//!
+//! - `operation`: `install`, `update`, `uninstall` or `extract`
+//! - `state`: `running`, `succeeded` or `failed`
+//! - `phase`: what is being done, as printed to stdout
+//! - `percent`, and `remaining_secs` where it is known
+//! - `last_error`: the last error reported, if any
+//! - `updated`: when the file was last written, as RFC 3339. This isn't updated unless
+//!   progress is made, so a stale time while `running` means the operation has hung.
+//! - `pid`: the process writing the file
+//!
+//! As with `--log-file`, the user the operation runs as has to be able to write to it.
+//!
+//! The environment can set `LIFTINSTALL_LOG` (see `logging::LogFilters`), and the
+//! `HTTPS_PROXY`, `HTTP_PROXY`, `ALL_PROXY` and `NO_PROXY` proxy settings. These are
+//! passed on to the console user.
+//!
+//! `--timeout SECS` (or `limits.install_timeout`) aborts an operation which runs for
+//! longer than that, from fetching the configuration through to shutting down, but not
+//! counting time spent waiting for the download window to open. It is cancelled and
+//! rolled back, and if it is stuck past cancelling (e.g. on a wedged network filesystem),
+//! the process exits anyway after `TIMEOUT_GRACE`, leaving the transaction behind.
+//! Uninstalls aren't made within a transaction, so a timed out uninstall only stops,
+//! leaving whatever hasn't been removed yet.
+//!
+//! Suites (see `suite`) install their default products, or those given with `--packages`,
+//! into their directories within `--install-dir`.
+//!
+//! Exit codes are 0 on success, `EXIT_FAILED` (including for invalid arguments),
+//! `EXIT_NOT_INSTALLED`, `EXIT_NO_CONSOLE_USER` and `EXIT_TIMED_OUT`.
+
+use control;
+
+use installer::{InstallMessage, InstallerFramework};
+
+use native;
+
+use policy;
+
+use prompts;
+
+use suite;
+
+use atomic;
+
+use bandwidth;
+
+use logging::LoggingErrors;
+
+use chrono::Local;
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::{self, exit};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Exit code for when the operation itself failed.
+pub const EXIT_FAILED: i32 = 1;
+/// Exit code for when an update or uninstall was requested, but nothing is installed.
+pub const EXIT_NOT_INSTALLED: i32 = 2;
+/// Exit code for when `--console-user` was given, but nobody is logged in.
+pub const EXIT_NO_CONSOLE_USER: i32 = 3;
+/// Exit code for when the operation took longer than its timeout, and was aborted.
+pub const EXIT_TIMED_OUT: i32 = 4;
+
+/// How long an operation which has timed out is given to stop and roll back, before the
+/// process exits regardless.
+const TIMEOUT_GRACE: Duration = Duration::from_secs(120);
+
+static TIMED_OUT: AtomicBool = AtomicBool::new(false);
+
+/// How long the operation has run for, which its timeout is measured against.
+#[derive(Default)]
+struct Clock {
+    /// Time run for before `since`
+    used: Duration,
+    /// When the clock was last started, if it is running
+    since: Option<Instant>,
+    /// If the operation is rolled back when it times out
+    undoable: bool,
+    /// The timeout being watched for
+    timeout: Option<Duration>,
+    /// Tells the watchdog that the clock has stopped, by being dropped
+    watchdog: Option<(Sender<()>, thread::JoinHandle<()>)>,
+}
+
+/// How often the status file is rewritten while progress is being made.
+const STATUS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The progress of an operation, as written to the status file.
+#[derive(Serialize)]
+struct Status {
+    operation: String,
+    state: &'static str,
+    phase: String,
+    percent: f64,
+    remaining_secs: Option<u64>,
+    last_error: Option<String>,
+    updated: String,
+    pid: u32,
+}
+
+/// Where progress is written to, along with what was last written.
+struct StatusFile {
+    path: PathBuf,
+    status: Status,
+    written: Option<Instant>,
+}
+
+lazy_static! {
+    static ref CLOCK: Mutex<Clock> = Mutex::new(Clock::default());
+    static ref STATUS_FILE: Mutex<Option<StatusFile>> = Mutex::new(None);
+}
+
+/// Options for a non-interactive install.
+pub struct HeadlessOptions {
+    /// Where to install to. Ignored when modifying an existing installation.
+    pub install_dir: Option<String>,
+    /// Packages to install. Defaults to those already installed, or the configured defaults.
+    pub packages: Option<Vec<String>>,
+    /// If this is a machine-wide install, instead of the configured scope. Ignored when
+    /// modifying.
+    pub all_users: bool,
+    /// The MSI which is running this install, if any (see `msi`)
+    pub msi_product_code: Option<String>,
+}
+
+/// Rewrites Windows-style installer switches into their LiftInstall equivalents:
+///
+/// - `/S`, `/SILENT`, `/VERYSILENT`, `/Q`, `/QUIET`, `/QN` -> `--silent`
+/// - `/D=<path>` (NSIS) and `/DIR=<path>` (Inno Setup) -> `--install-dir <path>`
+/// - `/SUPPRESSMSGBOXES`, `/NORESTART`, `/SP-` are accepted and ignored
+pub fn normalize_args<I: IntoIterator<Item = String>>(args: I) -> Vec<String> {
+    let mut args = args.into_iter();
+    let mut result = Vec::new();
+
+    // The executable name is passed through untouched
+    if let Some(exe) = args.next() {
+        result.push(exe);
+    }
+
+    while let Some(arg) = args.next() {
+        let upper = arg.to_ascii_uppercase();
+
+        match upper.as_str() {
+            "/S" | "/SILENT" | "/VERYSILENT" | "/Q" | "/QUIET" | "/QN" => {
+                result.push("--silent".to_string());
+            }
+            // Silent mode never shows message boxes or restarts the machine anyway
+            "/SUPPRESSMSGBOXES" | "/NORESTART" | "/SP-" => {}
+            _ if upper.starts_with("/D=") => {
+                // NSIS requires /D= to come last, and doesn't quote paths containing spaces
+                let mut path = arg[3..].to_string();
+                for rest in args.by_ref() {
+                    path.push(' ');
+                    path.push_str(&rest);
+                }
+
+                result.push("--install-dir".to_string());
+                result.push(path);
+            }
+            _ if upper.starts_with("/DIR=") => {
+                result.push("--install-dir".to_string());
+                result.push(arg[5..].trim_matches('"').to_string());
+            }
+            _ => result.push(arg),
+        }
+    }
+
+    result
+}
+
+/// Hands over to the user logged in at the console (see `--console-user`), exiting with
+/// the code of the installer run as them.
+pub fn run_as_console_user() -> ! {
+    let args: Vec<String> = env::args()
+        .skip(1)
+        .filter(|x| x != "--console-user")
+        .collect();
+
+    match native::run_as_console_user(&args) {
+        Ok(Some(code)) => exit(code),
+        Ok(None) => {
+            eprintln!("Nobody is logged in at the console to install for");
+            exit(EXIT_NO_CONSOLE_USER);
+        }
+        Err(v) => {
+            eprintln!("Unable to run as the console user: {}", v);
+            exit(EXIT_FAILED);
+        }
+    }
+}
+
+/// Hands over to an elevated copy of the installer, for operations which need more
+/// privileges than this process has, exiting with its code. On Windows, the copy's
+/// output goes to a console window of its own.
+pub fn run_elevated() -> ! {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    args.extend(bandwidth::elevation_args());
+
+    println!("Requesting administrator permission...");
+    match native::run_elevated(&args, true) {
+        Ok(Some(code)) => exit(code),
+        Ok(None) => exit(EXIT_FAILED),
+        Err(v) => {
+            eprintln!("Unable to run elevated: {}", v);
+            exit(EXIT_FAILED);
+        }
+    }
+}
+
+/// Works out which packages to operate on. Defaults to those that are already installed,
+/// or the configured defaults for fresh installs.
+fn select_packages(
+    framework: &InstallerFramework,
+    requested: Option<Vec<String>>,
+) -> Result<Vec<String>, String> {
+    let config = framework
+        .config
+        .as_ref()
+        .ok_or("Configuration was not loaded")?;
+
+    let packages = match requested {
+        Some(packages) => {
+            for name in &packages {
+                if !config.packages.iter().any(|x| &x.name == name) {
+                    return Err(format!("Unknown package: {:?}", name));
+                }
+            }
+
+            packages
+        }
+        None if framework.preexisting_install => framework
+            .database
+            .packages
+            .iter()
+            .map(|x| x.name.clone())
+            .collect(),
+        None => config
+            .packages
+            .iter()
+            .filter(|x| x.default.unwrap_or(false))
+            .map(|x| x.name.clone())
+            .collect(),
+    };
+
+    if packages.is_empty() {
+        return Err("No packages were selected".to_string());
+    }
+
+    Ok(packages)
+}
+
+/// Writes the status file, if one was given. Progress is only written once per
+/// `STATUS_INTERVAL` (as messages include byte and file counts), unless `force` is set.
+fn update_status<F: FnOnce(&mut Status)>(force: bool, update: F) {
+    let mut file = STATUS_FILE
+        .lock()
+        .log_expect("Status file has been dirtied");
+    let file = match *file {
+        Some(ref mut v) => v,
+        None => return,
+    };
+
+    update(&mut file.status);
+
+    let is_due = file
+        .written
+        .map_or(true, |x| x.elapsed() >= STATUS_INTERVAL);
+    if !force && !is_due {
+        return;
+    }
+
+    file.status.updated = Local::now().to_rfc3339();
+    file.written = Some(Instant::now());
+
+    let result = serde_json::to_vec_pretty(&file.status)
+        .map_err(|x| format!("{:?}", x))
+        .and_then(|x| atomic::write(&file.path, &x).map_err(|x| format!("{:?}", x)));
+
+    if let Err(v) = result {
+        warn!("Unable to write status file {:?}: {}", file.path, v);
+    }
+}
+
+/// Starts writing the progress of an operation to a file (see `--status-file`).
+pub fn set_status_file(path: &str, operation: &str) {
+    info!("Writing status to {:?}", path);
+
+    *STATUS_FILE
+        .lock()
+        .log_expect("Status file has been dirtied") = Some(StatusFile {
+        path: PathBuf::from(path),
+        status: Status {
+            operation: operation.to_string(),
+            state: "running",
+            phase: "Starting".to_string(),
+            percent: 0.0,
+            remaining_secs: None,
+            last_error: None,
+            updated: String::new(),
+            pid: process::id(),
+        },
+        written: None,
+    });
+
+    update_status(true, |_| {});
+}
+
+/// Records in the status file (if any) how an operation finished.
+pub fn finish_status(result: &Result<(), String>) {
+    update_status(true, |status| match *result {
+        Ok(_) => {
+            status.state = "succeeded";
+            status.phase = "Finished".to_string();
+            status.percent = 100.0;
+            status.remaining_secs = None;
+        }
+        Err(ref v) => {
+            status.state = "failed";
+            status.last_error = Some(v.clone());
+        }
+    });
+}
+
+/// Returns if the operation was aborted for running past its timeout.
+pub fn timed_out() -> bool {
+    TIMED_OUT.load(Ordering::SeqCst)
+}
+
+/// Returns how long operations may run for, from the command line or the configuration.
+fn get_timeout(framework: &InstallerFramework) -> Option<Duration> {
+    framework
+        .install_timeout
+        .or_else(|| {
+            framework
+                .config
+                .as_ref()
+                .and_then(|x| x.limits.install_timeout)
+        })
+        .filter(|x| *x > 0)
+        .map(Duration::from_secs)
+}
+
+/// Cancels the operation if the clock isn't stopped (as signalled by `done` disconnecting)
+/// within `remaining` of its `timeout`, and exits the process if it doesn't stop within
+/// `TIMEOUT_GRACE` of that.
+fn watch(timeout: Duration, remaining: Duration, undoable: bool, done: &Receiver<()>) {
+    if done.recv_timeout(remaining) != Err(RecvTimeoutError::Timeout) {
+        return;
+    }
+
+    error!(
+        "The operation is still running after {} seconds, aborting it",
+        timeout.as_secs()
+    );
+    if undoable {
+        eprintln!("Timed out, undoing changes...");
+    } else {
+        eprintln!("Timed out, stopping...");
+    }
+    TIMED_OUT.store(true, Ordering::SeqCst);
+
+    let deadline = Instant::now() + TIMEOUT_GRACE;
+    while Instant::now() < deadline {
+        // Cancelling is cleared as each pipeline starts, so has to be repeated
+        if !control::is_cancelled() {
+            control::cancel();
+        }
+
+        if done.recv_timeout(Duration::from_secs(1)) != Err(RecvTimeoutError::Timeout) {
+            return;
+        }
+    }
+
+    let error = format!(
+        "The operation didn't stop within {} seconds of timing out, exiting anyway",
+        TIMEOUT_GRACE.as_secs()
+    );
+    error!("{}", error);
+    eprintln!("Error: {}", error);
+    finish_status(&Err(error));
+    exit(EXIT_TIMED_OUT);
+}
+
+/// Starts timing an operation, which is rolled back if it times out when `undoable` is
+/// set (see `start_clock`).
+fn begin_timing(framework: &InstallerFramework, undoable: bool) {
+    CLOCK
+        .lock()
+        .log_expect("Timeout clock has been dirtied")
+        .undoable = undoable;
+
+    start_clock(framework);
+}
+
+/// Starts (or resumes) the clock, watching for the operation running past its timeout if
+/// one is known yet. This is called again once the configuration has been loaded, as it
+/// may give one.
+fn start_clock(framework: &InstallerFramework) {
+    let timeout = get_timeout(framework);
+
+    let mut clock = CLOCK.lock().log_expect("Timeout clock has been dirtied");
+    let now = Instant::now();
+    let since = *clock.since.get_or_insert(now);
+
+    let timeout = match timeout {
+        Some(v) if clock.watchdog.is_none() => v,
+        _ => return,
+    };
+
+    let used = clock.used + (now - since);
+    let remaining = if timeout > used {
+        timeout - used
+    } else {
+        Duration::from_secs(0)
+    };
+
+    let undoable = clock.undoable;
+    let (done_sender, done) = channel::<()>();
+    let watchdog = thread::spawn(move || watch(timeout, remaining, undoable, &done));
+
+    clock.timeout = Some(timeout);
+    clock.watchdog = Some((done_sender, watchdog));
+}
+
+/// Stops the clock, until `start_clock` is called again.
+fn stop_clock() {
+    let watchdog = {
+        let mut clock = CLOCK.lock().log_expect("Timeout clock has been dirtied");
+        if let Some(since) = clock.since.take() {
+            clock.used += since.elapsed();
+        }
+
+        clock.watchdog.take()
+    };
+
+    // Not joined while holding the lock, as the watchdog may be exiting the process
+    if let Some((done_sender, watchdog)) = watchdog {
+        drop(done_sender);
+        if watchdog.join().is_err() {
+            warn!("Watchdog thread panicked");
+        }
+    }
+}
+
+/// Stops timing the operation once it has been shut down, returning its result (or that
+/// it timed out, if it failed because of that).
+pub fn finish_timing(result: Result<(), String>) -> Result<(), String> {
+    stop_clock();
+
+    if timed_out() && result.is_err() {
+        let timeout = CLOCK
+            .lock()
+            .log_expect("Timeout clock has been dirtied")
+            .timeout;
+
+        return Err(format!(
+            "Timed out after {} seconds",
+            timeout.map_or(0, |x| x.as_secs())
+        ));
+    }
+
+    result
+}
+
+/// Runs an operation on the framework, printing its progress messages to stdout.
+fn run_with_progress<F>(callback: F) -> Result<(), String>
+where
+    F: FnOnce(&Sender<InstallMessage>) -> Result<(), String>,
+{
+    // There is no-one around to answer questions
+    prompts::set_interactive(false);
+
+    let (sender, receiver) = channel();
+
+    let printer = thread::spawn(move || {
+        for message in receiver {
+            match message {
+                InstallMessage::Status(status, progress, remaining, _) => {
+                    match remaining {
+                        Some(secs) => println!(
+                            "[{:>3.0}%] {} ({}:{:02} remaining)",
+                            progress * 100.0,
+                            status,
+                            secs / 60,
+                            secs % 60
+                        ),
+                        None => println!("[{:>3.0}%] {}", progress * 100.0, status),
+                    }
+
+                    update_status(false, |x| {
+                        x.percent = (progress * 100.0).round();
+                        x.remaining_secs = remaining;
+                        x.phase = status;
+                    });
+                }
+                InstallMessage::Error(v) => {
+                    eprintln!("Error: {}", v);
+                    update_status(true, |x| x.last_error = Some(v));
+                }
+                InstallMessage::AuthorizationRequired(v) => {
+                    eprintln!(
+                        "Authorization is required ({}), which isn't possible silently",
+                        v
+                    )
+                }
+                InstallMessage::Downloads(_)
+                | InstallMessage::PackageInstalled
+                | InstallMessage::Prompt(_, _)
+                | InstallMessage::DirectoryPrompt(_, _)
+                | InstallMessage::TextPrompt(_, _)
+                | InstallMessage::EOF => {}
+            }
+        }
+    });
+
+    let result = callback(&sender);
+
+    drop(sender);
+    if printer.join().is_err() {
+        warn!("Progress printer thread panicked");
+    }
+
+    result
+}
+
+/// Waits for the configured download window to open, so that fleets of machines can keep
+/// downloads out of working hours.
+fn wait_for_download_window(framework: &InstallerFramework) -> Result<(), String> {
+    let window = match framework
+        .config
+        .as_ref()
+        .and_then(|x| x.limits.download_window.clone())
+    {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+
+    let wait = window.time_until_open(Local::now().time())?;
+    if wait.as_secs() > 0 {
+        info!(
+            "Outside of the {}-{} download window, waiting {} minutes",
+            window.start,
+            window.end,
+            wait.as_secs() / 60
+        );
+        println!("Waiting until {} to start downloading...", window.start);
+
+        stop_clock();
+        thread::sleep(wait);
+        start_clock(framework);
+    }
+
+    Ok(())
+}
+
+/// Installs (or updates) packages without any user interaction, printing progress to stdout.
+pub fn install(framework: &mut InstallerFramework, options: HeadlessOptions) -> Result<(), String> {
+    if !framework.preexisting_install {
+        if options.all_users {
+            framework.database.all_users = true;
+        }
+
+        let path = match options.install_dir {
+            Some(v) => v,
+            None => framework
+                .get_default_path()
+                .ok_or("Unable to determine a default install directory")?,
+        };
+        let path = policy::install_dir(&path);
+
+        if Path::new(&path).join("metadata.json").exists() {
+            info!(
+                "Existing install detected at {:?}, modifying it instead",
+                path
+            );
+            let mut existing = InstallerFramework::new_with_db(
+                framework.base_attributes.clone(),
+                Path::new(&path),
+            )?;
+            existing.carry_over(framework);
+            *framework = existing;
+        } else {
+            framework.set_install_dir(&path);
+        }
+    } else if options.install_dir.is_some() {
+        warn!("Ignoring install directory, as an existing installation is being modified");
+    }
+
+    if options.msi_product_code.is_some() {
+        framework.database.msi_product_code = options.msi_product_code;
+    }
+
+    begin_timing(framework, true);
+    framework.fetch_config()?;
+    start_clock(framework);
+
+    // Suites install products, rather than packages of their own
+    let products = match framework.config {
+        Some(ref config) if !config.suite.is_empty() => Some(
+            options
+                .packages
+                .clone()
+                .unwrap_or_else(|| suite::default_products(config)),
+        ),
+        _ => None,
+    };
+    if let Some(products) = products {
+        wait_for_download_window(framework)?;
+
+        return run_with_progress(|sender| suite::install(framework, &products, sender, false));
+    }
+
+    let packages = select_packages(framework, options.packages)?;
+
+    wait_for_download_window(framework)?;
+
+    let fresh_install = !framework.preexisting_install;
+    run_with_progress(|sender| framework.install(packages, sender, fresh_install, false))
+}
+
+/// Updates an existing installation's packages without any user interaction, printing progress to stdout.
+pub fn update(framework: &mut InstallerFramework) -> Result<(), String> {
+    begin_timing(framework, true);
+    framework.fetch_config()?;
+    start_clock(framework);
+
+    let packages = select_packages(framework, None)?;
+
+    wait_for_download_window(framework)?;
+
+    run_with_progress(|sender| framework.install(packages, sender, false, false))
+}
+
+/// Uninstalls an existing installation without any user interaction, printing progress to stdout.
+/// This isn't rolled back if it times out.
+pub fn uninstall(framework: &mut InstallerFramework) -> Result<(), String> {
+    begin_timing(framework, false);
+
+    // The configuration says who may uninstall, and how packages are cleaned up
+    if let Err(v) = framework.fetch_config() {
+        warn!("Uninstalling without the configuration: {}", v);
+    }
+    start_clock(framework);
+
+    run_with_progress(|sender| framework.uninstall(sender))
+}
+
+/// Downloads and extracts packages into an (empty) directory, without installing them.
+pub fn extract(
+    framework: &mut InstallerFramework,
+    target: &str,
+    packages: Option<Vec<String>>,
+) -> Result<(), String> {
+    // Extraction is never treated as modifying an existing installation
+    let mut extraction = InstallerFramework::new(framework.base_attributes.clone());
+    extraction.carry_over(framework);
+    *framework = extraction;
+    framework.set_install_dir(target);
+
+    begin_timing(framework, true);
+    framework.fetch_config()?;
+    start_clock(framework);
+
+    let packages = select_packages(framework, packages)?;
+
+    run_with_progress(|sender| framework.extract(packages, sender))
+}
@@ -0,0 +1,198 @@
+//! heartbeat.rs
+//!
+//! Tells operations which are slow apart from those which have hung. Tasks show that they
+//! are still getting somewhere with heartbeats: every progress message they send is one,
+//! and long-running work with no progress to show (such as writing out a large file, or
+//! a command printing output) calls `beat` as it goes.
+//!
+//! While an operation runs, a supervisor flags the running task as stalled once no
+//! heartbeat has arrived for `limits.stall_timeout` seconds, publishing
+//! `Event::TaskStalled`, and `Event::TaskResumed` if one arrives after all. Time spent
+//! paused, or waiting on the user to answer a question, doesn't count. The state is
+//! also available from /api/task-status.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use control;
+
+use events::{self, Event};
+
+use logging::LoggingErrors;
+
+/// How often the supervisor checks for heartbeats.
+const SUPERVISOR_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The operation being supervised.
+struct Supervised {
+    task: Option<String>,
+    message: Option<String>,
+    last_beat: Instant,
+    /// How many waits on the user are in progress
+    waiting: usize,
+    stalled: bool,
+}
+
+lazy_static! {
+    static ref STATE: Mutex<Option<Supervised>> = Mutex::new(None);
+}
+
+/// Identifies the running operation, so that supervisors of earlier ones stop.
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+/// The state of the running task, as shown to the frontend.
+#[derive(Serialize)]
+pub struct TaskStatus {
+    /// If an operation is running at all
+    pub running: bool,
+    pub task: Option<String>,
+    /// The last progress message sent
+    pub message: Option<String>,
+    pub secs_since_heartbeat: u64,
+    /// If it is paused, or waiting on the user, rather than working
+    pub waiting: bool,
+    /// If no heartbeat has arrived for longer than the stall timeout
+    pub stalled: bool,
+}
+
+/// Runs `update` on the operation's state, if one is being supervised.
+fn with_state<F: FnOnce(&mut Supervised)>(update: F) {
+    if let Some(ref mut state) = *STATE.lock().log_expect("Heartbeat state has been dirtied") {
+        update(state);
+    }
+}
+
+/// Records that work is still being done, clearing a stall if one was flagged.
+fn beat_state(state: &mut Supervised) {
+    state.last_beat = Instant::now();
+
+    if state.stalled {
+        state.stalled = false;
+
+        let task = state.task.clone().unwrap_or_default();
+        info!("Task {:?} has resumed", task);
+        events::publish(Event::TaskResumed { task });
+    }
+}
+
+/// Checks for a stall, once every `SUPERVISOR_INTERVAL`, until the operation finishes.
+fn supervise(generation: usize, timeout: Duration) {
+    while GENERATION.load(Ordering::SeqCst) == generation {
+        thread::sleep(SUPERVISOR_INTERVAL);
+
+        if GENERATION.load(Ordering::SeqCst) != generation {
+            break;
+        }
+
+        with_state(|state| {
+            // Waits are counted from when they end, not when they started
+            if state.waiting > 0 || control::is_paused() {
+                state.last_beat = Instant::now();
+                return;
+            }
+
+            let elapsed = state.last_beat.elapsed();
+            if state.stalled || elapsed < timeout {
+                return;
+            }
+
+            state.stalled = true;
+
+            let task = state.task.clone().unwrap_or_default();
+            warn!(
+                "Task {:?} has stalled, with no progress for {} seconds",
+                task,
+                elapsed.as_secs()
+            );
+            events::publish(Event::TaskStalled {
+                task,
+                secs: elapsed.as_secs(),
+            });
+        });
+    }
+}
+
+/// Starts supervising an operation. Stalls are flagged after `timeout` seconds without
+/// a heartbeat, or never if it is 0.
+pub fn start(timeout: u64) {
+    let generation = GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+
+    *STATE.lock().log_expect("Heartbeat state has been dirtied") = Some(Supervised {
+        task: None,
+        message: None,
+        last_beat: Instant::now(),
+        waiting: 0,
+        stalled: false,
+    });
+
+    if timeout > 0 {
+        let timeout = Duration::from_secs(timeout);
+        thread::spawn(move || supervise(generation, timeout));
+    }
+}
+
+/// Stops supervising the operation, as it has finished.
+pub fn stop() {
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+    *STATE.lock().log_expect("Heartbeat state has been dirtied") = None;
+}
+
+/// Records that a task has started, which counts as a heartbeat.
+pub fn task_started(task: &str) {
+    with_state(|state| {
+        beat_state(state);
+        state.task = Some(task.to_string());
+    });
+}
+
+/// Records a progress message, which counts as a heartbeat.
+pub fn progress(message: &str) {
+    with_state(|state| {
+        beat_state(state);
+        state.message = Some(message.to_string());
+    });
+}
+
+/// Records that work is still being done, for work which has no progress to show.
+pub fn beat() {
+    with_state(beat_state);
+}
+
+/// Runs `wait` (e.g. waiting on the user to answer a question) without it counting
+/// towards a stall.
+pub fn waiting<T, F: FnOnce() -> T>(wait: F) -> T {
+    with_state(|state| state.waiting += 1);
+    let result = wait();
+    with_state(|state| {
+        state.waiting = state.waiting.saturating_sub(1);
+        beat_state(state);
+    });
+
+    result
+}
+
+/// Returns the state of the running task.
+pub fn status() -> TaskStatus {
+    let state = STATE.lock().log_expect("Heartbeat state has been dirtied");
+
+    match *state {
+        Some(ref state) => TaskStatus {
+            running: true,
+            task: state.task.clone(),
+            message: state.message.clone(),
+            secs_since_heartbeat: state.last_beat.elapsed().as_secs(),
+            waiting: state.waiting > 0 || control::is_paused(),
+            stalled: state.stalled,
+        },
+        None => TaskStatus {
+            running: false,
+            task: None,
+            message: None,
+            secs_since_heartbeat: 0,
+            waiting: false,
+            stalled: false,
+        },
+    }
+}
@@ -2,15 +2,34 @@
 //!
 //! A simple wrapper around Hyper's HTTP client.
 
-use reqwest::header::CONTENT_LENGTH;
+use reqwest::header::{ACCEPT, AUTHORIZATION, CONTENT_LENGTH, CONTENT_RANGE, RANGE};
 
-use std::io::Read;
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::{Mutex, RwLock};
 use std::time::Duration;
 
 use reqwest::async::Client as AsyncClient;
 use reqwest::Client;
+use reqwest::Proxy;
+use reqwest::RequestBuilder;
 use reqwest::StatusCode;
 
+use url::Url;
+
+use logging::LoggingErrors;
+
+use config::ProxyConfig;
+
+use bandwidth;
+use control;
+use dns;
+use error_help;
+use native;
+use strings;
+
 /// Asserts that a URL is valid HTTPS, else returns an error.
 pub fn assert_ssl(url: &str) -> Result<(), String> {
     if url.starts_with("https://") {
@@ -20,45 +39,659 @@ pub fn assert_ssl(url: &str) -> Result<(), String> {
     }
 }
 
-/// Builds a customised HTTP client.
-pub fn build_client() -> Result<Client, String> {
-    Client::builder()
+/// Rewrites a URL to be fetched through a caching server, which serves upstream
+/// resources at `<cache>/<host>/<path>`.
+pub fn cache_url(cache: &str, url: &str) -> Option<String> {
+    let parsed = Url::parse(url).ok()?;
+    let host = parsed.host_str()?;
+
+    let mut result = format!("{}/{}{}", cache.trim_end_matches('/'), host, parsed.path());
+    if let Some(query) = parsed.query() {
+        result.push('?');
+        result.push_str(query);
+    }
+
+    Some(result)
+}
+
+/// Rewrites a URL to be fetched from a mirror, which serves the same paths as the
+/// upstream host at `<mirror>/<path>`.
+pub fn mirror_url(mirror: &str, url: &str) -> Option<String> {
+    let parsed = Url::parse(url).ok()?;
+
+    let mut result = format!("{}{}", mirror.trim_end_matches('/'), parsed.path());
+    if let Some(query) = parsed.query() {
+        result.push('?');
+        result.push_str(query);
+    }
+
+    Some(result)
+}
+
+/// How many idle connections to keep open to a single host.
+const MAX_IDLE_PER_HOST: usize = 8;
+
+lazy_static! {
+    /// Shared between all requests so that connections (and TLS sessions) are pooled,
+    /// rather than being renegotiated for every source query and asset.
+    static ref CLIENT: Result<Client, String> = Client::builder()
         .timeout(Duration::from_secs(8))
+        .max_idle_per_host(MAX_IDLE_PER_HOST)
+        .proxy(Proxy::custom(find_proxy))
         .build()
-        .map_err(|x| format!("Unable to build client: {:?}", x))
-}
+        .map_err(|x| format!("Unable to build client: {:?}", x));
 
-/// Builds a customised async HTTP client.
-pub fn build_async_client() -> Result<AsyncClient, String> {
-    AsyncClient::builder()
+    /// Shared between the frontend's requests for the same reason. Idle connections are
+    /// kept on the frontend's event loop, which runs for as long as the installer does.
+    static ref ASYNC_CLIENT: Result<AsyncClient, String> = AsyncClient::builder()
         .timeout(Duration::from_secs(8))
+        .max_idle_per_host(MAX_IDLE_PER_HOST)
+        .proxy(Proxy::custom(find_proxy))
         .build()
-        .map_err(|x| format!("Unable to build client: {:?}", x))
+        .map_err(|x| format!("Unable to build client: {:?}", x));
+
+    /// Proxy auto-config script specified by the configuration, if any.
+    static ref PAC_URL: RwLock<Option<String>> = RwLock::new(None);
+
+    /// Proxy specified by the configuration, if any.
+    static ref CONFIGURED_PROXY: RwLock<Option<ProxyConfig>> = RwLock::new(None);
+
+    /// Proxy chosen by the user, which takes priority over the configuration's.
+    static ref USER_PROXY: RwLock<Option<ProxyConfig>> = RwLock::new(None);
+
+    /// Proxy enforced by the machine's policy, which takes priority over everything else.
+    static ref MACHINE_PROXY: RwLock<Option<ProxyConfig>> = RwLock::new(None);
+
+    /// Proxies already selected for each scheme + host, as evaluating PAC scripts is slow.
+    static ref PROXY_CACHE: Mutex<HashMap<String, Option<Url>>> = Mutex::new(HashMap::new());
+
+    /// Credentials for sources which need them, by the URL prefix they are sent to.
+    static ref CREDENTIALS: RwLock<HashMap<String, Credentials>> = RwLock::new(HashMap::new());
+}
+
+/// Headers which authorise requests to a private source.
+#[derive(Clone, Debug)]
+pub struct Credentials {
+    /// The complete value of the Authorization header, e.g. `Bearer <token>`
+    pub authorization: String,
+    /// What to send as the Accept header, for APIs which only serve files when asked to
+    pub accept: Option<String>,
+}
+
+/// Sends the specified credentials to every URL starting with a prefix (e.g.
+/// `https://api.github.com/`), unless a request is authorised some other way.
+pub fn set_credentials(prefix: &str, credentials: Credentials) {
+    info!("Using credentials for {}", prefix);
+    CREDENTIALS
+        .write()
+        .log_expect("Credential state has been dirtied")
+        .insert(prefix.to_string(), credentials);
+}
+
+/// Finds the credentials for a URL. The most specific prefix wins.
+pub fn find_credentials(url: &str) -> Option<Credentials> {
+    CREDENTIALS
+        .read()
+        .log_expect("Credential state has been dirtied")
+        .iter()
+        .filter(|x| url.starts_with(x.0.as_str()))
+        .max_by_key(|x| x.0.len())
+        .map(|x| x.1.clone())
+}
+
+/// Starts a GET request, authorised with the specified bearer token or otherwise any
+/// credentials registered for the URL.
+pub fn get(url: &str, authorization: Option<&str>) -> Result<RequestBuilder, String> {
+    Ok(authorize(build_client()?.get(url), url, authorization))
+}
+
+/// Adds the specified bearer token, or otherwise any credentials registered for the URL,
+/// to a request.
+fn authorize(
+    mut request: RequestBuilder,
+    url: &str,
+    authorization: Option<&str>,
+) -> RequestBuilder {
+    match authorization {
+        Some(auth) => {
+            request = request.header(AUTHORIZATION, format!("Bearer {}", auth));
+        }
+        None => {
+            if let Some(credentials) = find_credentials(url) {
+                request = request.header(AUTHORIZATION, credentials.authorization);
+                if let Some(accept) = credentials.accept {
+                    request = request.header(ACCEPT, accept);
+                }
+            }
+        }
+    }
+
+    request
+}
+
+/// Finds the size of a resource without downloading it, if the server reports it.
+pub fn get_content_length(url: &str, authorization: Option<&str>) -> Result<Option<u64>, String> {
+    if url.starts_with("file://") {
+        let path = Url::parse(url)
+            .ok()
+            .and_then(|x| x.to_file_path().ok())
+            .ok_or_else(|| format!("Invalid file URL {:?}", url))?;
+
+        return path
+            .metadata()
+            .map(|x| Some(x.len()))
+            .map_err(|x| format!("Unable to read {:?}: {:?}", path, x));
+    }
+
+    assert_ssl(url)?;
+
+    let response = authorize(build_client()?.head(url), url, authorization)
+        .send()
+        .map_err(|x| format!("Failed to HEAD resource: {:?}", x))?;
+
+    if !response.status().is_success() {
+        return Err(format!("Bad status code: {:?}.", response.status()));
+    }
+
+    Ok(response
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.parse().ok()))
+}
+
+/// Sets the proxy auto-config script to use, instead of the system's configuration.
+pub fn set_pac_url(url: Option<String>) {
+    *PAC_URL.write().log_expect("PAC state has been dirtied") = url;
+    PROXY_CACHE
+        .lock()
+        .log_expect("Proxy cache has been dirtied")
+        .clear();
+}
+
+/// Schemes which proxies can be reached with.
+const PROXY_SCHEMES: [&str; 4] = ["http", "https", "socks5", "socks5h"];
+
+/// Checks that a manual proxy can be used, returning its URL along with any credentials.
+pub fn check_proxy(proxy: &ProxyConfig) -> Result<Url, String> {
+    let mut url =
+        Url::parse(&proxy.url).map_err(|x| format!("Invalid proxy {:?}: {}", proxy.url, x))?;
+
+    if !PROXY_SCHEMES.contains(&url.scheme()) {
+        return Err(format!(
+            "Unsupported proxy scheme {:?} (expected one of {})",
+            url.scheme(),
+            PROXY_SCHEMES.join(", ")
+        ));
+    }
+
+    if let Some(ref username) = proxy.username {
+        url.set_username(username)
+            .map_err(|_| format!("Proxy {:?} can't have credentials", proxy.url))?;
+        url.set_password(proxy.password.as_ref().map(|x| x.as_str()))
+            .map_err(|_| format!("Proxy {:?} can't have credentials", proxy.url))?;
+    }
+
+    Ok(url)
+}
+
+/// Sets the proxy specified by the configuration.
+pub fn set_configured_proxy(proxy: Option<ProxyConfig>) {
+    *CONFIGURED_PROXY
+        .write()
+        .log_expect("Proxy state has been dirtied") = proxy;
+    PROXY_CACHE
+        .lock()
+        .log_expect("Proxy cache has been dirtied")
+        .clear();
+}
+
+/// Sets (or with `None`, clears) the proxy chosen by the user, which is used instead of
+/// both the configuration's and the system's.
+pub fn set_user_proxy(proxy: Option<ProxyConfig>) -> Result<(), String> {
+    if MACHINE_PROXY
+        .read()
+        .log_expect("Proxy state has been dirtied")
+        .is_some()
+    {
+        return Err(error_help::coded(
+            "proxy",
+            strings::tr("errors.proxy_locked"),
+        ));
+    }
+
+    if let Some(ref proxy) = proxy {
+        check_proxy(proxy)?;
+        info!("Using proxy {} for all requests", proxy.url);
+    }
+
+    *USER_PROXY
+        .write()
+        .log_expect("Proxy state has been dirtied") = proxy;
+    PROXY_CACHE
+        .lock()
+        .log_expect("Proxy cache has been dirtied")
+        .clear();
+
+    Ok(())
+}
+
+/// Sets (or with `None`, clears) the proxy enforced by the machine's policy, which is
+/// used instead of any other.
+pub fn set_machine_proxy(proxy: Option<ProxyConfig>) {
+    *MACHINE_PROXY
+        .write()
+        .log_expect("Proxy state has been dirtied") = proxy;
+    PROXY_CACHE
+        .lock()
+        .log_expect("Proxy cache has been dirtied")
+        .clear();
+}
+
+/// Returns the manual proxy in use (enforced by the machine's policy, else chosen by the
+/// user, else by the configuration), if any.
+pub fn get_manual_proxy() -> Option<ProxyConfig> {
+    let machine = MACHINE_PROXY
+        .read()
+        .log_expect("Proxy state has been dirtied")
+        .clone();
+    let user = USER_PROXY
+        .read()
+        .log_expect("Proxy state has been dirtied")
+        .clone();
+
+    machine.or(user).or_else(|| {
+        CONFIGURED_PROXY
+            .read()
+            .log_expect("Proxy state has been dirtied")
+            .clone()
+    })
+}
+
+/// Returns if a host (or a domain it is part of) is in a list of hosts to connect to
+/// directly, in the style of `NO_PROXY`.
+fn is_bypassed<'a, I>(host: &str, list: I) -> bool
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let host = host.to_lowercase();
+
+    list.into_iter()
+        .map(|x| x.trim().to_lowercase())
+        .filter(|x| !x.is_empty())
+        .any(|x| {
+            let domain = x.trim_start_matches('*').trim_start_matches('.');
+            x == "*" || host == domain || host.ends_with(&format!(".{}", domain))
+        })
+}
+
+/// Reads the first of the specified environment variables which is set.
+fn read_env(names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .filter_map(|x| env::var(x).ok())
+        .map(|x| x.trim().to_string())
+        .find(|x| !x.is_empty())
+}
+
+/// Selects a proxy from the conventional `HTTPS_PROXY`, `HTTP_PROXY`, `ALL_PROXY` and
+/// `NO_PROXY` environment variables. Returns `None` if they don't apply to the request.
+fn env_proxy(url: &Url) -> Option<Option<Url>> {
+    let proxy = match url.scheme() {
+        "https" => read_env(&["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"]),
+        _ => read_env(&["HTTP_PROXY", "http_proxy", "ALL_PROXY", "all_proxy"]),
+    }?;
+
+    if let Some(list) = read_env(&["NO_PROXY", "no_proxy"]) {
+        if is_bypassed(url.host_str()?, list.split(',')) {
+            return Some(None);
+        }
+    }
+
+    let proxy = if proxy.contains("://") {
+        proxy
+    } else {
+        format!("http://{}", proxy)
+    };
+
+    match Url::parse(&proxy) {
+        Ok(v) => Some(Some(v)),
+        Err(v) => {
+            warn!("Ignoring invalid proxy from the environment: {}", v);
+            None
+        }
+    }
+}
+
+/// Selects a proxy for a request. A manual proxy is preferred, then those given by the
+/// environment, then the system's (using WPAD/PAC where the platform supports it).
+fn find_proxy(url: &Url) -> Option<Url> {
+    let key = format!("{}://{}", url.scheme(), url.host_str()?);
+
+    if let Some(proxy) = PROXY_CACHE
+        .lock()
+        .log_expect("Proxy cache has been dirtied")
+        .get(&key)
+    {
+        return proxy.clone();
+    }
+
+    let host = url.host_str()?;
+    let proxy = get_manual_proxy().and_then(|manual| {
+        if is_bypassed(host, manual.no_proxy.iter().map(|x| x.as_str())) {
+            return Some(None);
+        }
+
+        match check_proxy(&manual) {
+            Ok(v) => Some(Some(v)),
+            Err(v) => {
+                warn!("Ignoring manual proxy: {}", v);
+                None
+            }
+        }
+    });
+
+    let proxy = match proxy.or_else(|| env_proxy(url)) {
+        Some(v) => v,
+        None => system_proxy(url),
+    }
+    .or_else(|| dns::fallback_proxy(url));
+
+    if let Some(ref v) = proxy {
+        let mut shown = v.clone();
+        let _ = shown.set_password(None);
+        info!("Using proxy {} for {}", shown, key);
+    }
+
+    PROXY_CACHE
+        .lock()
+        .log_expect("Proxy cache has been dirtied")
+        .insert(key, proxy.clone());
+
+    proxy
+}
+
+/// Selects the proxy the system would use for a request, if any.
+fn system_proxy(url: &Url) -> Option<Url> {
+    let pac_url = PAC_URL.read().log_expect("PAC state has been dirtied").clone();
+
+    // Proxy lists look like "host:port;host2:port", or "http=host:port;https=..."
+    native::get_proxy_for_url(url.as_str(), pac_url.as_ref().map(|x| x.as_str()))
+        .and_then(|list| {
+            list.split(|c| c == ';' || c == ' ')
+                .map(|x| x.trim())
+                .filter(|x| !x.is_empty())
+                .map(|x| match x.find('=') {
+                    Some(i) => x[i + 1..].to_string(),
+                    None => x.to_string(),
+                })
+                .next()
+        })
+        .and_then(|x| {
+            let proxy = if x.contains("://") {
+                x
+            } else {
+                format!("http://{}", x)
+            };
+
+            Url::parse(&proxy).ok()
+        })
+}
+
+/// Retries a request using the system's integrated authentication, for proxies that
+/// require NTLM or Kerberos. Returns the status code.
+fn stream_with_proxy_auth<F>(
+    url: &str,
+    authorization: Option<String>,
+    range: Option<(u64, u64)>,
+    mut callback: F,
+) -> Result<u32, String>
+where
+    F: FnMut(Vec<u8>, u64) -> (),
+{
+    info!("Proxy requires authentication, retrying with the current user's credentials");
+
+    let mut headers = Vec::new();
+    match authorization {
+        Some(auth) => headers.push(("Authorization", format!("Bearer {}", auth))),
+        None => {
+            if let Some(credentials) = find_credentials(url) {
+                headers.push(("Authorization", credentials.authorization));
+                if let Some(accept) = credentials.accept {
+                    headers.push(("Accept", accept));
+                }
+            }
+        }
+    }
+    if let Some((start, end)) = range {
+        headers.push(("Range", format!("bytes={}-{}", start, end)));
+    }
+
+    let proxy = Url::parse(url)
+        .ok()
+        .and_then(|x| find_proxy(&x))
+        .and_then(|x| Some(format!("{}:{}", x.host_str()?, x.port_or_known_default()?)));
+
+    native::stream_with_system_auth(url, &headers, proxy, move |data, size| {
+        bandwidth::record(data.len() as u64);
+        callback(data, size)
+    })
+}
+
+/// Returns the shared, customised HTTP client.
+pub fn build_client() -> Result<Client, String> {
+    CLIENT.clone()
+}
+
+/// Returns the shared, customised async HTTP client.
+pub fn build_async_client() -> Result<AsyncClient, String> {
+    ASYNC_CLIENT.clone()
+}
+
+/// Reads a `file://` URL from the local file system, as used by local package sources.
+fn read_local_file<F>(url: &str, range: Option<(u64, u64)>, mut callback: F) -> Result<(), String>
+where
+    F: FnMut(Vec<u8>, u64) -> (),
+{
+    let path = Url::parse(url)
+        .ok()
+        .and_then(|x| x.to_file_path().ok())
+        .ok_or_else(|| format!("Invalid file URL {:?}", url))?;
+
+    let mut file = File::open(&path).map_err(|x| format!("Unable to open {:?}: {:?}", path, x))?;
+    let size = file
+        .metadata()
+        .map_err(|x| format!("Unable to read {:?}: {:?}", path, x))?
+        .len();
+
+    let mut remaining = size;
+    if let Some((start, end)) = range {
+        if start >= size {
+            return Err(format!(
+                "Range {}-{} is beyond the end of {:?}",
+                start, end, path
+            ));
+        }
+
+        file.seek(SeekFrom::Start(start))
+            .map_err(|x| format!("Unable to seek {:?}: {:?}", path, x))?;
+        remaining = (end + 1).min(size) - start;
+    }
+
+    let mut buf = [0 as u8; 8192];
+    while remaining > 0 {
+        let len = file
+            .read(&mut buf[..(remaining as usize).min(8192)])
+            .map_err(|x| format!("Failed to read {:?}: {:?}", path, x))?;
+
+        if len == 0 {
+            break;
+        }
+
+        remaining -= len as u64;
+        callback(buf[0..len].to_vec(), size);
+        control::check()?;
+    }
+
+    Ok(())
+}
+
+/// Downloads a resource from a HTTP server into memory, optionally only fetching the
+/// specified (inclusive) byte range.
+pub fn fetch_bytes(
+    url: &str,
+    authorization: Option<String>,
+    range: Option<(u64, u64)>,
+) -> Result<Vec<u8>, String> {
+    if url.starts_with("file://") {
+        let mut data = Vec::new();
+        read_local_file(url, range, |x, _| data.extend_from_slice(&x))?;
+        return Ok(data);
+    }
+
+    assert_ssl(url)?;
+
+    let mut client = get(url, authorization.as_ref().map(|x| x.as_str()))?;
+
+    if let Some((start, end)) = range {
+        client = client.header(RANGE, format!("bytes={}-{}", start, end));
+    }
+
+    let mut response = client
+        .send()
+        .map_err(|x| format!("Failed to GET resource: {:?}", x))?;
+
+    let mut data = Vec::new();
+    let mut status = response.status();
+    if status == StatusCode::PROXY_AUTHENTICATION_REQUIRED {
+        let code = stream_with_proxy_auth(url, authorization, range, |x, _| {
+            data.extend_from_slice(&x)
+        })?;
+        status = StatusCode::from_u16(code as u16)
+            .map_err(|x| format!("Bad status code: {:?}.", x))?;
+    } else if status != StatusCode::OK && status != StatusCode::PARTIAL_CONTENT {
+        return Err(format!("Bad status code: {:?}.", status));
+    } else {
+        response
+            .read_to_end(&mut data)
+            .map_err(|x| format!("Failed to read resource: {:?}", x))?;
+        bandwidth::record(data.len() as u64);
+    }
+
+    // Servers are free to ignore range requests, and return the whole resource instead
+    if let Some((start, end)) = range {
+        if status == StatusCode::OK {
+            let end = (end as usize + 1).min(data.len());
+            if start as usize >= end {
+                return Err(format!("Range {}-{} is beyond the end of the resource", start, end));
+            }
+
+            data = data[start as usize..end].to_vec();
+        }
+    }
+
+    Ok(data)
+}
+
+/// Fetches an (inclusive) byte range of a resource, failing instead of downloading all of
+/// it where the server doesn't support range requests. Ranges starting past the end of
+/// the resource are empty.
+pub fn fetch_range(url: &str, start: u64, end: u64) -> Result<Vec<u8>, String> {
+    if url.starts_with("file://") {
+        return fetch_bytes(url, None, Some((start, end)));
+    }
+
+    assert_ssl(url)?;
+
+    let mut response = get(url, None)?
+        .header(RANGE, format!("bytes={}-{}", start, end))
+        .send()
+        .map_err(|x| format!("Failed to GET resource: {:?}", x))?;
+
+    match response.status() {
+        StatusCode::PARTIAL_CONTENT => {}
+        StatusCode::RANGE_NOT_SATISFIABLE => return Ok(Vec::new()),
+        x => return Err(format!("Range request was answered with {:?}.", x)),
+    }
+
+    let mut data = Vec::new();
+    response
+        .read_to_end(&mut data)
+        .map_err(|x| format!("Failed to read resource: {:?}", x))?;
+    bandwidth::record(data.len() as u64);
+
+    Ok(data)
+}
+
+/// Passes data on to a callback, after discarding the first `skip` bytes of the stream.
+fn deliver<F>(skip: &mut u64, data: &[u8], size: u64, callback: &mut F)
+where
+    F: FnMut(Vec<u8>, u64) -> (),
+{
+    let skipped = (*skip).min(data.len() as u64) as usize;
+    *skip -= skipped as u64;
+
+    if skipped < data.len() {
+        callback(data[skipped..].to_vec(), size);
+    }
 }
 
 /// Streams a file from a HTTP server.
-pub fn stream_file<F>(
+pub fn stream_file<F>(url: &str, authorization: Option<String>, callback: F) -> Result<(), String>
+where
+    F: FnMut(Vec<u8>, u64) -> (),
+{
+    stream_file_from(url, authorization, 0, callback)
+}
+
+/// Streams a file from a HTTP server, starting at the specified offset to resume an
+/// interrupted download. Servers which don't support range requests are read from the
+/// start, with what was already received being discarded.
+///
+/// callback: called with (data, total size of the file)
+pub fn stream_file_from<F>(
     url: &str,
     authorization: Option<String>,
+    offset: u64,
     mut callback: F,
 ) -> Result<(), String>
 where
     F: FnMut(Vec<u8>, u64) -> (),
 {
+    if url.starts_with("file://") {
+        let range = if offset > 0 {
+            Some((offset, u64::max_value() - 1))
+        } else {
+            None
+        };
+        return read_local_file(url, range, callback);
+    }
+
     assert_ssl(url)?;
 
-    let mut client = build_client()?.get(url);
+    let mut client = get(url, authorization.as_ref().map(|x| x.as_str()))?;
 
-    if let Some(auth) = authorization {
-        client = client.header("Authorization", format!("Bearer {}", auth));
+    if offset > 0 {
+        client = client.header(RANGE, format!("bytes={}-", offset));
     }
 
     let mut client = client
         .send()
         .map_err(|x| format!("Failed to GET resource: {:?}", x))?;
 
+    let mut skip = 0;
     match client.status() {
-        StatusCode::OK => {}
+        StatusCode::OK => skip = offset,
+        StatusCode::PARTIAL_CONTENT if offset > 0 => {}
+        // Everything has already been received
+        StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => return Ok(()),
+        StatusCode::PROXY_AUTHENTICATION_REQUIRED => {
+            let mut skip = offset;
+            return stream_with_proxy_auth(url, authorization, None, |data, size| {
+                deliver(&mut skip, &data, size, &mut callback)
+            })
+            .map(|_| ());
+        }
         StatusCode::TOO_MANY_REQUESTS => {
             return Err(
                 "Your token has exceeded the number of daily allowable IP addresses. \
@@ -71,7 +704,7 @@ where
         }
     }
 
-    let size = match client.headers().get(CONTENT_LENGTH) {
+    let length: u64 = match client.headers().get(CONTENT_LENGTH) {
         Some(ref v) => v
             .to_str()
             .map_err(|x| format!("Content length header was invalid: {:?}", x))?
@@ -80,6 +713,19 @@ where
         None => 0,
     };
 
+    // Partial responses describe the full size as `bytes <start>-<end>/<size>`
+    let size = if skip > 0 || offset == 0 {
+        length
+    } else {
+        client
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|x| x.to_str().ok())
+            .and_then(|x| x.rsplit('/').next())
+            .and_then(|x| x.parse().ok())
+            .unwrap_or(if length > 0 { offset + length } else { 0 })
+    };
+
     let mut buf = [0 as u8; 8192];
     loop {
         let len = client
@@ -90,10 +736,9 @@ where
             break;
         }
 
-        let buf_copy = &buf[0..len];
-        let buf_copy = buf_copy.to_vec();
-
-        callback(buf_copy, size);
+        bandwidth::record(len as u64);
+        deliver(&mut skip, &buf[0..len], size, &mut callback);
+        control::check()?;
     }
 
     Ok(())
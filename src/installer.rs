@@ -4,8 +4,12 @@
 
 use serde_json;
 
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use std::fs::read;
+use std::fs::read_to_string;
 use std::fs::File;
-use std::fs::OpenOptions;
 
 use std::env;
 use std::env::var;
@@ -15,42 +19,95 @@ use std::path::PathBuf;
 
 use std::sync::mpsc::Sender;
 
-use std::io::copy;
-use std::io::Cursor;
+use std::io;
 
+use std::process::exit;
 use std::process::Command;
-use std::process::{exit, Stdio};
+
+use std::time::Instant;
 
 use config::BaseAttributes;
 use config::Config;
+use config::HookCommand;
+use config::InstallScope;
+use config::PackageDescription;
+use config::ReleaseChannel;
+use config::WebhookEvent;
 
+use sources::types::File as ReleaseFile;
 use sources::types::Version;
 
+use tasks::download_packages::DownloadProgress;
 use tasks::install::InstallTask;
+use tasks::register_uninstaller::RegisterUninstallerTask;
+use tasks::repair::RepairTask;
+use tasks::repair_files::RepairFilesTask;
+use tasks::resolver::find_latest;
+use tasks::transaction::Transaction;
 use tasks::uninstall::UninstallTask;
 use tasks::uninstall_global_shortcut::UninstallGlobalShortcutsTask;
+use tasks::update_tool::UpdateToolTask;
 use tasks::DependencyTree;
 use tasks::TaskMessage;
 
+use locations;
+use logging;
 use logging::LoggingErrors;
 
 use dirs::home_dir;
 
-use std::fs::remove_file;
-
+use std::fs::{remove_dir_all, remove_file};
+
+use atomic;
+use audit::{self, AuditAction};
+use bandwidth;
+use bundle;
+use config_watch;
+use cpu;
+use detect;
+use dns;
+use environments;
+use eta::EtaEstimator;
+use gpu;
+use hashing::sha256_hex;
 use http;
-
-use number_prefix::{NumberPrefix, Prefixed, Standalone};
+use localization;
+use migration;
+use policy;
+use prefetch;
+use queue::{self, Job, JobAction};
+use self_update;
+use source_auth;
+use strings;
 
 use native;
 
+use webhooks;
+use webhooks::WebhookPayload;
+
+use events;
+use events::Event;
+use integrity;
+use integrity::MetadataStatus;
+use report;
+use report::InstallReport;
+
+use chrono::Local;
+
 /// A message thrown during the installation of packages.
 #[derive(Serialize)]
 pub enum InstallMessage {
-    Status(String, f64),
+    /// Message, progress, estimated seconds remaining, and the id of the message (which
+    /// stays the same throughout a phase, whatever language it is shown in)
+    Status(String, f64, Option<u64>, &'static str),
+    /// Progress of each package being downloaded at once
+    Downloads(Vec<DownloadProgress>),
     PackageInstalled,
     Error(String),
     AuthorizationRequired(String),
+    Prompt(String, Vec<String>),
+    DirectoryPrompt(String, String),
+    TextPrompt(String, String),
     EOF,
 }
 
@@ -62,6 +119,93 @@ pub struct Credentials {
     pub token: String,
 }
 
+/// The maximum number of history entries retained in the database.
+const MAX_HISTORY_ENTRIES: usize = 200;
+
+/// The maximum number of task errors kept for diagnostics.
+const MAX_TASK_ERRORS: usize = 20;
+
+/// The last configuration loaded successfully, kept next to metadata.json so that the
+/// maintenance tool can still start while offline.
+const CONFIG_CACHE_FILE: &str = "config.cache.toml";
+/// Directory (within the install directory by default) where files replaced by a running
+/// install are kept, until it either succeeds or is rolled back.
+const TRANSACTION_DIR: &str = ".transaction";
+/// Directory (next to `TRANSACTION_DIR`) where packages are downloaded before being
+/// extracted. Nothing in it is kept after the operation finishes.
+const DOWNLOADS_DIR: &str = ".downloads";
+/// Directory (next to `TRANSACTION_DIR`) where files are prefetched to.
+const PREFETCH_DIR: &str = ".prefetch";
+
+/// The kind of operation recorded in the installation history.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum HistoryOperation {
+    Install,
+    Modify,
+    Update,
+    Uninstall,
+}
+
+/// A single entry in the transcript of operations performed on this installation.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HistoryEntry {
+    pub timestamp: String,
+    pub operation: HistoryOperation,
+    #[serde(default)]
+    pub package: Option<String>,
+    #[serde(default)]
+    pub from_version: Option<Version>,
+    #[serde(default)]
+    pub to_version: Option<Version>,
+    pub success: bool,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl HistoryEntry {
+    /// Creates a new entry for an operation which is not specific to a single package.
+    pub fn new(operation: HistoryOperation, result: &Result<(), String>) -> HistoryEntry {
+        HistoryEntry {
+            timestamp: Local::now().to_rfc3339(),
+            operation,
+            package: None,
+            from_version: None,
+            to_version: None,
+            success: result.is_ok(),
+            error: result.clone().err(),
+        }
+    }
+}
+
+/// A task which failed during this session.
+#[derive(Serialize, Clone, Debug)]
+pub struct TaskError {
+    pub timestamp: String,
+    pub task: String,
+    pub error: String,
+}
+
+/// A system user or group created by the installer.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SystemAccount {
+    /// The package this account was created for
+    pub package: String,
+    pub name: String,
+    pub is_group: bool,
+    pub remove_on_uninstall: bool,
+}
+
+/// A directory link created by the installer.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DirectoryLink {
+    /// The package this link was created for
+    pub package: String,
+    /// Absolute path to the link itself
+    pub path: String,
+    /// Absolute path the link points to, which is never removed by the installer
+    pub target: String,
+}
+
 /// Metadata about the current installation itself.
 #[derive(Serialize, Deserialize, Clone)]
 pub struct InstallationDatabase {
@@ -69,6 +213,63 @@ pub struct InstallationDatabase {
     pub shortcuts: Vec<String>,
     #[serde(default)]
     pub credentials: Credentials,
+    /// Compact transcript of operations performed on this installation
+    #[serde(default)]
+    pub history: Vec<HistoryEntry>,
+    /// System accounts created during installation
+    #[serde(default)]
+    pub system_accounts: Vec<SystemAccount>,
+    /// Directory links created during installation. These survive updates, so that
+    /// targets chosen by the user are kept.
+    #[serde(default)]
+    pub links: Vec<DirectoryLink>,
+    /// Progress per second of each phase of previous operations, for estimating times
+    #[serde(default)]
+    pub phase_rates: HashMap<String, f64>,
+    /// If this is a machine-wide installation, whose shortcuts and registration are for
+    /// all users of the machine rather than whoever runs the installer. These are only
+    /// maintained with elevated privileges.
+    #[serde(default)]
+    pub all_users: bool,
+    /// Shortcuts created in a single user's profile, and the user they belong to
+    #[serde(default)]
+    pub user_shortcuts: HashMap<String, String>,
+    /// Where each declared user data location was for the installing user, so installed
+    /// applications can find them
+    #[serde(default)]
+    pub data_locations: HashMap<String, String>,
+    /// Where the installation was when this was last saved, for noticing when it has been
+    /// moved elsewhere
+    #[serde(default)]
+    pub install_path: Option<String>,
+    /// Where the configuration was fetched from, if overridden using --config-url
+    #[serde(default)]
+    pub config_url: Option<String>,
+    /// The release channel chosen for each package, if not the default one
+    #[serde(default)]
+    pub channels: HashMap<String, String>,
+    /// Encrypted access tokens entered for private sources, by package (see `source_auth`)
+    #[serde(default)]
+    pub source_tokens: HashMap<String, String>,
+    /// The product code of the MSI this was installed through, which owns the
+    /// installation's "Apps & Features" entry (see `msi`)
+    #[serde(default)]
+    pub msi_product_code: Option<String>,
+    /// Where privileged actions taken on this installation are recorded, if anywhere
+    /// (see `audit`)
+    #[serde(default)]
+    pub audit_log: Option<String>,
+    /// Languages which language packs and localized assets are chosen for (see
+    /// `localization`)
+    #[serde(default)]
+    pub languages: Vec<String>,
+    /// The product of a suite this installation is, which its maintenance tool runs as
+    /// instead of the suite (see `suite`)
+    #[serde(default)]
+    pub product_name: Option<String>,
+    /// The environment selected when this was installed, if any (see `environments`)
+    #[serde(default)]
+    pub environment: Option<String>,
 }
 
 impl InstallationDatabase {
@@ -81,8 +282,66 @@ impl InstallationDatabase {
                 username: String::new(),
                 token: String::new(),
             },
+            history: Vec::new(),
+            system_accounts: Vec::new(),
+            links: Vec::new(),
+            phase_rates: HashMap::new(),
+            all_users: false,
+            user_shortcuts: HashMap::new(),
+            data_locations: HashMap::new(),
+            install_path: None,
+            config_url: None,
+            channels: HashMap::new(),
+            source_tokens: HashMap::new(),
+            msi_product_code: None,
+            audit_log: None,
+            languages: Vec::new(),
+            product_name: None,
+            environment: None,
+        }
+    }
+
+    /// Remembers which user a newly created shortcut belongs to, unless it was created
+    /// for all users.
+    pub fn record_shortcut(&mut self, path: &str) {
+        if !self.all_users && !path.is_empty() {
+            self.user_shortcuts
+                .insert(path.to_string(), native::get_current_user());
+        }
+    }
+
+    /// Forgets about a removed shortcut, describing the failure if it couldn't be
+    /// removed. Shortcuts in another user's profile may be inaccessible to whoever is
+    /// uninstalling.
+    pub fn forget_shortcut(&mut self, path: &str, result: io::Result<()>) -> Result<(), String> {
+        let owner = self.user_shortcuts.remove(path);
+
+        result.map_err(|x| match owner {
+            Some(ref owner) if *owner != native::get_current_user() => format!(
+                "Unable to delete shortcut {:?} belonging to {}: {:?}",
+                path, owner, x
+            ),
+            _ => format!("Unable to delete shortcut {:?}: {:?}", path, x),
+        })
+    }
+
+    /// Appends an entry to the history, discarding the oldest entries if needed.
+    pub fn record(&mut self, entry: HistoryEntry) {
+        self.history.push(entry);
+
+        if self.history.len() > MAX_HISTORY_ENTRIES {
+            let excess = self.history.len() - MAX_HISTORY_ENTRIES;
+            self.history.drain(0..excess);
         }
     }
+
+    /// Returns the installed version of a package, if any.
+    fn installed_version(&self, name: &str) -> Option<Version> {
+        self.packages
+            .iter()
+            .find(|x| x.name == name)
+            .map(|x| x.version.clone())
+    }
 }
 
 /// The installer framework contains metadata about packages, what is installable, what isn't,
@@ -90,6 +349,8 @@ impl InstallationDatabase {
 pub struct InstallerFramework {
     pub base_attributes: BaseAttributes,
     pub config: Option<Config>,
+    /// The text of the currently loaded configuration, to be cached with the database
+    pub raw_config: Option<String>,
     pub database: InstallationDatabase,
     pub install_path: Option<PathBuf>,
     pub preexisting_install: bool,
@@ -97,6 +358,56 @@ pub struct InstallerFramework {
     // If we just completed an uninstall, and we should clean up after ourselves.
     pub burn_after_exit: bool,
     pub launcher_path: Option<String>,
+    /// Changes made to the file system by the running install, if any
+    pub transaction: Transaction,
+    /// The outcome of the last install during this session
+    pub report: Option<InstallReport>,
+    /// A newer build of the installer found through the configuration's `self_update`
+    pub tool_update: Option<(Version, ReleaseFile)>,
+    /// If the installation's metadata matched its signature when loaded
+    pub metadata_status: MetadataStatus,
+    /// Overrides the configured staging directory, from the command line
+    pub staging_dir: Option<PathBuf>,
+    /// Overrides the configured timeout of non-interactive operations, from the command
+    /// line, in seconds
+    pub install_timeout: Option<u64>,
+    /// Applications closed so that installed files could be replaced, to be restarted
+    /// once the running install has finished
+    pub restart_session: Option<native::RestartSession>,
+    /// Packages which have been (re)installed by the running operation, and are yet to
+    /// have their post-install hooks run
+    pub pending_hooks: HashSet<String>,
+    /// Packages which have been (re)installed by the running operation, and are yet to
+    /// have their smoke tests run
+    pub pending_smoke_tests: HashSet<String>,
+    /// If a maintenance token has been given, allowing a restricted installation to be
+    /// modified or uninstalled (see `policy`)
+    pub maintenance_unlocked: bool,
+    /// Packages resolved ahead of time by the running operation, which don't need to be
+    /// looked up again
+    pub resolved: HashMap<String, (Version, ReleaseFile)>,
+    /// Packages downloaded ahead of time by the running operation, which are waiting in
+    /// the downloads directory (see `get_download_path`)
+    pub downloaded: HashSet<String>,
+    /// The most recent tasks to have failed during this session, oldest first
+    pub task_errors: VecDeque<TaskError>,
+    /// Languages given on the command line, instead of those recorded or shown
+    pub languages: Vec<String>,
+    /// Choices made before being handed over to an elevated copy, which it starts with
+    pub handed_over: HandedOverChoices,
+    /// How much more the running operation may download without asking, as the user has
+    /// already been warned about the data budget (see `bandwidth::confirm`)
+    pub confirmed_download: u64,
+}
+
+/// Choices made in the UI of a fresh install before it was handed over to an elevated
+/// copy (see `InstallerFramework::elevation_args`).
+#[derive(Debug, Clone, Default)]
+pub struct HandedOverChoices {
+    /// Packages which start off selected, instead of the defaults
+    pub packages: Option<Vec<String>>,
+    /// If the desktop shortcut option starts off checked
+    pub desktop_shortcut: Option<bool>,
 }
 
 /// Contains basic properties on the status of the session. Subset of InstallationFramework.
@@ -107,6 +418,9 @@ pub struct InstallationStatus {
     pub preexisting_install: bool,
     pub is_launcher: bool,
     pub launcher_path: Option<String>,
+    /// Where the installation was before being moved by the user, if it has been
+    pub moved_from: Option<String>,
+    pub metadata_status: MetadataStatus,
 }
 
 /// Tracks the state of a local installation
@@ -118,14 +432,68 @@ pub struct LocalInstallation {
     pub files: Vec<String>,
     /// Absolute paths to generated shortcut files
     pub shortcuts: Vec<String>,
+    /// Absolute path that files are relative to, if not the main install directory
+    #[serde(default)]
+    pub root: Option<String>,
+    /// Files which this package replaced, and are restored when it is removed
+    #[serde(default)]
+    pub overwritten: Vec<OverwrittenFile>,
+    /// SHA-256 hashes of installed files, for detecting modifications
+    #[serde(default)]
+    pub hashes: HashMap<String, String>,
+    /// Packages this one required when it was installed
+    #[serde(default)]
+    pub requires: Vec<String>,
+    /// Why the package's smoke test failed after it was installed, if it did. Repairing
+    /// reinstalls packages which failed.
+    #[serde(default)]
+    pub suspect: Option<String>,
+    /// The package's `pre_uninstall` hooks as of when it was installed, for uninstalling
+    /// without the configuration
+    #[serde(default)]
+    pub pre_uninstall: Vec<HookCommand>,
+}
+
+/// A file which was replaced by a package.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OverwrittenFile {
+    /// Path relative to the package's root
+    pub path: String,
+    /// Absolute path to the backed up original
+    pub backup: String,
+    /// If the backup is compressed
+    #[serde(default)]
+    pub compressed: bool,
+    /// Package which provided the original, if any
+    pub owner: Option<String>,
+}
+
+impl LocalInstallation {
+    /// Returns the directory this package's files are relative to.
+    pub fn get_root(&self, install_path: &Path) -> PathBuf {
+        match self.root {
+            Some(ref v) => PathBuf::from(v),
+            None => install_path.to_owned(),
+        }
+    }
 }
 
 macro_rules! declare_messenger_callback {
-    ($target:expr) => {
+    ($target:expr, $eta:expr) => {
         &|msg: &TaskMessage| match *msg {
-            TaskMessage::DisplayMessage(msg, progress) => {
-                if let Err(v) = $target.send(InstallMessage::Status(msg.to_string(), progress as _))
-                {
+            TaskMessage::DisplayMessage(id, msg, progress) => {
+                let remaining = $eta.borrow_mut().update(id, progress);
+                events::publish(Event::Progress {
+                    message: msg.to_string(),
+                    progress: progress as _,
+                    remaining,
+                });
+                if let Err(v) = $target.send(InstallMessage::Status(
+                    msg.to_string(),
+                    progress as _,
+                    remaining,
+                    id,
+                )) {
                     error!("Failed to submit queue message: {:?}", v);
                 }
             }
@@ -135,11 +503,36 @@ macro_rules! declare_messenger_callback {
                     error!("Failed to submit queue message: {:?}", v);
                 }
             }
+            TaskMessage::Downloads(downloads) => {
+                if let Err(v) = $target.send(InstallMessage::Downloads(downloads.to_vec())) {
+                    error!("Failed to submit queue message: {:?}", v);
+                }
+            }
             TaskMessage::PackageInstalled => {
                 if let Err(v) = $target.send(InstallMessage::PackageInstalled) {
                     error!("Failed to submit queue message: {:?}", v);
                 }
             }
+            TaskMessage::Prompt(question, choices) => {
+                let choices = choices.iter().map(|x| x.to_string()).collect();
+                if let Err(v) = $target.send(InstallMessage::Prompt(question.to_string(), choices))
+                {
+                    error!("Failed to submit queue message: {:?}", v);
+                }
+            }
+            TaskMessage::DirectoryPrompt(question, default) => {
+                let message =
+                    InstallMessage::DirectoryPrompt(question.to_string(), default.to_string());
+                if let Err(v) = $target.send(message) {
+                    error!("Failed to submit queue message: {:?}", v);
+                }
+            }
+            TaskMessage::TextPrompt(question, default) => {
+                let message = InstallMessage::TextPrompt(question.to_string(), default.to_string());
+                if let Err(v) = $target.send(message) {
+                    error!("Failed to submit queue message: {:?}", v);
+                }
+            }
         }
     };
 }
@@ -150,10 +543,55 @@ impl InstallerFramework {
         self.config.clone()
     }
 
-    /// Returns the default install path.
+    /// Attempts to find the install directory within a host application. Returns None if
+    /// the configuration doesn't describe a host application.
+    pub fn find_host_path(&self) -> Option<Result<PathBuf, String>> {
+        let host = self.config.as_ref()?.host_application.as_ref()?;
+        Some(locations::find_host(host))
+    }
+
+    /// Returns the default install path, for the installation's scope.
     pub fn get_default_path(&self) -> Option<String> {
+        self.get_default_path_for(self.database.all_users)
+    }
+
+    /// Returns the default install path for a per-user or machine-wide installation.
+    pub fn get_default_path_for(&self, all_users: bool) -> Option<String> {
+        if let Some(path) = policy::machine().install_dir {
+            return Some(path);
+        }
+
+        match self.find_host_path() {
+            Some(Ok(path)) => return Some(path.to_str()?.to_owned()),
+            Some(Err(v)) => warn!("{}, falling back to the default path", v),
+            None => {}
+        }
+
+        // Installations made by other installers are upgraded where they are
+        if let Some(found) = self.config.as_ref().and_then(migration::find) {
+            return Some(found.location.to_str()?.to_owned());
+        }
+
         let app_name = &self.base_attributes.name;
 
+        if all_users {
+            let base_dir = if cfg!(windows) {
+                PathBuf::from(
+                    var("ProgramFiles").unwrap_or_else(|_| "C:\\Program Files".to_string()),
+                )
+            } else {
+                PathBuf::from("/opt")
+            };
+
+            let file_name = if cfg!(unix) {
+                app_name.to_ascii_lowercase()
+            } else {
+                app_name.to_string()
+            };
+
+            return Some(base_dir.join(file_name).to_str()?.to_owned());
+        }
+
         let base_dir = match var("LOCALAPPDATA") {
             Ok(path) => PathBuf::from(path),
             Err(_) => home_dir()?,
@@ -176,7 +614,7 @@ impl InstallerFramework {
     /// fresh_install: If the install directory must be empty
     pub fn install(
         &mut self,
-        items: Vec<String>,
+        mut items: Vec<String>,
         messages: &Sender<InstallMessage>,
         fresh_install: bool,
         create_desktop_shortcuts: bool,
@@ -189,6 +627,130 @@ impl InstallerFramework {
                 .log_expect("Install directory not initialised")
         );
 
+        // Updates are always allowed, but changing what is installed may not be. Only what
+        // was asked for counts, as an update may require packages which weren't before.
+        let adds_packages = items
+            .iter()
+            .any(|x| self.database.installed_version(x).is_none());
+
+        // Installed packages this processor can't run (e.g. once moved to another machine)
+        // are replaced by their fallbacks, rather than failing every update from then on
+        let mut migrated = Vec::new();
+        if let Some(ref config) = self.config {
+            for package in &config.packages {
+                let fallback = match package.cpu_fallback {
+                    Some(ref v) if package.unsupported.is_some() => v,
+                    _ => continue,
+                };
+
+                let installed = self.database.installed_version(&package.name).is_some();
+                if let Some(i) = items.iter().position(|x| x == &package.name) {
+                    if installed {
+                        info!(
+                            "Framework: Replacing {:?} with {:?}, as this processor can't run it",
+                            package.name, fallback
+                        );
+
+                        items.remove(i);
+                        if !items.contains(fallback) {
+                            items.push(fallback.clone());
+                        }
+                        migrated.push(package.name.clone());
+                    }
+                }
+            }
+        }
+
+        // Dependent payloads rely on being installed after what they depend on
+        if let Some(ref config) = self.config {
+            let mut resolved = config.resolve_packages(&items)?;
+
+            // Headless installs have no other chance to check the drivers. Existing
+            // installations are kept up to date, even if the drivers have since changed.
+            if let Some(ref requirements) = config.gpu {
+                if !self.preexisting_install {
+                    let check = gpu::check(requirements);
+                    if check.block {
+                        return Err(check.problems.join(" "));
+                    }
+                }
+            }
+
+            // Builds this processor can't run would only crash once installed
+            let unsupported = config
+                .packages
+                .iter()
+                .filter(|x| resolved.contains(&x.name))
+                .find_map(|x| x.unsupported.clone());
+            if let Some(reason) = unsupported {
+                return Err(reason);
+            }
+
+            // Requirements which are already on the system (e.g. runtimes installed by
+            // something else) don't need to be installed again
+            let install_path = self
+                .install_path
+                .clone()
+                .log_expect("Install directory not initialised");
+            let database = &self.database;
+            resolved.retain(|name| {
+                if items.contains(name) || database.packages.iter().any(|x| &x.name == name) {
+                    return true;
+                }
+
+                let detection = config
+                    .packages
+                    .iter()
+                    .find(|x| &x.name == name)
+                    .and_then(|x| x.detect.as_ref());
+
+                match detection.map(|x| detect::is_satisfied(x, &install_path, config)) {
+                    Some(Ok(true)) => {
+                        info!("Framework: Not adding {:?}, as it is already present", name);
+                        false
+                    }
+                    Some(Err(v)) => {
+                        warn!("Framework: Unable to detect {:?}: {}", name, v);
+                        true
+                    }
+                    _ => true,
+                }
+            });
+
+            for name in resolved.iter().filter(|x| !items.contains(x)) {
+                info!("Framework: Adding {:?}, as it is required by other packages", name);
+            }
+
+            items = resolved;
+        }
+
+        if fresh_install {
+            self.check_install_location()?;
+        }
+
+        // Installations made by other installers are taken over when installing over them
+        let adopting = match self.config {
+            Some(ref config) if fresh_install => {
+                let install_path = self
+                    .install_path
+                    .as_ref()
+                    .log_expect("Install directory not initialised");
+                migration::find(config).filter(|x| x.is_at(install_path))
+            }
+            _ => None,
+        };
+        if let Some(ref found) = adopting {
+            migration::adopt(self, found)?;
+
+            if !items.contains(&found.source.package) {
+                info!(
+                    "Framework: Adding {:?}, as it takes over the existing installation",
+                    found.source.package
+                );
+                items.push(found.source.package.clone());
+            }
+        }
+
         // Calculate packages to *uninstall*
         let mut uninstall_items = Vec::new();
         if !fresh_install {
@@ -204,205 +766,1313 @@ impl InstallerFramework {
             );
         }
 
-        let task = Box::new(InstallTask {
-            items,
-            uninstall_items,
-            fresh_install,
-            create_desktop_shortcuts,
-        });
-
-        let mut tree = DependencyTree::build(task);
+        let removes_packages = uninstall_items.iter().any(|x| !migrated.contains(x));
+        if self.preexisting_install && (adds_packages || removes_packages) {
+            policy::check(self)?;
+        }
 
-        info!("Dependency tree:\n{}", tree);
+        let limits = self
+            .config
+            .as_ref()
+            .map(|x| x.limits.clone())
+            .unwrap_or_default();
 
-        tree.execute(self, declare_messenger_callback!(messages))
-            .map(|_x| ())
-    }
+        if limits.low_priority_io {
+            info!("Lowering I/O priority of installation");
+            if let Err(v) = native::set_background_io_priority() {
+                warn!("Unable to lower I/O priority: {}", v);
+            }
+        }
 
-    /// Sends a request for everything to be uninstalled.
-    pub fn uninstall(&mut self, messages: &Sender<InstallMessage>) -> Result<(), String> {
-        let items: Vec<String> = self
-            .database
-            .packages
-            .iter()
-            .map(|x| x.name.clone())
-            .collect();
+        let previous_database = self.database.clone();
+        self.database.languages = self.get_languages();
+
+        // Remembered in the database, so that uninstalling records to the same log
+        self.database.audit_log = match self.config {
+            Some(ref config) if config.audit.enabled => {
+                let install_path = self
+                    .install_path
+                    .as_ref()
+                    .log_expect("Install directory not initialised");
+                let path = match config.audit.path {
+                    Some(ref spec) => {
+                        locations::resolve(spec, install_path, &config.data_locations)
+                            .map_err(|x| format!("Unable to resolve audit log location: {}", x))?
+                    }
+                    None => audit::default_path(&config.name, self.database.all_users)?,
+                };
+
+                Some(path.to_string_lossy().to_string())
+            }
+            _ => None,
+        };
 
-        let task = Box::new(UninstallTask { items });
+        let task = Box::new(InstallTask {
+            items: items.clone(),
+            uninstall_items: uninstall_items.clone(),
+            fresh_install,
+            adopting: adopting.is_some(),
+            create_desktop_shortcuts,
+            extract_only: false,
+            parallel_downloads: limits.download_parallelism > 1,
+        });
 
         let mut tree = DependencyTree::build(task);
 
         info!("Dependency tree:\n{}", tree);
 
-        tree.execute(self, declare_messenger_callback!(messages))
-            .map(|_x| ())?;
-
-        // Uninstall shortcuts
-        let task = Box::new(UninstallGlobalShortcutsTask {});
+        self.notify_webhooks(&WebhookPayload::new(
+            WebhookEvent::InstallStarted,
+            &self.base_attributes.name,
+            &items,
+        ));
 
-        let mut tree = DependencyTree::build(task);
+        let eta = RefCell::new(EtaEstimator::new(self.database.phase_rates.clone()));
 
-        tree.execute(self, declare_messenger_callback!(messages))
-            .map(|_x| ())?;
+        // Anything logged from here on is summarised in the install report
+        let log_events = events::subscribe();
+        let started = Local::now().to_rfc3339();
+        let start_time = Instant::now();
 
-        // Delete the metadata file
-        let path = self
+        // Anything written from here on is undone if the install fails part way through
+        let install_path = self
             .install_path
+            .clone()
+            .log_expect("Install directory not initialised");
+        let staging = self.get_staging_path(&install_path);
+        let backups = self
+            .config
             .as_ref()
-            .log_expect("No install path specified");
-
-        remove_file(path.join("metadata.json"))
-            .map_err(|x| format!("Failed to delete metadata: {:?}", x))?;
+            .map(|x| x.backups.clone())
+            .unwrap_or_default();
+        Transaction::recover(&staging);
+        self.transaction.begin(staging, backups)?;
+        for file in &[
+            "metadata.json",
+            integrity::SIGNATURE_FILE,
+            CONFIG_CACHE_FILE,
+        ] {
+            let file = install_path.join(file);
+            self.transaction.created(&atomic::backup_path(&file));
+            self.transaction.created(&file);
+        }
 
-        // Logging will have to be done later
-        self.burn_after_exit = true;
+        let result = tree
+            .run(self, declare_messenger_callback!(messages, eta))
+            .map(|_x| ());
 
-        Ok(())
-    }
+        self.database.phase_rates = eta.into_inner().finish();
+        self.resolved.clear();
+        self.confirmed_download = 0;
+        self.clean_downloads(&install_path);
+        bandwidth::flush();
 
-    /// Verifies that the config has all requirements met (no need to update the
-    /// updater, for example). This will terminate if this is the case after applying
-    /// the correct actions.
-    pub fn update_updater(&mut self, messages: &Sender<InstallMessage>) -> Result<(), String> {
-        let tool = self
-            .config
-            .as_ref()
-            .log_expect("Config should exist by now")
-            .new_tool
-            .as_ref()
-            .log_expect("Frontend asked for updater update when one doesn't exist");
+        match result {
+            Ok(_) => {
+                self.transaction.commit();
 
-        let mut downloaded = 0;
-        let mut data_storage: Vec<u8> = Vec::new();
+                if let Some(ref found) = adopting {
+                    migration::remove_uninstall_entry(self, found);
+                }
 
-        http::stream_file(tool, None, |data, size| {
-            {
-                data_storage.extend_from_slice(&data);
+                // Now that there is an installation, further operations can be queued for it
+                queue::load(&install_path);
             }
+            Err(ref v) => {
+                error!("Install failed, rolling back: {}", v);
+                if let Err(v) = messages.send(InstallMessage::Status(
+                    "Installation failed, undoing changes...".to_string(),
+                    1.0,
+                    None,
+                    "rollback",
+                )) {
+                    error!("Failed to submit queue message: {:?}", v);
+                }
 
-            downloaded += data.len();
+                self.transaction.rollback();
 
-            let percentage = if size == 0 {
-                0.0
-            } else {
-                (downloaded as f64) / (size as f64)
-            };
+                // Recorded to the log this install was going to use
+                audit::record(self, AuditAction::RolledBack { error: v.clone() });
 
-            // Pretty print data volumes
-            let pretty_current = match NumberPrefix::decimal(downloaded as f64) {
-                Standalone(bytes) => format!("{} bytes", bytes),
-                Prefixed(prefix, n) => format!("{:.0} {}B", n, prefix),
-            };
-            let pretty_total = match NumberPrefix::decimal(size as f64) {
-                Standalone(bytes) => format!("{} bytes", bytes),
-                Prefixed(prefix, n) => format!("{:.0} {}B", n, prefix),
-            };
+                // Nothing the failed install changed is kept, other than how long it took
+                let phase_rates = self.database.phase_rates.clone();
+                self.database = previous_database.clone();
+                self.database.phase_rates = phase_rates;
+            }
+        }
 
-            if let Err(v) = messages.send(InstallMessage::Status(
-                format!(
-                    "Downloading self-update ({} of {})...",
-                    pretty_current, pretty_total
-                ),
-                percentage as _,
-            )) {
-                error!("Failed to submit queue message: {:?}", v);
+        if let Some(session) = self.restart_session.take() {
+            info!("Restarting applications closed during the install");
+            if let Err(v) = session.restart() {
+                warn!("{}", v);
             }
-        })?;
+        }
 
-        info!("Launching new updater...");
+        let event = if result.is_ok() {
+            WebhookEvent::InstallSucceeded
+        } else {
+            WebhookEvent::InstallFailed
+        };
+        self.notify_webhooks(&WebhookPayload {
+            error: result.clone().err(),
+            ..WebhookPayload::new(event, &self.base_attributes.name, &items)
+        });
 
-        // Save to file in current dir
-        let current_exe = env::current_exe().log_expect("Current executable could not be found");
-        let path = current_exe
-            .parent()
-            .log_expect("Parent directory of executable could not be found");
+        if let Some(ref config) = self.config {
+            for location in &config.data_locations {
+                match locations::resolve_data_location(location) {
+                    Ok(path) => {
+                        self.database
+                            .data_locations
+                            .insert(location.name.clone(), path.to_string_lossy().to_string());
+                    }
+                    Err(v) => warn!("Unable to resolve data location {:?}: {}", location.name, v),
+                }
+            }
+        }
 
-        let platform_extension = if cfg!(windows) {
-            "maintenancetool_new.exe"
+        // Record what ended up changing, regardless of the outcome
+        let operation = if fresh_install {
+            HistoryOperation::Install
         } else {
-            "maintenancetool_new"
+            HistoryOperation::Modify
         };
+        self.database.record(HistoryEntry::new(operation, &result));
 
-        let new_app = path.join(platform_extension);
+        let mut updates = Vec::new();
+        for name in items.iter().chain(uninstall_items.iter()) {
+            let from_version = previous_database.installed_version(name);
+            let to_version = self.database.installed_version(name);
 
-        let mut file_metadata = OpenOptions::new();
-        file_metadata.write(true).create(true);
-
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::OpenOptionsExt;
+            if from_version == to_version {
+                continue;
+            }
 
-            file_metadata.mode(0o770);
-        }
+            let operation = match (&from_version, &to_version) {
+                (None, _) => HistoryOperation::Install,
+                (_, None) => HistoryOperation::Uninstall,
+                _ => HistoryOperation::Update,
+            };
 
-        {
-            let mut new_app_file = match file_metadata.open(&new_app) {
-                Ok(v) => v,
-                Err(v) => return Err(format!("Unable to open installer binary: {:?}", v)),
+            let entry = HistoryEntry {
+                package: Some(name.clone()),
+                from_version,
+                to_version,
+                ..HistoryEntry::new(operation, &Ok(()))
             };
 
-            if let Err(v) = copy(&mut Cursor::new(data_storage), &mut new_app_file) {
-                return Err(format!("Unable to copy installer binary: {:?}", v));
+            if let HistoryOperation::Update = entry.operation {
+                updates.push(entry.clone());
             }
+
+            self.database.record(entry);
         }
 
-        // Save current command line arguments
-        let args_file = path.join("args.json");
-        let args: Vec<String> = env::args_os()
-            .map(|x| {
-                x.to_str()
-                    .log_expect("Unable to convert argument to String")
-                    .to_string()
-            })
+        if !updates.is_empty() {
+            self.notify_webhooks(&WebhookPayload {
+                updates,
+                ..WebhookPayload::new(
+                    WebhookEvent::UpdateApplied,
+                    &self.base_attributes.name,
+                    &items,
+                )
+            });
+        }
+
+        let mut install_report = InstallReport::new(
+            &self.base_attributes.name,
+            &install_path,
+            started,
+            start_time.elapsed().as_secs(),
+            &result,
+            &self.database,
+        );
+        install_report.removed = uninstall_items
+            .into_iter()
+            .filter(|x| self.database.installed_version(x).is_none())
             .collect();
+        install_report.add_warnings(log_events.try_iter());
 
-        {
-            let new_app_file = match File::create(&args_file) {
-                Ok(v) => v,
-                Err(v) => return Err(format!("Unable to open args file: {:?}", v)),
-            };
+        // Failed fresh installs shouldn't leave a database (or report) behind
+        if result.is_ok() || self.preexisting_install {
+            if let Err(v) = self.save_database() {
+                error!("Failed to save installation history: {:?}", v);
+            }
 
-            serde_json::to_writer(new_app_file, &args).log_expect("Unable to write args");
+            if let Err(v) = install_report.save(&install_path) {
+                error!("Failed to save install report: {}", v);
+            }
         }
 
-        let current_exe = env::current_exe().log_expect("Current executable could not be found");
-
-        // Launch this new process
-        Command::new(new_app)
-            .arg("--swap")
-            .arg(current_exe)
-            .spawn()
-            .log_expect("Unable to start child process");
+        self.report = Some(install_report);
 
-        exit(0);
+        result
     }
 
-    /// Saves the applications database.
-    pub fn save_database(&self) -> Result<(), String> {
-        // We have to have a install path for us to be able to do anything
-        let path = match self.install_path.clone() {
-            Some(v) => v,
-            None => return Err("No install directory for installer".to_string()),
-        };
+    /// Sends an install event to any configured webhooks.
+    fn notify_webhooks(&self, payload: &WebhookPayload) {
+        if policy::machine().disable_telemetry {
+            return;
+        }
 
-        let metadata_path = path.join("metadata.json");
-        let metadata_file = match File::create(metadata_path) {
-            Ok(v) => v,
-            Err(v) => return Err(format!("Unable to open file handle: {:?}", v)),
+        if let Some(ref config) = self.config {
+            webhooks::notify(&config.webhooks, payload);
+        }
+    }
+
+    /// Returns the directory running operations keep their working files in. Staging
+    /// directories elsewhere are shared, so each product gets its own directory within.
+    fn get_staging_root(&self, install_path: &Path) -> PathBuf {
+        match self.get_staging_dir() {
+            Some(dir) => dir.join(&self.base_attributes.name),
+            None => install_path.to_owned(),
+        }
+    }
+
+    /// Returns the staging directory given on the command line or configured, if any.
+    fn get_staging_dir(&self) -> Option<PathBuf> {
+        let configured = self
+            .config
+            .as_ref()
+            .and_then(|x| x.limits.staging_dir.as_ref())
+            .map(PathBuf::from);
+
+        self.staging_dir.clone().or(configured)
+    }
+
+    /// Returns where files are prefetched to (see `prefetch`), which is before there is an
+    /// install directory to stage them in.
+    pub fn get_prefetch_dir(&self) -> PathBuf {
+        match self.get_staging_dir() {
+            Some(dir) => dir.join(&self.base_attributes.name).join(PREFETCH_DIR),
+            None => env::temp_dir()
+                .join("liftinstall-prefetch")
+                .join(&self.base_attributes.name),
+        }
+    }
+
+    /// Returns where files replaced by an install are stashed until it completes.
+    fn get_staging_path(&self, install_path: &Path) -> PathBuf {
+        self.get_staging_root(install_path).join(TRANSACTION_DIR)
+    }
+
+    /// Returns where a package is downloaded to before it is extracted.
+    pub fn get_download_path(&self, name: &str) -> Result<PathBuf, String> {
+        let install_path = self
+            .install_path
+            .as_ref()
+            .ok_or("No install path specified")?;
+
+        // Package names can contain anything
+        Ok(self
+            .get_staging_root(install_path)
+            .join(DOWNLOADS_DIR)
+            .join(format!("{}.part", &sha256_hex(name.as_bytes())[..16])))
+    }
+
+    /// Removes whatever the running operation downloaded but didn't install.
+    fn clean_downloads(&mut self, install_path: &Path) {
+        self.downloaded.clear();
+
+        let path = self.get_staging_root(install_path).join(DOWNLOADS_DIR);
+        if path.exists() {
+            if let Err(v) = remove_dir_all(&path) {
+                warn!("Unable to remove downloads in {:?}: {:?}", path, v);
+            }
+        }
+    }
+
+    /// Returns the release channel a package is installed from, if it has any. A channel
+    /// set by the machine's policy or the environment is used instead of the chosen one,
+    /// where the package has it.
+    pub fn get_channel<'a>(&self, package: &'a PackageDescription) -> Option<&'a ReleaseChannel> {
+        let environment = match (&self.config, &self.database.environment) {
+            (Some(config), Some(name)) => environments::find(config, name)
+                .ok()
+                .and_then(|x| x.channel.clone()),
+            _ => None,
+        };
+
+        let forced = policy::machine()
+            .channel
+            .filter(|x| package.channels.iter().any(|channel| channel.name == *x))
+            .or_else(|| {
+                environment.filter(|x| package.channels.iter().any(|channel| channel.name == *x))
+            });
+        let chosen = forced.or_else(|| self.database.channels.get(&package.name).cloned());
+        package.get_channel(chosen.as_ref().map(|x| x.as_str()))
+    }
+
+    /// Moves an installed package onto another release channel, reinstalling it from
+    /// that channel's latest release (which may be older than what is installed).
+    pub fn switch_channel(
+        &mut self,
+        name: &str,
+        channel: &str,
+        messages: &Sender<InstallMessage>,
+    ) -> Result<(), String> {
+        if policy::machine().channel.is_some() {
+            return Err(strings::tr("errors.channel_locked"));
+        }
+
+        let package = self
+            .config
+            .as_ref()
+            .and_then(|x| x.packages.iter().find(|x| x.name == name))
+            .ok_or_else(|| format!("Package {:?} could not be found.", name))?;
+
+        if !package.channels.iter().any(|x| x.name == channel) {
+            return Err(format!("Package {:?} has no {:?} channel.", name, channel));
+        }
+
+        info!(
+            "Framework: Switching {:?} to the {:?} channel",
+            name, channel
+        );
+
+        let previous = self
+            .database
+            .channels
+            .insert(name.to_string(), channel.to_string());
+
+        // Channels of packages which aren't installed yet just apply when they are
+        if self.database.installed_version(name).is_none() {
+            return self.save_database();
+        }
+
+        let items = self
+            .database
+            .packages
+            .iter()
+            .map(|x| x.name.clone())
+            .collect();
+
+        let result = self.install(items, messages, false, false);
+
+        if result.is_err() {
+            match previous {
+                Some(v) => self.database.channels.insert(name.to_string(), v),
+                None => self.database.channels.remove(name),
+            };
+
+            if let Err(v) = self.save_database() {
+                error!("Failed to restore previous channel: {:?}", v);
+            }
+        }
+
+        result
+    }
+
+    /// Downloads and extracts packages into the install directory, without creating
+    /// shortcuts, the maintenance tool or any installation metadata.
+    /// items: Array of named packages to be extracted
+    /// messages: Channel used to send progress messages
+    pub fn extract(
+        &mut self,
+        mut items: Vec<String>,
+        messages: &Sender<InstallMessage>,
+    ) -> Result<(), String> {
+        if let Some(ref config) = self.config {
+            items = config.resolve_packages(&items)?;
+        }
+
+        info!(
+            "Framework: Extracting {:?} to {:?}",
+            items,
+            self.install_path
+                .clone()
+                .log_expect("Install directory not initialised")
+        );
+
+        let task = Box::new(InstallTask {
+            items,
+            uninstall_items: Vec::new(),
+            fresh_install: true,
+            adopting: false,
+            create_desktop_shortcuts: false,
+            extract_only: true,
+            parallel_downloads: self
+                .config
+                .as_ref()
+                .map_or(false, |x| x.limits.download_parallelism > 1),
+        });
+
+        let mut tree = DependencyTree::build(task);
+
+        info!("Dependency tree:\n{}", tree);
+
+        let eta = RefCell::new(EtaEstimator::new(self.database.phase_rates.clone()));
+
+        // Cancelling (or failing) part way through leaves the directory as it was
+        let install_path = self
+            .install_path
+            .clone()
+            .log_expect("Install directory not initialised");
+        let staging = self.get_staging_path(&install_path);
+        let backups = self
+            .config
+            .as_ref()
+            .map(|x| x.backups.clone())
+            .unwrap_or_default();
+        Transaction::recover(&staging);
+        self.transaction.begin(staging, backups)?;
+
+        let result = tree
+            .run(self, declare_messenger_callback!(messages, eta))
+            .map(|_x| ());
+
+        self.resolved.clear();
+        self.clean_downloads(&install_path);
+
+        match result {
+            Ok(_) => self.transaction.commit(),
+            Err(ref v) => {
+                error!("Extraction failed, rolling back: {}", v);
+                self.transaction.rollback();
+            }
+        }
+
+        result
+    }
+
+    /// Removes individual packages, leaving the rest of the installation in place.
+    /// items: Array of named packages to be removed
+    /// messages: Channel used to send progress messages
+    pub fn uninstall_packages(
+        &mut self,
+        items: Vec<String>,
+        messages: &Sender<InstallMessage>,
+    ) -> Result<(), String> {
+        info!("Framework: Removing {:?}", items);
+
+        policy::check(self)?;
+
+        for name in &items {
+            if self.database.installed_version(name).is_none() {
+                return Err(format!("Package {:?} is not installed.", name));
+            }
+        }
+
+        let remaining: Vec<String> = self
+            .database
+            .packages
+            .iter()
+            .map(|x| x.name.clone())
+            .filter(|x| !items.contains(x))
+            .collect();
+
+        if remaining.is_empty() {
+            return Err("Removing every package would uninstall the application.".to_string());
+        }
+
+        // Packages which are still installed mustn't lose what they depend on. What was
+        // recorded at install time is used for packages no longer in the configuration.
+        for package in self
+            .database
+            .packages
+            .iter()
+            .filter(|x| remaining.contains(&x.name))
+        {
+            let requires = self
+                .config
+                .as_ref()
+                .and_then(|x| x.packages.iter().find(|x| x.name == package.name))
+                .map_or(&package.requires, |x| &x.requires);
+
+            if let Some(required) = requires.iter().find(|x| items.contains(x)) {
+                return Err(format!(
+                    "Package {:?} is required by {:?}, which is still installed.",
+                    required, package.name
+                ));
+            }
+        }
+
+        let previous_database = self.database.clone();
+
+        let task = Box::new(UninstallTask {
+            items: items.clone(),
+        });
+
+        let mut tree = DependencyTree::build(task);
+
+        info!("Dependency tree:\n{}", tree);
+
+        let eta = RefCell::new(EtaEstimator::new(self.database.phase_rates.clone()));
+
+        let result = tree
+            .run(self, declare_messenger_callback!(messages, eta))
+            .map(|_x| ());
+
+        self.database.phase_rates = eta.into_inner().finish();
+
+        self.database
+            .record(HistoryEntry::new(HistoryOperation::Modify, &result));
+
+        for name in &items {
+            let from_version = previous_database.installed_version(name);
+            if from_version.is_none() || self.database.installed_version(name).is_some() {
+                continue;
+            }
+
+            self.database.record(HistoryEntry {
+                package: Some(name.clone()),
+                from_version,
+                ..HistoryEntry::new(HistoryOperation::Uninstall, &Ok(()))
+            });
+        }
+
+        if let Err(v) = self.save_database() {
+            error!("Failed to save installation history: {:?}", v);
+        }
+
+        result
+    }
+
+    /// Repairs damaged files, and recreates any shortcuts recorded in the installation
+    /// metadata which have since gone missing, such as when the user has deleted them.
+    /// messages: Channel used to send progress messages
+    pub fn repair(&mut self, messages: &Sender<InstallMessage>) -> Result<(), String> {
+        // Damaged files are fetched again by themselves where possible
+        if self.config.is_some() {
+            let mut tree = DependencyTree::build(Box::new(RepairFilesTask {}));
+            let eta = RefCell::new(EtaEstimator::new(self.database.phase_rates.clone()));
+
+            tree.run(self, declare_messenger_callback!(messages, eta))?;
+        } else {
+            warn!("Framework: Not checking files, as the config hasn't been loaded");
+        }
+
+        // Packages which failed their smoke tests, or have damaged files which couldn't be
+        // fetched by themselves, are installed again. Everything else is up to date, so is
+        // left as it is.
+        let suspect: Vec<String> = self
+            .database
+            .packages
+            .iter()
+            .filter(|x| x.suspect.is_some())
+            .map(|x| x.name.clone())
+            .collect();
+
+        if !suspect.is_empty() {
+            info!(
+                "Framework: Reinstalling {:?}, which failed smoke tests",
+                suspect
+            );
+
+            let items = self
+                .database
+                .packages
+                .iter()
+                .map(|x| x.name.clone())
+                .collect();
+            self.install(items, messages, false, false)?;
+        }
+
+        self.recreate_shortcuts(messages, false, true)
+    }
+
+    /// Runs a queued job (see `queue`), against the packages installed at the time.
+    /// job: The job to run
+    /// messages: Channel used to send progress messages
+    pub fn run_job(&mut self, job: &Job, messages: &Sender<InstallMessage>) -> Result<(), String> {
+        if self.config.is_none() {
+            self.fetch_config()?;
+        }
+
+        // Installing replaces the set of installed packages, so has to include them all
+        let mut items: Vec<String> = self
+            .database
+            .packages
+            .iter()
+            .map(|x| x.name.clone())
+            .collect();
+
+        match job.action {
+            JobAction::Install => {
+                for name in &job.packages {
+                    if !items.contains(name) {
+                        items.push(name.clone());
+                    }
+                }
+
+                self.install(items, messages, false, false)
+            }
+            JobAction::Update => self.install(items, messages, false, false),
+            JobAction::Remove => self.uninstall_packages(job.packages.clone(), messages),
+        }
+    }
+
+    /// Updates the installation metadata and shortcuts to point to where the user has moved
+    /// the installation to, instead of where it was installed.
+    /// messages: Channel used to send progress messages
+    pub fn adopt_moved_install(&mut self, messages: &Sender<InstallMessage>) -> Result<(), String> {
+        let old_path = self
+            .moved_from()
+            .ok_or("The installation has not been moved.")?;
+        let new_path = self
+            .install_path
+            .clone()
+            .log_expect("No install path specified");
+
+        info!(
+            "Framework: Adopting installation moved from {:?} to {:?}",
+            old_path, new_path
+        );
+
+        let rebase = |x: &str| match Path::new(x).strip_prefix(&old_path) {
+            Ok(v) => new_path.join(v).to_string_lossy().to_string(),
+            Err(_) => x.to_string(),
+        };
+
+        for package in &mut self.database.packages {
+            package.root = package.root.as_ref().map(|x| rebase(x));
+
+            for file in &mut package.overwritten {
+                file.backup = rebase(&file.backup);
+            }
+        }
+
+        // Links inside the installation moved with it
+        for link in &mut self.database.links {
+            link.path = rebase(&link.path);
+            link.target = rebase(&link.target);
+        }
+
+        self.database.install_path = Some(new_path.to_string_lossy().to_string());
+        integrity::move_keys(&old_path, &new_path);
+
+        // Shortcuts still point to the old maintenance tool
+        self.recreate_shortcuts(messages, true, false)?;
+
+        // As does the entry in "Apps & Features", which also says where the installation is
+        let mut tree = DependencyTree::build(Box::new(RegisterUninstallerTask {}));
+        let eta = RefCell::new(EtaEstimator::new(self.database.phase_rates.clone()));
+
+        tree.run(self, declare_messenger_callback!(messages, eta))
+            .map(|_x| ())
+    }
+
+    /// Recreates shortcuts which are missing, or every shortcut if `everything` is set.
+    /// If `integrations` is set, directory links, system users and the entry in "Apps &
+    /// Features" are recreated too.
+    fn recreate_shortcuts(
+        &mut self,
+        messages: &Sender<InstallMessage>,
+        everything: bool,
+        integrations: bool,
+    ) -> Result<(), String> {
+        let needs_recreating = |x: &String| !x.is_empty() && (everything || !Path::new(x).exists());
+
+        let mut shortcuts = Vec::new();
+        if let Some(ref config) = self.config {
+            for package in &mut self.database.packages {
+                if !package.shortcuts.iter().any(&needs_recreating) {
+                    continue;
+                }
+
+                // Each configured shortcut is created once more on the desktop, if asked for
+                let configured = config
+                    .packages
+                    .iter()
+                    .find(|x| x.name == package.name)
+                    .map(|x| x.shortcuts.len())
+                    .unwrap_or(0);
+                let desktop_shortcuts = configured > 0 && package.shortcuts.len() > configured;
+
+                info!("Framework: Recreating shortcuts of {:?}", package.name);
+                package.shortcuts.clear();
+                shortcuts.push((package.name.clone(), desktop_shortcuts));
+            }
+        }
+
+        let global_shortcut = self.database.shortcuts.iter().any(&needs_recreating);
+        if global_shortcut {
+            info!("Framework: Recreating the maintenance tool shortcut");
+            self.database.shortcuts.retain(|x| !needs_recreating(x));
+        }
+
+        // These need the configuration, and leave whatever still exists as it is
+        let integrated = match self.config {
+            Some(ref config) if integrations => self
+                .database
+                .packages
+                .iter()
+                .filter(|x| config.packages.iter().any(|package| package.name == x.name))
+                .map(|x| x.name.clone())
+                .collect(),
+            _ => Vec::new(),
         };
 
-        match serde_json::to_writer(metadata_file, &self.database) {
+        let task = Box::new(RepairTask {
+            shortcuts,
+            global_shortcut,
+            integrations: integrated,
+            register_uninstaller: integrations,
+        });
+
+        let mut tree = DependencyTree::build(task);
+
+        info!("Dependency tree:\n{}", tree);
+
+        let eta = RefCell::new(EtaEstimator::new(self.database.phase_rates.clone()));
+
+        tree.run(self, declare_messenger_callback!(messages, eta))
+            .map(|_x| ())
+    }
+
+    /// Sends a request for everything to be uninstalled.
+    pub fn uninstall(&mut self, messages: &Sender<InstallMessage>) -> Result<(), String> {
+        policy::check(self)?;
+
+        let items: Vec<String> = self
+            .database
+            .packages
+            .iter()
+            .map(|x| x.name.clone())
+            .collect();
+
+        let task = Box::new(UninstallTask { items });
+
+        let mut tree = DependencyTree::build(task);
+
+        info!("Dependency tree:\n{}", tree);
+
+        let eta = RefCell::new(EtaEstimator::new(self.database.phase_rates.clone()));
+
+        let result = tree
+            .run(self, declare_messenger_callback!(messages, eta))
+            .map(|_x| ());
+
+        if result.is_err() {
+            // The database survives a failed uninstall, so keep a record of this
+            self.database
+                .record(HistoryEntry::new(HistoryOperation::Uninstall, &result));
+
+            if let Err(v) = self.save_database() {
+                error!("Failed to save installation history: {:?}", v);
+            }
+        }
+
+        result?;
+
+        // Uninstall shortcuts
+        let task = Box::new(UninstallGlobalShortcutsTask {});
+
+        let mut tree = DependencyTree::build(task);
+
+        tree.run(self, declare_messenger_callback!(messages, eta))
+            .map(|_x| ())?;
+
+        // Delete the metadata file
+        let path = self
+            .install_path
+            .as_ref()
+            .log_expect("No install path specified");
+
+        remove_file(path.join("metadata.json"))
+            .map_err(|x| format!("Failed to delete metadata: {:?}", x))?;
+
+        let backup_path = atomic::backup_path(&path.join("metadata.json"));
+        if backup_path.exists() {
+            remove_file(backup_path)
+                .map_err(|x| format!("Failed to delete metadata backup: {:?}", x))?;
+        }
+
+        let cache_path = path.join(CONFIG_CACHE_FILE);
+        for cache_path in &[atomic::backup_path(&cache_path), cache_path] {
+            if cache_path.exists() {
+                remove_file(cache_path)
+                    .map_err(|x| format!("Failed to delete cached configuration: {:?}", x))?;
+            }
+        }
+
+        report::remove(path)?;
+        integrity::remove(path);
+        queue::remove_saved(path);
+
+        // Logging will have to be done later
+        self.burn_after_exit = true;
+
+        Ok(())
+    }
+
+    /// Verifies that the config has all requirements met (no need to update the
+    /// updater, for example). This will terminate if this is the case after applying
+    /// the correct actions.
+    pub fn update_updater(&mut self, messages: &Sender<InstallMessage>) -> Result<(), String> {
+        let tool = self
+            .config
+            .as_ref()
+            .log_expect("Config should exist by now")
+            .new_tool
+            .clone()
+            .log_expect("Frontend asked for updater update when one doesn't exist");
+
+        // Builds found through `self_update` carry what is needed to verify them
+        let (file, verification) = match self.tool_update {
+            Some((_, ref file)) if file.url == tool => (
+                file.clone(),
+                self.config
+                    .as_ref()
+                    .and_then(|x| x.self_update.as_ref())
+                    .and_then(|x| x.verification.clone()),
+            ),
+            _ => (
+                ReleaseFile {
+                    name: "maintenancetool".to_string(),
+                    url: tool,
+                    requires_authorization: false,
+                    parts: Vec::new(),
+                    chunk_index: None,
+                    checksums: None,
+                    sha1: None,
+                    signature: None,
+                    size: None,
+                    content_type: None,
+                    torrent: None,
+                },
+                None,
+            ),
+        };
+
+        let mut tree = DependencyTree::build(Box::new(UpdateToolTask { file, verification }));
+        let eta = RefCell::new(EtaEstimator::new(self.database.phase_rates.clone()));
+
+        tree.run(self, declare_messenger_callback!(messages, eta))
+            .map(|_x| ())?;
+
+        info!("Launching new updater...");
+
+        let current_exe = env::current_exe().log_expect("Current executable could not be found");
+        let path = current_exe
+            .parent()
+            .log_expect("Parent directory of executable could not be found");
+        let new_app = self_update::staged_tool_path(path);
+
+        // Save current command line arguments
+        let args_file = path.join("args.json");
+        let args: Vec<String> = env::args_os()
+            .map(|x| {
+                x.to_str()
+                    .log_expect("Unable to convert argument to String")
+                    .to_string()
+            })
+            .collect();
+
+        {
+            let new_app_file = match File::create(&args_file) {
+                Ok(v) => v,
+                Err(v) => return Err(format!("Unable to open args file: {:?}", v)),
+            };
+
+            serde_json::to_writer(new_app_file, &args).log_expect("Unable to write args");
+        }
+
+        // Launch this new process
+        Command::new(new_app)
+            .arg("--swap")
+            .arg(current_exe)
+            .spawn()
+            .log_expect("Unable to start child process");
+
+        exit(0);
+    }
+
+    /// Saves the applications database.
+    pub fn save_database(&self) -> Result<(), String> {
+        // We have to have a install path for us to be able to do anything
+        let path = match self.install_path.clone() {
+            Some(v) => v,
+            None => return Err("No install directory for installer".to_string()),
+        };
+
+        let metadata = match serde_json::to_vec(&self.database) {
             Ok(v) => v,
             Err(v) => return Err(format!("Unable to write to file: {:?}", v)),
         };
 
+        // Signing metadata which was tampered with, or whose signature can't be checked,
+        // would make it look legitimate. This is signed first, as the previous signature is
+        // kept in case a crash leaves the previous metadata in place.
+        if self.metadata_status != MetadataStatus::Tampered
+            && self.metadata_status != MetadataStatus::KeyMissing
+        {
+            if let Err(v) = integrity::sign(&path, &metadata) {
+                warn!("Unable to sign installation metadata: {}", v);
+            }
+        }
+
+        if let Err(v) = atomic::write(&path.join("metadata.json"), &metadata) {
+            return Err(format!("Unable to write to file: {:?}", v));
+        }
+
+        // Not being able to cache the configuration only matters when offline later
+        if let Some(ref text) = self.raw_config {
+            if let Err(v) = atomic::write(&path.join(CONFIG_CACHE_FILE), text.as_bytes()) {
+                warn!("Unable to cache configuration: {:?}", v);
+            }
+        }
+
         Ok(())
     }
 
+    /// Reads the configuration cached by a previous run, if there is one.
+    pub fn read_cached_config(&self) -> Option<String> {
+        let path = self.install_path.as_ref()?.join(CONFIG_CACHE_FILE);
+        if !path.exists() {
+            return None;
+        }
+
+        match read_to_string(&path) {
+            Ok(v) => Some(v),
+            Err(v) => {
+                warn!("Unable to read cached configuration {:?}: {:?}", path, v);
+                None
+            }
+        }
+    }
+
+    /// Keeps a task's failure for diagnostics, forgetting the oldest once there are too
+    /// many.
+    pub fn record_task_error(&mut self, task: &str, error: &str) {
+        if self.task_errors.len() >= MAX_TASK_ERRORS {
+            self.task_errors.pop_front();
+        }
+
+        self.task_errors.push_back(TaskError {
+            timestamp: Local::now().to_rfc3339(),
+            task: task.to_string(),
+            error: error.to_string(),
+        });
+    }
+
+    /// Returns the directory a package is (or will be) installed into.
+    pub fn get_package_root(&self, name: &str) -> Result<PathBuf, String> {
+        let install_path = self
+            .install_path
+            .clone()
+            .ok_or("No install path specified")?;
+
+        self.get_package_root_in(name, &install_path)
+    }
+
+    /// Returns the directory a package would be installed to, if the installation was in
+    /// the specified directory.
+    pub fn get_package_root_in(&self, name: &str, install_path: &Path) -> Result<PathBuf, String> {
+        if let Some(package) = self.database.packages.iter().find(|x| x.name == name) {
+            return Ok(package.get_root(install_path));
+        }
+
+        let config = match self.config {
+            Some(ref v) => v,
+            None => return Ok(install_path.to_owned()),
+        };
+
+        let install_root = config
+            .packages
+            .iter()
+            .find(|x| x.name == name)
+            .and_then(|x| x.install_root.clone());
+
+        match install_root {
+            Some(spec) => locations::resolve(&spec, install_path, &config.data_locations),
+            None => Ok(install_path.to_owned()),
+        }
+    }
+
+    /// Downloads the configuration for this release, as the frontend otherwise would via
+    /// /api/config.
+    pub fn fetch_config(&mut self) -> Result<(), String> {
+        if let Some(text) = bundle::config_text() {
+            info!("Using the offline bundle's configuration");
+            return self.load_config(&text);
+        }
+
+        if let Some(path) = config_watch::local_config_path() {
+            info!("Using local configuration {:?}", path);
+            let text = read_to_string(&path)
+                .map_err(|x| format!("Failed to read configuration: {:?}", x))?;
+            return self.load_config(&text);
+        }
+
+        let text = match self.download_config() {
+            Ok(v) => v,
+            Err(v) => match self.read_cached_config() {
+                Some(text) => {
+                    warn!("{} - using the cached configuration instead", v);
+                    text
+                }
+                None => return Err(v),
+            },
+        };
+
+        self.load_config(&text)
+    }
+
+    /// Checks if any installed package, or the maintenance tool itself, has a newer release
+    /// than is installed. The configuration needs to have been loaded.
+    pub fn has_updates(&self) -> Result<bool, String> {
+        if self.tool_update.is_some() {
+            return Ok(true);
+        }
+
+        let config = self
+            .config
+            .as_ref()
+            .ok_or_else(|| "No configuration has been loaded".to_string())?;
+
+        for package in &config.packages {
+            let installed = match self.database.installed_version(&package.name) {
+                Some(v) => v,
+                None => continue,
+            };
+
+            let (latest, _) = find_latest(package, self, &|_: &TaskMessage| {})?;
+            if latest > installed {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Downloads the configuration from the target URL.
+    pub fn download_config(&self) -> Result<String, String> {
+        let url = self.base_attributes.target_url.clone();
+        info!("Downloading configuration from {:?}...", url);
+
+        let mut response = http::build_client()?
+            .get(&url)
+            .send()
+            .map_err(|x| format!("Failed to download configuration: {:?}", x))?;
+
+        if !response.status().is_success() {
+            return Err(format!(
+                "Bad status code while downloading configuration: {:?}",
+                response.status()
+            ));
+        }
+
+        let text = response
+            .text()
+            .map_err(|x| format!("Failed to read configuration: {:?}", x))?;
+
+        // Make sure this parses, so a broken download doesn't replace the cache
+        Config::from_toml_str(&text)
+            .map_err(|x| format!("Failed to parse configuration: {:?}", x))?;
+        info!("Configuration file downloaded successfully.");
+
+        Ok(text)
+    }
+
+    /// Returns the languages to install: those given on the command line, otherwise those
+    /// recorded by the installation, otherwise the language being shown.
+    pub fn get_languages(&self) -> Vec<String> {
+        if !self.languages.is_empty() {
+            self.languages.clone()
+        } else if !self.database.languages.is_empty() {
+            self.database.languages.clone()
+        } else {
+            vec![strings::get_language()]
+        }
+    }
+
+    /// Parses and applies a configuration.
+    pub fn load_config(&mut self, text: &str) -> Result<(), String> {
+        let mut config = Config::from_toml_str(text)
+            .map_err(|x| format!("Failed to parse configuration: {:?}", x))?;
+
+        // Environments may have been removed from the configuration since being installed
+        // from, and are only applied if they apply completely
+        if let Some(ref name) = self.database.environment {
+            let mut applied = config.clone();
+            match environments::apply(&mut applied, Some(name)) {
+                Ok(_) => config = applied,
+                Err(v) => warn!("{} - using the default environment instead", v),
+            }
+        }
+
+        bundle::apply(&mut config, &self.database.channels)?;
+        localization::apply(&mut config, &self.get_languages());
+        cpu::apply(&mut config);
+
+        // The UI starts with the defaults, so an elevated copy starts with what was chosen
+        if !self.preexisting_install {
+            if let Some(ref packages) = self.handed_over.packages {
+                for package in &mut config.packages {
+                    package.default = Some(packages.contains(&package.name));
+                }
+            }
+            if let Some(desktop_shortcut) = self.handed_over.desktop_shortcut {
+                config.shortcuts.desktop_by_default = desktop_shortcut;
+            }
+        }
+
+        dns::set_doh_url(config.dns_over_https.clone());
+        http::set_pac_url(config.proxy_pac_url.clone());
+        http::set_configured_proxy(config.proxy.clone());
+
+        // An explicit new_tool takes priority over looking for one
+        let tool_update = match (&config.new_tool, &config.self_update) {
+            (None, Some(update)) => {
+                self_update::find_update_once(update, &config.url_rewrites, || {
+                    if let Err(v) =
+                        source_auth::authorize_silently("self_update", &update.source, Some(self))
+                    {
+                        warn!("Unable to authorise self-update source: {}", v);
+                    }
+                })
+            }
+            _ => None,
+        };
+        self.tool_update = tool_update;
+
+        if let Some((_, ref file)) = self.tool_update {
+            config.new_tool = Some(file.url.clone());
+        }
+
+        self.config = Some(config);
+
+        // Tokens in the configuration must never end up in the cache or diagnostics
+        self.raw_config = match source_auth::strip_tokens(text) {
+            Ok(v) => Some(v),
+            Err(v) => {
+                warn!("Not keeping the configuration's text: {}", v);
+                None
+            }
+        };
+
+        Ok(())
+    }
+
+    /// Returns the arguments an elevated copy of the installer is started with to carry on
+    /// where this one is, built from what this session has been told rather than how it
+    /// was started (which may not parse again once more arguments are added).
+    pub fn elevation_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+
+        if self.database.config_url.is_some() {
+            args.push("--config-url".to_string());
+            args.push(self.base_attributes.target_url.clone());
+        }
+        if let Some(ref environment) = self.database.environment {
+            args.push("--environment".to_string());
+            args.push(environment.clone());
+        }
+        if let Some(ref path) = bundle::source_path() {
+            args.push("--bundle".to_string());
+            args.push(path.to_string_lossy().to_string());
+        }
+        if let Some(ref dir) = self.staging_dir {
+            args.push("--staging-dir".to_string());
+            args.push(dir.to_string_lossy().to_string());
+        }
+        if !self.languages.is_empty() {
+            args.push("--languages".to_string());
+            args.push(self.languages.join(","));
+        }
+        if let Some(ref path) = self.launcher_path {
+            args.push("--launcher".to_string());
+            args.push(path.clone());
+        }
+
+        args.push("--locale".to_string());
+        args.push(strings::get_language());
+
+        args.extend(bandwidth::elevation_args());
+
+        args
+    }
+
+    /// Carries over what this session was told on the command line from `previous`, which
+    /// this replaces to install somewhere else.
+    pub fn carry_over(&mut self, previous: &InstallerFramework) {
+        self.staging_dir = previous.staging_dir.clone();
+        self.install_timeout = previous.install_timeout;
+        self.languages = previous.languages.clone();
+
+        if previous.database.environment.is_some() {
+            self.database.environment = previous.database.environment.clone();
+        }
+
+        // As on startup, an overridden configuration URL is kept by the installation
+        match previous.database.config_url {
+            Some(ref url) => self.database.config_url = Some(url.clone()),
+            None => {
+                if let Some(url) = self.database.config_url.clone() {
+                    self.base_attributes.target_url = url;
+                }
+            }
+        }
+    }
+
     /// Configures this installer to install to the specified location.
     /// If there was a currently configured install path, this will be left as-is.
+    ///
+    /// The location is canonicalized where possible, resolving `..`, symlinks and
+    /// substituted drives.
     pub fn set_install_dir(&mut self, dir: &str) {
-        self.install_path = Some(Path::new(dir).to_owned());
+        let path = match locations::canonicalize_install_dir(dir) {
+            Ok(v) => v,
+            Err(v) => {
+                warn!("Unable to canonicalize install directory: {}", v);
+                Path::new(dir).to_owned()
+            }
+        };
+
+        self.database.install_path = Some(path.to_string_lossy().to_string());
+        self.install_path = Some(path);
+    }
+
+    /// Returns where the installation was recorded to be, if it has since been moved.
+    pub fn moved_from(&self) -> Option<PathBuf> {
+        let current = self.install_path.as_ref()?;
+        let recorded = PathBuf::from(self.database.install_path.as_ref()?);
+
+        let is_same = match (recorded.canonicalize(), current.canonicalize()) {
+            (Ok(recorded), Ok(current)) => recorded == current,
+            _ => recorded == *current,
+        };
+
+        if is_same {
+            None
+        } else {
+            Some(recorded)
+        }
+    }
+
+    /// Refuses to install onto network shares unless the configuration allows it, as they
+    /// are slow and often unreliable when extracting.
+    fn check_install_location(&self) -> Result<(), String> {
+        let path = self
+            .install_path
+            .as_ref()
+            .ok_or("No install path specified")?;
+
+        if !locations::is_network_location(path) {
+            return Ok(());
+        }
+
+        let allowed = self
+            .config
+            .as_ref()
+            .map_or(false, |x| x.allow_network_install);
+
+        if !allowed {
+            return Err(format!(
+                "{:?} is on a network share, which {} can't be installed to. \
+                 Please choose a directory on this computer.",
+                path, self.base_attributes.name
+            ));
+        }
+
+        warn!(
+            "Installing to network share {:?} - this may be slow, and requires the share to \
+             stay connected",
+            path
+        );
+
+        Ok(())
     }
 
     /// Returns metadata on the current status of the installation.
@@ -416,9 +2086,33 @@ impl InstallerFramework {
             preexisting_install: self.preexisting_install,
             is_launcher: self.is_launcher,
             launcher_path: self.launcher_path.clone(),
+            moved_from: self.moved_from().map(|x| x.display().to_string()),
+            metadata_status: self.metadata_status.clone(),
         }
     }
 
+    /// Refuses to act on metadata which doesn't match its signature, as whatever it lists
+    /// may not have been installed by us.
+    pub fn check_metadata(&self) -> Result<(), String> {
+        if self.metadata_status == MetadataStatus::Tampered {
+            return Err(
+                "The installation's metadata has been modified outside of the installer, so \
+                 nothing will be removed based on it."
+                    .to_string(),
+            );
+        }
+
+        if self.metadata_status == MetadataStatus::KeyMissing {
+            return Err(
+                "The key the installation's metadata was signed with can't be found, so \
+                 nothing will be removed based on it. Try again as the user who installed it."
+                    .to_string(),
+            );
+        }
+
+        Ok(())
+    }
+
     /// Shuts down the installer instance.
     pub fn shutdown(&mut self) -> Result<(), String> {
         info!("Shutting down installer framework...");
@@ -426,11 +2120,7 @@ impl InstallerFramework {
         if let Some(ref v) = self.launcher_path.take() {
             info!("Launching {:?}", v);
 
-            Command::new(v)
-                .stdout(Stdio::null())
-                .stderr(Stdio::null())
-                .spawn()
-                .map_err(|x| format!("Unable to start application: {:?}", x))?;
+            native::spawn_unelevated(v)?;
         }
 
         if self.burn_after_exit {
@@ -439,20 +2129,59 @@ impl InstallerFramework {
             self.burn_after_exit = false;
         }
 
+        bundle::close();
+        bandwidth::flush();
+        prefetch::discard();
+
         Ok(())
     }
 
     /// Creates a new instance of the Installer Framework with a specified Config.
     pub fn new(attrs: BaseAttributes) -> Self {
+        let database = InstallationDatabase {
+            all_users: attrs.install_scope == InstallScope::Machine,
+            ..InstallationDatabase::new()
+        };
+
         InstallerFramework {
             base_attributes: attrs,
             config: None,
-            database: InstallationDatabase::new(),
+            raw_config: None,
+            database,
             install_path: None,
             preexisting_install: false,
             is_launcher: false,
             burn_after_exit: false,
             launcher_path: None,
+            transaction: Transaction::new(),
+            report: None,
+            tool_update: None,
+            metadata_status: MetadataStatus::Verified,
+            staging_dir: None,
+            install_timeout: None,
+            restart_session: None,
+            pending_hooks: HashSet::new(),
+            pending_smoke_tests: HashSet::new(),
+            maintenance_unlocked: false,
+            resolved: HashMap::new(),
+            downloaded: HashSet::new(),
+            task_errors: VecDeque::new(),
+            languages: Vec::new(),
+            handed_over: HandedOverChoices::default(),
+            confirmed_download: 0,
+        }
+    }
+
+    /// Reads and parses a metadata file, returning its raw contents alongside it.
+    fn read_database(path: &Path) -> Result<(Vec<u8>, InstallationDatabase), String> {
+        let metadata = match read(path) {
+            Ok(v) => v,
+            Err(v) => return Err(format!("Unable to open file handle: {:?}", v)),
+        };
+
+        match serde_json::from_slice(&metadata) {
+            Ok(v) => Ok((metadata, v)),
+            Err(v) => Err(format!("Unable to read metadata file: {:?}", v)),
         }
     }
 
@@ -461,25 +2190,57 @@ impl InstallerFramework {
     pub fn new_with_db(attrs: BaseAttributes, install_path: &Path) -> Result<Self, String> {
         let path = install_path.to_owned();
         let metadata_path = path.join("metadata.json");
-        let metadata_file = match File::open(metadata_path) {
+        let (metadata, mut database) = match Self::read_database(&metadata_path) {
             Ok(v) => v,
-            Err(v) => return Err(format!("Unable to open file handle: {:?}", v)),
-        };
+            Err(v) => {
+                // A previous save may have been interrupted, or the file damaged since
+                let backup_path = atomic::backup_path(&metadata_path);
+                if !backup_path.exists() {
+                    return Err(v);
+                }
 
-        let database: InstallationDatabase = match serde_json::from_reader(metadata_file) {
-            Ok(v) => v,
-            Err(v) => return Err(format!("Unable to read metadata file: {:?}", v)),
+                error!("{} - using the previous version instead", v);
+                Self::read_database(&backup_path)?
+            }
         };
 
+        let installed_at = database.install_path.as_ref().map(PathBuf::from);
+        let metadata_status =
+            integrity::verify(&path, installed_at.as_ref().map(|x| x.as_path()), &metadata);
+
+        // Older installations didn't record where they were, so can't tell if they've moved
+        if database.install_path.is_none() {
+            database.install_path = Some(path.to_string_lossy().to_string());
+        }
+
+        logging::register_secret(&database.credentials.token);
+
         Ok(InstallerFramework {
             base_attributes: attrs,
             config: None,
+            raw_config: None,
             database,
             install_path: Some(path),
             preexisting_install: true,
             is_launcher: false,
             burn_after_exit: false,
             launcher_path: None,
+            transaction: Transaction::new(),
+            report: None,
+            tool_update: None,
+            metadata_status,
+            staging_dir: None,
+            install_timeout: None,
+            restart_session: None,
+            pending_hooks: HashSet::new(),
+            pending_smoke_tests: HashSet::new(),
+            maintenance_unlocked: false,
+            resolved: HashMap::new(),
+            downloaded: HashSet::new(),
+            task_errors: VecDeque::new(),
+            languages: Vec::new(),
+            handed_over: HandedOverChoices::default(),
+            confirmed_download: 0,
         })
     }
 }
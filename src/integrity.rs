@@ -0,0 +1,204 @@
+//! integrity.rs
+//!
+//! Signs metadata.json with an HMAC, using a key kept outside of the install directory,
+//! so that metadata which has been corrupted or tampered with is noticed before anything
+//! is removed based on what it lists.
+//!
+//! Keys are kept machine-wide where possible, so that other users (or the same user,
+//! elevated) can still check an installation, and in the user's own data otherwise.
+
+use std::fs::{create_dir_all, read_to_string, remove_file, write};
+use std::path::{Path, PathBuf};
+
+use dirs::data_local_dir;
+
+use ring::constant_time::verify_slices_are_equal;
+use ring::rand::{SecureRandom, SystemRandom};
+
+use atomic;
+
+use hashing::{hmac_sha256_hex, sha256_hex, to_hex};
+
+use locations;
+
+/// Where the signature of metadata.json is kept, within the install directory.
+pub const SIGNATURE_FILE: &str = "metadata.json.sig";
+/// Length of generated keys, in bytes.
+const KEY_LENGTH: usize = 32;
+/// Extension of the key metadata.json is signed with.
+const METADATA_KEY: &str = "key";
+/// Extension of the key tokens for private sources are encrypted with (see `source_auth`).
+pub const TOKEN_KEY: &str = "tokens.key";
+
+/// The outcome of checking the signature of an installation's metadata.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub enum MetadataStatus {
+    /// The metadata matches its signature
+    Verified,
+    /// The metadata can't be checked, as it predates signing
+    Unverified,
+    /// The metadata has been signed, but the key it was signed with can't be found or
+    /// read (e.g. when installed by another user, who kept it in their own data)
+    KeyMissing,
+    /// The metadata doesn't match its signature
+    Tampered,
+}
+
+/// Where a key for an installation may be kept, in the order they are tried. Each
+/// install directory has its own keys.
+fn key_paths(install_path: &Path, extension: &str) -> Vec<PathBuf> {
+    let id = sha256_hex(install_path.to_string_lossy().as_bytes());
+
+    locations::machine_data_dir()
+        .into_iter()
+        .chain(data_local_dir())
+        .map(|x| {
+            x.join("liftinstall")
+                .join("keys")
+                .join(format!("{}.{}", &id[..16], extension))
+        })
+        .collect()
+}
+
+/// Reads a key for an installation, if there is one.
+fn read_key(install_path: &Path, extension: &str) -> Option<String> {
+    key_paths(install_path, extension)
+        .iter()
+        .filter_map(|x| read_to_string(x).ok())
+        .map(|x| x.trim().to_string())
+        .find(|x| !x.is_empty())
+}
+
+/// Writes a key where it is first possible to.
+fn write_key(install_path: &Path, extension: &str, key: &str) -> Result<(), String> {
+    let mut last_error = "Unable to find a directory to keep keys in".to_string();
+
+    for path in key_paths(install_path, extension) {
+        let result = path
+            .parent()
+            .map_or(Ok(()), create_dir_all)
+            .and_then(|_| write(&path, key));
+
+        match result {
+            Ok(_) => {
+                #[cfg(unix)]
+                {
+                    use std::fs::{set_permissions, Permissions};
+                    use std::os::unix::fs::PermissionsExt;
+
+                    if let Err(v) = set_permissions(&path, Permissions::from_mode(0o600)) {
+                        warn!("Unable to restrict access to {:?}: {:?}", path, v);
+                    }
+                }
+
+                return Ok(());
+            }
+            Err(v) => last_error = format!("Unable to write key {:?}: {:?}", path, v),
+        }
+    }
+
+    Err(last_error)
+}
+
+/// Reads a key for an installation, creating one if needed.
+pub fn get_or_create_key(install_path: &Path, extension: &str) -> Result<String, String> {
+    if let Some(key) = read_key(install_path, extension) {
+        return Ok(key);
+    }
+
+    let mut key = [0 as u8; KEY_LENGTH];
+    SystemRandom::new()
+        .fill(&mut key)
+        .map_err(|_| "Unable to generate a metadata key".to_string())?;
+    let key = to_hex(&key);
+    write_key(install_path, extension, &key)?;
+
+    Ok(key)
+}
+
+/// Copies the keys of an installation which has been moved, so that they are found for
+/// where it is now.
+pub fn move_keys(old_path: &Path, new_path: &Path) {
+    for extension in &[METADATA_KEY, TOKEN_KEY] {
+        if read_key(new_path, extension).is_some() {
+            continue;
+        }
+
+        if let Some(key) = read_key(old_path, extension) {
+            if let Err(v) = write_key(new_path, extension, &key) {
+                warn!("Unable to keep the key of the moved installation: {}", v);
+            }
+        }
+    }
+}
+
+/// Signs the contents of an installation's metadata.json.
+pub fn sign(install_path: &Path, metadata: &[u8]) -> Result<(), String> {
+    let key = get_or_create_key(install_path, METADATA_KEY)?;
+
+    atomic::write(
+        &install_path.join(SIGNATURE_FILE),
+        hmac_sha256_hex(key.as_bytes(), metadata).as_bytes(),
+    )
+    .map_err(|x| format!("Unable to write metadata signature: {:?}", x))
+}
+
+/// Checks the contents of an installation's metadata.json against its signature.
+/// `installed_at` is where the metadata says the installation is, which is where its keys
+/// are found for if it has been moved since.
+pub fn verify(install_path: &Path, installed_at: Option<&Path>, metadata: &[u8]) -> MetadataStatus {
+    // Metadata is signed before being saved, so if saving was interrupted the previous
+    // signature is the one which matches.
+    let signature_path = install_path.join(SIGNATURE_FILE);
+    let signatures: Vec<String> = [signature_path.clone(), atomic::backup_path(&signature_path)]
+        .iter()
+        .filter_map(|x| read_to_string(x).ok())
+        .map(|x| x.trim().to_lowercase())
+        .collect();
+
+    let key = read_key(install_path, METADATA_KEY)
+        .or_else(|| installed_at.and_then(|x| read_key(x, METADATA_KEY)));
+    let key = match key {
+        Some(v) => v,
+        None if signatures.is_empty() => {
+            warn!("Installation metadata has never been signed, so can't be verified");
+            return MetadataStatus::Unverified;
+        }
+        None => {
+            error!("Installation metadata has been signed, but its key can't be found");
+            return MetadataStatus::KeyMissing;
+        }
+    };
+
+    // Once a key exists, the metadata has been signed, so a missing signature is suspect
+    if signatures.is_empty() {
+        error!("Installation metadata has a key, but no signature");
+        return MetadataStatus::Tampered;
+    }
+
+    let expected = hmac_sha256_hex(key.as_bytes(), metadata);
+    if signatures
+        .iter()
+        .any(|x| verify_slices_are_equal(expected.as_bytes(), x.as_bytes()).is_ok())
+    {
+        info!("Verified installation metadata");
+        MetadataStatus::Verified
+    } else {
+        error!("Installation metadata doesn't match its signature");
+        MetadataStatus::Tampered
+    }
+}
+
+/// Removes the signature and keys of an installation once it has been uninstalled.
+pub fn remove(install_path: &Path) {
+    let signature_path = install_path.join(SIGNATURE_FILE);
+    let mut paths = vec![atomic::backup_path(&signature_path), signature_path];
+    paths.extend(key_paths(install_path, METADATA_KEY));
+    paths.extend(key_paths(install_path, TOKEN_KEY));
+
+    for path in paths.iter().filter(|x| x.exists()) {
+        if let Err(v) = remove_file(path) {
+            warn!("Unable to remove {:?}: {:?}", path, v);
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! large_files.rs
+//!
+//! Writing of large archive entries: entries which are mostly zeros become sparse files,
+//! and space is reserved up front for the rest so that they aren't fragmented.
+
+use archives::EntryMetadata;
+
+use config::LargeFileSettings;
+
+use heartbeat;
+
+use native;
+
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom, Write};
+
+/// Size of the blocks which are checked for zeros. Only whole blocks are skipped.
+const BLOCK_SIZE: usize = 4096;
+
+/// How much smaller than its contents an entry has to be stored as for it to be treated as
+/// mostly zeros. Runs of zeros compress far better than any real data.
+const SPARSE_COMPRESSION_RATIO: u64 = 20;
+
+/// Returns if an entry looks like it is mostly zeros, based on how well it compressed.
+fn is_mostly_zeros(entry: &EntryMetadata, settings: &LargeFileSettings) -> bool {
+    match (entry.size, entry.compressed_size) {
+        (Some(size), Some(compressed_size)) => {
+            size >= settings.threshold
+                && compressed_size.saturating_mul(SPARSE_COMPRESSION_RATIO) < size
+        }
+        _ => false,
+    }
+}
+
+/// Writes to a file, skipping over blocks of zeros when writing sparsely so that they
+/// don't take up any space.
+pub struct SparseWriter {
+    file: File,
+    sparse: bool,
+    /// How far into the file has been written, including any zeros skipped
+    length: u64,
+}
+
+impl SparseWriter {
+    /// Prepares a newly created file for an entry's contents, marking it as sparse or
+    /// reserving space for it as configured. Failing to do either isn't fatal, as the
+    /// contents can still be written out normally.
+    pub fn new(file: File, entry: &EntryMetadata, settings: &LargeFileSettings) -> Self {
+        let size = entry.size.unwrap_or(0);
+
+        let mut sparse = settings.sparse && is_mostly_zeros(entry, settings);
+        if sparse {
+            if let Err(v) = native::set_sparse(&file) {
+                warn!("Writing file normally, as it can't be made sparse: {}", v);
+                sparse = false;
+            }
+        }
+
+        if !sparse && settings.preallocate && size >= settings.threshold {
+            if let Err(v) = native::preallocate(&file, size) {
+                warn!("Unable to reserve {} bytes for file: {}", size, v);
+            }
+        }
+
+        SparseWriter {
+            file,
+            sparse,
+            length: 0,
+        }
+    }
+
+    /// Sets the final length of the file. This covers any zeros skipped at the end, as
+    /// well as any space reserved beyond what was actually written.
+    pub fn finish(self) -> Result<(), String> {
+        self.file
+            .set_len(self.length)
+            .map_err(|x| format!("Unable to set length of file: {:?}", x))
+    }
+}
+
+impl Write for SparseWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Large entries take a while, with no progress shown until they are done
+        heartbeat::beat();
+
+        if !self.sparse {
+            let len = self.file.write(buf)?;
+            self.length += len as u64;
+            return Ok(len);
+        }
+
+        // Work through blocks aligned to the file, so that the file system can leave
+        // whole blocks unallocated
+        let offset = (self.length % BLOCK_SIZE as u64) as usize;
+        let block = &buf[..buf.len().min(BLOCK_SIZE - offset)];
+
+        let len = if block.iter().all(|x| *x == 0) {
+            self.file.seek(SeekFrom::Current(block.len() as i64))?;
+            block.len()
+        } else {
+            self.file.write(block)?
+        };
+
+        self.length += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
@@ -0,0 +1,175 @@
+//! leftovers.rs
+//!
+//! Finds (and optionally removes) data left behind after uninstalling, such as user data,
+//! logs and files added by third parties.
+
+use std::fs::{read_dir, remove_dir_all, remove_file};
+use std::path::Path;
+
+use audit::{self, AuditAction};
+
+use installer::InstallerFramework;
+
+use locations;
+use native;
+
+/// What kind of thing was left behind.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub enum LeftoverKind {
+    File,
+    Directory,
+    RegistryKey,
+}
+
+/// Something that still exists after uninstalling.
+#[derive(Serialize, Clone, Debug)]
+pub struct Leftover {
+    pub path: String,
+    pub kind: LeftoverKind,
+    /// Size on disk, in bytes
+    pub size: u64,
+    /// What this is, if it is a declared user data location
+    pub description: Option<String>,
+}
+
+/// Returns the total size of a file or directory, in bytes.
+fn disk_usage(path: &Path) -> u64 {
+    if path.is_dir() {
+        match read_dir(path) {
+            Ok(entries) => entries
+                .filter_map(|x| x.ok())
+                .map(|x| disk_usage(&x.path()))
+                .sum(),
+            Err(_) => 0,
+        }
+    } else {
+        path.metadata().map(|x| x.len()).unwrap_or(0)
+    }
+}
+
+fn describe_path(path: &Path) -> Option<Leftover> {
+    let kind = if path.is_dir() {
+        LeftoverKind::Directory
+    } else if path.exists() {
+        LeftoverKind::File
+    } else {
+        return None;
+    };
+
+    Some(Leftover {
+        path: path.to_str()?.to_string(),
+        kind,
+        size: disk_usage(path),
+        description: None,
+    })
+}
+
+/// Lists what remains in the install directory, as well as any configured locations
+/// which still exist.
+pub fn find_leftovers(framework: &InstallerFramework) -> Vec<Leftover> {
+    let mut leftovers = Vec::new();
+
+    let install_path = match framework.install_path {
+        Some(ref v) => v.clone(),
+        None => return leftovers,
+    };
+
+    // The maintenance tool and its log remove themselves on exit
+    let app_name = &framework.base_attributes.name;
+    let ignored = [
+        "maintenancetool".to_string(),
+        "maintenancetool.exe".to_string(),
+        format!("{}_installer.log", app_name),
+    ];
+
+    if let Ok(entries) = read_dir(&install_path) {
+        for entry in entries.filter_map(|x| x.ok()) {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if ignored.contains(&name) {
+                continue;
+            }
+
+            leftovers.extend(describe_path(&entry.path()));
+        }
+    }
+
+    if let Some(ref config) = framework.config {
+        for location in &config.data_locations {
+            match locations::resolve_data_location(location) {
+                Ok(path) => leftovers.extend(describe_path(&path).map(|x| Leftover {
+                    description: Some(format!(
+                        "{} ({:?} user data)",
+                        location.name, location.scope
+                    )),
+                    ..x
+                })),
+                Err(v) => warn!("Unable to check {:?} for leftovers: {}", location.name, v),
+            }
+        }
+
+        for location in &config.leftovers.paths {
+            match locations::resolve(location, &install_path, &config.data_locations) {
+                Ok(path) => leftovers.extend(describe_path(&path)),
+                Err(v) => warn!("Unable to check {:?} for leftovers: {}", location, v),
+            }
+        }
+
+        for key in &config.leftovers.registry_keys {
+            if native::registry_key_exists(key) {
+                leftovers.push(Leftover {
+                    path: key.to_owned(),
+                    kind: LeftoverKind::RegistryKey,
+                    size: 0,
+                    description: None,
+                });
+            }
+        }
+    }
+
+    leftovers
+}
+
+/// Deletes the specified leftovers. Only things which are still reported as leftovers are
+/// deleted, so that arbitrary paths can't be removed.
+pub fn delete_leftovers(framework: &InstallerFramework, selected: &[String]) -> Result<(), String> {
+    let mut errors = Vec::new();
+
+    for leftover in find_leftovers(framework) {
+        if !selected.contains(&leftover.path) {
+            continue;
+        }
+
+        info!("Deleting leftover {:?}", leftover.path);
+
+        let result = match leftover.kind {
+            LeftoverKind::File => remove_file(&leftover.path).map_err(|x| format!("{:?}", x)),
+            LeftoverKind::Directory => {
+                remove_dir_all(&leftover.path).map_err(|x| format!("{:?}", x))
+            }
+            LeftoverKind::RegistryKey => native::delete_registry_key(&leftover.path),
+        };
+
+        if let Err(v) = result {
+            errors.push(format!("{}: {}", leftover.path, v));
+            continue;
+        }
+
+        let action = match leftover.kind {
+            LeftoverKind::RegistryKey => AuditAction::RegistryDeleted { key: leftover.path },
+            _ => AuditAction::FilesRemoved {
+                package: None,
+                files: vec![leftover.path],
+            },
+        };
+        audit::record(framework, action);
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Unable to delete some leftovers: {}",
+            errors.join(", ")
+        ))
+    }
+}
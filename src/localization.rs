@@ -0,0 +1,48 @@
+//! localization.rs
+//!
+//! Lets packages vary by locale, so that localized builds only download the resources for
+//! the languages which are needed:
+//!
+//! - Packages with `locales` are language packs, which are selected by default for
+//!   installations in one of those locales, and not otherwise. They can still be picked
+//!   (or left out) like any other package.
+//! - Sources with `locale_match` install a different asset for each locale, e.g. a build
+//!   of the application for that language.
+//!
+//! An installation's languages are given with `--languages`, or otherwise are the language
+//! the installer was shown in when it was first installed. They are recorded in the
+//! database, so that updates keep installing the same languages.
+
+use config::Config;
+
+use strings;
+
+/// Returns if any of the patterns covers any of the languages.
+fn any_matches(patterns: &[String], languages: &[String]) -> bool {
+    patterns
+        .iter()
+        .any(|x| languages.iter().any(|y| strings::locale_matches(x, y)))
+}
+
+/// Selects language packs and localized assets for the specified languages, which are in
+/// order of preference.
+pub fn apply(config: &mut Config, languages: &[String]) {
+    for package in &mut config.packages {
+        if !package.locales.is_empty() {
+            package.default = Some(any_matches(&package.locales, languages));
+        }
+
+        let localized = languages.iter().find_map(|language| {
+            package
+                .source
+                .locale_match
+                .iter()
+                .find(|x| strings::locale_matches(x.0, language))
+        });
+
+        if let Some((locale, match_regex)) = localized {
+            info!("Using the {:?} build of {:?}", locale, package.name);
+            package.source.match_regex = match_regex.clone();
+        }
+    }
+}
@@ -0,0 +1,421 @@
+//! locations.rs
+//!
+//! Resolves configurable install locations, such as per-package install roots.
+
+use regex::{Captures, Regex};
+
+use std::env;
+use std::fs::{remove_file, OpenOptions};
+use std::path::{Component, Path, PathBuf};
+use std::process;
+
+use url::Url;
+
+use config::{DataLocation, DataScope, HostApplication};
+
+use dirs::{data_dir, data_local_dir, home_dir};
+
+use native;
+
+lazy_static! {
+    static ref ENV_PATTERN: Regex = Regex::new(r"\$\{([A-Za-z0-9_]+)\}").unwrap();
+    static ref DATA_PATTERN: Regex = Regex::new(r"\{data:([A-Za-z0-9_-]+)\}").unwrap();
+}
+
+/// Prefix for locations which are read from the Windows registry.
+const REGISTRY_PREFIX: &str = "registry:";
+/// Placeholder for the main install directory.
+const INSTALL_DIR_PLACEHOLDER: &str = "{install_dir}";
+/// Placeholder for the user's roaming application data directory.
+const ROAMING_DATA_PLACEHOLDER: &str = "{roaming_data}";
+/// Placeholder for the user's local (machine-specific) application data directory.
+const LOCAL_DATA_PLACEHOLDER: &str = "{local_data}";
+/// Placeholder for the directory machine-wide application data is kept in.
+const MACHINE_DATA_PLACEHOLDER: &str = "{machine_data}";
+
+/// Where machine-wide application data is kept, if anywhere.
+pub fn machine_data_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        env::var_os("ProgramData").map(PathBuf::from)
+    } else {
+        Some(PathBuf::from("/var/lib"))
+    }
+}
+
+/// Returns the user's application data directory for a scope. These are the same outside
+/// of Windows.
+fn scope_dir(scope: &DataScope) -> Result<PathBuf, String> {
+    match *scope {
+        DataScope::Roaming => data_dir(),
+        DataScope::Local => data_local_dir(),
+    }
+    .ok_or_else(|| format!("Unable to find the {:?} application data directory", scope))
+}
+
+fn path_to_string(path: &Path) -> Result<String, String> {
+    path.to_str()
+        .map(|x| x.to_string())
+        .ok_or_else(|| format!("Unable to get str from path {:?}", path))
+}
+
+/// Resolves where a declared user data location is for the current user.
+pub fn resolve_data_location(location: &DataLocation) -> Result<PathBuf, String> {
+    resolve(&location.path, &scope_dir(&location.scope)?, &[])
+}
+
+/// Expands a location specification into a concrete path:
+///
+/// - `registry:<HKLM|HKCU>\<key>\<value>` reads a directory from the Windows registry
+/// - `${NAME}` is replaced with the environment variable `NAME`
+/// - `{install_dir}` is replaced with the main install directory
+/// - `{roaming_data}` and `{local_data}` are replaced with the user's roaming and local
+///   application data directories
+/// - `{machine_data}` is replaced with the machine-wide application data directory
+/// - `{data:<name>}` is replaced with the named entry of `data_locations`
+///
+/// Relative paths are treated as relative to the main install directory.
+pub fn resolve(
+    spec: &str,
+    install_path: &Path,
+    data_locations: &[DataLocation],
+) -> Result<PathBuf, String> {
+    let expanded = if spec.starts_with(REGISTRY_PREFIX) {
+        native::read_registry_string(&spec[REGISTRY_PREFIX.len()..])?
+    } else {
+        let mut missing = None;
+        let expanded = ENV_PATTERN.replace_all(spec, |captures: &Captures| {
+            env::var(&captures[1]).unwrap_or_else(|_| {
+                missing = Some(captures[1].to_string());
+                String::new()
+            })
+        });
+
+        if let Some(name) = missing {
+            return Err(format!(
+                "Environment variable {:?} needed by {:?} is not set",
+                name, spec
+            ));
+        }
+
+        let mut failure = None;
+        let expanded = DATA_PATTERN.replace_all(&expanded, |captures: &Captures| {
+            let path = data_locations
+                .iter()
+                .find(|x| x.name == captures[1])
+                .ok_or_else(|| format!("Unknown data location {:?} in {:?}", &captures[1], spec))
+                .and_then(resolve_data_location)
+                .and_then(|x| path_to_string(&x));
+
+            path.unwrap_or_else(|v| {
+                failure = Some(v);
+                String::new()
+            })
+        });
+
+        if let Some(v) = failure {
+            return Err(v);
+        }
+
+        let mut expanded =
+            expanded.replace(INSTALL_DIR_PLACEHOLDER, &path_to_string(install_path)?);
+
+        for (placeholder, scope) in &[
+            (ROAMING_DATA_PLACEHOLDER, DataScope::Roaming),
+            (LOCAL_DATA_PLACEHOLDER, DataScope::Local),
+        ] {
+            if expanded.contains(placeholder) {
+                expanded = expanded.replace(placeholder, &path_to_string(&scope_dir(scope)?)?);
+            }
+        }
+
+        if expanded.contains(MACHINE_DATA_PLACEHOLDER) {
+            let dir = machine_data_dir()
+                .ok_or("Unable to find the machine-wide application data directory")?;
+            expanded = expanded.replace(MACHINE_DATA_PLACEHOLDER, &path_to_string(&dir)?);
+        }
+
+        expanded
+    };
+
+    let path = PathBuf::from(expanded);
+    if path.is_absolute() {
+        Ok(path)
+    } else {
+        Ok(install_path.join(path))
+    }
+}
+
+/// Finds where to install into an existing host application, trying each configured
+/// location before looking for a running instance of the host.
+pub fn find_host(host: &HostApplication) -> Result<PathBuf, String> {
+    let mut found = None;
+
+    for location in &host.locations {
+        match resolve(location, Path::new(""), &[]) {
+            Ok(ref path) if path.is_absolute() && path.is_dir() => {
+                found = Some(path.to_owned());
+                break;
+            }
+            Ok(path) => debug!("Host application is not at {:?}", path),
+            Err(v) => debug!("Unable to check host location {:?}: {}", location, v),
+        }
+    }
+
+    // Process paths are only available on Windows - elsewhere, only names are known
+    if found.is_none() {
+        if let Some(ref name) = host.process_name {
+            found = native::get_process_names()
+                .into_iter()
+                .map(|x| PathBuf::from(x.name))
+                .find(|path| {
+                    path.is_absolute()
+                        && path
+                            .file_name()
+                            .and_then(|x| x.to_str())
+                            .map(|x| x.eq_ignore_ascii_case(name))
+                            .unwrap_or(false)
+                })
+                .and_then(|path| path.parent().map(|x| x.to_owned()));
+        }
+    }
+
+    let path = found.ok_or("Unable to find the host application")?;
+    info!("Found host application at {:?}", path);
+
+    Ok(match host.subdirectory {
+        Some(ref v) => path.join(v),
+        None => path,
+    })
+}
+
+/// The outcome of checking a directory the user picked, such as by dropping it onto the UI.
+#[derive(Serialize, Debug, Default)]
+pub struct InstallDirValidation {
+    /// The canonical form of the directory, if it could be resolved
+    pub path: Option<String>,
+    /// If the directory exists already (otherwise, it will be created)
+    pub exists: bool,
+    /// If files can be created inside of it (or inside of whatever will contain it).
+    /// Directories which can't be are installed into with elevated privileges.
+    pub writable: bool,
+    /// If the directory belongs to the operating system, or is otherwise unsuitable
+    pub protected: bool,
+    /// If the directory is on a network share
+    pub network: bool,
+    /// Why the directory can't be installed into, if it can't be
+    pub error: Option<String>,
+}
+
+/// Directories which must never be installed into. Anything within those marked `true` is
+/// also protected.
+fn protected_dirs() -> Vec<(PathBuf, bool)> {
+    let mut dirs = Vec::new();
+
+    if cfg!(windows) {
+        for (name, recursive) in &[
+            ("WINDIR", true),
+            ("ProgramFiles", false),
+            ("ProgramFiles(x86)", false),
+            ("ProgramData", false),
+            ("USERPROFILE", false),
+        ] {
+            if let Ok(v) = env::var(name) {
+                dirs.push((PathBuf::from(v), *recursive));
+            }
+        }
+    } else {
+        for (name, recursive) in &[
+            ("/bin", true),
+            ("/boot", true),
+            ("/dev", true),
+            ("/etc", true),
+            ("/lib", true),
+            ("/proc", true),
+            ("/sbin", true),
+            ("/sys", true),
+            ("/usr", false),
+            ("/var", false),
+        ] {
+            dirs.push((PathBuf::from(name), *recursive));
+        }
+
+        if let Some(home) = home_dir() {
+            dirs.push((home, false));
+        }
+    }
+
+    dirs
+}
+
+/// Canonicalizes a path which may not exist yet, by resolving its closest existing
+/// ancestor (including symlinks and substituted drives), then applying the rest of the
+/// path to it. Returns the path, and that ancestor.
+fn canonicalize_partial(path: &Path) -> Result<(PathBuf, PathBuf), String> {
+    let mut existing = path.to_owned();
+    while !existing.exists() {
+        if !existing.pop() {
+            return Err(format!("No part of {:?} exists", path));
+        }
+    }
+
+    let remainder = path
+        .strip_prefix(&existing)
+        .map_err(|x| format!("Unable to relativize {:?}: {:?}", path, x))?
+        .to_owned();
+
+    let existing = existing
+        .canonicalize()
+        .map_err(|x| format!("Unable to resolve {:?}: {:?}", path, x))?;
+
+    // Windows returns verbatim paths, which aren't suitable for showing to users
+    let display = existing.to_string_lossy().to_string();
+    let existing = if display.starts_with(r"\\?\UNC\") {
+        PathBuf::from(format!(r"\\{}", &display[8..]))
+    } else if display.starts_with(r"\\?\") {
+        PathBuf::from(&display[4..])
+    } else {
+        existing
+    };
+
+    let mut full = existing.clone();
+    for component in remainder.components() {
+        match component {
+            Component::ParentDir => {
+                full.pop();
+            }
+            Component::Normal(part) => full.push(part),
+            _ => {}
+        }
+    }
+
+    Ok((full, existing))
+}
+
+/// Canonicalizes an install directory chosen by the user, which may not exist yet.
+pub fn canonicalize_install_dir(dir: &str) -> Result<PathBuf, String> {
+    let path = Path::new(dir);
+    if !path.is_absolute() {
+        return Err(format!("{:?} isn't a complete path", dir));
+    }
+
+    canonicalize_partial(path).map(|x| x.0)
+}
+
+/// Returns if a path (or whatever will contain it, if it doesn't exist yet) is on a
+/// network share.
+pub fn is_network_location(path: &Path) -> bool {
+    path.ancestors()
+        .find(|x| x.exists())
+        .map_or(false, native::is_network_path)
+}
+
+/// Checks if a directory is on a case-insensitive file system.
+pub fn is_case_insensitive(dir: &Path) -> bool {
+    let name = format!(".liftinstall-case-test-{}", process::id());
+    let probe = dir.join(&name);
+
+    match OpenOptions::new().write(true).create_new(true).open(&probe) {
+        Ok(_) => {
+            let insensitive = dir.join(name.to_uppercase()).exists();
+            if let Err(v) = remove_file(&probe) {
+                warn!("Unable to remove case probe {:?}: {:?}", probe, v);
+            }
+            insensitive
+        }
+        // Assume the worst
+        Err(_) => true,
+    }
+}
+
+/// Checks if files can be created within a directory.
+fn is_writable(dir: &Path) -> bool {
+    let probe = dir.join(format!(".liftinstall-write-test-{}", process::id()));
+
+    match OpenOptions::new().write(true).create_new(true).open(&probe) {
+        Ok(_) => {
+            if let Err(v) = remove_file(&probe) {
+                warn!("Unable to remove write probe {:?}: {:?}", probe, v);
+            }
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Returns if a path (or whatever will contain it, if it doesn't exist yet) can be
+/// written to by this process.
+pub fn is_writable_location(path: &Path) -> bool {
+    path.ancestors()
+        .find(|x| x.exists())
+        .map_or(false, |x| x.is_dir() && is_writable(x))
+}
+
+/// Returns if installing to (or maintaining the installation at) a path needs more
+/// privileges than this process has. Machine-wide installations always do, as their
+/// shortcuts and registration are shared by every user.
+pub fn needs_elevation(path: &Path, all_users: bool) -> bool {
+    !native::is_elevated() && (all_users || !is_writable_location(path))
+}
+
+/// Canonicalizes and validates a directory the user would like to install into. Dropped
+/// files resolve to the directory containing them, and `file://` URLs are accepted.
+pub fn validate_install_dir(input: &str) -> InstallDirValidation {
+    let mut result = InstallDirValidation::default();
+
+    let input = input.trim().trim_matches('"');
+    let path = if input.starts_with("file:") {
+        match Url::parse(input).ok().and_then(|x| x.to_file_path().ok()) {
+            Some(v) => v,
+            None => {
+                result.error = Some(format!("{:?} isn't a local path", input));
+                return result;
+            }
+        }
+    } else {
+        PathBuf::from(input)
+    };
+
+    if !path.is_absolute() {
+        result.error = Some(format!("{:?} isn't a complete path", input));
+        return result;
+    }
+
+    let path = if path.is_file() {
+        match path.parent() {
+            Some(v) => v.to_owned(),
+            None => path,
+        }
+    } else {
+        path
+    };
+
+    let (path, existing) = match canonicalize_partial(&path) {
+        Ok(v) => v,
+        Err(v) => {
+            result.error = Some(v);
+            return result;
+        }
+    };
+
+    result.exists = path == existing;
+    result.writable = existing.is_dir() && is_writable(&existing);
+    result.network = is_network_location(&existing);
+    result.protected = path.parent().is_none()
+        || protected_dirs()
+            .iter()
+            .any(|(dir, recursive)| path == *dir || (*recursive && path.starts_with(dir)));
+    result.path = path.to_str().map(|x| x.to_string());
+
+    result.error = if result.path.is_none() {
+        Some("The path contains unsupported characters".to_string())
+    } else if result.protected {
+        Some(format!(
+            "{:?} is used by the system, so can't be installed into",
+            path
+        ))
+    } else {
+        None
+    };
+
+    result
+}
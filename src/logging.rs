@@ -4,23 +4,189 @@ use chrono;
 use fern;
 use log;
 
+use regex::Regex;
+
+use events::{self, Event};
+
+use std::env;
 use std::fmt::Debug;
 use std::io;
+use std::path::PathBuf;
+use std::sync::RwLock;
 
-pub fn setup_logger(file_name: String) -> Result<(), fern::InitError> {
-    fern::Dispatch::new()
+/// Environment variable used to override logging filters, in the form
+/// `level,module=level,...` (e.g. `info,sources=trace,hyper=warn`).
+pub const LOG_ENV_VAR: &str = "LIFTINSTALL_LOG";
+
+/// Placeholder written in place of any redacted value.
+const REDACTED: &str = "[REDACTED]";
+
+lazy_static! {
+    /// Patterns matching well-known secrets which should never hit the log file.
+    static ref SECRET_PATTERNS: Vec<(Regex, &'static str)> = vec![
+        // Authorization headers, with an optional auth scheme
+        (
+            Regex::new(r#"(?i)(authorization["']?\s*[:=]\s*["']?)((?:bearer|basic)\s+)?[^\s,;&"']+"#)
+                .unwrap(),
+            "${1}${2}[REDACTED]",
+        ),
+        // Bare bearer tokens
+        (
+            Regex::new(r#"(?i)(bearer\s+)[^\s,;&"']+"#).unwrap(),
+            "${1}[REDACTED]",
+        ),
+        // Key/value pairs, e.g. `token=...`, `"password": "..."`, `X-TOKEN: ...`
+        (
+            Regex::new(
+                r#"(?i)((?:token|password|secret|license[_-]?key|api[_-]?key)["']?\s*[:=]\s*["']?)[^\s,;&"']+"#
+            )
+            .unwrap(),
+            "${1}[REDACTED]",
+        ),
+        // JWTs
+        (
+            Regex::new(r"eyJ[A-Za-z0-9_-]+\.[A-Za-z0-9_-]+\.[A-Za-z0-9_-]*").unwrap(),
+            "[REDACTED]",
+        ),
+    ];
+
+    /// Secrets known at runtime (e.g. stored credentials) which are redacted verbatim.
+    static ref KNOWN_SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+    /// Absolute path of the file being logged to, once logging has been set up.
+    static ref LOG_FILE: RwLock<Option<PathBuf>> = RwLock::new(None);
+}
+
+/// Returns the file this session is being logged to, if any.
+pub fn get_log_file() -> Option<PathBuf> {
+    LOG_FILE.read().ok().and_then(|x| x.clone())
+}
+
+/// Registers a value which should be masked wherever it appears in log output.
+pub fn register_secret(secret: &str) {
+    // Very short values would mangle unrelated log output
+    if secret.len() < 4 {
+        return;
+    }
+
+    if let Ok(mut secrets) = KNOWN_SECRETS.write() {
+        if !secrets.iter().any(|x| x == secret) {
+            secrets.push(secret.to_string());
+        }
+    }
+}
+
+/// Masks tokens, keys and authorization headers within a log message.
+pub fn redact(message: &str) -> String {
+    let mut message = message.to_string();
+
+    if let Ok(secrets) = KNOWN_SECRETS.read() {
+        for secret in secrets.iter() {
+            message = message.replace(secret.as_str(), REDACTED);
+        }
+    }
+
+    for (pattern, replacement) in SECRET_PATTERNS.iter() {
+        message = pattern.replace_all(&message, *replacement).into_owned();
+    }
+
+    message
+}
+
+/// The set of level filters to apply to log output.
+pub struct LogFilters {
+    /// The level used for any module without a more specific filter.
+    pub level: log::LevelFilter,
+    /// Per-module overrides, applied in order.
+    pub modules: Vec<(String, log::LevelFilter)>,
+}
+
+impl LogFilters {
+    /// Parses a filter specification in the form `level,module=level,...`.
+    pub fn parse(spec: &str) -> Result<LogFilters, String> {
+        let mut filters = LogFilters::default();
+
+        for directive in spec.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+            let mut parts = directive.splitn(2, '=');
+            let first = parts.next().unwrap_or("").trim();
+
+            match parts.next() {
+                Some(level) => {
+                    let level = level
+                        .trim()
+                        .parse()
+                        .map_err(|_| format!("Invalid log level in {:?}", directive))?;
+                    filters.modules.push((first.to_string(), level));
+                }
+                None => {
+                    filters.level = first
+                        .parse()
+                        .map_err(|_| format!("Invalid log level in {:?}", directive))?;
+                }
+            }
+        }
+
+        Ok(filters)
+    }
+}
+
+impl Default for LogFilters {
+    fn default() -> Self {
+        LogFilters {
+            level: log::LevelFilter::Info,
+            modules: Vec::new(),
+        }
+    }
+}
+
+pub fn setup_logger(file_name: String, filters: LogFilters) -> Result<(), fern::InitError> {
+    let mut dispatch = fern::Dispatch::new()
         .format(|out, message, record| {
             out.finish(format_args!(
                 "{}[{}][{}] {}",
                 chrono::Local::now().format("[%H:%M:%S]"),
                 record.target(),
                 record.level(),
-                message
+                redact(&message.to_string())
             ))
         })
+        .level(filters.level);
+
+    for (module, level) in filters.modules {
+        // Allow our own modules to be referred to without the crate prefix
+        if !module.starts_with("liftinstall") {
+            dispatch = dispatch.level_for(format!("liftinstall::{}", module), level);
+        }
+
+        dispatch = dispatch.level_for(module, level);
+    }
+
+    // Frontends receive log lines as structured events, rather than formatted text
+    let events = fern::Dispatch::new()
         .level(log::LevelFilter::Info)
-        .chain(io::stdout())
-        .chain(fern::log_file(file_name)?)
+        .chain(fern::Output::call(|record| {
+            events::publish(Event::Log {
+                level: record.level().to_string(),
+                target: record.target().to_string(),
+                message: redact(&record.args().to_string()),
+            })
+        }));
+
+    // The working directory may change later on, so remember where the log really is
+    if let Ok(mut log_file) = LOG_FILE.write() {
+        *log_file = Some(match env::current_dir() {
+            Ok(dir) => dir.join(&file_name),
+            Err(_) => PathBuf::from(&file_name),
+        });
+    }
+
+    fern::Dispatch::new()
+        .chain(
+            dispatch
+                .chain(io::stdout())
+                .chain(fern::log_file(file_name)?),
+        )
+        .chain(events)
         .apply()?;
     Ok(())
 }
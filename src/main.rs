@@ -24,11 +24,14 @@ extern crate toml;
 
 extern crate regex;
 extern crate semver;
+extern crate sha2;
 
 extern crate dirs;
+extern crate flate2;
 extern crate tar;
 extern crate xz2;
 extern crate zip;
+extern crate zstd;
 
 extern crate fern;
 #[macro_use]
@@ -36,51 +39,116 @@ extern crate log;
 
 extern crate chrono;
 
+#[macro_use]
+extern crate lazy_static;
+
 extern crate clap;
 #[cfg(windows)]
 extern crate widestring;
 #[cfg(windows)]
 extern crate winapi;
 
+#[cfg(not(windows))]
+extern crate libc;
 #[cfg(not(windows))]
 extern crate slug;
 #[cfg(not(windows))]
 extern crate sysinfo;
+#[cfg(not(windows))]
+extern crate tokio_core;
+#[cfg(not(windows))]
+extern crate tokio_uds;
 
 extern crate jsonwebtoken as jwt;
 
 extern crate base64;
 
+extern crate blake2_rfc;
+extern crate ring;
+extern crate untrusted;
+
+mod announcements;
 mod archives;
+mod atomic;
+mod audit;
+mod bandwidth;
+mod bundle;
 mod config;
+mod config_check;
+mod config_diff;
+mod config_watch;
+mod control;
+mod cpu;
+mod dedup;
+mod detect;
+mod diagnostics;
+mod differential;
+mod disk_space;
+mod dns;
+mod environments;
+mod error_help;
+mod eta;
+mod events;
 mod frontend;
+mod gpu;
+mod hashing;
+mod headless;
+mod heartbeat;
 mod http;
 mod installer;
+mod integrity;
+mod large_files;
+mod leftovers;
+mod localization;
+mod locations;
 mod logging;
+mod migration;
+mod mirrors;
+mod msi;
 mod native;
+mod policy;
+mod power;
+mod prefetch;
+mod preflight;
+mod prompts;
+mod queue;
+mod release_check;
+mod report;
+mod sandbox;
 mod self_update;
+mod signing;
+mod source_auth;
 mod sources;
+mod strings;
+mod suite;
 mod tasks;
+mod verification;
+mod webhooks;
 
-use installer::InstallerFramework;
+use installer::{InstallationDatabase, InstallerFramework};
 
-use logging::LoggingErrors;
-use std::path::PathBuf;
+use logging::{LogFilters, LoggingErrors};
+use std::env;
+use std::path::{Path, PathBuf};
 
 use clap::App;
 use clap::Arg;
+use clap::SubCommand;
+
+use log::LevelFilter;
+
+use config::{BaseAttributes, Config};
+
+use msi::MsiOptions;
 
-use config::BaseAttributes;
 use std::process::{Command, Stdio, exit};
+use std::collections::HashMap;
 use std::fs;
 
 static RAW_CONFIG: &'static str = include_str!(concat!(env!("OUT_DIR"), "/bootstrap.toml"));
 
 fn main() {
-    let config = BaseAttributes::from_toml_str(RAW_CONFIG).expect("Config file could not be read");
-
-    logging::setup_logger(format!("{}_installer.log", config.name))
-        .expect("Unable to setup logging!");
+    let mut config = BaseAttributes::from_toml_str(RAW_CONFIG).expect("Config file could not be read");
 
     // Parse CLI arguments
     let app_name = config.name.clone();
@@ -102,12 +170,617 @@ fn main() {
                 .value_name("TARGET")
                 .help("Internal usage - swaps around a new installer executable")
                 .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("silent")
+                .long("silent")
+                .help("Installs without any user interaction (also accepts /S and /VERYSILENT)"),
+        )
+        .arg(
+            Arg::with_name("install-dir")
+                .long("install-dir")
+                .value_name("DIR")
+                .help("Sets the install directory (also accepts /D=DIR)")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("packages")
+                .long("packages")
+                .value_name("PACKAGES")
+                .help(
+                    "Comma-separated list of packages to install silently or extract, or to \
+                     start off selected interactively",
+                )
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("environment")
+                .long("environment")
+                .value_name("NAME")
+                .help(
+                    "Installs from the release feeds of a configured environment, e.g. \
+                     staging (also accepts LIFTINSTALL_ENVIRONMENT)",
+                )
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("answer")
+                .long("answer")
+                .value_name("ID=VALUE")
+                .help("Answers a dialog asked during a silent operation, instead of its default")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("desktop-shortcut")
+                .long("desktop-shortcut")
+                .value_name("BOOL")
+                .help("Internal usage - sets if the desktop shortcut option starts off checked")
+                .takes_value(true)
+                .possible_values(&["true", "false"])
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("bandwidth-file")
+                .long("bandwidth-file")
+                .value_name("FILE")
+                .help("Internal usage - records bandwidth usage into FILE")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("all-users")
+                .long("all-users")
+                .help("Installs machine-wide, with shortcuts and registration for all users")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("console-user")
+                .long("console-user")
+                .help(
+                    "When started as root, runs as the user logged in at the console instead \
+                     (for per-user installs from .pkg scripts on macOS)",
+                )
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("log-file")
+                .long("log-file")
+                .value_name("FILE")
+                .help("Writes the log to FILE, instead of the working directory")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("status-file")
+                .long("status-file")
+                .value_name("FILE")
+                .help("Keeps FILE up to date with the progress of silent operations, as JSON")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("timeout")
+                .long("timeout")
+                .value_name("SECS")
+                .help("Aborts and rolls back silent operations which run for longer than SECS")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("locale")
+                .long("locale")
+                .value_name("LOCALE")
+                .help("Shows text in LOCALE (e.g. \"de\"), instead of the user's language")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("languages")
+                .long("languages")
+                .value_name("LOCALE")
+                .help(
+                    "Installs language packs and localized builds for these locales, \
+                     instead of the language being shown",
+                )
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .use_delimiter(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("msi-product-code")
+                .long("msi-product-code")
+                .value_name("CODE")
+                .help("Internal usage - records the MSI which this was installed by")
+                .takes_value(true)
+                .global(true),
+        )
+        .subcommand(
+            SubCommand::with_name("install")
+                .about("Installs (or modifies) without any user interaction, like --silent"),
+        )
+        .subcommand(
+            SubCommand::with_name("update")
+                .about("Updates the existing installation without any user interaction"),
+        )
+        .subcommand(
+            SubCommand::with_name("uninstall")
+                .about("Uninstalls the existing installation without any user interaction"),
+        )
+        .subcommand(
+            SubCommand::with_name("check-release")
+                .about(
+                    "Publisher usage - reports which asset each package would install from \
+                     the specified release, exiting with an error if any wouldn't resolve",
+                )
+                .arg(
+                    Arg::with_name("version")
+                        .value_name("VERSION")
+                        .help("The version of the release to check")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("config")
+                        .long("config")
+                        .value_name("CONFIG")
+                        .help("Checks the specified configuration file, instead of target_url")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("diff-config")
+                .about(
+                    "Publisher usage - prints what updating to a configuration would add, \
+                     remove or change, compared to an earlier configuration or an \
+                     installation's metadata.json",
+                )
+                .arg(
+                    Arg::with_name("base")
+                        .value_name("BASE")
+                        .help("The configuration (or metadata.json) to compare against")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("config")
+                        .long("config")
+                        .value_name("CONFIG")
+                        .help("Compares the specified configuration file, instead of target_url")
+                        .takes_value(true),
+                ),
+        )
+        .arg(
+            Arg::with_name("extract-only")
+                .long("extract-only")
+                .value_name("DIR")
+                .help(
+                    "Downloads and extracts packages into DIR, without creating shortcuts \
+                     or installation metadata",
+                )
+                .takes_value(true)
+                .conflicts_with("silent"),
+        )
+        .arg(
+            Arg::with_name("generate-chunk-index")
+                .long("generate-chunk-index")
+                .value_names(&["DIR", "ASSET"])
+                .number_of_values(2)
+                .help(
+                    "Publisher usage - chunks the payload in DIR for differential updates of \
+                     the release asset ASSET, writing ASSET.chunks.json and ASSET.chunks.blob",
+                ),
+        )
+        .arg(
+            Arg::with_name("generate-checksums")
+                .long("generate-checksums")
+                .value_name("ASSET")
+                .multiple(true)
+                .help(
+                    "Publisher usage - writes a SHA256SUMS manifest for the specified release \
+                     assets (or directories of assets) into the current directory",
+                ),
+        )
+        .arg(
+            Arg::with_name("generate-msi")
+                .long("generate-msi")
+                .value_names(&["OUTPUT", "VERSION"])
+                .number_of_values(2)
+                .help(
+                    "Publisher usage - wraps this installer in an MSI for deployment systems, \
+                     writing OUTPUT (requires the WiX Toolset v3)",
+                ),
+        )
+        .arg(
+            Arg::with_name("generate-bundle")
+                .long("generate-bundle")
+                .value_names(&["OUTPUT", "CONFIG"])
+                .min_values(1)
+                .max_values(2)
+                .help(
+                    "Publisher usage - downloads every package into an offline bundle at \
+                     OUTPUT, along with the specified configuration file (or the \
+                     configuration at target_url)",
+                ),
+        )
+        .arg(
+            Arg::with_name("sign")
+                .long("sign")
+                .value_name("FILE")
+                .multiple(true)
+                .requires("signing-config")
+                .help(
+                    "Publisher usage - signs the specified built installers (or other files) \
+                     with the identity in --signing-config",
+                ),
+        )
+        .arg(
+            Arg::with_name("signing-config")
+                .long("signing-config")
+                .value_name("CONFIG")
+                .help(
+                    "Publisher usage - signs with the identities in the specified TOML file, \
+                     including MSIs made with --generate-msi",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("msi-manufacturer")
+                .long("msi-manufacturer")
+                .value_name("NAME")
+                .help("Sets the publisher shown for generated MSIs (defaults to the app name)")
+                .takes_value(true)
+                .requires("generate-msi"),
+        )
+        .arg(
+            Arg::with_name("check-config")
+                .long("check-config")
+                .value_name("CONFIG")
+                .help(
+                    "Publisher usage - validates the specified configuration file (or the \
+                     configuration at target_url), exiting with an error if it has problems",
+                )
+                .takes_value(true)
+                .min_values(0)
+                .max_values(1),
+        )
+        .arg(
+            Arg::with_name("port")
+                .long("port")
+                .value_name("PORT")
+                .help("Serves the user interface on a fixed local port, instead of a random one")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("socket")
+                .long("socket")
+                .value_name("PATH")
+                .help(
+                    "Also serves the user interface on a Unix domain socket at PATH, for \
+                     integrations which don't use the built-in web view",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("config-url")
+                .long("config-url")
+                .value_name("URL")
+                .help(
+                    "Fetches the configuration from the specified HTTPS URL, instead of the \
+                     one built into the installer",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("bundle")
+                .long("bundle")
+                .value_name("FILE")
+                .help(
+                    "Installs from the specified offline bundle, instead of downloading the \
+                     configuration and packages",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("staging-dir")
+                .long("staging-dir")
+                .value_name("DIR")
+                .help(
+                    "Keeps files replaced during installation in DIR until it completes, \
+                     instead of within the install directory",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("history")
+                .long("history")
+                .help("Prints the history of operations performed on this installation"),
+        )
+        .arg(
+            Arg::with_name("export-audit-log")
+                .long("export-audit-log")
+                .value_name("OUTPUT")
+                .help(
+                    "Writes the audit log of privileged actions taken on this installation \
+                     to OUTPUT, exiting with an error if it has been tampered with",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .long("verbose")
+                .short("v")
+                .multiple(true)
+                .help("Increases logging verbosity (can be specified twice for trace output)"),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .long("quiet")
+                .short("q")
+                .conflicts_with("verbose")
+                .help("Only logs warnings and errors"),
         );
 
     let reinterpret_app = app.clone(); // In case a reparse is needed
-    let mut matches = app.get_matches();
+    let mut matches = app.get_matches_from(headless::normalize_args(env::args()));
+
+    // Environment filters are applied first, with command line flags taking precedence
+    let mut log_filters = match env::var(logging::LOG_ENV_VAR) {
+        Ok(spec) => LogFilters::parse(&spec).unwrap_or_else(|e| {
+            eprintln!("Ignoring {}: {}", logging::LOG_ENV_VAR, e);
+            LogFilters::default()
+        }),
+        Err(_) => LogFilters::default(),
+    };
+
+    match matches.occurrences_of("verbose") {
+        0 => {}
+        1 => log_filters.level = LevelFilter::Debug,
+        _ => log_filters.level = LevelFilter::Trace,
+    }
+
+    if matches.is_present("quiet") {
+        log_filters.level = LevelFilter::Warn;
+    }
+
+    // Products installed as part of a suite are maintained under their own name, which
+    // their log file, policy and window are named after too
+    let suite_product = installed_product_name();
+    let app_name = match suite_product {
+        Some(ref name) => {
+            config.name = name.clone();
+            name.clone()
+        }
+        None => app_name,
+    };
+
+    // Global arguments given after a subcommand are only seen by the subcommand
+    let log_file = {
+        let operation_matches = matches.subcommand().1.unwrap_or(&matches);
+
+        // This happens before logging starts, so the user can open the log themselves
+        if operation_matches.is_present("console-user")
+            && !operation_matches.is_present("all-users")
+            && native::is_elevated()
+        {
+            headless::run_as_console_user();
+        }
+
+        operation_matches
+            .value_of("log-file")
+            .map(|x| x.to_string())
+            .unwrap_or_else(|| format!("{}_installer.log", config.name))
+    };
+
+    logging::setup_logger(log_file, log_filters).expect("Unable to setup logging!");
 
     info!("{} installer", app_name);
+    if suite_product.is_some() {
+        info!("Maintaining {:?}, installed as part of a suite", app_name);
+    }
+
+    let locale = matches
+        .subcommand()
+        .1
+        .unwrap_or(&matches)
+        .value_of("locale");
+    strings::init(locale.or(config.locale.as_ref().map(|x| x.as_str())));
+
+    policy::load(&app_name);
+
+    if let Some(url) = matches.value_of("config-url") {
+        if let Err(v) = http::assert_ssl(url) {
+            error!("Invalid configuration URL {:?}: {}", url, v);
+            exit(1);
+        }
+
+        config.target_url = url.to_string();
+    }
+
+    if let Some(mut values) = matches.values_of("generate-chunk-index") {
+        let payload_dir = PathBuf::from(values.next().log_expect("No payload directory"));
+        let asset = values.next().log_expect("No asset name");
+
+        let blob_name = format!("{}.chunks.blob", asset);
+        let index = differential::build_index(&payload_dir, Path::new(&blob_name), &blob_name)
+            .log_expect("Failed to build chunk index");
+
+        let index_file = fs::File::create(format!("{}.chunks.json", asset))
+            .log_expect("Unable to create chunk index");
+        serde_json::to_writer(index_file, &index).log_expect("Unable to write chunk index");
+        return;
+    }
+
+    if let Some(values) = matches.values_of("generate-checksums") {
+        let assets: Vec<PathBuf> = values.map(PathBuf::from).collect();
+        let manifest = hashing::build_manifest(&assets).log_expect("Failed to hash assets");
+
+        fs::write(hashing::MANIFEST_NAME, manifest).log_expect("Unable to write manifest");
+        info!("Wrote {}", hashing::MANIFEST_NAME);
+        return;
+    }
+
+    let signing_config = matches.value_of("signing-config").map(|x| {
+        signing::SigningConfig::load(Path::new(x)).log_expect("Unable to load signing settings")
+    });
+
+    if let Some(files) = matches.values_of("sign") {
+        let signing_config = signing_config.log_expect("No signing settings given");
+
+        for file in files {
+            if let Err(v) = signing::sign(&signing_config, Path::new(file)) {
+                error!("{}", v);
+                exit(1);
+            }
+
+            info!("Signed {}", file);
+        }
+        return;
+    }
+
+    if let Some(mut values) = matches.values_of("generate-msi") {
+        let output = values.next().log_expect("No output file");
+        let options = MsiOptions {
+            version: values.next().log_expect("No version").to_string(),
+            manufacturer: matches
+                .value_of("msi-manufacturer")
+                .unwrap_or(&config.name)
+                .to_string(),
+        };
+
+        let executable =
+            std::env::current_exe().log_expect("Current executable could not be found");
+        if let Err(v) = msi::generate(&config, &executable, Path::new(output), &options) {
+            error!("Failed to generate MSI: {}", v);
+            exit(1);
+        }
+
+        if let Some(signing_config) = signing_config {
+            if let Err(v) = signing::sign(&signing_config, Path::new(output)) {
+                error!("{}", v);
+                exit(1);
+            }
+        }
+
+        info!("Wrote {}", output);
+        return;
+    }
+
+    if let Some(mut values) = matches.values_of("generate-bundle") {
+        let output = values.next().log_expect("No output file");
+        let (file_name, contents) = read_publisher_config(values.next(), &config.target_url);
+
+        info!("Bundling packages for {}", file_name);
+        if let Err(v) = bundle::build(&config, &contents, Path::new(output)) {
+            error!("Failed to generate offline bundle: {}", v);
+            exit(1);
+        }
+
+        info!("Wrote {}", output);
+        return;
+    }
+
+    if matches.is_present("check-config") {
+        let (file_name, contents) =
+            read_publisher_config(matches.value_of("check-config"), &config.target_url);
+
+        let problems = config_check::check_config(&file_name, &contents);
+        if problems.is_empty() {
+            println!("{}: no problems found", file_name);
+            return;
+        }
+
+        for problem in &problems {
+            eprintln!("{}", problem);
+        }
+        eprintln!("{}: {} problem(s) found", file_name, problems.len());
+        exit(1);
+    }
+
+    if let Some(check_matches) = matches.subcommand_matches("check-release") {
+        let version = check_matches
+            .value_of("version")
+            .log_expect("No version specified");
+        let (file_name, contents) =
+            read_publisher_config(check_matches.value_of("config"), &config.target_url);
+        let release_config =
+            Config::from_toml_str(&contents).log_expect("Unable to parse configuration");
+
+        let problems = release_check::check_release(&release_config, version);
+        if problems.is_empty() {
+            println!(
+                "{}: every package resolves for release {}",
+                file_name, version
+            );
+            return;
+        }
+
+        for problem in &problems {
+            eprintln!("{}", problem);
+        }
+        eprintln!(
+            "{}: {} package(s) won't resolve for release {}",
+            file_name,
+            problems.len(),
+            version
+        );
+        exit(1);
+    }
+
+    if let Some(diff_matches) = matches.subcommand_matches("diff-config") {
+        let base = diff_matches
+            .value_of("base")
+            .log_expect("No base specified");
+        let base_contents = fs::read_to_string(base).log_expect("Unable to read base file");
+        let (file_name, contents) =
+            read_publisher_config(diff_matches.value_of("config"), &config.target_url);
+        let new_config =
+            Config::from_toml_str(&contents).log_expect("Unable to parse configuration");
+
+        let result = if base.ends_with(".json") {
+            let database: InstallationDatabase = serde_json::from_str(&base_contents)
+                .log_expect("Unable to parse installation metadata");
+            config_diff::diff_installation(&database, &new_config)
+        } else {
+            let base_config = Config::from_toml_str(&base_contents)
+                .log_expect("Unable to parse base configuration");
+            config_diff::diff_configs(&base_config, &new_config)
+        };
+
+        let changes = match result {
+            Ok(v) => v,
+            Err(v) => {
+                error!("{}", v);
+                exit(1);
+            }
+        };
+
+        for change in &changes {
+            println!("{}", change);
+        }
+        println!(
+            "{}: {} difference(s) from {}",
+            file_name,
+            changes.len(),
+            base
+        );
+        return;
+    }
+
+    let server_port = match matches.value_of("port") {
+        Some(port) => Some(port.parse::<u16>().log_expect("Invalid port specified")),
+        None => config.server_port,
+    };
+    let server_socket = matches
+        .value_of("socket")
+        .map(|x| x.to_string())
+        .or_else(|| config.server_socket.clone())
+        .map(PathBuf::from);
 
     let current_exe = std::env::current_exe().log_expect("Current executable could not be found");
     let current_path = current_exe
@@ -133,6 +806,216 @@ fn main() {
         InstallerFramework::new(config)
     };
 
+    // The maintenance tool keeps using an overridden configuration URL
+    if matches.is_present("config-url") {
+        framework.database.config_url = Some(framework.base_attributes.target_url.clone());
+    } else if let Some(url) = framework.database.config_url.clone() {
+        info!("Using configuration from {:?}", url);
+        framework.base_attributes.target_url = url;
+    }
+
+    // Global arguments given after a subcommand are only seen by the subcommand
+    let operation_matches = matches.subcommand().1.unwrap_or(&matches);
+
+    // The maintenance tool keeps using the environment it was installed from, unless told
+    // otherwise
+    let environment = operation_matches
+        .value_of("environment")
+        .map(|x| x.to_string())
+        .or_else(|| env::var(environments::ENV_VAR).ok())
+        .filter(|x| !x.is_empty());
+    if environment.is_some() {
+        framework.database.environment = environment;
+    }
+
+    if let Some(dir) = matches.value_of("staging-dir") {
+        framework.staging_dir = Some(PathBuf::from(dir));
+    }
+
+    if let Some(path) = operation_matches.value_of("bandwidth-file") {
+        bandwidth::set_usage_path(Path::new(path));
+    }
+
+    if let Some(timeout) = operation_matches.value_of("timeout") {
+        match timeout.parse() {
+            Ok(v) => framework.install_timeout = Some(v),
+            Err(_) => {
+                error!("Invalid timeout {:?}, expected seconds", timeout);
+                exit(headless::EXIT_FAILED);
+            }
+        }
+    }
+
+    if let Some(languages) = operation_matches.values_of("languages") {
+        framework.languages = languages.map(strings::normalize_locale).collect();
+    }
+
+    // Offline bundles can be given explicitly, or shipped alongside the installer
+    let bundle_path = matches.value_of("bundle").map(PathBuf::from).or_else(|| {
+        framework
+            .base_attributes
+            .offline_bundle
+            .as_ref()
+            .map(|x| current_path.join(x))
+            .filter(|x| x.exists())
+    });
+
+    if let Some(path) = bundle_path {
+        let staging_dir = framework.staging_dir.clone();
+        if let Err(v) = bundle::open(
+            &path,
+            &framework.base_attributes,
+            staging_dir.as_ref().map(|x| x.as_path()),
+        ) {
+            error!("Unable to open offline bundle {:?}: {}", path, v);
+            exit(1);
+        }
+    }
+
+    if matches.is_present("history") {
+        let history = serde_json::to_string_pretty(&framework.database.history)
+            .log_expect("Failed to render installation history");
+        println!("{}", history);
+        return;
+    }
+
+    if let Some(output) = matches.value_of("export-audit-log") {
+        let result = audit::export(
+            &framework.base_attributes.name,
+            &framework.database,
+            Path::new(output),
+        );
+
+        if let Err(v) = result {
+            error!("{}", v);
+            exit(1);
+        }
+
+        info!("Wrote {}", output);
+        return;
+    }
+
+    let packages = operation_matches
+        .value_of("packages")
+        .map(|x| x.split(',').map(|x| x.trim().to_string()).collect());
+    let status_file = operation_matches.value_of("status-file");
+
+    // Dialogs asked by hooks during silent operations can be answered ahead of time
+    if let Some(values) = operation_matches.values_of("answer") {
+        let mut answers = HashMap::new();
+        for value in values {
+            match value.find('=') {
+                Some(i) => {
+                    answers.insert(value[..i].trim().to_string(), value[i + 1..].to_string());
+                }
+                None => {
+                    error!("Invalid answer {:?}, which should be ID=VALUE", value);
+                    exit(1);
+                }
+            }
+        }
+
+        prompts::set_answers(answers);
+    }
+
+    if let Some(target) = matches.value_of("extract-only") {
+        if let Some(path) = status_file {
+            headless::set_status_file(path, "extract");
+        }
+
+        let result = headless::extract(&mut framework, target, packages);
+        bundle::close();
+        headless::finish_status(&result);
+
+        match result {
+            Ok(_) => exit(0),
+            Err(v) => {
+                error!("Extraction failed: {}", v);
+                exit(if headless::timed_out() {
+                    headless::EXIT_TIMED_OUT
+                } else {
+                    1
+                });
+            }
+        }
+    }
+
+    let operation = match matches.subcommand_name() {
+        Some(v) => Some(v),
+        None if matches.is_present("silent") => Some("install"),
+        None => None,
+    };
+
+    if let Some(operation) = operation {
+        if let Some(path) = status_file {
+            headless::set_status_file(path, operation);
+        }
+
+        if operation != "install" && !framework.preexisting_install {
+            let error = format!("There is no installation to {}", operation);
+            error!("{}", error);
+            headless::finish_status(&Err(error));
+            exit(headless::EXIT_NOT_INSTALLED);
+        }
+
+        // Operations which need more privileges than we have are handed over to an
+        // elevated copy
+        if !framework.preexisting_install && operation_matches.is_present("all-users") {
+            framework.database.all_users = true;
+        }
+
+        let target = if framework.preexisting_install {
+            framework.install_path.clone()
+        } else {
+            operation_matches
+                .value_of("install-dir")
+                .map(|x| x.to_string())
+                .or_else(|| framework.get_default_path())
+                .map(PathBuf::from)
+        };
+
+        if target.map_or(false, |x| {
+            locations::needs_elevation(&x, framework.database.all_users)
+        }) {
+            headless::run_elevated();
+        }
+
+        let result = match operation {
+            "update" => headless::update(&mut framework),
+            "uninstall" => headless::uninstall(&mut framework),
+            _ => {
+                let options = headless::HeadlessOptions {
+                    install_dir: operation_matches
+                        .value_of("install-dir")
+                        .map(|x| x.to_string()),
+                    packages,
+                    all_users: operation_matches.is_present("all-users"),
+                    msi_product_code: operation_matches
+                        .value_of("msi-product-code")
+                        .map(|x| x.to_string()),
+                };
+
+                headless::install(&mut framework, options)
+            }
+        };
+
+        let result = result.and_then(|_| framework.shutdown());
+        let result = headless::finish_timing(result);
+        headless::finish_status(&result);
+
+        match result {
+            Ok(_) => exit(0),
+            Err(v) => {
+                error!("Silent {} failed: {}", operation, v);
+                exit(if headless::timed_out() {
+                    headless::EXIT_TIMED_OUT
+                } else {
+                    headless::EXIT_FAILED
+                });
+            }
+        }
+    }
+
     // check for existing installs if we are running as a fresh install
     let installed_path = PathBuf::from(framework.get_default_path().unwrap());
     if fresh_install && installed_path.join("metadata.json").exists() {
@@ -149,8 +1032,100 @@ fn main() {
         false
     };
 
+    let needs_elevation = framework.install_path.as_ref().map_or(false, |x| {
+        locations::needs_elevation(x, framework.database.all_users)
+    });
+
+    if fresh_install {
+        // Given when handed over to an elevated copy, so that the choices made carry over
+        if operation_matches.is_present("all-users") {
+            framework.database.all_users = true;
+        }
+        if let Some(dir) = operation_matches.value_of("install-dir") {
+            framework.set_install_dir(&policy::install_dir(dir));
+        }
+        framework.handed_over.packages = operation_matches
+            .value_of("packages")
+            .map(|x| x.split(',').map(|x| x.trim().to_string()).collect());
+        framework.handed_over.desktop_shortcut = operation_matches
+            .value_of("desktop-shortcut")
+            .map(|x| x == "true");
+    } else if needs_elevation && is_launcher {
+        // Asking for permission every time the application is started would be worse
+        // than missing out on updates, so this is only done when there are any
+        let has_updates = framework
+            .fetch_config()
+            .and_then(|_| framework.has_updates());
+        match has_updates {
+            Ok(true) => {
+                info!("Updates are available, which need elevated privileges to install");
+                if let Err(v) = native::run_elevated(&framework.elevation_args(), false) {
+                    error!("Unable to run elevated: {}", v);
+                } else {
+                    exit(0);
+                }
+            }
+            Ok(false) => info!("No updates are available"),
+            Err(v) => warn!("Unable to check for updates: {}", v),
+        }
+
+        if let Err(v) = framework.shutdown() {
+            error!("Unable to launch the application: {}", v);
+            exit(1);
+        }
+        exit(0);
+    } else if needs_elevation {
+        info!("Maintaining this installation needs elevated privileges");
+        match native::run_elevated(&framework.elevation_args(), false) {
+            Ok(_) => exit(0),
+            Err(v) => error!("Unable to run elevated, continuing without: {}", v),
+        }
+    }
+
     // Start up the UI
-    frontend::launch(&app_name, is_launcher, framework);
+    if let Err(v) = frontend::launch(
+        &app_name,
+        is_launcher,
+        server_port,
+        server_socket,
+        framework,
+    ) {
+        error!("Unable to show the user interface: {}", v);
+        exit(1);
+    }
+}
+
+/// Returns the product a maintenance tool was installed as part of a suite as (see
+/// `InstallationDatabase::product_name`), if it was. This is needed before the
+/// installation's metadata is properly loaded, as logging is named after it.
+fn installed_product_name() -> Option<String> {
+    let current_exe = std::env::current_exe().ok()?;
+    let metadata = fs::read(current_exe.parent()?.join("metadata.json")).ok()?;
+    let metadata: serde_json::Value = serde_json::from_slice(&metadata).ok()?;
+
+    metadata
+        .get("product_name")?
+        .as_str()
+        .map(|x| x.to_string())
+}
+
+/// Reads a configuration for publisher tools, from the specified file or otherwise from
+/// the configured target URL. Returns where it was read from, along with its contents.
+fn read_publisher_config(path: Option<&str>, target_url: &str) -> (String, String) {
+    match path {
+        Some(path) => (
+            path.to_string(),
+            fs::read_to_string(path).log_expect("Unable to read configuration file"),
+        ),
+        None => {
+            let contents = http::fetch_bytes(target_url, None, None)
+                .log_expect("Unable to download configuration");
+            (
+                target_url.to_string(),
+                String::from_utf8(contents).log_expect("Configuration is not valid UTF-8"),
+            )
+        }
+    }
 }
 
 fn replace_existing_install(current_exe: &PathBuf, installed_path: &PathBuf) -> Result<(), String> {
@@ -0,0 +1,370 @@
+//! migration.rs
+//!
+//! Takes over installations made by another installer framework (NSIS, Inno Setup or
+//! Squirrel), so that products switching to LiftInstall can upgrade their existing users
+//! in place. Each framework to migrate from is listed with `migrate_from` in the
+//! configuration:
+//!
+//! ```toml
+//! [[migrate_from]]
+//! framework = "inno"
+//! uninstall_key = "{8A2B6C1E-0000-4D4B-9C3F-6B2E3A1F0D77}_is1"
+//! package = "MyApp"
+//! keep = ["^config/", "\\.sav$"]
+//! ```
+//!
+//! Installations are found through their "Apps & Features" uninstall entry, which gives
+//! where they are and which version they are at. Fresh installs default to going there,
+//! and those which do adopt everything in the directory into the configured package,
+//! other than paths matching `keep` (such as user data). That package is then installed
+//! over it as an update would be, which removes what the old version left behind,
+//! including the other framework's uninstaller. The Start Menu and desktop shortcuts
+//! which point into the directory are adopted too, so that they are replaced by the
+//! package's own. Once this has succeeded, the other framework's uninstall entry is
+//! removed, leaving only ours.
+//!
+//! These frameworks only exist on Windows, so nothing is ever found elsewhere.
+
+use std::collections::HashMap;
+use std::fs::{read, read_dir};
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use audit::{self, AuditAction};
+
+use config::{Config, ForeignFramework, ForeignInstall};
+
+use installer::{InstallerFramework, LocalInstallation};
+
+use native;
+
+use sources::types::Version;
+
+use tasks::register_uninstaller::uninstall_key;
+
+/// Where uninstall entries are kept, within HKLM or HKCU. 32-bit installers registering
+/// for all users on 64-bit Windows have theirs redirected to the second.
+const UNINSTALL_KEYS: &[&str] = &[
+    "Software\\Microsoft\\Windows\\CurrentVersion\\Uninstall",
+    "Software\\WOW6432Node\\Microsoft\\Windows\\CurrentVersion\\Uninstall",
+];
+
+/// An installation made by another installer framework.
+#[derive(Debug, Clone)]
+pub struct FoundInstall {
+    pub source: ForeignInstall,
+    /// The uninstall entry which describes it
+    pub key: String,
+    pub location: PathBuf,
+    pub version: Option<String>,
+    /// If it was installed for all users, rather than just the current one
+    pub all_users: bool,
+}
+
+impl FoundInstall {
+    /// Returns if this is the installation at a path.
+    pub fn is_at(&self, path: &Path) -> bool {
+        match (self.location.canonicalize(), path.canonicalize()) {
+            (Ok(location), Ok(path)) => location == path,
+            _ => self.location == path,
+        }
+    }
+}
+
+/// Works out where an installation is from its uninstall entry. Not every NSIS installer
+/// records an `InstallLocation`, but all of them have an uninstaller within it.
+fn find_location(key: &str) -> Option<PathBuf> {
+    let value = |name: &str| {
+        native::read_registry_string(&format!("{}\\{}", key, name))
+            .ok()
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty())
+    };
+
+    if let Some(location) = value("InstallLocation") {
+        return Some(PathBuf::from(location.trim_matches('"')));
+    }
+
+    // e.g. `"C:\Program Files\App\uninst.exe" /S`, or Squirrel's `Update.exe --uninstall`
+    let uninstaller = value("UninstallString")?;
+    let uninstaller = if uninstaller.starts_with('"') {
+        uninstaller[1..].split('"').next()?.to_string()
+    } else {
+        let end = uninstaller
+            .to_lowercase()
+            .find(".exe")
+            .map_or(uninstaller.len(), |x| x + 4);
+        uninstaller[..end].to_string()
+    };
+
+    Path::new(&uninstaller).parent().map(|x| x.to_owned())
+}
+
+/// Looks for an installation made by a framework.
+fn find_one(source: &ForeignInstall) -> Option<FoundInstall> {
+    // Squirrel only ever installs for the current user
+    let roots: &[&str] = match source.framework {
+        ForeignFramework::Squirrel => &["HKCU"],
+        ForeignFramework::Nsis | ForeignFramework::Inno => &["HKCU", "HKLM"],
+    };
+
+    for root in roots {
+        for uninstall in UNINSTALL_KEYS {
+            let key = format!("{}\\{}\\{}", root, uninstall, source.uninstall_key);
+            if !native::registry_key_exists(&key) {
+                continue;
+            }
+
+            let location = match find_location(&key) {
+                Some(v) => v,
+                None => {
+                    warn!("Ignoring uninstall entry {:?}, as it has no location", key);
+                    continue;
+                }
+            };
+
+            if !location.is_dir() {
+                warn!(
+                    "Ignoring uninstall entry {:?}, as {:?} no longer exists",
+                    key, location
+                );
+                continue;
+            }
+
+            let version = native::read_registry_string(&format!("{}\\DisplayVersion", key))
+                .ok()
+                .map(|x| x.trim().to_string())
+                .filter(|x| !x.is_empty());
+
+            return Some(FoundInstall {
+                source: source.clone(),
+                all_users: *root == "HKLM",
+                key,
+                location,
+                version,
+            });
+        }
+    }
+
+    None
+}
+
+/// Finds the first installation to migrate from, if there is one on this machine.
+pub fn find(config: &Config) -> Option<FoundInstall> {
+    if !cfg!(windows) {
+        return None;
+    }
+
+    let found = config.migrate_from.iter().find_map(find_one)?;
+    info!(
+        "Found {:?} installation at {:?} (version {:?}), from {:?}",
+        found.source.framework, found.location, found.version, found.key
+    );
+
+    Some(found)
+}
+
+/// Recursively lists what is in a directory, relative to the root, as paths with forward
+/// slashes. Directories are listed after what they contain.
+fn list_files(
+    root: &Path,
+    dir: &Path,
+    keep: &[Regex],
+    files: &mut Vec<String>,
+) -> Result<(), String> {
+    let entries = read_dir(dir).map_err(|x| format!("Unable to read {:?}: {:?}", dir, x))?;
+
+    for entry in entries {
+        let entry = entry.map_err(|x| format!("Unable to read {:?}: {:?}", dir, x))?;
+        let path = entry.path();
+
+        let relative = path
+            .strip_prefix(root)
+            .map_err(|x| format!("Unable to relativize {:?}: {:?}", path, x))?
+            .to_string_lossy()
+            .replace('\\', "/");
+
+        // Links to directories are adopted as they are, rather than followed
+        let is_dir = entry.file_type().map(|x| x.is_dir()).unwrap_or(false);
+
+        // Directories are matched with a trailing slash, so that `^config/` keeps all of it
+        let name = if is_dir {
+            format!("{}/", relative)
+        } else {
+            relative.clone()
+        };
+        if keep.iter().any(|x| x.is_match(&name)) {
+            info!("Leaving {:?} in place, as it is to be kept", name);
+            continue;
+        }
+
+        if is_dir {
+            list_files(root, &path, keep, files)?;
+        }
+
+        files.push(relative);
+    }
+
+    Ok(())
+}
+
+/// Returns if a shortcut points into a directory. Shortcuts aren't parsed, but the paths
+/// within them are kept as both ANSI and UTF-16 text, so are looked for as either.
+fn points_into(shortcut: &Path, location: &Path) -> bool {
+    let data = match read(shortcut) {
+        Ok(v) => v,
+        Err(v) => {
+            warn!("Unable to read shortcut {:?}: {:?}", shortcut, v);
+            return false;
+        }
+    };
+
+    // Matched with a trailing separator, so that `C:\App` doesn't match `C:\Apple`
+    let location =
+        format!("{}\\", location.to_string_lossy().trim_end_matches('\\')).to_lowercase();
+
+    if String::from_utf8_lossy(&data)
+        .to_lowercase()
+        .contains(&location)
+    {
+        return true;
+    }
+
+    (0..2).any(|offset| {
+        let wide: Vec<u16> = data[offset.min(data.len())..]
+            .chunks(2)
+            .filter(|x| x.len() == 2)
+            .map(|x| u16::from_le_bytes([x[0], x[1]]))
+            .collect();
+
+        String::from_utf16_lossy(&wide)
+            .to_lowercase()
+            .contains(&location)
+    })
+}
+
+/// Finds the shortcuts within a directory which point into an installation, as absolute
+/// paths, returning if there was nothing else. If `folders` is set, those directly within
+/// it are looked in too (e.g. a product's folder in the Start Menu), and those with nothing
+/// else in them are listed after what they contain, so that they are removed along with
+/// it.
+fn find_shortcuts(dir: &Path, location: &Path, folders: bool, shortcuts: &mut Vec<String>) -> bool {
+    let entries = match read_dir(dir) {
+        Ok(v) => v,
+        Err(_) => return false,
+    };
+
+    let mut total = 0;
+    let mut found = 0;
+    for entry in entries.filter_map(|x| x.ok()) {
+        total += 1;
+
+        let path = entry.path();
+        let is_dir = entry.file_type().map(|x| x.is_dir()).unwrap_or(false);
+
+        if is_dir && folders {
+            if find_shortcuts(&path, location, false, shortcuts) {
+                shortcuts.push(path.to_string_lossy().to_string());
+                found += 1;
+            }
+        } else if !is_dir
+            && path
+                .extension()
+                .map_or(false, |x| x.to_string_lossy().to_lowercase() == "lnk")
+            && points_into(&path, location)
+        {
+            shortcuts.push(path.to_string_lossy().to_string());
+            found += 1;
+        }
+    }
+
+    found > 0 && found == total
+}
+
+/// Adopts the files of an installation into its package, so that installing the package
+/// replaces them.
+pub fn adopt(framework: &mut InstallerFramework, found: &FoundInstall) -> Result<(), String> {
+    let keep = found
+        .source
+        .keep
+        .iter()
+        .map(|x| Regex::new(x).map_err(|v| format!("Invalid keep regex {:?}: {}", x, v)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut files = Vec::new();
+    list_files(&found.location, &found.location, &keep, &mut files)?;
+
+    info!(
+        "Adopting {} files from {:?} into {:?}",
+        files.len(),
+        found.location,
+        found.source.package
+    );
+
+    let mut shortcuts = Vec::new();
+    if let Some(dir) = native::get_start_menu_dir(found.all_users) {
+        find_shortcuts(&dir, &found.location, true, &mut shortcuts);
+    }
+    if let Some(dir) = native::get_desktop_dir(found.all_users) {
+        find_shortcuts(&dir, &found.location, false, &mut shortcuts);
+    }
+    info!("Adopting shortcuts {:?}", shortcuts);
+
+    let version = found
+        .version
+        .as_ref()
+        .and_then(|x| Version::parse(x))
+        .unwrap_or_else(|| Version::new_number(0));
+
+    framework
+        .database
+        .packages
+        .retain(|x| x.name != found.source.package);
+    framework.database.packages.push(LocalInstallation {
+        name: found.source.package.clone(),
+        version,
+        files,
+        shortcuts,
+        root: None,
+        overwritten: Vec::new(),
+        hashes: HashMap::new(),
+        requires: Vec::new(),
+        suspect: None,
+        pre_uninstall: Vec::new(),
+    });
+
+    // Our own uninstall entry replaces theirs, so belongs in the same place
+    framework.database.all_users = found.all_users;
+
+    Ok(())
+}
+
+/// Removes the other framework's uninstall entry, once the installation has been taken
+/// over. Failures leave a second entry behind, so are only logged.
+pub fn remove_uninstall_entry(framework: &InstallerFramework, found: &FoundInstall) {
+    // NSIS entries are often named after the product, as ours are, so have been
+    // overwritten with ours already
+    if found.key.to_lowercase() == uninstall_key(framework).to_lowercase() {
+        info!("The previous installer's uninstall entry has been replaced with ours");
+        return;
+    }
+
+    info!(
+        "Removing {:?} uninstall entry {:?}",
+        found.source.framework, found.key
+    );
+
+    match native::delete_registry_key(&found.key) {
+        Ok(_) => audit::record(
+            framework,
+            AuditAction::RegistryDeleted {
+                key: found.key.clone(),
+            },
+        ),
+        Err(v) => warn!(
+            "Unable to remove the previous installer's uninstall entry: {}",
+            v
+        ),
+    }
+}
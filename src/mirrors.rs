@@ -0,0 +1,110 @@
+//! mirrors.rs
+//!
+//! Picks which of a file's hosts (its source's own, and its mirrors) to download from
+//! first. Rather than going through them in the order they are configured, each is
+//! probed with a HEAD request in parallel, and the ones which respond are tried fastest
+//! first. Hosts which didn't respond are kept as a last resort, in their original order.
+//!
+//! Results are remembered for the session, as the same hosts serve every file of a
+//! source. When a host fails part way through a download, those left are probed again,
+//! as whatever took it down may well have affected others.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use url::Url;
+
+use http;
+
+use logging::LoggingErrors;
+
+lazy_static! {
+    /// How long each host took to respond, or `None` if it didn't, by host.
+    static ref LATENCIES: Mutex<HashMap<String, Option<Duration>>> = Mutex::new(HashMap::new());
+}
+
+/// Identifies the host serving a URL, which results are kept for.
+fn host_of(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|x| {
+            let host = x.host_str()?.to_string();
+            Some(match x.port() {
+                Some(port) => format!("{}:{}", host, port),
+                None => host,
+            })
+        })
+        .unwrap_or_else(|| url.to_string())
+}
+
+/// Measures how long a host takes to respond to a HEAD request for a URL.
+fn probe(url: &str) -> Option<Duration> {
+    let start = Instant::now();
+
+    match http::get_content_length(url, None) {
+        Ok(_) => {
+            let latency = start.elapsed();
+            info!("Mirror probe of {:?} responded in {:?}", url, latency);
+            Some(latency)
+        }
+        Err(v) => {
+            warn!("Mirror probe of {:?} failed: {}", url, v);
+            None
+        }
+    }
+}
+
+/// Probes hosts (in parallel) which haven't been probed yet this session, or all of them
+/// if `fresh` is set.
+fn probe_all(urls: &[String], fresh: bool) {
+    let pending: Vec<String> = {
+        let latencies = LATENCIES.lock().log_expect("Mirror state has been dirtied");
+        urls.iter()
+            .filter(|x| fresh || !latencies.contains_key(&host_of(x)))
+            .cloned()
+            .collect()
+    };
+
+    let probes: Vec<_> = pending
+        .into_iter()
+        .map(|url| thread::spawn(move || (host_of(&url), probe(&url))))
+        .collect();
+
+    for probe in probes {
+        if let Ok((host, latency)) = probe.join() {
+            LATENCIES
+                .lock()
+                .log_expect("Mirror state has been dirtied")
+                .insert(host, latency);
+        }
+    }
+}
+
+/// Orders URLs for the same file so that the fastest responding host comes first, with
+/// hosts which didn't respond last. Nothing is probed unless there is a choice to make.
+pub fn rank(urls: &mut [String], fresh: bool) {
+    if urls.len() < 2 {
+        return;
+    }
+
+    probe_all(urls, fresh);
+
+    let latencies = LATENCIES.lock().log_expect("Mirror state has been dirtied");
+
+    // Sorting is stable, so unresponsive hosts stay in the order they were configured
+    urls.sort_by_key(|x| match latencies.get(&host_of(x)) {
+        Some(&Some(latency)) => (false, latency),
+        _ => (true, Duration::from_secs(0)),
+    });
+}
+
+/// Records that a host failed part way through a download, so that it is tried last until
+/// it is probed again.
+pub fn mark_failed(url: &str) {
+    LATENCIES
+        .lock()
+        .log_expect("Mirror state has been dirtied")
+        .insert(host_of(url), None);
+}
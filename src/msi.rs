@@ -0,0 +1,219 @@
+//! msi.rs
+//!
+//! Publisher usage - wraps the installer in a thin MSI for deployment systems (such as
+//! Intune or SCCM) which expect one. The MSI carries this executable, runs it silently
+//! for all users when installed, and runs the installed maintenance tool when removed.
+//!
+//! The MSI is described as WiX source, which is compiled with the WiX Toolset (v3) if
+//! it can be found.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use config::BaseAttributes;
+
+use hashing::sha256_hex;
+
+/// What to put in the MSI, beyond what the installer already knows.
+pub struct MsiOptions {
+    /// Version of the MSI, as up to four dot separated numbers
+    pub version: String,
+    /// Shown as the publisher in "Apps & Features"
+    pub manufacturer: String,
+}
+
+/// Escapes text for including in XML attributes.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Derives a GUID from a name, so that every MSI built for the same product shares an
+/// upgrade code and replaces earlier versions.
+fn stable_guid(name: &str) -> String {
+    let hash = sha256_hex(format!("liftinstall-msi:{}", name).as_bytes()).to_uppercase();
+
+    // Marked as a name-based (version 5) GUID, with the RFC 4122 variant
+    format!(
+        "{}-{}-5{}-{}{}-{}",
+        &hash[0..8],
+        &hash[8..12],
+        &hash[13..16],
+        ['8', '9', 'A', 'B'][usize::from_str_radix(&hash[16..17], 16).unwrap_or(0) % 4],
+        &hash[17..20],
+        &hash[20..32]
+    )
+}
+
+/// Checks that a version can be used by Windows Installer, which only compares numbers.
+fn check_version(version: &str) -> Result<(), String> {
+    let parts: Vec<&str> = version.trim_start_matches('v').split('.').collect();
+    let valid = parts.len() <= 4
+        && parts
+            .iter()
+            .enumerate()
+            .all(|(i, x)| match x.parse::<u32>() {
+                // Major and minor versions are limited to 255, builds to 65535
+                Ok(v) => v <= if i < 2 { 255 } else { 65535 },
+                Err(_) => false,
+            });
+
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "MSI versions have to be up to four numbers (e.g. 1.2.3), not {:?}",
+            version
+        ))
+    }
+}
+
+/// Describes the MSI as WiX source.
+pub fn build_source(
+    attributes: &BaseAttributes,
+    executable: &Path,
+    options: &MsiOptions,
+) -> Result<String, String> {
+    check_version(&options.version)?;
+
+    let name = escape(&attributes.name);
+    let win64 = if cfg!(target_pointer_width = "64") {
+        "yes"
+    } else {
+        "no"
+    };
+
+    // `InstallLocation` is registered by `RegisterUninstallerTask`. The install directory
+    // is passed last, NSIS-style, as a trailing backslash would escape a closing quote.
+    Ok(format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<Wix xmlns="http://schemas.microsoft.com/wix/2006/wi">
+  <Product Id="*" Name="{name}" Language="1033" Version="{version}"
+           Manufacturer="{manufacturer}" UpgradeCode="{{{upgrade_code}}}">
+    <Package InstallerVersion="500" Compressed="yes" InstallScope="perMachine"
+             InstallPrivileges="elevated" Description="{name} installer" />
+    <MajorUpgrade DowngradeErrorMessage="A newer version of {name} is already installed." />
+
+    <Binary Id="LiftInstall" SourceFile="{executable}" />
+
+    <Property Id="ARPNOMODIFY" Value="1" />
+    <Property Id="ARPNOREPAIR" Value="1" />
+    <Property Id="INSTALLDIR" Secure="yes" />
+    <Property Id="PACKAGES" Secure="yes" />
+    <Property Id="LIFTINSTALLDIR">
+      <RegistrySearch Id="FindInstallLocation" Root="HKLM" Win64="{win64}"
+                      Key="Software\Microsoft\Windows\CurrentVersion\Uninstall\{name}"
+                      Name="InstallLocation" Type="raw" />
+    </Property>
+
+    <Directory Id="TARGETDIR" Name="SourceDir">
+      <Component Id="Registration" Guid="{{{component_guid}}}" Win64="{win64}">
+        <RegistryValue Root="HKLM" Key="Software\{name}" Name="MsiProductCode"
+                       Type="string" Value="[ProductCode]" KeyPath="yes" />
+      </Component>
+    </Directory>
+
+    <Feature Id="Main" Level="1">
+      <ComponentRef Id="Registration" />
+    </Feature>
+
+    <CustomAction Id="SetPackageArgs" Property="PACKAGEARGS" Value=" --packages &quot;[PACKAGES]&quot;" />
+    <CustomAction Id="SetInstallDirArgs" Property="INSTALLDIRARGS" Value=" /D=[INSTALLDIR]" />
+    <CustomAction Id="SetUninstallTool" Property="LIFTINSTALLTOOL" Value="[LIFTINSTALLDIR]\maintenancetool.exe" />
+    <CustomAction Id="RunInstall" BinaryKey="LiftInstall" Execute="deferred" Impersonate="no"
+                  Return="check"
+                  ExeCommand="install --all-users --msi-product-code [ProductCode][PACKAGEARGS][INSTALLDIRARGS]" />
+    <CustomAction Id="RunUninstall" Property="LIFTINSTALLTOOL" Execute="deferred" Impersonate="no"
+                  Return="check" ExeCommand="uninstall" />
+
+    <InstallExecuteSequence>
+      <Custom Action="SetPackageArgs" After="CostFinalize">NOT Installed AND PACKAGES</Custom>
+      <Custom Action="SetInstallDirArgs" After="SetPackageArgs">NOT Installed AND INSTALLDIR</Custom>
+      <Custom Action="SetUninstallTool" After="SetInstallDirArgs">REMOVE~="ALL" AND LIFTINSTALLDIR</Custom>
+      <Custom Action="RunUninstall" After="InstallInitialize">REMOVE~="ALL" AND NOT UPGRADINGPRODUCTCODE AND LIFTINSTALLTOOL</Custom>
+      <Custom Action="RunInstall" Before="InstallFinalize">NOT Installed AND NOT REMOVE</Custom>
+    </InstallExecuteSequence>
+  </Product>
+</Wix>
+"#,
+        name = name,
+        version = escape(options.version.trim_start_matches('v')),
+        manufacturer = escape(&options.manufacturer),
+        upgrade_code = stable_guid(&attributes.name),
+        component_guid = stable_guid(&format!("{}:registration", attributes.name)),
+        win64 = win64,
+        executable = escape(&executable.to_string_lossy()),
+    ))
+}
+
+/// Finds a WiX Toolset executable, which its installer points to with `WIX`.
+fn wix_tool(name: &str) -> PathBuf {
+    match ::std::env::var("WIX") {
+        Ok(dir) => Path::new(&dir).join("bin").join(format!("{}.exe", name)),
+        Err(_) => PathBuf::from(name),
+    }
+}
+
+/// Runs a WiX Toolset executable.
+fn run_wix(name: &str, args: &[&str]) -> Result<(), String> {
+    let tool = wix_tool(name);
+    info!("Running {:?} {:?}", tool, args);
+
+    let status = Command::new(&tool).args(args).status().map_err(|x| {
+        if x.kind() == ErrorKind::NotFound {
+            "The WiX Toolset (v3) wasn't found - install it, or set WIX to where it is".to_string()
+        } else {
+            format!("Unable to run {:?}: {:?}", tool, x)
+        }
+    })?;
+
+    if !status.success() {
+        return Err(format!("{} failed ({})", name, status));
+    }
+
+    Ok(())
+}
+
+/// Writes the WiX source for an MSI wrapping the specified executable next to `output`,
+/// then compiles it into `output`.
+pub fn generate(
+    attributes: &BaseAttributes,
+    executable: &Path,
+    output: &Path,
+    options: &MsiOptions,
+) -> Result<(), String> {
+    let source = build_source(attributes, executable, options)?;
+
+    let source_path = output.with_extension("wxs");
+    let object_path = output.with_extension("wixobj");
+    fs::write(&source_path, source)
+        .map_err(|x| format!("Unable to write {:?}: {:?}", source_path, x))?;
+    info!("Wrote {:?}", source_path);
+
+    let arch = if cfg!(target_pointer_width = "64") {
+        "x64"
+    } else {
+        "x86"
+    };
+
+    let source_arg = source_path.to_string_lossy();
+    let object_arg = object_path.to_string_lossy();
+    let output_arg = output.to_string_lossy();
+
+    run_wix(
+        "candle",
+        &["-nologo", "-arch", arch, "-out", &object_arg, &source_arg],
+    )
+    .and_then(|_| run_wix("light", &["-nologo", "-out", &output_arg, &object_arg]))
+    .map_err(|x| format!("{} ({:?} can be built manually)", x, source_path))?;
+
+    if let Err(v) = fs::remove_file(&object_path) {
+        warn!("Unable to remove {:?}: {:?}", object_path, v);
+    }
+
+    Ok(())
+}
@@ -7,6 +7,32 @@ pub struct Process {
     pub name: String,
 }
 
+/// An application which has files open, as found by `RestartSession`.
+#[derive(Debug, Clone)]
+pub struct LockingApplication {
+    pub pid: u32,
+    pub name: String,
+    /// If the application can be restarted automatically once it has been closed
+    pub restartable: bool,
+}
+
+/// A graphics adapter, as found by `get_gpus`.
+#[derive(Debug, Clone)]
+pub struct Gpu {
+    pub name: String,
+    /// PCI vendor ID, e.g. 0x10de for NVIDIA
+    pub vendor_id: Option<u16>,
+    /// Version of the driver in use, as the vendor's own tools show it where known
+    pub driver_version: Option<String>,
+}
+
+/// A value which can be written to the registry.
+#[derive(Debug)]
+pub enum RegistryValue {
+    String(String),
+    Dword(u32),
+}
+
 #[cfg(windows)]
 mod natives {
     #![allow(non_upper_case_globals)]
@@ -18,18 +44,86 @@ mod natives {
     use logging::LoggingErrors;
 
     use std::env;
+    use std::fs;
+    use std::io::Write;
+    use std::iter;
+    use std::mem;
+    use std::os::windows::io::AsRawHandle;
+    use std::path::{Component, Path, PathBuf, Prefix};
+    use std::process;
+    use std::process::{Command, Stdio};
+    use std::ptr;
+    use std::sync::Mutex;
 
-    use winapi::shared::minwindef::{DWORD, FALSE, MAX_PATH};
+    use winapi::shared::minwindef::{BYTE, DWORD, FALSE, LPVOID, MAX_PATH, TRUE};
 
     use winapi::shared::winerror::HRESULT;
-    use winapi::um::processthreadsapi::OpenProcess;
+    use winapi::um::handleapi::CloseHandle;
+    use winapi::um::processthreadsapi::{
+        GetCurrentProcess, GetCurrentThread, GetExitCodeProcess, OpenProcess, OpenProcessToken,
+        SetThreadPriority,
+    };
+    use winapi::um::shellapi::{ShellExecuteExW, SEE_MASK_NOCLOSEPROCESS, SHELLEXECUTEINFOW};
+    use winapi::um::synchapi::WaitForSingleObject;
+    use winapi::um::winbase::{INFINITE, THREAD_MODE_BACKGROUND_BEGIN};
+    use winapi::um::winuser::{
+        SystemParametersInfoW, HCF_HIGHCONTRASTON, HIGHCONTRASTW, SPI_GETCLIENTAREAANIMATION,
+        SPI_GETHIGHCONTRAST, SW_SHOWNORMAL,
+    };
     use winapi::um::psapi::{
         EnumProcessModulesEx, GetModuleFileNameExW, K32EnumProcesses, LIST_MODULES_ALL,
     };
+    use winapi::um::securitybaseapi::GetTokenInformation;
     use winapi::um::winnt::{
-        HANDLE, PROCESS_QUERY_INFORMATION, PROCESS_TERMINATE, PROCESS_VM_READ,
+        TokenElevation, HANDLE, PROCESS_QUERY_INFORMATION, PROCESS_TERMINATE, PROCESS_VM_READ,
+        TOKEN_ELEVATION, TOKEN_QUERY,
+    };
+    use winapi::shared::minwindef::HKEY;
+    use winapi::um::winbase::{GetSystemPowerStatus, GlobalFree, SYSTEM_POWER_STATUS};
+    use winapi::um::errhandlingapi::GetLastError;
+    use winapi::um::jobapi2::{AssignProcessToJobObject, TerminateJobObject};
+    use winapi::um::winbase::CreateJobObjectW;
+    use winapi::um::fileapi::{GetDiskFreeSpaceExW, GetDriveTypeW};
+    use winapi::um::ioapiset::DeviceIoControl;
+    use winapi::um::minwinbase::FileAllocationInfo;
+    use winapi::um::winbase::{SetFileInformationByHandle, FILE_ALLOCATION_INFO};
+    use winapi::um::winioctl::FSCTL_SET_SPARSE;
+    use winapi::um::winbase::DRIVE_REMOTE;
+    use winapi::um::winbase::{MoveFileExW, MOVEFILE_DELAY_UNTIL_REBOOT};
+    use winapi::um::winnt::ULARGE_INTEGER;
+    use winapi::shared::winerror::{ERROR_CANCELLED, ERROR_MORE_DATA, ERROR_SUCCESS};
+    use winapi::um::restartmanager::{
+        RmEndSession, RmGetList, RmRegisterResources, RmRestart, RmShutdown, RmStartSession,
+        CCH_RM_SESSION_KEY, RM_PROCESS_INFO,
+    };
+    use winapi::um::winhttp::{
+        WinHttpCloseHandle, WinHttpConnect, WinHttpGetIEProxyConfigForCurrentUser,
+        WinHttpGetProxyForUrl, WinHttpOpen, WinHttpOpenRequest, WinHttpQueryAuthSchemes,
+        WinHttpQueryHeaders, WinHttpReadData, WinHttpReceiveResponse, WinHttpSendRequest,
+        WinHttpSetCredentials, WinHttpSetOption, HINTERNET, WINHTTP_ACCESS_TYPE_DEFAULT_PROXY,
+        WINHTTP_ACCESS_TYPE_NAMED_PROXY, WINHTTP_ACCESS_TYPE_NO_PROXY, WINHTTP_AUTH_SCHEME_NEGOTIATE,
+        WINHTTP_AUTH_SCHEME_NTLM, WINHTTP_AUTH_TARGET_PROXY, WINHTTP_AUTOLOGON_SECURITY_LEVEL_LOW,
+        WINHTTP_AUTOPROXY_AUTO_DETECT, WINHTTP_AUTOPROXY_CONFIG_URL, WINHTTP_AUTOPROXY_OPTIONS,
+        WINHTTP_AUTO_DETECT_TYPE_DHCP, WINHTTP_AUTO_DETECT_TYPE_DNS_A,
+        WINHTTP_CURRENT_USER_IE_PROXY_CONFIG, WINHTTP_FLAG_SECURE, WINHTTP_OPTION_AUTOLOGON_POLICY,
+        WINHTTP_OPTION_PROXY, WINHTTP_PROXY_INFO, WINHTTP_QUERY_CONTENT_LENGTH,
+        WINHTTP_QUERY_FLAG_NUMBER, WINHTTP_QUERY_STATUS_CODE,
+    };
+
+    use url::Url;
+    use winapi::um::winnt::{KEY_READ, KEY_WRITE, REG_DWORD, REG_OPTION_NON_VOLATILE, REG_SZ};
+    use winapi::um::winnls::GetUserDefaultLocaleName;
+    use winapi::um::winnt::LOCALE_NAME_MAX_LENGTH;
+    use winapi::um::winreg::{
+        RegCloseKey, RegCreateKeyExW, RegDeleteKeyW, RegDeleteTreeW, RegGetValueW,
+        RegOpenKeyExW, RegSetValueExW, HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE,
+        RRF_RT_REG_EXPAND_SZ, RRF_RT_REG_SZ,
     };
 
+    use winapi::shared::minwindef::{LPCVOID, UINT};
+    use winapi::um::verrsrc::VS_FIXEDFILEINFO;
+    use winapi::um::winver::{GetFileVersionInfoSizeW, GetFileVersionInfoW, VerQueryValueW};
+
     use widestring::{U16CString};
 
     extern "C" {
@@ -63,14 +157,19 @@ mod natives {
         args: &str,
         working_dir: &str,
         exe_path: &str,
+        all_users: bool,
     ) -> Result<String, String> {
-        let mut cmd_path = [0u16; MAX_PATH + 1];
-        let _result = unsafe { getDesktopFolder(cmd_path.as_mut_ptr()) };
-        let source_path = format!(
-            "{}\\{}.lnk",
-            String::from_utf16_lossy(&cmd_path[..count_u16(&cmd_path)]).as_str(),
-            name
-        );
+        let desktop = if all_users {
+            format!(
+                "{}\\Desktop",
+                env::var("PUBLIC").log_expect("PUBLIC is bad, apparently")
+            )
+        } else {
+            let mut cmd_path = [0u16; MAX_PATH + 1];
+            let _result = unsafe { getDesktopFolder(cmd_path.as_mut_ptr()) };
+            String::from_utf16_lossy(&cmd_path[..count_u16(&cmd_path)])
+        };
+        let source_path = format!("{}\\{}.lnk", desktop, name);
         create_shortcut_inner(source_path, name, description, target, args, working_dir, exe_path)
     }
 
@@ -83,11 +182,17 @@ mod natives {
         args: &str,
         working_dir: &str,
         exe_path: &str,
+        all_users: bool,
     ) -> Result<String, String> {
+        // The all users start menu lives alongside other machine-wide data
+        let root = if all_users {
+            env::var("ProgramData").log_expect("ProgramData is bad, apparently")
+        } else {
+            env::var("APPDATA").log_expect("APPDATA is bad, apparently")
+        };
         let source_file = format!(
             "{}\\Microsoft\\Windows\\Start Menu\\Programs\\{}.lnk",
-            env::var("APPDATA").log_expect("APPDATA is bad, apparently"),
-            name
+            root, name
         );
         create_shortcut_inner(source_file, name, description, target, args, working_dir, exe_path)
     }
@@ -150,53 +255,118 @@ mod natives {
         pos
     }
 
-    /// Cleans up the installer
+    /// Returns the name of the user running the installer.
+    pub fn get_current_user() -> String {
+        env::var("USERNAME").unwrap_or_default()
+    }
+
+    /// Schedules a file (or empty directory) to be deleted when the machine next starts.
+    /// This needs administrator rights.
+    #[allow(unsafe_code)]
+    fn delete_on_reboot(path: &Path) -> Result<(), String> {
+        let wide = U16CString::from_os_str(path.as_os_str())
+            .map_err(|x| format!("Invalid path {:?}: {:?}", path, x))?;
+
+        let result =
+            unsafe { MoveFileExW(wide.as_ptr(), ptr::null(), MOVEFILE_DELAY_UNTIL_REBOOT) };
+        if result == 0 {
+            return Err(format!(
+                "Unable to schedule {:?} for deletion (error {})",
+                path,
+                unsafe { GetLastError() }
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Cleans up the installer: the maintenance tool, its log and the install directory
+    /// (if nothing else is left in it) are deleted once this process exits.
+    ///
+    /// A running executable can't be deleted, so this leaves behind a batch script which
+    /// keeps trying until it can, then deletes itself. If the script can't be started,
+    /// the files are deleted on the next reboot instead.
     pub fn burn_on_exit(app_name: &str) {
         let current_exe = env::current_exe().log_expect("Current executable could not be found");
         let path = current_exe
             .parent()
             .log_expect("Parent directory of executable could not be found");
+        let log = path.join(format!("{}_installer.log", app_name));
 
-        // Need a cmd workaround here.
-        let tool = path.join("maintenancetool.exe");
-        let tool = tool
-            .to_str()
-            .log_expect("Unable to convert tool path to string")
-            .replace(" ", "\\ ");
+        // Gives up after about a minute, in case something else has the tool open
+        let script = format!(
+            "@echo off\r\n\
+             chcp 65001 >nul\r\n\
+             cd /D \"%TEMP%\"\r\n\
+             set tries=0\r\n\
+             :retry\r\n\
+             del /F /Q \"{exe}\" >nul 2>&1\r\n\
+             if not exist \"{exe}\" goto cleanup\r\n\
+             set /A tries+=1\r\n\
+             if %tries% GEQ 60 goto cleanup\r\n\
+             ping -n 2 127.0.0.1 >nul\r\n\
+             goto retry\r\n\
+             :cleanup\r\n\
+             del /F /Q \"{log}\" >nul 2>&1\r\n\
+             rd \"{dir}\" >nul 2>&1\r\n\
+             (goto) 2>nul & del \"%~f0\"\r\n",
+            exe = current_exe.display(),
+            log = log.display(),
+            dir = path.display()
+        );
 
-        let log = path.join(format!("{}_installer.log", app_name));
-        let log = log
-            .to_str()
-            .log_expect("Unable to convert log path to string")
-            .replace(" ", "\\ ");
+        let script_path =
+            env::temp_dir().join(format!("{}_cleanup_{}.cmd", app_name, process::id()));
 
-        let target_arguments = format!("/C choice /C Y /N /D Y /T 2 & del {} {}", tool, log);
+        let spawned = fs::write(&script_path, script)
+            .map_err(|x| format!("Unable to write cleanup script: {:?}", x))
+            .and_then(|_| {
+                let target_arguments = format!("/C \"{}\"", script_path.display());
+                info!("Launching cmd with {:?}", target_arguments);
 
-        info!("Launching cmd with {:?}", target_arguments);
+                // Needs to use `spawnDetached` which is an unsafe C/C++ function from interop.cpp
+                #[allow(unsafe_code)]
+                let spawn_result: i32 = unsafe {
+                    let mut cmd_path = [0u16; MAX_PATH + 1];
+                    let result = getSystemFolder(cmd_path.as_mut_ptr());
+                    if result != winapi::shared::winerror::S_OK {
+                        return Err("Unable to find the system folder".to_string());
+                    }
 
-        // Needs to use `spawnDetached` which is an unsafe C/C++ function from interop.cpp
-        #[allow(unsafe_code)]
-        let spawn_result: i32 = unsafe {
-            let mut cmd_path = [0u16; MAX_PATH + 1];
-            let result = getSystemFolder(cmd_path.as_mut_ptr());
-            if result != winapi::shared::winerror::S_OK {
-                return;
-            }
+                    spawnDetached(
+                        U16CString::from_str(
+                            format!(
+                                "{}\\cmd.exe",
+                                String::from_utf16_lossy(&cmd_path[..count_u16(&cmd_path)])
+                            )
+                            .as_str(),
+                        )
+                        .log_expect("Unable to convert string to wchar_t")
+                        .as_ptr(),
+                        U16CString::from_str(target_arguments.as_str())
+                            .log_expect("Unable to convert string to wchar_t")
+                            .as_ptr(),
+                    )
+                };
 
-            spawnDetached(
-                U16CString::from_str(
-                    format!("{}\\cmd.exe", String::from_utf16_lossy(&cmd_path[..count_u16(&cmd_path)])).as_str(),
-                )
-                .log_expect("Unable to convert string to wchar_t")
-                .as_ptr(),
-                U16CString::from_str(target_arguments.as_str())
-                    .log_expect("Unable to convert string to wchar_t")
-                    .as_ptr(),
-            )
-        };
+                if spawn_result != 0 {
+                    return Err(format!(
+                        "Unable to start child process (error {})",
+                        spawn_result
+                    ));
+                }
+
+                Ok(())
+            });
+
+        if let Err(v) = spawned {
+            warn!("{}, deleting the maintenance tool on reboot instead", v);
 
-        if spawn_result != 0 {
-            warn!("Unable to start child process");
+            for file in &[current_exe.as_path(), log.as_path(), path] {
+                if let Err(v) = delete_on_reboot(file) {
+                    warn!("{}", v);
+                }
+            }
         }
     }
 
@@ -289,117 +459,2371 @@ mod natives {
     pub fn is_dark_mode_active() -> bool {
         unsafe { isDarkThemeActive() == 1 }
     }
-}
 
-#[cfg(not(windows))]
-mod natives {
-    use std::fs::remove_file;
+    /// Returns if a high contrast theme is in use.
+    #[allow(unsafe_code)]
+    pub fn is_high_contrast_active() -> bool {
+        let mut settings: HIGHCONTRASTW = unsafe { mem::zeroed() };
+        settings.cbSize = mem::size_of::<HIGHCONTRASTW>() as u32;
 
-    use std::env;
+        let result = unsafe {
+            SystemParametersInfoW(
+                SPI_GETHIGHCONTRAST,
+                settings.cbSize,
+                &mut settings as *mut _ as LPVOID,
+                0,
+            )
+        };
 
-    use logging::LoggingErrors;
+        result != 0 && settings.dwFlags & HCF_HIGHCONTRASTON != 0
+    }
 
-    use sysinfo::{ProcessExt, SystemExt};
+    /// Returns if the user has turned off animations.
+    #[allow(unsafe_code)]
+    pub fn is_reduced_motion_preferred() -> bool {
+        let mut animations = TRUE;
 
-    use dirs;
+        let result = unsafe {
+            SystemParametersInfoW(
+                SPI_GETCLIENTAREAANIMATION,
+                0,
+                &mut animations as *mut _ as LPVOID,
+                0,
+            )
+        };
 
-    use slug::slugify;
-    use std::fs::{create_dir_all, File};
-    use std::io::Write;
+        result != 0 && animations == FALSE
+    }
 
-    #[cfg(target_os = "linux")]
-    pub fn create_shortcut(
-        name: &str,
-        description: &str,
-        target: &str,
-        args: &str,
-        working_dir: &str,
-    ) -> Result<String, String> {
-        // FIXME: no icon will be shown since no icon is provided
-        let data_local_dir = dirs::data_local_dir();
-        match data_local_dir {
-            Some(x) => {
-                let mut path = x;
-                path.push("applications");
-                match create_dir_all(path.to_path_buf()) {
-                    Ok(_) => (()),
-                    Err(e) => {
-                        return Err(format!(
-                            "Local data directory does not exist and cannot be created: {}",
-                            e
-                        ));
-                    }
-                };
-                path.push(format!("{}.desktop", slugify(name))); // file name
-                let desktop_file = format!(
-                    "[Desktop Entry]\nName={}\nExec=\"{}\" {}\nComment={}\nPath={}\n",
-                    name, target, args, description, working_dir
-                );
-                let desktop_f = File::create(path);
-                let mut desktop_f = match desktop_f {
-                    Ok(file) => file,
-                    Err(e) => return Err(format!("Unable to create desktop file: {}", e)),
-                };
-                let mut desktop_f = desktop_f.write_all(desktop_file.as_bytes());
-                match desktop_f {
-                    Ok(_) => Ok("".to_string()),
-                    Err(e) => Err(format!("Unable to write desktop file: {}", e)),
-                }
+    /// Returns if this process is running with an elevated token.
+    #[allow(unsafe_code)]
+    pub fn is_elevated() -> bool {
+        unsafe {
+            let mut token: HANDLE = ptr::null_mut();
+            if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) == 0 {
+                return false;
             }
-            // return error when failed to acquire local data directory
-            None => Err("Unable to determine local data directory".to_string()),
+
+            let mut elevation: TOKEN_ELEVATION = mem::zeroed();
+            let mut size = mem::size_of::<TOKEN_ELEVATION>() as DWORD;
+            let result = GetTokenInformation(
+                token,
+                TokenElevation,
+                &mut elevation as *mut _ as LPVOID,
+                size,
+                &mut size,
+            );
+            CloseHandle(token);
+
+            result != 0 && elevation.TokenIsElevated != 0
         }
     }
 
-    #[cfg(target_os = "macos")]
-    pub fn create_shortcut(
-        name: &str,
-        description: &str,
-        target: &str,
-        args: &str,
-        working_dir: &str,
-    ) -> Result<String, String> {
-        warn!("STUB! Creating shortcut is not implemented on macOS");
-        Ok("".to_string())
+    /// Moves the current thread into background mode, lowering its I/O and CPU priority.
+    #[allow(unsafe_code)]
+    pub fn set_background_io_priority() -> Result<(), String> {
+        let result =
+            unsafe { SetThreadPriority(GetCurrentThread(), THREAD_MODE_BACKGROUND_BEGIN as _) };
+
+        if result == 0 {
+            Err("Windows refused to enter background processing mode".to_string())
+        } else {
+            Ok(())
+        }
     }
 
-    /// Cleans up the installer
-    pub fn burn_on_exit(app_name: &str) {
-        let current_exe = env::current_exe().log_expect("Current executable could not be found");
+    /// Shortcuts show their target's own icon, and Windows has no shortcut-level file
+    /// associations.
+    pub fn integrate_desktop_file(
+        _path: &str,
+        _icon: Option<&Path>,
+        _mime_types: &[String],
+        _default_handler: bool,
+    ) -> Result<(), String> {
+        Ok(())
+    }
 
-        // Thank god for *nix platforms
-        if let Err(e) = remove_file(&current_exe) {
-            // No regular logging now.
-            eprintln!("Failed to delete maintenancetool: {:?}", e);
+    /// The shell notices new shortcuts by itself.
+    pub fn refresh_desktop_caches(_all_users: bool) {}
+
+    /// Returns the Start Menu's programs directory, for the current user or all users.
+    pub fn get_start_menu_dir(all_users: bool) -> Option<PathBuf> {
+        let root = if all_users {
+            env::var("ProgramData")
+        } else {
+            env::var("APPDATA")
         };
 
-        let current_dir = env::current_dir().log_expect("Current directory cannot be found");
+        root.ok()
+            .map(|x| PathBuf::from(format!("{}\\Microsoft\\Windows\\Start Menu\\Programs", x)))
+    }
 
-        if let Err(e) = remove_file(current_dir.join(format!("{}_installer.log", app_name))) {
-            // No regular logging now.
-            eprintln!("Failed to delete installer log: {:?}", e);
-        };
+    /// Returns the desktop directory, for the current user or all users.
+    #[allow(unsafe_code)]
+    pub fn get_desktop_dir(all_users: bool) -> Option<PathBuf> {
+        if all_users {
+            return env::var("PUBLIC")
+                .ok()
+                .map(|x| PathBuf::from(format!("{}\\Desktop", x)));
+        }
+
+        let mut cmd_path = [0u16; MAX_PATH + 1];
+        let _result = unsafe { getDesktopFolder(cmd_path.as_mut_ptr()) };
+        let desktop = String::from_utf16_lossy(&cmd_path[..count_u16(&cmd_path)]);
+        Some(PathBuf::from(desktop)).filter(|_| !desktop.is_empty())
     }
 
-    /// Returns a list of running processes
-    pub fn get_process_names() -> Vec<super::Process> {
-        // a platform-independent implementation using sysinfo crate
-        let mut processes: Vec<super::Process> = Vec::new();
-        let mut system = sysinfo::System::new();
-        system.refresh_all();
-        for (pid, procs) in system.get_process_list() {
-            processes.push(super::Process {
-                pid: *pid as usize,
-                name: procs.name().to_string(),
+    /// Installers are run as whoever started them on Windows.
+    pub fn run_as_console_user(_args: &[String]) -> Result<Option<i32>, String> {
+        Err("Running as the console user is only supported on macOS".to_string())
+    }
+
+    /// Quotes an argument so that it survives being parsed from a command line by
+    /// `CommandLineToArgvW`.
+    fn quote_argument(arg: &str) -> String {
+        if !arg.is_empty() && !arg.contains(|x| x == ' ' || x == '\t' || x == '"') {
+            return arg.to_string();
+        }
+
+        // Backslashes are only special before a quote, where they have to be doubled
+        let mut quoted = String::from("\"");
+        let mut backslashes = 0;
+        for c in arg.chars() {
+            if c == '\\' {
+                backslashes += 1;
+                continue;
+            }
+
+            let escapes = if c == '"' {
+                backslashes * 2 + 1
+            } else {
+                backslashes
+            };
+            quoted.extend(iter::repeat('\\').take(escapes));
+            quoted.push(c);
+            backslashes = 0;
+        }
+
+        quoted.extend(iter::repeat('\\').take(backslashes * 2));
+        quoted.push('"');
+        quoted
+    }
+
+    /// Runs the installer again with the specified arguments, elevated through UAC. If
+    /// `wait` is set, waits for it to finish and returns its exit code.
+    #[allow(unsafe_code)]
+    pub fn run_elevated(args: &[String], wait: bool) -> Result<Option<i32>, String> {
+        let exe = env::current_exe()
+            .map_err(|x| format!("Current executable could not be found: {:?}", x))?;
+        let parameters = args
+            .iter()
+            .map(|x| quote_argument(x))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let verb = U16CString::from_str("runas").log_expect("Error while converting to wchar_t");
+        let file =
+            U16CString::from_os_str(exe.as_os_str()).log_expect("Error while converting to wchar_t");
+        let parameters =
+            U16CString::from_str(parameters).log_expect("Error while converting to wchar_t");
+
+        let mut info: SHELLEXECUTEINFOW = unsafe { mem::zeroed() };
+        info.cbSize = mem::size_of::<SHELLEXECUTEINFOW>() as DWORD;
+        info.fMask = SEE_MASK_NOCLOSEPROCESS;
+        info.lpVerb = verb.as_ptr();
+        info.lpFile = file.as_ptr();
+        info.lpParameters = parameters.as_ptr();
+        info.nShow = SW_SHOWNORMAL;
+
+        info!("Requesting elevation to run {:?} {:?}", exe, args);
+        if unsafe { ShellExecuteExW(&mut info) } == 0 {
+            let error = unsafe { GetLastError() };
+            return Err(if error == ERROR_CANCELLED {
+                "Administrator permission was declined".to_string()
+            } else {
+                format!("Unable to run elevated: error code {}", error)
             });
         }
-        processes // return running processes
+
+        if info.hProcess.is_null() {
+            return Ok(None);
+        }
+
+        let mut code: DWORD = 0;
+        let result = if wait {
+            unsafe {
+                WaitForSingleObject(info.hProcess, INFINITE);
+                GetExitCodeProcess(info.hProcess, &mut code)
+            }
+        } else {
+            FALSE
+        };
+
+        unsafe {
+            CloseHandle(info.hProcess);
+        }
+
+        Ok(if result != 0 { Some(code as i32) } else { None })
     }
 
-    /// Returns if dark mode is active on this system.
-    pub fn is_dark_mode_active() -> bool {
-        // No-op
-        false
+    /// Launches an application, dropping elevation if the installer is currently elevated.
+    pub fn spawn_unelevated(path: &str) -> Result<(), String> {
+        let mut command = if is_elevated() {
+            // Explorer runs with the shell's (unelevated) token, so let it launch the application
+            info!("Launching {:?} through the shell to drop elevation", path);
+            let explorer = match env::var("WINDIR") {
+                Ok(v) => format!("{}\\explorer.exe", v),
+                Err(_) => "explorer.exe".to_string(),
+            };
+
+            let mut command = Command::new(explorer);
+            command.arg(path);
+            command
+        } else {
+            Command::new(path)
+        };
+
+        command
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map(|_x| ())
+            .map_err(|x| format!("Unable to start application: {:?}", x))
+    }
+
+    /// Splits a registry path into its root key and the remainder.
+    fn split_registry_path(path: &str) -> Result<(HKEY, &str), String> {
+        let (root, rest) = match path.find('\\') {
+            Some(i) => (&path[..i], &path[i + 1..]),
+            None => return Err(format!("Invalid registry path: {:?}", path)),
+        };
+
+        let root = match root.to_ascii_uppercase().as_str() {
+            "HKLM" | "HKEY_LOCAL_MACHINE" => HKEY_LOCAL_MACHINE,
+            "HKCU" | "HKEY_CURRENT_USER" => HKEY_CURRENT_USER,
+            _ => return Err(format!("Unsupported registry root: {:?}", root)),
+        };
+
+        Ok((root, rest))
+    }
+
+    /// Returns if a registry key (specified as `<HKLM|HKCU>\<key>`) exists.
+    #[allow(unsafe_code)]
+    pub fn registry_key_exists(path: &str) -> bool {
+        let (root, key) = match split_registry_path(path) {
+            Ok(v) => v,
+            Err(_) => return false,
+        };
+
+        let key = match U16CString::from_str(key) {
+            Ok(v) => v,
+            Err(_) => return false,
+        };
+
+        unsafe {
+            let mut handle: HKEY = ptr::null_mut();
+            if RegOpenKeyExW(root, key.as_ptr(), 0, KEY_READ, &mut handle) != 0 {
+                return false;
+            }
+
+            RegCloseKey(handle);
+            true
+        }
+    }
+
+    /// Deletes a registry key (specified as `<HKLM|HKCU>\<key>`), along with everything in it.
+    #[allow(unsafe_code)]
+    pub fn delete_registry_key(path: &str) -> Result<(), String> {
+        let (root, key) = split_registry_path(path)?;
+
+        let key_str = U16CString::from_str(key)
+            .map_err(|x| format!("Invalid registry key {:?}: {:?}", key, x))?;
+
+        let result = unsafe { RegDeleteTreeW(root, key_str.as_ptr()) };
+        let delete_result = if result == 0 {
+            unsafe { RegDeleteKeyW(root, key_str.as_ptr()) }
+        } else {
+            result
+        };
+
+        if delete_result != 0 {
+            return Err(format!(
+                "Unable to delete registry key {:?} (error {})",
+                path, delete_result
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Creates a registry key (specified as `<HKLM|HKCU>\<key>`) if it doesn't exist, and
+    /// sets the specified values within it.
+    #[allow(unsafe_code)]
+    pub fn write_registry_values(
+        path: &str,
+        values: &[(&str, super::RegistryValue)],
+    ) -> Result<(), String> {
+        let (root, key) = split_registry_path(path)?;
+
+        let key_str = U16CString::from_str(key)
+            .map_err(|x| format!("Invalid registry key {:?}: {:?}", key, x))?;
+
+        // Values are converted up front, as to not leak the key's handle on failure
+        let mut converted = Vec::with_capacity(values.len());
+        for (name, value) in values {
+            let name_str = U16CString::from_str(name)
+                .map_err(|x| format!("Invalid registry value {:?}: {:?}", name, x))?;
+
+            let (kind, data) = match *value {
+                super::RegistryValue::String(ref v) => {
+                    let wide = U16CString::from_str(v)
+                        .map_err(|x| format!("Invalid registry data {:?}: {:?}", v, x))?;
+                    let data: Vec<u8> = wide
+                        .as_slice_with_nul()
+                        .iter()
+                        .flat_map(|x| x.to_le_bytes().to_vec())
+                        .collect();
+                    (REG_SZ, data)
+                }
+                super::RegistryValue::Dword(v) => (REG_DWORD, v.to_le_bytes().to_vec()),
+            };
+
+            converted.push((name, name_str, kind, data));
+        }
+
+        let mut handle: HKEY = ptr::null_mut();
+        let result = unsafe {
+            RegCreateKeyExW(
+                root,
+                key_str.as_ptr(),
+                0,
+                ptr::null_mut(),
+                REG_OPTION_NON_VOLATILE,
+                KEY_WRITE,
+                ptr::null_mut(),
+                &mut handle,
+                ptr::null_mut(),
+            )
+        };
+
+        if result != 0 {
+            return Err(format!(
+                "Unable to create registry key {:?} (error {})",
+                path, result
+            ));
+        }
+
+        let mut outcome = Ok(());
+        for (name, name_str, kind, data) in converted {
+            let result = unsafe {
+                RegSetValueExW(
+                    handle,
+                    name_str.as_ptr(),
+                    0,
+                    kind,
+                    data.as_ptr() as *const BYTE,
+                    data.len() as DWORD,
+                )
+            };
+
+            if result != 0 {
+                outcome = Err(format!(
+                    "Unable to write registry value {:?} of {:?} (error {})",
+                    name, path, result
+                ));
+                break;
+            }
+        }
+
+        unsafe {
+            RegCloseKey(handle);
+        }
+
+        outcome
+    }
+
+    /// Takes ownership of a string allocated by WinHTTP, freeing it.
+    #[allow(unsafe_code)]
+    unsafe fn take_winhttp_string(ptr: *mut u16) -> Option<String> {
+        if ptr.is_null() {
+            return None;
+        }
+
+        let value = U16CString::from_ptr_str(ptr).to_string_lossy();
+        GlobalFree(ptr as LPVOID);
+
+        if value.is_empty() {
+            None
+        } else {
+            Some(value)
+        }
+    }
+
+    /// Finds the proxy to use for a URL through WinHTTP, which evaluates WPAD and PAC
+    /// scripts. An explicit PAC URL takes precedence over the user's Internet settings.
+    /// Returns the proxy list in WinHTTP's format (e.g. `host:port;host2:port`).
+    #[allow(unsafe_code)]
+    pub fn get_proxy_for_url(url: &str, pac_url: Option<&str>) -> Option<String> {
+        unsafe {
+            let mut ie_config: WINHTTP_CURRENT_USER_IE_PROXY_CONFIG = mem::zeroed();
+            let (auto_detect, ie_pac_url, static_proxy) =
+                if WinHttpGetIEProxyConfigForCurrentUser(&mut ie_config) != 0 {
+                    let _ = take_winhttp_string(ie_config.lpszProxyBypass);
+                    (
+                        ie_config.fAutoDetect != FALSE,
+                        take_winhttp_string(ie_config.lpszAutoConfigUrl),
+                        take_winhttp_string(ie_config.lpszProxy),
+                    )
+                } else {
+                    // No user settings (e.g. a service account), so try WPAD anyway
+                    (true, None, None)
+                };
+
+            let pac_url = pac_url.map(|x| x.to_string()).or(ie_pac_url);
+            if pac_url.is_none() && !auto_detect {
+                return static_proxy;
+            }
+
+            let agent = U16CString::from_str("liftinstall").ok()?;
+            let session = WinHttpOpen(
+                agent.as_ptr(),
+                WINHTTP_ACCESS_TYPE_NO_PROXY,
+                ptr::null(),
+                ptr::null(),
+                0,
+            );
+            if session.is_null() {
+                return static_proxy;
+            }
+
+            let pac_url = match pac_url {
+                Some(v) => Some(U16CString::from_str(v).ok()?),
+                None => None,
+            };
+
+            let mut options: WINHTTP_AUTOPROXY_OPTIONS = mem::zeroed();
+            options.fAutoLogonIfChallenged = TRUE;
+            match pac_url {
+                Some(ref v) => {
+                    options.dwFlags = WINHTTP_AUTOPROXY_CONFIG_URL;
+                    options.lpszAutoConfigUrl = v.as_ptr();
+                }
+                None => {
+                    options.dwFlags = WINHTTP_AUTOPROXY_AUTO_DETECT;
+                    options.dwAutoDetectFlags =
+                        WINHTTP_AUTO_DETECT_TYPE_DHCP | WINHTTP_AUTO_DETECT_TYPE_DNS_A;
+                }
+            }
+
+            let url = U16CString::from_str(url).ok()?;
+            let mut info: WINHTTP_PROXY_INFO = mem::zeroed();
+            let result = WinHttpGetProxyForUrl(session, url.as_ptr(), &mut options, &mut info);
+            WinHttpCloseHandle(session);
+
+            if result == 0 {
+                // Auto-configuration isn't available on this network
+                return static_proxy;
+            }
+
+            let _ = take_winhttp_string(info.lpszProxyBypass);
+            let proxy = take_winhttp_string(info.lpszProxy);
+
+            if info.dwAccessType == WINHTTP_ACCESS_TYPE_NAMED_PROXY {
+                proxy
+            } else {
+                None
+            }
+        }
+    }
+
+    /// Closes a WinHTTP handle when dropped.
+    struct WinHttpHandle(HINTERNET);
+
+    impl Drop for WinHttpHandle {
+        #[allow(unsafe_code)]
+        fn drop(&mut self) {
+            unsafe {
+                WinHttpCloseHandle(self.0);
+            }
+        }
+    }
+
+    /// Reads a numeric header (such as the status code) from a WinHTTP response.
+    #[allow(unsafe_code)]
+    unsafe fn query_number(request: HINTERNET, header: DWORD) -> Option<DWORD> {
+        let mut value: DWORD = 0;
+        let mut size = mem::size_of::<DWORD>() as DWORD;
+
+        if WinHttpQueryHeaders(
+            request,
+            header | WINHTTP_QUERY_FLAG_NUMBER,
+            ptr::null(),
+            &mut value as *mut _ as LPVOID,
+            &mut size,
+            ptr::null_mut(),
+        ) == 0
+        {
+            None
+        } else {
+            Some(value)
+        }
+    }
+
+    /// Streams a resource through WinHTTP, which can authenticate against proxies using the
+    /// current user's Windows credentials (NTLM/Kerberos via SSPI). Returns the status code.
+    ///
+    /// callback: called with (data, total size)
+    #[allow(unsafe_code)]
+    pub fn stream_with_system_auth<F>(
+        url: &str,
+        headers: &[(&str, String)],
+        proxy: Option<String>,
+        mut callback: F,
+    ) -> Result<u32, String>
+    where
+        F: FnMut(Vec<u8>, u64) -> (),
+    {
+        let parsed = Url::parse(url).map_err(|x| format!("Invalid URL {:?}: {:?}", url, x))?;
+        let host = parsed.host_str().ok_or("URL has no host")?;
+        let port = parsed.port_or_known_default().unwrap_or(443);
+
+        let mut path = parsed.path().to_string();
+        if let Some(query) = parsed.query() {
+            path.push('?');
+            path.push_str(query);
+        }
+
+        let to_wide = |x: &str| {
+            U16CString::from_str(x).map_err(|e| format!("Unable to convert {:?}: {:?}", x, e))
+        };
+        let agent = to_wide("liftinstall")?;
+        let host = to_wide(host)?;
+        let verb = to_wide("GET")?;
+        let path = to_wide(&path)?;
+        let header_block: String = headers
+            .iter()
+            .map(|(key, value)| format!("{}: {}\r\n", key, value))
+            .collect();
+        let header_block = to_wide(&header_block)?;
+
+        let last_error = |action: &str| format!("WinHTTP failed to {} (error {})", action, unsafe {
+            GetLastError()
+        });
+
+        unsafe {
+            let session = WinHttpHandle(WinHttpOpen(
+                agent.as_ptr(),
+                WINHTTP_ACCESS_TYPE_DEFAULT_PROXY,
+                ptr::null(),
+                ptr::null(),
+                0,
+            ));
+            if session.0.is_null() {
+                return Err(last_error("open a session"));
+            }
+
+            if let Some(proxy) = proxy {
+                let proxy = to_wide(&proxy)?;
+                let mut info: WINHTTP_PROXY_INFO = mem::zeroed();
+                info.dwAccessType = WINHTTP_ACCESS_TYPE_NAMED_PROXY;
+                info.lpszProxy = proxy.as_ptr() as *mut _;
+
+                if WinHttpSetOption(
+                    session.0,
+                    WINHTTP_OPTION_PROXY,
+                    &mut info as *mut _ as LPVOID,
+                    mem::size_of::<WINHTTP_PROXY_INFO>() as DWORD,
+                ) == 0
+                {
+                    return Err(last_error("set the proxy"));
+                }
+            }
+
+            let connection = WinHttpHandle(WinHttpConnect(session.0, host.as_ptr(), port, 0));
+            if connection.0.is_null() {
+                return Err(last_error("connect"));
+            }
+
+            let request = WinHttpHandle(WinHttpOpenRequest(
+                connection.0,
+                verb.as_ptr(),
+                path.as_ptr(),
+                ptr::null(),
+                ptr::null(),
+                ptr::null_mut(),
+                WINHTTP_FLAG_SECURE,
+            ));
+            if request.0.is_null() {
+                return Err(last_error("open a request"));
+            }
+
+            // Allow the logged in user's credentials to be sent automatically
+            let mut policy: DWORD = WINHTTP_AUTOLOGON_SECURITY_LEVEL_LOW;
+            WinHttpSetOption(
+                request.0,
+                WINHTTP_OPTION_AUTOLOGON_POLICY,
+                &mut policy as *mut _ as LPVOID,
+                mem::size_of::<DWORD>() as DWORD,
+            );
+
+            // Challenge-response schemes need a few round trips
+            let mut status = 0;
+            for _ in 0..3 {
+                if WinHttpSendRequest(
+                    request.0,
+                    header_block.as_ptr(),
+                    !0,
+                    ptr::null_mut(),
+                    0,
+                    0,
+                    0,
+                ) == 0
+                {
+                    return Err(last_error("send the request"));
+                }
+
+                if WinHttpReceiveResponse(request.0, ptr::null_mut()) == 0 {
+                    return Err(last_error("receive the response"));
+                }
+
+                status = query_number(request.0, WINHTTP_QUERY_STATUS_CODE).unwrap_or(0);
+                if status != 407 {
+                    break;
+                }
+
+                let (mut supported, mut first, mut target) = (0, 0, 0);
+                if WinHttpQueryAuthSchemes(request.0, &mut supported, &mut first, &mut target) == 0
+                {
+                    return Err(last_error("query the proxy's authentication schemes"));
+                }
+
+                let scheme = [WINHTTP_AUTH_SCHEME_NEGOTIATE, WINHTTP_AUTH_SCHEME_NTLM]
+                    .iter()
+                    .find(|x| supported & **x != 0)
+                    .ok_or("The proxy doesn't support Windows integrated authentication")?;
+
+                // No username or password means the current user's credentials
+                if WinHttpSetCredentials(
+                    request.0,
+                    WINHTTP_AUTH_TARGET_PROXY,
+                    *scheme,
+                    ptr::null(),
+                    ptr::null(),
+                    ptr::null_mut(),
+                ) == 0
+                {
+                    return Err(last_error("set proxy credentials"));
+                }
+            }
+
+            if status < 200 || status >= 300 {
+                return Err(format!("Bad status code: {}.", status));
+            }
+
+            let size = query_number(request.0, WINHTTP_QUERY_CONTENT_LENGTH).unwrap_or(0);
+
+            let mut buf = [0 as u8; 8192];
+            loop {
+                let mut len: DWORD = 0;
+                if WinHttpReadData(
+                    request.0,
+                    buf.as_mut_ptr() as LPVOID,
+                    buf.len() as DWORD,
+                    &mut len,
+                ) == 0
+                {
+                    return Err(last_error("read the response"));
+                }
+
+                if len == 0 {
+                    break;
+                }
+
+                callback(buf[0..len as usize].to_vec(), u64::from(size));
+            }
+
+            Ok(status)
+        }
+    }
+
+    /// Reads a string value from the registry, specified as `<HKLM|HKCU>\<key>\<value>`.
+    /// A trailing backslash reads the key's default value.
+    #[allow(unsafe_code)]
+    pub fn read_registry_string(path: &str) -> Result<String, String> {
+        let (root, rest) = split_registry_path(path)?;
+
+        let (key, value) = match rest.rfind('\\') {
+            Some(i) => (&rest[..i], &rest[i + 1..]),
+            None => ("", rest),
+        };
+
+        let key = U16CString::from_str(key)
+            .map_err(|x| format!("Invalid registry key {:?}: {:?}", key, x))?;
+        let value = U16CString::from_str(value)
+            .map_err(|x| format!("Invalid registry value {:?}: {:?}", value, x))?;
+
+        let mut data = [0u16; 4096];
+        let mut size = (data.len() * mem::size_of::<u16>()) as DWORD;
+
+        let result = unsafe {
+            RegGetValueW(
+                root,
+                key.as_ptr(),
+                value.as_ptr(),
+                RRF_RT_REG_SZ | RRF_RT_REG_EXPAND_SZ,
+                ptr::null_mut(),
+                data.as_mut_ptr() as LPVOID,
+                &mut size,
+            )
+        };
+
+        if result != 0 {
+            return Err(format!(
+                "Unable to read registry value {:?} (error {})",
+                path, result
+            ));
+        }
+
+        Ok(String::from_utf16_lossy(&data[..count_u16(&data)]))
+    }
+
+    /// Reads the file version (e.g. "14.30.30704.0") from a file's version resource.
+    #[allow(unsafe_code)]
+    pub fn get_file_version(path: &Path) -> Result<String, String> {
+        let wide_path = U16CString::from_os_str(path.as_os_str())
+            .map_err(|x| format!("Invalid path {:?}: {:?}", path, x))?;
+
+        let size = unsafe { GetFileVersionInfoSizeW(wide_path.as_ptr(), ptr::null_mut()) };
+        if size == 0 {
+            return Err(format!(
+                "{:?} has no version information (error {})",
+                path,
+                unsafe { GetLastError() }
+            ));
+        }
+
+        let mut data = vec![0u8; size as usize];
+        let result = unsafe {
+            GetFileVersionInfoW(wide_path.as_ptr(), 0, size, data.as_mut_ptr() as LPVOID)
+        };
+        if result == FALSE {
+            return Err(format!(
+                "Unable to read version information of {:?} (error {})",
+                path,
+                unsafe { GetLastError() }
+            ));
+        }
+
+        // The root block holds the fixed, numeric version information
+        let root = U16CString::from_str("\\").log_expect("Unable to convert string");
+        let mut info: LPVOID = ptr::null_mut();
+        let mut length: UINT = 0;
+        let result = unsafe {
+            VerQueryValueW(
+                data.as_ptr() as LPCVOID,
+                root.as_ptr(),
+                &mut info,
+                &mut length,
+            )
+        };
+        if result == FALSE || (length as usize) < mem::size_of::<VS_FIXEDFILEINFO>() {
+            return Err(format!("{:?} has no file version", path));
+        }
+
+        let info = unsafe { &*(info as *const VS_FIXEDFILEINFO) };
+        Ok(format!(
+            "{}.{}.{}.{}",
+            info.dwFileVersionMS >> 16,
+            info.dwFileVersionMS & 0xffff,
+            info.dwFileVersionLS >> 16,
+            info.dwFileVersionLS & 0xffff
+        ))
+    }
+
+    /// Returns if a path is on a network share, either by UNC path or a mapped drive.
+    #[allow(unsafe_code)]
+    pub fn is_network_path(path: &Path) -> bool {
+        let root = match path.components().next() {
+            Some(Component::Prefix(prefix)) => match prefix.kind() {
+                Prefix::UNC(..) | Prefix::VerbatimUNC(..) => return true,
+                Prefix::Disk(letter) | Prefix::VerbatimDisk(letter) => {
+                    format!("{}:\\", letter as char)
+                }
+                _ => return false,
+            },
+            _ => return false,
+        };
+
+        let wide_root = match U16CString::from_str(&root) {
+            Ok(v) => v,
+            Err(_) => return false,
+        };
+
+        unsafe { GetDriveTypeW(wide_root.as_ptr()) == DRIVE_REMOTE }
+    }
+
+    /// Returns the space available to the current user on the volume containing a path.
+    #[allow(unsafe_code)]
+    pub fn get_free_space(path: &Path) -> Result<u64, String> {
+        let wide_path = U16CString::from_os_str(path.as_os_str())
+            .map_err(|x| format!("Invalid path {:?}: {:?}", path, x))?;
+
+        unsafe {
+            let mut available: ULARGE_INTEGER = mem::zeroed();
+            if GetDiskFreeSpaceExW(
+                wide_path.as_ptr(),
+                &mut available,
+                ptr::null_mut(),
+                ptr::null_mut(),
+            ) == 0
+            {
+                return Err(format!(
+                    "Unable to get free space for {:?} (error {})",
+                    path,
+                    GetLastError()
+                ));
+            }
+
+            Ok(*available.QuadPart())
+        }
+    }
+
+    /// Marks a file as sparse, so that ranges which are skipped over when writing don't
+    /// take up any space.
+    #[allow(unsafe_code)]
+    pub fn set_sparse(file: &fs::File) -> Result<(), String> {
+        let mut returned: DWORD = 0;
+
+        unsafe {
+            if DeviceIoControl(
+                file.as_raw_handle() as _,
+                FSCTL_SET_SPARSE,
+                ptr::null_mut(),
+                0,
+                ptr::null_mut(),
+                0,
+                &mut returned,
+                ptr::null_mut(),
+            ) == 0
+            {
+                return Err(format!(
+                    "Unable to mark file as sparse (error {})",
+                    GetLastError()
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reserves space for a file's contents up front. Unlike `SetFileValidData`, this
+    /// needs no special privileges and never exposes what was previously on the disk.
+    #[allow(unsafe_code)]
+    pub fn preallocate(file: &fs::File, size: u64) -> Result<(), String> {
+        unsafe {
+            let mut info: FILE_ALLOCATION_INFO = mem::zeroed();
+            *info.AllocationSize.QuadPart_mut() = size as i64;
+
+            if SetFileInformationByHandle(
+                file.as_raw_handle() as _,
+                FileAllocationInfo,
+                &mut info as *mut _ as LPVOID,
+                mem::size_of::<FILE_ALLOCATION_INFO>() as DWORD,
+            ) == 0
+            {
+                return Err(format!(
+                    "Unable to reserve space (error {})",
+                    GetLastError()
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns if the system is running from a battery, if known.
+    #[allow(unsafe_code)]
+    pub fn is_on_battery() -> Option<bool> {
+        unsafe {
+            let mut status: SYSTEM_POWER_STATUS = mem::zeroed();
+            if GetSystemPowerStatus(&mut status) == 0 {
+                return None;
+            }
+
+            match status.ACLineStatus {
+                0 => Some(true),
+                1 => Some(false),
+                _ => None,
+            }
+        }
+    }
+
+    /// A Windows Restart Manager session covering a set of files, which can find the
+    /// applications holding them open, close them and later restart them. The session is
+    /// ended when dropped.
+    pub struct RestartSession {
+        handle: DWORD,
+    }
+
+    impl RestartSession {
+        /// Starts a session covering the specified files.
+        #[allow(unsafe_code)]
+        pub fn new(paths: &[PathBuf]) -> Result<RestartSession, String> {
+            let mut handle: DWORD = 0;
+            let mut key = [0 as u16; CCH_RM_SESSION_KEY + 1];
+
+            let result = unsafe { RmStartSession(&mut handle, 0, key.as_mut_ptr()) };
+            if result != ERROR_SUCCESS {
+                return Err(format!(
+                    "Unable to start Restart Manager session (error {})",
+                    result
+                ));
+            }
+
+            let session = RestartSession { handle };
+
+            let names = paths
+                .iter()
+                .map(|x| U16CString::from_os_str(x.as_os_str()))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|x| format!("Invalid path: {:?}", x))?;
+            let mut pointers: Vec<_> = names.iter().map(|x| x.as_ptr()).collect();
+
+            let result = unsafe {
+                RmRegisterResources(
+                    session.handle,
+                    pointers.len() as _,
+                    pointers.as_mut_ptr(),
+                    0,
+                    ptr::null_mut(),
+                    0,
+                    ptr::null_mut(),
+                )
+            };
+            if result != ERROR_SUCCESS {
+                return Err(format!(
+                    "Unable to register files with Restart Manager (error {})",
+                    result
+                ));
+            }
+
+            Ok(session)
+        }
+
+        /// Lists the applications and services which have any of the files open.
+        #[allow(unsafe_code)]
+        pub fn get_applications(&self) -> Result<Vec<super::LockingApplication>, String> {
+            let mut needed = 0;
+            let mut count;
+            let mut reasons: DWORD = 0;
+            let mut info: Vec<RM_PROCESS_INFO> = Vec::new();
+
+            // The list can grow between calls, so keep asking until it fits
+            loop {
+                count = info.len() as _;
+                let result = unsafe {
+                    RmGetList(
+                        self.handle,
+                        &mut needed,
+                        &mut count,
+                        info.as_mut_ptr(),
+                        &mut reasons,
+                    )
+                };
+
+                match result {
+                    ERROR_SUCCESS => break,
+                    ERROR_MORE_DATA => {
+                        info = (0..needed).map(|_| unsafe { mem::zeroed() }).collect();
+                    }
+                    _ => {
+                        return Err(format!(
+                            "Unable to list applications using files (error {})",
+                            result
+                        ));
+                    }
+                }
+            }
+
+            Ok(info[..count as usize]
+                .iter()
+                .map(|x| super::LockingApplication {
+                    pid: x.Process.dwProcessId,
+                    name: String::from_utf16_lossy(&x.strAppName[..count_u16(&x.strAppName)]),
+                    restartable: x.bRestartable != FALSE,
+                })
+                .collect())
+        }
+
+        /// Asks the applications to close, failing if any of them refuse to.
+        #[allow(unsafe_code)]
+        pub fn shutdown(&self) -> Result<(), String> {
+            let result = unsafe { RmShutdown(self.handle, 0, None) };
+            if result != ERROR_SUCCESS {
+                return Err(format!(
+                    "Unable to close applications using files (error {})",
+                    result
+                ));
+            }
+
+            Ok(())
+        }
+
+        /// Starts the applications closed by `shutdown` again, where they support it.
+        #[allow(unsafe_code)]
+        pub fn restart(&self) -> Result<(), String> {
+            let result = unsafe { RmRestart(self.handle, 0, None) };
+            if result != ERROR_SUCCESS {
+                return Err(format!("Unable to restart applications (error {})", result));
+            }
+
+            Ok(())
+        }
+    }
+
+    impl Drop for RestartSession {
+        #[allow(unsafe_code)]
+        fn drop(&mut self) {
+            unsafe {
+                RmEndSession(self.handle);
+            }
+        }
+    }
+
+    /// Returns a human readable name and version of the operating system.
+    pub fn get_os_version() -> String {
+        let key = "HKLM\\SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion\\";
+
+        let name = read_registry_string(&format!("{}ProductName", key))
+            .unwrap_or_else(|_| "Windows".to_string());
+
+        match read_registry_string(&format!("{}CurrentBuild", key)) {
+            Ok(build) => format!("{} (build {})", name, build),
+            Err(_) => name,
+        }
+    }
+
+    /// Converts an NVIDIA driver's Windows version into the one NVIDIA shows, which is made
+    /// up of its last five digits (e.g. 31.0.15.3623 is 536.23).
+    fn nvidia_version(version: &str) -> String {
+        let digits: String = version.split('.').skip(2).collect();
+        if digits.len() < 5 || !digits.chars().all(|x| x.is_ascii_digit()) {
+            return version.to_string();
+        }
+
+        let digits = &digits[digits.len() - 5..];
+        format!("{}.{}", &digits[..3], &digits[3..])
+    }
+
+    /// Lists the display adapters which have drivers installed, from the registry.
+    pub fn get_gpus() -> Vec<super::Gpu> {
+        let class =
+            "HKLM\\SYSTEM\\CurrentControlSet\\Control\\Class\\{4d36e968-e325-11ce-bfc1-08002be10318}";
+
+        let mut gpus = Vec::new();
+        for i in 0..32 {
+            let key = format!("{}\\{:04}\\", class, i);
+
+            let name = match read_registry_string(&format!("{}DriverDesc", key)) {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+
+            // e.g. "pci\ven_10de&dev_2484&subsys_..."
+            let vendor_id = read_registry_string(&format!("{}MatchingDeviceId", key))
+                .ok()
+                .and_then(|x| {
+                    let x = x.to_lowercase();
+                    let start = x.find("ven_")? + 4;
+                    x.get(start..start + 4)
+                        .and_then(|x| u16::from_str_radix(x, 16).ok())
+                });
+
+            // AMD's own tools show the version of Radeon Software instead
+            let driver_version = read_registry_string(&format!("{}RadeonSoftwareVersion", key))
+                .or_else(|_| read_registry_string(&format!("{}DriverVersion", key)))
+                .ok()
+                .map(|x| match vendor_id {
+                    Some(0x10de) => nvidia_version(&x),
+                    _ => x,
+                });
+
+            gpus.push(super::Gpu {
+                name,
+                vendor_id,
+                driver_version,
+            });
+        }
+
+        gpus
+    }
+
+    /// Returns the user's locale as a language tag (e.g. "de-DE"), if known.
+    #[allow(unsafe_code)]
+    pub fn get_user_locale() -> Option<String> {
+        let mut buffer = [0u16; LOCALE_NAME_MAX_LENGTH];
+
+        let length = unsafe { GetUserDefaultLocaleName(buffer.as_mut_ptr(), buffer.len() as i32) };
+        if length <= 1 {
+            return None;
+        }
+
+        // The returned length includes the terminating null
+        Some(String::from_utf16_lossy(&buffer[..length as usize - 1]))
+    }
+
+    lazy_static! {
+        /// PowerShell process showing the notifications written to its stdin, started as
+        /// the first one is shown. It exits once its stdin is closed, as the installer does.
+        static ref NOTIFIER: Mutex<Option<(process::Child, process::ChildStdin)>> =
+            Mutex::new(None);
+    }
+
+    /// Writes text as a JSON string for the notifier. Everything but printable ASCII is
+    /// escaped, as PowerShell reads its stdin in the console's code page.
+    fn notification_json(text: &str) -> String {
+        let mut json = String::from("\"");
+        for unit in text.encode_utf16() {
+            match unit {
+                0x20..=0x7e if unit != u16::from(b'"') && unit != u16::from(b'\\') => {
+                    json.push(unit as u8 as char)
+                }
+                _ => json.push_str(&format!("\\u{:04x}", unit)),
+            }
+        }
+        json.push('"');
+        json
+    }
+
+    /// Starts the PowerShell process which shows notifications.
+    fn start_notifier() -> Result<(process::Child, process::ChildStdin), String> {
+        use std::os::windows::process::CommandExt;
+
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+        // Toasts have to come from a registered application, so they are shown as
+        // coming from PowerShell
+        let script = "\
+            [Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications, \
+                ContentType = WindowsRuntime] > $null; \
+            $notifier = [Windows.UI.Notifications.ToastNotificationManager]::CreateToastNotifier(\
+                '{1AC14E77-02E7-4E5D-B744-2EB1AE5198B7}\\WindowsPowerShell\\v1.0\\powershell.exe'); \
+            while ($null -ne ($line = [Console]::In.ReadLine())) { \
+                $notification = $line | ConvertFrom-Json; \
+                $template = [Windows.UI.Notifications.ToastNotificationManager]::GetTemplateContent(\
+                    [Windows.UI.Notifications.ToastTemplateType]::ToastText02); \
+                $text = $template.GetElementsByTagName('text'); \
+                $text.Item(0).InnerText = $notification.title; \
+                $text.Item(1).InnerText = $notification.message; \
+                $notifier.Show([Windows.UI.Notifications.ToastNotification]::new($template)) \
+            }";
+
+        let mut child = Command::new("powershell")
+            .args(&["-NoProfile", "-NonInteractive", "-Command", script])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .creation_flags(CREATE_NO_WINDOW)
+            .spawn()
+            .map_err(|x| format!("Unable to show notification: {:?}", x))?;
+
+        let stdin = child.stdin.take().log_expect("stdin should be piped");
+        Ok((child, stdin))
+    }
+
+    /// Shows a toast notification, which Narrator reads out. A single PowerShell process
+    /// shows every notification, rather than one being started for each.
+    pub fn show_notification(title: &str, message: &str) -> Result<(), String> {
+        let line = format!(
+            "{{\"title\": {}, \"message\": {}}}",
+            notification_json(title),
+            notification_json(message)
+        );
+
+        let mut notifier = NOTIFIER.lock().log_expect("Notifier has been dirtied");
+
+        // The notifier is started again if it has exited since the last notification
+        for _ in 0..2 {
+            let (mut child, mut stdin) = match notifier.take() {
+                Some(v) => v,
+                None => start_notifier()?,
+            };
+
+            match writeln!(stdin, "{}", line).and_then(|_| stdin.flush()) {
+                Ok(_) => {
+                    *notifier = Some((child, stdin));
+                    return Ok(());
+                }
+                Err(v) => {
+                    warn!("Notifier has stopped ({:?}), starting it again", v);
+                    drop(stdin);
+                    let _ = child.kill();
+                    let _ = child.wait();
+                }
+            }
+        }
+
+        Err("Unable to show notification, as the notifier keeps stopping".to_string())
+    }
+
+    /// Creates a directory junction (or symbolic link) at `link`, pointing to `target`.
+    pub fn create_directory_link(link: &Path, target: &Path, junction: bool) -> Result<(), String> {
+        use std::os::windows::process::CommandExt;
+
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+        if !junction {
+            return ::std::os::windows::fs::symlink_dir(target, link)
+                .map_err(|x| format!("Unable to link {:?} to {:?}: {:?}", link, target, x));
+        }
+
+        // The standard library can't create junctions, but mklink can without needing any
+        // special permissions
+        let output = Command::new("cmd")
+            .args(&["/C", "mklink", "/J"])
+            .arg(link)
+            .arg(target)
+            .creation_flags(CREATE_NO_WINDOW)
+            .output()
+            .map_err(|x| format!("Unable to run mklink: {:?}", x))?;
+
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(format!(
+                "Unable to link {:?} to {:?} ({}): {}",
+                link,
+                target,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+        }
+    }
+
+    /// Removes a directory junction or symbolic link, leaving whatever it points to.
+    pub fn remove_directory_link(link: &Path) -> Result<(), String> {
+        fs::remove_dir(link).map_err(|x| format!("Unable to remove link {:?}: {:?}", link, x))
+    }
+
+    /// A command and everything it starts, which can be killed together. On Windows, this
+    /// is a job object the command is added to once it has started.
+    pub struct ProcessTree {
+        job: HANDLE,
+    }
+
+    impl ProcessTree {
+        /// Kills the command and everything it has started which is still running.
+        #[allow(unsafe_code)]
+        pub fn kill(&self) -> Result<(), String> {
+            if unsafe { TerminateJobObject(self.job, 1) } == FALSE {
+                return Err(format!("Unable to terminate job: {}", unsafe {
+                    GetLastError()
+                }));
+            }
+
+            Ok(())
+        }
+    }
+
+    impl Drop for ProcessTree {
+        #[allow(unsafe_code)]
+        fn drop(&mut self) {
+            unsafe {
+                CloseHandle(self.job);
+            }
+        }
+    }
+
+    /// Prepares a command to be tracked along with everything it starts (see
+    /// `track_process_tree`). Nothing needs to be done before starting it on Windows.
+    pub fn isolate_process_tree(_command: &mut Command) {}
+
+    /// Tracks a started command along with everything it starts from then on. Anything it
+    /// started before being added to the job isn't tracked.
+    #[allow(unsafe_code)]
+    pub fn track_process_tree(child: &process::Child) -> Result<ProcessTree, String> {
+        let job = unsafe { CreateJobObjectW(ptr::null_mut(), ptr::null()) };
+        if job.is_null() {
+            return Err(format!("Unable to create job: {}", unsafe {
+                GetLastError()
+            }));
+        }
+        let tree = ProcessTree { job };
+
+        if unsafe { AssignProcessToJobObject(job, child.as_raw_handle() as HANDLE) } == FALSE {
+            return Err(format!("Unable to add process to job: {}", unsafe {
+                GetLastError()
+            }));
+        }
+
+        Ok(tree)
+    }
+}
+
+#[cfg(not(windows))]
+mod natives {
+    use std::fs::{remove_dir, remove_file};
+
+    use std::env;
+
+    use logging::LoggingErrors;
+
+    use sysinfo::{DiskExt, ProcessExt, SystemExt};
+
+    use dirs;
+
+    use libc;
+
+    use slug::slugify;
+    use std::fs::{
+        canonicalize, create_dir_all, metadata, read_dir, read_link, read_to_string,
+        set_permissions, File, OpenOptions, Permissions,
+    };
+    use std::io::{ErrorKind, Write};
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    use std::os::unix::process::CommandExt;
+    use std::path::{Path, PathBuf};
+    use std::process;
+    use std::process::{Command, Stdio};
+    use std::thread;
+
+    /// Writes a `.desktop` file into the given directory, returning its path.
+    #[cfg(target_os = "linux")]
+    fn write_desktop_file(
+        mut path: PathBuf,
+        name: &str,
+        description: &str,
+        target: &str,
+        args: &str,
+        working_dir: &str,
+    ) -> Result<String, String> {
+        // Icons are only added by `integrate_desktop_file`, if the shortcut has one
+        match create_dir_all(path.to_path_buf()) {
+            Ok(_) => (()),
+            Err(e) => {
+                return Err(format!(
+                    "Directory {:?} does not exist and cannot be created: {}",
+                    path, e
+                ));
+            }
+        };
+        path.push(format!("{}.desktop", slugify(name))); // file name
+        let desktop_file = format!(
+            "[Desktop Entry]\nType=Application\nName={}\nExec=\"{}\" {}\nComment={}\nPath={}\n",
+            name, target, args, description, working_dir
+        );
+        let desktop_f = File::create(&path);
+        let mut desktop_f = match desktop_f {
+            Ok(file) => file,
+            Err(e) => return Err(format!("Unable to create desktop file: {}", e)),
+        };
+        if let Err(e) = desktop_f.write_all(desktop_file.as_bytes()) {
+            return Err(format!("Unable to write desktop file: {}", e));
+        }
+
+        // File managers refuse to launch desktop files which aren't executable
+        if let Err(e) = set_permissions(&path, Permissions::from_mode(0o755)) {
+            warn!("Unable to mark {:?} as executable: {}", path, e);
+        }
+
+        path.to_str()
+            .map(|x| x.to_string())
+            .ok_or_else(|| format!("Desktop file path {:?} is not valid UTF-8", path))
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn create_shortcut(
+        name: &str,
+        description: &str,
+        target: &str,
+        args: &str,
+        working_dir: &str,
+        _exe_path: &str,
+        all_users: bool,
+    ) -> Result<String, String> {
+        let data_local_dir = if all_users {
+            Some(PathBuf::from("/usr/share"))
+        } else {
+            dirs::data_local_dir()
+        };
+        match data_local_dir {
+            Some(x) => write_desktop_file(
+                x.join("applications"),
+                name,
+                description,
+                target,
+                args,
+                working_dir,
+            ),
+            // return error when failed to acquire local data directory
+            None => Err("Unable to determine local data directory".to_string()),
+        }
+    }
+
+    /// Adds an icon and the MIME types an application opens to a `.desktop` file, making
+    /// it the user's default application for those types if asked to.
+    #[cfg(target_os = "linux")]
+    pub fn integrate_desktop_file(
+        path: &str,
+        icon: Option<&Path>,
+        mime_types: &[String],
+        default_handler: bool,
+    ) -> Result<(), String> {
+        let mut entries = String::new();
+        if let Some(icon) = icon {
+            entries.push_str(&format!("Icon={}\n", icon.display()));
+        }
+        if !mime_types.is_empty() {
+            entries.push_str(&format!("MimeType={};\n", mime_types.join(";")));
+        }
+
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        OpenOptions::new()
+            .append(true)
+            .open(path)
+            .and_then(|mut x| x.write_all(entries.as_bytes()))
+            .map_err(|x| format!("Unable to update desktop file {:?}: {}", path, x))?;
+
+        if default_handler && !mime_types.is_empty() {
+            let name = Path::new(path)
+                .file_name()
+                .log_expect("Desktop files should have a name");
+            run_desktop_tool(
+                Command::new("xdg-mime")
+                    .arg("default")
+                    .arg(name)
+                    .args(mime_types),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Runs a tool which updates desktop integration. These are only warned about on
+    /// failure, as not every desktop environment (or distribution) has them.
+    #[cfg(target_os = "linux")]
+    fn run_desktop_tool(command: &mut Command) {
+        match command.output() {
+            Ok(ref x) if x.status.success() => debug!("Ran {:?}", command),
+            Ok(x) => warn!(
+                "{:?} failed ({}): {}",
+                command,
+                x.status,
+                String::from_utf8_lossy(&x.stderr).trim()
+            ),
+            Err(ref x) if x.kind() == ErrorKind::NotFound => {
+                debug!("Not running {:?}, as it isn't installed", command)
+            }
+            Err(x) => warn!("Unable to run {:?}: {:?}", command, x),
+        }
+    }
+
+    /// Refreshes the desktop entry, MIME and icon caches of the data directory shortcuts
+    /// are installed into, so that changes show up without logging in again.
+    #[cfg(target_os = "linux")]
+    pub fn refresh_desktop_caches(all_users: bool) {
+        let data_dir = if all_users {
+            Some(PathBuf::from("/usr/share"))
+        } else {
+            dirs::data_local_dir()
+        };
+        let data_dir = match data_dir {
+            Some(v) => v,
+            None => return,
+        };
+
+        let applications = data_dir.join("applications");
+        if applications.is_dir() {
+            run_desktop_tool(
+                Command::new("update-desktop-database")
+                    .arg("-q")
+                    .arg(&applications),
+            );
+        }
+
+        // Packages can install their own definitions (see `locations`)
+        let mime = data_dir.join("mime");
+        if mime.join("packages").is_dir() {
+            run_desktop_tool(Command::new("update-mime-database").arg(&mime));
+        }
+
+        let icons = data_dir.join("icons").join("hicolor");
+        if icons.is_dir() {
+            // Users' own icon directories don't usually have a theme index
+            run_desktop_tool(
+                Command::new("gtk-update-icon-cache")
+                    .args(&["-q", "-t", "-f"])
+                    .arg(&icons),
+            );
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn create_desktop_shortcut(
+        name: &str,
+        description: &str,
+        target: &str,
+        args: &str,
+        working_dir: &str,
+        _exe_path: &str,
+        _all_users: bool,
+    ) -> Result<String, String> {
+        // There isn't a shared desktop for all users, so this is always the current user's
+        match dirs::desktop_dir() {
+            Some(x) => write_desktop_file(x, name, description, target, args, working_dir),
+            None => Err("Unable to determine desktop directory".to_string()),
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn create_shortcut(
+        name: &str,
+        description: &str,
+        target: &str,
+        args: &str,
+        working_dir: &str,
+        _exe_path: &str,
+        _all_users: bool,
+    ) -> Result<String, String> {
+        warn!("STUB! Creating shortcut is not implemented on macOS");
+        Ok("".to_string())
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn create_desktop_shortcut(
+        _name: &str,
+        _description: &str,
+        _target: &str,
+        _args: &str,
+        _working_dir: &str,
+        _exe_path: &str,
+        _all_users: bool,
+    ) -> Result<String, String> {
+        warn!("STUB! Creating desktop shortcut is not implemented on macOS");
+        Ok("".to_string())
+    }
+
+    /// Shortcuts aren't created on macOS.
+    #[cfg(target_os = "macos")]
+    pub fn integrate_desktop_file(
+        _path: &str,
+        _icon: Option<&Path>,
+        _mime_types: &[String],
+        _default_handler: bool,
+    ) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Launch Services picks up applications by itself.
+    #[cfg(target_os = "macos")]
+    pub fn refresh_desktop_caches(_all_users: bool) {}
+
+    /// Returns where other installers create Start Menu shortcuts. Only installations made
+    /// on Windows are looked for (see `migration`), so there is nowhere.
+    pub fn get_start_menu_dir(_all_users: bool) -> Option<PathBuf> {
+        None
+    }
+
+    /// Returns where other installers create desktop shortcuts, as with
+    /// `get_start_menu_dir`.
+    pub fn get_desktop_dir(_all_users: bool) -> Option<PathBuf> {
+        None
+    }
+
+    /// Returns the name of the user running the installer.
+    pub fn get_current_user() -> String {
+        env::var("USER").unwrap_or_default()
+    }
+
+    /// Cleans up the installer
+    pub fn burn_on_exit(app_name: &str) {
+        let current_exe = env::current_exe().log_expect("Current executable could not be found");
+
+        // Thank god for *nix platforms
+        if let Err(e) = remove_file(&current_exe) {
+            // No regular logging now.
+            eprintln!("Failed to delete maintenancetool: {:?}", e);
+        };
+
+        let current_dir = env::current_dir().log_expect("Current directory cannot be found");
+
+        if let Err(e) = remove_file(current_dir.join(format!("{}_installer.log", app_name))) {
+            // No regular logging now.
+            eprintln!("Failed to delete installer log: {:?}", e);
+        };
+
+        // Only succeeds if nothing else is left in the install directory
+        if let Some(parent) = current_exe.parent() {
+            let _ = remove_dir(parent);
+        }
+    }
+
+    /// Returns a list of running processes
+    pub fn get_process_names() -> Vec<super::Process> {
+        // a platform-independent implementation using sysinfo crate
+        let mut processes: Vec<super::Process> = Vec::new();
+        let mut system = sysinfo::System::new();
+        system.refresh_all();
+        for (pid, procs) in system.get_process_list() {
+            processes.push(super::Process {
+                pid: *pid as usize,
+                name: procs.name().to_string(),
+            });
+        }
+        processes // return running processes
+    }
+
+    /// Returns if dark mode is active on this system.
+    pub fn is_dark_mode_active() -> bool {
+        // No-op
+        false
+    }
+
+    /// Reads a GNOME setting, if gsettings is available.
+    fn read_gsetting(schema: &str, key: &str) -> Option<String> {
+        Command::new("gsettings")
+            .args(&["get", schema, key])
+            .output()
+            .ok()
+            .filter(|x| x.status.success())
+            .and_then(|x| String::from_utf8(x.stdout).ok())
+            .map(|x| x.trim().trim_matches('\'').to_string())
+    }
+
+    /// Reads one of the macOS accessibility display settings.
+    fn read_universal_access(key: &str) -> bool {
+        Command::new("defaults")
+            .args(&["read", "com.apple.universalaccess", key])
+            .output()
+            .ok()
+            .and_then(|x| String::from_utf8(x.stdout).ok())
+            .map_or(false, |x| x.trim() == "1")
+    }
+
+    /// Returns if a high contrast theme is in use.
+    pub fn is_high_contrast_active() -> bool {
+        if cfg!(target_os = "macos") {
+            return read_universal_access("increaseContrast");
+        }
+
+        read_gsetting("org.gnome.desktop.a11y.interface", "high-contrast")
+            .map_or(false, |x| x == "true")
+            || read_gsetting("org.gnome.desktop.interface", "gtk-theme")
+                .map_or(false, |x| x.contains("HighContrast"))
+    }
+
+    /// Returns if the user has turned off animations.
+    pub fn is_reduced_motion_preferred() -> bool {
+        if cfg!(target_os = "macos") {
+            return read_universal_access("reduceMotion");
+        }
+
+        read_gsetting("org.gnome.desktop.interface", "enable-animations")
+            .map_or(false, |x| x == "false")
+    }
+
+    /// Lowers the I/O (where supported) and CPU scheduling priority of this process.
+    pub fn set_background_io_priority() -> Result<(), String> {
+        let pid = process::id().to_string();
+
+        // The idle I/O scheduling class is Linux specific
+        if cfg!(target_os = "linux") {
+            let status = Command::new("ionice")
+                .args(&["-c", "3", "-p", &pid])
+                .status()
+                .map_err(|x| format!("Unable to launch ionice: {:?}", x))?;
+
+            if !status.success() {
+                return Err(format!("ionice failed: {}", status));
+            }
+        }
+
+        let status = Command::new("renice")
+            .args(&["-n", "10", "-p", &pid])
+            .stdout(Stdio::null())
+            .status()
+            .map_err(|x| format!("Unable to launch renice: {:?}", x))?;
+
+        if status.success() {
+            Ok(())
+        } else {
+            Err(format!("renice failed: {}", status))
+        }
+    }
+
+    /// Returns if this process is running as root.
+    pub fn is_elevated() -> bool {
+        Command::new("id")
+            .arg("-u")
+            .output()
+            .ok()
+            .and_then(|x| String::from_utf8(x.stdout).ok())
+            .map(|x| x.trim() == "0")
+            .unwrap_or(false)
+    }
+
+    /// Looks up the primary group and home directory of a user from /etc/passwd.
+    fn get_passwd_entry(uid: u32) -> Option<(u32, String)> {
+        let passwd = read_to_string("/etc/passwd").ok()?;
+
+        for line in passwd.lines() {
+            let fields: Vec<&str> = line.split(':').collect();
+            if fields.len() < 6 || fields[2].parse::<u32>().ok() != Some(uid) {
+                continue;
+            }
+
+            let gid = fields[3].parse().ok()?;
+            return Some((gid, fields[5].to_string()));
+        }
+
+        None
+    }
+
+    /// Environment variables which are passed on when running as another user, as sudo
+    /// otherwise clears them.
+    const PRESERVED_ENV: [&str; 9] = [
+        "LIFTINSTALL_LOG",
+        "HTTPS_PROXY",
+        "https_proxy",
+        "HTTP_PROXY",
+        "http_proxy",
+        "ALL_PROXY",
+        "all_proxy",
+        "NO_PROXY",
+        "no_proxy",
+    ];
+
+    /// Returns the uid and name of the user logged in at the console, unless that is the
+    /// login window or the setup assistant.
+    fn get_console_user() -> Option<(u32, String)> {
+        let uid = metadata("/dev/console").ok()?.uid();
+        let output = Command::new("stat")
+            .args(&["-f", "%Su", "/dev/console"])
+            .output()
+            .ok()?;
+        let name = String::from_utf8(output.stdout).ok()?.trim().to_string();
+
+        // System accounts (such as _mbsetupuser) start with an underscore
+        if uid == 0 || name.is_empty() || name == "loginwindow" || name.starts_with('_') {
+            return None;
+        }
+
+        Some((uid, name))
+    }
+
+    /// Runs the installer again with the specified arguments as the user logged in at the
+    /// console, waiting for it to finish. Returns its exit code, or None if nobody is
+    /// logged in. Only supported on macOS.
+    pub fn run_as_console_user(args: &[String]) -> Result<Option<i32>, String> {
+        if !cfg!(target_os = "macos") {
+            return Err("Running as the console user is only supported on macOS".to_string());
+        }
+
+        let (uid, name) = match get_console_user() {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        let exe = env::current_exe()
+            .map_err(|x| format!("Current executable could not be found: {:?}", x))?;
+
+        // Running within the user's launchd context gives access to their keychain and
+        // session, as well as their files
+        let mut command = Command::new("launchctl");
+        command
+            .arg("asuser")
+            .arg(uid.to_string())
+            .args(&["sudo", "-u", &name, "-H", "env"]);
+
+        for var in PRESERVED_ENV.iter() {
+            if let Ok(value) = env::var(var) {
+                command.arg(format!("{}={}", var, value));
+            }
+        }
+
+        println!("Running as {:?}, who is logged in at the console", name);
+        let status = command
+            .arg(exe)
+            .args(args)
+            .status()
+            .map_err(|x| format!("Unable to run as {:?}: {:?}", name, x))?;
+
+        Ok(Some(status.code().unwrap_or(1)))
+    }
+
+    /// Environment variables which graphical applications need to reach the user's
+    /// display, as pkexec otherwise clears them.
+    const DISPLAY_ENV: [&str; 4] = [
+        "DISPLAY",
+        "XAUTHORITY",
+        "WAYLAND_DISPLAY",
+        "XDG_RUNTIME_DIR",
+    ];
+
+    /// Returns if an executable can be found on the PATH.
+    fn is_on_path(name: &str) -> bool {
+        env::var_os("PATH").map_or(false, |paths| {
+            env::split_paths(&paths).any(|x| x.join(name).is_file())
+        })
+    }
+
+    /// Quotes an argument for a POSIX shell.
+    fn shell_quote(arg: &str) -> String {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+
+    /// Runs the installer again with the specified arguments as root. Graphical sessions
+    /// ask for permission through polkit (pkexec) on Linux, or the authorization dialog
+    /// on macOS, otherwise sudo asks on the terminal. If `wait` is set, waits for it to
+    /// finish and returns its exit code.
+    pub fn run_elevated(args: &[String], wait: bool) -> Result<Option<i32>, String> {
+        let exe = env::current_exe()
+            .map_err(|x| format!("Current executable could not be found: {:?}", x))?;
+
+        let graphical = !wait
+            && (cfg!(target_os = "macos")
+                || env::var_os("DISPLAY").is_some()
+                || env::var_os("WAYLAND_DISPLAY").is_some());
+
+        let mut environment = Vec::new();
+        for var in PRESERVED_ENV.iter().chain(DISPLAY_ENV.iter()) {
+            if let Ok(value) = env::var(var) {
+                environment.push(format!("{}={}", var, value));
+            }
+        }
+
+        let mut command = if graphical && cfg!(target_os = "macos") {
+            let shell_command = Some("env".to_string())
+                .into_iter()
+                .chain(environment.into_iter())
+                .chain(Some(exe.to_string_lossy().to_string()))
+                .chain(args.iter().cloned())
+                .map(|x| shell_quote(&x))
+                .collect::<Vec<_>>()
+                .join(" ");
+
+            let mut command = Command::new("osascript");
+            command.arg("-e").arg(format!(
+                "do shell script \"{}\" with administrator privileges",
+                shell_command.replace('\\', "\\\\").replace('"', "\\\"")
+            ));
+            command
+        } else {
+            let mut command = if graphical && is_on_path("pkexec") {
+                Command::new("pkexec")
+            } else {
+                Command::new("sudo")
+            };
+
+            command.arg("env").args(&environment).arg(&exe).args(args);
+            command
+        };
+
+        info!("Requesting elevation to run {:?} {:?}", exe, args);
+        if wait {
+            let status = command
+                .status()
+                .map_err(|x| format!("Unable to run elevated: {:?}", x))?;
+
+            Ok(Some(status.code().unwrap_or(1)))
+        } else {
+            // sudo asks on the terminal, so it keeps it
+            if graphical {
+                command
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null());
+            }
+
+            command
+                .spawn()
+                .map_err(|x| format!("Unable to run elevated: {:?}", x))?;
+
+            Ok(None)
+        }
+    }
+
+    /// Launches an application as the user who invoked sudo/pkexec, if the installer is
+    /// running as root on their behalf.
+    pub fn spawn_unelevated(path: &str) -> Result<(), String> {
+        let mut command = Command::new(path);
+        command.stdout(Stdio::null()).stderr(Stdio::null());
+
+        let invoking_uid = env::var("SUDO_UID")
+            .or_else(|_| env::var("PKEXEC_UID"))
+            .ok()
+            .and_then(|x| x.parse::<u32>().ok());
+
+        if let Some(uid) = invoking_uid {
+            if is_elevated() {
+                let passwd = get_passwd_entry(uid);
+                let gid = env::var("SUDO_GID")
+                    .ok()
+                    .and_then(|x| x.parse::<u32>().ok())
+                    .or_else(|| passwd.as_ref().map(|x| x.0))
+                    .unwrap_or(uid);
+
+                info!("Dropping to uid {}, gid {} to launch {:?}", uid, gid, path);
+                command.uid(uid).gid(gid);
+
+                if let Some((_, home)) = passwd {
+                    command.env("HOME", home);
+                }
+
+                if let Ok(user) = env::var("SUDO_USER") {
+                    command.env("USER", &user).env("LOGNAME", &user);
+                }
+            }
+        }
+
+        command
+            .spawn()
+            .map(|_x| ())
+            .map_err(|x| format!("Unable to start application: {:?}", x))
+    }
+
+    /// Integrated proxy authentication relies on Windows' SSPI.
+    pub fn stream_with_system_auth<F>(
+        _url: &str,
+        _headers: &[(&str, String)],
+        _proxy: Option<String>,
+        _callback: F,
+    ) -> Result<u32, String>
+    where
+        F: FnMut(Vec<u8>, u64) -> (),
+    {
+        Err("The proxy requires authentication, which is only supported on Windows".to_string())
+    }
+
+    /// PAC scripts can't be evaluated without a JavaScript engine, so proxies can only be
+    /// configured through the environment on this platform.
+    pub fn get_proxy_for_url(_url: &str, pac_url: Option<&str>) -> Option<String> {
+        if let Some(pac_url) = pac_url {
+            warn!("Ignoring proxy auto-config script {:?}, as PAC is only supported on Windows", pac_url);
+        }
+
+        None
+    }
+
+    /// The registry only exists on Windows.
+    pub fn registry_key_exists(_path: &str) -> bool {
+        false
+    }
+
+    /// The registry only exists on Windows.
+    pub fn delete_registry_key(path: &str) -> Result<(), String> {
+        Err(format!(
+            "Unable to delete {:?}: the registry is only available on Windows",
+            path
+        ))
+    }
+
+    /// The registry only exists on Windows.
+    pub fn write_registry_values(
+        path: &str,
+        _values: &[(&str, super::RegistryValue)],
+    ) -> Result<(), String> {
+        Err(format!(
+            "Unable to write to {:?}: the registry is only available on Windows",
+            path
+        ))
+    }
+
+    /// Registry lookups only exist on Windows.
+    pub fn read_registry_string(path: &str) -> Result<String, String> {
+        Err(format!(
+            "Unable to read {:?}: the registry is only available on Windows",
+            path
+        ))
+    }
+
+    /// Reads the version of a macOS app bundle from its Info.plist. Other files don't
+    /// carry a version outside of Windows.
+    pub fn get_file_version(path: &Path) -> Result<String, String> {
+        let is_bundle = path.extension().map_or(false, |x| x == "app");
+        if !cfg!(target_os = "macos") || !is_bundle {
+            return Err(format!(
+                "Unable to read the version of {:?}: only app bundles have one outside of Windows",
+                path
+            ));
+        }
+
+        let output = Command::new("defaults")
+            .arg("read")
+            .arg(path.join("Contents").join("Info"))
+            .arg("CFBundleShortVersionString")
+            .output()
+            .map_err(|x| format!("Unable to run defaults: {:?}", x))?;
+
+        let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if !output.status.success() || version.is_empty() {
+            return Err(format!("{:?} has no version in its Info.plist", path));
+        }
+
+        Ok(version)
+    }
+    /// File systems which are mounted over the network.
+    const NETWORK_FILE_SYSTEMS: [&str; 9] = [
+        "nfs", "nfs4", "cifs", "smbfs", "smb3", "afpfs", "webdav", "fuse.sshfs", "9p",
+    ];
+
+    /// Returns if a path is on a network mount.
+    pub fn is_network_path(path: &Path) -> bool {
+        let path = match path.canonicalize() {
+            Ok(v) => v,
+            Err(_) => return false,
+        };
+
+        let system = sysinfo::System::new();
+
+        system
+            .get_disks()
+            .iter()
+            .filter(|x| path.starts_with(x.get_mount_point()))
+            .max_by_key(|x| x.get_mount_point().as_os_str().len())
+            .map_or(false, |x| {
+                let file_system = String::from_utf8_lossy(x.get_file_system()).to_lowercase();
+                NETWORK_FILE_SYSTEMS.contains(&file_system.as_str())
+            })
+    }
+
+    /// Returns the space available on the volume containing a path.
+    pub fn get_free_space(path: &Path) -> Result<u64, String> {
+        let path = path
+            .canonicalize()
+            .map_err(|x| format!("Unable to resolve {:?}: {:?}", path, x))?;
+
+        let system = sysinfo::System::new();
+
+        // The most specific mount point is the volume containing this path
+        system
+            .get_disks()
+            .iter()
+            .filter(|x| path.starts_with(x.get_mount_point()))
+            .max_by_key(|x| x.get_mount_point().as_os_str().len())
+            .map(|x| x.get_available_space())
+            .ok_or_else(|| format!("No volume found containing {:?}", path))
+    }
+
+    /// Files are sparse wherever ranges are skipped over when writing, without needing to
+    /// be marked as such.
+    pub fn set_sparse(_file: &File) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Reserves space for a file's contents up front. The file is extended to the size,
+    /// filled with zeros.
+    #[cfg(target_os = "linux")]
+    #[allow(unsafe_code)]
+    pub fn preallocate(file: &File, size: u64) -> Result<(), String> {
+        use std::io;
+        use std::os::raw::c_int;
+        use std::os::unix::io::AsRawFd;
+
+        // Unlike posix_fallocate, this fails rather than writing out zeros on file
+        // systems which can't reserve space
+        extern "C" {
+            fn fallocate64(fd: c_int, mode: c_int, offset: i64, len: i64) -> c_int;
+        }
+
+        if unsafe { fallocate64(file.as_raw_fd(), 0, 0, size as i64) } != 0 {
+            return Err(format!("Unable to reserve space: {}", io::Error::last_os_error()));
+        }
+
+        Ok(())
+    }
+
+    /// Reserving space isn't supported here, so files are just written out as normal.
+    #[cfg(not(target_os = "linux"))]
+    pub fn preallocate(_file: &File, _size: u64) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Returns if the system is running from a battery, if known.
+    pub fn is_on_battery() -> Option<bool> {
+        if cfg!(target_os = "macos") {
+            let output = Command::new("pmset").args(&["-g", "batt"]).output().ok()?;
+            return Some(String::from_utf8_lossy(&output.stdout).contains("'Battery Power'"));
+        }
+
+        let mut has_battery = false;
+        for supply in read_dir("/sys/class/power_supply").ok()?.filter_map(|x| x.ok()) {
+            let path = supply.path();
+            let kind = read_to_string(path.join("type")).unwrap_or_default();
+
+            match kind.trim() {
+                "Mains" | "USB" => {
+                    let online = read_to_string(path.join("online")).unwrap_or_default();
+                    if online.trim() == "1" {
+                        return Some(false);
+                    }
+                }
+                "Battery" => has_battery = true,
+                _ => {}
+            }
+        }
+
+        // Desktops have no power supplies listed at all
+        if has_battery {
+            Some(true)
+        } else {
+            None
+        }
+    }
+
+    /// Files can be replaced while they are open elsewhere, so there is nothing for the
+    /// Restart Manager to do outside of Windows.
+    pub struct RestartSession {}
+
+    impl RestartSession {
+        pub fn new(_paths: &[PathBuf]) -> Result<RestartSession, String> {
+            Ok(RestartSession {})
+        }
+
+        pub fn get_applications(&self) -> Result<Vec<super::LockingApplication>, String> {
+            Ok(Vec::new())
+        }
+
+        pub fn shutdown(&self) -> Result<(), String> {
+            Ok(())
+        }
+
+        pub fn restart(&self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    /// Returns a human readable name and version of the operating system.
+    pub fn get_os_version() -> String {
+        if let Ok(release) = read_to_string("/etc/os-release") {
+            if let Some(name) = release
+                .lines()
+                .find(|x| x.starts_with("PRETTY_NAME="))
+                .map(|x| x["PRETTY_NAME=".len()..].trim_matches('"').to_string())
+            {
+                return name;
+            }
+        }
+
+        Command::new("uname")
+            .arg("-sr")
+            .output()
+            .ok()
+            .and_then(|x| String::from_utf8(x.stdout).ok())
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty())
+            .unwrap_or_else(|| env::consts::OS.to_string())
+    }
+
+    /// Reads a PCI device's name (e.g. "NVIDIA Corporation GA104 [GeForce RTX 3060 Ti]")
+    /// from lspci.
+    fn lspci_name(slot: &str) -> Option<String> {
+        let output = Command::new("lspci")
+            .args(&["-mm", "-s", slot])
+            .output()
+            .ok()?;
+        let output = String::from_utf8(output.stdout).ok()?;
+
+        // Fields are quoted: class, vendor, then device
+        let fields: Vec<&str> = output.split('"').skip(1).step_by(2).collect();
+        match (fields.get(1), fields.get(2)) {
+            (Some(vendor), Some(device)) => Some(format!("{} {}", vendor, device)),
+            _ => None,
+        }
+    }
+
+    /// Reads the version of Mesa, which provides the drivers for most GPUs other than
+    /// NVIDIA's, from what OpenGL reports.
+    fn mesa_version() -> Option<String> {
+        let output = Command::new("glxinfo").arg("-B").output().ok()?;
+        let output = String::from_utf8(output.stdout).ok()?;
+
+        // e.g. "OpenGL version string: 4.6 (Compatibility Profile) Mesa 23.1.3"
+        output
+            .lines()
+            .filter(|x| x.starts_with("OpenGL version string:"))
+            .filter_map(|x| x.split("Mesa ").nth(1))
+            .filter_map(|x| x.split_whitespace().next())
+            .map(|x| x.to_string())
+            .next()
+    }
+
+    /// Lists the graphics adapters which the kernel has drivers for, from sysfs.
+    pub fn get_gpus() -> Vec<super::Gpu> {
+        let entries = match read_dir("/sys/class/drm") {
+            Ok(v) => v,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut mesa = None;
+        let mut gpus = Vec::new();
+        for entry in entries.filter_map(|x| x.ok()) {
+            // Outputs (e.g. card0-HDMI-A-1) are listed alongside the cards themselves
+            let card = entry.file_name().to_string_lossy().to_string();
+            if !card.starts_with("card") || !card[4..].chars().all(|x| x.is_ascii_digit()) {
+                continue;
+            }
+
+            let device = entry.path().join("device");
+            let vendor_id = read_to_string(device.join("vendor"))
+                .ok()
+                .and_then(|x| u16::from_str_radix(x.trim().trim_start_matches("0x"), 16).ok());
+            let driver = read_link(device.join("driver"))
+                .ok()
+                .and_then(|x| x.file_name().map(|x| x.to_string_lossy().to_string()));
+
+            let name = canonicalize(&device)
+                .ok()
+                .and_then(|x| x.file_name().map(|x| x.to_string_lossy().to_string()))
+                .and_then(|x| lspci_name(&x))
+                .or_else(|| driver.clone())
+                .unwrap_or_else(|| card.clone());
+
+            let driver_version = match driver.as_ref().map(|x| x.as_str()) {
+                Some("nvidia") => read_to_string("/sys/module/nvidia/version")
+                    .ok()
+                    .map(|x| x.trim().to_string()),
+                Some(_) => {
+                    if mesa.is_none() {
+                        mesa = Some(mesa_version());
+                    }
+                    mesa.clone().and_then(|x| x)
+                }
+                None => None,
+            };
+
+            gpus.push(super::Gpu {
+                name,
+                vendor_id,
+                driver_version,
+            });
+        }
+
+        gpus
+    }
+
+    /// Returns the user's locale as a POSIX locale name (e.g. "de_DE.UTF-8"), if known.
+    pub fn get_user_locale() -> Option<String> {
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|x| env::var(x).ok())
+            .find(|x| !x.is_empty() && x != "C" && x != "POSIX");
+        if locale.is_some() {
+            return locale;
+        }
+
+        // Applications started from the Finder don't inherit a shell's environment
+        if cfg!(target_os = "macos") {
+            return Command::new("defaults")
+                .args(&["read", "-g", "AppleLocale"])
+                .output()
+                .ok()
+                .and_then(|x| String::from_utf8(x.stdout).ok())
+                .map(|x| x.trim().to_string())
+                .filter(|x| !x.is_empty());
+        }
+
+        None
+    }
+
+    /// Shows a desktop notification, which screen readers read out.
+    pub fn show_notification(title: &str, message: &str) -> Result<(), String> {
+        let mut command = if cfg!(target_os = "macos") {
+            // Arguments are passed through, so that they never have to be escaped
+            let mut command = Command::new("osascript");
+            command
+                .args(&["-e", "on run argv"])
+                .args(&[
+                    "-e",
+                    "display notification (item 2 of argv) with title (item 1 of argv)",
+                ])
+                .args(&["-e", "end run"])
+                .args(&[title, message]);
+            command
+        } else if is_on_path("notify-send") {
+            let mut command = Command::new("notify-send");
+            command.args(&["--", title, message]);
+            command
+        } else {
+            return Err("notify-send isn't available to show notifications".to_string());
+        };
+
+        let mut child = command
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|x| format!("Unable to show notification: {:?}", x))?;
+
+        // Waited on elsewhere, so that it doesn't linger as a zombie once it exits
+        thread::spawn(move || {
+            let _ = child.wait();
+        });
+
+        Ok(())
+    }
+
+    /// Creates a symbolic link at `link`, pointing to `target`. Junctions are specific to
+    /// Windows, so these are always symbolic links.
+    pub fn create_directory_link(
+        link: &Path,
+        target: &Path,
+        _junction: bool,
+    ) -> Result<(), String> {
+        ::std::os::unix::fs::symlink(target, link)
+            .map_err(|x| format!("Unable to link {:?} to {:?}: {:?}", link, target, x))
+    }
+
+    /// Removes a symbolic link, leaving whatever it points to.
+    pub fn remove_directory_link(link: &Path) -> Result<(), String> {
+        remove_file(link).map_err(|x| format!("Unable to remove link {:?}: {:?}", link, x))
+    }
+
+    /// A command and everything it starts, which can be killed together. On Unix, this is
+    /// the process group the command leads.
+    pub struct ProcessTree {
+        group: i32,
+    }
+
+    impl ProcessTree {
+        /// Kills the command and everything it has started which is still running, unless
+        /// it has left the group (e.g. daemons, which start a session of their own).
+        #[allow(unsafe_code)]
+        pub fn kill(&self) -> Result<(), String> {
+            if unsafe { libc::kill(-self.group, libc::SIGKILL) } != 0 {
+                return Err(format!(
+                    "Unable to kill process group {}: {:?}",
+                    self.group,
+                    ::std::io::Error::last_os_error()
+                ));
+            }
+
+            Ok(())
+        }
+    }
+
+    /// Prepares a command to be tracked along with everything it starts (see
+    /// `track_process_tree`), by having it lead a process group of its own.
+    #[allow(unsafe_code)]
+    pub fn isolate_process_tree(command: &mut Command) {
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setpgid(0, 0) != 0 {
+                    return Err(::std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+
+    /// Tracks a command started after `isolate_process_tree`, along with everything it
+    /// starts.
+    pub fn track_process_tree(child: &process::Child) -> Result<ProcessTree, String> {
+        Ok(ProcessTree {
+            group: child.id() as i32,
+        })
     }
 }
 
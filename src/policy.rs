@@ -0,0 +1,311 @@
+//! policy.rs
+//!
+//! Applies settings enforced by administrators for every installation on a machine, which
+//! take precedence over both the configuration and the user's choices. These can:
+//!
+//! - disable telemetry (webhooks, which report install events to the publisher)
+//! - force the install directory of new installations
+//! - lock every package to a release channel
+//! - set the proxy to use
+//! - restrict who may modify or uninstall the application
+//!
+//! On Windows, these are read from group policy under `HKLM\SOFTWARE\Policies\<name>`,
+//! as REG_SZ values:
+//!
+//! - `DisableTelemetry`: "1" or "true"
+//! - `InstallDir`: the directory to install to
+//! - `Channel`: the release channel to use
+//! - `ProxyUrl`, `ProxyBypass` (comma separated): the proxy to use
+//! - `MaintenanceRestricted`: "1" or "true" to restrict modifying and uninstalling
+//! - `MaintenanceTokenSha256`: the SHA-256 hash of a token which allows it anyway, as hex
+//!
+//! Elsewhere, they are read from `/etc/liftinstall/<name>/policy.toml` (see
+//! `MachinePolicy`), so that each application has its own policy as it does on Windows.
+//!
+//! Restricted installations can only be modified or uninstalled by an elevated
+//! maintenance tool, or after a token handed out by an administrator has been given.
+//! Updating and repairing are always allowed. An installation whose configuration can't
+//! be loaded (not even the copy cached by the last run) is treated as restricted, as there
+//! is no telling if it was.
+
+use std::fs::read_to_string;
+use std::path::Path;
+use std::sync::RwLock;
+
+use ring::constant_time::verify_slices_are_equal;
+
+use config::{Config, MaintenancePolicy, ProxyConfig};
+
+use error_help;
+
+use hashing::sha256_hex;
+
+use http;
+
+use installer::InstallerFramework;
+
+use logging::LoggingErrors;
+
+use native;
+
+use strings;
+
+/// Where the machine's policies are read from, outside of Windows. Each application's is
+/// in a directory of its own name.
+const POLICY_DIR: &str = "/etc/liftinstall";
+/// Name of each application's policy file, within its directory.
+const POLICY_FILE: &str = "policy.toml";
+
+/// Settings enforced by administrators for every installation on the machine.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct MachinePolicy {
+    /// Keeps webhooks from being notified of install events
+    #[serde(default)]
+    pub disable_telemetry: bool,
+    /// Where new installations go, which the user can't change
+    #[serde(default)]
+    pub install_dir: Option<String>,
+    /// The release channel packages are installed from, where they have it
+    #[serde(default)]
+    pub channel: Option<String>,
+    /// The proxy all requests are sent through, which the user can't change
+    #[serde(default)]
+    pub proxy: Option<ProxyConfig>,
+    /// Overrides the configuration's `maintenance_policy`
+    #[serde(default)]
+    pub maintenance: Option<MaintenancePolicy>,
+}
+
+lazy_static! {
+    static ref MACHINE: RwLock<MachinePolicy> = RwLock::new(MachinePolicy::default());
+}
+
+/// The policy in place, as shown to the frontend.
+#[derive(Serialize)]
+pub struct PolicyStatus {
+    pub telemetry_disabled: bool,
+    pub install_dir: Option<String>,
+    pub channel: Option<String>,
+    pub proxy_locked: bool,
+    /// If modifying and uninstalling are restricted
+    pub restricted: bool,
+    /// If modifying and uninstalling are allowed, having been elevated or given a token
+    pub allowed: bool,
+    /// If a token can be given to be allowed
+    pub accepts_token: bool,
+}
+
+/// Returns if a policy value turns something on.
+fn is_enabled(value: &str) -> bool {
+    let value = value.trim().to_lowercase();
+    value == "1" || value == "true"
+}
+
+/// Reads the policy set through group policy, if any has been.
+fn read_group_policy(name: &str) -> Option<MachinePolicy> {
+    let key = format!("HKLM\\SOFTWARE\\Policies\\{}\\", name);
+    let value = |name: &str| {
+        native::read_registry_string(&format!("{}{}", key, name))
+            .ok()
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty())
+    };
+
+    let proxy = value("ProxyUrl").map(|url| ProxyConfig {
+        url,
+        username: None,
+        password: None,
+        no_proxy: value("ProxyBypass")
+            .map(|x| {
+                x.split(',')
+                    .map(|x| x.trim().to_string())
+                    .filter(|x| !x.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default(),
+    });
+
+    let maintenance = value("MaintenanceRestricted").map(|x| MaintenancePolicy {
+        restricted: is_enabled(&x),
+        token_sha256: value("MaintenanceTokenSha256"),
+    });
+
+    let policy = MachinePolicy {
+        disable_telemetry: value("DisableTelemetry").map_or(false, |x| is_enabled(&x)),
+        install_dir: value("InstallDir"),
+        channel: value("Channel"),
+        proxy,
+        maintenance,
+    };
+
+    let is_set = policy.disable_telemetry
+        || policy.install_dir.is_some()
+        || policy.channel.is_some()
+        || policy.proxy.is_some()
+        || policy.maintenance.is_some();
+    if is_set {
+        Some(policy)
+    } else {
+        None
+    }
+}
+
+/// Reads an application's policy file, if there is one.
+fn read_policy_file(name: &str) -> Option<MachinePolicy> {
+    let path = Path::new(POLICY_DIR).join(name).join(POLICY_FILE);
+    let text = read_to_string(&path).ok()?;
+
+    match toml::from_str(&text) {
+        Ok(v) => Some(v),
+        Err(v) => {
+            error!("Ignoring invalid policy file {:?}: {:?}", path, v);
+            None
+        }
+    }
+}
+
+/// Reads the machine's policy for an application, and applies what can be applied up
+/// front.
+pub fn load(name: &str) {
+    let policy = if cfg!(windows) {
+        read_group_policy(name)
+    } else {
+        read_policy_file(name)
+    };
+
+    let policy = match policy {
+        Some(v) => v,
+        None => return,
+    };
+
+    info!(
+        "Applying machine policy (telemetry disabled: {}, install dir: {:?}, channel: {:?}, \
+         proxy: {:?})",
+        policy.disable_telemetry,
+        policy.install_dir,
+        policy.channel,
+        policy.proxy.as_ref().map(|x| &x.url)
+    );
+
+    let proxy = policy.proxy.clone().filter(|x| match http::check_proxy(x) {
+        Ok(_) => true,
+        Err(v) => {
+            error!("Ignoring invalid proxy from machine policy: {}", v);
+            false
+        }
+    });
+    http::set_machine_proxy(proxy);
+
+    *MACHINE.write().log_expect("Policy state has been dirtied") = policy;
+}
+
+/// Returns the machine's policy.
+pub fn machine() -> MachinePolicy {
+    MACHINE
+        .read()
+        .log_expect("Policy state has been dirtied")
+        .clone()
+}
+
+/// Returns where a new installation goes: the directory forced by the machine's policy
+/// if there is one, else the one chosen.
+pub fn install_dir(chosen: &str) -> String {
+    match machine().install_dir {
+        Some(ref forced) if forced != chosen => {
+            info!(
+                "Installing to {:?} instead of {:?}, as set by machine policy",
+                forced, chosen
+            );
+            forced.to_string()
+        }
+        _ => chosen.to_string(),
+    }
+}
+
+/// Returns the restrictions on modifying and uninstalling an installation.
+pub fn get_maintenance(framework: &InstallerFramework) -> MaintenancePolicy {
+    if let Some(policy) = machine().maintenance {
+        return policy;
+    }
+
+    if let Some(ref config) = framework.config {
+        return config.maintenance_policy.clone();
+    }
+
+    // Nothing has been installed, so there is nothing to protect yet
+    if !framework.preexisting_install {
+        return MaintenancePolicy::default();
+    }
+
+    let cached = framework
+        .read_cached_config()
+        .and_then(|x| Config::from_toml_str(&x).ok());
+    match cached {
+        Some(config) => config.maintenance_policy,
+        None => {
+            warn!("No configuration is available to check the maintenance policy with");
+            MaintenancePolicy {
+                restricted: true,
+                token_sha256: None,
+            }
+        }
+    }
+}
+
+/// Returns the policy in place, and whether its restrictions apply to this process.
+pub fn status(framework: &InstallerFramework) -> PolicyStatus {
+    let machine = machine();
+    let maintenance = get_maintenance(framework);
+
+    PolicyStatus {
+        telemetry_disabled: machine.disable_telemetry,
+        install_dir: machine.install_dir,
+        channel: machine.channel,
+        proxy_locked: machine.proxy.is_some(),
+        restricted: maintenance.restricted,
+        allowed: !maintenance.restricted || framework.maintenance_unlocked || native::is_elevated(),
+        accepts_token: maintenance.token_sha256.is_some(),
+    }
+}
+
+/// Returns an error if the installation can't be modified or uninstalled by this process.
+pub fn check(framework: &InstallerFramework) -> Result<(), String> {
+    if status(framework).allowed {
+        return Ok(());
+    }
+
+    warn!("Refusing to modify or uninstall, as this is restricted by policy");
+    Err(error_help::coded(
+        "maintenance_restricted",
+        strings::tr("errors.maintenance_restricted"),
+    ))
+}
+
+/// Allows the installation to be modified or uninstalled for the rest of the session, if
+/// the token is valid.
+pub fn unlock(framework: &mut InstallerFramework, token: &str) -> Result<(), String> {
+    let expected = get_maintenance(framework)
+        .token_sha256
+        .ok_or_else(|| {
+            error_help::coded(
+                "maintenance_restricted",
+                strings::tr("errors.maintenance_token_unsupported"),
+            )
+        })?
+        .trim()
+        .to_lowercase();
+
+    let hash = sha256_hex(token.trim().as_bytes());
+    if verify_slices_are_equal(expected.as_bytes(), hash.as_bytes()).is_err() {
+        warn!("An invalid maintenance token was given");
+        return Err(error_help::coded(
+            "maintenance_restricted",
+            strings::tr("errors.maintenance_token_invalid"),
+        ));
+    }
+
+    info!("Maintenance token accepted, allowing modification for this session");
+    framework.maintenance_unlocked = true;
+
+    Ok(())
+}
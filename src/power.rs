@@ -0,0 +1,113 @@
+//! power.rs
+//!
+//! Pauses or throttles downloads while the machine is running on battery power.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use config::{BatteryPolicy, ResourceLimits};
+
+use native;
+
+/// How often the power source is checked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// Throttled transfers are averaged over windows of this length.
+const THROTTLE_WINDOW: Duration = Duration::from_secs(5);
+
+/// Applies the configured battery policy to a stream of downloaded data.
+pub struct BatteryGovernor {
+    policy: BatteryPolicy,
+    rate: u64,
+    last_check: Option<Instant>,
+    on_battery: bool,
+    window_start: Instant,
+    window_bytes: u64,
+    paused: bool,
+}
+
+impl BatteryGovernor {
+    /// Creates a new governor from the configured resource limits.
+    pub fn new(limits: &ResourceLimits) -> BatteryGovernor {
+        BatteryGovernor {
+            policy: limits.on_battery.clone(),
+            rate: limits.battery_download_rate.max(1),
+            last_check: None,
+            on_battery: false,
+            window_start: Instant::now(),
+            window_bytes: 0,
+            paused: false,
+        }
+    }
+
+    /// Checks the power source, if it hasn't been checked recently.
+    fn check(&mut self) -> bool {
+        let due = self
+            .last_check
+            .map_or(true, |x| x.elapsed() >= CHECK_INTERVAL);
+
+        if due {
+            let on_battery = native::is_on_battery().unwrap_or(false);
+            if on_battery != self.on_battery {
+                info!(
+                    "Now running on {}",
+                    if on_battery {
+                        "battery power"
+                    } else {
+                        "AC power"
+                    }
+                );
+            }
+
+            self.on_battery = on_battery;
+            self.last_check = Some(Instant::now());
+        }
+
+        self.on_battery
+    }
+
+    /// Called as data arrives. Blocks while downloads should be paused, or for long
+    /// enough to keep within the throttled rate. `notify` is called while paused.
+    pub fn wait<F>(&mut self, bytes: usize, notify: F)
+    where
+        F: Fn(),
+    {
+        match self.policy {
+            BatteryPolicy::Pause => {
+                while self.check() {
+                    self.paused = true;
+                    notify();
+                    thread::sleep(CHECK_INTERVAL);
+                }
+            }
+            BatteryPolicy::Throttle => {
+                if !self.check() {
+                    self.window_bytes = 0;
+                    self.window_start = Instant::now();
+                    return;
+                }
+
+                self.window_bytes += bytes as u64;
+
+                let expected = Duration::from_millis(self.window_bytes * 1000 / self.rate);
+                let elapsed = self.window_start.elapsed();
+                if expected > elapsed {
+                    thread::sleep(expected - elapsed);
+                }
+
+                if elapsed >= THROTTLE_WINDOW {
+                    self.window_bytes = 0;
+                    self.window_start = Instant::now();
+                }
+            }
+            BatteryPolicy::Ignore => {}
+        }
+    }
+
+    /// Returns if downloads were paused since this was last called, clearing the flag.
+    /// Connections may be dropped by servers while paused.
+    pub fn take_paused(&mut self) -> bool {
+        let paused = self.paused;
+        self.paused = false;
+        paused
+    }
+}
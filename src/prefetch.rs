@@ -0,0 +1,342 @@
+//! prefetch.rs
+//!
+//! Starts downloading while the user reads the welcome screen, if `prefetch` is set, so
+//! that part of the download is already done by the time they choose to install. Once
+//! the configuration has been loaded, the first package selected by default is resolved
+//! (which also fills in the source's cached releases) and its file is fetched to disk in
+//! ranges, picking up from anything fetched by an earlier run of the same release.
+//!
+//! What the worker needs is copied out of the framework before it starts, so it never
+//! waits on the framework's lock. Downloading that package takes over what has been
+//! fetched, and stops fetching any more. Exiting without installing it removes what was
+//! fetched.
+//!
+//! Prefetching is never asked for, so it doesn't wait for the download window to open or
+//! for the machine to be plugged in, as installing does: it stops instead. It also
+//! doesn't start if the download would use up a large part of the data budget.
+
+use std::fs::{copy, create_dir_all, read_dir, remove_file, rename, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use chrono::Local;
+
+use bandwidth;
+
+use bundle;
+
+use config::{BatteryPolicy, PackageDescription, ReleaseChannel, ResourceLimits, UrlRewrite};
+
+use hashing::sha256_hex;
+
+use http::fetch_range;
+
+use installer::InstallerFramework;
+
+use logging::LoggingErrors;
+
+use native;
+
+use sources::types::{File, Version};
+
+use tasks::resolver::find_latest_in;
+use tasks::TaskMessage;
+
+/// How much is requested at once.
+const RANGE_SIZE: u64 = 4 * 1024 * 1024;
+/// Extension of files being prefetched.
+const PARTIAL_EXTENSION: &str = "part";
+
+/// A file being fetched ahead of time.
+struct Prefetched {
+    package: String,
+    url: String,
+    path: PathBuf,
+}
+
+/// Everything the worker needs, copied out of the framework.
+struct Job {
+    package: PackageDescription,
+    rewrites: Vec<UrlRewrite>,
+    channel: Option<ReleaseChannel>,
+    limits: ResourceLimits,
+    dir: PathBuf,
+}
+
+lazy_static! {
+    /// Only held while a range is written out, so taking it never waits on the network
+    static ref CURRENT: Mutex<Option<Prefetched>> = Mutex::new(None);
+}
+
+/// If this run has started prefetching, which only happens once.
+static STARTED: AtomicBool = AtomicBool::new(false);
+/// Asks the worker to stop once the current request has finished.
+static STOP: AtomicBool = AtomicBool::new(false);
+
+/// Where what has been fetched of a file is kept. Files published again under the same
+/// URL are told apart by their release and size, so that what was fetched of an older
+/// one is never picked up from.
+fn partial_path(dir: &Path, version: &Version, file: &File) -> PathBuf {
+    let key = format!("{}\n{}\n{:?}", file.url, version, file.size);
+    dir.join(format!(
+        "{}.{}",
+        &sha256_hex(key.as_bytes())[..16],
+        PARTIAL_EXTENSION
+    ))
+}
+
+/// Removes what earlier runs fetched of anything other than `keep`.
+fn remove_stale(dir: &Path, keep: &Path) {
+    let entries = match read_dir(dir) {
+        Ok(v) => v,
+        Err(_) => return,
+    };
+
+    for entry in entries.filter_map(|x| x.ok()).map(|x| x.path()) {
+        let is_partial = entry.extension().map_or(false, |x| x == PARTIAL_EXTENSION);
+        if is_partial && entry != keep {
+            info!(
+                "Removing {:?}, which was prefetched by an earlier run",
+                entry
+            );
+
+            if let Err(v) = remove_file(&entry) {
+                warn!("Unable to remove {:?}: {:?}", entry, v);
+            }
+        }
+    }
+}
+
+/// Checks that downloading is allowed at the moment.
+fn check_allowed(limits: &ResourceLimits) -> Result<(), String> {
+    if let Some(ref window) = limits.download_window {
+        if window.time_until_open(Local::now().time())? > Duration::from_secs(0) {
+            return Err("Outside of the download window".to_string());
+        }
+    }
+
+    if limits.on_battery != BatteryPolicy::Ignore && native::is_on_battery().unwrap_or(false) {
+        return Err("Running on battery power".to_string());
+    }
+
+    Ok(())
+}
+
+/// Stops tracking what was fetched, removing it if `remove` is set.
+fn forget(remove: bool) {
+    let prefetched = CURRENT
+        .lock()
+        .log_expect("Prefetch state has been dirtied")
+        .take();
+
+    if let Some(prefetched) = prefetched {
+        if remove && prefetched.path.exists() {
+            info!(
+                "Removing {:?}, which was prefetched but not used",
+                prefetched.package
+            );
+
+            if let Err(v) = remove_file(&prefetched.path) {
+                warn!("Unable to remove {:?}: {:?}", prefetched.path, v);
+            }
+        }
+    }
+}
+
+/// Works out what to fetch, and fetches it until it is complete or asked to stop.
+fn run(job: &Job) -> Result<(), String> {
+    check_allowed(&job.limits)?;
+
+    let (version, file) = find_latest_in(
+        &job.package,
+        &job.rewrites,
+        job.channel.clone(),
+        &|_: &TaskMessage| {},
+    )?;
+
+    // Where each part begins isn't recorded, so multi-part archives can't be picked up from
+    if file.requires_authorization || !file.parts.is_empty() {
+        return Ok(());
+    }
+
+    // Users with a data budget haven't agreed to anything being downloaded yet
+    let budget_warning = match file.size {
+        Some(size) => bandwidth::check(size),
+        None => bandwidth::summary()
+            .monthly_budget
+            .map(|_| "The size of the download isn't known".to_string()),
+    };
+    if let Some(v) = budget_warning {
+        return Err(v);
+    }
+
+    let path = partial_path(&job.dir, &version, &file);
+    create_dir_all(&job.dir).map_err(|x| format!("Unable to create {:?}: {:?}", job.dir, x))?;
+    remove_stale(&job.dir, &path);
+
+    let mut offset = path.metadata().map(|x| x.len()).unwrap_or(0);
+    if file.size.map_or(false, |x| offset > x) {
+        remove_file(&path).map_err(|x| format!("Unable to remove {:?}: {:?}", path, x))?;
+        offset = 0;
+    }
+
+    info!(
+        "Prefetching {:?} from {:?}, starting at {} bytes",
+        job.package.name, file.url, offset
+    );
+    *CURRENT.lock().log_expect("Prefetch state has been dirtied") = Some(Prefetched {
+        package: job.package.name.clone(),
+        url: file.url.clone(),
+        path: path.clone(),
+    });
+
+    while !STOP.load(Ordering::SeqCst) {
+        check_allowed(&job.limits)?;
+
+        let mut end = offset + RANGE_SIZE - 1;
+        if let Some(size) = file.size {
+            if offset >= size {
+                break;
+            }
+            end = end.min(size - 1);
+        }
+
+        let requested = end - offset + 1;
+        let data = match fetch_range(&file.url, offset, end) {
+            Ok(v) => v,
+            Err(v) => {
+                // What has been fetched is of no use if ranges aren't supported
+                if offset == 0 {
+                    forget(true);
+                }
+                return Err(v);
+            }
+        };
+
+        {
+            // Anything fetched after being handed over is no longer needed
+            let current = CURRENT.lock().log_expect("Prefetch state has been dirtied");
+            if current.is_none() || STOP.load(Ordering::SeqCst) {
+                break;
+            }
+
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .and_then(|mut x| x.write_all(&data))
+                .map_err(|x| format!("Unable to write {:?}: {:?}", path, x))?;
+        }
+
+        offset += data.len() as u64;
+
+        if (data.len() as u64) < requested {
+            break;
+        }
+    }
+
+    info!("Prefetched {} bytes of {:?}", offset, job.package.name);
+    Ok(())
+}
+
+/// Starts prefetching in the background, if the configuration asks for it. Nothing waits
+/// on the network here, so this can be called while holding the framework's lock.
+pub fn start(framework: &InstallerFramework) {
+    // Maintenance runs have nothing new to download yet
+    if framework.preexisting_install || bundle::config_text().is_some() {
+        return;
+    }
+
+    let config = match framework.config {
+        Some(ref v) if v.prefetch => v,
+        _ => return,
+    };
+
+    // Private sources can't be fetched from until the user has logged in
+    let package = config.packages.iter().find(|x| {
+        x.default.unwrap_or(false)
+            && x.unsupported.is_none()
+            && !x.requires_authorization.unwrap_or(false)
+    });
+    let package = match package {
+        Some(v) => v,
+        None => return,
+    };
+
+    if STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let job = Job {
+        package: package.clone(),
+        rewrites: config.url_rewrites.clone(),
+        channel: framework.get_channel(package).cloned(),
+        limits: config.limits.clone(),
+        dir: framework.get_prefetch_dir(),
+    };
+
+    thread::spawn(move || {
+        if let Err(v) = run(&job) {
+            info!("Prefetching stopped: {}", v);
+        }
+    });
+}
+
+/// Hands what was fetched of a package's file over to its download at `path`, if it was
+/// the one being fetched, and stops prefetching. This only waits for the worker to finish
+/// writing what it has already received, never on the network.
+pub fn take(package: &str, url: &str, path: &Path) -> bool {
+    STOP.store(true, Ordering::SeqCst);
+
+    let prefetched = {
+        let mut current = CURRENT.lock().log_expect("Prefetch state has been dirtied");
+        let matches = current
+            .as_ref()
+            .map_or(false, |x| x.package == package && x.url == url);
+        if !matches {
+            return false;
+        }
+
+        match current.take() {
+            Some(v) => v,
+            None => return false,
+        }
+    };
+
+    if let Some(parent) = path.parent() {
+        if let Err(v) = create_dir_all(parent) {
+            warn!("Unable to create {:?}: {:?}", parent, v);
+        }
+    }
+
+    // Prefetched files may be on another drive (e.g. in the temporary directory)
+    let moved = rename(&prefetched.path, path)
+        .or_else(|_| copy(&prefetched.path, path).and_then(|_| remove_file(&prefetched.path)));
+
+    match moved {
+        Ok(_) => {
+            info!(
+                "Picking up from what was fetched of {:?} ahead of time",
+                package
+            );
+            true
+        }
+        Err(v) => {
+            warn!("Unable to use prefetched {:?}: {:?}", prefetched.path, v);
+            if let Err(v) = remove_file(&prefetched.path) {
+                warn!("Unable to remove {:?}: {:?}", prefetched.path, v);
+            }
+            false
+        }
+    }
+}
+
+/// Stops prefetching, and removes anything which was fetched but not used.
+pub fn discard() {
+    STOP.store(true, Ordering::SeqCst);
+    forget(true);
+}
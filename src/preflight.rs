@@ -0,0 +1,288 @@
+//! preflight.rs
+//!
+//! Estimates how much space an install will take before it starts, from the sizes the
+//! package sources report, so that the user can be warned about a lack of free space
+//! up front rather than part way through extracting.
+//!
+//! What is needed is copied out of the framework first (see `prepare`), so that sources
+//! are queried without holding it. Packages which are already installed only need the
+//! difference in space, and nothing at all if they are already up to date.
+
+use std::path::{Path, PathBuf};
+
+use bandwidth;
+
+use config::{PackageDescription, ReleaseChannel, UrlRewrite};
+
+use disk_space::{existing_ancestor, pretty_size};
+
+use http;
+
+use installer::InstallerFramework;
+
+use native;
+
+use source_auth;
+
+use sources::types::{File, Version};
+
+use tasks::resolver::find_latest_in;
+use tasks::TaskMessage;
+
+/// How much larger archives are expected to be once extracted, by extension. These err
+/// on the generous side, as a spurious warning is better than running out of space.
+const EXPANSION_RATIOS: [(&str, f64); 5] = [
+    (".zip", 2.5),
+    (".tar.gz", 3.0),
+    (".tgz", 3.0),
+    (".tar.xz", 4.0),
+    (".txz", 4.0),
+];
+
+/// What a single package is expected to take up.
+#[derive(Serialize)]
+pub struct PackageEstimate {
+    pub name: String,
+    pub version: Option<String>,
+    /// Size of what is downloaded, in bytes
+    pub download_size: Option<u64>,
+    /// Space taken once installed, in bytes, beyond what is already installed of it
+    pub installed_size: Option<u64>,
+    /// Why the package's size couldn't be estimated, if it couldn't
+    pub error: Option<String>,
+}
+
+/// The space needed within a directory packages are installed into, compared to what
+/// is free on its volume.
+#[derive(Serialize)]
+pub struct VolumeEstimate {
+    pub path: String,
+    /// Space needed, including what is kept free (see `ResourceLimits::min_free_space`)
+    pub required: u64,
+    pub required_text: String,
+    /// Free space, if it could be read
+    pub available: Option<u64>,
+    pub available_text: Option<String>,
+    pub sufficient: bool,
+}
+
+/// The outcome of checking an install before it starts.
+#[derive(Serialize)]
+pub struct SpaceEstimate {
+    pub packages: Vec<PackageEstimate>,
+    pub volumes: Vec<VolumeEstimate>,
+    pub download_size: u64,
+    pub download_text: String,
+    /// If the size of every package was known, rather than some being left out
+    pub complete: bool,
+    /// If every volume has enough free space
+    pub sufficient: bool,
+    /// Why the download is worth confirming, given the user's monthly data budget
+    pub data_warning: Option<String>,
+}
+
+/// A package to estimate, along with what is already installed of it.
+struct PlannedPackage {
+    package: PackageDescription,
+    channel: Option<ReleaseChannel>,
+    /// Why requests to the package's source couldn't be authorised, if they couldn't
+    unauthorized: Option<String>,
+    root: PathBuf,
+    /// The installed version, and where its files are
+    installed: Option<(Version, Vec<PathBuf>)>,
+}
+
+/// Everything an estimate needs, copied out of the framework.
+pub struct Plan {
+    packages: Vec<PlannedPackage>,
+    rewrites: Vec<UrlRewrite>,
+    min_free_space: u64,
+}
+
+/// Finds the size of a file, asking the server for each part the source didn't report
+/// the size of.
+fn download_size(file: &File) -> Result<u64, String> {
+    if let Some(size) = file.size {
+        return Ok(size);
+    }
+
+    let mut total = 0;
+    for url in Some(&file.url).into_iter().chain(file.parts.iter()) {
+        total += http::get_content_length(url, None)?
+            .ok_or_else(|| format!("The server didn't report the size of {}", url))?;
+    }
+
+    Ok(total)
+}
+
+/// Estimates the space a package takes once installed.
+fn installed_size(package: &PackageDescription, file: &File, download_size: u64) -> u64 {
+    if let Some(size) = package.installed_size {
+        return size;
+    }
+
+    let name = file.name.to_lowercase();
+    let ratio = EXPANSION_RATIOS
+        .iter()
+        .find(|x| name.ends_with(x.0))
+        .map_or(1.0, |x| x.1);
+
+    (download_size as f64 * ratio) as u64
+}
+
+/// Works out what is needed to estimate the space needed to install the specified
+/// packages (along with anything they require) into a directory. This doesn't touch the
+/// network, so can be done while holding the framework.
+pub fn prepare(
+    framework: &InstallerFramework,
+    items: &[String],
+    install_path: &Path,
+) -> Result<Plan, String> {
+    let config = framework
+        .config
+        .as_ref()
+        .ok_or("Configuration hasn't been loaded")?;
+    let items = config.resolve_packages(items)?;
+
+    let mut packages = Vec::new();
+    for package in config.packages.iter().filter(|x| items.contains(&x.name)) {
+        let root = framework.get_package_root_in(&package.name, install_path)?;
+        let installed = framework
+            .database
+            .packages
+            .iter()
+            .find(|x| x.name == package.name)
+            .map(|x| {
+                let files = x.files.iter().map(|file| root.join(file)).collect();
+                (x.version.clone(), files)
+            });
+
+        // Private sources are only queried if a token is already available
+        let unauthorized =
+            source_auth::authorize_silently(&package.name, &package.source, Some(framework)).err();
+
+        packages.push(PlannedPackage {
+            package: package.clone(),
+            channel: framework.get_channel(package).cloned(),
+            unauthorized,
+            root,
+            installed,
+        });
+    }
+
+    Ok(Plan {
+        packages,
+        rewrites: config.url_rewrites.clone(),
+        min_free_space: config.limits.min_free_space,
+    })
+}
+
+/// Adds up the space taken by files which are already installed.
+fn size_on_disk(files: &[PathBuf]) -> u64 {
+    files
+        .iter()
+        .filter_map(|x| x.symlink_metadata().ok())
+        .filter(|x| x.is_file())
+        .map(|x| x.len())
+        .sum()
+}
+
+/// Estimates the space needed to carry out a plan (see `prepare`), and checks it against
+/// what is free.
+pub fn estimate(plan: &Plan) -> Result<SpaceEstimate, String> {
+    let mut packages = Vec::new();
+    let mut roots: Vec<(PathBuf, u64)> = Vec::new();
+
+    for planned in &plan.packages {
+        let package = &planned.package;
+        let mut estimate = PackageEstimate {
+            name: package.name.clone(),
+            version: None,
+            download_size: None,
+            installed_size: None,
+            error: None,
+        };
+
+        let result = match planned.unauthorized {
+            Some(ref v) => Err(v.clone()),
+            None => find_latest_in(
+                package,
+                &plan.rewrites,
+                planned.channel.clone(),
+                &|_: &TaskMessage| {},
+            ),
+        }
+        .and_then(|(version, file)| {
+            estimate.version = Some(version.to_string());
+
+            match planned.installed {
+                // Packages which are up to date aren't installed again
+                Some((ref installed, _)) if *installed == version => Ok((0, 0)),
+                // What is installed is replaced, so only the difference is needed
+                Some((_, ref files)) => {
+                    let size = download_size(&file)?;
+                    let needed =
+                        installed_size(package, &file, size).saturating_sub(size_on_disk(files));
+                    Ok((size, needed))
+                }
+                None => {
+                    let size = download_size(&file)?;
+                    Ok((size, installed_size(package, &file, size)))
+                }
+            }
+        });
+
+        match result {
+            Ok((download, installed)) => {
+                estimate.download_size = Some(download);
+                estimate.installed_size = Some(installed);
+
+                match roots.iter_mut().find(|x| x.0 == planned.root) {
+                    Some(entry) => entry.1 += installed,
+                    None => roots.push((planned.root.clone(), installed)),
+                }
+            }
+            Err(v) => {
+                warn!("Unable to estimate the size of {:?}: {}", package.name, v);
+                estimate.error = Some(v);
+            }
+        }
+
+        packages.push(estimate);
+    }
+
+    let volumes: Vec<VolumeEstimate> = roots
+        .into_iter()
+        .map(|(root, installed)| {
+            let required = installed + plan.min_free_space;
+            let available = match native::get_free_space(existing_ancestor(&root)) {
+                Ok(v) => Some(v),
+                Err(v) => {
+                    warn!("Unable to read free space for {:?}: {}", root, v);
+                    None
+                }
+            };
+
+            VolumeEstimate {
+                path: root.to_string_lossy().to_string(),
+                required,
+                required_text: pretty_size(required),
+                available,
+                available_text: available.map(pretty_size),
+                sufficient: available.map_or(true, |x| x >= required),
+            }
+        })
+        .collect();
+
+    let download_size = packages.iter().filter_map(|x| x.download_size).sum();
+
+    Ok(SpaceEstimate {
+        complete: packages.iter().all(|x| x.error.is_none()),
+        sufficient: volumes.iter().all(|x| x.sufficient),
+        download_size,
+        download_text: pretty_size(download_size),
+        data_warning: bandwidth::check(download_size),
+        packages,
+        volumes,
+    })
+}
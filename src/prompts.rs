@@ -0,0 +1,301 @@
+//! prompts.rs
+//!
+//! Lets tasks ask the user to make a decision, blocking until the frontend answers.
+//!
+//! Dialogs are questions described at runtime rather than in Rust, such as those asked by
+//! hook commands (see `sandbox`). Each has an ID, so that headless installs can answer
+//! them with `--answer ID=VALUE`. Those which aren't answered use their default, and fail
+//! if they have none.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+
+use events::{self, Event};
+
+use heartbeat;
+
+use logging;
+use logging::LoggingErrors;
+
+use strings;
+
+use tasks::TaskMessage;
+
+/// If there is anyone to answer questions. Otherwise, defaults are used.
+static INTERACTIVE: AtomicBool = AtomicBool::new(true);
+
+lazy_static! {
+    /// Where the answer to the question currently being asked should be sent.
+    static ref PENDING: Mutex<Option<Sender<String>>> = Mutex::new(None);
+    /// Answers to dialogs given ahead of time, by ID.
+    static ref ANSWERS: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+}
+
+/// What sort of answer a dialog takes.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DialogKind {
+    /// Answered with `yes` or `no`
+    Confirm,
+    /// Answered with one of the dialog's choices
+    Choice,
+    /// Answered with any text
+    Text,
+}
+
+/// A question described at runtime.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Dialog {
+    /// Identifies the dialog, e.g. for answering it with `--answer`
+    pub id: String,
+    pub kind: DialogKind,
+    pub question: String,
+    /// The possible answers, for `choice` dialogs
+    #[serde(default)]
+    pub choices: Vec<String>,
+    /// The answer used if there is no-one to ask
+    pub default: Option<String>,
+}
+
+/// Sets if questions can be asked. Headless installs have no-one to answer them.
+pub fn set_interactive(interactive: bool) {
+    INTERACTIVE.store(interactive, Ordering::SeqCst);
+}
+
+/// Sets the answers to dialogs, by ID, for when questions can't be asked.
+pub fn set_answers(answers: HashMap<String, String>) {
+    *ANSWERS.lock().log_expect("Prompt state has been dirtied") = answers;
+}
+
+/// Sends a question to the frontend, returning where its answer will arrive.
+fn send_question(
+    messenger: &dyn Fn(&TaskMessage),
+    question: &str,
+    choices: &[&str],
+) -> Receiver<String> {
+    let (sender, receiver) = channel();
+    *PENDING.lock().log_expect("Prompt state has been dirtied") = Some(sender);
+
+    messenger(&TaskMessage::Prompt(question, choices));
+    events::publish(Event::Prompt {
+        question: question.to_string(),
+        choices: choices.iter().map(|x| x.to_string()).collect(),
+    });
+
+    receiver
+}
+
+/// Asks the user to pick one of the specified choices, blocking until they do.
+/// The default is used if there is no user to ask, or if the answer is invalid.
+pub fn ask(
+    messenger: &dyn Fn(&TaskMessage),
+    question: &str,
+    choices: &[&str],
+    default: &str,
+) -> String {
+    if !INTERACTIVE.load(Ordering::SeqCst) {
+        info!("Answering {:?} with {:?}, as prompts are disabled", question, default);
+        return default.to_string();
+    }
+
+    let receiver = send_question(messenger, question, choices);
+
+    let answer = match heartbeat::waiting(|| receiver.recv()) {
+        Ok(ref v) if choices.contains(&v.as_str()) => v.to_owned(),
+        Ok(v) => {
+            warn!("Invalid answer {:?} to {:?}, using {:?}", v, question, default);
+            default.to_string()
+        }
+        Err(_) => default.to_string(),
+    };
+
+    info!("Answered {:?} with {:?}", question, answer);
+    answer
+}
+
+/// Asks the user to type in a secret (e.g. an access token), blocking until they do.
+/// Questions without any choices take free-form answers. Returns None if there is no
+/// user to ask, or if they didn't answer.
+pub fn ask_secret(messenger: &dyn Fn(&TaskMessage), question: &str) -> Option<String> {
+    if !INTERACTIVE.load(Ordering::SeqCst) {
+        info!("Not asking {:?}, as prompts are disabled", question);
+        return None;
+    }
+
+    let receiver = send_question(messenger, question, &[]);
+    let answer = heartbeat::waiting(|| receiver.recv())
+        .ok()
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty());
+
+    match answer {
+        Some(ref v) => {
+            logging::register_secret(v);
+            info!("Answered {:?}", question);
+        }
+        None => info!("No answer was given to {:?}", question),
+    }
+
+    answer
+}
+
+/// Asks the user to choose a directory, blocking until they do. The default is used if
+/// there is no user to ask, or if they didn't answer.
+pub fn ask_directory(messenger: &dyn Fn(&TaskMessage), question: &str, default: &str) -> String {
+    if !INTERACTIVE.load(Ordering::SeqCst) {
+        info!("Answering {:?} with {:?}, as prompts are disabled", question, default);
+        return default.to_string();
+    }
+
+    let (sender, receiver) = channel();
+    *PENDING.lock().log_expect("Prompt state has been dirtied") = Some(sender);
+
+    messenger(&TaskMessage::DirectoryPrompt(question, default));
+    events::publish(Event::DirectoryPrompt {
+        question: question.to_string(),
+        default: default.to_string(),
+    });
+
+    let answer = heartbeat::waiting(|| receiver.recv())
+        .ok()
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty())
+        .unwrap_or_else(|| default.to_string());
+
+    info!("Answered {:?} with {:?}", question, answer);
+    answer
+}
+
+/// Asks the user to type in some text, blocking until they do. The default is used if
+/// there is no user to ask, or if they didn't answer.
+pub fn ask_text(messenger: &dyn Fn(&TaskMessage), question: &str, default: &str) -> String {
+    if !INTERACTIVE.load(Ordering::SeqCst) {
+        info!("Answering {:?} with {:?}, as prompts are disabled", question, default);
+        return default.to_string();
+    }
+
+    let (sender, receiver) = channel();
+    *PENDING.lock().log_expect("Prompt state has been dirtied") = Some(sender);
+
+    messenger(&TaskMessage::TextPrompt(question, default));
+    events::publish(Event::TextPrompt {
+        question: question.to_string(),
+        default: default.to_string(),
+    });
+
+    let answer = heartbeat::waiting(|| receiver.recv())
+        .ok()
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty())
+        .unwrap_or_else(|| default.to_string());
+
+    info!("Answered {:?} with {:?}", question, answer);
+    answer
+}
+
+/// Checks that an answer is one a dialog takes.
+fn check_answer(dialog: &Dialog, answer: &str) -> Result<(), String> {
+    let valid = match dialog.kind {
+        DialogKind::Confirm => answer == "yes" || answer == "no",
+        DialogKind::Choice => dialog.choices.iter().any(|x| x == answer),
+        DialogKind::Text => true,
+    };
+
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "{:?} is not a valid answer to dialog {:?}",
+            answer, dialog.id
+        ))
+    }
+}
+
+/// Answers a dialog without asking, from `--answer` or its default.
+fn answer_headless(dialog: &Dialog) -> Result<String, String> {
+    let given = ANSWERS
+        .lock()
+        .log_expect("Prompt state has been dirtied")
+        .get(&dialog.id)
+        .cloned();
+
+    let answer = match given.or_else(|| dialog.default.clone()) {
+        Some(v) => v,
+        None => {
+            return Err(format!(
+                "Dialog {:?} ({:?}) has no default, so needs to be answered with \
+                 --answer {}=VALUE",
+                dialog.id, dialog.question, dialog.id
+            ))
+        }
+    };
+
+    check_answer(dialog, &answer)?;
+    info!("Answering dialog {:?} with {:?}", dialog.id, answer);
+
+    Ok(answer)
+}
+
+/// Asks the user to answer a dialog, blocking until they do. Confirmations are answered
+/// with `yes` or `no`, whichever language is being shown.
+pub fn ask_dialog(messenger: &dyn Fn(&TaskMessage), dialog: &Dialog) -> Result<String, String> {
+    if !INTERACTIVE.load(Ordering::SeqCst) {
+        return answer_headless(dialog);
+    }
+
+    if let Some(ref default) = dialog.default {
+        check_answer(dialog, default)?;
+    }
+    let default = dialog.default.clone().unwrap_or_default();
+
+    let answer = match dialog.kind {
+        DialogKind::Confirm => {
+            let yes = strings::tr("ui.yes");
+            let no = strings::tr("ui.no");
+            let shown_default = if default == "no" { &no } else { &yes };
+
+            let answer = ask(messenger, &dialog.question, &[&yes, &no], shown_default);
+            if answer == yes { "yes" } else { "no" }.to_string()
+        }
+        DialogKind::Choice => {
+            if dialog.choices.is_empty() {
+                return Err(format!("Dialog {:?} has no choices", dialog.id));
+            }
+
+            let choices: Vec<&str> = dialog.choices.iter().map(|x| x.as_str()).collect();
+            let shown_default = if default.is_empty() {
+                choices[0]
+            } else {
+                default.as_str()
+            };
+            ask(messenger, &dialog.question, &choices, shown_default)
+        }
+        DialogKind::Text => ask_text(messenger, &dialog.question, &default),
+    };
+
+    Ok(answer)
+}
+
+/// Answers the question currently being asked. Returns false if nothing was asked.
+pub fn answer(choice: String) -> bool {
+    let pending = PENDING
+        .lock()
+        .log_expect("Prompt state has been dirtied")
+        .take();
+
+    match pending {
+        Some(sender) => sender.send(choice).is_ok(),
+        None => false,
+    }
+}
+
+/// Stops waiting for an answer to the question currently being asked, so that its
+/// default is used.
+pub fn dismiss() {
+    PENDING
+        .lock()
+        .log_expect("Prompt state has been dirtied")
+        .take();
+}
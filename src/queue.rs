@@ -0,0 +1,237 @@
+//! queue.rs
+//!
+//! Operations requested while another is running (or to be run later), such as installing
+//! one package and then updating the rest, are queued up and run one after another. This
+//! allows launcher-style "queue this download" requests, from the UI or anything else
+//! using the API.
+//!
+//! The queue is saved alongside the installation's metadata, so jobs which hadn't been
+//! run by the time the installer closed are still there the next time it starts. Operations
+//! hold the framework locked while they run, so the queue is kept separately, and can be
+//! looked at (or added to) at any time.
+
+use std::fs::{read, remove_file};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::sync::{Mutex, RwLock};
+
+use chrono::Local;
+
+use atomic;
+
+use installer::{InstallMessage, InstallerFramework};
+
+use logging::LoggingErrors;
+
+use strings;
+
+/// Where the queue is saved, relative to the install directory.
+const QUEUE_FILE: &str = "queue.json";
+
+/// What a queued job does.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobAction {
+    /// Installs packages alongside those already installed
+    Install,
+    /// Updates everything which is installed
+    Update,
+    /// Removes packages, leaving the rest in place
+    Remove,
+}
+
+/// An operation waiting to be run.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Job {
+    pub id: u64,
+    pub action: JobAction,
+    /// Packages to install or remove. Updates apply to everything installed.
+    #[serde(default)]
+    pub packages: Vec<String>,
+    /// When the job was queued, as RFC 3339
+    pub queued: String,
+}
+
+/// The jobs waiting to be run.
+#[derive(Serialize, Clone, Default)]
+pub struct Queue {
+    pub jobs: Vec<Job>,
+    /// The job being run, if any
+    pub running: Option<u64>,
+    /// If jobs are being run at all, rather than only waiting
+    #[serde(skip)]
+    active: bool,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+lazy_static! {
+    static ref QUEUE: Mutex<Queue> = Mutex::new(Queue::default());
+}
+
+/// Saves the queue, so that it survives the installer being closed. Failures only mean
+/// that the queue is lost when it is, so are only logged.
+fn save(queue: &Queue) {
+    let path = match queue.path {
+        Some(ref v) => v,
+        None => return,
+    };
+
+    let result = serde_json::to_vec(&queue.jobs)
+        .map_err(|x| format!("{:?}", x))
+        .and_then(|x| atomic::write(path, &x).map_err(|x| format!("{:?}", x)));
+
+    if let Err(v) = result {
+        warn!("Unable to save the job queue to {:?}: {}", path, v);
+    }
+}
+
+/// Loads jobs left over from when the installer last closed, from an existing
+/// installation. Jobs can only be queued once this has been called.
+pub fn load(install_path: &Path) {
+    let path = install_path.join(QUEUE_FILE);
+    if QUEUE
+        .lock()
+        .log_expect("Job queue has been dirtied")
+        .path
+        .as_ref()
+        == Some(&path)
+    {
+        return;
+    }
+
+    let jobs: Vec<Job> = match read(&path) {
+        Ok(v) => serde_json::from_slice(&v).unwrap_or_else(|x| {
+            warn!("Ignoring unreadable job queue {:?}: {:?}", path, x);
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    };
+
+    if !jobs.is_empty() {
+        info!("{} job(s) were left queued from before", jobs.len());
+    }
+
+    let mut queue = QUEUE.lock().log_expect("Job queue has been dirtied");
+    queue.jobs = jobs;
+    queue.path = Some(path);
+}
+
+/// Removes the saved queue, as the installation it was for is uninstalled.
+pub fn remove_saved(install_path: &Path) {
+    let path = install_path.join(QUEUE_FILE);
+
+    let mut queue = QUEUE.lock().log_expect("Job queue has been dirtied");
+    queue.jobs.clear();
+    queue.path = None;
+
+    let paths = [atomic::backup_path(&path), path];
+    for path in paths.iter().filter(|x| x.exists()) {
+        if let Err(v) = remove_file(path) {
+            warn!("Unable to remove {:?}: {:?}", path, v);
+        }
+    }
+}
+
+/// Returns the jobs waiting to be run.
+pub fn get() -> Queue {
+    QUEUE
+        .lock()
+        .log_expect("Job queue has been dirtied")
+        .clone()
+}
+
+/// Adds a job to the end of the queue.
+pub fn push(action: JobAction, packages: Vec<String>) -> Result<Job, String> {
+    let mut queue = QUEUE.lock().log_expect("Job queue has been dirtied");
+    if queue.path.is_none() {
+        return Err(strings::tr("errors.queue_not_installed"));
+    }
+
+    if action != JobAction::Update && packages.is_empty() {
+        return Err(strings::tr("errors.queue_no_packages"));
+    }
+
+    let job = Job {
+        id: queue.jobs.iter().map(|x| x.id).max().unwrap_or(0) + 1,
+        action,
+        packages,
+        queued: Local::now().to_rfc3339(),
+    };
+
+    info!("Queued {:?}", job);
+    queue.jobs.push(job.clone());
+    save(&queue);
+
+    Ok(job)
+}
+
+/// Removes a job which hasn't started yet.
+pub fn remove(id: u64) -> Result<(), String> {
+    let mut queue = QUEUE.lock().log_expect("Job queue has been dirtied");
+    if queue.running == Some(id) {
+        return Err(strings::tr("errors.job_running"));
+    }
+
+    let count = queue.jobs.len();
+    queue.jobs.retain(|x| x.id != id);
+    if queue.jobs.len() == count {
+        return Err(strings::format(
+            "errors.no_such_job",
+            &[("id", &id.to_string())],
+        ));
+    }
+
+    save(&queue);
+    Ok(())
+}
+
+/// Takes the next job to run, or stops running jobs if there are none left.
+fn next(queue: &mut Queue) -> Option<Job> {
+    queue.running = queue.jobs.first().map(|x| x.id);
+    if queue.running.is_none() {
+        queue.active = false;
+    }
+
+    queue.jobs.first().cloned()
+}
+
+/// Runs queued jobs one after another, until none are left (including any queued in the
+/// meantime). Jobs which fail are dropped, and stop the rest from being run.
+pub fn run(
+    framework: &RwLock<InstallerFramework>,
+    messages: &Sender<InstallMessage>,
+) -> Result<(), String> {
+    let mut job = {
+        let mut queue = QUEUE.lock().log_expect("Job queue has been dirtied");
+        if queue.active {
+            return Err(strings::tr("errors.queue_running"));
+        }
+
+        queue.active = true;
+        next(&mut queue)
+    };
+
+    while let Some(current) = job {
+        info!("Running queued job {:?}", current);
+
+        let result = framework
+            .write()
+            .log_expect("InstallerFramework has been dirtied")
+            .run_job(&current, messages);
+
+        let mut queue = QUEUE.lock().log_expect("Job queue has been dirtied");
+        queue.jobs.retain(|x| x.id != current.id);
+        save(&queue);
+
+        if let Err(v) = result {
+            queue.running = None;
+            queue.active = false;
+            return Err(v);
+        }
+
+        job = next(&mut queue);
+    }
+
+    Ok(())
+}
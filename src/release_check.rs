@@ -0,0 +1,110 @@
+//! release_check.rs
+//!
+//! Checks what each package would resolve to for a given release, so that publishers can
+//! catch assets which have been renamed (or regexes which are too strict) before users
+//! fail to install that release.
+
+use config::Config;
+
+use source_auth;
+
+use sources::types::Version;
+
+use tasks::resolver::{complete_file, AssetSelector};
+
+/// Resolves every package against the specified release version, printing which asset
+/// each would install. Returns a description of each package which wouldn't resolve.
+pub fn check_release(config: &Config, version: &str) -> Vec<String> {
+    let version = match Version::parse(version) {
+        Some(v) => v,
+        None => return vec![format!("{:?} is not a valid version", version)],
+    };
+
+    let mut problems = Vec::new();
+
+    for package in &config.packages {
+        let selector = match AssetSelector::new(&package.source) {
+            Ok(v) => v,
+            Err(v) => {
+                problems.push(format!("{}: {}", package.name, v));
+                continue;
+            }
+        };
+
+        if let Err(v) = source_auth::authorize_silently(&package.name, &package.source, None) {
+            problems.push(format!("{}: {}", package.name, v));
+            continue;
+        }
+
+        let releases = match package.source.get_current_releases(&config.url_rewrites) {
+            Ok(v) => v,
+            Err(v) => {
+                problems.push(format!(
+                    "{}: unable to list releases from {}: {}",
+                    package.name, package.source.name, v
+                ));
+                continue;
+            }
+        };
+
+        let release = match releases.iter().find(|x| x.version == version) {
+            Some(v) => v,
+            None => {
+                problems.push(format!(
+                    "{}: {} has no release {}",
+                    package.name, package.source.name, version
+                ));
+                continue;
+            }
+        };
+
+        let file = match selector.select(release) {
+            Some(v) => v,
+            None => {
+                let names: Vec<&str> = release.files.iter().map(|x| x.name.as_str()).collect();
+                problems.push(format!(
+                    "{}: no asset of release {} matches {:?} (assets are {:?})",
+                    package.name, version, package.source.match_regex, names
+                ));
+                continue;
+            }
+        };
+
+        let file = match complete_file(file, release, package) {
+            Ok(v) => v,
+            Err(v) => {
+                problems.push(format!("{}: {}: {}", package.name, file.name, v));
+                continue;
+            }
+        };
+
+        println!("{}: {} ({})", package.name, file.name, file.url);
+        for part in &file.parts {
+            println!("    part: {}", part);
+        }
+        if let Some(ref url) = file.chunk_index {
+            println!("    chunk index: {}", url);
+        }
+        if let Some(ref url) = file.checksums {
+            println!("    checksums: {}", url);
+        }
+        if let Some(ref url) = file.signature {
+            println!("    signature: {}", url);
+        }
+
+        // Users are only offered the latest release with a matching asset
+        let newer = releases
+            .iter()
+            .filter(|x| x.version > version && selector.select(x).is_some())
+            .map(|x| &x.version)
+            .max();
+        if let Some(newer) = newer {
+            println!(
+                "    note: {} is newer, and would be installed instead",
+                newer
+            );
+        }
+    }
+
+    problems
+}
@@ -0,0 +1,180 @@
+//! report.rs
+//!
+//! Summarises the outcome of an install (what was installed where, how long it took and
+//! anything which went wrong along the way), written into the install directory as both
+//! text and JSON so that support staff and other tooling don't need to read the logs.
+
+use std::fs::{read_to_string, remove_file, write};
+use std::path::Path;
+
+use serde_json;
+
+use installer::InstallationDatabase;
+
+use events::Event;
+
+/// The machine readable version of the report.
+pub const REPORT_JSON_FILE: &str = "install_report.json";
+/// The human readable version of the report.
+pub const REPORT_TEXT_FILE: &str = "install_report.txt";
+
+/// A package which is installed after the operation.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReportedPackage {
+    pub name: String,
+    pub version: String,
+    /// Absolute path that the package's files are relative to
+    pub root: String,
+    pub files: usize,
+    pub shortcuts: Vec<String>,
+}
+
+/// The outcome of a single install or modification.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InstallReport {
+    pub product: String,
+    pub installer_version: String,
+    pub install_path: String,
+    /// When the operation started, as an RFC 3339 timestamp
+    pub started: String,
+    pub duration_secs: u64,
+    pub success: bool,
+    #[serde(default)]
+    pub error: Option<String>,
+    pub packages: Vec<ReportedPackage>,
+    /// Packages which were removed by the operation
+    #[serde(default)]
+    pub removed: Vec<String>,
+    /// Warnings and errors logged during the operation
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+impl InstallReport {
+    /// Builds a report from the installation database once an operation has finished.
+    pub fn new(
+        product: &str,
+        install_path: &Path,
+        started: String,
+        duration_secs: u64,
+        result: &Result<(), String>,
+        database: &InstallationDatabase,
+    ) -> InstallReport {
+        let packages = database
+            .packages
+            .iter()
+            .map(|x| ReportedPackage {
+                name: x.name.clone(),
+                version: x.version.to_string(),
+                root: x.get_root(install_path).to_string_lossy().to_string(),
+                files: x.files.len(),
+                shortcuts: x.shortcuts.clone(),
+            })
+            .collect();
+
+        InstallReport {
+            product: product.to_string(),
+            installer_version: env!("CARGO_PKG_VERSION").to_string(),
+            install_path: install_path.to_string_lossy().to_string(),
+            started,
+            duration_secs,
+            success: result.is_ok(),
+            error: result.clone().err(),
+            packages,
+            removed: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Collects any warnings or errors from events received during the operation.
+    pub fn add_warnings<I: Iterator<Item = Event>>(&mut self, events: I) {
+        for event in events {
+            if let Event::Log { level, message, .. } = event {
+                if level == "WARN" || level == "ERROR" {
+                    self.warnings.push(message);
+                }
+            }
+        }
+    }
+
+    /// Renders the report for people to read.
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{} install report\n\n", self.product);
+
+        text.push_str(&format!("Installer version: {}\n", self.installer_version));
+        text.push_str(&format!("Location:          {}\n", self.install_path));
+        text.push_str(&format!("Started:           {}\n", self.started));
+        text.push_str(&format!("Duration:          {}s\n", self.duration_secs));
+        text.push_str(&match self.error {
+            Some(ref v) => format!("Result:            Failed ({})\n", v),
+            None => "Result:            Succeeded\n".to_string(),
+        });
+
+        text.push_str("\nInstalled packages:\n");
+        if self.packages.is_empty() {
+            text.push_str("  (none)\n");
+        }
+        for package in &self.packages {
+            text.push_str(&format!(
+                "  {} {} ({} files) in {}\n",
+                package.name, package.version, package.files, package.root
+            ));
+
+            for shortcut in package.shortcuts.iter().filter(|x| !x.is_empty()) {
+                text.push_str(&format!("    Shortcut: {}\n", shortcut));
+            }
+        }
+
+        if !self.removed.is_empty() {
+            text.push_str("\nRemoved packages:\n");
+            for name in &self.removed {
+                text.push_str(&format!("  {}\n", name));
+            }
+        }
+
+        text.push_str(&format!("\nWarnings ({}):\n", self.warnings.len()));
+        for warning in &self.warnings {
+            text.push_str(&format!("  {}\n", warning));
+        }
+
+        text
+    }
+
+    /// Writes both versions of the report into the given directory.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|x| format!("Failed to serialise install report: {:?}", x))?;
+
+        write(path.join(REPORT_JSON_FILE), json)
+            .map_err(|x| format!("Failed to write install report: {:?}", x))?;
+        write(path.join(REPORT_TEXT_FILE), self.to_text())
+            .map_err(|x| format!("Failed to write install report: {:?}", x))
+    }
+
+    /// Reads the report of the last operation within the given directory, if any.
+    pub fn load(path: &Path) -> Result<Option<InstallReport>, String> {
+        let path = path.join(REPORT_JSON_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let text =
+            read_to_string(&path).map_err(|x| format!("Failed to read {:?}: {:?}", path, x))?;
+
+        serde_json::from_str(&text)
+            .map(Some)
+            .map_err(|x| format!("Failed to parse {:?}: {:?}", path, x))
+    }
+}
+
+/// Removes any reports from the given directory.
+pub fn remove(path: &Path) -> Result<(), String> {
+    for file in &[REPORT_JSON_FILE, REPORT_TEXT_FILE] {
+        let file = path.join(file);
+        if file.exists() {
+            remove_file(&file).map_err(|x| format!("Failed to delete install report: {:?}", x))?;
+        }
+    }
+
+    Ok(())
+}
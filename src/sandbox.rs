@@ -0,0 +1,355 @@
+//! sandbox.rs
+//!
+//! Runs external commands on behalf of the installer with a scrubbed environment, a pinned
+//! working directory, a timeout and a cap on how much of their output is kept, so that a
+//! misbehaving command can't hang the install or flood the log.
+//!
+//! Commands run with `run_with_dialogs` can ask the user questions, by printing a line
+//! starting with `::liftinstall-dialog::` followed by a dialog (see `prompts::Dialog`) as
+//! JSON, e.g. `::liftinstall-dialog::{"id": "port", "kind": "text", "question": "Port
+//! 8080 is in use. Which port should be used instead?", "default": "8081"}`. The answer
+//! is written to their stdin as a line. Waiting for an answer doesn't count towards the
+//! timeout. Everything else is run with an empty stdin, so commands which read from it
+//! don't wait for input which never comes.
+//!
+//! Commands which time out or are cancelled are killed along with everything they have
+//! started (see `native::ProcessTree`). Anything a command leaves running once it has exited
+//! may still hold onto its output, so that is only read for a little while longer.
+
+use std::env;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use config::ResourceLimits;
+
+use control;
+
+use events::{self, Event};
+
+use heartbeat;
+
+use logging::LoggingErrors;
+
+use native::{self, ProcessTree};
+
+use prompts::{self, Dialog};
+
+use tasks::TaskMessage;
+
+/// Environment variables passed through to commands. Everything else is removed.
+const KEPT_VARIABLES: &[&str] = &[
+    "PATH",
+    "HOME",
+    "USER",
+    "LOGNAME",
+    "LANG",
+    "LC_ALL",
+    "TMPDIR",
+    "TEMP",
+    "TMP",
+    "SystemRoot",
+    "SystemDrive",
+    "windir",
+    "ComSpec",
+    "PATHEXT",
+    "USERPROFILE",
+    "APPDATA",
+    "LOCALAPPDATA",
+    "ProgramData",
+    "ProgramFiles",
+    "ProgramFiles(x86)",
+];
+
+/// How often a running command is checked for having exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long output is still read for once a command has exited.
+const OUTPUT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Starts lines of output which ask a dialog.
+const DIALOG_PREFIX: &str = "::liftinstall-dialog::";
+
+/// What a command printed, and how it exited.
+pub struct CommandOutput {
+    pub status: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Output being read from a stream on another thread (see `capture`).
+struct Captured {
+    kept: Arc<Mutex<Vec<u8>>>,
+    /// Disconnects once the stream has closed
+    done: Receiver<()>,
+}
+
+impl Captured {
+    /// Waits for the stream to close, returning what was kept of it. Streams held open by
+    /// something else are given up on after `OUTPUT_TIMEOUT`, keeping what was read.
+    fn finish(self, program: &str) -> String {
+        if let Err(RecvTimeoutError::Timeout) = self.done.recv_timeout(OUTPUT_TIMEOUT) {
+            warn!(
+                "Output of {} is still open after it exited, so is no longer being read",
+                program
+            );
+        }
+
+        let kept = self.kept.lock().log_expect("Output has been dirtied");
+        String::from_utf8_lossy(&kept).to_string()
+    }
+}
+
+/// Logs and publishes a line of output as it arrives. Output shows the command is still
+/// getting somewhere.
+fn print_line(program: &str, line: &[u8], error: bool) {
+    heartbeat::beat();
+
+    let line = String::from_utf8_lossy(line).trim_end().to_string();
+
+    if error {
+        warn!("[{}] {}", program, line);
+    } else {
+        info!("[{}] {}", program, line);
+    }
+
+    events::publish(Event::CommandOutput {
+        program: program.to_string(),
+        line,
+        error,
+    });
+}
+
+/// Reads a stream until it closes on another thread, printing each line and keeping at most
+/// `limit` bytes. The remainder is still drained, as to not block the command on a full pipe.
+fn capture<R: Read + Send + 'static>(
+    program: &str,
+    mut stream: R,
+    limit: usize,
+    error: bool,
+    dialogs: Option<Sender<Dialog>>,
+) -> Captured {
+    let program = program.to_string();
+    let kept = Arc::new(Mutex::new(Vec::new()));
+    let (done_sender, done) = channel();
+
+    let shared = kept.clone();
+    thread::spawn(move || {
+        let _done = done_sender;
+        let mut truncated = false;
+        let mut line = Vec::new();
+        let mut buf = [0 as u8; 8192];
+
+        loop {
+            let read = match stream.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(v) => v,
+            };
+
+            if !truncated {
+                for byte in &buf[..read] {
+                    if *byte == b'\n' {
+                        handle_line(&program, &line, error, &dialogs);
+                        line.clear();
+                    } else {
+                        line.push(*byte);
+                    }
+                }
+            }
+
+            let mut kept = shared.lock().log_expect("Output has been dirtied");
+            let room = limit.saturating_sub(kept.len());
+            kept.extend_from_slice(&buf[..read.min(room)]);
+
+            if read > room && !truncated {
+                truncated = true;
+                warn!("Output of {} was truncated to {} bytes", program, limit);
+            }
+        }
+
+        if !line.is_empty() && !truncated {
+            handle_line(&program, &line, error, &dialogs);
+        }
+    });
+
+    Captured { kept, done }
+}
+
+/// Kills a command along with everything it has started, if they are being tracked.
+fn kill(program: &str, child: &mut Child, tree: &Option<ProcessTree>) {
+    if let Some(ref tree) = *tree {
+        if let Err(v) = tree.kill() {
+            warn!("Unable to kill what {} started: {}", program, v);
+        }
+    }
+
+    if let Err(v) = child.kill() {
+        warn!("Unable to kill {}: {:?}", program, v);
+    }
+    let _ = child.wait();
+}
+
+/// Handles a line of output, which may be asking a dialog if `dialogs` is given.
+fn handle_line(program: &str, line: &[u8], error: bool, dialogs: &Option<Sender<Dialog>>) {
+    if let Some(ref dialogs) = *dialogs {
+        let text = String::from_utf8_lossy(line);
+
+        if text.starts_with(DIALOG_PREFIX) {
+            match serde_json::from_str::<Dialog>(&text[DIALOG_PREFIX.len()..]) {
+                Ok(dialog) => {
+                    info!("{} is asking dialog {:?}", program, dialog.id);
+                    heartbeat::beat();
+
+                    if let Err(v) = dialogs.send(dialog) {
+                        warn!("Unable to ask dialog for {}: {:?}", program, v);
+                    }
+                    return;
+                }
+                Err(v) => warn!("{} asked an invalid dialog: {:?}", program, v),
+            }
+        }
+    }
+
+    print_line(program, line, error);
+}
+
+/// Runs a command to completion within the configured limits.
+pub fn run(
+    program: &str,
+    args: &[&str],
+    working_dir: &Path,
+    limits: &ResourceLimits,
+) -> Result<CommandOutput, String> {
+    run_command(program, args, working_dir, limits, None)
+}
+
+/// Runs a command to completion within the configured limits, letting it ask the user
+/// questions through `messenger`.
+pub fn run_with_dialogs(
+    program: &str,
+    args: &[&str],
+    working_dir: &Path,
+    limits: &ResourceLimits,
+    messenger: &dyn Fn(&TaskMessage),
+) -> Result<CommandOutput, String> {
+    run_command(program, args, working_dir, limits, Some(messenger))
+}
+
+fn run_command(
+    program: &str,
+    args: &[&str],
+    working_dir: &Path,
+    limits: &ResourceLimits,
+    messenger: Option<&dyn Fn(&TaskMessage)>,
+) -> Result<CommandOutput, String> {
+    info!("Running {} {:?} in {:?}", program, args, working_dir);
+
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .current_dir(working_dir)
+        .env_clear()
+        .stdin(if messenger.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    for name in KEPT_VARIABLES {
+        if let Some(value) = env::var_os(name) {
+            command.env(name, value);
+        }
+    }
+
+    native::isolate_process_tree(&mut command);
+
+    let mut child = command
+        .spawn()
+        .map_err(|x| format!("Unable to launch {}: {:?}", program, x))?;
+
+    let tree = match native::track_process_tree(&child) {
+        Ok(v) => Some(v),
+        Err(v) => {
+            warn!("Unable to track what {} starts: {}", program, v);
+            None
+        }
+    };
+
+    // Dialogs are only read from stdout, as errors can't be answered
+    let (dialog_sender, dialogs) = channel();
+    let mut stdin = child.stdin.take();
+
+    let limit = limits.command_output_limit;
+    let stdout = capture(
+        program,
+        child.stdout.take().log_expect("stdout should be piped"),
+        limit,
+        false,
+        messenger.map(|_| dialog_sender),
+    );
+    let stderr = capture(
+        program,
+        child.stderr.take().log_expect("stderr should be piped"),
+        limit,
+        true,
+        None,
+    );
+
+    let mut timeout = Duration::from_secs(limits.command_timeout);
+    let start = Instant::now();
+    let status = loop {
+        if let (Some(messenger), Ok(dialog)) = (messenger, dialogs.try_recv()) {
+            let asked = Instant::now();
+            let answer = prompts::ask_dialog(messenger, &dialog);
+            timeout += asked.elapsed();
+
+            match answer {
+                Ok(answer) => {
+                    if let Some(ref mut stdin) = stdin {
+                        if let Err(v) = writeln!(stdin, "{}", answer) {
+                            warn!("Unable to answer dialog {:?}: {:?}", dialog.id, v);
+                        }
+                    }
+                }
+                Err(v) => {
+                    kill(program, &mut child, &tree);
+                    return Err(v);
+                }
+            }
+
+            continue;
+        }
+
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if control::is_cancelled() => {
+                info!("Stopping {}, as the operation was cancelled", program);
+                kill(program, &mut child, &tree);
+
+                return Err(control::CANCELLED.to_string());
+            }
+            Ok(None) if start.elapsed() >= timeout => {
+                kill(program, &mut child, &tree);
+
+                return Err(format!(
+                    "{} timed out after {} seconds",
+                    program, limits.command_timeout
+                ));
+            }
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(v) => return Err(format!("Unable to wait for {}: {:?}", program, v)),
+        }
+    };
+
+    Ok(CommandOutput {
+        status,
+        stdout: stdout.finish(program),
+        stderr: stderr.finish(program),
+    })
+}
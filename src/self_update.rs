@@ -2,14 +2,124 @@
 //!
 //! Handles different components of self-updating.
 
-use std::fs::{remove_file, File};
+use std::collections::HashMap;
+use std::fs::{remove_file, rename, File};
 use std::path::{Path, PathBuf};
 use std::process::{exit, Command};
+use std::sync::Mutex;
 use std::{thread, time};
 
 use clap::{App, ArgMatches};
 
+use serde_json;
+
+use config::{SelfUpdate, UrlRewrite};
+
+use sources::types::{File as ReleaseFile, Version};
+
+use tasks::resolver::AssetSelector;
+
+use headless;
 use logging::LoggingErrors;
+use verification;
+
+lazy_static! {
+    /// What checking each self-update source found, keyed by the source (as JSON)
+    static ref CHECKED: Mutex<HashMap<String, Option<(Version, ReleaseFile)>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Where a downloaded maintenance tool is kept until it is swapped in.
+pub fn staged_tool_path(dir: &Path) -> PathBuf {
+    dir.join(if cfg!(windows) {
+        "maintenancetool_new.exe"
+    } else {
+        "maintenancetool_new"
+    })
+}
+
+/// Where the maintenance tool being replaced is kept until the swap has succeeded.
+fn backup_tool_path(dir: &Path) -> PathBuf {
+    dir.join(if cfg!(windows) {
+        "maintenancetool_old.exe"
+    } else {
+        "maintenancetool_old"
+    })
+}
+
+/// Finds the newest build of the installer released after this one, if any.
+pub fn find_update(
+    update: &SelfUpdate,
+    rewrites: &[UrlRewrite],
+) -> Result<Option<(Version, ReleaseFile)>, String> {
+    let current = Version::parse(env!("CARGO_PKG_VERSION"))
+        .ok_or("The installer's own version couldn't be parsed")?;
+
+    let selector = AssetSelector::new(&update.source)?;
+    let mut releases = update.source.get_current_releases(rewrites)?;
+    releases.sort_by(|a, b| b.version.cmp(&a.version));
+
+    for release in releases.iter().filter(|x| x.version > current) {
+        let mut file = match selector.select(release) {
+            Some(v) => v.clone(),
+            None => {
+                debug!("Release {} has no matching installer", release.version);
+                continue;
+            }
+        };
+
+        if let Some(ref verification) = update.verification {
+            verification::find_files(&mut file, &release.files, verification)?;
+        }
+
+        return Ok(Some((release.version.clone(), file)));
+    }
+
+    Ok(None)
+}
+
+/// Finds the newest build of the installer released after this one, as `find_update`
+/// does, but only checks each source once a run. Configurations are loaded again as they
+/// change (and once for each product of a suite), which shouldn't each wait on the
+/// network. `authorize` is only called before actually checking. Checks which failed
+/// aren't tried again.
+pub fn find_update_once<F>(
+    update: &SelfUpdate,
+    rewrites: &[UrlRewrite],
+    authorize: F,
+) -> Option<(Version, ReleaseFile)>
+where
+    F: FnOnce(),
+{
+    let key = serde_json::to_string(update).unwrap_or_default();
+
+    let mut checked = CHECKED
+        .lock()
+        .log_expect("Self-update checks have been dirtied");
+    if let Some(found) = checked.get(&key) {
+        return found.clone();
+    }
+
+    authorize();
+
+    let found = match find_update(update, rewrites) {
+        Ok(Some((version, file))) => {
+            info!("Installer {} is available (from {:?})", version, file.url);
+            Some((version, file))
+        }
+        Ok(None) => {
+            info!("The installer is up to date");
+            None
+        }
+        Err(v) => {
+            warn!("Unable to check for installer updates: {}", v);
+            None
+        }
+    };
+
+    checked.insert(key, found.clone());
+    found
+}
 
 /// Swaps around the main executable if needed.
 pub fn perform_swap(current_exe: &PathBuf, to_path: Option<&str>) {
@@ -26,6 +136,23 @@ pub fn perform_swap(current_exe: &PathBuf, to_path: Option<&str>) {
             to_path.display()
         );
 
+        // The old binary can be renamed even while Windows still has it locked, and is
+        // kept around so that a failed copy doesn't leave no maintenance tool at all
+        let backup = to_path.parent().map(backup_tool_path);
+        if let Some(ref backup) = backup {
+            if backup.exists() {
+                if let Err(v) = remove_file(backup) {
+                    warn!("Unable to remove old backup {:?}: {:?}", backup, v);
+                }
+            }
+
+            if to_path.exists() {
+                if let Err(v) = rename(&to_path, backup) {
+                    warn!("Unable to back up {:?}: {:?}", to_path, v);
+                }
+            }
+        }
+
         // Attempt it a few times because Windows can hold a lock
         for i in 1..=5 {
             let swap_result = if cfg!(windows) {
@@ -33,8 +160,6 @@ pub fn perform_swap(current_exe: &PathBuf, to_path: Option<&str>) {
 
                 copy(&current_exe, &to_path).map(|_x| ())
             } else {
-                use std::fs::rename;
-
                 rename(&current_exe, &to_path)
             };
 
@@ -45,6 +170,15 @@ pub fn perform_swap(current_exe: &PathBuf, to_path: Option<&str>) {
                         info!("Copy attempt failed: {:?}, retrying in 3 seconds.", e);
                         thread::sleep(time::Duration::from_millis(3000));
                     } else {
+                        // Put the previous version back, so that it can try again later
+                        if let Some(ref backup) = backup {
+                            if backup.exists() {
+                                if let Err(v) = rename(backup, &to_path) {
+                                    error!("Unable to restore {:?}: {:?}", to_path, v);
+                                }
+                            }
+                        }
+
                         Err::<(), _>(e).log_expect("Copying new binary failed");
                     }
                 }
@@ -71,7 +205,7 @@ pub fn check_args<'a>(app: App<'a, '_>, current_path: &Path) -> Option<ArgMatche
             serde_json::from_reader(metadata_file).log_expect("Unable to read metadata file")
         };
 
-        let matches = app.get_matches_from(database);
+        let matches = app.get_matches_from(headless::normalize_args(database));
 
         info!("Parsed command line arguments from original instance");
         remove_file(args_file).log_expect("Unable to clean up args file");
@@ -83,9 +217,16 @@ pub fn check_args<'a>(app: App<'a, '_>, current_path: &Path) -> Option<ArgMatche
 }
 
 pub fn cleanup(current_path: &Path) {
+    let backup = backup_tool_path(current_path);
+    if backup.exists() {
+        if let Err(v) = remove_file(&backup) {
+            warn!("Unable to remove previous maintenance tool: {:?}", v);
+        }
+    }
+
     // Cleanup any remaining new maintenance tool instances if they exist
     if cfg!(windows) {
-        let updater_executable = current_path.join("maintenancetool_new.exe");
+        let updater_executable = staged_tool_path(current_path);
 
         if updater_executable.exists() {
             // Sleep a little bit to allow Windows to close the previous file handle
@@ -0,0 +1,266 @@
+//! signing.rs
+//!
+//! Publisher usage - signs built installers (which are also installed as the maintenance
+//! tool), and the MSIs wrapping them, using the platform's own tools. Unsigned downloads
+//! are blocked by SmartScreen and Gatekeeper for many users.
+//!
+//! Identities are described in a TOML file kept alongside the build, rather than in the
+//! bootstrap configuration, so that nothing about them ends up in the installer:
+//!
+//! ```toml
+//! [windows]
+//! # Either a certificate in the certificate store...
+//! certificate_thumbprint = "0123456789abcdef0123456789abcdef01234567"
+//! # ...or a .pfx file, with its password in an environment variable
+//! # certificate_file = "certificate.pfx"
+//! # password_env = "SIGNING_PASSWORD"
+//!
+//! [macos]
+//! identity = "Developer ID Application: Example Ltd (TEAMID)"
+//! # Notarizes with credentials saved by `xcrun notarytool store-credentials`
+//! notary_profile = "example-notary"
+//! ```
+//!
+//! On Windows, files are signed with `signtool` (from the Windows SDK) and timestamped.
+//! `signtool` can only be given the password of a .pfx file on its command line, where
+//! other processes on the machine can read it for as long as it runs. Build machines
+//! which are shared should import the certificate and sign by its thumbprint instead.
+//! On macOS, they are signed with `codesign` for the hardened runtime, then notarized with
+//! `notarytool` if a profile is given. Notarization tickets are stapled to app bundles and
+//! disk images, as bare executables can't hold one.
+//!
+//! MSIs carry the installer as it is, so the installer has to be signed (with `--sign`)
+//! before being wrapped with `--generate-msi`.
+
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::process::Command;
+
+/// Signs with a code signing certificate using `signtool`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct WindowsSigning {
+    /// Path to signtool.exe, if it isn't on the PATH
+    #[serde(default = "default_signtool")]
+    pub signtool: String,
+    /// SHA-1 thumbprint of a certificate in the certificate store
+    #[serde(default)]
+    pub certificate_thumbprint: Option<String>,
+    /// A .pfx file to sign with, instead of a certificate in the store
+    #[serde(default)]
+    pub certificate_file: Option<String>,
+    /// Environment variable containing the password of `certificate_file`. This is passed
+    /// on to signtool's command line, so can be seen in the process list while it runs.
+    #[serde(default)]
+    pub password_env: Option<String>,
+    /// RFC 3161 timestamp server, so that signatures outlive the certificate
+    #[serde(default = "default_timestamp_url")]
+    pub timestamp_url: String,
+    /// Shown as the name of the program when asking for administrator permission
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+fn default_signtool() -> String {
+    "signtool".to_string()
+}
+
+fn default_timestamp_url() -> String {
+    "http://timestamp.digicert.com".to_string()
+}
+
+/// Signs with a Developer ID using `codesign`, optionally notarizing with `notarytool`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct MacSigning {
+    /// Name (or hash) of the signing identity in the keychain
+    pub identity: String,
+    /// Entitlements to sign with, if any
+    #[serde(default)]
+    pub entitlements: Option<String>,
+    /// Keychain profile holding notarization credentials
+    #[serde(default)]
+    pub notary_profile: Option<String>,
+}
+
+/// Identities to sign with on each platform.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SigningConfig {
+    #[serde(default)]
+    pub windows: Option<WindowsSigning>,
+    #[serde(default)]
+    pub macos: Option<MacSigning>,
+}
+
+impl SigningConfig {
+    /// Reads signing settings from a TOML file.
+    pub fn load(path: &Path) -> Result<SigningConfig, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|x| format!("Unable to read signing settings {:?}: {:?}", path, x))?;
+
+        toml::from_str(&contents)
+            .map_err(|x| format!("Unable to parse signing settings {:?}: {}", path, x))
+    }
+}
+
+/// Runs a signing tool. Arguments aren't logged, as they may contain a password. They
+/// can still be seen by other processes while the tool runs.
+fn run_tool(program: &str, args: &[&str]) -> Result<(), String> {
+    info!("Running {}", program);
+
+    let output = Command::new(program).args(args).output().map_err(|x| {
+        if x.kind() == ErrorKind::NotFound {
+            format!(
+                "{} wasn't found - install it, or add it to the PATH",
+                program
+            )
+        } else {
+            format!("Unable to run {}: {:?}", program, x)
+        }
+    })?;
+
+    // signtool reports its errors on stdout
+    if !output.status.success() {
+        return Err(format!(
+            "{} failed ({}): {} {}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stdout).trim(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(())
+}
+
+fn sign_windows(settings: &WindowsSigning, path: &str) -> Result<(), String> {
+    let mut args = vec![
+        "sign",
+        "/fd",
+        "SHA256",
+        "/tr",
+        settings.timestamp_url.as_str(),
+        "/td",
+        "SHA256",
+    ];
+
+    let password;
+    match (
+        settings.certificate_thumbprint.as_ref(),
+        settings.certificate_file.as_ref(),
+    ) {
+        (Some(thumbprint), _) => args.extend_from_slice(&["/sha1", thumbprint.as_str()]),
+        (None, Some(file)) => {
+            args.extend_from_slice(&["/f", file.as_str()]);
+
+            if let Some(ref name) = settings.password_env {
+                password = env::var(name)
+                    .map_err(|_| format!("The signing password wasn't set in {}", name))?;
+                warn!(
+                    "The password of {} can be seen in the process list while signing",
+                    file
+                );
+                args.extend_from_slice(&["/p", password.as_str()]);
+            }
+        }
+        (None, None) => {
+            return Err(
+                "Either certificate_thumbprint or certificate_file has to be given to sign with"
+                    .to_string(),
+            )
+        }
+    }
+
+    if let Some(ref description) = settings.description {
+        args.extend_from_slice(&["/d", description.as_str()]);
+    }
+
+    args.push(path);
+    run_tool(&settings.signtool, &args)?;
+
+    run_tool(&settings.signtool, &["verify", "/pa", path])
+}
+
+fn sign_macos(settings: &MacSigning, path: &str) -> Result<(), String> {
+    let mut args = vec![
+        "--force",
+        "--timestamp",
+        "--options",
+        "runtime",
+        "--sign",
+        settings.identity.as_str(),
+    ];
+
+    if let Some(ref entitlements) = settings.entitlements {
+        args.extend_from_slice(&["--entitlements", entitlements.as_str()]);
+    }
+
+    args.push(path);
+    run_tool("codesign", &args)?;
+    run_tool("codesign", &["--verify", "--strict", path])?;
+
+    let profile = match settings.notary_profile {
+        Some(ref v) => v,
+        None => return Ok(()),
+    };
+
+    // Only archives and disk images can be submitted
+    let is_disk_image = path.ends_with(".dmg");
+    let archive = format!("{}.zip", path);
+    let submission = if is_disk_image {
+        path.to_string()
+    } else {
+        run_tool(
+            "ditto",
+            &["-c", "-k", "--keepParent", path, archive.as_str()],
+        )?;
+        archive.clone()
+    };
+
+    let result = run_tool(
+        "xcrun",
+        &[
+            "notarytool",
+            "submit",
+            submission.as_str(),
+            "--keychain-profile",
+            profile.as_str(),
+            "--wait",
+        ],
+    );
+
+    if !is_disk_image {
+        if let Err(v) = fs::remove_file(&archive) {
+            warn!("Unable to remove {:?}: {:?}", archive, v);
+        }
+    }
+    result?;
+
+    if is_disk_image || path.trim_end_matches('/').ends_with(".app") {
+        run_tool("xcrun", &["stapler", "staple", path])?;
+    }
+
+    Ok(())
+}
+
+/// Signs a file in place with the identity configured for this platform.
+pub fn sign(config: &SigningConfig, path: &Path) -> Result<(), String> {
+    let path_arg = path.to_string_lossy();
+    info!("Signing {:?}", path);
+
+    let result = if cfg!(windows) {
+        match config.windows {
+            Some(ref settings) => sign_windows(settings, &path_arg),
+            None => Err("No [windows] identity is configured to sign with".to_string()),
+        }
+    } else if cfg!(target_os = "macos") {
+        match config.macos {
+            Some(ref settings) => sign_macos(settings, &path_arg),
+            None => Err("No [macos] identity is configured to sign with".to_string()),
+        }
+    } else {
+        Err("Signing is only supported on Windows and macOS".to_string())
+    };
+
+    result.map_err(|x| format!("Unable to sign {:?}: {}", path, x))
+}
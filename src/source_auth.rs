@@ -0,0 +1,264 @@
+//! source_auth.rs
+//!
+//! Finds access tokens for private sources (from the environment, the configuration, an
+//! earlier session or the user) and hands them to the HTTP layer, which adds them to
+//! requests for wherever the source is hosted.
+
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+use std::sync::Mutex;
+
+use ring::aead::{self, Aad, Nonce, OpeningKey, SealingKey, CHACHA20_POLY1305};
+use ring::rand::{SecureRandom, SystemRandom};
+
+use config::PackageSource;
+
+use hashing::hmac_sha256;
+
+use http;
+use http::Credentials;
+
+use installer::InstallerFramework;
+
+use integrity;
+
+use logging;
+use logging::LoggingErrors;
+
+use prompts;
+
+use sources::get_by_name;
+
+use tasks::TaskMessage;
+
+lazy_static! {
+    /// Tokens already handed to the HTTP layer this session, by package.
+    static ref APPLIED: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+}
+
+/// Derives the key remembered tokens are encrypted with.
+fn token_key(install_path: &Path) -> Result<Vec<u8>, String> {
+    let key = integrity::get_or_create_key(install_path, integrity::TOKEN_KEY)?;
+    Ok(hmac_sha256(key.as_bytes(), b"liftinstall source tokens"))
+}
+
+/// Encrypts a token for keeping in metadata.json, as a nonce followed by the sealed
+/// token, encoded as base64.
+fn encrypt(install_path: &Path, token: &str) -> Result<String, String> {
+    let key = SealingKey::new(&CHACHA20_POLY1305, &token_key(install_path)?)
+        .map_err(|_| "Unable to create token key".to_string())?;
+
+    let mut nonce = [0 as u8; aead::NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| "Unable to generate a nonce".to_string())?;
+
+    let tag_len = CHACHA20_POLY1305.tag_len();
+    let mut data = token.as_bytes().to_vec();
+    data.extend(vec![0 as u8; tag_len]);
+
+    let length = aead::seal_in_place(
+        &key,
+        Nonce::assume_unique_for_key(nonce),
+        Aad::empty(),
+        &mut data,
+        tag_len,
+    )
+    .map_err(|_| "Unable to encrypt token".to_string())?;
+
+    let mut result = nonce.to_vec();
+    result.extend_from_slice(&data[..length]);
+
+    Ok(base64::encode(&result))
+}
+
+/// Reverses `encrypt`.
+fn decrypt(install_path: &Path, text: &str) -> Result<String, String> {
+    let key = OpeningKey::new(&CHACHA20_POLY1305, &token_key(install_path)?)
+        .map_err(|_| "Unable to create token key".to_string())?;
+
+    let mut data =
+        base64::decode(text).map_err(|x| format!("Invalid remembered token: {:?}", x))?;
+    if data.len() < aead::NONCE_LEN {
+        return Err("Remembered token is truncated".to_string());
+    }
+
+    let mut sealed = data.split_off(aead::NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(&data)
+        .map_err(|_| "Invalid remembered token nonce".to_string())?;
+
+    let token = aead::open_in_place(&key, nonce, Aad::empty(), 0, &mut sealed)
+        .map_err(|_| "Remembered token couldn't be decrypted".to_string())?;
+
+    String::from_utf8(token.to_vec()).map_err(|x| format!("Invalid remembered token: {:?}", x))
+}
+
+/// Looks for a token without asking the user. Tokens are only remembered within an
+/// installation.
+fn find_token(
+    name: &str,
+    source: &PackageSource,
+    context: Option<&InstallerFramework>,
+) -> Option<String> {
+    let auth = source.auth.as_ref()?;
+
+    if let Some(token) = APPLIED
+        .lock()
+        .log_expect("Token state has been dirtied")
+        .get(name)
+    {
+        return Some(token.clone());
+    }
+
+    if let Some(ref var) = auth.env {
+        match env::var(var) {
+            Ok(ref v) if !v.trim().is_empty() => return Some(v.trim().to_string()),
+            _ => info!("{} isn't set, looking elsewhere for a token", var),
+        }
+    }
+
+    if let Some(ref token) = auth.token {
+        return Some(token.clone());
+    }
+
+    let context = context?;
+    let remembered = context.database.source_tokens.get(name)?;
+    let install_path = context.install_path.as_ref()?;
+    match decrypt(install_path, remembered) {
+        Ok(v) => Some(v),
+        Err(v) => {
+            warn!("Ignoring the remembered token for {:?}: {}", name, v);
+            None
+        }
+    }
+}
+
+/// Hands a token to the HTTP layer, for the source's own hosts and any configured.
+fn apply(name: &str, source: &PackageSource, token: &str) -> Result<(), String> {
+    logging::register_secret(token);
+
+    let handler =
+        get_by_name(&source.name).ok_or_else(|| format!("Handler {} not found", source.name))?;
+    handler.authorize(&source.config, token)?;
+
+    for host in source.auth.iter().flat_map(|x| x.hosts.iter()) {
+        http::set_credentials(
+            host,
+            Credentials {
+                authorization: format!("Bearer {}", token),
+                accept: None,
+            },
+        );
+    }
+
+    APPLIED
+        .lock()
+        .log_expect("Token state has been dirtied")
+        .insert(name.to_string(), token.to_string());
+
+    Ok(())
+}
+
+/// Makes sure requests to a private source are authorised, asking the user for a token
+/// (if the configuration allows it, and there is a `messenger` to ask through) when
+/// there isn't one already. Public sources are left alone.
+///
+/// Without a token, sources are queried anonymously, which will usually fail with an
+/// explanation of what is missing.
+pub fn authorize(
+    name: &str,
+    source: &PackageSource,
+    context: &mut InstallerFramework,
+    messenger: Option<&dyn Fn(&TaskMessage)>,
+) -> Result<(), String> {
+    let auth = match source.auth {
+        Some(ref v) => v,
+        None => return Ok(()),
+    };
+
+    if let Some(token) = find_token(name, source, Some(context)) {
+        return apply(name, source, &token);
+    }
+
+    let messenger = match messenger {
+        Some(v) if auth.prompt => v,
+        _ => {
+            warn!("No access token is available for {:?}", name);
+            return Ok(());
+        }
+    };
+
+    let token = match prompts::ask_secret(
+        messenger,
+        &format!(
+            "{} is downloaded from a private source. Enter your access token:",
+            name
+        ),
+    ) {
+        Some(v) => v,
+        None => return Err(format!("An access token is needed to download {}", name)),
+    };
+
+    apply(name, source, &token)?;
+
+    if auth.remember {
+        match context.install_path.clone() {
+            Some(path) => {
+                let encrypted = encrypt(&path, &token)?;
+                context
+                    .database
+                    .source_tokens
+                    .insert(name.to_string(), encrypted);
+            }
+            None => warn!(
+                "Not remembering token for {:?}, as there is no install path",
+                name
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+/// Makes sure requests to a private source are authorised, without asking the user.
+pub fn authorize_silently(
+    name: &str,
+    source: &PackageSource,
+    context: Option<&InstallerFramework>,
+) -> Result<(), String> {
+    match find_token(name, source, context) {
+        Some(token) => apply(name, source, &token),
+        None => Ok(()),
+    }
+}
+
+/// Removes any tokens written into the configuration's sources, so that the text can be
+/// kept (e.g. as the cached configuration) without them.
+pub fn strip_tokens(text: &str) -> Result<String, String> {
+    fn strip(value: &mut toml::Value) {
+        match *value {
+            toml::Value::Table(ref mut table) => {
+                if let Some(&mut toml::Value::Table(ref mut auth)) = table.get_mut("auth") {
+                    auth.remove("token");
+                }
+
+                for value in table.values_mut() {
+                    strip(value);
+                }
+            }
+            toml::Value::Array(ref mut values) => {
+                for value in values.iter_mut() {
+                    strip(value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut value: toml::Value =
+        toml::from_str(text).map_err(|x| format!("Unable to parse configuration: {:?}", x))?;
+    strip(&mut value);
+
+    toml::to_string(&value).map_err(|x| format!("Unable to render configuration: {:?}", x))
+}
@@ -0,0 +1,132 @@
+//! azure.rs
+//!
+//! Contains a release source for Azure Blob Storage containers, either public or accessed
+//! using a SAS token.
+
+use regex::Regex;
+
+use url::Url;
+
+use sources::object_storage::{self, ObjectStore};
+use sources::types::*;
+
+use http::fetch_bytes;
+
+use logging;
+
+pub struct AzureReleases {}
+
+/// The configuration for this release.
+#[derive(Serialize, Deserialize)]
+struct AzureConfig {
+    /// The storage account name, as in `<account>.blob.core.windows.net`
+    account: String,
+    container: String,
+    /// Directory within the container holding version directories
+    prefix: Option<String>,
+    /// A SAS token granting read and list access, for private containers
+    sas_token: Option<String>,
+}
+
+impl AzureReleases {
+    pub fn new() -> Self {
+        AzureReleases {}
+    }
+}
+
+struct AzureContainer {
+    base_url: String,
+    sas_token: Option<String>,
+}
+
+impl AzureContainer {
+    /// Adds the SAS token (if any) to a URL.
+    fn sign(&self, url: &str) -> String {
+        match self.sas_token {
+            Some(ref token) => {
+                let separator = if url.contains('?') { '&' } else { '?' };
+                format!("{}{}{}", url, separator, token.trim_start_matches('?'))
+            }
+            None => url.to_string(),
+        }
+    }
+}
+
+impl ObjectStore for AzureContainer {
+    fn list(&self, prefix: &str) -> Result<Vec<String>, String> {
+        let blob_name = Regex::new(r"<Blob>\s*<Name>(.*?)</Name>")
+            .map_err(|x| format!("An error occurred while compiling regex: {:?}", x))?;
+        let next_marker = Regex::new(r"<NextMarker>(.*?)</NextMarker>")
+            .map_err(|x| format!("An error occurred while compiling regex: {:?}", x))?;
+
+        let mut names = Vec::new();
+        let mut marker: Option<String> = None;
+
+        loop {
+            let mut url = Url::parse(&self.base_url)
+                .map_err(|x| format!("Invalid container URL: {:?}", x))?;
+            url.query_pairs_mut()
+                .append_pair("restype", "container")
+                .append_pair("comp", "list")
+                .append_pair("prefix", prefix);
+            if let Some(ref marker) = marker {
+                url.query_pairs_mut().append_pair("marker", marker);
+            }
+
+            let body = fetch_bytes(&self.sign(url.as_str()), None, None)
+                .map_err(|x| format!("Failed to list container: {}", x))?;
+            let body = String::from_utf8(body)
+                .map_err(|x| format!("Container listing is not valid UTF-8: {:?}", x))?;
+
+            for captures in blob_name.captures_iter(&body) {
+                names.push(object_storage::unescape_xml(&captures[1]));
+            }
+
+            marker = next_marker
+                .captures(&body)
+                .map(|x| object_storage::unescape_xml(&x[1]))
+                .filter(|x| !x.is_empty());
+
+            if marker.is_none() {
+                break;
+            }
+        }
+
+        Ok(names)
+    }
+
+    fn object_url(&self, name: &str) -> String {
+        self.sign(&format!(
+            "{}/{}",
+            self.base_url,
+            object_storage::encode_name(name)
+        ))
+    }
+}
+
+impl ReleaseSource for AzureReleases {
+    fn get_current_releases(&self, config: &TomlValue) -> Result<Vec<Release>, String> {
+        let config: AzureConfig = match config.clone().try_into() {
+            Ok(v) => v,
+            Err(v) => return Err(format!("Failed to parse release config: {:?}", v)),
+        };
+
+        // Tokens end up in download URLs, which are logged
+        if let Some(ref token) = config.sas_token {
+            logging::register_secret(token);
+        }
+
+        let container = AzureContainer {
+            base_url: format!(
+                "https://{}.blob.core.windows.net/{}",
+                config.account, config.container
+            ),
+            sas_token: config.sas_token.clone(),
+        };
+
+        object_storage::find_releases(
+            &container,
+            &object_storage::normalize_prefix(&config.prefix),
+        )
+    }
+}
@@ -0,0 +1,43 @@
+//! bundle.rs
+//!
+//! Contains a release source for the assets within an offline bundle, which every package
+//! is switched over to while installing from one (see `bundle::apply`).
+
+use bundle;
+
+use sources::local::local_file;
+use sources::types::*;
+
+pub struct BundleReleases {}
+
+/// The configuration for this release.
+#[derive(Serialize, Deserialize)]
+struct BundleConfig {
+    /// Name of the package within the bundle
+    package: String,
+}
+
+impl BundleReleases {
+    pub fn new() -> Self {
+        BundleReleases {}
+    }
+}
+
+impl ReleaseSource for BundleReleases {
+    fn get_current_releases(&self, config: &TomlValue) -> Result<Vec<Release>, String> {
+        let config: BundleConfig = match config.clone().try_into() {
+            Ok(v) => v,
+            Err(v) => return Err(format!("Failed to parse release config: {:?}", v)),
+        };
+
+        let (version, paths) = bundle::find_package(&config.package)?;
+
+        Ok(vec![Release {
+            version,
+            files: paths
+                .iter()
+                .map(|x| local_file(x))
+                .collect::<Result<_, _>>()?,
+        }])
+    }
+}
@@ -0,0 +1,127 @@
+//! direct.rs
+//!
+//! Contains a release source for projects with their own update servers. The latest
+//! version is read from a small endpoint (as plain text, or a JSON document), and
+//! substituted into a templated download URL.
+
+use serde_json;
+
+use url::Url;
+
+use sources::types::*;
+
+use http::fetch_bytes;
+
+/// Placeholder in download URLs which is replaced with the latest version.
+const VERSION_PLACEHOLDER: &str = "{{version}}";
+
+pub struct DirectReleases {}
+
+/// The configuration for this release.
+#[derive(Serialize, Deserialize)]
+struct DirectConfig {
+    /// Responds with the latest version, e.g. `1.2.3`, or `{"version": "1.2.3"}`
+    version_url: String,
+    /// Where to download a version from, containing `{{version}}`
+    download_url: String,
+    /// The field of a JSON response containing the version, separated with dots if
+    /// nested. Defaults to `version`.
+    version_key: Option<String>,
+    /// The name of the downloaded file, which may also contain `{{version}}`. Defaults
+    /// to the last segment of the download URL.
+    file_name: Option<String>,
+}
+
+impl DirectReleases {
+    pub fn new() -> Self {
+        DirectReleases {}
+    }
+}
+
+/// Extracts the version from a version endpoint's response.
+pub fn find_version(body: &str, version_key: Option<&str>) -> Result<String, String> {
+    let body = body.trim();
+
+    let json: serde_json::Value = match serde_json::from_str(body) {
+        Ok(v) => v,
+        Err(_) if version_key.is_none() => return Ok(body.to_string()),
+        Err(v) => return Err(format!("Failed to parse version response: {:?}", v)),
+    };
+
+    // Plain numbers and strings are valid JSON too
+    if !json.is_object() && version_key.is_none() {
+        return Ok(body.trim_matches('"').to_string());
+    }
+
+    let key = version_key.unwrap_or("version");
+    let mut value = &json;
+    for part in key.split('.') {
+        value = value
+            .get(part)
+            .ok_or_else(|| format!("Version response has no {:?} field", key))?;
+    }
+
+    match *value {
+        serde_json::Value::String(ref v) => Ok(v.trim().to_string()),
+        serde_json::Value::Number(ref v) => Ok(v.to_string()),
+        _ => Err(format!("{:?} in version response isn't a version", key)),
+    }
+}
+
+impl ReleaseSource for DirectReleases {
+    fn get_current_releases(&self, config: &TomlValue) -> Result<Vec<Release>, String> {
+        let config: DirectConfig = match config.clone().try_into() {
+            Ok(v) => v,
+            Err(v) => return Err(format!("Failed to parse release config: {:?}", v)),
+        };
+
+        if !config.download_url.contains(VERSION_PLACEHOLDER) {
+            return Err(format!(
+                "Download URL {:?} doesn't contain {}",
+                config.download_url, VERSION_PLACEHOLDER
+            ));
+        }
+
+        let body = fetch_bytes(&config.version_url, None, None)
+            .map_err(|x| format!("Failed to fetch latest version: {}", x))?;
+        let body = String::from_utf8(body)
+            .map_err(|x| format!("Version response is not valid UTF-8: {:?}", x))?;
+
+        let raw_version = find_version(&body, config.version_key.as_ref().map(|x| x.as_str()))?;
+        let version = Version::parse(&raw_version)
+            .ok_or_else(|| format!("Invalid version from version endpoint: {:?}", raw_version))?;
+
+        let url = config
+            .download_url
+            .replace(VERSION_PLACEHOLDER, &raw_version);
+
+        let name = match config.file_name {
+            Some(ref v) => v.replace(VERSION_PLACEHOLDER, &raw_version),
+            None => Url::parse(&url)
+                .ok()
+                .and_then(|x| {
+                    x.path_segments()
+                        .and_then(|x| x.last().map(|x| x.to_string()))
+                })
+                .filter(|x| !x.is_empty())
+                .ok_or_else(|| format!("Unable to get a file name from {:?}", url))?,
+        };
+
+        Ok(vec![Release {
+            version,
+            files: vec![File {
+                name,
+                url,
+                requires_authorization: false,
+                parts: Vec::new(),
+                chunk_index: None,
+                checksums: None,
+                sha1: None,
+                signature: None,
+                size: None,
+                content_type: None,
+                torrent: None,
+            }],
+        }])
+    }
+}
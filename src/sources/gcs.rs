@@ -0,0 +1,104 @@
+//! gcs.rs
+//!
+//! Contains a release source for public Google Cloud Storage buckets.
+//!
+//! Signed URLs are only valid for a single object, so can't be used to list a bucket.
+
+use serde_json;
+
+use url::Url;
+
+use sources::object_storage::{self, ObjectStore};
+use sources::types::*;
+
+use http::fetch_bytes;
+
+pub struct GcsReleases {}
+
+/// The configuration for this release.
+#[derive(Serialize, Deserialize)]
+struct GcsConfig {
+    bucket: String,
+    /// Directory within the bucket holding version directories
+    prefix: Option<String>,
+}
+
+impl GcsReleases {
+    pub fn new() -> Self {
+        GcsReleases {}
+    }
+}
+
+struct GcsBucket {
+    bucket: String,
+}
+
+impl ObjectStore for GcsBucket {
+    fn list(&self, prefix: &str) -> Result<Vec<String>, String> {
+        let mut names = Vec::new();
+        let mut page_token: Option<String> = None;
+
+        loop {
+            let mut url = Url::parse(&format!(
+                "https://storage.googleapis.com/storage/v1/b/{}/o",
+                self.bucket
+            ))
+            .map_err(|x| format!("Invalid bucket name {:?}: {:?}", self.bucket, x))?;
+            url.query_pairs_mut()
+                .append_pair("prefix", prefix)
+                .append_pair("fields", "items(name),nextPageToken");
+            if let Some(ref token) = page_token {
+                url.query_pairs_mut().append_pair("pageToken", token);
+            }
+
+            let body = fetch_bytes(url.as_str(), None, None)
+                .map_err(|x| format!("Failed to list bucket: {}", x))?;
+            let result: serde_json::Value = serde_json::from_slice(&body)
+                .map_err(|x| format!("Failed to parse response: {:?}", x))?;
+
+            // Empty listings have no items at all
+            if let Some(items) = result["items"].as_array() {
+                for item in items {
+                    match item["name"].as_str() {
+                        Some(v) => names.push(v.to_string()),
+                        None => {
+                            return Err(
+                                "JSON payload missing information about object name".to_string()
+                            );
+                        }
+                    }
+                }
+            }
+
+            page_token = result["nextPageToken"].as_str().map(|x| x.to_string());
+            if page_token.is_none() {
+                break;
+            }
+        }
+
+        Ok(names)
+    }
+
+    fn object_url(&self, name: &str) -> String {
+        format!(
+            "https://storage.googleapis.com/{}/{}",
+            self.bucket,
+            object_storage::encode_name(name)
+        )
+    }
+}
+
+impl ReleaseSource for GcsReleases {
+    fn get_current_releases(&self, config: &TomlValue) -> Result<Vec<Release>, String> {
+        let config: GcsConfig = match config.clone().try_into() {
+            Ok(v) => v,
+            Err(v) => return Err(format!("Failed to parse release config: {:?}", v)),
+        };
+
+        let bucket = GcsBucket {
+            bucket: config.bucket.clone(),
+        };
+
+        object_storage::find_releases(&bucket, &object_storage::normalize_prefix(&config.prefix))
+    }
+}
@@ -9,7 +9,11 @@ use serde_json;
 
 use sources::types::*;
 
-use http::build_client;
+use http;
+use http::Credentials;
+
+/// Where the GitHub API is hosted.
+const API_URL: &str = "https://api.github.com/";
 
 pub struct GithubReleases {}
 
@@ -25,23 +29,34 @@ impl GithubReleases {
     }
 }
 
+/// Reparses our Config as strongly typed.
+fn parse_config(config: &TomlValue) -> Result<GithubConfig, String> {
+    match config.clone().try_into() {
+        Ok(v) => Ok(v),
+        Err(v) => Err(format!("Failed to parse release config: {:?}", v)),
+    }
+}
+
+/// Where assets of a repository are downloaded from through the API.
+fn assets_url(config: &GithubConfig) -> String {
+    format!("{}repos/{}/releases/assets/", API_URL, config.repo)
+}
+
 impl ReleaseSource for GithubReleases {
     fn get_current_releases(&self, config: &TomlValue) -> Result<Vec<Release>, String> {
-        // Reparse our Config as strongly typed
-        let config: GithubConfig = match config.clone().try_into() {
-            Ok(v) => v,
-            Err(v) => return Err(format!("Failed to parse release config: {:?}", v)),
-        };
+        let config = parse_config(config)?;
 
         let mut results: Vec<Release> = Vec::new();
 
-        // Build the HTTP client up
-        let client = build_client()?;
-        let mut response = client
-            .get(&format!(
-                "https://api.github.com/repos/{}/releases",
-                config.repo
-            ))
+        // Assets of private repositories can only be downloaded through the API
+        let url_field = if http::find_credentials(&assets_url(&config)).is_some() {
+            "url"
+        } else {
+            "browser_download_url"
+        };
+
+        let url = format!("{}repos/{}/releases", API_URL, config.repo);
+        let mut response = http::get(&url, None)?
             .header(USER_AGENT, "liftinstall (j-selby)")
             .send()
             .map_err(|x| format!("Error while sending HTTP request: {:?}", x))?;
@@ -55,6 +70,13 @@ impl ReleaseSource for GithubReleases {
                         .to_string(),
                 );
             }
+            StatusCode::NOT_FOUND => {
+                return Err(format!(
+                    "GitHub repository {:?} doesn't exist, or isn't public (private \
+                     repositories need an access token, see `source.auth`)",
+                    config.repo
+                ));
+            }
             _ => {
                 return Err(format!("Bad status code: {:?}.", response.status()));
             }
@@ -95,7 +117,7 @@ impl ReleaseSource for GithubReleases {
                     }
                 };
 
-                let url = match asset["browser_download_url"].as_str() {
+                let url = match asset[url_field].as_str() {
                     Some(v) => v,
                     None => {
                         return Err(
@@ -108,6 +130,14 @@ impl ReleaseSource for GithubReleases {
                     name: string.to_string(),
                     url: url.to_string(),
                     requires_authorization: false,
+                    parts: Vec::new(),
+                    chunk_index: None,
+                    checksums: None,
+                    sha1: None,
+                    signature: None,
+                    size: asset["size"].as_u64(),
+                    content_type: asset["content_type"].as_str().map(|x| x.to_string()),
+                    torrent: None,
                 });
             }
 
@@ -119,4 +149,27 @@ impl ReleaseSource for GithubReleases {
 
         Ok(results)
     }
+
+    fn authorize(&self, config: &TomlValue, token: &str) -> Result<(), String> {
+        let config = parse_config(config)?;
+
+        http::set_credentials(
+            API_URL,
+            Credentials {
+                authorization: format!("token {}", token),
+                accept: None,
+            },
+        );
+
+        // Asset URLs return metadata unless the file itself is asked for
+        http::set_credentials(
+            &assets_url(&config),
+            Credentials {
+                authorization: format!("token {}", token),
+                accept: Some("application/octet-stream".to_string()),
+            },
+        );
+
+        Ok(())
+    }
 }
@@ -0,0 +1,174 @@
+//! gitlab.rs
+//!
+//! Contains the GitLab API implementation of a release source, for both gitlab.com and
+//! self-hosted instances.
+
+use reqwest::header::USER_AGENT;
+use reqwest::StatusCode;
+
+use serde_json;
+
+use url::form_urlencoded::byte_serialize;
+
+use sources::types::*;
+
+use http;
+use http::Credentials;
+
+/// The instance used when no base URL is configured.
+const DEFAULT_BASE_URL: &str = "https://gitlab.com";
+
+pub struct GitlabReleases {}
+
+/// The configuration for this release.
+#[derive(Serialize, Deserialize)]
+struct GitlabConfig {
+    /// The project's path (e.g. `group/project`), or its numeric ID
+    project: String,
+    /// The instance hosting the project. Defaults to gitlab.com.
+    base_url: Option<String>,
+}
+
+impl GitlabReleases {
+    pub fn new() -> Self {
+        GitlabReleases {}
+    }
+}
+
+/// Parses the configuration of a release, returning it along with the instance's URL.
+fn parse_config(config: &TomlValue) -> Result<(GitlabConfig, String), String> {
+    let config: GitlabConfig = match config.clone().try_into() {
+        Ok(v) => v,
+        Err(v) => return Err(format!("Failed to parse release config: {:?}", v)),
+    };
+
+    let base_url = config
+        .base_url
+        .as_ref()
+        .map(|x| x.trim_end_matches('/'))
+        .unwrap_or(DEFAULT_BASE_URL)
+        .to_string();
+
+    Ok((config, base_url))
+}
+
+impl ReleaseSource for GitlabReleases {
+    fn get_current_releases(&self, config: &TomlValue) -> Result<Vec<Release>, String> {
+        let (config, base_url) = parse_config(config)?;
+
+        // Project paths are passed as a single, encoded segment
+        let project: String = byte_serialize(config.project.as_bytes()).collect();
+
+        let url = format!("{}/api/v4/projects/{}/releases", base_url, project);
+        let mut response = http::get(&url, None)?
+            .header(USER_AGENT, "liftinstall (j-selby)")
+            .send()
+            .map_err(|x| format!("Error while sending HTTP request: {:?}", x))?;
+
+        match response.status() {
+            StatusCode::OK => {}
+            StatusCode::NOT_FOUND => {
+                return Err(format!(
+                    "GitLab project {:?} doesn't exist, or isn't public (private projects need \
+                     an access token, see `source.auth`)",
+                    config.project
+                ));
+            }
+            StatusCode::TOO_MANY_REQUESTS => {
+                return Err(
+                    "GitLab is rate limiting you. Try moving to a internet connection \
+                     that isn't shared, and/or disabling VPNs."
+                        .to_string(),
+                );
+            }
+            _ => {
+                return Err(format!("Bad status code: {:?}.", response.status()));
+            }
+        }
+
+        let body = response
+            .text()
+            .map_err(|x| format!("Failed to decode HTTP response body: {:?}", x))?;
+
+        let result: serde_json::Value = serde_json::from_str(&body)
+            .map_err(|x| format!("Failed to parse response: {:?}", x))?;
+
+        let result: &Vec<serde_json::Value> = result
+            .as_array()
+            .ok_or_else(|| "Response was not an array!".to_string())?;
+
+        let mut results: Vec<Release> = Vec::new();
+
+        // Releases are listed newest first
+        for (i, entry) in result.iter().enumerate() {
+            let tag = match entry["tag_name"].as_str() {
+                Some(v) => v,
+                None => return Err("JSON payload missing information about tag".to_string()),
+            };
+
+            // Tags which aren't versions are ordered by their release date instead
+            let version = Version::parse(tag)
+                .unwrap_or_else(|| Version::new_number((result.len() - i) as u64));
+
+            let links = match entry["assets"]["links"].as_array() {
+                Some(v) => v,
+                None => return Err("JSON payload not an array".to_string()),
+            };
+
+            let mut files = Vec::new();
+
+            for link in links.iter() {
+                let name = match link["name"].as_str() {
+                    Some(v) => v,
+                    None => {
+                        return Err("JSON payload missing information about asset name".to_string());
+                    }
+                };
+
+                // Permanent links redirect to the latest location of the asset
+                let url = match link["direct_asset_url"]
+                    .as_str()
+                    .or_else(|| link["url"].as_str())
+                {
+                    Some(v) => v,
+                    None => {
+                        return Err("JSON payload missing information about asset URL".to_string());
+                    }
+                };
+
+                files.push(File {
+                    name: name.to_string(),
+                    url: url.to_string(),
+                    requires_authorization: false,
+                    parts: Vec::new(),
+                    chunk_index: None,
+                    checksums: None,
+                    sha1: None,
+                    signature: None,
+                    size: None,
+                    content_type: None,
+                    torrent: None,
+                });
+            }
+
+            results.push(Release { version, files });
+        }
+
+        Ok(results)
+    }
+
+    /// Personal, project and group access tokens are all accepted as bearer tokens.
+    fn authorize(&self, config: &TomlValue, token: &str) -> Result<(), String> {
+        let (_, base_url) = parse_config(config)?;
+
+        http::set_credentials(
+            &format!("{}/", base_url),
+            Credentials {
+                authorization: format!("Bearer {}", token),
+                accept: None,
+            },
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+//! itch.rs
+//!
+//! Contains the itch.io API implementation of a release source. Each upload of a game
+//! (such as a channel pushed using butler) is treated as its own release, versioned by
+//! the user version of its latest build.
+//!
+//! itch.io's API requires a key even for free games. This should be a key for the
+//! publisher's account, which only needs to be able to see the game's uploads.
+
+use reqwest::header::{AUTHORIZATION, USER_AGENT};
+use reqwest::StatusCode;
+
+use serde_json;
+
+use sources::types::*;
+
+use http::build_client;
+
+use logging;
+
+pub struct ItchReleases {}
+
+/// The configuration for this release.
+#[derive(Serialize, Deserialize)]
+struct ItchConfig {
+    game_id: u64,
+    api_key: String,
+    /// Only use uploads from this butler channel
+    channel: Option<String>,
+}
+
+impl ItchReleases {
+    pub fn new() -> Self {
+        ItchReleases {}
+    }
+}
+
+impl ReleaseSource for ItchReleases {
+    fn get_current_releases(&self, config: &TomlValue) -> Result<Vec<Release>, String> {
+        let config: ItchConfig = match config.clone().try_into() {
+            Ok(v) => v,
+            Err(v) => return Err(format!("Failed to parse release config: {:?}", v)),
+        };
+
+        // Keys end up in download URLs, which are logged
+        logging::register_secret(&config.api_key);
+
+        let client = build_client()?;
+        let mut response = client
+            .get(&format!(
+                "https://api.itch.io/games/{}/uploads",
+                config.game_id
+            ))
+            .header(USER_AGENT, "liftinstall (j-selby)")
+            .header(AUTHORIZATION, format!("Bearer {}", config.api_key))
+            .send()
+            .map_err(|x| format!("Error while sending HTTP request: {:?}", x))?;
+
+        match response.status() {
+            StatusCode::OK => {}
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                return Err("itch.io rejected the configured API key".to_string());
+            }
+            _ => {
+                return Err(format!("Bad status code: {:?}.", response.status()));
+            }
+        }
+
+        let body = response
+            .text()
+            .map_err(|x| format!("Failed to decode HTTP response body: {:?}", x))?;
+
+        let result: serde_json::Value = serde_json::from_str(&body)
+            .map_err(|x| format!("Failed to parse response: {:?}", x))?;
+
+        if let Some(errors) = result["errors"].as_array() {
+            return Err(format!("itch.io returned errors: {:?}", errors));
+        }
+
+        let uploads = match result["uploads"].as_array() {
+            Some(v) => v,
+            None => return Err("JSON payload missing list of uploads".to_string()),
+        };
+
+        let mut results = Vec::new();
+
+        for upload in uploads.iter() {
+            if let Some(ref channel) = config.channel {
+                if upload["channel_name"].as_str() != Some(channel.as_str()) {
+                    continue;
+                }
+            }
+
+            let id = match upload["id"].as_u64() {
+                Some(v) => v,
+                None => return Err("JSON payload missing information about upload ID".to_string()),
+            };
+
+            let name = match upload["filename"].as_str() {
+                Some(v) => v,
+                None => {
+                    return Err("JSON payload missing information about upload name".to_string());
+                }
+            };
+
+            // Uploads not pushed using butler don't have builds, so fall back to their ID
+            let version = upload["build"]["user_version"]
+                .as_str()
+                .and_then(Version::parse)
+                .or_else(|| upload["build_id"].as_u64().map(Version::new_number))
+                .unwrap_or_else(|| Version::new_number(id));
+
+            results.push(Release {
+                version,
+                files: vec![File {
+                    name: name.to_string(),
+                    url: format!(
+                        "https://api.itch.io/uploads/{}/download?api_key={}",
+                        id, config.api_key
+                    ),
+                    requires_authorization: false,
+                    parts: Vec::new(),
+                    chunk_index: None,
+                    checksums: None,
+                    sha1: None,
+                    signature: None,
+                    size: upload["size"].as_u64(),
+                    content_type: None,
+                    torrent: None,
+                }],
+            });
+        }
+
+        Ok(results)
+    }
+}
@@ -0,0 +1,148 @@
+//! local.rs
+//!
+//! Contains a release source for packages on the local file system, such as on a USB stick
+//! or network share. The version is read from a sidecar file next to the package.
+
+use std::fs::{read_dir, read_to_string};
+use std::path::{Path, PathBuf};
+
+use url::Url;
+
+use sources::types::*;
+
+/// Sidecar file within a directory source which holds its version.
+const DEFAULT_VERSION_FILE: &str = "version.txt";
+
+pub struct LocalReleases {}
+
+/// The configuration for this release.
+#[derive(Serialize, Deserialize)]
+struct LocalConfig {
+    /// A directory containing release files, or a single archive
+    path: String,
+    /// Overrides where the version is read from. Defaults to `version.txt` in a directory,
+    /// or `<archive>.version` next to a single archive.
+    version_file: Option<String>,
+}
+
+impl LocalReleases {
+    pub fn new() -> Self {
+        LocalReleases {}
+    }
+}
+
+/// Accepts both plain paths and `file://` URLs.
+fn to_path(path: &str) -> Result<PathBuf, String> {
+    if path.starts_with("file://") {
+        Url::parse(path)
+            .ok()
+            .and_then(|x| x.to_file_path().ok())
+            .ok_or_else(|| format!("Invalid file URL {:?}", path))
+    } else {
+        Ok(PathBuf::from(path))
+    }
+}
+
+fn to_url(path: &Path) -> Result<String, String> {
+    let path = path
+        .canonicalize()
+        .map_err(|x| format!("Unable to find {:?}: {:?}", path, x))?;
+
+    // Windows' verbatim paths aren't understood by URLs
+    let display = path.to_string_lossy().to_string();
+    let path = if display.starts_with(r"\\?\UNC\") {
+        PathBuf::from(format!(r"\\{}", &display[8..]))
+    } else if display.starts_with(r"\\?\") {
+        PathBuf::from(&display[4..])
+    } else {
+        path
+    };
+
+    Url::from_file_path(&path)
+        .map(|x| x.to_string())
+        .map_err(|_| format!("Unable to build a URL for {:?}", path))
+}
+
+/// Reads a version, as either semver or a plain number.
+fn read_version(path: &Path) -> Result<Version, String> {
+    let contents = read_to_string(path)
+        .map_err(|x| format!("Unable to read version from {:?}: {:?}", path, x))?;
+
+    Version::parse(&contents)
+        .ok_or_else(|| format!("{:?} doesn't contain a valid version: {:?}", path, contents))
+}
+
+/// Describes a file on the local file system, as found in a release.
+pub fn local_file(path: &Path) -> Result<File, String> {
+    let name = path
+        .file_name()
+        .and_then(|x| x.to_str())
+        .ok_or_else(|| format!("Unable to get file name of {:?}", path))?;
+
+    Ok(File {
+        name: name.to_string(),
+        url: to_url(path)?,
+        requires_authorization: false,
+        parts: Vec::new(),
+        chunk_index: None,
+        checksums: None,
+        sha1: None,
+        signature: None,
+        size: path.metadata().ok().map(|x| x.len()),
+        content_type: None,
+        torrent: None,
+    })
+}
+
+impl ReleaseSource for LocalReleases {
+    fn get_current_releases(&self, config: &TomlValue) -> Result<Vec<Release>, String> {
+        let config: LocalConfig = match config.clone().try_into() {
+            Ok(v) => v,
+            Err(v) => return Err(format!("Failed to parse release config: {:?}", v)),
+        };
+
+        let path = to_path(&config.path)?;
+
+        let (version_file, files) = if path.is_dir() {
+            let version_file = match config.version_file {
+                Some(ref v) => to_path(v)?,
+                None => path.join(DEFAULT_VERSION_FILE),
+            };
+
+            let entries =
+                read_dir(&path).map_err(|x| format!("Unable to read {:?}: {:?}", path, x))?;
+
+            let mut files = Vec::new();
+            for entry in entries {
+                let entry = entry
+                    .map_err(|x| format!("Unable to read {:?}: {:?}", path, x))?
+                    .path();
+
+                if entry.is_file() && entry != version_file {
+                    files.push(local_file(&entry)?);
+                }
+            }
+
+            (version_file, files)
+        } else if path.is_file() {
+            let version_file = match config.version_file {
+                Some(ref v) => to_path(v)?,
+                None => PathBuf::from(format!("{}.version", path.display())),
+            };
+
+            (version_file, vec![local_file(&path)?])
+        } else {
+            return Err(format!("Package source {:?} can't be found", path));
+        };
+
+        Ok(vec![Release {
+            version: read_version(&version_file)?,
+            files,
+        }])
+    }
+
+    /// Payloads may be rebuilt while iterating on them, and are cheap to list.
+    fn is_cacheable(&self) -> bool {
+        false
+    }
+}
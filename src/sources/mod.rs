@@ -4,17 +4,51 @@
 
 pub mod types;
 
+mod object_storage;
+
+pub mod azure;
+
+pub mod bundle;
+
+pub mod direct;
+
+pub mod gcs;
+
 pub mod github;
 
+pub mod gitlab;
+
+pub mod itch;
+
+pub mod local;
+
 pub mod patreon;
 
+pub mod resolver;
+
+pub mod sourceforge;
+
+pub mod squirrel;
+
+pub mod webseed;
+
 use self::types::ReleaseSource;
 
 /// Returns a ReleaseSource by a name, if possible
 pub fn get_by_name(name: &str) -> Option<Box<dyn ReleaseSource>> {
     match name {
+        "azure" => Some(Box::new(azure::AzureReleases::new())),
+        "bundle" => Some(Box::new(bundle::BundleReleases::new())),
+        "direct" => Some(Box::new(direct::DirectReleases::new())),
+        "gcs" => Some(Box::new(gcs::GcsReleases::new())),
         "github" => Some(Box::new(github::GithubReleases::new())),
+        "gitlab" => Some(Box::new(gitlab::GitlabReleases::new())),
+        "itch" => Some(Box::new(itch::ItchReleases::new())),
+        "local" => Some(Box::new(local::LocalReleases::new())),
         "patreon" => Some(Box::new(patreon::PatreonReleases::new())),
+        "sourceforge" => Some(Box::new(sourceforge::SourceforgeReleases::new())),
+        "squirrel" => Some(Box::new(squirrel::SquirrelReleases::new())),
+        "webseed" => Some(Box::new(webseed::WebseedReleases::new())),
         _ => None,
     }
 }
@@ -0,0 +1,99 @@
+//! object_storage.rs
+//!
+//! Shared support for release sources backed by cloud object storage. Releases are
+//! expected to be laid out as `<prefix>/<version>/<file>`, where the version directory
+//! is parsed as a version.
+
+use url::percent_encoding::{utf8_percent_encode, PATH_SEGMENT_ENCODE_SET};
+
+use sources::types::*;
+
+/// A bucket or container of objects.
+pub trait ObjectStore {
+    /// Lists the names of all objects starting with the prefix.
+    fn list(&self, prefix: &str) -> Result<Vec<String>, String>;
+
+    /// Returns the URL an object can be downloaded from.
+    fn object_url(&self, name: &str) -> String;
+}
+
+/// Normalises a configured prefix into the form used for listing, e.g. `releases/`.
+pub fn normalize_prefix(prefix: &Option<String>) -> String {
+    match *prefix {
+        Some(ref v) if !v.trim_matches('/').is_empty() => format!("{}/", v.trim_matches('/')),
+        _ => String::new(),
+    }
+}
+
+/// Encodes an object's name for use in a URL path, keeping its slashes.
+pub fn encode_name(name: &str) -> String {
+    name.split('/')
+        .map(|x| utf8_percent_encode(x, PATH_SEGMENT_ENCODE_SET).to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Undoes the escaping of text within XML listings.
+pub fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Finds releases within a store, grouping objects by their version directory.
+pub fn find_releases(store: &dyn ObjectStore, prefix: &str) -> Result<Vec<Release>, String> {
+    let mut results: Vec<Release> = Vec::new();
+
+    for name in store.list(prefix)? {
+        let relative = &name[prefix.len().min(name.len())..];
+
+        let mut segments = relative.splitn(2, '/');
+        let (directory, file_name) = match (segments.next(), segments.next()) {
+            (Some(directory), Some(file_name)) if !file_name.is_empty() => (directory, file_name),
+            _ => {
+                debug!("Ignoring {:?}, as it isn't in a version directory", name);
+                continue;
+            }
+        };
+
+        // Release files are expected directly within their version directory
+        if file_name.contains('/') {
+            debug!("Ignoring {:?}, as it is nested too deeply", name);
+            continue;
+        }
+
+        let version = match Version::parse(directory) {
+            Some(v) => v,
+            None => {
+                debug!("Ignoring {:?}, as {:?} isn't a version", name, directory);
+                continue;
+            }
+        };
+
+        let file = File {
+            name: file_name.to_string(),
+            url: store.object_url(&name),
+            requires_authorization: false,
+            parts: Vec::new(),
+            chunk_index: None,
+            checksums: None,
+            sha1: None,
+            signature: None,
+            size: None,
+            content_type: None,
+            torrent: None,
+        };
+
+        match results.iter_mut().find(|x| x.version == version) {
+            Some(release) => release.files.push(file),
+            None => results.push(Release {
+                version,
+                files: vec![file],
+            }),
+        }
+    }
+
+    Ok(results)
+}
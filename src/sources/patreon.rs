@@ -90,6 +90,14 @@ impl ReleaseSource for PatreonReleases {
                 name: string.to_string(),
                 url: url.to_string(),
                 requires_authorization: true,
+                parts: Vec::new(),
+                chunk_index: None,
+                checksums: None,
+                sha1: None,
+                signature: None,
+                size: None,
+                content_type: None,
+                torrent: None,
             });
         }
 
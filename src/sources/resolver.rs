@@ -0,0 +1,71 @@
+//! sources/resolver.rs
+//!
+//! Memoizes queries to release sources for the rest of the session, so that checking for
+//! updates, estimating sizes and installing don't each query the same service again.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use sources::types::{Release, ReleaseSource, TomlValue};
+
+use logging::LoggingErrors;
+
+/// Releases previously listed by sources.
+#[derive(Default)]
+pub struct Resolver {
+    releases: Mutex<HashMap<String, Vec<Release>>>,
+}
+
+lazy_static! {
+    pub static ref RESOLVER: Resolver = Resolver::new();
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Resolver::default()
+    }
+
+    /// Lists the releases of a source, using the results of an earlier query with the
+    /// same configuration if there was one. Failures aren't remembered, so that they
+    /// can be retried.
+    pub fn get_releases(
+        &self,
+        name: &str,
+        source: &dyn ReleaseSource,
+        config: &TomlValue,
+    ) -> Result<Vec<Release>, String> {
+        if !source.is_cacheable() {
+            return source.get_current_releases(config);
+        }
+
+        let key = format!("{}:{}", name, config);
+
+        if let Some(releases) = self
+            .releases
+            .lock()
+            .log_expect("Release cache has been dirtied")
+            .get(&key)
+        {
+            debug!("Using cached releases from {}", name);
+            return Ok(releases.clone());
+        }
+
+        // Queries can be slow, so other sources may be looked up in the meantime
+        let releases = source.get_current_releases(config)?;
+
+        self.releases
+            .lock()
+            .log_expect("Release cache has been dirtied")
+            .insert(key, releases.clone());
+
+        Ok(releases)
+    }
+
+    /// Forgets all releases listed so far.
+    pub fn clear(&self) {
+        self.releases
+            .lock()
+            .log_expect("Release cache has been dirtied")
+            .clear();
+    }
+}
@@ -0,0 +1,153 @@
+//! sourceforge.rs
+//!
+//! Contains a release source for projects hosting their files on SourceForge. Files are
+//! listed using the project's RSS feed, with releases expected to be in a directory
+//! named after their version (e.g. `/1.2.3/app-1.2.3.zip`).
+//!
+//! Downloads go through SourceForge's redirector, which picks the best mirror for the
+//! user, unless a specific mirror is configured.
+
+use regex::Regex;
+
+use reqwest::header::USER_AGENT;
+use reqwest::StatusCode;
+
+use url::percent_encoding::{utf8_percent_encode, PATH_SEGMENT_ENCODE_SET};
+use url::Url;
+
+use sources::types::*;
+
+use http::build_client;
+
+pub struct SourceforgeReleases {}
+
+/// The configuration for this release.
+#[derive(Serialize, Deserialize)]
+struct SourceforgeConfig {
+    /// The project's name, as in `sourceforge.net/projects/<project>`
+    project: String,
+    /// Only list files within this directory of the project
+    path: Option<String>,
+    /// Always download from this mirror (e.g. `netcologne`)
+    mirror: Option<String>,
+}
+
+impl SourceforgeReleases {
+    pub fn new() -> Self {
+        SourceforgeReleases {}
+    }
+}
+
+impl ReleaseSource for SourceforgeReleases {
+    fn get_current_releases(&self, config: &TomlValue) -> Result<Vec<Release>, String> {
+        let config: SourceforgeConfig = match config.clone().try_into() {
+            Ok(v) => v,
+            Err(v) => return Err(format!("Failed to parse release config: {:?}", v)),
+        };
+
+        let mut feed_url = Url::parse(&format!(
+            "https://sourceforge.net/projects/{}/rss",
+            config.project
+        ))
+        .map_err(|x| format!("Invalid project name {:?}: {:?}", config.project, x))?;
+
+        if let Some(ref path) = config.path {
+            feed_url
+                .query_pairs_mut()
+                .append_pair("path", &format!("/{}", path.trim_matches('/')));
+        }
+
+        let client = build_client()?;
+        let mut response = client
+            .get(feed_url)
+            .header(USER_AGENT, "liftinstall (j-selby)")
+            .send()
+            .map_err(|x| format!("Error while sending HTTP request: {:?}", x))?;
+
+        match response.status() {
+            StatusCode::OK => {}
+            StatusCode::NOT_FOUND => {
+                return Err(format!(
+                    "SourceForge project {:?} doesn't exist",
+                    config.project
+                ));
+            }
+            _ => {
+                return Err(format!("Bad status code: {:?}.", response.status()));
+            }
+        }
+
+        let body = response
+            .text()
+            .map_err(|x| format!("Failed to decode HTTP response body: {:?}", x))?;
+
+        let download_host = match config.mirror {
+            Some(ref mirror) => format!("https://{}.dl.sourceforge.net", mirror),
+            None => "https://downloads.sourceforge.net".to_string(),
+        };
+
+        // Each item's title is the path of a file within the project
+        let title = Regex::new(r"<title><!\[CDATA\[(.*?)\]\]></title>")
+            .map_err(|x| format!("An error occurred while compiling regex: {:?}", x))?;
+
+        let mut results: Vec<Release> = Vec::new();
+
+        for item in body.split("<item>").skip(1) {
+            let path = match title.captures(item) {
+                Some(v) => v[1].to_string(),
+                None => return Err("RSS feed item is missing a title".to_string()),
+            };
+
+            let segments: Vec<&str> = path.split('/').filter(|x| !x.is_empty()).collect();
+            if segments.len() < 2 {
+                debug!("Ignoring {:?}, as it isn't in a version directory", path);
+                continue;
+            }
+
+            let name = segments[segments.len() - 1];
+            let directory = segments[segments.len() - 2];
+
+            let version = match Version::parse(directory) {
+                Some(v) => v,
+                None => {
+                    debug!("Ignoring {:?}, as {:?} isn't a version", path, directory);
+                    continue;
+                }
+            };
+
+            let encoded: Vec<String> = segments
+                .iter()
+                .map(|x| utf8_percent_encode(x, PATH_SEGMENT_ENCODE_SET).to_string())
+                .collect();
+
+            let file = File {
+                name: name.to_string(),
+                url: format!(
+                    "{}/project/{}/{}",
+                    download_host,
+                    config.project,
+                    encoded.join("/")
+                ),
+                requires_authorization: false,
+                parts: Vec::new(),
+                chunk_index: None,
+                checksums: None,
+                sha1: None,
+                signature: None,
+                size: None,
+                content_type: None,
+                torrent: None,
+            };
+
+            match results.iter_mut().find(|x| x.version == version) {
+                Some(release) => release.files.push(file),
+                None => results.push(Release {
+                    version,
+                    files: vec![file],
+                }),
+            }
+        }
+
+        Ok(results)
+    }
+}
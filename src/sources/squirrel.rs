@@ -0,0 +1,212 @@
+//! squirrel.rs
+//!
+//! Contains a release source for applications published with Squirrel.Windows (as
+//! electron-builder's `squirrel` target does), so that they can keep their existing
+//! publishing pipeline while moving over. `url` is what was given to Squirrel's
+//! `UpdateManager`: the directory containing the `RELEASES` file and the packages it
+//! lists.
+//!
+//! Each line of `RELEASES` is a package's SHA-1 hash, file name (or URL) and size, e.g.
+//! `94689fede03fed7ab59c24337673a27837f0c3ec MyApp-1.0.0-full.nupkg 1004502`. Every full
+//! package is offered as a release, and checked against its hash once downloaded.
+//!
+//! Delta packages are skipped, so updates always download the full package. Applying
+//! them would mean reimplementing Squirrel's binary patching, which is out of scope.
+
+use std::collections::BTreeMap;
+
+use regex::Regex;
+
+use url::Url;
+
+use sources::types::*;
+
+use http::fetch_bytes;
+
+/// Name of the file listing a directory's packages.
+const RELEASES_FILE: &str = "RELEASES";
+
+pub struct SquirrelReleases {}
+
+/// The configuration for this release.
+#[derive(Serialize, Deserialize)]
+struct SquirrelConfig {
+    /// The directory containing `RELEASES`, e.g. `https://example.com/releases/win32/`
+    url: String,
+    /// The NuGet ID of the application, if packages of more than one are published to the
+    /// same directory
+    app_id: Option<String>,
+}
+
+impl SquirrelReleases {
+    pub fn new() -> Self {
+        SquirrelReleases {}
+    }
+}
+
+/// A package listed in `RELEASES`.
+struct SquirrelPackage {
+    sha1: Vec<u8>,
+    name: String,
+    url: String,
+    size: u64,
+    app_id: String,
+    version: String,
+    is_delta: bool,
+}
+
+/// Decodes a hash written as hex.
+fn parse_hex(value: &str) -> Option<Vec<u8>> {
+    if value.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..value.len())
+        .step_by(2)
+        .map(|x| u8::from_str_radix(value.get(x..x + 2)?, 16).ok())
+        .collect()
+}
+
+/// Reads the packages listed in `RELEASES`, with names relative to the directory.
+fn parse_releases(body: &str, base: &Url) -> Result<Vec<SquirrelPackage>, String> {
+    // e.g. `MyApp-1.2.3-full.nupkg` or `MyApp-1.2.3-beta.1-delta.nupkg`
+    let name_regex =
+        Regex::new(r"(?i)^(.+?)-([0-9]+(?:\.[0-9]+)*(?:-[0-9a-z.]+)?)-(full|delta)\.nupkg$")
+            .unwrap();
+
+    let mut packages = Vec::new();
+
+    // Windows tools write this with a byte order mark
+    for line in body.trim_start_matches('\u{feff}').lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() != 3 {
+            return Err(format!("Invalid line in {}: {:?}", RELEASES_FILE, line));
+        }
+
+        let sha1 = parse_hex(parts[0])
+            .filter(|x| x.len() == 20)
+            .ok_or_else(|| format!("Invalid SHA-1 hash in {}: {:?}", RELEASES_FILE, line))?;
+        let size = parts[2]
+            .parse()
+            .map_err(|x| format!("Invalid size in {}: {:?}: {:?}", RELEASES_FILE, line, x))?;
+
+        // Packages may be given by URL, when hosted elsewhere
+        let url = base
+            .join(parts[1])
+            .map_err(|x| format!("Invalid package in {}: {:?}: {:?}", RELEASES_FILE, line, x))?;
+        let name = url
+            .path_segments()
+            .and_then(|x| x.last())
+            .unwrap_or(parts[1])
+            .to_string();
+
+        let (app_id, version, is_delta) = match name_regex.captures(&name) {
+            Some(v) => (
+                v[1].to_string(),
+                v[2].to_string(),
+                v[3].eq_ignore_ascii_case("delta"),
+            ),
+            None => {
+                warn!(
+                    "Ignoring {:?} in {}, as it isn't named as a package",
+                    name, RELEASES_FILE
+                );
+                continue;
+            }
+        };
+
+        packages.push(SquirrelPackage {
+            sha1,
+            name,
+            url: url.to_string(),
+            size,
+            app_id,
+            version,
+            is_delta,
+        });
+    }
+
+    Ok(packages)
+}
+
+impl ReleaseSource for SquirrelReleases {
+    fn get_current_releases(&self, config: &TomlValue) -> Result<Vec<Release>, String> {
+        let config: SquirrelConfig = match config.clone().try_into() {
+            Ok(v) => v,
+            Err(v) => return Err(format!("Failed to parse release config: {:?}", v)),
+        };
+
+        // Package names are relative to the directory, so it needs a trailing slash
+        let base = if config.url.ends_with('/') {
+            config.url.clone()
+        } else {
+            format!("{}/", config.url)
+        };
+        let base = Url::parse(&base).map_err(|x| format!("Invalid URL {:?}: {:?}", base, x))?;
+        let releases_url = base
+            .join(RELEASES_FILE)
+            .map_err(|x| format!("Invalid URL {:?}: {:?}", base, x))?;
+
+        let body = fetch_bytes(releases_url.as_str(), None, None)
+            .map_err(|x| format!("Failed to fetch {}: {}", RELEASES_FILE, x))?;
+        let body = String::from_utf8(body)
+            .map_err(|x| format!("{} is not valid UTF-8: {:?}", RELEASES_FILE, x))?;
+
+        let mut releases: BTreeMap<Version, Vec<File>> = BTreeMap::new();
+
+        for package in parse_releases(&body, &base)? {
+            if let Some(ref app_id) = config.app_id {
+                if !package.app_id.eq_ignore_ascii_case(app_id) {
+                    continue;
+                }
+            }
+
+            if package.is_delta {
+                debug!("Skipping delta package {:?}", package.name);
+                continue;
+            }
+
+            let version = match Version::parse(&package.version) {
+                Some(v) => v,
+                None => {
+                    warn!("Ignoring {:?}, as its version is invalid", package.name);
+                    continue;
+                }
+            };
+
+            releases.entry(version).or_insert_with(Vec::new).push(File {
+                name: package.name,
+                url: package.url,
+                requires_authorization: false,
+                parts: Vec::new(),
+                chunk_index: None,
+                checksums: None,
+                sha1: Some(package.sha1),
+                signature: None,
+                size: Some(package.size),
+                content_type: None,
+                torrent: None,
+            });
+        }
+
+        if releases.is_empty() {
+            return Err(format!(
+                "{} at {:?} lists no full packages",
+                RELEASES_FILE,
+                releases_url.as_str()
+            ));
+        }
+
+        // Newest first
+        Ok(releases
+            .into_iter()
+            .rev()
+            .map(|(version, files)| Release { version, files })
+            .collect())
+    }
+}
@@ -3,6 +3,10 @@
 //! Contains traits that should be implemented by sources, as well as corresponding types.
 
 use std::cmp::Ordering;
+use std::fmt;
+use std::io::Read;
+
+use ring::digest;
 
 pub use semver::Version as SemverVersion;
 
@@ -38,6 +42,18 @@ impl Version {
     pub fn new_number(version: u64) -> Version {
         Version::Integer(version)
     }
+
+    /// Parses a version as published by a release server, as either semver (with an
+    /// optional leading `v`) or a plain number.
+    pub fn parse(version: &str) -> Option<Version> {
+        let version = version.trim();
+
+        if let Ok(v) = SemverVersion::parse(version.trim_start_matches('v')) {
+            return Some(Version::new_semver(v));
+        }
+
+        version.parse().ok().map(Version::new_number)
+    }
 }
 
 impl PartialOrd for Version {
@@ -61,18 +77,85 @@ impl Ord for Version {
     }
 }
 
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Version::Semver(ref version) => write!(f, "{}", version),
+            Version::Integer(ref version) => write!(f, "{}", version),
+        }
+    }
+}
+
 /// A individual file in a release.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct File {
     pub name: String,
     pub url: String,
     pub requires_authorization: bool,
+    /// URLs of any further parts of a multi-part archive, in order
+    pub parts: Vec<String>,
+    /// URL of a chunk index, if this release supports differential downloads
+    pub chunk_index: Option<String>,
+    /// URL of a checksum manifest covering this file, if it should be verified
+    pub checksums: Option<String>,
+    /// SHA-1 hash the source publishes for this file, which it is checked against once
+    /// downloaded
+    pub sha1: Option<Vec<u8>>,
+    /// URL of a minisign signature of the checksum manifest (or this file, if there is no
+    /// manifest)
+    pub signature: Option<String>,
+    /// Size in bytes, if the source reports it
+    pub size: Option<u64>,
+    /// MIME type, if the source reports it
+    pub content_type: Option<String>,
+    /// Pieces and further webseeds, if this file is published as a torrent
+    pub torrent: Option<TorrentFile>,
 }
 
 impl File {}
 
+/// The SHA-1 hashes of each piece of a torrent's file.
+#[derive(Debug, Clone)]
+pub struct PieceHashes {
+    pub piece_length: u64,
+    pub hashes: Vec<Vec<u8>>,
+}
+
+impl PieceHashes {
+    /// Returns how much of the start of a file matches its pieces, in whole pieces (or
+    /// all of it, if the last piece matches too). The file is read a piece at a time.
+    pub fn verified_length(&self, data: &mut dyn Read) -> Result<u64, String> {
+        let mut verified = 0;
+        let mut piece = Vec::with_capacity(self.piece_length as usize);
+
+        for hash in &self.hashes {
+            piece.clear();
+            Read::take(&mut *data, self.piece_length)
+                .read_to_end(&mut piece)
+                .map_err(|x| format!("Unable to read piece: {:?}", x))?;
+
+            if piece.is_empty() || digest::digest(&digest::SHA1, &piece).as_ref() != hash.as_slice()
+            {
+                break;
+            }
+
+            verified += piece.len() as u64;
+        }
+
+        Ok(verified)
+    }
+}
+
+/// Details of a file which is published as a torrent.
+#[derive(Debug, Clone)]
+pub struct TorrentFile {
+    pub pieces: PieceHashes,
+    /// Other webseeds serving the file, tried like mirrors
+    pub webseeds: Vec<String>,
+}
+
 /// A individual release of an application.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Release {
     pub version: Version,
     pub files: Vec<File>,
@@ -80,7 +163,18 @@ pub struct Release {
 
 /// A source of releases.
 pub trait ReleaseSource {
-    /// Gets a list of the available releases from this source. Should cache internally
-    /// if possible using a mutex.
+    /// Gets a list of the available releases from this source.
     fn get_current_releases(&self, config: &TomlValue) -> Result<Vec<Release>, String>;
+
+    /// If releases can be reused for the rest of the session, instead of listing them
+    /// each time they are needed.
+    fn is_cacheable(&self) -> bool {
+        true
+    }
+
+    /// Registers a token for a private source with the HTTP layer (see
+    /// `http::set_credentials`), for wherever this source queries and downloads from.
+    fn authorize(&self, _config: &TomlValue, _token: &str) -> Result<(), String> {
+        Ok(())
+    }
 }
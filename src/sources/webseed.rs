@@ -0,0 +1,307 @@
+//! webseed.rs
+//!
+//! Contains a release source for very large payloads published as torrents, which are
+//! downloaded from the torrent's HTTPS webseeds (BEP 19, `url-list`) rather than from
+//! peers. The latest version is read from a version endpoint (as with the `direct`
+//! source), and the torrent for it is fetched from a templated URL.
+//!
+//! Further webseeds can be given in the configuration. Webseeds are read from start to
+//! end, so pieces arrive in order, and each piece is checked against the torrent's hashes.
+//! Pieces which don't match are fetched again from the next webseed. Only single-file
+//! torrents are supported.
+//!
+//! There is no peer-to-peer client, so this doesn't take any load off the webseeds.
+
+use std::collections::BTreeMap;
+use std::str;
+
+use url::Url;
+
+use sources::direct::find_version;
+use sources::types::*;
+
+use http::{assert_ssl, fetch_bytes};
+
+/// Placeholder in torrent URLs which is replaced with the latest version.
+const VERSION_PLACEHOLDER: &str = "{{version}}";
+/// Length of each of the SHA-1 hashes in a torrent's `pieces`.
+const PIECE_HASH_LENGTH: usize = 20;
+
+pub struct WebseedReleases {}
+
+/// The configuration for this release.
+#[derive(Serialize, Deserialize)]
+struct WebseedConfig {
+    /// Responds with the latest version, e.g. `1.2.3`, or `{"version": "1.2.3"}`
+    version_url: String,
+    /// Where to download the torrent of a version from, containing `{{version}}`
+    torrent_url: String,
+    /// The field of a JSON response containing the version, separated with dots if
+    /// nested. Defaults to `version`.
+    version_key: Option<String>,
+    /// Webseeds to use as well as the torrent's own, which may contain `{{version}}`
+    #[serde(default)]
+    webseeds: Vec<String>,
+}
+
+impl WebseedReleases {
+    pub fn new() -> Self {
+        WebseedReleases {}
+    }
+}
+
+/// A bencoded value, as torrents are made of.
+enum Bencode {
+    Integer(i64),
+    Bytes(Vec<u8>),
+    List(Vec<Bencode>),
+    Dictionary(BTreeMap<Vec<u8>, Bencode>),
+}
+
+impl Bencode {
+    fn get(&self, key: &str) -> Option<&Bencode> {
+        match *self {
+            Bencode::Dictionary(ref v) => v.get(key.as_bytes()),
+            _ => None,
+        }
+    }
+
+    fn as_bytes(&self) -> Option<&[u8]> {
+        match *self {
+            Bencode::Bytes(ref v) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<String> {
+        self.as_bytes()
+            .and_then(|x| String::from_utf8(x.to_vec()).ok())
+    }
+
+    fn as_integer(&self) -> Option<i64> {
+        match *self {
+            Bencode::Integer(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+/// Reads up to (and past) a delimiter, returning what came before it.
+fn read_until<'a>(data: &'a [u8], pos: &mut usize, delimiter: u8) -> Result<&'a str, String> {
+    let end = data[*pos..]
+        .iter()
+        .position(|x| *x == delimiter)
+        .map(|x| x + *pos)
+        .ok_or("Torrent ended unexpectedly")?;
+
+    let value = str::from_utf8(&data[*pos..end])
+        .map_err(|_| format!("Invalid number in torrent at {}", *pos))?;
+    *pos = end + 1;
+
+    Ok(value)
+}
+
+/// Parses a bencoded value, starting at `pos`.
+fn parse(data: &[u8], pos: &mut usize) -> Result<Bencode, String> {
+    match data.get(*pos) {
+        Some(b'i') => {
+            *pos += 1;
+            let value = read_until(data, pos, b'e')?;
+            value
+                .parse()
+                .map(Bencode::Integer)
+                .map_err(|x| format!("Invalid integer {:?} in torrent: {:?}", value, x))
+        }
+        Some(b'l') => {
+            *pos += 1;
+            let mut list = Vec::new();
+            while data.get(*pos) != Some(&b'e') {
+                list.push(parse(data, pos)?);
+            }
+            *pos += 1;
+            Ok(Bencode::List(list))
+        }
+        Some(b'd') => {
+            *pos += 1;
+            let mut dictionary = BTreeMap::new();
+            while data.get(*pos) != Some(&b'e') {
+                let key = match parse(data, pos)? {
+                    Bencode::Bytes(v) => v,
+                    _ => {
+                        return Err("Torrent has a dictionary key which isn't a string".to_string())
+                    }
+                };
+                dictionary.insert(key, parse(data, pos)?);
+            }
+            *pos += 1;
+            Ok(Bencode::Dictionary(dictionary))
+        }
+        Some(x) if x.is_ascii_digit() => {
+            let length = read_until(data, pos, b':')?;
+            let length: usize = length
+                .parse()
+                .map_err(|x| format!("Invalid string length {:?} in torrent: {:?}", length, x))?;
+
+            let end = *pos + length;
+            if end > data.len() {
+                return Err("Torrent ended unexpectedly".to_string());
+            }
+
+            let value = data[*pos..end].to_vec();
+            *pos = end;
+            Ok(Bencode::Bytes(value))
+        }
+        Some(x) => Err(format!(
+            "Unexpected {:?} in torrent at {}",
+            *x as char, *pos
+        )),
+        None => Err("Torrent ended unexpectedly".to_string()),
+    }
+}
+
+/// Reads a torrent's file, returning it along with its webseeds.
+fn read_torrent(data: &[u8]) -> Result<(String, u64, PieceHashes, Vec<String>), String> {
+    let torrent = parse(data, &mut 0)?;
+    let info = torrent.get("info").ok_or("Torrent has no info")?;
+
+    if info.get("files").is_some() {
+        return Err("Torrents with more than one file aren't supported".to_string());
+    }
+
+    let name = info
+        .get("name")
+        .and_then(|x| x.as_str())
+        .ok_or("Torrent has no name")?;
+    let length = info
+        .get("length")
+        .and_then(|x| x.as_integer())
+        .filter(|x| *x >= 0)
+        .ok_or("Torrent has no length")? as u64;
+    let piece_length = info
+        .get("piece length")
+        .and_then(|x| x.as_integer())
+        .filter(|x| *x > 0)
+        .ok_or("Torrent has no piece length")? as u64;
+
+    let pieces = info
+        .get("pieces")
+        .and_then(|x| x.as_bytes())
+        .ok_or("Torrent has no pieces")?;
+    if pieces.len() % PIECE_HASH_LENGTH != 0 {
+        return Err("Torrent has truncated piece hashes".to_string());
+    }
+
+    let hashes: Vec<Vec<u8>> = pieces
+        .chunks(PIECE_HASH_LENGTH)
+        .map(|x| x.to_vec())
+        .collect();
+    if (hashes.len() as u64) * piece_length < length {
+        return Err("Torrent has fewer pieces than its length needs".to_string());
+    }
+
+    // `url-list` may be a single URL, or a list of them
+    let webseeds = match torrent.get("url-list") {
+        Some(Bencode::List(ref v)) => v.iter().filter_map(|x| x.as_str()).collect(),
+        Some(v) => v.as_str().into_iter().collect(),
+        None => Vec::new(),
+    };
+
+    Ok((
+        name,
+        length,
+        PieceHashes {
+            piece_length,
+            hashes,
+        },
+        webseeds,
+    ))
+}
+
+/// Finds where a webseed serves a single-file torrent's file. Webseeds ending with a
+/// slash serve it by name from within that directory.
+fn webseed_url(webseed: &str, name: &str) -> Result<String, String> {
+    let url = if webseed.ends_with('/') {
+        Url::parse(webseed)
+            .and_then(|x| x.join(name))
+            .map_err(|x| format!("Invalid webseed {:?}: {:?}", webseed, x))?
+            .to_string()
+    } else {
+        webseed.to_string()
+    };
+
+    assert_ssl(&url)?;
+    Ok(url)
+}
+
+impl ReleaseSource for WebseedReleases {
+    fn get_current_releases(&self, config: &TomlValue) -> Result<Vec<Release>, String> {
+        let config: WebseedConfig = match config.clone().try_into() {
+            Ok(v) => v,
+            Err(v) => return Err(format!("Failed to parse release config: {:?}", v)),
+        };
+
+        if !config.torrent_url.contains(VERSION_PLACEHOLDER) {
+            return Err(format!(
+                "Torrent URL {:?} doesn't contain {}",
+                config.torrent_url, VERSION_PLACEHOLDER
+            ));
+        }
+
+        let body = fetch_bytes(&config.version_url, None, None)
+            .map_err(|x| format!("Failed to fetch latest version: {}", x))?;
+        let body = String::from_utf8(body)
+            .map_err(|x| format!("Version response is not valid UTF-8: {:?}", x))?;
+
+        let raw_version = find_version(&body, config.version_key.as_ref().map(|x| x.as_str()))?;
+        let version = Version::parse(&raw_version)
+            .ok_or_else(|| format!("Invalid version from version endpoint: {:?}", raw_version))?;
+
+        let torrent_url = config
+            .torrent_url
+            .replace(VERSION_PLACEHOLDER, &raw_version);
+        let torrent = fetch_bytes(&torrent_url, None, None)
+            .map_err(|x| format!("Failed to fetch torrent {:?}: {}", torrent_url, x))?;
+
+        let (name, length, pieces, webseeds) = read_torrent(&torrent)
+            .map_err(|x| format!("Failed to read torrent {:?}: {}", torrent_url, x))?;
+
+        let mut urls = Vec::new();
+        for webseed in webseeds.into_iter().chain(
+            config
+                .webseeds
+                .iter()
+                .map(|x| x.replace(VERSION_PLACEHOLDER, &raw_version)),
+        ) {
+            match webseed_url(&webseed, &name) {
+                Ok(v) => urls.push(v),
+                Err(v) => warn!("Ignoring webseed of {:?}: {}", torrent_url, v),
+            }
+        }
+
+        if urls.is_empty() {
+            return Err(format!("Torrent {:?} has no usable webseeds", torrent_url));
+        }
+
+        let url = urls.remove(0);
+
+        Ok(vec![Release {
+            version,
+            files: vec![File {
+                name,
+                url,
+                requires_authorization: false,
+                parts: Vec::new(),
+                chunk_index: None,
+                checksums: None,
+                sha1: None,
+                signature: None,
+                size: Some(length),
+                content_type: None,
+                torrent: Some(TorrentFile {
+                    pieces,
+                    webseeds: urls,
+                }),
+            }],
+        }])
+    }
+}
@@ -0,0 +1,221 @@
+//! strings.rs
+//!
+//! User-visible text, looked up from the locale catalogs bundled from `src/locales`.
+//! The catalog is picked from the user's locale (or the `locale` attribute), and English
+//! is used for anything it doesn't translate. The same table is served to the frontend
+//! by /api/strings.
+//!
+//! Errors the user can do something about are translated. Those which only carry the
+//! details of what went wrong underneath (e.g. "Unable to create ...: ...") are meant for
+//! whoever is diagnosing them, and are shown as they are.
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use serde_json::Error as SerdeError;
+
+use toml::Value;
+
+use logging::LoggingErrors;
+
+use native;
+
+/// Catalogs which are bundled, by language tag.
+const CATALOGS: [(&str, &str); 2] = [
+    ("en", include_str!("locales/en.toml")),
+    ("de", include_str!("locales/de.toml")),
+];
+
+/// The catalog every other catalog falls back to.
+const FALLBACK: &str = "en";
+
+/// Identifies a native string.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NativeString {
+    /// Window title, with `{app_name}` substituted
+    WindowTitle,
+    /// Title of the folder picker for the install directory
+    SelectInstallDir,
+}
+
+impl NativeString {
+    /// Returns the key of the string within catalogs.
+    fn key(self) -> &'static str {
+        match self {
+            NativeString::WindowTitle => "native.window_title",
+            NativeString::SelectInstallDir => "native.select_install_dir",
+        }
+    }
+}
+
+/// The strings of the active locale.
+#[derive(Serialize)]
+struct StringTable {
+    locale: String,
+    strings: HashMap<String, String>,
+}
+
+lazy_static! {
+    static ref ACTIVE: RwLock<StringTable> = RwLock::new(load(FALLBACK));
+    /// The locale asked for, which may not have a catalog of its own
+    static ref REQUESTED: RwLock<Option<String>> = RwLock::new(None);
+}
+
+/// Adds every string of a catalog's table to `strings`, keyed by their dotted path.
+fn flatten(prefix: &str, table: &toml::value::Table, strings: &mut HashMap<String, String>) {
+    for (key, value) in table {
+        let key = if prefix.is_empty() {
+            key.to_owned()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+
+        match value {
+            Value::String(v) => {
+                strings.insert(key, v.to_owned());
+            }
+            Value::Table(v) => flatten(&key, v, &mut *strings),
+            _ => warn!("Ignoring non-string {:?} in locale catalog", key),
+        }
+    }
+}
+
+/// Parses a bundled catalog.
+fn parse_catalog(locale: &str) -> HashMap<String, String> {
+    let mut strings = HashMap::new();
+
+    let text = match CATALOGS.iter().find(|x| x.0 == locale) {
+        Some(v) => v.1,
+        None => return strings,
+    };
+
+    match text.parse::<Value>() {
+        Ok(Value::Table(v)) => flatten("", &v, &mut strings),
+        Ok(_) => error!("Locale catalog {:?} isn't a table", locale),
+        Err(v) => error!("Unable to parse locale catalog {:?}: {:?}", locale, v),
+    }
+
+    strings
+}
+
+/// Loads a bundled catalog over the fallback catalog.
+fn load(locale: &str) -> StringTable {
+    let mut strings = parse_catalog(FALLBACK);
+    if locale != FALLBACK {
+        strings.extend(parse_catalog(locale));
+    }
+
+    StringTable {
+        locale: locale.to_owned(),
+        strings,
+    }
+}
+
+/// Converts a locale name to a lowercase language tag, e.g. "de_DE.UTF-8" to "de-de".
+pub fn normalize_locale(locale: &str) -> String {
+    locale
+        .split(|x| x == '.' || x == '@')
+        .next()
+        .unwrap_or("")
+        .replace('_', "-")
+        .to_lowercase()
+}
+
+/// Returns if a locale is covered by a pattern, which is either a language tag ("pt-BR")
+/// or just a language ("pt").
+pub fn locale_matches(pattern: &str, locale: &str) -> bool {
+    let pattern = normalize_locale(pattern);
+    let locale = normalize_locale(locale);
+
+    !pattern.is_empty() && (pattern == locale || locale.starts_with(&format!("{}-", pattern)))
+}
+
+/// Finds the bundled catalog which best matches a locale name, accepting both language
+/// tags ("de-DE") and POSIX names ("de_DE.UTF-8").
+fn find_catalog(locale: &str) -> Option<&'static str> {
+    let tag = normalize_locale(locale);
+    let language = tag.split('-').next().unwrap_or("");
+
+    CATALOGS
+        .iter()
+        .find(|x| x.0 == tag)
+        .or_else(|| CATALOGS.iter().find(|x| x.0 == language))
+        .map(|x| x.0)
+}
+
+/// Picks the catalog to use for the rest of the session, from the specified locale if
+/// there is one, otherwise the user's.
+pub fn init(locale: Option<&str>) {
+    let requested = locale
+        .map(|x| x.to_owned())
+        .or_else(native::get_user_locale);
+
+    let catalog = match requested {
+        Some(ref v) => match find_catalog(v) {
+            Some(catalog) => catalog,
+            None => {
+                info!("No catalog for locale {:?}, using {:?}", v, FALLBACK);
+                FALLBACK
+            }
+        },
+        None => FALLBACK,
+    };
+
+    info!("Using {:?} strings", catalog);
+    *ACTIVE.write().log_expect("String table has been dirtied") = load(catalog);
+    *REQUESTED
+        .write()
+        .log_expect("Requested locale has been dirtied") = requested.map(|x| normalize_locale(&x));
+}
+
+/// Returns the language the user asked for as a language tag, which may not have a
+/// catalog of its own. Otherwise, returns the language of the catalog in use.
+pub fn get_language() -> String {
+    let requested = REQUESTED
+        .read()
+        .log_expect("Requested locale has been dirtied")
+        .clone();
+
+    requested.unwrap_or_else(|| {
+        ACTIVE
+            .read()
+            .log_expect("String table has been dirtied")
+            .locale
+            .clone()
+    })
+}
+
+/// Returns the text of a string. Unknown keys are returned as they are, so that they
+/// can still be spotted.
+pub fn tr(key: &str) -> String {
+    ACTIVE
+        .read()
+        .log_expect("String table has been dirtied")
+        .strings
+        .get(key)
+        .cloned()
+        .unwrap_or_else(|| {
+            warn!("No string for {:?}", key);
+            key.to_owned()
+        })
+}
+
+/// Returns the text of a string, substituting `{name}` placeholders.
+pub fn format(key: &str, args: &[(&str, &str)]) -> String {
+    let mut text = tr(key);
+    for (name, value) in args {
+        text = text.replace(&format!("{{{}}}", name), value);
+    }
+
+    text
+}
+
+/// Returns the text of a native string, substituting `{app_name}`.
+pub fn get(key: NativeString, app_name: &str) -> String {
+    format(key.key(), &[("app_name", app_name)])
+}
+
+/// Serialises the active locale and its strings as a JSON string.
+pub fn to_json_str() -> Result<String, SerdeError> {
+    serde_json::to_string(&*ACTIVE.read().log_expect("String table has been dirtied"))
+}
@@ -0,0 +1,208 @@
+//! suite.rs
+//!
+//! Installs several products behind one installer, for publishers shipping suites of
+//! tools. Each product is an installation of its own, with its own configuration,
+//! directory, maintenance tool and "Apps & Features" entry, so that it can be updated and
+//! uninstalled by itself later. The suite's configuration lists them with `suite`,
+//! instead of having packages:
+//!
+//! ```toml
+//! installing_message = "Installing the Example Suite"
+//!
+//! [[suite]]
+//! name = "Example Editor"
+//! description = "Edits examples"
+//! config_url = "https://example.com/editor/config.toml"
+//! directory = "Editor"
+//! default = true
+//! ```
+//!
+//! The chosen products are installed one after another, into their directories within
+//! the location chosen for the suite. This happens within the same process, so they share
+//! its downloads, bandwidth limits and proxy. Products which don't configure servers of
+//! their own use the suite's cache servers, URL rewrites, proxy and DNS resolver.
+//! Products which are already installed are updated instead.
+
+use std::path::Path;
+use std::sync::mpsc::Sender;
+
+use config::{BaseAttributes, Config, SuiteProduct};
+
+use dns;
+
+use http;
+
+use installer::{InstallMessage, InstallerFramework};
+
+use logging::LoggingErrors;
+
+use strings;
+
+/// Fills in the servers a product doesn't configure with the suite's, and applies them.
+fn inherit_servers(suite: &Config, product: &mut Config) {
+    if product.cache_servers.is_empty() {
+        product.cache_servers = suite.cache_servers.clone();
+    }
+    product
+        .url_rewrites
+        .extend(suite.url_rewrites.iter().cloned());
+
+    if product.proxy.is_none() && product.proxy_pac_url.is_none() {
+        product.proxy = suite.proxy.clone();
+        product.proxy_pac_url = suite.proxy_pac_url.clone();
+    }
+    if product.dns_over_https.is_none() {
+        product.dns_over_https = suite.dns_over_https.clone();
+    }
+
+    // Loading the product's configuration replaced the suite's
+    dns::set_doh_url(product.dns_over_https.clone());
+    http::set_pac_url(product.proxy_pac_url.clone());
+    http::set_configured_proxy(product.proxy.clone());
+}
+
+/// Sets up the installation of a product within the suite's install location, loading its
+/// configuration.
+fn open_product(
+    suite: &InstallerFramework,
+    product: &SuiteProduct,
+    root: &Path,
+) -> Result<InstallerFramework, String> {
+    let attributes = BaseAttributes {
+        name: product.name.clone(),
+        target_url: product.config_url.clone(),
+        ..suite.base_attributes.clone()
+    };
+
+    let path = root.join(product.get_directory());
+    let mut framework = if path.join("metadata.json").exists() {
+        info!(
+            "{:?} is already installed at {:?}, updating it",
+            product.name, path
+        );
+        InstallerFramework::new_with_db(attributes, &path)?
+    } else {
+        let mut framework = InstallerFramework::new(attributes);
+        framework.set_install_dir(&path.to_string_lossy());
+        framework.database.all_users = suite.database.all_users;
+        framework
+    };
+
+    // The product's maintenance tool is a copy of this one, so needs to know what it is
+    framework.database.config_url = Some(product.config_url.clone());
+    framework.database.product_name = Some(product.name.clone());
+    framework.database.environment = suite.database.environment.clone();
+    framework.staging_dir = suite.staging_dir.clone();
+    framework.install_timeout = suite.install_timeout;
+    framework.languages = suite.languages.clone();
+
+    let text = framework.download_config()?;
+    framework.load_config(&text)?;
+
+    if let Some(ref suite_config) = suite.config {
+        if let Some(ref mut config) = framework.config {
+            inherit_servers(suite_config, config);
+        }
+    }
+
+    Ok(framework)
+}
+
+/// Works out which of a product's packages to install: those already installed, or its
+/// defaults.
+fn select_packages(framework: &InstallerFramework) -> Vec<String> {
+    if framework.preexisting_install {
+        return framework
+            .database
+            .packages
+            .iter()
+            .map(|x| x.name.clone())
+            .collect();
+    }
+
+    framework
+        .config
+        .as_ref()
+        .map(|config| {
+            config
+                .packages
+                .iter()
+                .filter(|x| x.default.unwrap_or(false))
+                .map(|x| x.name.clone())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns the products selected by default.
+pub fn default_products(config: &Config) -> Vec<String> {
+    config
+        .suite
+        .iter()
+        .filter(|x| x.default)
+        .map(|x| x.name.clone())
+        .collect()
+}
+
+/// Installs (or updates) products of the suite into the framework's install directory.
+/// Products are installed in the order the suite lists them, stopping at the first which
+/// fails. Those installed before it are kept.
+pub fn install(
+    framework: &mut InstallerFramework,
+    products: &[String],
+    messages: &Sender<InstallMessage>,
+    create_desktop_shortcuts: bool,
+) -> Result<(), String> {
+    let config = framework
+        .config
+        .clone()
+        .ok_or("Configuration was not loaded")?;
+    let root = framework
+        .install_path
+        .clone()
+        .log_expect("Install directory not initialised");
+
+    for name in products {
+        if !config.suite.iter().any(|x| &x.name == name) {
+            return Err(format!("Unknown product: {:?}", name));
+        }
+    }
+
+    let selected: Vec<&SuiteProduct> = config
+        .suite
+        .iter()
+        .filter(|x| products.contains(&x.name))
+        .collect();
+    if selected.is_empty() {
+        return Err("No products were selected".to_string());
+    }
+
+    info!("Suite: Installing {:?} to {:?}", products, root);
+
+    for product in selected {
+        if let Err(v) = messages.send(InstallMessage::Status(
+            strings::format("progress.installing_product", &[("product", &product.name)]),
+            0.0,
+            None,
+            "progress.installing_product",
+        )) {
+            error!("Failed to send progress: {:?}", v);
+        }
+
+        let result = open_product(framework, product, &root).and_then(|mut installation| {
+            let packages = select_packages(&installation);
+            let fresh_install = !installation.preexisting_install;
+            let result =
+                installation.install(packages, messages, fresh_install, create_desktop_shortcuts);
+
+            framework.report = installation.report.take();
+            result
+        });
+
+        if let Err(v) = result {
+            return Err(format!("{}: {}", product.name, v));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,210 @@
+//! Creates the directory links (junctions or symbolic links) declared by a package, asking
+//! the user where they should point to if the package allows it.
+
+use installer::DirectoryLink;
+use installer::InstallerFramework;
+
+use tasks::remove_links::remove_link;
+use tasks::Task;
+use tasks::TaskDependency;
+use tasks::TaskMessage;
+use tasks::TaskParamType;
+
+use config::{LinkKind, PackageDescription, PackageLink};
+
+use logging::LoggingErrors;
+
+use audit::{self, AuditAction};
+
+use locations;
+
+use native;
+
+use prompts;
+
+use strings;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub struct CreateLinksTask {
+    pub name: String,
+}
+
+/// Returns if a path is a junction or symbolic link, rather than what it points to.
+fn is_link(path: &Path) -> bool {
+    fs::symlink_metadata(path).map_or(false, |x| x.file_type().is_symlink())
+}
+
+/// Returns if a path is an empty directory, which can be replaced by a link.
+fn is_empty_dir(path: &Path) -> bool {
+    fs::read_dir(path).map_or(false, |mut x| x.next().is_none())
+}
+
+impl CreateLinksTask {
+    /// Works out where a link should point to, keeping whatever was chosen for it when the
+    /// package was last installed.
+    fn choose_target(
+        &self,
+        link: &PackageLink,
+        path: &str,
+        default: PathBuf,
+        previous: Option<&DirectoryLink>,
+        messenger: &dyn Fn(&TaskMessage),
+    ) -> PathBuf {
+        if !link.user_selectable {
+            return default;
+        }
+
+        if let Some(previous) = previous {
+            return PathBuf::from(&previous.target);
+        }
+
+        let question = match link.description {
+            Some(ref description) => strings::format(
+                "prompts.link_target_described",
+                &[("description", description), ("path", path)],
+            ),
+            None => strings::format("prompts.link_target", &[("path", path)]),
+        };
+
+        PathBuf::from(prompts::ask_directory(
+            messenger,
+            &question,
+            &default.to_string_lossy(),
+        ))
+    }
+}
+
+impl Task for CreateLinksTask {
+    fn execute(
+        &mut self,
+        _: Vec<TaskParamType>,
+        context: &mut InstallerFramework,
+        messenger: &dyn Fn(&TaskMessage),
+    ) -> Result<TaskParamType, String> {
+        let config = context
+            .config
+            .as_ref()
+            .log_expect("Should have packages by now");
+
+        let mut metadata: Option<PackageDescription> = None;
+        for description in &config.packages {
+            if self.name == description.name {
+                metadata = Some(description.clone());
+                break;
+            }
+        }
+
+        let links = match metadata {
+            Some(v) => v.links,
+            None => return Err(format!("Package {:?} could not be found.", self.name)),
+        };
+
+        let data_locations = config.data_locations.clone();
+        let install_path = context
+            .install_path
+            .clone()
+            .log_expect("No install path specified");
+
+        if !links.is_empty() {
+            messenger(&TaskMessage::DisplayMessage(
+                "progress.creating_links",
+                &strings::format("progress.creating_links", &[("package", &self.name)]),
+                0.0,
+            ));
+        }
+
+        let mut declared = Vec::new();
+        for link in &links {
+            let path = locations::resolve(&link.path, &install_path, &data_locations)?;
+            let default = locations::resolve(&link.target, &install_path, &data_locations)?;
+            let path_str = path.to_string_lossy().to_string();
+            declared.push(path_str.clone());
+
+            let previous = context
+                .database
+                .links
+                .iter()
+                .position(|x| x.path == path_str)
+                .map(|x| context.database.links.remove(x));
+
+            let target = self.choose_target(link, &path_str, default, previous.as_ref(), messenger);
+            let target_str = target.to_string_lossy().to_string();
+
+            // Links survive updates, so only need replacing if they have been changed
+            if is_link(&path) {
+                let current = fs::read_link(&path).ok();
+                if previous.is_some() && current.as_ref() == Some(&target) {
+                    info!("Keeping link {:?} to {:?}", path, target);
+                    context.database.links.extend(previous);
+                    continue;
+                }
+
+                context.transaction.remove(&path)?;
+            } else if is_empty_dir(&path) {
+                context.transaction.remove(&path)?;
+            } else if path.exists() {
+                return Err(format!(
+                    "{:?} already exists, so can't be linked to {:?}",
+                    path, target
+                ));
+            }
+
+            if !target.is_dir() {
+                context.transaction.created(&target);
+                fs::create_dir_all(&target)
+                    .map_err(|x| format!("Unable to create {:?}: {:?}", target, x))?;
+            }
+
+            if let Some(parent) = path.parent() {
+                context.transaction.created(parent);
+                fs::create_dir_all(parent)
+                    .map_err(|x| format!("Unable to create {:?}: {:?}", parent, x))?;
+            }
+
+            info!("Linking {:?} to {:?}", path, target);
+            context.transaction.created(&path);
+            native::create_directory_link(&path, &target, link.kind == LinkKind::Junction)?;
+
+            audit::record(
+                context,
+                AuditAction::LinkCreated {
+                    package: self.name.clone(),
+                    path: path_str.clone(),
+                    target: target_str.clone(),
+                },
+            );
+
+            context.database.links.push(DirectoryLink {
+                package: self.name.clone(),
+                path: path_str,
+                target: target_str,
+            });
+        }
+
+        // Links which the package no longer declares are removed
+        let (stale, remaining) = context
+            .database
+            .links
+            .drain(..)
+            .partition::<Vec<_>, _>(|x| x.package == self.name && !declared.contains(&x.path));
+        context.database.links = remaining;
+
+        for link in stale {
+            if !remove_link(context, &link) {
+                context.database.links.push(link);
+            }
+        }
+
+        Ok(TaskParamType::None)
+    }
+
+    fn dependencies(&self) -> Vec<TaskDependency> {
+        vec![]
+    }
+
+    fn name(&self) -> String {
+        format!("CreateLinksTask (for {:?})", self.name)
+    }
+}
@@ -0,0 +1,186 @@
+//! Creates a dedicated system user/group for a package, and hands over ownership of
+//! the relevant directories to it.
+
+use installer::InstallerFramework;
+use installer::SystemAccount;
+
+use tasks::Task;
+use tasks::TaskDependency;
+use tasks::TaskMessage;
+use tasks::TaskParamType;
+
+use config::PackageDescription;
+use config::ResourceLimits;
+
+use logging::LoggingErrors;
+
+use audit::{self, AuditAction};
+
+use sandbox;
+
+use strings;
+
+use std::path::Path;
+use std::process::Command;
+
+pub struct CreateSystemUserTask {
+    pub name: String,
+}
+
+/// Returns if the named entry exists in the specified account database (passwd/group).
+fn account_exists(database: &str, name: &str) -> bool {
+    Command::new("getent")
+        .arg(database)
+        .arg(name)
+        .output()
+        .map(|x| x.status.success())
+        .unwrap_or(false)
+}
+
+/// Runs a account management command, turning failures into an error.
+pub fn run_account_command(
+    program: &str,
+    args: &[&str],
+    working_dir: &Path,
+    limits: &ResourceLimits,
+) -> Result<(), String> {
+    let output = sandbox::run(program, args, working_dir, limits)?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} failed ({}): {}",
+            program,
+            output.status,
+            output.stderr.trim()
+        ))
+    }
+}
+
+impl Task for CreateSystemUserTask {
+    fn execute(
+        &mut self,
+        _: Vec<TaskParamType>,
+        context: &mut InstallerFramework,
+        messenger: &dyn Fn(&TaskMessage),
+    ) -> Result<TaskParamType, String> {
+        let config = context
+            .config
+            .as_ref()
+            .log_expect("Should have packages by now");
+        let limits = config.limits.clone();
+
+        let mut metadata: Option<PackageDescription> = None;
+        for description in &config.packages {
+            if self.name == description.name {
+                metadata = Some(description.clone());
+                break;
+            }
+        }
+
+        let user = match metadata.and_then(|x| x.system_user) {
+            Some(v) => v,
+            None => return Ok(TaskParamType::None),
+        };
+
+        if !cfg!(target_os = "linux") {
+            warn!(
+                "System user creation is only supported on Linux, skipping {:?}",
+                user.name
+            );
+            return Ok(TaskParamType::None);
+        }
+
+        messenger(&TaskMessage::DisplayMessage(
+            "progress.creating_system_user",
+            &strings::format("progress.creating_system_user", &[("user", &user.name)]),
+            0.0,
+        ));
+
+        let path = context
+            .install_path
+            .clone()
+            .log_expect("No install path specified");
+
+        let home_dir = path
+            .to_str()
+            .log_expect("Unable to convert install path to string")
+            .to_string();
+
+        let group = user.group.clone().unwrap_or_else(|| user.name.clone());
+
+        let mut created = Vec::new();
+
+        if !account_exists("group", &group) {
+            run_account_command("groupadd", &["--system", &group], &path, &limits)?;
+            created.push((group.clone(), true));
+        }
+
+        if !account_exists("passwd", &user.name) {
+            run_account_command(
+                "useradd",
+                &[
+                    "--system",
+                    "--no-create-home",
+                    "--home-dir",
+                    &home_dir,
+                    "--shell",
+                    "/usr/sbin/nologin",
+                    "--gid",
+                    &group,
+                    &user.name,
+                ],
+                &path,
+                &limits,
+            )?;
+            created.push((user.name.clone(), false));
+        }
+
+        // Only record accounts we created ourselves, as to not delete pre-existing ones
+        for (name, is_group) in created {
+            audit::record(
+                context,
+                AuditAction::AccountCreated {
+                    package: self.name.clone(),
+                    name: name.clone(),
+                    is_group,
+                },
+            );
+
+            context.database.system_accounts.push(SystemAccount {
+                package: self.name.clone(),
+                name,
+                is_group,
+                remove_on_uninstall: user.remove_on_uninstall,
+            });
+        }
+
+        let owner = format!("{}:{}", user.name, group);
+        for owned_path in &user.owned_paths {
+            let target = path.join(owned_path);
+            let target = target
+                .to_str()
+                .log_expect("Unable to convert owned path to string");
+
+            run_account_command("chown", &["-R", &owner, target], &path, &limits)?;
+            audit::record(
+                context,
+                AuditAction::OwnershipChanged {
+                    path: target.to_string(),
+                    owner: owner.clone(),
+                },
+            );
+        }
+
+        Ok(TaskParamType::None)
+    }
+
+    fn dependencies(&self) -> Vec<TaskDependency> {
+        vec![]
+    }
+
+    fn name(&self) -> String {
+        format!("CreateSystemUserTask (for {:?})", self.name)
+    }
+}
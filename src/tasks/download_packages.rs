@@ -0,0 +1,326 @@
+//! Downloads several packages at once, ahead of them being installed.
+//!
+//! Packages are resolved one after another, then downloaded by up to
+//! `download_parallelism` workers. Each is written to its download path, for
+//! `DownloadPackageTask` to pick up. With more than one of `extraction_workers`, up to
+//! that many compressed tarballs are decompressed as they finish downloading, though
+//! their files are still installed one package at a time.
+//! Packages which need credentials or can be updated differentially are left to be
+//! downloaded as normal, as are any which fail here (picking up from what was received).
+
+use installer::InstallerFramework;
+
+use archives;
+
+use bandwidth;
+
+use tasks::download_pkg::DownloadRequest;
+use tasks::resolver::ResolvePackageTask;
+use tasks::Task;
+use tasks::TaskDependency;
+use tasks::TaskMessage;
+use tasks::TaskOrdering;
+use tasks::TaskParamType;
+
+use disk_space::pretty_size;
+
+use control;
+
+use strings;
+
+use std::cmp::min;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+/// How far along a package being downloaded alongside others is.
+#[derive(Serialize, Clone, Debug)]
+pub struct DownloadProgress {
+    pub package: String,
+    pub downloaded: u64,
+    /// Zero if the size isn't known yet
+    pub total: u64,
+    pub finished: bool,
+}
+
+impl DownloadProgress {
+    /// Returns how much of this download is done, between 0 and 1.
+    fn fraction(&self) -> f64 {
+        if self.finished {
+            1.0
+        } else if self.total == 0 {
+            0.0
+        } else {
+            self.downloaded as f64 / self.total as f64
+        }
+    }
+}
+
+/// Sent from workers as downloads progress.
+enum WorkerMessage {
+    Progress(usize, u64, u64),
+    Prompt(String, Vec<String>),
+    Finished(usize, Result<(), String>),
+    Decompressed(usize, Result<bool, String>),
+}
+
+pub struct DownloadPackagesTask {
+    pub items: Vec<String>,
+}
+
+impl DownloadPackagesTask {
+    /// Returns downloads for the resolved packages which can be fetched ahead of time.
+    fn requests(
+        &self,
+        resolved: Vec<TaskParamType>,
+        context: &mut InstallerFramework,
+    ) -> Result<Vec<DownloadRequest>, String> {
+        let mut requests = Vec::new();
+
+        for (name, result) in self.items.iter().zip(resolved) {
+            let (version, file) = match result {
+                TaskParamType::File(version, file) => (version, file),
+                _ => continue,
+            };
+
+            let installed = context.database.packages.iter().find(|x| &x.name == name);
+            let is_up_to_date =
+                installed.map_or(false, |x| x.version == version && x.suspect.is_none());
+            let is_differential = installed.is_some()
+                && file.chunk_index.is_some()
+                && context
+                    .config
+                    .as_ref()
+                    .and_then(|x| x.packages.iter().find(|x| &x.name == name))
+                    .map_or(false, |x| x.verification.is_none());
+
+            if !is_up_to_date && !is_differential && !file.requires_authorization {
+                requests.push(DownloadRequest::new(name, &file, None, context)?);
+            }
+
+            // So that installing the package doesn't look it up again
+            context.resolved.insert(name.clone(), (version, file));
+        }
+
+        Ok(requests)
+    }
+}
+
+impl Task for DownloadPackagesTask {
+    fn execute(
+        &mut self,
+        input: Vec<TaskParamType>,
+        context: &mut InstallerFramework,
+        messenger: &dyn Fn(&TaskMessage),
+    ) -> Result<TaskParamType, String> {
+        let parallelism = context
+            .config
+            .as_ref()
+            .map_or(1, |x| x.limits.download_parallelism);
+        let extraction_workers = context
+            .config
+            .as_ref()
+            .map_or(1, |x| x.limits.extraction_workers);
+
+        let requests = self.requests(input, context)?;
+        if requests.len() < 2 {
+            return Ok(TaskParamType::None);
+        }
+
+        let download_size = requests.iter().filter_map(|x| x.file.size).sum();
+        bandwidth::confirm(context, download_size, messenger)?;
+
+        let mut progress: Vec<DownloadProgress> = requests
+            .iter()
+            .map(|x| DownloadProgress {
+                package: x.name.clone(),
+                downloaded: 0,
+                total: x.file.size.unwrap_or(0),
+                finished: false,
+            })
+            .collect();
+
+        info!(
+            "Downloading {} packages with {} workers",
+            requests.len(),
+            parallelism
+        );
+
+        let workers = min(parallelism, requests.len());
+        let queue = Arc::new(Mutex::new(
+            requests.into_iter().enumerate().collect::<VecDeque<_>>(),
+        ));
+        let (sender, receiver) = channel();
+
+        // Finished downloads, for decompressing while the others carry on
+        let decompressing = extraction_workers > 1;
+        let (finished_sender, finished_receiver) = channel::<(usize, String, PathBuf)>();
+        let finished_receiver = Arc::new(Mutex::new(finished_receiver));
+
+        if decompressing {
+            for _ in 0..min(extraction_workers, requests.len()) {
+                let finished_receiver = finished_receiver.clone();
+                let sender = sender.clone();
+
+                thread::spawn(move || loop {
+                    let next = match finished_receiver.lock() {
+                        Ok(v) => v.recv().ok(),
+                        Err(_) => None,
+                    };
+
+                    let (index, name, path) = match next {
+                        Some(v) => v,
+                        None => break,
+                    };
+
+                    let result =
+                        control::check().and_then(|_| archives::decompress_file(&name, &path));
+                    if sender
+                        .send(WorkerMessage::Decompressed(index, result))
+                        .is_err()
+                    {
+                        break;
+                    }
+                });
+            }
+        }
+
+        for _ in 0..workers {
+            let queue = queue.clone();
+            let sender = sender.clone();
+            let finished_sender = finished_sender.clone();
+
+            thread::spawn(move || loop {
+                let next = match queue.lock() {
+                    Ok(mut v) => v.pop_front(),
+                    Err(_) => None,
+                };
+
+                let (index, request) = match next {
+                    Some(v) => v,
+                    None => break,
+                };
+
+                // Progress is reported as a whole, but questions still need answering
+                let result = request.download(
+                    &|msg: &TaskMessage| {
+                        if let TaskMessage::Prompt(question, choices) = *msg {
+                            let choices = choices.iter().map(|x| x.to_string()).collect();
+                            let _ =
+                                sender.send(WorkerMessage::Prompt(question.to_string(), choices));
+                        }
+                    },
+                    &|downloaded, total| {
+                        let _ = sender.send(WorkerMessage::Progress(index, downloaded, total));
+                    },
+                );
+
+                let downloaded = result.is_ok();
+                if sender.send(WorkerMessage::Finished(index, result)).is_err() {
+                    break;
+                }
+
+                if decompressing && downloaded {
+                    let _ = finished_sender.send((index, request.file.name.clone(), request.path));
+                }
+            });
+        }
+
+        drop(sender);
+        drop(finished_sender);
+
+        let started = Instant::now();
+
+        // Finishes once every worker has run out of packages
+        for message in receiver {
+            match message {
+                WorkerMessage::Progress(index, downloaded, total) => {
+                    progress[index].downloaded = downloaded;
+                    progress[index].total = total;
+                }
+                WorkerMessage::Prompt(question, choices) => {
+                    let choices: Vec<&str> = choices.iter().map(|x| x.as_str()).collect();
+                    messenger(&TaskMessage::Prompt(&question, &choices));
+                    continue;
+                }
+                WorkerMessage::Finished(index, Ok(_)) => {
+                    progress[index].finished = true;
+                    if !decompressing {
+                        context.downloaded.insert(progress[index].package.clone());
+                    }
+                }
+                WorkerMessage::Finished(index, Err(v)) => {
+                    // Carried on with when installed, so that the error ends up there
+                    progress[index].finished = true;
+                    warn!(
+                        "Unable to download {:?} ahead of time: {}",
+                        progress[index].package, v
+                    );
+                }
+                WorkerMessage::Decompressed(index, result) => {
+                    // Left compressed if not, to be decompressed when installed
+                    if let Err(v) = result {
+                        warn!(
+                            "Unable to decompress {:?} ahead of time: {}",
+                            progress[index].package, v
+                        );
+                    }
+
+                    context.downloaded.insert(progress[index].package.clone());
+                    continue;
+                }
+            }
+
+            let downloaded: u64 = progress.iter().map(|x| x.downloaded).sum();
+            let total: u64 = progress.iter().map(|x| x.total).sum();
+            let elapsed = started.elapsed();
+            let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_millis()) / 1000.0;
+            let rate = if elapsed > 0.0 {
+                (downloaded as f64 / elapsed) as u64
+            } else {
+                0
+            };
+
+            let fraction =
+                progress.iter().map(|x| x.fraction()).sum::<f64>() / progress.len() as f64;
+
+            messenger(&TaskMessage::Downloads(&progress));
+            messenger(&TaskMessage::DisplayMessage(
+                "progress.downloading_packages",
+                &strings::format(
+                    "progress.downloading_packages",
+                    &[
+                        ("count", &progress.len().to_string()),
+                        ("downloaded", &pretty_size(downloaded)),
+                        ("total", &pretty_size(total)),
+                        ("rate", &pretty_size(rate)),
+                    ],
+                ),
+                fraction,
+            ));
+        }
+
+        control::check()?;
+
+        Ok(TaskParamType::None)
+    }
+
+    fn dependencies(&self) -> Vec<TaskDependency> {
+        self.items
+            .iter()
+            .map(|x| {
+                TaskDependency::build(
+                    TaskOrdering::Pre,
+                    Box::new(ResolvePackageTask { name: x.clone() }),
+                )
+            })
+            .collect()
+    }
+
+    fn name(&self) -> String {
+        format!("DownloadPackagesTask (for {:?})", self.items)
+    }
+}
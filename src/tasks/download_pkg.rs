@@ -1,20 +1,514 @@
-//! Downloads a package into memory.
+//! Downloads a package, ready to be installed.
 
 use installer::InstallerFramework;
 
 use tasks::check_authorization::CheckAuthorizationTask;
 use tasks::{Task, TaskDependency, TaskMessage, TaskOrdering, TaskParamType};
 
-use http::stream_file;
+use bandwidth;
+
+use differential;
+
+use events;
+use events::Event;
+
+use http::{cache_url, mirror_url, stream_file_from};
+
+use mirrors;
+
+use sources::types::{File, PieceHashes};
+
+use config::{PackageVerification, ResourceLimits};
 
 use number_prefix::{NumberPrefix, Prefixed, Standalone};
 
+use power::BatteryGovernor;
+
+use prefetch;
+
+use strings;
+
+use control;
+
+use disk_space::SpaceMonitor;
+
+use verification;
+
 use logging::LoggingErrors;
 
+use std::cmp::min;
+use std::env;
+use std::fs::{self, create_dir_all, remove_file, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// Environment variable which overrides the configured cache servers (comma separated).
+const CACHE_ENV_VAR: &str = "LIFTINSTALL_CACHE_URL";
+/// Longest time to wait between attempts at a download, in seconds.
+const MAX_RETRY_DELAY: u64 = 30;
+
 pub struct DownloadPackageTask {
     pub name: String,
 }
 
+/// Returns the list of local cache servers to try before the upstream source.
+fn get_cache_servers(context: &InstallerFramework) -> Vec<String> {
+    if let Ok(servers) = env::var(CACHE_ENV_VAR) {
+        return servers
+            .split(',')
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty())
+            .collect();
+    }
+
+    context
+        .config
+        .as_ref()
+        .map(|x| x.cache_servers.clone())
+        .unwrap_or_default()
+}
+
+/// Everything needed to download a package's file, so that it can be done away from the
+/// framework (e.g. alongside other packages).
+pub struct DownloadRequest {
+    pub name: String,
+    pub file: File,
+    pub auth: Option<String>,
+    cache_servers: Vec<String>,
+    mirrors: Vec<String>,
+    probe_mirrors: bool,
+    limits: ResourceLimits,
+    package_root: PathBuf,
+    verification: Option<PackageVerification>,
+    /// Where the file is downloaded to
+    pub path: PathBuf,
+}
+
+/// A download being written to disk, so that it isn't held in memory and can be picked up
+/// from if interrupted.
+struct PartialFile {
+    path: PathBuf,
+    file: fs::File,
+    len: u64,
+    /// The first error writing to it, reported once the current request finishes
+    error: Option<String>,
+}
+
+impl PartialFile {
+    /// Opens a download, keeping anything an earlier attempt received.
+    fn open(path: &Path) -> Result<PartialFile, String> {
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)
+                .map_err(|x| format!("Unable to create {:?}: {:?}", parent, x))?;
+        }
+
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)
+            .map_err(|x| format!("Unable to open {:?}: {:?}", path, x))?;
+        let len = file
+            .metadata()
+            .map_err(|x| format!("Unable to read {:?}: {:?}", path, x))?
+            .len();
+
+        Ok(PartialFile {
+            path: path.to_owned(),
+            file,
+            len,
+            error: None,
+        })
+    }
+
+    fn append(&mut self, data: &[u8]) {
+        if self.error.is_some() {
+            return;
+        }
+
+        match self.file.write_all(data) {
+            Ok(_) => self.len += data.len() as u64,
+            Err(v) => self.error = Some(format!("Unable to write {:?}: {:?}", self.path, v)),
+        }
+    }
+
+    fn truncate(&mut self, len: u64) -> Result<(), String> {
+        self.file
+            .set_len(len)
+            .map_err(|x| format!("Unable to truncate {:?}: {:?}", self.path, x))?;
+        self.len = len;
+        Ok(())
+    }
+
+    /// Returns how much of what was received since `start` matches a torrent's pieces.
+    fn verified_length(&mut self, start: u64, pieces: &PieceHashes) -> Result<u64, String> {
+        self.file
+            .seek(SeekFrom::Start(start))
+            .map_err(|x| format!("Unable to read {:?}: {:?}", self.path, x))?;
+
+        let mut received = Read::take(&self.file, self.len - start);
+        pieces.verified_length(&mut received)
+    }
+}
+
+impl DownloadRequest {
+    pub fn new(
+        name: &str,
+        file: &File,
+        auth: Option<String>,
+        context: &InstallerFramework,
+    ) -> Result<DownloadRequest, String> {
+        let package = context
+            .config
+            .as_ref()
+            .and_then(|x| x.packages.iter().find(|x| x.name == name));
+
+        // Local caches are preferred, but never see files which require our credentials
+        let cache_servers = if file.requires_authorization {
+            Vec::new()
+        } else {
+            get_cache_servers(context)
+        };
+
+        Ok(DownloadRequest {
+            name: name.to_string(),
+            file: file.clone(),
+            auth,
+            cache_servers,
+            mirrors: package
+                .map(|x| x.source.mirrors.clone())
+                .unwrap_or_default(),
+            probe_mirrors: package.map_or(true, |x| x.source.probe_mirrors),
+            limits: context
+                .config
+                .as_ref()
+                .map(|x| x.limits.clone())
+                .unwrap_or_default(),
+            package_root: context.get_package_root(name)?,
+            // Reassembled archives are checked against the checksums published with them
+            // (see `multipart`), even if verification isn't configured
+            verification: package.and_then(|x| x.verification.clone()).or_else(|| {
+                file.checksums
+                    .as_ref()
+                    .map(|_| PackageVerification::default())
+            }),
+            path: context.get_download_path(name)?,
+        })
+    }
+
+    /// Downloads (and verifies) the file to `path`, picking up from anything an earlier
+    /// attempt left there. `on_progress` is given how much has been received so far, and
+    /// the total size (zero if not known yet).
+    pub fn download(
+        &self,
+        messenger: &dyn Fn(&TaskMessage),
+        on_progress: &dyn Fn(u64, u64),
+    ) -> Result<(), String> {
+        // Whatever was fetched while the welcome screen was shown is picked up from
+        if self.file.parts.is_empty() && !self.path.exists() {
+            prefetch::take(&self.name, &self.file.url, &self.path);
+        }
+
+        let mut storage = PartialFile::open(&self.path)?;
+
+        let file = &self.file;
+        let limits = &self.limits;
+        let mut governor = BatteryGovernor::new(limits);
+
+        // Where each part began isn't recorded, so only single files are picked up from
+        let oversized = file.size.map_or(false, |x| storage.len > x);
+        if storage.len > 0 && (!file.parts.is_empty() || oversized) {
+            storage.truncate(0)?;
+        } else if storage.len > 0 {
+            info!(
+                "Picking up from the {} bytes of {:?} already downloaded",
+                storage.len, self.name
+            );
+        }
+
+        // There is no point fetching something which can't be extracted afterwards
+        let mut space = SpaceMonitor::new(&self.package_root, limits);
+        let mut space_error = None;
+        let mut space_checked = file.size.is_some();
+        let mut user_paused = false;
+        if let Some(size) = file.size {
+            space.wait(size, messenger)?;
+        }
+
+        // Multi-part archives are concatenated back together in order
+        let urls: Vec<&String> = Some(&file.url).into_iter().chain(file.parts.iter()).collect();
+        let part_count = urls.len();
+
+        for (part, url) in urls.into_iter().enumerate() {
+            let part_start = storage.len;
+
+            // Caches are used while they are available, then the upstream host and its
+            // mirrors (which may be retried). Mirrors don't see our credentials.
+            let mut candidates: Vec<(String, bool)> = self
+                .cache_servers
+                .iter()
+                .filter_map(|x| cache_url(x, url))
+                .map(|x| (x, false))
+                .collect();
+
+            let mut hosts: Vec<String> = self
+                .mirrors
+                .iter()
+                .filter_map(|x| mirror_url(x, url))
+                .collect();
+            if let Some(ref torrent) = file.torrent {
+                hosts.extend(torrent.webseeds.iter().cloned());
+            }
+            if self.probe_mirrors && self.auth.is_none() {
+                hosts.insert(0, url.to_owned());
+                mirrors::rank(&mut hosts, false);
+            } else {
+                // Probes can't be authorized, so private hosts always come first
+                if self.probe_mirrors {
+                    mirrors::rank(&mut hosts, false);
+                }
+                hosts.insert(0, url.to_owned());
+            }
+            candidates.extend(hosts.into_iter().map(|x| (x, true)));
+
+            let mut last_error = None;
+            let mut index = 0;
+            let mut attempt = 0;
+            while index < candidates.len() {
+                let (ref candidate, retryable) = candidates[index];
+                let is_upstream = candidate == url;
+
+                // Whatever has already been received is kept, and the rest requested
+                let offset = storage.len - part_start;
+                let mut downloaded = offset;
+
+                let auth = if is_upstream { self.auth.clone() } else { None };
+                let result = stream_file_from(candidate, auth, offset, |data, size| {
+                    storage.append(&data);
+
+                    downloaded += data.len() as u64;
+
+                    // Otherwise, the size is only known once the server has responded
+                    if !space_checked && size > 0 {
+                        space_checked = true;
+                        if let Err(v) = space.wait(size * part_count as u64, messenger) {
+                            space_error = Some(v);
+                        }
+                    }
+
+                    let percentage = if size == 0 {
+                        0.0
+                    } else {
+                        (downloaded as f64) / (size as f64)
+                    };
+
+                    // Pretty print data volumes
+                    let pretty_current = match NumberPrefix::decimal(downloaded as f64) {
+                        Standalone(bytes) => format!("{} bytes", bytes),
+                        Prefixed(prefix, n) => format!("{:.0} {}B", n, prefix),
+                    };
+                    let pretty_total = match NumberPrefix::decimal(size as f64) {
+                        Standalone(bytes) => format!("{} bytes", bytes),
+                        Prefixed(prefix, n) => format!("{:.0} {}B", n, prefix),
+                    };
+
+                    let message = if part_count > 1 {
+                        strings::format(
+                            "progress.downloading_part",
+                            &[
+                                ("package", &self.name),
+                                ("part", &(part + 1).to_string()),
+                                ("parts", &part_count.to_string()),
+                                ("downloaded", &pretty_current),
+                                ("total", &pretty_total),
+                            ],
+                        )
+                    } else {
+                        strings::format(
+                            "progress.downloading",
+                            &[
+                                ("package", &self.name),
+                                ("downloaded", &pretty_current),
+                                ("total", &pretty_total),
+                            ],
+                        )
+                    };
+
+                    let progress = (part as f64 + percentage) / part_count as f64;
+                    messenger(&TaskMessage::DisplayMessage(
+                        "progress.downloading",
+                        &message,
+                        progress,
+                    ));
+
+                    events::publish(Event::Download {
+                        package: self.name.clone(),
+                        downloaded,
+                        total: size,
+                    });
+                    on_progress(storage.len, file.size.unwrap_or(size * part_count as u64));
+
+                    governor.wait(data.len(), || {
+                        messenger(&TaskMessage::DisplayMessage(
+                            "progress.paused_on_battery",
+                            &strings::tr("progress.paused_on_battery"),
+                            progress,
+                        ))
+                    });
+
+                    if control::wait_while_paused(|| {
+                        messenger(&TaskMessage::DisplayMessage(
+                            "progress.paused",
+                            &strings::tr("progress.paused"),
+                            progress,
+                        ))
+                    }) {
+                        user_paused = true;
+                    }
+                });
+
+                if let Some(v) = space_error.take() {
+                    return Err(v);
+                }
+                if let Some(v) = storage.error.take() {
+                    return Err(v);
+                }
+
+                // Pieces which don't match are fetched again from the next source
+                let mut corrupt = false;
+                let result = match (result, &file.torrent) {
+                    (Ok(_), &Some(ref torrent)) => {
+                        let received = storage.len - part_start;
+                        let verified = storage.verified_length(part_start, &torrent.pieces)?;
+                        if verified < received {
+                            storage.truncate(part_start + verified)?;
+                            corrupt = true;
+                            Err(format!(
+                                "{:?} served a piece which doesn't match the torrent",
+                                candidate
+                            ))
+                        } else {
+                            Ok(())
+                        }
+                    }
+                    (result, _) => result,
+                };
+
+                match result {
+                    Ok(_) => {
+                        last_error = None;
+                        break;
+                    }
+                    Err(v) => {
+                        control::check()?;
+
+                        // The server may have given up on us while paused, so pick up again
+                        if governor.take_paused() || user_paused {
+                            user_paused = false;
+                            info!("Resuming {:?} after pausing: {}", candidate, v);
+                            continue;
+                        }
+
+                        // Only give up on a source once it stops making progress
+                        if downloaded > offset {
+                            attempt = 0;
+                        }
+
+                        if retryable && !corrupt && attempt < limits.download_retries {
+                            attempt += 1;
+                            let delay = min(1u64 << min(attempt - 1, 16), MAX_RETRY_DELAY);
+                            warn!(
+                                "Download of {:?} failed (retrying in {}s, attempt {} of {}): {}",
+                                candidate, delay, attempt, limits.download_retries, v
+                            );
+
+                            messenger(&TaskMessage::DisplayMessage(
+                                "progress.download_retrying",
+                                &strings::format(
+                                    "progress.download_retrying",
+                                    &[("delay", &delay.to_string())],
+                                ),
+                                part as f64 / part_count as f64,
+                            ));
+
+                            thread::sleep(Duration::from_secs(delay));
+                            continue;
+                        }
+
+                        if retryable {
+                            warn!("Giving up on {:?}, trying next source: {}", candidate, v);
+
+                            // Whatever took this host down may have affected the others
+                            if self.probe_mirrors {
+                                mirrors::mark_failed(candidate);
+
+                                let rest = &mut candidates[index + 1..];
+                                let mut urls: Vec<String> =
+                                    rest.iter().map(|x| x.0.clone()).collect();
+                                mirrors::rank(&mut urls, true);
+                                for (candidate, url) in rest.iter_mut().zip(urls) {
+                                    candidate.0 = url;
+                                }
+                            }
+                        } else {
+                            info!(
+                                "Cache {:?} unavailable, trying next source: {}",
+                                candidate, v
+                            );
+                        }
+                        last_error = Some(v);
+                    }
+                }
+
+                attempt = 0;
+                index += 1;
+            }
+
+            if let Some(v) = last_error {
+                return Err(v);
+            }
+
+            if storage.len == part_start {
+                return Err(format!(
+                    "Part {} of {:?} was empty",
+                    part + 1,
+                    file.name
+                ));
+            }
+        }
+
+        // Downloads which don't match are removed, as picking up from them would fail too
+        if let Err(v) = verification::verify_sha1(file, &self.path) {
+            drop(storage);
+            if let Err(x) = remove_file(&self.path) {
+                warn!("Unable to remove {:?}: {:?}", self.path, x);
+            }
+            return Err(v);
+        }
+
+        if let Some(ref verification) = self.verification {
+            messenger(&TaskMessage::DisplayMessage(
+                "progress.verifying",
+                &strings::format("progress.verifying", &[("package", &self.name)]),
+                1.0,
+            ));
+
+            // Anything picked up from later would fail again
+            if let Err(v) = verification::verify_download_file(file, &self.path, verification) {
+                drop(storage);
+                if let Err(x) = remove_file(&self.path) {
+                    warn!("Unable to remove {:?}: {:?}", self.path, x);
+                }
+                return Err(v);
+            }
+        }
+
+        Ok(())
+    }
+}
+
 impl Task for DownloadPackageTask {
     fn execute(
         &mut self,
@@ -37,10 +531,11 @@ impl Task for DownloadPackageTask {
             return Ok(TaskParamType::Break);
         }
 
-        // Check to see if this is the newest file available already
+        // Check to see if this is the newest file available already. Packages which failed
+        // their smoke tests are installed again regardless.
         for element in &context.database.packages {
             if element.name == self.name {
-                if element.version == version {
+                if element.version == version && element.suspect.is_none() {
                     info!("{:?} is already up to date.", self.name);
                     return Ok(TaskParamType::Break);
                 }
@@ -48,47 +543,89 @@ impl Task for DownloadPackageTask {
             }
         }
 
+        // Packages downloaded ahead of time, alongside others
+        if context.downloaded.remove(&self.name) {
+            info!("Using already downloaded {:?}", self.name);
+            let path = context.get_download_path(&self.name)?;
+            return Ok(TaskParamType::DownloadedFile(version, file, path));
+        }
+
         messenger(&TaskMessage::DisplayMessage(
-            &format!("Downloading package {:?}...", self.name),
+            "progress.downloading_package",
+            &strings::format("progress.downloading_package", &[("package", &self.name)]),
             0.0,
         ));
 
-        let mut downloaded = 0;
-        let mut data_storage: Vec<u8> = Vec::new();
+        let verification = context
+            .config
+            .as_ref()
+            .and_then(|x| x.packages.iter().find(|x| x.name == self.name))
+            .and_then(|x| x.verification.clone());
 
-        stream_file(&file.url, auth, |data, size| {
-            {
-                data_storage.extend_from_slice(&data);
-            }
+        // Attempt to only fetch what has changed since the installed version. Reassembled
+        // payloads can't be checked against what was published, so aren't used when
+        // downloads need to be verified.
+        let installed_files = context
+            .database
+            .packages
+            .iter()
+            .find(|x| x.name == self.name)
+            .map(|x| x.files.clone());
 
-            downloaded += data.len();
+        let chunk_index = file.chunk_index.as_ref().filter(|_| verification.is_none());
+        if let (Some(index_url), Some(installed_files)) = (chunk_index, installed_files) {
+            let install_path = context.get_package_root(&self.name)?;
 
-            let percentage = if size == 0 {
-                0.0
-            } else {
-                (downloaded as f64) / (size as f64)
-            };
-
-            // Pretty print data volumes
-            let pretty_current = match NumberPrefix::decimal(downloaded as f64) {
-                Standalone(bytes) => format!("{} bytes", bytes),
-                Prefixed(prefix, n) => format!("{:.0} {}B", n, prefix),
-            };
-            let pretty_total = match NumberPrefix::decimal(size as f64) {
-                Standalone(bytes) => format!("{} bytes", bytes),
-                Prefixed(prefix, n) => format!("{:.0} {}B", n, prefix),
-            };
+            let result = differential::download_payload(
+                index_url,
+                auth.clone(),
+                &install_path,
+                &installed_files,
+                |downloaded, total| {
+                    let percentage = if total == 0 {
+                        1.0
+                    } else {
+                        (downloaded as f64) / (total as f64)
+                    };
 
-            messenger(&TaskMessage::DisplayMessage(
-                &format!(
-                    "Downloading {} ({} of {})...",
-                    self.name, pretty_current, pretty_total
+                    messenger(&TaskMessage::DisplayMessage(
+                        "progress.downloading_changes",
+                        &strings::format(
+                            "progress.downloading_changes",
+                            &[
+                                ("package", &self.name),
+                                ("downloaded", &downloaded.to_string()),
+                                ("total", &total.to_string()),
+                            ],
+                        ),
+                        percentage,
+                    ));
+                },
+            );
+
+            match result {
+                Ok(data) => {
+                    let payload = File {
+                        name: format!("{}.tar", file.name),
+                        ..file.clone()
+                    };
+                    return Ok(TaskParamType::FileContents(version, payload, data));
+                }
+                Err(v) => warn!(
+                    "Differential download failed, falling back to a full download: {}",
+                    v
                 ),
-                percentage,
-            ));
-        })?;
+            }
+        }
+
+        if let Some(size) = file.size {
+            bandwidth::confirm(context, size, messenger)?;
+        }
+
+        let request = DownloadRequest::new(&self.name, &file, auth, context)?;
+        request.download(messenger, &|_, _| {})?;
 
-        Ok(TaskParamType::FileContents(version, file, data_storage))
+        Ok(TaskParamType::DownloadedFile(version, file, request.path))
     }
 
     fn dependencies(&self) -> Vec<TaskDependency> {
@@ -7,19 +7,108 @@ use tasks::TaskDependency;
 use tasks::TaskMessage;
 use tasks::TaskParamType;
 
+use config::LockedFilePolicy;
+
+use error_help;
+
 use native::get_process_names;
 use native::Process;
+use native::RestartSession;
+
+use prompts;
+
+use strings;
 
+use std::path::PathBuf;
 use std::process;
 
 pub struct EnsureOnlyInstanceTask {}
 
+impl EnsureOnlyInstanceTask {
+    /// Closes applications which have installed files open, as found by the Restart
+    /// Manager. The session is kept so that they can be restarted after the install.
+    fn close_locking_applications(
+        context: &mut InstallerFramework,
+        messenger: &dyn Fn(&TaskMessage),
+    ) -> Result<(), String> {
+        let install_path = match context.install_path {
+            Some(ref v) => v.clone(),
+            None => return Ok(()),
+        };
+
+        let files: Vec<PathBuf> = context
+            .database
+            .packages
+            .iter()
+            .flat_map(|x| {
+                let root = x.get_root(&install_path);
+                x.files
+                    .iter()
+                    .map(move |file| root.join(file))
+                    .collect::<Vec<_>>()
+            })
+            .filter(|x| x.is_file())
+            .collect();
+
+        if files.is_empty() {
+            return Ok(());
+        }
+
+        let session = RestartSession::new(&files)?;
+        let applications = session.get_applications()?;
+        if applications.is_empty() {
+            return Ok(());
+        }
+
+        let names = applications
+            .iter()
+            .map(|x| format!("{} (PID {})", x.name, x.pid))
+            .collect::<Vec<_>>()
+            .join(", ");
+        info!("Installed files are in use by: {}", names);
+
+        let policy = context
+            .config
+            .as_ref()
+            .map(|x| x.locked_files.clone())
+            .unwrap_or_default();
+
+        let close = match policy {
+            LockedFilePolicy::Error => false,
+            LockedFilePolicy::Close => true,
+            LockedFilePolicy::Prompt => {
+                let close = strings::tr("prompts.close");
+                let cancel = strings::tr("prompts.cancel");
+
+                prompts::ask(
+                    messenger,
+                    &strings::format("prompts.close_applications", &[("applications", &names)]),
+                    &[&close, &cancel],
+                    &cancel,
+                ) == close
+            }
+        };
+
+        if !close {
+            return Err(error_help::coded(
+                "application_running",
+                strings::format("errors.applications_running", &[("applications", &names)]),
+            ));
+        }
+
+        session.shutdown()?;
+        context.restart_session = Some(session);
+
+        Ok(())
+    }
+}
+
 impl Task for EnsureOnlyInstanceTask {
     fn execute(
         &mut self,
         input: Vec<TaskParamType>,
         context: &mut InstallerFramework,
-        _messenger: &dyn Fn(&TaskMessage),
+        messenger: &dyn Fn(&TaskMessage),
     ) -> Result<TaskParamType, String> {
         assert_eq!(input.len(), 0);
 
@@ -32,18 +121,29 @@ impl Task for EnsureOnlyInstanceTask {
             let exe = name;
 
             if exe.ends_with("maintenancetool.exe") || exe.ends_with("maintenancetool") {
-                return Err("Maintenance tool is already running!".to_string());
+                return Err(strings::tr("errors.maintenance_tool_running"));
+            }
+
+            // On Windows, the Restart Manager finds applications by the files they have
+            // open instead, which also catches those loading installed libraries
+            if cfg!(windows) {
+                continue;
             }
 
             for package in &context.database.packages {
                 for file in &package.files {
                     if exe.ends_with(file) {
-                        return Err("The installed application is currently running!".to_string());
+                        return Err(error_help::coded(
+                            "application_running",
+                            strings::tr("errors.application_running"),
+                        ));
                     }
                 }
             }
         }
 
+        Self::close_locking_applications(context, messenger)?;
+
         Ok(TaskParamType::None)
     }
 
@@ -2,10 +2,12 @@
 
 use installer::InstallerFramework;
 
+use tasks::download_packages::DownloadPackagesTask;
 use tasks::ensure_only_instance::EnsureOnlyInstanceTask;
 use tasks::install_dir::VerifyInstallDirTask;
 use tasks::install_global_shortcut::InstallGlobalShortcutsTask;
 use tasks::install_pkg::InstallPackageTask;
+use tasks::register_uninstaller::RegisterUninstallerTask;
 use tasks::save_executable::SaveExecutableTask;
 use tasks::uninstall_pkg::UninstallPackageTask;
 use tasks::launch_installed_on_exit::LaunchOnExitTask;
@@ -16,11 +18,21 @@ use tasks::TaskMessage;
 use tasks::TaskOrdering;
 use tasks::TaskParamType;
 
+use strings;
+
 pub struct InstallTask {
     pub items: Vec<String>,
     pub uninstall_items: Vec<String>,
     pub fresh_install: bool,
+    /// Taking over an installation made by another installer (see `migration`), so the
+    /// install directory isn't empty
+    pub adopting: bool,
     pub create_desktop_shortcuts: bool,
+    /// Only extracts packages, skipping shortcuts, the maintenance tool and metadata.
+    pub extract_only: bool,
+    /// Downloads packages alongside each other before installing them (see
+    /// `DownloadPackagesTask`)
+    pub parallel_downloads: bool,
 }
 
 impl Task for InstallTask {
@@ -30,7 +42,11 @@ impl Task for InstallTask {
         _: &mut InstallerFramework,
         messenger: &dyn Fn(&TaskMessage),
     ) -> Result<TaskParamType, String> {
-        messenger(&TaskMessage::DisplayMessage("Wrapping up...", 0.0));
+        messenger(&TaskMessage::DisplayMessage(
+            "progress.wrapping_up",
+            &strings::tr("progress.wrapping_up"),
+            0.0,
+        ));
         Ok(TaskParamType::None)
     }
 
@@ -45,10 +61,20 @@ impl Task for InstallTask {
         elements.push(TaskDependency::build(
             TaskOrdering::Pre,
             Box::new(VerifyInstallDirTask {
-                clean_install: self.fresh_install,
+                clean_install: self.fresh_install && !self.adopting,
             }),
         ));
 
+        // Downloads everything at once, before anything is changed
+        if self.parallel_downloads && self.items.len() > 1 {
+            elements.push(TaskDependency::build(
+                TaskOrdering::Pre,
+                Box::new(DownloadPackagesTask {
+                    items: self.items.clone(),
+                }),
+            ));
+        }
+
         for item in &self.uninstall_items {
             elements.push(TaskDependency::build(
                 TaskOrdering::Pre,
@@ -62,11 +88,15 @@ impl Task for InstallTask {
         for item in &self.items {
             elements.push(TaskDependency::build(
                 TaskOrdering::Pre,
-                Box::new(InstallPackageTask { name: item.clone(), create_desktop_shortcuts: self.create_desktop_shortcuts }),
+                Box::new(InstallPackageTask {
+                    name: item.clone(),
+                    create_desktop_shortcuts: self.create_desktop_shortcuts,
+                    extract_only: self.extract_only,
+                }),
             ));
         }
 
-        if self.fresh_install {
+        if self.fresh_install && !self.extract_only {
             elements.push(TaskDependency::build(
                 TaskOrdering::Pre,
                 Box::new(SaveExecutableTask {}),
@@ -83,6 +113,14 @@ impl Task for InstallTask {
             ))
         }
 
+        // Updates change the version and size that are shown
+        if !self.extract_only {
+            elements.push(TaskDependency::build(
+                TaskOrdering::Pre,
+                Box::new(RegisterUninstallerTask {}),
+            ));
+        }
+
         elements
     }
 
@@ -11,7 +11,11 @@ use config::PackageDescription;
 
 use logging::LoggingErrors;
 
-use native::create_desktop_shortcut;
+use audit::{self, AuditAction};
+
+use native::{self, create_desktop_shortcut};
+
+use strings;
 
 pub struct InstallDesktopShortcutTask {
     pub name: String,
@@ -25,12 +29,20 @@ impl Task for InstallDesktopShortcutTask {
         context: &mut InstallerFramework,
         messenger: &dyn Fn(&TaskMessage),
     ) -> Result<TaskParamType, String> {
-        if !self.should_run {
+        let enabled = context
+            .config
+            .as_ref()
+            .map_or(true, |x| x.shortcuts.desktop);
+        if !self.should_run || !enabled {
             return Ok(TaskParamType::GeneratedShortcuts(Vec::new()));
         }
 
         messenger(&TaskMessage::DisplayMessage(
-            &format!("Generating desktop shortcuts for package {:?}...", self.name),
+            "progress.generating_desktop_shortcuts",
+            &strings::format(
+                "progress.generating_desktop_shortcuts",
+                &[("package", &self.name)],
+            ),
             0.0,
         ));
 
@@ -63,6 +75,8 @@ impl Task for InstallDesktopShortcutTask {
             None => return Err(format!("Package {:?} could not be found.", self.name)),
         };
 
+        let package_root = context.get_package_root(&self.name)?;
+
         // Generate installer path
         let platform_extension = if cfg!(windows) {
             "maintenancetool.exe"
@@ -76,12 +90,12 @@ impl Task for InstallDesktopShortcutTask {
                 .to_str()
                 .log_expect("Unable to build shortcut metadata (tool)");
 
-            let exe_path = path.join(shortcut.relative_path);
+            let exe_path = package_root.join(&shortcut.relative_path);
             let exe_path = exe_path
                 .to_str()
                 .log_expect("Unable to build shortcut metadata (exe)");
 
-            installed_files.push(create_desktop_shortcut(
+            let shortcut_file = create_desktop_shortcut(
                 &shortcut.name,
                 &shortcut.description,
                 tool_path,
@@ -89,7 +103,40 @@ impl Task for InstallDesktopShortcutTask {
                 &format!("--launcher \"{}\"", exe_path),
                 &starting_dir,
                 exe_path,
-            )?);
+                context.database.all_users,
+            )?;
+
+            // File associations belong to the shortcut in the applications menu
+            if !shortcut_file.is_empty() {
+                let icon = shortcut.icon.as_ref().map(|x| package_root.join(x));
+                native::integrate_desktop_file(
+                    &shortcut_file,
+                    icon.as_ref().map(|x| x.as_path()),
+                    &[],
+                    false,
+                )?;
+            }
+
+            installed_files.push(shortcut_file);
+        }
+
+        let written: Vec<String> = installed_files
+            .iter()
+            .filter(|x| !x.is_empty())
+            .cloned()
+            .collect();
+        if !written.is_empty() {
+            audit::record(
+                context,
+                AuditAction::FilesWritten {
+                    package: Some(self.name.clone()),
+                    files: written,
+                },
+            );
+        }
+
+        for shortcut in &installed_files {
+            context.database.record_shortcut(shortcut);
         }
 
         // Update the installed packages shortcuts information in the database
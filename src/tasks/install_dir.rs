@@ -7,6 +7,8 @@ use tasks::TaskDependency;
 use tasks::TaskMessage;
 use tasks::TaskParamType;
 
+use strings;
+
 use std::fs::create_dir_all;
 use std::fs::read_dir;
 
@@ -25,7 +27,8 @@ impl Task for VerifyInstallDirTask {
     ) -> Result<TaskParamType, String> {
         assert_eq!(input.len(), 0);
         messenger(&TaskMessage::DisplayMessage(
-            "Polling installation directory...",
+            "progress.polling_install_dir",
+            &strings::tr("progress.polling_install_dir"),
             0.0,
         ));
 
@@ -7,8 +7,12 @@ use tasks::TaskDependency;
 use tasks::TaskMessage;
 use tasks::TaskParamType;
 
+use strings;
+
 use logging::LoggingErrors;
 
+use audit::{self, AuditAction};
+
 use native::create_shortcut;
 use tasks::save_database::SaveDatabaseTask;
 use tasks::TaskOrdering;
@@ -23,7 +27,8 @@ impl Task for InstallGlobalShortcutsTask {
         messenger: &dyn Fn(&TaskMessage),
     ) -> Result<TaskParamType, String> {
         messenger(&TaskMessage::DisplayMessage(
-            "Generating global shortcut...",
+            "progress.generating_global_shortcut",
+            &strings::tr("progress.generating_global_shortcut"),
             0.0,
         ));
 
@@ -59,9 +64,19 @@ impl Task for InstallGlobalShortcutsTask {
             "",
             &starting_dir,
             "",
+            context.database.all_users,
         )?;
 
         if !shortcut_file.is_empty() {
+            audit::record(
+                context,
+                AuditAction::FilesWritten {
+                    package: None,
+                    files: vec![shortcut_file.clone()],
+                },
+            );
+
+            context.database.record_shortcut(&shortcut_file);
             context.database.shortcuts.push(shortcut_file);
         }
 
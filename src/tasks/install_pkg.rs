@@ -2,9 +2,14 @@
 
 use installer::InstallerFramework;
 
+use tasks::create_links::CreateLinksTask;
+use tasks::create_system_user::CreateSystemUserTask;
 use tasks::download_pkg::DownloadPackageTask;
 use tasks::install_shortcuts::InstallShortcutsTask;
+use tasks::run_hooks::{HookPoint, RunHooksTask};
 use tasks::save_database::SaveDatabaseTask;
+use tasks::smoke_test::SmokeTestTask;
+use tasks::transaction::Transaction;
 use tasks::uninstall_pkg::UninstallPackageTask;
 use tasks::Task;
 use tasks::TaskDependency;
@@ -12,23 +17,281 @@ use tasks::TaskMessage;
 use tasks::TaskOrdering;
 use tasks::TaskParamType;
 
-use config::PackageDescription;
-use installer::LocalInstallation;
+use config::{
+    CaseCollisionPolicy, ConflictPolicy, ExistingFilePolicy, InvalidNamePolicy, PackageDescription,
+};
+use installer::{LocalInstallation, OverwrittenFile};
 
+use std::collections::{HashMap, HashSet};
+use std::fs;
 use std::fs::create_dir_all;
-use std::io::copy;
+use std::io::{copy, sink};
 
 use logging::LoggingErrors;
 
 use archives;
+use archives::Archive;
+
+use audit::{self, AuditAction};
+
+use hashing::HashingWriter;
+
+use large_files::SparseWriter;
+
+use dedup::Deduplicator;
+
+use disk_space::SpaceMonitor;
+
+use locations;
+
+use control;
+use prompts;
+
+use strings;
 
 use std::fs::OpenOptions;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use tasks::install_desktop_shortcut::InstallDesktopShortcutTask;
 
+/// Directory (within the install directory) that replaced files are backed up into.
+const BACKUP_DIR: &str = ".backups";
+
+/// Finds archive entries which would overwrite each other on a case-insensitive file
+/// system, returning those to skip as per the policy.
+fn find_case_collisions<'a>(
+    archive: &mut dyn Archive<'a>,
+    policy: &CaseCollisionPolicy,
+) -> Result<HashSet<String>, String> {
+    let mut groups: HashMap<String, Vec<String>> = HashMap::new();
+    let mut order = Vec::new();
+
+    for name in archive.list()? {
+        let name = name
+            .to_str()
+            .ok_or("Unable to get str from file name")?
+            .replace("\\", "/");
+        let key = name.to_lowercase();
+
+        let group = groups.entry(key.clone()).or_insert_with(|| {
+            order.push(key);
+            Vec::new()
+        });
+        if !group.contains(&name) {
+            group.push(name);
+        }
+    }
+
+    let mut skipped = HashSet::new();
+    for key in order {
+        let group = &groups[&key];
+        if group.len() < 2 {
+            continue;
+        }
+
+        let kept = match *policy {
+            CaseCollisionPolicy::Error => {
+                return Err(format!(
+                    "The archive contains {:?}, which only differ by case and would \
+                     overwrite each other on this file system",
+                    group
+                ));
+            }
+            CaseCollisionPolicy::KeepFirst => &group[0],
+            CaseCollisionPolicy::KeepLast => &group[group.len() - 1],
+        };
+
+        warn!(
+            "{:?} only differ by case, only extracting {:?}",
+            group, kept
+        );
+        skipped.extend(group.iter().filter(|x| *x != kept).cloned());
+    }
+
+    Ok(skipped)
+}
+
+/// Names Windows reserves for devices, which can't be used for files even with an
+/// extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Characters which can't be used in file names on Windows, besides control characters.
+const INVALID_CHARS: [char; 7] = ['<', '>', ':', '"', '|', '?', '*'];
+
+/// Returns if the part of a file name before its extension is a reserved device name.
+fn is_reserved_name(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or("").trim_end();
+    RESERVED_NAMES.iter().any(|x| x.eq_ignore_ascii_case(stem))
+}
+
+/// Returns why a file name (a single path component) can't be used on Windows, if it
+/// can't.
+fn windows_name_problem(name: &str) -> Option<&'static str> {
+    if is_reserved_name(name) {
+        Some("is a reserved device name")
+    } else if name
+        .chars()
+        .any(|x| x.is_control() || INVALID_CHARS.contains(&x))
+    {
+        Some("contains characters which aren't allowed")
+    } else if name.ends_with('.') || name.ends_with(' ') {
+        Some("ends with a dot or space")
+    } else {
+        None
+    }
+}
+
+/// Makes a file name usable on Windows, replacing whatever isn't allowed with `_`.
+fn sanitize_windows_name(name: &str) -> String {
+    let mut name: String = name
+        .chars()
+        .map(|x| {
+            if x.is_control() || INVALID_CHARS.contains(&x) {
+                '_'
+            } else {
+                x
+            }
+        })
+        .collect();
+
+    if is_reserved_name(&name) {
+        let stem_end = name.find('.').unwrap_or_else(|| name.len());
+        name.insert(stem_end, '_');
+    }
+
+    // Windows drops trailing dots and spaces, which would then collide with other names
+    let end = name.trim_end_matches(|x| x == '.' || x == ' ').len();
+    if end < name.len() {
+        name.truncate(end);
+        name.push('_');
+    }
+
+    name
+}
+
+/// Finds archive entries whose names can't be used on Windows, returning what each is
+/// extracted as instead as per the policy (or None, if it is skipped).
+fn find_invalid_names<'a>(
+    archive: &mut dyn Archive<'a>,
+    policy: &InvalidNamePolicy,
+) -> Result<HashMap<String, Option<PathBuf>>, String> {
+    let mut invalid = HashMap::new();
+    let mut problems = Vec::new();
+
+    for name in archive.list()? {
+        let name = name
+            .to_str()
+            .ok_or("Unable to get str from file name")?
+            .replace("\\", "/");
+
+        let (component, problem) = match name
+            .split('/')
+            .find_map(|x| windows_name_problem(x).map(|problem| (x.to_string(), problem)))
+        {
+            Some(v) => v,
+            None => continue,
+        };
+
+        match *policy {
+            InvalidNamePolicy::Error => {
+                problems.push(format!("{:?} ({:?} {})", name, component, problem));
+            }
+            InvalidNamePolicy::Rename => {
+                let renamed: PathBuf = name.split('/').map(sanitize_windows_name).collect();
+                warn!(
+                    "Extracting {:?} as {:?}, as {:?} {} on Windows",
+                    name, renamed, component, problem
+                );
+                invalid.insert(name, Some(renamed));
+            }
+            InvalidNamePolicy::Skip => {
+                warn!(
+                    "Skipping {:?}, as {:?} {} on Windows",
+                    name, component, problem
+                );
+                invalid.insert(name, None);
+            }
+        }
+    }
+
+    if !problems.is_empty() {
+        return Err(format!(
+            "The archive contains names which can't be used on Windows: {}",
+            problems.join(", ")
+        ));
+    }
+
+    Ok(invalid)
+}
+
+/// Moves a file, falling back to copying it when it is on another volume.
+pub fn move_file(from: &Path, to: &Path) -> Result<(), String> {
+    if let Some(parent) = to.parent() {
+        create_dir_all(parent).map_err(|x| format!("Unable to create dir: {:?}", x))?;
+    }
+
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    fs::copy(from, to)
+        .and_then(|_| fs::remove_file(from))
+        .map(|_x| ())
+        .map_err(|x| format!("Unable to move {:?} to {:?}: {:?}", from, to, x))
+}
+
+/// Creates a symbolic link from an archive, returning if it was created. Links left by
+/// previous versions are replaced, but other files are kept.
+#[cfg(unix)]
+fn create_link(
+    transaction: &mut Transaction,
+    path: &Path,
+    target: &Path,
+    name: &str,
+) -> Result<bool, String> {
+    use std::os::unix::fs::symlink;
+
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if !metadata.file_type().is_symlink() {
+            warn!(
+                "Not creating link {:?}, as a file already exists there",
+                name
+            );
+            return Ok(false);
+        }
+
+        transaction.remove(path)?;
+    }
+
+    info!("Creating link: {:?} -> {:?}", name, target);
+    transaction.created(path);
+    symlink(target, path).map_err(|x| format!("Unable to create link {:?}: {:?}", name, x))?;
+
+    Ok(true)
+}
+
+/// Links generally need administrator rights to create on Windows, so aren't extracted.
+#[cfg(not(unix))]
+fn create_link(
+    _transaction: &mut Transaction,
+    _path: &Path,
+    target: &Path,
+    name: &str,
+) -> Result<bool, String> {
+    warn!(
+        "Skipping link {:?} -> {:?}, as links aren't supported on this platform",
+        name, target
+    );
+
+    Ok(false)
+}
+
 pub struct InstallPackageTask {
     pub name: String,
     pub create_desktop_shortcuts: bool,
+    pub extract_only: bool,
 }
 
 impl Task for InstallPackageTask {
@@ -39,14 +302,16 @@ impl Task for InstallPackageTask {
         messenger: &dyn Fn(&TaskMessage),
     ) -> Result<TaskParamType, String> {
         messenger(&TaskMessage::DisplayMessage(
-            &format!("Installing package {:?}...", self.name),
+            "progress.installing_package",
+            &strings::format("progress.installing_package", &[("package", &self.name)]),
             0.0,
         ));
 
-        let path = context
+        let install_path = context
             .install_path
-            .as_ref()
+            .clone()
             .log_expect("No install path specified");
+        let path = context.get_package_root(&self.name)?;
 
         let mut installed_files = Vec::new();
 
@@ -70,36 +335,149 @@ impl Task for InstallPackageTask {
 
         // Ignore input from the uninstaller - no useful information passed
         // If a previous task Breaks, then just early exit
-        match input.pop().log_expect("Install Package Task should have guaranteed output!") {
+        let preserved = match input
+            .pop()
+            .log_expect("Install Package Task should have guaranteed output!")
+        {
             TaskParamType::Break => return Ok(TaskParamType::None),
-            _ => (),
+            TaskParamType::PreservedFiles(v) => v,
+            _ => Vec::new(),
         };
 
         // Grab data from the resolver
         let data = input.pop().log_expect("Install Package Task should have input from resolver!");
         let (version, file, data) = match data {
             TaskParamType::FileContents(version, file, data) => (version, file, data),
+            TaskParamType::DownloadedFile(version, file, path) => {
+                let data = fs::read(&path)
+                    .map_err(|x| format!("Unable to read download {:?}: {:?}", path, x))?;
+
+                // Only the copy in memory is needed from here on
+                if let Err(v) = fs::remove_file(&path) {
+                    warn!("Unable to remove download {:?}: {:?}", path, v);
+                }
+
+                (version, file, data)
+            }
             _ => return Err("Unexpected file contents param type to install package".to_string()),
         };
 
+        let config = context
+            .config
+            .clone()
+            .log_expect("Should have packages by now");
+        let policy = config.conflict_policy.clone();
+        let backup_root = install_path.join(BACKUP_DIR).join(&package.name);
+
+        // Files provided by other packages sharing this root, and files this package provided
+        // which others have since replaced (and so are kept in their backups)
+        let mut owners: HashMap<String, String> = HashMap::new();
+        let mut owner_hashes: HashMap<String, String> = HashMap::new();
+        let mut shadowed: HashMap<String, OverwrittenFile> = HashMap::new();
+        for other in &context.database.packages {
+            if other.get_root(&install_path) != path {
+                continue;
+            }
+
+            for file in &other.files {
+                owners.insert(file.clone(), other.name.clone());
+
+                if let Some(hash) = other.hashes.get(file) {
+                    owner_hashes.insert(file.clone(), hash.clone());
+                }
+            }
+
+            for file in &other.overwritten {
+                if file.owner.as_ref() == Some(&package.name) {
+                    shadowed.insert(file.path.clone(), file.clone());
+                }
+            }
+        }
+
+        let mut overwritten = Vec::new();
+        let mut hashes = HashMap::new();
+        let mut space = SpaceMonitor::new(&path, &config.limits);
+
+        let mut dedup = if config.deduplication.enabled {
+            Some(Deduplicator::new(
+                &context.database,
+                &install_path,
+                &config.deduplication,
+            ))
+        } else {
+            None
+        };
+
+        let transaction = &mut context.transaction;
+
+        // Package roots outside the install directory may not exist yet
+        transaction.created(&path);
+        create_dir_all(&path).map_err(|x| format!("Unable to create {:?}: {:?}", path, x))?;
+
         let mut archive = archives::read_archive(&file.name, data.as_slice())?;
 
-        archive.for_each(&mut |i, archive_size, filename, mut file| {
+        let case_collisions = if locations::is_case_insensitive(&path) {
+            find_case_collisions(archive.as_mut(), &config.case_collisions)?
+        } else {
+            HashSet::new()
+        };
+
+        let invalid_names = if cfg!(windows) {
+            find_invalid_names(archive.as_mut(), &config.invalid_names)?
+        } else {
+            HashMap::new()
+        };
+
+        archive.for_each(&mut |i, archive_size, filename, entry, mut file| {
+            control::check()?;
+
             let string_name = filename
                 .to_str()
                 .ok_or("Unable to get str from file name")?
                 .to_string();
 
+            if case_collisions.contains(&string_name.replace("\\", "/")) {
+                info!(
+                    "Skipping {:?}, as it collides with another file",
+                    string_name
+                );
+                return Ok(());
+            }
+
+            let (filename, string_name) = match invalid_names.get(&string_name.replace("\\", "/")) {
+                Some(Some(renamed)) => (
+                    renamed.clone(),
+                    renamed
+                        .to_str()
+                        .ok_or("Unable to get str from file name")?
+                        .to_string(),
+                ),
+                Some(None) => return Ok(()),
+                None => (filename, string_name),
+            };
+
             match &archive_size {
                 Some(size) => {
                     messenger(&TaskMessage::DisplayMessage(
-                        &format!("Extracting {} ({} of {})", string_name, i + 1, size),
+                        "progress.extracting",
+                        &strings::format(
+                            "progress.extracting",
+                            &[
+                                ("file", &string_name),
+                                ("number", &(i + 1).to_string()),
+                                ("count", &size.to_string()),
+                            ],
+                        ),
                         (i as f64) / (*size as f64),
                     ));
                 }
                 _ => {
                     messenger(&TaskMessage::DisplayMessage(
-                        &format!("Extracting {} ({} of ??)", string_name, i + 1),
+                        "progress.extracting_unknown",
+                        &strings::format(
+                            "progress.extracting_unknown",
+                            &[("file", &string_name), ("number", &(i + 1).to_string())],
+                        ),
                         0.0,
                     ));
                 }
@@ -124,21 +502,140 @@ impl Task for InstallPackageTask {
                     installed_files.push(string_name);
                 }
 
+                transaction.created(&path.join(&parent_dir));
                 match create_dir_all(path.join(&parent_dir)) {
                     Ok(v) => v,
                     Err(v) => return Err(format!("Unable to create dir: {:?}", v)),
                 }
             }
 
+            if let Some(ref link_target) = entry.link_target {
+                if create_link(
+                    transaction,
+                    &path.join(&filename),
+                    link_target,
+                    &string_name,
+                )? && !installed_files.contains(&string_name)
+                {
+                    installed_files.push(string_name);
+                }
+                return Ok(());
+            }
+
             // Create target file
-            let target_path = path.join(&filename);
+            let mut target_path = path.join(&filename);
+            let mut compress_to = None;
 
             info!("Creating file: {:?}", string_name);
 
+            if preserved.contains(&string_name) {
+                // Keep the user's version, but remember what we would have installed so
+                // that it is still seen as modified next time
+                info!("Keeping modified file {:?}", string_name);
+                let mut hasher = HashingWriter::new(sink());
+                copy(&mut file, &mut hasher)
+                    .map_err(|x| format!("Unable to read {:?}: {:?}", string_name, x))?;
+
+                hashes.insert(string_name.clone(), hasher.finish());
+                if !installed_files.contains(&string_name) {
+                    installed_files.push(string_name);
+                }
+                return Ok(());
+            } else if let Some(backup) = shadowed.get(&string_name) {
+                // Another package takes precedence, so update the original it hides instead
+                info!("{:?} is replaced by another package, updating its backup", string_name);
+                target_path = Path::new(&backup.backup).to_owned();
+
+                if target_path.exists() {
+                    transaction.remove(&target_path)?;
+                }
+
+                // Compressed backups are written out as normal, then compressed in place
+                if backup.compressed {
+                    compress_to = Some(target_path.clone());
+                    target_path = target_path.with_extension("");
+                }
+            } else if target_path.is_file() {
+                let owner = owners.get(&string_name).cloned();
+
+                let mut existing_policy = match owner {
+                    Some(_) if policy == ConflictPolicy::Error => ExistingFilePolicy::Error,
+                    Some(_) => ExistingFilePolicy::Backup,
+                    None => config.get_existing_file_policy(&package, &string_name),
+                };
+
+                if existing_policy == ExistingFilePolicy::Prompt {
+                    let back_up = strings::tr("prompts.back_up");
+                    let overwrite = strings::tr("prompts.overwrite");
+                    let skip = strings::tr("prompts.skip");
+
+                    let answer = prompts::ask(
+                        messenger,
+                        &strings::format("prompts.file_exists", &[("file", &string_name)]),
+                        &[&back_up, &overwrite, &skip],
+                        &back_up,
+                    );
+
+                    existing_policy = if answer == overwrite {
+                        ExistingFilePolicy::Overwrite
+                    } else if answer == skip {
+                        ExistingFilePolicy::Skip
+                    } else {
+                        ExistingFilePolicy::Backup
+                    };
+                }
+
+                let description = match owner {
+                    Some(ref v) => format!("a file from package {:?}", v),
+                    None => "an existing file".to_string(),
+                };
+
+                match existing_policy {
+                    ExistingFilePolicy::Error | ExistingFilePolicy::Prompt => {
+                        return Err(format!("{:?} conflicts with {}", string_name, description));
+                    }
+                    ExistingFilePolicy::Skip => {
+                        info!("Keeping existing file {:?}", string_name);
+                        return Ok(());
+                    }
+                    ExistingFilePolicy::Overwrite => {
+                        info!("Overwriting existing file {:?}", string_name);
+                        transaction.remove(&target_path)?;
+                    }
+                    ExistingFilePolicy::Backup => {
+                        let backup = backup_root.join(&filename);
+                        warn!(
+                            "{:?} replaces {}, backing it up to {:?}",
+                            string_name, description, backup
+                        );
+
+                        let (backup, compressed) = transaction.back_up(&target_path, &backup)?;
+
+                        if let (Some(dedup), Some(hash), false) =
+                            (dedup.as_mut(), owner_hashes.get(&string_name), compressed)
+                        {
+                            dedup.add(hash, backup.clone());
+                        }
+
+                        overwritten.push(OverwrittenFile {
+                            path: string_name.clone(),
+                            backup: backup
+                                .to_str()
+                                .ok_or("Unable to get str from backup path")?
+                                .to_string(),
+                            compressed,
+                            owner,
+                        });
+                    }
+                }
+            }
+
             if !installed_files.contains(&string_name) {
                 installed_files.push(string_name.to_string());
             }
 
+            space.wait(entry.size.unwrap_or(0), messenger)?;
+
             let mut file_metadata = OpenOptions::new();
             file_metadata.write(true).create_new(true);
 
@@ -146,38 +643,88 @@ impl Task for InstallPackageTask {
             {
                 use std::os::unix::fs::OpenOptionsExt;
 
-                file_metadata.mode(0o770);
+                file_metadata.mode(entry.mode.unwrap_or(0o770));
             }
 
-            let mut target_file = match file_metadata.open(target_path) {
+            transaction.created(&target_path);
+            let target_file = match file_metadata.open(&target_path) {
                 Ok(v) => v,
                 Err(v) => return Err(format!("Unable to open file handle: {:?}", v)),
             };
 
             // Cross the streams
-            match copy(&mut file, &mut target_file) {
+            let mut target_file = SparseWriter::new(target_file, entry, &config.large_files);
+            let mut hasher = HashingWriter::new(&mut target_file);
+            match copy(&mut file, &mut hasher) {
                 Ok(v) => v,
                 Err(v) => return Err(format!("Unable to write to file: {:?}", v)),
             };
 
+            let hash = hasher.finish();
+            target_file.finish()?;
+
+            if let Some(compress_to) = compress_to {
+                transaction.compress(&target_path, &compress_to)?;
+            } else if let Some(ref mut dedup) = dedup {
+                dedup.link(&target_path, &hash);
+            }
+
+            hashes.insert(string_name, hash);
+
             Ok(())
         })?;
 
+        if let Some(saved) = dedup.map(|x| x.saved()).filter(|x| *x > 0) {
+            info!("Saved {} bytes by linking to identical files", saved);
+        }
+
         // Save metadata about this package
+        let root = if path != install_path {
+            Some(
+                path.to_str()
+                    .ok_or("Unable to get str from package root")?
+                    .to_string(),
+            )
+        } else {
+            None
+        };
+
+        if root.is_some() {
+            audit::record(
+                context,
+                AuditAction::FilesWritten {
+                    package: Some(package.name.clone()),
+                    files: installed_files
+                        .iter()
+                        .map(|x| path.join(x).to_string_lossy().to_string())
+                        .collect(),
+                },
+            );
+        }
+
         context.database.packages.push(LocalInstallation {
             name: package.name.to_owned(),
             version,
             shortcuts: Vec::new(),
             files: installed_files,
+            root,
+            overwritten,
+            hashes,
+            requires: package.requires.clone(),
+            suspect: None,
+            pre_uninstall: package.hooks.pre_uninstall.clone(),
         });
 
+        context.transaction.sync();
+        context.pending_hooks.insert(package.name.clone());
+        context.pending_smoke_tests.insert(package.name.clone());
         messenger(&TaskMessage::PackageInstalled);
 
         Ok(TaskParamType::None)
     }
 
     fn dependencies(&self) -> Vec<TaskDependency> {
-        vec![
+        let mut elements = vec![
             TaskDependency::build(
                 TaskOrdering::Pre,
                 Box::new(DownloadPackageTask {
@@ -191,21 +738,58 @@ impl Task for InstallPackageTask {
                     optional: true,
                 }),
             ),
-            TaskDependency::build(
-                TaskOrdering::Post,
-                Box::new(InstallShortcutsTask {
-                    name: self.name.clone(),
-                }),
-            ),
-            TaskDependency::build(
-                TaskOrdering::Post,
-                Box::new(InstallDesktopShortcutTask {
-                    name: self.name.clone(),
-                    should_run: self.create_desktop_shortcuts
-                }),
-            ),
-            TaskDependency::build(TaskOrdering::Post, Box::new(SaveDatabaseTask {})),
-        ]
+        ];
+
+        // Extracted payloads aren't integrated with the system at all
+        if self.extract_only {
+            return elements;
+        }
+
+        elements.push(TaskDependency::build(
+            TaskOrdering::Post,
+            Box::new(InstallShortcutsTask {
+                name: self.name.clone(),
+            }),
+        ));
+        elements.push(TaskDependency::build(
+            TaskOrdering::Post,
+            Box::new(InstallDesktopShortcutTask {
+                name: self.name.clone(),
+                should_run: self.create_desktop_shortcuts,
+            }),
+        ));
+        elements.push(TaskDependency::build(
+            TaskOrdering::Post,
+            Box::new(CreateLinksTask {
+                name: self.name.clone(),
+            }),
+        ));
+        elements.push(TaskDependency::build(
+            TaskOrdering::Post,
+            Box::new(CreateSystemUserTask {
+                name: self.name.clone(),
+            }),
+        ));
+        elements.push(TaskDependency::build(
+            TaskOrdering::Post,
+            Box::new(RunHooksTask {
+                name: self.name.clone(),
+                point: HookPoint::PostInstall,
+                should_run: true,
+            }),
+        ));
+        elements.push(TaskDependency::build(
+            TaskOrdering::Post,
+            Box::new(SmokeTestTask {
+                name: self.name.clone(),
+            }),
+        ));
+        elements.push(TaskDependency::build(
+            TaskOrdering::Post,
+            Box::new(SaveDatabaseTask {}),
+        ));
+
+        elements
     }
 
     fn name(&self) -> String {
@@ -11,7 +11,11 @@ use config::PackageDescription;
 
 use logging::LoggingErrors;
 
-use native::create_shortcut;
+use audit::{self, AuditAction};
+
+use native::{self, create_shortcut};
+
+use strings;
 
 pub struct InstallShortcutsTask {
     pub name: String,
@@ -24,8 +28,17 @@ impl Task for InstallShortcutsTask {
         context: &mut InstallerFramework,
         messenger: &dyn Fn(&TaskMessage),
     ) -> Result<TaskParamType, String> {
+        let enabled = context
+            .config
+            .as_ref()
+            .map_or(true, |x| x.shortcuts.start_menu);
+        if !enabled {
+            return Ok(TaskParamType::GeneratedShortcuts(Vec::new()));
+        }
+
         messenger(&TaskMessage::DisplayMessage(
-            &format!("Generating shortcuts for package {:?}...", self.name),
+            "progress.generating_shortcuts",
+            &strings::format("progress.generating_shortcuts", &[("package", &self.name)]),
             0.0,
         ));
 
@@ -58,6 +71,8 @@ impl Task for InstallShortcutsTask {
             None => return Err(format!("Package {:?} could not be found.", self.name)),
         };
 
+        let package_root = context.get_package_root(&self.name)?;
+
         // Generate installer path
         let platform_extension = if cfg!(windows) {
             "maintenancetool.exe"
@@ -71,12 +86,12 @@ impl Task for InstallShortcutsTask {
                 .to_str()
                 .log_expect("Unable to build shortcut metadata (tool)");
 
-            let exe_path = path.join(shortcut.relative_path);
+            let exe_path = package_root.join(&shortcut.relative_path);
             let exe_path = exe_path
                 .to_str()
                 .log_expect("Unable to build shortcut metadata (exe)");
 
-            installed_files.push(create_shortcut(
+            let shortcut_file = create_shortcut(
                 &shortcut.name,
                 &shortcut.description,
                 tool_path,
@@ -84,7 +99,43 @@ impl Task for InstallShortcutsTask {
                 &format!("--launcher \"{}\"", exe_path),
                 &starting_dir,
                 exe_path,
-            )?);
+                context.database.all_users,
+            )?;
+
+            if !shortcut_file.is_empty() {
+                let icon = shortcut.icon.as_ref().map(|x| package_root.join(x));
+                native::integrate_desktop_file(
+                    &shortcut_file,
+                    icon.as_ref().map(|x| x.as_path()),
+                    &shortcut.mime_types,
+                    shortcut.default_handler && !context.database.all_users,
+                )?;
+            }
+
+            installed_files.push(shortcut_file);
+        }
+
+        if !installed_files.is_empty() {
+            native::refresh_desktop_caches(context.database.all_users);
+        }
+
+        let written: Vec<String> = installed_files
+            .iter()
+            .filter(|x| !x.is_empty())
+            .cloned()
+            .collect();
+        if !written.is_empty() {
+            audit::record(
+                context,
+                AuditAction::FilesWritten {
+                    package: Some(self.name.clone()),
+                    files: written,
+                },
+            );
+        }
+
+        for shortcut in &installed_files {
+            context.database.record_shortcut(shortcut);
         }
 
         // Update the installed packages shortcuts information in the database
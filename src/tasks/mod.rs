@@ -3,13 +3,25 @@
 
 use std::fmt;
 use std::fmt::Display;
+use std::path::PathBuf;
 
 use installer::InstallerFramework;
 
+use events::{self, Event};
+
+use control;
+
+use heartbeat;
+
 use sources::types::File;
 use sources::types::Version;
 
+use tasks::download_packages::DownloadProgress;
+
 pub mod check_authorization;
+pub mod create_links;
+pub mod create_system_user;
+pub mod download_packages;
 pub mod download_pkg;
 pub mod ensure_only_instance;
 pub mod install;
@@ -19,13 +31,23 @@ pub mod install_global_shortcut;
 pub mod install_pkg;
 pub mod install_shortcuts;
 pub mod launch_installed_on_exit;
+pub mod register_uninstaller;
+pub mod remove_links;
+pub mod remove_system_user;
+pub mod repair;
+pub mod repair_files;
 pub mod resolver;
+pub mod run_hooks;
 pub mod save_database;
 pub mod save_executable;
+pub mod smoke_test;
+pub mod transaction;
 pub mod uninstall;
 pub mod uninstall_global_shortcut;
 pub mod uninstall_pkg;
 pub mod uninstall_shortcuts;
+pub mod unregister_uninstaller;
+pub mod update_tool;
 
 /// An abstraction over the various parameters that can be passed around.
 pub enum TaskParamType {
@@ -36,8 +58,12 @@ pub enum TaskParamType {
     Authentication(Version, File, Option<String>),
     /// Downloaded contents of a file
     FileContents(Version, File, Vec<u8>),
+    /// A file which has been downloaded to disk, to be removed once it is installed
+    DownloadedFile(Version, File, PathBuf),
     /// List of shortcuts that have been generated
     GeneratedShortcuts(Vec<String>),
+    /// Files which were kept during an update, as the user had modified them
+    PreservedFiles(Vec<String>),
     /// Tells the runtime to break parsing other dependencies
     Break,
 }
@@ -66,9 +92,19 @@ impl TaskDependency {
 
 /// A message from a task.
 pub enum TaskMessage<'a> {
-    DisplayMessage(&'a str, f64),
+    /// The id of a progress message in `strings` (which phases are told apart by), the
+    /// message itself, and how far through the task is.
+    DisplayMessage(&'a str, &'a str, f64),
     AuthorizationRequired(&'a str),
+    /// Progress of each package being downloaded at once
+    Downloads(&'a [DownloadProgress]),
     PackageInstalled,
+    /// A question for the user, along with the possible answers.
+    Prompt(&'a str, &'a [&'a str]),
+    /// A question answered by choosing a directory, along with the suggested one.
+    DirectoryPrompt(&'a str, &'a str),
+    /// A question answered by typing in some text, along with the suggested answer.
+    TextPrompt(&'a str, &'a str),
 }
 
 /// A Task is a small, async task conforming to a fixed set of inputs/outputs.
@@ -122,6 +158,39 @@ impl DependencyTree {
         buf
     }
 
+    /// Executes this pipeline as the current operation, which may be paused by the frontend
+    /// until it finishes. It can only be cancelled if it is running within a transaction.
+    pub fn run(
+        &mut self,
+        context: &mut InstallerFramework,
+        messenger: &dyn Fn(&TaskMessage),
+    ) -> Result<TaskParamType, String> {
+        let stall_timeout = context
+            .config
+            .as_ref()
+            .map(|x| x.limits.clone())
+            .unwrap_or_default()
+            .stall_timeout;
+
+        control::reset();
+        control::set_cancellable(context.transaction.is_active());
+        heartbeat::start(stall_timeout);
+
+        // Every progress message shows that the running task is still getting somewhere
+        let result = self.execute(context, &|msg: &TaskMessage| {
+            if let TaskMessage::DisplayMessage(_, msg, _) = *msg {
+                heartbeat::progress(msg);
+            }
+            messenger(msg)
+        });
+
+        heartbeat::stop();
+        control::reset();
+        control::set_cancellable(false);
+
+        result
+    }
+
     /// Executes this pipeline.
     pub fn execute(
         &mut self,
@@ -139,9 +208,12 @@ impl DependencyTree {
                 continue;
             }
 
+            control::check()?;
+
             let result = i.execute(context, &|msg: &TaskMessage| match *msg {
-                TaskMessage::DisplayMessage(msg, progress) => {
+                TaskMessage::DisplayMessage(id, msg, progress) => {
                     messenger(&TaskMessage::DisplayMessage(
+                        id,
                         msg,
                         progress / total_tasks + (1.0 / total_tasks) * f64::from(count),
                     ))
@@ -163,17 +235,35 @@ impl DependencyTree {
             }
         }
 
+        control::check()?;
+
+        let name = self.task.name();
+        events::publish(Event::TaskStarted { task: name.clone() });
+        heartbeat::task_started(&name);
+
         let task_result = self
             .task
             .execute(inputs, context, &|msg: &TaskMessage| match *msg {
-                TaskMessage::DisplayMessage(msg, progress) => {
+                TaskMessage::DisplayMessage(id, msg, progress) => {
                     messenger(&TaskMessage::DisplayMessage(
+                        id,
                         msg,
                         progress / total_tasks + (1.0 / total_tasks) * f64::from(count),
                     ))
                 }
                 _ => messenger(msg),
-            })?;
+            });
+
+        if let Err(ref v) = task_result {
+            context.record_task_error(&name, v);
+        }
+
+        events::publish(Event::TaskFinished {
+            task: name,
+            error: task_result.as_ref().err().cloned(),
+        });
+
+        let task_result = task_result?;
 
         if let TaskParamType::Break = task_result {
             // We are done here
@@ -185,9 +275,12 @@ impl DependencyTree {
                 continue;
             }
 
+            control::check()?;
+
             let result = i.execute(context, &|msg: &TaskMessage| match *msg {
-                TaskMessage::DisplayMessage(msg, progress) => {
+                TaskMessage::DisplayMessage(id, msg, progress) => {
                     messenger(&TaskMessage::DisplayMessage(
+                        id,
                         msg,
                         progress / total_tasks + (1.0 / total_tasks) * f64::from(count),
                     ))
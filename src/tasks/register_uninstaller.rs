@@ -0,0 +1,139 @@
+//! Registers the maintenance tool with Windows' "Apps & Features", so that the application
+//! can be uninstalled like any other.
+
+use installer::InstallerFramework;
+
+use tasks::Task;
+use tasks::TaskDependency;
+use tasks::TaskMessage;
+use tasks::TaskParamType;
+
+use strings;
+
+use audit::{self, AuditAction};
+
+use native::{self, RegistryValue};
+
+use logging::LoggingErrors;
+
+/// Returns the registry key describing this application to "Apps & Features".
+pub fn uninstall_key(context: &InstallerFramework) -> String {
+    let root = if context.database.all_users {
+        "HKLM"
+    } else {
+        "HKCU"
+    };
+
+    format!(
+        "{}\\Software\\Microsoft\\Windows\\CurrentVersion\\Uninstall\\{}",
+        root, context.base_attributes.name
+    )
+}
+
+pub struct RegisterUninstallerTask {}
+
+impl Task for RegisterUninstallerTask {
+    fn execute(
+        &mut self,
+        _: Vec<TaskParamType>,
+        context: &mut InstallerFramework,
+        messenger: &dyn Fn(&TaskMessage),
+    ) -> Result<TaskParamType, String> {
+        if !cfg!(windows) {
+            return Ok(TaskParamType::None);
+        }
+
+        messenger(&TaskMessage::DisplayMessage(
+            "progress.registering_uninstaller",
+            &strings::tr("progress.registering_uninstaller"),
+            0.0,
+        ));
+
+        let path = context
+            .install_path
+            .as_ref()
+            .log_expect("No install path specified");
+
+        let tool_path = path.join("maintenancetool.exe");
+        let tool_path = tool_path
+            .to_str()
+            .log_expect("Unable to build uninstaller metadata (tool)");
+
+        // Sizes are given in KiB
+        let size: u64 = context
+            .database
+            .packages
+            .iter()
+            .flat_map(|x| {
+                let root = x.get_root(path);
+                x.files.iter().map(move |file| root.join(file))
+            })
+            .filter_map(|x| x.symlink_metadata().ok())
+            .filter(|x| x.is_file())
+            .map(|x| x.len())
+            .sum();
+
+        let mut values = vec![
+            (
+                "DisplayName",
+                RegistryValue::String(context.base_attributes.name.clone()),
+            ),
+            (
+                "InstallLocation",
+                RegistryValue::String(path.to_string_lossy().to_string()),
+            ),
+            (
+                "UninstallString",
+                RegistryValue::String(format!("\"{}\"", tool_path)),
+            ),
+            (
+                "QuietUninstallString",
+                RegistryValue::String(format!("\"{}\" uninstall", tool_path)),
+            ),
+            ("DisplayIcon", RegistryValue::String(tool_path.to_string())),
+            (
+                "EstimatedSize",
+                RegistryValue::Dword((size / 1024).min(u64::from(u32::max_value())) as u32),
+            ),
+            ("NoRepair", RegistryValue::Dword(1)),
+        ];
+
+        // The first package is the application itself, rather than an addon
+        if let Some(package) = context.database.packages.first() {
+            values.push((
+                "DisplayVersion",
+                RegistryValue::String(package.version.to_string()),
+            ));
+        }
+
+        // The MSI shows its own entry, which runs the maintenance tool when removed
+        if context.database.msi_product_code.is_some() {
+            values.push(("SystemComponent", RegistryValue::Dword(1)));
+        }
+
+        let key = uninstall_key(context);
+        info!("Registering uninstaller at {:?}", key);
+
+        // Not appearing in "Apps & Features" doesn't break the installation
+        match native::write_registry_values(&key, &values) {
+            Ok(_) => audit::record(
+                context,
+                AuditAction::RegistryWritten {
+                    key,
+                    values: values.iter().map(|(name, _)| name.to_string()).collect(),
+                },
+            ),
+            Err(v) => warn!("Unable to register uninstaller: {}", v),
+        }
+
+        Ok(TaskParamType::None)
+    }
+
+    fn dependencies(&self) -> Vec<TaskDependency> {
+        vec![]
+    }
+
+    fn name(&self) -> String {
+        "RegisterUninstallerTask".to_string()
+    }
+}
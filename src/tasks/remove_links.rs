@@ -0,0 +1,107 @@
+//! Removes directory links which were created for a package. Whatever they point to is
+//! left in place, as it may hold the user's data.
+
+use installer::DirectoryLink;
+use installer::InstallerFramework;
+
+use tasks::Task;
+use tasks::TaskDependency;
+use tasks::TaskMessage;
+use tasks::TaskParamType;
+
+use audit::{self, AuditAction};
+
+use strings;
+
+use std::fs;
+use std::path::Path;
+
+pub struct RemoveLinksTask {
+    pub name: String,
+    pub should_run: bool,
+}
+
+/// Removes a single link, if it is still there. Failures are logged, but aren't fatal.
+/// Links which couldn't be removed are kept in the database, so returns if it is gone.
+pub fn remove_link(context: &mut InstallerFramework, link: &DirectoryLink) -> bool {
+    let path = Path::new(&link.path);
+
+    // Something else may have been put in the link's place since
+    let is_link = fs::symlink_metadata(path).map_or(false, |x| x.file_type().is_symlink());
+    if !is_link {
+        info!("{:?} is no longer a link, so is being left alone", path);
+        return true;
+    }
+
+    info!("Removing link {:?} to {:?}", path, link.target);
+
+    match context.transaction.remove_directory_link(path) {
+        Ok(_) => {
+            audit::record(
+                context,
+                AuditAction::LinkRemoved {
+                    path: link.path.clone(),
+                    target: link.target.clone(),
+                },
+            );
+            true
+        }
+        Err(v) => {
+            warn!("Failed to remove link: {}", v);
+            false
+        }
+    }
+}
+
+impl Task for RemoveLinksTask {
+    fn execute(
+        &mut self,
+        _: Vec<TaskParamType>,
+        context: &mut InstallerFramework,
+        messenger: &dyn Fn(&TaskMessage),
+    ) -> Result<TaskParamType, String> {
+        // Updates reinstall the package, and links (along with where the user chose for
+        // them to point) should survive this
+        if !self.should_run {
+            return Ok(TaskParamType::None);
+        }
+
+        context.check_metadata()?;
+
+        let (links, remaining) = context
+            .database
+            .links
+            .drain(..)
+            .partition::<Vec<_>, _>(|x| x.package == self.name);
+        context.database.links = remaining;
+
+        if links.is_empty() {
+            return Ok(TaskParamType::None);
+        }
+
+        messenger(&TaskMessage::DisplayMessage(
+            "progress.removing_links",
+            &strings::format("progress.removing_links", &[("package", &self.name)]),
+            0.0,
+        ));
+
+        for link in links {
+            if !remove_link(context, &link) {
+                context.database.links.push(link);
+            }
+        }
+
+        Ok(TaskParamType::None)
+    }
+
+    fn dependencies(&self) -> Vec<TaskDependency> {
+        vec![]
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "RemoveLinksTask (for {:?}, should_run = {:?})",
+            self.name, self.should_run
+        )
+    }
+}
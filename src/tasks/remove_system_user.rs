@@ -0,0 +1,96 @@
+//! Removes system users/groups which were created for a package, if configured to do so.
+
+use installer::InstallerFramework;
+
+use std::env;
+
+use tasks::create_system_user::run_account_command;
+use tasks::Task;
+use tasks::TaskDependency;
+use tasks::TaskMessage;
+use tasks::TaskParamType;
+
+use strings;
+
+use audit::{self, AuditAction};
+
+pub struct RemoveSystemUserTask {
+    pub name: String,
+    pub should_run: bool,
+}
+
+impl Task for RemoveSystemUserTask {
+    fn execute(
+        &mut self,
+        _: Vec<TaskParamType>,
+        context: &mut InstallerFramework,
+        messenger: &dyn Fn(&TaskMessage),
+    ) -> Result<TaskParamType, String> {
+        // Updates reinstall the package, and the account should survive this
+        if !self.should_run {
+            return Ok(TaskParamType::None);
+        }
+
+        context.check_metadata()?;
+
+        let (mut accounts, remaining) = context
+            .database
+            .system_accounts
+            .drain(..)
+            .partition::<Vec<_>, _>(|x| x.package == self.name);
+        context.database.system_accounts = remaining;
+
+        let limits = context
+            .config
+            .as_ref()
+            .map(|x| x.limits.clone())
+            .unwrap_or_default();
+        let working_dir = context.install_path.clone().unwrap_or_else(env::temp_dir);
+
+        // Users need to be removed before their groups
+        accounts.sort_by_key(|x| x.is_group);
+
+        for account in accounts {
+            if !account.remove_on_uninstall {
+                info!("Preserving system account {:?}", account.name);
+                continue;
+            }
+
+            messenger(&TaskMessage::DisplayMessage(
+                "progress.removing_system_user",
+                &strings::format("progress.removing_system_user", &[("user", &account.name)]),
+                0.0,
+            ));
+
+            let program = if account.is_group {
+                "groupdel"
+            } else {
+                "userdel"
+            };
+
+            match run_account_command(program, &[&account.name], &working_dir, &limits) {
+                Ok(_) => audit::record(
+                    context,
+                    AuditAction::AccountRemoved {
+                        name: account.name,
+                        is_group: account.is_group,
+                    },
+                ),
+                Err(v) => warn!("Failed to remove system account: {}", v),
+            }
+        }
+
+        Ok(TaskParamType::None)
+    }
+
+    fn dependencies(&self) -> Vec<TaskDependency> {
+        vec![]
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "RemoveSystemUserTask (for {:?}, should_run = {:?})",
+            self.name, self.should_run
+        )
+    }
+}
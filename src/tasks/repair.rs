@@ -0,0 +1,105 @@
+//! Recreates things recorded in the installation metadata which have gone missing:
+//! shortcuts (and the file associations which belong to them), directory links, system
+//! users and the entry in "Apps & Features".
+
+use installer::InstallerFramework;
+
+use tasks::create_links::CreateLinksTask;
+use tasks::create_system_user::CreateSystemUserTask;
+use tasks::install_desktop_shortcut::InstallDesktopShortcutTask;
+use tasks::install_global_shortcut::InstallGlobalShortcutsTask;
+use tasks::install_shortcuts::InstallShortcutsTask;
+use tasks::register_uninstaller::RegisterUninstallerTask;
+use tasks::save_database::SaveDatabaseTask;
+
+use tasks::Task;
+use tasks::TaskDependency;
+use tasks::TaskMessage;
+use tasks::TaskOrdering;
+use tasks::TaskParamType;
+
+use strings;
+
+pub struct RepairTask {
+    /// Packages to recreate the shortcuts of, and if they had desktop shortcuts
+    pub shortcuts: Vec<(String, bool)>,
+    /// If the maintenance tool's shortcut should be recreated
+    pub global_shortcut: bool,
+    /// Packages to recreate the directory links and system users of. Both are left as
+    /// they are if they still exist.
+    pub integrations: Vec<String>,
+    /// If the entry in "Apps & Features" should be written again
+    pub register_uninstaller: bool,
+}
+
+impl Task for RepairTask {
+    fn execute(
+        &mut self,
+        _: Vec<TaskParamType>,
+        _: &mut InstallerFramework,
+        messenger: &dyn Fn(&TaskMessage),
+    ) -> Result<TaskParamType, String> {
+        messenger(&TaskMessage::DisplayMessage(
+            "progress.wrapping_up",
+            &strings::tr("progress.wrapping_up"),
+            0.0,
+        ));
+        Ok(TaskParamType::None)
+    }
+
+    fn dependencies(&self) -> Vec<TaskDependency> {
+        let mut elements = Vec::new();
+
+        for (name, desktop_shortcuts) in &self.shortcuts {
+            elements.push(TaskDependency::build(
+                TaskOrdering::Pre,
+                Box::new(InstallShortcutsTask { name: name.clone() }),
+            ));
+
+            elements.push(TaskDependency::build(
+                TaskOrdering::Pre,
+                Box::new(InstallDesktopShortcutTask {
+                    name: name.clone(),
+                    should_run: *desktop_shortcuts,
+                }),
+            ));
+        }
+
+        for name in &self.integrations {
+            elements.push(TaskDependency::build(
+                TaskOrdering::Pre,
+                Box::new(CreateLinksTask { name: name.clone() }),
+            ));
+
+            elements.push(TaskDependency::build(
+                TaskOrdering::Pre,
+                Box::new(CreateSystemUserTask { name: name.clone() }),
+            ));
+        }
+
+        if self.global_shortcut {
+            elements.push(TaskDependency::build(
+                TaskOrdering::Pre,
+                Box::new(InstallGlobalShortcutsTask {}),
+            ));
+        }
+
+        if self.register_uninstaller {
+            elements.push(TaskDependency::build(
+                TaskOrdering::Pre,
+                Box::new(RegisterUninstallerTask {}),
+            ));
+        }
+
+        elements.push(TaskDependency::build(
+            TaskOrdering::Post,
+            Box::new(SaveDatabaseTask {}),
+        ));
+
+        elements
+    }
+
+    fn name(&self) -> String {
+        "RepairTask".to_string()
+    }
+}
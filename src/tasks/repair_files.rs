@@ -0,0 +1,208 @@
+//! Checks installed files against the hashes recorded when they were installed. Damaged
+//! (or missing) files are fetched again by themselves where the release has a chunk index,
+//! so that repairing a large installation doesn't mean downloading all of it again.
+//! Packages which can't be repaired like this are marked as suspect, so that repairing
+//! installs them again.
+
+use std::path::{Path, PathBuf};
+
+use installer::InstallerFramework;
+
+use tasks::resolver::ResolvePackageTask;
+use tasks::Task;
+use tasks::TaskDependency;
+use tasks::TaskMessage;
+use tasks::TaskParamType;
+
+use audit::{self, AuditAction};
+
+use control;
+
+use differential;
+
+use hashing::sha256_file;
+
+use strings;
+
+pub struct RepairFilesTask {}
+
+/// Returns the files of a package which no longer match what was installed, as (path,
+/// expected SHA-256) pairs.
+fn find_damaged(root: &Path, hashes: &[(String, String)]) -> Vec<(String, String)> {
+    let mut damaged = Vec::new();
+
+    for (file, expected) in hashes {
+        let path = root.join(file);
+        match sha256_file(&path) {
+            Ok(ref v) if v == expected => {}
+            Ok(_) => {
+                info!("{:?} has been damaged", path);
+                damaged.push((file.clone(), expected.clone()));
+            }
+            Err(v) => {
+                info!("{:?} is unreadable: {}", path, v);
+                damaged.push((file.clone(), expected.clone()));
+            }
+        }
+    }
+
+    damaged
+}
+
+/// Fetches damaged files of a package again, by themselves.
+fn repair_package(
+    context: &mut InstallerFramework,
+    name: &str,
+    root: &Path,
+    damaged: &[(String, String)],
+    messenger: &dyn Fn(&TaskMessage),
+) -> Result<(), String> {
+    let installed = context
+        .database
+        .packages
+        .iter()
+        .find(|x| x.name == name)
+        .map(|x| x.version.clone())
+        .ok_or_else(|| format!("{:?} is not installed", name))?;
+
+    let mut resolver = ResolvePackageTask {
+        name: name.to_string(),
+    };
+    let (version, file) = match resolver.execute(vec![], context, messenger)? {
+        TaskParamType::File(version, file) => (version, file),
+        _ => return Err("Unexpected param type from resolver".to_string()),
+    };
+
+    // Files are checked against the hashes recorded when they were installed (after any
+    // verification), so the release has to be the installed one
+    if version != installed {
+        return Err(format!(
+            "A newer version of {:?} has been released since it was installed",
+            name
+        ));
+    }
+    if file.requires_authorization {
+        return Err("The release requires authorization".to_string());
+    }
+    let index_url = file
+        .chunk_index
+        .as_ref()
+        .ok_or("The release has no chunk index")?;
+
+    let count = damaged.len().to_string();
+    differential::repair_files(index_url, None, root, damaged, |downloaded, total| {
+        let percentage = if total == 0 {
+            1.0
+        } else {
+            (downloaded as f64) / (total as f64)
+        };
+
+        messenger(&TaskMessage::DisplayMessage(
+            "progress.repairing_files",
+            &strings::format(
+                "progress.repairing_files",
+                &[
+                    ("package", name),
+                    ("count", &count),
+                    ("downloaded", &downloaded.to_string()),
+                    ("total", &total.to_string()),
+                ],
+            ),
+            percentage,
+        ));
+    })
+}
+
+impl Task for RepairFilesTask {
+    fn execute(
+        &mut self,
+        _: Vec<TaskParamType>,
+        context: &mut InstallerFramework,
+        messenger: &dyn Fn(&TaskMessage),
+    ) -> Result<TaskParamType, String> {
+        let install_path = context
+            .install_path
+            .clone()
+            .ok_or("No install path specified")?;
+
+        // Packages which are already suspect are installed again regardless
+        let packages: Vec<(String, PathBuf, Vec<(String, String)>)> = context
+            .database
+            .packages
+            .iter()
+            .filter(|x| x.suspect.is_none())
+            .map(|x| {
+                (
+                    x.name.clone(),
+                    x.get_root(&install_path),
+                    x.files
+                        .iter()
+                        .filter_map(|file| x.hashes.get(file).map(|v| (file.clone(), v.clone())))
+                        .collect(),
+                )
+            })
+            .collect();
+
+        let count = packages.len();
+        for (i, (name, root, hashes)) in packages.into_iter().enumerate() {
+            control::check()?;
+
+            messenger(&TaskMessage::DisplayMessage(
+                "progress.checking_files",
+                &strings::format("progress.checking_files", &[("package", &name)]),
+                i as f64 / count as f64,
+            ));
+
+            let damaged = find_damaged(&root, &hashes);
+            if damaged.is_empty() {
+                continue;
+            }
+
+            info!(
+                "{} files of {:?} have been damaged, repairing them",
+                damaged.len(),
+                name
+            );
+
+            match repair_package(context, &name, &root, &damaged, messenger) {
+                Ok(_) => audit::record(
+                    context,
+                    AuditAction::FilesWritten {
+                        package: Some(name.clone()),
+                        files: damaged
+                            .iter()
+                            .map(|x| root.join(&x.0).to_string_lossy().to_string())
+                            .collect(),
+                    },
+                ),
+                Err(v) => {
+                    warn!(
+                        "Unable to repair the files of {:?} by themselves, so it will be \
+                         installed again: {}",
+                        name, v
+                    );
+
+                    if let Some(package) = context
+                        .database
+                        .packages
+                        .iter_mut()
+                        .find(|x| x.name == name)
+                    {
+                        package.suspect =
+                            Some(format!("{} files have been damaged", damaged.len()));
+                    }
+                }
+            }
+        }
+
+        Ok(TaskParamType::None)
+    }
+
+    fn dependencies(&self) -> Vec<TaskDependency> {
+        vec![]
+    }
+
+    fn name(&self) -> String {
+        "RepairFilesTask".to_string()
+    }
+}
@@ -9,16 +9,219 @@ use tasks::TaskDependency;
 use tasks::TaskMessage;
 use tasks::TaskParamType;
 
-use config::PackageDescription;
+use config::{AssetSelection, PackageDescription, PackageSource, ReleaseChannel, UrlRewrite};
 
 use regex::Regex;
 
+use archives::multipart;
+
+use sources::types::{File, Release, Version};
+
+use source_auth;
+
+use strings;
+
+use verification;
+
 use logging::LoggingErrors;
 
 pub struct ResolvePackageTask {
     pub name: String,
 }
 
+/// Decides which files of a release can be installed, and which is preferred.
+pub struct AssetSelector<'a> {
+    regex: Regex,
+    prefer: Vec<Regex>,
+    selection: &'a AssetSelection,
+}
+
+/// Compiles a regex from the config, filling in the current platform.
+fn compile_pattern(pattern: &str) -> Result<Regex, String> {
+    Regex::new(&pattern.replace("#PLATFORM#", OS))
+        .map_err(|x| format!("An error occurred while compiling regex: {:?}", x))
+}
+
+impl<'a> AssetSelector<'a> {
+    pub fn new(source: &'a PackageSource) -> Result<Self, String> {
+        Ok(AssetSelector {
+            regex: compile_pattern(&source.match_regex)?,
+            prefer: source
+                .select
+                .prefer
+                .iter()
+                .map(|x| compile_pattern(x))
+                .collect::<Result<_, _>>()?,
+            selection: &source.select,
+        })
+    }
+
+    /// Checks a file against the name regex and any bounds, which are skipped where the
+    /// source didn't report what they apply to.
+    pub fn accepts(&self, file: &File) -> bool {
+        if !self.regex.is_match(&file.name) {
+            return false;
+        }
+
+        if let Some(size) = file.size {
+            if self.selection.min_size.map_or(false, |x| size < x)
+                || self.selection.max_size.map_or(false, |x| size > x)
+            {
+                debug!(
+                    "Ignoring {:?}, as its size of {} is out of bounds",
+                    file.name, size
+                );
+                return false;
+            }
+        }
+
+        if let Some(ref content_type) = file.content_type {
+            // Types may have parameters, e.g. `text/plain; charset=utf-8`
+            let essence = content_type.split(';').next().unwrap_or("").trim();
+            if !self.selection.content_types.is_empty()
+                && !self
+                    .selection
+                    .content_types
+                    .iter()
+                    .any(|x| x.eq_ignore_ascii_case(essence))
+            {
+                debug!("Ignoring {:?}, as it is a {:?}", file.name, content_type);
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Ranks a file by the first preferred pattern it matches, lower being better.
+    fn preference(&self, file: &File) -> usize {
+        self.prefer
+            .iter()
+            .position(|x| x.is_match(&file.name))
+            .unwrap_or(self.prefer.len())
+    }
+
+    /// Finds the most preferred file of a release that can be installed, keeping the
+    /// source's order between equally preferred files.
+    pub fn select<'b>(&self, release: &'b Release) -> Option<&'b File> {
+        release
+            .files
+            .iter()
+            .filter(|x| self.accepts(x))
+            .min_by_key(|x| self.preference(x))
+    }
+}
+
+/// Fills in everything needed to download a selected file from the rest of its release.
+pub fn complete_file(
+    file: &File,
+    release: &Release,
+    package: &PackageDescription,
+) -> Result<File, String> {
+    // Pull in any other parts if this has been split into multiple files
+    let mut file = multipart::assemble(file, &release.files)?;
+
+    // Releases can publish a chunk index next to the file for differential updates
+    let chunk_index_name = format!("{}.chunks.json", file.name);
+    file.chunk_index = release
+        .files
+        .iter()
+        .find(|x| x.name == chunk_index_name)
+        .map(|x| x.url.clone());
+
+    if let Some(ref verification) = package.verification {
+        verification::find_files(&mut file, &release.files, verification)?;
+    }
+
+    Ok(file)
+}
+
+/// Finds the latest release of a package (within its channel, if any) and the file of it
+/// which would be installed. Private sources should already have been authorised.
+pub fn find_latest(
+    package: &PackageDescription,
+    context: &InstallerFramework,
+    messenger: &dyn Fn(&TaskMessage),
+) -> Result<(Version, File), String> {
+    let rewrites = context
+        .config
+        .as_ref()
+        .map(|x| x.url_rewrites.clone())
+        .unwrap_or_default();
+    let channel = context.get_channel(&package).cloned();
+
+    find_latest_in(package, &rewrites, channel, messenger)
+}
+
+/// Like `find_latest`, given the URL rewrites and channel to use, so that it can be done
+/// away from the framework.
+pub fn find_latest_in(
+    package: &PackageDescription,
+    rewrites: &[UrlRewrite],
+    channel: Option<ReleaseChannel>,
+    messenger: &dyn Fn(&TaskMessage),
+) -> Result<(Version, File), String> {
+    let results = package.source.get_current_releases(rewrites)?;
+
+    messenger(&TaskMessage::DisplayMessage(
+        "progress.resolving_dependency",
+        &strings::format(
+            "progress.resolving_dependency",
+            &[("package", &package.name)],
+        ),
+        0.5,
+    ));
+
+    // Channels narrow down which releases (and files within them) are installed
+    let mut source = package.source.clone();
+    if let Some(ref channel) = channel {
+        info!("Using the {:?} channel of {:?}", channel.name, package.name);
+
+        if let Some(ref regex) = channel.match_regex {
+            source.match_regex = regex.clone();
+        }
+    }
+
+    let selector = AssetSelector::new(&source)?;
+
+    let mut candidates = Vec::new();
+    for release in results {
+        if let Some(ref channel) = channel {
+            if !channel.accepts(&release.version)? {
+                continue;
+            }
+        }
+
+        if release.files.iter().any(|x| selector.accepts(x)) {
+            candidates.push(release);
+        }
+    }
+
+    // Find the latest release in here
+    let latest_result = match candidates.into_iter().max_by_key(|f| f.version.clone()) {
+        Some(v) => v,
+        None => match channel {
+            Some(ref channel) => {
+                return Err(format!(
+                    "No release with correct file found in the {:?} channel",
+                    channel.name
+                ));
+            }
+            None => return Err("No release with correct file found".to_string()),
+        },
+    };
+
+    let latest_version = latest_result.version.clone();
+
+    // Find the matching file in here
+    let latest_file = selector
+        .select(&latest_result)
+        .log_expect("Searched file should have existed, but didn't");
+    let latest_file = complete_file(latest_file, &latest_result, &package)?;
+
+    Ok((latest_version, latest_file))
+}
+
 impl Task for ResolvePackageTask {
     fn execute(
         &mut self,
@@ -27,6 +230,12 @@ impl Task for ResolvePackageTask {
         messenger: &dyn Fn(&TaskMessage),
     ) -> Result<TaskParamType, String> {
         assert_eq!(input.len(), 0);
+
+        if let Some((version, file)) = context.resolved.remove(&self.name) {
+            info!("Using already resolved {:?}", self.name);
+            return Ok(TaskParamType::File(version, file));
+        }
+
         let mut metadata: Option<PackageDescription> = None;
         for description in &context
             .config
@@ -46,45 +255,16 @@ impl Task for ResolvePackageTask {
         };
 
         messenger(&TaskMessage::DisplayMessage(
-            &format!(
-                "Polling {} for latest version of {:?}...",
-                package.source.name, package.name
+            "progress.polling_source",
+            &strings::format(
+                "progress.polling_source",
+                &[("source", &package.source.name), ("package", &package.name)],
             ),
             0.0,
         ));
 
-        let results = package.source.get_current_releases()?;
-
-        messenger(&TaskMessage::DisplayMessage(
-            &format!("Resolving dependency for {:?}...", package.name),
-            0.5,
-        ));
-
-        let filtered_regex = package.source.match_regex.replace("#PLATFORM#", OS);
-        let regex = match Regex::new(&filtered_regex) {
-            Ok(v) => v,
-            Err(v) => return Err(format!("An error occurred while compiling regex: {:?}", v)),
-        };
-
-        // Find the latest release in here
-        let latest_result = results
-            .into_iter()
-            .filter(|f| f.files.iter().filter(|x| regex.is_match(&x.name)).count() > 0)
-            .max_by_key(|f| f.version.clone());
-
-        let latest_result = match latest_result {
-            Some(v) => v,
-            None => return Err("No release with correct file found".to_string()),
-        };
-
-        let latest_version = latest_result.version.clone();
-
-        // Find the matching file in here
-        let latest_file = latest_result
-            .files
-            .into_iter()
-            .find(|x| regex.is_match(&x.name))
-            .log_expect("Searched file should have existed, but didn't");
+        source_auth::authorize(&package.name, &package.source, context, Some(messenger))?;
+        let (latest_version, latest_file) = find_latest(&package, context, messenger)?;
 
         info!("Selected file: {:?}", latest_file);
 
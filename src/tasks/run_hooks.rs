@@ -0,0 +1,252 @@
+//! Runs the hook commands a package declares for a point in its lifecycle. Hooks can ask
+//! the user questions as they go (see `sandbox`).
+
+use installer::InstallerFramework;
+
+use tasks::Task;
+use tasks::TaskDependency;
+use tasks::TaskMessage;
+use tasks::TaskParamType;
+
+use config::{HookCommand, ResourceLimits};
+
+use audit::{self, AuditAction};
+
+use control;
+
+use detect;
+
+use locations;
+
+use prompts;
+
+use sandbox;
+
+use strings;
+
+use std::path::Path;
+
+/// The point of a package's lifecycle which hooks are run at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HookPoint {
+    PostInstall,
+    PreUninstall,
+}
+
+/// Asks the user if uninstalling should carry on after a hook failed, which it does if
+/// there is no-one to ask.
+fn continue_uninstalling(
+    hook: &HookCommand,
+    package: &str,
+    error: &str,
+    messenger: &dyn Fn(&TaskMessage),
+) -> bool {
+    let continue_anyway = strings::tr("prompts.continue_anyway");
+    let cancel = strings::tr("prompts.cancel");
+    let question = strings::format(
+        "prompts.uninstall_hook_failed",
+        &[
+            ("command", &hook.command),
+            ("package", package),
+            ("error", error),
+        ],
+    );
+
+    prompts::ask(
+        messenger,
+        &question,
+        &[&continue_anyway, &cancel],
+        &continue_anyway,
+    ) == continue_anyway
+}
+
+pub struct RunHooksTask {
+    pub name: String,
+    pub point: HookPoint,
+    pub should_run: bool,
+}
+
+/// Runs a single hook, turning failures (including a non-zero exit code) into an error.
+fn run_hook(
+    hook: &HookCommand,
+    context: &InstallerFramework,
+    install_path: &Path,
+    root: &Path,
+    messenger: &dyn Fn(&TaskMessage),
+) -> Result<(), String> {
+    // Hooks recorded by the installation can be run without the configuration
+    let (data_locations, mut limits) = match context.config {
+        Some(ref config) => (config.data_locations.clone(), config.limits.clone()),
+        None => (Vec::new(), ResourceLimits::default()),
+    };
+
+    // Bare program names are left for the PATH to resolve
+    let program = if hook.command.contains(|x| x == '/' || x == '\\') {
+        locations::resolve(&hook.command, install_path, &data_locations)?
+            .to_string_lossy()
+            .to_string()
+    } else {
+        hook.command.clone()
+    };
+
+    let working_dir = match hook.working_dir {
+        Some(ref v) => locations::resolve(v, install_path, &data_locations)?,
+        None => root.to_owned(),
+    };
+
+    let install_dir = install_path.to_string_lossy();
+    let args: Vec<String> = hook
+        .args
+        .iter()
+        .map(|x| x.replace("{install_dir}", &install_dir))
+        .collect();
+    let args: Vec<&str> = args.iter().map(|x| x.as_str()).collect();
+
+    if let Some(timeout) = hook.timeout {
+        limits.command_timeout = timeout;
+    }
+
+    let output = if hook.dialogs {
+        sandbox::run_with_dialogs(&program, &args, &working_dir, &limits, messenger)?
+    } else {
+        sandbox::run(&program, &args, &working_dir, &limits)?
+    };
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} failed ({}): {}",
+            program,
+            output.status,
+            output.stderr.trim()
+        ))
+    }
+}
+
+impl Task for RunHooksTask {
+    fn execute(
+        &mut self,
+        _: Vec<TaskParamType>,
+        context: &mut InstallerFramework,
+        messenger: &dyn Fn(&TaskMessage),
+    ) -> Result<TaskParamType, String> {
+        // Packages which were already up to date haven't been installed again
+        let should_run = match self.point {
+            HookPoint::PostInstall => context.pending_hooks.remove(&self.name),
+            HookPoint::PreUninstall => {
+                self.should_run
+                    && context
+                        .database
+                        .packages
+                        .iter()
+                        .any(|x| x.name == self.name)
+            }
+        };
+
+        if !should_run {
+            return Ok(TaskParamType::None);
+        }
+
+        let package = context
+            .config
+            .as_ref()
+            .and_then(|x| x.packages.iter().find(|x| x.name == self.name));
+        let hooks = match (package, self.point) {
+            (Some(package), HookPoint::PostInstall) => package.hooks.post_install.clone(),
+            (Some(package), HookPoint::PreUninstall) => package.hooks.pre_uninstall.clone(),
+            // Uninstalls may not have the configuration, or it may no longer list the
+            // package, so the installation records what to run
+            (None, HookPoint::PreUninstall) => context
+                .database
+                .packages
+                .iter()
+                .find(|x| x.name == self.name)
+                .map(|x| x.pre_uninstall.clone())
+                .unwrap_or_default(),
+            (None, HookPoint::PostInstall) => {
+                warn!(
+                    "No configuration for {:?}, so its hooks can't be run",
+                    self.name
+                );
+                return Ok(TaskParamType::None);
+            }
+        };
+
+        if hooks.is_empty() {
+            return Ok(TaskParamType::None);
+        }
+
+        let install_path = context
+            .install_path
+            .clone()
+            .ok_or("No install path specified")?;
+        let root = context.get_package_root(&self.name)?;
+
+        for (i, hook) in hooks.iter().enumerate() {
+            if let Some(ref detection) = hook.skip_if {
+                let satisfied = match context.config {
+                    Some(ref config) => detect::is_satisfied(detection, &install_path, config),
+                    None => Err("the configuration isn't loaded".to_string()),
+                };
+
+                match satisfied {
+                    Ok(true) => {
+                        info!(
+                            "Skipping {} for {:?}, as what it installs is already present",
+                            hook.command, self.name
+                        );
+                        continue;
+                    }
+                    Ok(false) => {}
+                    Err(v) => warn!("Unable to check if {} is needed: {}", hook.command, v),
+                }
+            }
+
+            messenger(&TaskMessage::DisplayMessage(
+                "progress.running_hook",
+                &strings::format(
+                    "progress.running_hook",
+                    &[("command", &hook.command), ("package", &self.name)],
+                ),
+                i as f64 / hooks.len() as f64,
+            ));
+
+            audit::record(
+                context,
+                AuditAction::CommandRun {
+                    package: self.name.clone(),
+                    command: hook.command.clone(),
+                },
+            );
+
+            if let Err(v) = run_hook(hook, context, &install_path, &root, messenger) {
+                // Hooks are killed when cancelled, which isn't them failing
+                if v == control::CANCELLED {
+                    return Err(v);
+                }
+
+                // A package whose cleanup fails would otherwise be impossible to uninstall
+                let required = hook.required
+                    && (self.point == HookPoint::PostInstall
+                        || !continue_uninstalling(hook, &self.name, &v, messenger));
+
+                if required {
+                    return Err(format!("Hook for {:?} failed: {}", self.name, v));
+                }
+
+                warn!("Ignoring failed hook for {:?}: {}", self.name, v);
+            }
+        }
+
+        Ok(TaskParamType::None)
+    }
+
+    fn dependencies(&self) -> Vec<TaskDependency> {
+        vec![]
+    }
+
+    fn name(&self) -> String {
+        format!("RunHooksTask (for {:?}, {:?})", self.name, self.point)
+    }
+}
@@ -7,6 +7,8 @@ use tasks::TaskDependency;
 use tasks::TaskMessage;
 use tasks::TaskParamType;
 
+use strings;
+
 pub struct SaveDatabaseTask {}
 
 impl Task for SaveDatabaseTask {
@@ -18,7 +20,8 @@ impl Task for SaveDatabaseTask {
     ) -> Result<TaskParamType, String> {
         assert_eq!(input.len(), 0);
         messenger(&TaskMessage::DisplayMessage(
-            "Saving application database...",
+            "progress.saving_database",
+            &strings::tr("progress.saving_database"),
             0.0,
         ));
 
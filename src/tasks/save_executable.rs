@@ -7,6 +7,8 @@ use tasks::TaskDependency;
 use tasks::TaskMessage;
 use tasks::TaskParamType;
 
+use strings;
+
 use std::fs::File;
 use std::fs::OpenOptions;
 
@@ -27,7 +29,8 @@ impl Task for SaveExecutableTask {
     ) -> Result<TaskParamType, String> {
         assert_eq!(input.len(), 0);
         messenger(&TaskMessage::DisplayMessage(
-            "Copying installer binary...",
+            "progress.copying_installer",
+            &strings::tr("progress.copying_installer"),
             0.0,
         ));
 
@@ -53,6 +56,7 @@ impl Task for SaveExecutableTask {
         };
 
         let new_app = path.join(platform_extension);
+        context.transaction.created(&new_app);
 
         let mut file_metadata = OpenOptions::new();
         file_metadata.write(true).create_new(true);
@@ -0,0 +1,145 @@
+//! Runs a package's smoke test once it has been installed. Packages which fail aren't
+//! rolled back, but are marked as suspect so that repairing installs them again.
+
+use installer::InstallerFramework;
+
+use tasks::Task;
+use tasks::TaskDependency;
+use tasks::TaskMessage;
+use tasks::TaskParamType;
+
+use config::SmokeTest;
+
+use regex::Regex;
+
+use control;
+
+use locations;
+
+use sandbox;
+
+use strings;
+
+pub struct SmokeTestTask {
+    pub name: String,
+}
+
+/// Runs a smoke test, turning failures (including unexpected output) into an error.
+fn run_test(test: &SmokeTest, context: &InstallerFramework, name: &str) -> Result<(), String> {
+    let config = context.config.as_ref().ok_or("Config not loaded")?;
+    let install_path = context
+        .install_path
+        .clone()
+        .ok_or("No install path specified")?;
+
+    let program = locations::resolve(&test.command, &install_path, &config.data_locations)?;
+    if !program.is_file() {
+        return Err(format!("{:?} is missing", program));
+    }
+
+    let install_dir = install_path.to_string_lossy();
+    let args: Vec<String> = test
+        .args
+        .iter()
+        .map(|x| x.replace("{install_dir}", &install_dir))
+        .collect();
+    let args: Vec<&str> = args.iter().map(|x| x.as_str()).collect();
+
+    let mut limits = config.limits.clone();
+    if let Some(timeout) = test.timeout {
+        limits.command_timeout = timeout;
+    }
+
+    let root = context.get_package_root(name)?;
+    let output = sandbox::run(&program.to_string_lossy(), &args, &root, &limits)?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "{:?} failed ({}): {}",
+            program,
+            output.status,
+            output.stderr.trim()
+        ));
+    }
+
+    if let Some(ref pattern) = test.pattern {
+        let pattern = Regex::new(pattern)
+            .map_err(|x| format!("Invalid smoke test pattern {:?}: {}", pattern, x))?;
+
+        // Some programs print their version as an error
+        if !pattern.is_match(&output.stdout) && !pattern.is_match(&output.stderr) {
+            return Err(format!(
+                "{:?} printed {:?}, which doesn't match {:?}",
+                program,
+                output.stdout.trim(),
+                pattern.as_str()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+impl Task for SmokeTestTask {
+    fn execute(
+        &mut self,
+        _: Vec<TaskParamType>,
+        context: &mut InstallerFramework,
+        messenger: &dyn Fn(&TaskMessage),
+    ) -> Result<TaskParamType, String> {
+        // Packages which were already up to date haven't been installed again
+        if !context.pending_smoke_tests.remove(&self.name) {
+            return Ok(TaskParamType::None);
+        }
+
+        let test = match context
+            .config
+            .as_ref()
+            .and_then(|x| x.packages.iter().find(|x| x.name == self.name))
+            .and_then(|x| x.smoke_test.clone())
+        {
+            Some(v) => v,
+            None => return Ok(TaskParamType::None),
+        };
+
+        messenger(&TaskMessage::DisplayMessage(
+            "progress.smoke_test",
+            &strings::format("progress.smoke_test", &[("package", &self.name)]),
+            0.0,
+        ));
+
+        // Tests are killed when cancelled, which doesn't say anything about the package
+        let result = match run_test(&test, context, &self.name) {
+            Err(ref v) if v == control::CANCELLED => return Err(v.clone()),
+            result => result,
+        };
+
+        let package = context
+            .database
+            .packages
+            .iter_mut()
+            .find(|x| x.name == self.name)
+            .ok_or_else(|| format!("{:?} hasn't been installed", self.name))?;
+
+        match result {
+            Ok(_) => {
+                info!("Smoke test for {:?} passed", self.name);
+                package.suspect = None;
+            }
+            Err(v) => {
+                warn!("Smoke test for {:?} failed: {}", self.name, v);
+                package.suspect = Some(v);
+            }
+        }
+
+        Ok(TaskParamType::None)
+    }
+
+    fn dependencies(&self) -> Vec<TaskDependency> {
+        vec![]
+    }
+
+    fn name(&self) -> String {
+        format!("SmokeTestTask (for {:?})", self.name)
+    }
+}
@@ -0,0 +1,576 @@
+//! Journals changes made to the file system while installing, so that a failed install
+//! or update can be undone instead of leaving a mix of old and new files behind.
+//!
+//! Files which are removed or replaced are moved into a staging directory rather than
+//! being deleted, and are only thrown away once the transaction is committed. These can
+//! be compressed, and limited in size, per the configured `BackupSettings`.
+//!
+//! The journal is also written to the staging directory as each change is made, which is
+//! enough for it to survive the installer being killed part way through. Flushing it to
+//! disk, so that it survives the machine going down as well, is done in batches: before
+//! anything is moved out of the way (so that what was done before is never lost), and
+//! once each package is done (see `sync`). Either way, `recover` rolls back what was left
+//! behind the next time the installer runs.
+
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use config::BackupSettings;
+
+use native;
+
+use tasks::install_pkg::move_file;
+
+use zstd;
+
+/// Extension given to compressed copies of files.
+const COMPRESSED_EXTENSION: &str = "zst";
+
+/// Name of the journal within the staging directory.
+const JOURNAL_FILE: &str = "journal.jsonl";
+
+/// Writes a compressed copy of a file, with the same permissions, returning its size.
+fn compress_file(from: &Path, to: &Path, level: i32) -> Result<u64, String> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent).map_err(|x| format!("Unable to create dir: {:?}", x))?;
+    }
+
+    let source = File::open(from).map_err(|x| format!("Unable to open {:?}: {:?}", from, x))?;
+    let dest = File::create(to).map_err(|x| format!("Unable to create {:?}: {:?}", to, x))?;
+
+    zstd::stream::copy_encode(&source, &dest, level)
+        .map_err(|x| format!("Unable to compress {:?}: {:?}", from, x))?;
+
+    let metadata = source
+        .metadata()
+        .map_err(|x| format!("Unable to read metadata of {:?}: {:?}", from, x))?;
+    fs::set_permissions(to, metadata.permissions())
+        .map_err(|x| format!("Unable to set permissions of {:?}: {:?}", to, x))?;
+
+    dest.metadata()
+        .map(|x| x.len())
+        .map_err(|x| format!("Unable to read metadata of {:?}: {:?}", to, x))
+}
+
+/// Writes out the original of a compressed file, with the same permissions.
+fn decompress_file(from: &Path, to: &Path) -> Result<(), String> {
+    let source = File::open(from).map_err(|x| format!("Unable to open {:?}: {:?}", from, x))?;
+    let dest = File::create(to).map_err(|x| format!("Unable to create {:?}: {:?}", to, x))?;
+
+    zstd::stream::copy_decode(&source, &dest)
+        .map_err(|x| format!("Unable to decompress {:?}: {:?}", from, x))?;
+
+    let metadata = source
+        .metadata()
+        .map_err(|x| format!("Unable to read metadata of {:?}: {:?}", from, x))?;
+    fs::set_permissions(to, metadata.permissions())
+        .map_err(|x| format!("Unable to set permissions of {:?}: {:?}", to, x))
+}
+
+/// Returns the path a compressed copy of a file is kept at.
+fn compressed_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(COMPRESSED_EXTENSION);
+    PathBuf::from(name)
+}
+
+/// Returns a path along with any of its parents which don't exist yet, outermost first,
+/// so that parents are undone last (after whatever has been created within them).
+fn missing_ancestors(path: &Path) -> Vec<PathBuf> {
+    let mut missing: Vec<PathBuf> = path
+        .ancestors()
+        .take_while(|x| !x.as_os_str().is_empty() && fs::symlink_metadata(x).is_err())
+        .map(|x| x.to_owned())
+        .collect();
+
+    missing.reverse();
+    missing
+}
+
+/// A single change made during a transaction.
+#[derive(Debug, Serialize, Deserialize)]
+enum JournalEntry {
+    /// A file or directory was created where nothing previously existed
+    Created(PathBuf),
+    /// A file was removed, and is stashed at the given path, taking up the given space
+    Removed {
+        path: PathBuf,
+        stash: PathBuf,
+        compressed: bool,
+        size: u64,
+    },
+    /// A file was removed, but its stash was discarded to stay within the size limit
+    Discarded(PathBuf),
+    /// An empty directory was removed
+    RemovedDir(PathBuf),
+    /// A link to a directory was removed, and is made again (as a junction, on Windows)
+    /// if rolled back
+    RemovedLink { path: PathBuf, target: PathBuf },
+    /// A file was moved elsewhere
+    Moved { from: PathBuf, to: PathBuf },
+    /// A file was replaced by a compressed copy elsewhere
+    Compressed { from: PathBuf, to: PathBuf },
+}
+
+/// Tracks changes to the file system. Until a transaction has begun, changes are made
+/// directly and can't be rolled back.
+#[derive(Debug, Default)]
+pub struct Transaction {
+    /// Where removed files are kept until the transaction is committed
+    staging: Option<PathBuf>,
+    journal: Vec<JournalEntry>,
+    stashed: usize,
+    /// How much space stashed files currently take up
+    stashed_size: u64,
+    settings: BackupSettings,
+    /// Where the journal is written out to, as changes are made
+    journal_file: Option<File>,
+    /// If entries have been written out since the journal was last flushed to disk
+    unsynced: bool,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Transaction::default()
+    }
+
+    /// Starts journaling changes, stashing removed files within the given directory.
+    pub fn begin(&mut self, staging: PathBuf, settings: BackupSettings) -> Result<(), String> {
+        info!("Beginning transaction (staging in {:?})", staging);
+
+        let missing = missing_ancestors(&staging);
+        fs::create_dir_all(&staging)
+            .map_err(|x| format!("Unable to create {:?}: {:?}", staging, x))?;
+
+        let journal_path = staging.join(JOURNAL_FILE);
+        let journal_file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&journal_path)
+            .map_err(|x| format!("Unable to create {:?}: {:?}", journal_path, x))?;
+
+        self.staging = Some(staging);
+        self.journal.clear();
+        self.journal_file = Some(journal_file);
+        self.unsynced = false;
+        self.stashed = 0;
+        self.stashed_size = 0;
+        self.settings = settings;
+
+        // e.g. the install directory of a fresh install
+        for path in missing {
+            self.record(JournalEntry::Created(path));
+        }
+
+        Ok(())
+    }
+
+    /// Returns if a transaction has begun, and not yet been committed or rolled back.
+    pub fn is_active(&self) -> bool {
+        self.staging.is_some()
+    }
+
+    /// Rolls back a transaction left behind in the given staging directory by an
+    /// installer which didn't get to finish, if there is one.
+    pub fn recover(staging: &Path) {
+        let journal_path = staging.join(JOURNAL_FILE);
+        let contents = match fs::read_to_string(&journal_path) {
+            Ok(v) => v,
+            Err(_) => return,
+        };
+
+        // The last entry may have been cut short as it was written
+        let mut journal = Vec::new();
+        for line in contents.lines() {
+            match serde_json::from_str(line) {
+                Ok(v) => journal.push(v),
+                Err(_) => break,
+            }
+        }
+
+        warn!(
+            "Found an unfinished transaction in {:?}, rolling it back",
+            staging
+        );
+
+        let mut transaction = Transaction {
+            staging: Some(staging.to_owned()),
+            journal,
+            ..Transaction::default()
+        };
+        transaction.rollback();
+    }
+
+    /// Adds a change to the journal, writing it out (see `sync`).
+    fn record(&mut self, entry: JournalEntry) {
+        if self.staging.is_none() {
+            return;
+        }
+
+        if let Some(ref mut file) = self.journal_file {
+            let result = serde_json::to_string(&entry)
+                .map_err(|x| format!("{:?}", x))
+                .and_then(|line| writeln!(file, "{}", line).map_err(|x| format!("{:?}", x)));
+
+            match result {
+                Ok(_) => self.unsynced = true,
+                Err(v) => error!(
+                    "Unable to write {:?} to the transaction journal: {}",
+                    entry, v
+                ),
+            }
+        }
+
+        self.journal.push(entry);
+    }
+
+    /// Flushes what has been written to the journal to disk, if anything is yet to be.
+    /// Tasks call this once they are done with a package.
+    pub fn sync(&mut self) {
+        if !self.unsynced {
+            return;
+        }
+
+        if let Some(ref file) = self.journal_file {
+            if let Err(v) = file.sync_data() {
+                error!("Unable to flush the transaction journal: {:?}", v);
+                return;
+            }
+        }
+
+        self.unsynced = false;
+    }
+
+    /// Writes out the whole journal again, after entries have been changed.
+    fn rewrite_journal(&mut self) {
+        let file = match self.journal_file {
+            Some(ref mut v) => v,
+            None => return,
+        };
+
+        let mut contents = String::new();
+        for entry in &self.journal {
+            if let Ok(line) = serde_json::to_string(entry) {
+                contents.push_str(&line);
+                contents.push('\n');
+            }
+        }
+
+        let result = file
+            .set_len(0)
+            .and_then(|_| file.seek(SeekFrom::Start(0)))
+            .and_then(|_| file.write_all(contents.as_bytes()))
+            .and_then(|_| file.sync_data());
+
+        match result {
+            Ok(_) => self.unsynced = false,
+            Err(v) => error!("Unable to rewrite the transaction journal: {:?}", v),
+        }
+    }
+
+    /// Records that a file or directory is about to be created, along with any of its
+    /// parent directories which don't exist yet. Paths which already exist are ignored.
+    pub fn created(&mut self, path: &Path) {
+        if self.staging.is_none() {
+            return;
+        }
+
+        for path in missing_ancestors(path) {
+            self.record(JournalEntry::Created(path));
+        }
+    }
+
+    /// Removes a file, link or empty directory.
+    pub fn remove(&mut self, path: &Path) -> Result<(), String> {
+        let is_link = fs::symlink_metadata(path).map_or(false, |x| x.file_type().is_symlink());
+        if is_link && fs::metadata(path).map_or(false, |x| x.is_dir()) {
+            return self.remove_directory_link(path);
+        }
+
+        let is_dir = fs::symlink_metadata(path).map_or(false, |x| x.is_dir());
+
+        if is_dir {
+            self.sync();
+            fs::remove_dir(path).map_err(|x| format!("Unable to remove {:?}: {:?}", path, x))?;
+
+            self.record(JournalEntry::RemovedDir(path.to_owned()));
+
+            return Ok(());
+        }
+
+        // Links are stashed as they are, rather than compressing what they point to
+        let is_file = fs::symlink_metadata(path).map_or(false, |x| x.is_file());
+        let compress = self.settings.compress && is_file;
+
+        self.stash(path, compress)
+    }
+
+    /// Removes a link to a directory (or a junction), leaving whatever it points to. These
+    /// can't be removed or stashed like files on Windows.
+    pub fn remove_directory_link(&mut self, path: &Path) -> Result<(), String> {
+        let target =
+            fs::read_link(path).map_err(|x| format!("Unable to read link {:?}: {:?}", path, x))?;
+
+        self.sync();
+        native::remove_directory_link(path)?;
+
+        self.record(JournalEntry::RemovedLink {
+            path: path.to_owned(),
+            target,
+        });
+
+        Ok(())
+    }
+
+    /// Removes a file, keeping it in the staging directory until the transaction is
+    /// committed.
+    fn stash(&mut self, path: &Path, compressed: bool) -> Result<(), String> {
+        let stash = match self.staging {
+            Some(ref v) => v.join(self.stashed.to_string()),
+            None => {
+                return fs::remove_file(path)
+                    .map_err(|x| format!("Unable to remove {:?}: {:?}", path, x));
+            }
+        };
+
+        self.sync();
+        let (stash, size) = if compressed {
+            let stash = compressed_path(&stash);
+            let size = compress_file(path, &stash, self.settings.compression_level)?;
+            fs::remove_file(path).map_err(|x| format!("Unable to remove {:?}: {:?}", path, x))?;
+            (stash, size)
+        } else {
+            move_file(path, &stash)?;
+            let size = fs::symlink_metadata(&stash).map_or(0, |x| x.len());
+            (stash, size)
+        };
+
+        self.stashed += 1;
+        self.stashed_size += size;
+        self.record(JournalEntry::Removed {
+            path: path.to_owned(),
+            stash,
+            compressed,
+            size,
+        });
+
+        self.prune();
+
+        Ok(())
+    }
+
+    /// Discards the oldest stashed files until they fit within the size limit.
+    fn prune(&mut self) {
+        let limit = match self.settings.max_rollback_size {
+            Some(v) => v,
+            None => return,
+        };
+
+        let mut pruned = false;
+        for entry in &mut self.journal {
+            if self.stashed_size <= limit {
+                break;
+            }
+
+            let discarded = match *entry {
+                JournalEntry::Removed {
+                    ref path,
+                    ref stash,
+                    size,
+                    ..
+                } => {
+                    warn!(
+                        "Discarding stashed copy of {:?} to stay within the rollback size limit",
+                        path
+                    );
+
+                    if let Err(v) = fs::remove_file(stash) {
+                        warn!("Unable to remove {:?}: {:?}", stash, v);
+                    }
+
+                    self.stashed_size -= size;
+                    JournalEntry::Discarded(path.clone())
+                }
+                _ => continue,
+            };
+
+            *entry = discarded;
+            pruned = true;
+        }
+
+        if pruned {
+            self.rewrite_journal();
+        }
+    }
+
+    /// Keeps a backup of a file elsewhere (compressed, if configured), removing the
+    /// original. Anything already at the backup's location is replaced. Returns where the
+    /// backup was put, and if it was compressed.
+    pub fn back_up(&mut self, from: &Path, to: &Path) -> Result<(PathBuf, bool), String> {
+        let is_file = fs::symlink_metadata(from).map_or(false, |x| x.is_file());
+        let compressed = self.settings.compress && is_file;
+        let to = if compressed {
+            compressed_path(to)
+        } else {
+            to.to_owned()
+        };
+
+        if fs::symlink_metadata(&to).is_ok() {
+            self.remove(&to)?;
+        }
+
+        if compressed {
+            self.compress(from, &to)?;
+        } else {
+            self.rename(from, &to)?;
+        }
+
+        Ok((to, compressed))
+    }
+
+    /// Replaces a file with a compressed copy elsewhere.
+    pub fn compress(&mut self, from: &Path, to: &Path) -> Result<(), String> {
+        self.created(to);
+        self.sync();
+        compress_file(from, to, self.settings.compression_level)?;
+        fs::remove_file(from).map_err(|x| format!("Unable to remove {:?}: {:?}", from, x))?;
+
+        self.record(JournalEntry::Compressed {
+            from: from.to_owned(),
+            to: to.to_owned(),
+        });
+
+        Ok(())
+    }
+
+    /// Puts a backup made by `back_up` back in place of the original.
+    pub fn restore(&mut self, backup: &Path, to: &Path, compressed: bool) -> Result<(), String> {
+        if !compressed {
+            return self.rename(backup, to);
+        }
+
+        self.created(to);
+        decompress_file(backup, to)?;
+
+        // The backup is already compressed
+        self.stash(backup, false)
+    }
+
+    /// Moves a file, creating any parent directories of where it is moved to.
+    pub fn rename(&mut self, from: &Path, to: &Path) -> Result<(), String> {
+        self.created(to);
+        self.sync();
+        move_file(from, to)?;
+
+        self.record(JournalEntry::Moved {
+            from: from.to_owned(),
+            to: to.to_owned(),
+        });
+
+        Ok(())
+    }
+
+    /// Keeps all changes made, discarding anything which was stashed.
+    pub fn commit(&mut self) {
+        self.journal_file = None;
+
+        if let Some(staging) = self.staging.take() {
+            info!("Committing transaction ({} changes)", self.journal.len());
+
+            if staging.exists() {
+                if let Err(v) = fs::remove_dir_all(&staging) {
+                    warn!("Unable to clean up {:?}: {:?}", staging, v);
+                }
+            }
+        }
+
+        self.journal.clear();
+    }
+
+    /// Undoes all changes made, in reverse order. Changes which can't be undone are
+    /// logged, but don't stop the rest of the transaction being rolled back.
+    pub fn rollback(&mut self) {
+        self.journal_file = None;
+
+        let staging = match self.staging.take() {
+            Some(v) => v,
+            None => return,
+        };
+
+        warn!("Rolling back transaction ({} changes)", self.journal.len());
+
+        // Directories holding the staging directory can only go once it has
+        let mut staging_parents = Vec::new();
+
+        while let Some(entry) = self.journal.pop() {
+            let result = match entry {
+                JournalEntry::Created(ref path) if staging.starts_with(path) => {
+                    staging_parents.push(path.clone());
+                    Ok(())
+                }
+                JournalEntry::Created(ref path) => match fs::symlink_metadata(path) {
+                    // Directory links are removed differently on each platform
+                    Ok(ref v) if v.file_type().is_symlink() => native::remove_directory_link(path),
+                    Ok(ref v) if v.is_dir() => fs::remove_dir(path).map_err(|x| format!("{:?}", x)),
+                    Ok(_) => fs::remove_file(path).map_err(|x| format!("{:?}", x)),
+                    Err(_) => Ok(()),
+                },
+                JournalEntry::Removed {
+                    ref path,
+                    ref stash,
+                    compressed,
+                    ..
+                } => {
+                    // Something may have been put in the file's place since it was removed
+                    if fs::symlink_metadata(path).is_ok() {
+                        if let Err(v) = fs::remove_file(path) {
+                            warn!("Unable to remove {:?}: {:?}", path, v);
+                        }
+                    }
+
+                    if compressed {
+                        decompress_file(stash, path)
+                    } else {
+                        move_file(stash, path)
+                    }
+                }
+                JournalEntry::Discarded(_) => {
+                    Err("its stashed copy was discarded to save space".to_string())
+                }
+                JournalEntry::RemovedDir(ref path) => {
+                    fs::create_dir_all(path).map_err(|x| format!("{:?}", x))
+                }
+                // Junctions can be made again without any special permissions
+                JournalEntry::RemovedLink {
+                    ref path,
+                    ref target,
+                } => native::create_directory_link(path, target, true),
+                JournalEntry::Moved { ref from, ref to } => move_file(to, from),
+                JournalEntry::Compressed { ref from, ref to } => decompress_file(to, from)
+                    .and_then(|_| fs::remove_file(to).map_err(|x| format!("{:?}", x))),
+            };
+
+            if let Err(v) = result {
+                error!("Failed to roll back {:?}: {}", entry, v);
+            }
+        }
+
+        if staging.exists() {
+            if let Err(v) = fs::remove_dir_all(&staging) {
+                warn!("Unable to clean up {:?}: {:?}", staging, v);
+            }
+        }
+
+        for path in staging_parents {
+            if path.exists() {
+                if let Err(v) = fs::remove_dir(&path) {
+                    error!("Failed to roll back creating {:?}: {:?}", path, v);
+                }
+            }
+        }
+    }
+}
@@ -5,6 +5,8 @@ use installer::InstallerFramework;
 use tasks::Task;
 use tasks::TaskParamType;
 
+use strings;
+
 use tasks::uninstall_pkg::UninstallPackageTask;
 use tasks::TaskDependency;
 use tasks::TaskMessage;
@@ -21,7 +23,11 @@ impl Task for UninstallTask {
         _: &mut InstallerFramework,
         messenger: &dyn Fn(&TaskMessage),
     ) -> Result<TaskParamType, String> {
-        messenger(&TaskMessage::DisplayMessage("Wrapping up...", 0.0));
+        messenger(&TaskMessage::DisplayMessage(
+            "progress.wrapping_up",
+            &strings::tr("progress.wrapping_up"),
+            0.0,
+        ));
         Ok(TaskParamType::None)
     }
 
@@ -7,8 +7,13 @@ use tasks::TaskDependency;
 use tasks::TaskMessage;
 use tasks::TaskParamType;
 
+use strings;
+
+use audit::{self, AuditAction};
+
 use std::fs::remove_file;
 use tasks::save_database::SaveDatabaseTask;
+use tasks::unregister_uninstaller::UnregisterUninstallerTask;
 use tasks::TaskOrdering;
 
 pub struct UninstallGlobalShortcutsTask {}
@@ -20,26 +25,36 @@ impl Task for UninstallGlobalShortcutsTask {
         context: &mut InstallerFramework,
         messenger: &dyn Fn(&TaskMessage),
     ) -> Result<TaskParamType, String> {
-        assert_eq!(input.len(), 0);
+        assert_eq!(input.len(), 1);
 
         messenger(&TaskMessage::DisplayMessage(
-            "Uninstalling global shortcut...",
+            "progress.uninstalling_global_shortcut",
+            &strings::tr("progress.uninstalling_global_shortcut"),
             0.0,
         ));
 
         while let Some(file) = context.database.shortcuts.pop() {
             info!("Deleting shortcut {:?}", file);
-            remove_file(file).map_err(|x| format!("Unable to delete global shortcut: {:?}", x))?;
+            let result = remove_file(&file);
+            context.database.forget_shortcut(&file, result)?;
+
+            audit::record(
+                context,
+                AuditAction::FilesRemoved {
+                    package: None,
+                    files: vec![file],
+                },
+            );
         }
 
         Ok(TaskParamType::None)
     }
 
     fn dependencies(&self) -> Vec<TaskDependency> {
-        vec![TaskDependency::build(
-            TaskOrdering::Post,
-            Box::new(SaveDatabaseTask {}),
-        )]
+        vec![
+            TaskDependency::build(TaskOrdering::Pre, Box::new(UnregisterUninstallerTask {})),
+            TaskDependency::build(TaskOrdering::Post, Box::new(SaveDatabaseTask {})),
+        ]
     }
 
     fn name(&self) -> String {
@@ -2,6 +2,9 @@
 
 use installer::InstallerFramework;
 
+use tasks::remove_links::RemoveLinksTask;
+use tasks::remove_system_user::RemoveSystemUserTask;
+use tasks::run_hooks::{HookPoint, RunHooksTask};
 use tasks::save_database::SaveDatabaseTask;
 use tasks::Task;
 use tasks::TaskDependency;
@@ -11,8 +14,15 @@ use tasks::TaskParamType;
 
 use installer::LocalInstallation;
 
-use std::fs::remove_dir;
-use std::fs::remove_file;
+use std::path::{Path, PathBuf};
+
+use audit::{self, AuditAction};
+
+use hashing::sha256_file;
+
+use prompts;
+
+use strings;
 
 use logging::LoggingErrors;
 use tasks::uninstall_shortcuts::UninstallShortcutsTask;
@@ -29,7 +39,9 @@ impl Task for UninstallPackageTask {
         context: &mut InstallerFramework,
         messenger: &dyn Fn(&TaskMessage),
     ) -> Result<TaskParamType, String> {
-        assert_eq!(input.len(), 1);
+        assert_eq!(input.len(), 3);
+
+        context.check_metadata()?;
 
         let path = context
             .install_path
@@ -59,40 +71,177 @@ impl Task for UninstallPackageTask {
         };
 
         messenger(&TaskMessage::DisplayMessage(
-            &format!("Uninstalling package {:?}...", self.name),
+            "progress.uninstalling_package",
+            &strings::format("progress.uninstalling_package", &[("package", &self.name)]),
             0.0,
         ));
 
+        let path = package.get_root(path);
+        let transaction = &mut context.transaction;
+
+        // Files which other packages have replaced are left in place. If this package is going
+        // away for good, those files belong to the replacing package from now on.
+        let mut replaced = Vec::new();
+        for other in &mut context.database.packages {
+            let optional = self.optional;
+            let name = &self.name;
+
+            other.overwritten.retain(|file| {
+                if file.owner.as_ref() != Some(name) {
+                    return true;
+                }
+
+                replaced.push(file.path.clone());
+
+                if optional {
+                    return true;
+                }
+
+                if let Err(v) = transaction.remove(Path::new(&file.backup)) {
+                    warn!("Unable to remove backup: {}", v);
+                }
+                false
+            });
+        }
+
+        // Files the user has changed since they were installed may be worth keeping
+        let mut preserved = Vec::new();
+        if self.optional {
+            for file in &package.files {
+                let expected = match package.hashes.get(file) {
+                    Some(v) => v,
+                    None => continue,
+                };
+
+                let target = path.join(file);
+                if replaced.contains(file) || !target.is_file() {
+                    continue;
+                }
+
+                match sha256_file(&target) {
+                    Ok(ref v) if v == expected => continue,
+                    Ok(_) => {}
+                    Err(v) => {
+                        warn!("Unable to check {:?} for modifications: {}", target, v);
+                        continue;
+                    }
+                }
+
+                let keep = strings::tr("prompts.keep_modified");
+                let back_up = strings::tr("prompts.back_up_modified");
+                let replace = strings::tr("prompts.replace_modified");
+
+                let answer = prompts::ask(
+                    messenger,
+                    &strings::format("prompts.file_modified", &[("file", file)]),
+                    &[&keep, &back_up, &replace],
+                    &back_up,
+                );
+
+                if answer == keep {
+                    preserved.push(file.clone());
+                } else if answer == back_up {
+                    let backup = PathBuf::from(format!("{}.backup", target.display()));
+                    info!("Backing up modified file {:?} to {:?}", target, backup);
+
+                    if backup.exists() {
+                        if let Err(v) = transaction.remove(&backup) {
+                            warn!("Unable to remove old backup: {}", v);
+                        }
+                    }
+
+                    transaction.rename(&target, &backup)?;
+                } else {
+                    info!("Replacing modified file {:?}", target);
+                }
+            }
+        }
+
         // Reverse, as to delete directories last
         package.files.reverse();
 
         let max = package.files.len();
+        let mut removed = Vec::new();
         for (i, file) in package.files.iter().enumerate() {
+            if replaced.contains(file) {
+                info!("Keeping {:?}, as it was replaced by another package", file);
+                continue;
+            }
+
+            if preserved.contains(file) {
+                continue;
+            }
+
             let name = file.clone();
             let file = path.join(file);
             info!("Deleting {:?}", file);
 
             messenger(&TaskMessage::DisplayMessage(
-                &format!("Deleting {} ({} of {})", name, i + 1, max),
+                "progress.deleting_file",
+                &strings::format(
+                    "progress.deleting_file",
+                    &[
+                        ("file", &name),
+                        ("number", &(i + 1).to_string()),
+                        ("count", &max.to_string()),
+                    ],
+                ),
                 (i as f64) / (max as f64),
             ));
 
-            let result = if file.is_dir() {
-                remove_dir(file)
+            match transaction.remove(&file) {
+                Ok(_) => removed.push(file.to_string_lossy().to_string()),
+                Err(v) => error!("Failed to delete file: {}", v),
+            }
+        }
+
+        // Put back anything that this package replaced
+        for file in &package.overwritten {
+            let target = path.join(&file.path);
+
+            // Originals from packages which have since been removed aren't restored
+            let is_orphaned = match file.owner {
+                Some(ref owner) => !context.database.packages.iter().any(|x| &x.name == owner),
+                None => false,
+            };
+
+            let result = if is_orphaned {
+                transaction.remove(Path::new(&file.backup))
             } else {
-                remove_file(file)
+                info!("Restoring {:?}", target);
+                transaction.restore(Path::new(&file.backup), &target, file.compressed)
             };
 
             if let Err(v) = result {
-                error!("Failed to delete file: {:?}", v);
+                error!("Failed to restore {:?}: {}", target, v);
             }
         }
 
-        Ok(TaskParamType::None)
+        transaction.sync();
+
+        if package.root.is_some() && !removed.is_empty() {
+            audit::record(
+                context,
+                AuditAction::FilesRemoved {
+                    package: Some(self.name.clone()),
+                    files: removed,
+                },
+            );
+        }
+
+        Ok(TaskParamType::PreservedFiles(preserved))
     }
 
     fn dependencies(&self) -> Vec<TaskDependency> {
         vec![
+            TaskDependency::build(
+                TaskOrdering::Pre,
+                Box::new(RunHooksTask {
+                    name: self.name.clone(),
+                    point: HookPoint::PreUninstall,
+                    should_run: !self.optional,
+                }),
+            ),
             TaskDependency::build(
                 TaskOrdering::Pre,
                 Box::new(UninstallShortcutsTask {
@@ -100,6 +249,20 @@ impl Task for UninstallPackageTask {
                     optional: self.optional,
                 }),
             ),
+            TaskDependency::build(
+                TaskOrdering::Pre,
+                Box::new(RemoveLinksTask {
+                    name: self.name.clone(),
+                    should_run: !self.optional,
+                }),
+            ),
+            TaskDependency::build(
+                TaskOrdering::Post,
+                Box::new(RemoveSystemUserTask {
+                    name: self.name.clone(),
+                    should_run: !self.optional,
+                }),
+            ),
             TaskDependency::build(TaskOrdering::Post, Box::new(SaveDatabaseTask {})),
         ]
     }
@@ -14,6 +14,12 @@ use std::fs::remove_file;
 
 use logging::LoggingErrors;
 
+use audit::{self, AuditAction};
+
+use native;
+
+use strings;
+
 pub struct UninstallShortcutsTask {
     pub name: String,
     pub optional: bool,
@@ -56,7 +62,11 @@ impl Task for UninstallShortcutsTask {
         };
 
         messenger(&TaskMessage::DisplayMessage(
-            &format!("Uninstalling shortcuts for package {:?}...", self.name),
+            "progress.uninstalling_shortcuts",
+            &strings::format(
+                "progress.uninstalling_shortcuts",
+                &[("package", &self.name)],
+            ),
             0.0,
         ));
 
@@ -64,13 +74,22 @@ impl Task for UninstallShortcutsTask {
         package.files.reverse();
 
         let max = package.files.len();
+        let mut removed = Vec::new();
         for (i, file) in package.shortcuts.iter().enumerate() {
             let name = file.clone();
             let file = path.join(file);
             info!("Deleting shortcut {:?}", file);
 
             messenger(&TaskMessage::DisplayMessage(
-                &format!("Deleting shortcut {} ({} of {})", name, i + 1, max),
+                "progress.deleting_shortcut",
+                &strings::format(
+                    "progress.deleting_shortcut",
+                    &[
+                        ("file", &name),
+                        ("number", &(i + 1).to_string()),
+                        ("count", &max.to_string()),
+                    ],
+                ),
                 (i as f64) / (max as f64),
             ));
 
@@ -80,11 +99,26 @@ impl Task for UninstallShortcutsTask {
                 remove_file(file)
             };
 
-            if let Err(v) = result {
-                error!("Failed to delete shortcut: {:?}", v);
+            match context.database.forget_shortcut(&name, result) {
+                Ok(_) => removed.push(path.join(&name).to_string_lossy().to_string()),
+                Err(v) => error!("Failed to delete shortcut: {}", v),
             }
         }
 
+        if !removed.is_empty() {
+            audit::record(
+                context,
+                AuditAction::FilesRemoved {
+                    package: Some(self.name.clone()),
+                    files: removed,
+                },
+            );
+        }
+
+        if !package.shortcuts.is_empty() {
+            native::refresh_desktop_caches(context.database.all_users);
+        }
+
         Ok(TaskParamType::None)
     }
 
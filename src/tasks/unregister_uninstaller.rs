@@ -0,0 +1,53 @@
+//! Removes the application from Windows' "Apps & Features".
+
+use installer::InstallerFramework;
+
+use tasks::register_uninstaller::uninstall_key;
+use tasks::Task;
+use tasks::TaskDependency;
+use tasks::TaskMessage;
+use tasks::TaskParamType;
+
+use strings;
+
+use audit::{self, AuditAction};
+
+use native;
+
+pub struct UnregisterUninstallerTask {}
+
+impl Task for UnregisterUninstallerTask {
+    fn execute(
+        &mut self,
+        _: Vec<TaskParamType>,
+        context: &mut InstallerFramework,
+        messenger: &dyn Fn(&TaskMessage),
+    ) -> Result<TaskParamType, String> {
+        let key = uninstall_key(context);
+        if !native::registry_key_exists(&key) {
+            return Ok(TaskParamType::None);
+        }
+
+        messenger(&TaskMessage::DisplayMessage(
+            "progress.unregistering_uninstaller",
+            &strings::tr("progress.unregistering_uninstaller"),
+            0.0,
+        ));
+
+        info!("Deleting registry key {:?}", key);
+        match native::delete_registry_key(&key) {
+            Ok(_) => audit::record(context, AuditAction::RegistryDeleted { key }),
+            Err(v) => warn!("Unable to unregister uninstaller: {}", v),
+        }
+
+        Ok(TaskParamType::None)
+    }
+
+    fn dependencies(&self) -> Vec<TaskDependency> {
+        vec![]
+    }
+
+    fn name(&self) -> String {
+        "UnregisterUninstallerTask".to_string()
+    }
+}
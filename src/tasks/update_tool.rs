@@ -0,0 +1,119 @@
+//! Downloads a newer build of the installer, staging it next to the running maintenance
+//! tool so that it can swap itself in once this instance has exited.
+
+use installer::InstallerFramework;
+
+use tasks::Task;
+use tasks::TaskDependency;
+use tasks::TaskMessage;
+use tasks::TaskParamType;
+
+use config::PackageVerification;
+
+use sources::types::File;
+
+use http;
+
+use self_update::staged_tool_path;
+
+use strings;
+
+use verification;
+
+use logging::LoggingErrors;
+
+use number_prefix::{NumberPrefix, Prefixed, Standalone};
+
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+
+pub struct UpdateToolTask {
+    pub file: File,
+    pub verification: Option<PackageVerification>,
+}
+
+impl Task for UpdateToolTask {
+    fn execute(
+        &mut self,
+        _: Vec<TaskParamType>,
+        _: &mut InstallerFramework,
+        messenger: &dyn Fn(&TaskMessage),
+    ) -> Result<TaskParamType, String> {
+        info!("Downloading self-update from {:?}", self.file.url);
+
+        let mut downloaded = 0;
+        let mut data: Vec<u8> = Vec::new();
+
+        http::stream_file(&self.file.url, None, |chunk, size| {
+            data.extend_from_slice(&chunk);
+            downloaded += chunk.len();
+
+            let percentage = if size == 0 {
+                0.0
+            } else {
+                (downloaded as f64) / (size as f64)
+            };
+
+            // Pretty print data volumes
+            let pretty_current = match NumberPrefix::decimal(downloaded as f64) {
+                Standalone(bytes) => format!("{} bytes", bytes),
+                Prefixed(prefix, n) => format!("{:.0} {}B", n, prefix),
+            };
+            let pretty_total = match NumberPrefix::decimal(size as f64) {
+                Standalone(bytes) => format!("{} bytes", bytes),
+                Prefixed(prefix, n) => format!("{:.0} {}B", n, prefix),
+            };
+
+            messenger(&TaskMessage::DisplayMessage(
+                "progress.downloading_self_update",
+                &strings::format(
+                    "progress.downloading_self_update",
+                    &[("downloaded", &pretty_current), ("total", &pretty_total)],
+                ),
+                percentage,
+            ));
+        })?;
+
+        // A corrupt maintenance tool couldn't update itself out of the problem later
+        if let Some(ref verification) = self.verification {
+            verification::verify_download(&self.file, &data, verification)?;
+        }
+
+        let current_exe = env::current_exe().log_expect("Current executable could not be found");
+        let new_app = staged_tool_path(
+            current_exe
+                .parent()
+                .log_expect("Parent directory of executable could not be found"),
+        );
+
+        let mut file_metadata = OpenOptions::new();
+        file_metadata.write(true).create(true).truncate(true);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+
+            file_metadata.mode(0o770);
+        }
+
+        let mut new_app_file = match file_metadata.open(&new_app) {
+            Ok(v) => v,
+            Err(v) => return Err(format!("Unable to open installer binary: {:?}", v)),
+        };
+
+        if let Err(v) = new_app_file.write_all(&data) {
+            return Err(format!("Unable to copy installer binary: {:?}", v));
+        }
+
+        Ok(TaskParamType::None)
+    }
+
+    fn dependencies(&self) -> Vec<TaskDependency> {
+        vec![]
+    }
+
+    fn name(&self) -> String {
+        format!("UpdateToolTask (from {:?})", self.file.url)
+    }
+}
@@ -0,0 +1,309 @@
+//! verification.rs
+//!
+//! Verifies downloaded packages against checksums and minisign signatures published
+//! alongside them, so that packages served by untrusted mirrors can't be tampered with.
+
+use std::borrow::Cow;
+use std::fs::read;
+use std::path::Path;
+
+use blake2_rfc::blake2b::blake2b;
+
+use ring::signature;
+
+use untrusted::Input;
+
+use config::PackageVerification;
+
+use error_help;
+
+use hashing::{sha1_file, sha256_file, sha256_hex, to_hex};
+
+use http::fetch_bytes;
+
+use sources::types::File;
+
+/// Placeholder replaced with the name of the downloaded file.
+const FILE_PLACEHOLDER: &str = "{{file}}";
+/// Extension of minisign signatures, which are published next to what they sign.
+const SIGNATURE_EXTENSION: &str = ".minisig";
+/// Prefix of the line of a signature which is covered by its global signature.
+const TRUSTED_COMMENT_PREFIX: &str = "trusted comment: ";
+
+/// Finds the URL of a file within a release by its name, or uses the name as a URL.
+fn find_in_release(name: &str, release_files: &[File]) -> Result<String, String> {
+    if name.contains("://") {
+        return Ok(name.to_string());
+    }
+
+    release_files
+        .iter()
+        .find(|x| x.name == name)
+        .map(|x| x.url.clone())
+        .ok_or_else(|| format!("The release is missing {:?}, needed to verify it", name))
+}
+
+/// Works out where the checksums and signature for a file can be found, recording them
+/// against the file. Releases missing any of these are refused.
+pub fn find_files(
+    file: &mut File,
+    release_files: &[File],
+    verification: &PackageVerification,
+) -> Result<(), String> {
+    let checksums = match verification.checksums {
+        Some(ref v) => Some(find_in_release(
+            &v.replace(FILE_PLACEHOLDER, &file.name),
+            release_files,
+        )?),
+        None => None,
+    };
+
+    if verification.minisign_key.is_some() {
+        file.signature = Some(match (&checksums, &verification.checksums) {
+            (Some(url), Some(name)) if name.contains("://") => {
+                format!("{}{}", url, SIGNATURE_EXTENSION)
+            }
+            (Some(_), Some(name)) => find_in_release(
+                &format!(
+                    "{}{}",
+                    name.replace(FILE_PLACEHOLDER, &file.name),
+                    SIGNATURE_EXTENSION
+                ),
+                release_files,
+            )?,
+            _ => find_in_release(
+                &format!("{}{}", file.name, SIGNATURE_EXTENSION),
+                release_files,
+            )?,
+        });
+    }
+
+    file.checksums = checksums;
+
+    Ok(())
+}
+
+/// Finds the expected hash of a file within a `sha256sum` style manifest. Manifests
+/// containing only a single hash apply to whatever file they were published for.
+fn find_checksum(manifest: &str, name: &str) -> Result<String, String> {
+    let lines: Vec<&str> = manifest
+        .lines()
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty())
+        .collect();
+
+    if lines.len() == 1 && !lines[0].contains(char::is_whitespace) {
+        return Ok(lines[0].to_lowercase());
+    }
+
+    for line in lines {
+        let mut parts = line.splitn(2, char::is_whitespace);
+        let (hash, file_name) = match (parts.next(), parts.next()) {
+            (Some(hash), Some(file_name)) => (hash, file_name.trim()),
+            _ => continue,
+        };
+
+        // Binary mode entries are marked with a `*`
+        if file_name.trim_start_matches('*') == name {
+            return Ok(hash.to_lowercase());
+        }
+    }
+
+    Err(format!("The checksum manifest doesn't list {:?}", name))
+}
+
+/// Decodes a minisign public key (or signature line) from base64, checking its length.
+fn decode(text: &str, length: usize, what: &str) -> Result<Vec<u8>, String> {
+    let data = base64::decode(text.trim()).map_err(|x| format!("Invalid {}: {:?}", what, x))?;
+
+    if data.len() != length {
+        return Err(format!("Invalid {}: unexpected length", what));
+    }
+
+    Ok(data)
+}
+
+fn verify_ed25519(public_key: &[u8], message: &[u8], sig: &[u8]) -> bool {
+    signature::verify(
+        &signature::ED25519,
+        Input::from(public_key),
+        Input::from(message),
+        Input::from(sig),
+    )
+    .is_ok()
+}
+
+/// Checks a minisign signature of some data, as described at
+/// <https://jedisct1.github.io/minisign/>.
+fn verify_minisign(public_key: &str, signature_file: &str, data: &[u8]) -> Result<(), String> {
+    // Keys may be given either bare, or as the contents of a .pub file
+    let public_key = public_key
+        .lines()
+        .map(|x| x.trim())
+        .find(|x| !x.is_empty() && !x.starts_with("untrusted comment:"))
+        .ok_or("The minisign public key is empty")?;
+    let public_key = decode(public_key, 42, "minisign public key")?;
+    let (key_id, key) = (&public_key[2..10], &public_key[10..]);
+
+    let lines: Vec<&str> = signature_file.lines().collect();
+    if lines.len() < 4 || !lines[2].starts_with(TRUSTED_COMMENT_PREFIX) {
+        return Err("The signature is not a valid minisign signature".to_string());
+    }
+
+    let sig = decode(lines[1], 74, "minisign signature")?;
+    let (algorithm, sig_key_id, sig) = (&sig[0..2], &sig[2..10], &sig[10..]);
+
+    if sig_key_id != key_id {
+        return Err("The signature was made with a different key".to_string());
+    }
+
+    // Newer signatures sign a BLAKE2b hash of the data, rather than the data itself
+    let prehashed;
+    let message = match algorithm {
+        b"Ed" => data,
+        b"ED" => {
+            prehashed = blake2b(64, &[], data);
+            prehashed.as_bytes()
+        }
+        _ => return Err("The signature uses an unknown algorithm".to_string()),
+    };
+
+    if !verify_ed25519(key, message, sig) {
+        return Err("The signature doesn't match".to_string());
+    }
+
+    // The trusted comment is signed too, so check that it hasn't been tampered with
+    let trusted_comment = &lines[2][TRUSTED_COMMENT_PREFIX.len()..];
+    let global_sig = decode(lines[3], 64, "minisign global signature")?;
+    let mut global_message = sig.to_vec();
+    global_message.extend_from_slice(trusted_comment.as_bytes());
+
+    if !verify_ed25519(key, &global_message, &global_sig) {
+        return Err("The signature's trusted comment doesn't match".to_string());
+    }
+
+    info!("Verified signature ({})", trusted_comment);
+
+    Ok(())
+}
+
+/// Verifies a file downloaded to disk against the SHA-1 hash its source published for
+/// it, if there is one.
+pub fn verify_sha1(file: &File, path: &Path) -> Result<(), String> {
+    let expected = match file.sha1 {
+        Some(ref v) => to_hex(v),
+        None => return Ok(()),
+    };
+
+    let actual = sha1_file(path)?;
+    if expected != actual {
+        return Err(error_help::coded(
+            "verification_failed",
+            format!(
+                "Verification of {:?} failed: expected a SHA-1 hash of {}, but got {}. The \
+                 download may be corrupt, or may have been tampered with.",
+                file.name, expected, actual
+            ),
+        ));
+    }
+
+    info!("Verified the SHA-1 hash of {:?}", file.name);
+
+    Ok(())
+}
+
+/// Verifies a downloaded file against the checksums and signature found for it.
+pub fn verify_download(
+    file: &File,
+    data: &[u8],
+    verification: &PackageVerification,
+) -> Result<(), String> {
+    verify(file, verification, &|| Ok(sha256_hex(data)), &|| {
+        Ok(Cow::Borrowed(data))
+    })
+}
+
+/// Verifies a file downloaded to disk. It is only read into memory where there is no
+/// checksum, and the signature has to be checked against it directly.
+pub fn verify_download_file(
+    file: &File,
+    path: &Path,
+    verification: &PackageVerification,
+) -> Result<(), String> {
+    verify(file, verification, &|| sha256_file(path), &|| {
+        read(path)
+            .map(Cow::Owned)
+            .map_err(|x| format!("Unable to read {:?}: {:?}", path, x))
+    })
+}
+
+/// Verifies a download, given its SHA-256 hash and contents as they are needed.
+fn verify<'a>(
+    file: &File,
+    verification: &PackageVerification,
+    sha256: &dyn Fn() -> Result<String, String>,
+    contents: &dyn Fn() -> Result<Cow<'a, [u8]>, String>,
+) -> Result<(), String> {
+    let fail = |reason: String| {
+        Err(error_help::coded(
+            "verification_failed",
+            format!(
+                "Verification of {:?} failed: {}. The download may be corrupt, or may have \
+                 been tampered with.",
+                file.name, reason
+            ),
+        ))
+    };
+
+    let signature_file = match (&verification.minisign_key, &file.signature) {
+        (Some(key), Some(url)) => {
+            let signature = fetch_bytes(url, None, None)
+                .map_err(|x| format!("Failed to download signature: {}", x))?;
+            let signature = String::from_utf8(signature)
+                .map_err(|x| format!("Signature is not valid UTF-8: {:?}", x))?;
+            Some((key, signature))
+        }
+        (Some(_), None) => return fail("no signature was found".to_string()),
+        (None, _) => None,
+    };
+
+    match file.checksums {
+        Some(ref url) => {
+            let manifest = fetch_bytes(url, None, None)
+                .map_err(|x| format!("Failed to download checksums: {}", x))?;
+
+            if let Some((key, ref signature)) = signature_file {
+                if let Err(v) = verify_minisign(key, signature, &manifest) {
+                    return fail(format!("the checksum manifest is not trusted ({})", v));
+                }
+            }
+
+            let manifest = String::from_utf8(manifest)
+                .map_err(|x| format!("Checksums are not valid UTF-8: {:?}", x))?;
+
+            let expected = match find_checksum(&manifest, &file.name) {
+                Ok(v) => v,
+                Err(v) => return fail(v),
+            };
+            let actual = sha256()?;
+
+            if expected != actual {
+                return fail(format!(
+                    "expected a SHA-256 hash of {}, but got {}",
+                    expected, actual
+                ));
+            }
+        }
+        None => {
+            if let Some((key, ref signature)) = signature_file {
+                if let Err(v) = verify_minisign(key, signature, &contents()?) {
+                    return fail(v);
+                }
+            }
+        }
+    }
+
+    info!("Verified download of {:?}", file.name);
+
+    Ok(())
+}
@@ -0,0 +1,102 @@
+//! webhooks.rs
+//!
+//! Notifies configured webhooks of install events, so that the rollout of a release can be
+//! tracked without any telemetry infrastructure. Failures are logged, but never interrupt
+//! the installation.
+
+use chrono::Local;
+
+use reqwest::header::CONTENT_TYPE;
+
+use config::{Webhook, WebhookEvent};
+
+use hashing::hmac_sha256_hex;
+use http::build_client;
+use installer::HistoryEntry;
+
+/// Header containing the signature of the payload, if the webhook has a secret.
+const SIGNATURE_HEADER: &str = "X-Liftinstall-Signature";
+/// Header containing the event being sent.
+const EVENT_HEADER: &str = "X-Liftinstall-Event";
+
+/// The JSON body sent to webhooks.
+#[derive(Serialize, Clone, Debug)]
+pub struct WebhookPayload {
+    pub event: WebhookEvent,
+    pub product: String,
+    pub timestamp: String,
+    /// Packages being installed
+    pub packages: Vec<String>,
+    pub error: Option<String>,
+    /// Packages which changed version, for `update_applied`
+    pub updates: Vec<HistoryEntry>,
+}
+
+impl WebhookPayload {
+    pub fn new(event: WebhookEvent, product: &str, packages: &[String]) -> WebhookPayload {
+        WebhookPayload {
+            event,
+            product: product.to_string(),
+            timestamp: Local::now().to_rfc3339(),
+            packages: packages.to_vec(),
+            error: None,
+            updates: Vec::new(),
+        }
+    }
+}
+
+fn send(webhook: &Webhook, event: &str, body: &str) -> Result<(), String> {
+    let mut request = build_client()?
+        .post(&webhook.url)
+        .header(CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, event);
+
+    if let Some(ref secret) = webhook.secret {
+        let signature = hmac_sha256_hex(secret.as_bytes(), body.as_bytes());
+        request = request.header(SIGNATURE_HEADER, format!("sha256={}", signature));
+    }
+
+    let response = request
+        .body(body.to_string())
+        .send()
+        .map_err(|x| format!("Failed to send webhook: {:?}", x))?;
+
+    if !response.status().is_success() {
+        return Err(format!("Webhook responded with {:?}", response.status()));
+    }
+
+    Ok(())
+}
+
+/// Sends a payload to every webhook interested in its event.
+pub fn notify(webhooks: &[Webhook], payload: &WebhookPayload) {
+    let webhooks: Vec<&Webhook> = webhooks
+        .iter()
+        .filter(|x| x.events.is_empty() || x.events.contains(&payload.event))
+        .collect();
+
+    if webhooks.is_empty() {
+        return;
+    }
+
+    let body = match serde_json::to_string(payload) {
+        Ok(v) => v,
+        Err(v) => {
+            warn!("Failed to serialise webhook payload: {:?}", v);
+            return;
+        }
+    };
+
+    let event = serde_json::to_value(&payload.event)
+        .ok()
+        .and_then(|x| x.as_str().map(|x| x.to_string()))
+        .unwrap_or_default();
+
+    for webhook in webhooks {
+        info!("Sending {:?} to webhook {:?}", event, webhook.url);
+
+        if let Err(v) = send(webhook, &event, &body) {
+            warn!("Webhook {:?} failed: {}", webhook.url, v);
+        }
+    }
+}
@@ -1,6 +1,8 @@
 'use strict'
 
 const express = require('express')
+const fs = require('fs')
+const path = require('path')
 const app = express()
 const port = 3000
 
@@ -57,6 +59,30 @@ app.get('/api/attrs', (req, res) => {
   )
 })
 
+// The catalogs only hold sections of basic strings, so don't need a full TOML parser
+function loadStrings (locale) {
+  var text = fs.readFileSync(path.join(__dirname, '..', 'src', 'locales', locale + '.toml'), 'utf8')
+  var strings = {}
+  var section = ''
+  text.split('\n').forEach((line) => {
+    var header = line.match(/^\[(\w+)\]/)
+    var entry = line.match(/^(\w+) = (".*")$/)
+    if (header) {
+      section = header[1]
+    } else if (entry) {
+      strings[section + '.' + entry[1]] = JSON.parse(entry[2])
+    }
+  })
+  return strings
+}
+
+app.get('/api/strings', (req, res) => {
+  var locale = req.query.locale || 'en'
+  res.send(
+    `var strings = ${JSON.stringify({ locale: locale, strings: Object.assign(loadStrings('en'), loadStrings(locale)) })};`
+  )
+})
+
 app.get('/api/dark-mode', (req, res) => {
   res.json(false)
 })
@@ -75,6 +101,10 @@ app.get('/api/default-path', (req, res) => {
   res.json({ path: '/tmp/test/' })
 })
 
+app.post('/api/default-path', (req, res) => {
+  res.json({ path: '/tmp/test/' })
+})
+
 app.get('/api/config', (req, res) => {
   setTimeout(() => {
     returnConfig(res)
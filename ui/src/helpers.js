@@ -6,6 +6,16 @@
 
 var request_id = 0
 
+/**
+ * Resolves an API path relative to the page, as the server only serves beneath a
+ * per-session prefix.
+ *
+ * @param path The absolute path to resolve.
+ */
+function resolvePath (path) {
+  return path.replace(/^\//, '')
+}
+
 /**
  * Makes a AJAX request.
  *
@@ -33,7 +43,7 @@ export function ajax (path, successCallback, failCallback, data) {
   })
   req.addEventListener('error', failCallback)
 
-  req.open(data == null ? 'GET' : 'POST', path + '?nocache=' + request_id++, true)
+  req.open(data == null ? 'GET' : 'POST', resolvePath(path) + '?nocache=' + request_id++, true)
   // Rocket only currently supports URL encoded forms.
   req.setRequestHeader('Content-Type', 'application/x-www-form-urlencoded')
 
@@ -67,6 +77,7 @@ export function ajax (path, successCallback, failCallback, data) {
  * @param successCallback A callback with a raw text payload.
  * @param failCallback A fail callback. Optional.
  * @param data POST data. Optional.
+ * @returns The request, so that it can be aborted.
  */
 export function stream_ajax (path, callback, successCallback, failCallback, data) {
   var req = new XMLHttpRequest()
@@ -108,7 +119,7 @@ export function stream_ajax (path, callback, successCallback, failCallback, data
 
   req.addEventListener('error', failCallback)
 
-  req.open(data == null ? 'GET' : 'POST', path + '?nocache=' + request_id++, true)
+  req.open(data == null ? 'GET' : 'POST', resolvePath(path) + '?nocache=' + request_id++, true)
   // Rocket only currently supports URL encoded forms.
   req.setRequestHeader('Content-Type', 'application/x-www-form-urlencoded')
 
@@ -131,6 +142,8 @@ export function stream_ajax (path, callback, successCallback, failCallback, data
   } else {
     req.send()
   }
+
+  return req
 }
 
 /**
@@ -8,6 +8,24 @@ import 'buefy/dist/buefy.css'
 Vue.config.productionTip = false
 Vue.use(Buefy)
 
+// Looks up a string of the active locale (from /api/strings), filling in {name}
+// placeholders from args
+function translate (key, args) {
+  var text = strings.strings[key]
+  if (text === undefined) {
+    console.warn('No string for ' + key)
+    text = key
+  }
+
+  for (var name in args || {}) {
+    text = text.split('{' + name + '}').join(args[name])
+  }
+  return text
+}
+
+Vue.prototype.$t = translate
+document.documentElement.lang = strings.locale
+
 // Borrowed from http://tobyho.com/2012/07/27/taking-over-console-log/
 function intercept (method) {
   console[method] = function () {
@@ -62,6 +80,16 @@ function disable_shortcuts (e) {
   }
 }
 
+// Follows the OS's high contrast and reduced motion settings
+ajax('/api/accessibility', function (settings) {
+  if (settings.high_contrast) {
+    document.body.classList.add('is-high-contrast')
+  }
+  if (settings.reduced_motion) {
+    document.body.classList.add('is-reduced-motion')
+  }
+})
+
 // Check to see if we need to enable dark mode
 ajax('/api/dark-mode', function (enable) {
   if (enable) {
@@ -71,19 +99,87 @@ ajax('/api/dark-mode', function (enable) {
 
 window.addEventListener('keydown', disable_shortcuts)
 
+// Links without an href are used as buttons throughout, but can't be reached with the
+// keyboard unless they are given a tab stop
+function is_keyboard_button (element) {
+  return (element.tagName === 'A' && !element.hasAttribute('href')) ||
+    (element.classList && element.classList.contains('clickable-box'))
+}
+
+function make_keyboard_accessible () {
+  if (!this.$el || !this.$el.querySelectorAll) {
+    return
+  }
+
+  var elements = this.$el.querySelectorAll('a:not([href]), .clickable-box')
+  for (var i = 0; i < elements.length; i++) {
+    if (!elements[i].hasAttribute('tabindex')) {
+      elements[i].setAttribute('tabindex', '0')
+      elements[i].setAttribute('role', 'button')
+    }
+  }
+}
+
+Vue.mixin({
+  mounted: make_keyboard_accessible,
+  updated: make_keyboard_accessible
+})
+
+// Enter and space press whichever of these buttons has focus
+window.addEventListener('keydown', function (e) {
+  if ((e.keyCode === 13 || e.keyCode === 32) && is_keyboard_button(e.target)) {
+    e.preventDefault()
+    e.target.click()
+  }
+})
+
 document.getElementById('window-title').innerText =
-  base_attributes.name + ' Installer'
+  translate('native.window_title', { app_name: base_attributes.name })
 
 function selectFileCallback (name) {
   app.install_location = name
 }
 
+// Callbacks for the diagnostics bundle being exported, if any
+var diagnostics_callbacks = null
+
+window.diagnosticsFolderCallback = function (path) {
+  var callbacks = diagnostics_callbacks
+  diagnostics_callbacks = null
+
+  ajax('/api/diagnostics', function (result) {
+    callbacks.success(result.path)
+  }, callbacks.error, {
+    path: path
+  })
+}
+
+window.validateInstallDirCallback = function (result) {
+  if (result.error) {
+    app.install_location_error = result.error
+  } else {
+    app.install_location = result.path
+    app.install_location_error = null
+  }
+}
+
 var app = new Vue({
   router: router,
   data: {
     attrs: base_attributes,
     config: {},
     install_location: '',
+    // Where the installation goes unless the user picks somewhere else
+    default_install_location: '',
+    // Why a directory dropped onto the install location can't be used
+    install_location_error: null,
+    // What screen readers were last told about the running operation (see announce)
+    announcement: '',
+    urgent_announcement: '',
+    // If this is a machine-wide install, for every user of this machine
+    install_for_all_users: false,
+    // Installed packages to remove, leaving the rest of the installation in place
+    remove_packages: [],
     username: '',
     token: '',
     jwt_token: {},
@@ -93,6 +189,8 @@ var app = new Vue({
     has_reward_tier: false,
     // If the option to pick an install location should be provided
     show_install_location: true,
+    // If the application this installs into couldn't be found automatically
+    host_not_found: false,
     metadata: {
       database: [],
       install_path: '',
@@ -109,12 +207,13 @@ var app = new Vue({
         function () {},
         function (msg) {
           var search_location = app.metadata.install_path.length > 0 ? app.metadata.install_path :
-            "the location where this installer is";
+            translate('ui.exit_error_location');
 
-          app.$router.replace({ name: 'showerr', params: { msg: msg +
-                '\n\nPlease upload the log file (in ' + search_location + ') to ' +
-                'the ' + app.attrs.name + ' team'
-          }});
+          app.$router.replace({ name: 'showerr', params: { msg: translate('ui.exit_error', {
+            error: msg,
+            location: search_location,
+            app_name: app.attrs.name
+          })}});
         },
         {} // pass in nothing to cause `ajax` to post instead of get
       )
@@ -147,9 +246,66 @@ var app = new Vue({
       })
     },
 
+    // Asks where to save a diagnostics bundle for bug reports, then writes it there.
+    // `success` is given where the bundle was written. Nothing happens if no directory
+    // is chosen.
+    export_diagnostics: function (success, error) {
+      diagnostics_callbacks = {
+        success: success,
+        error: error || function () {}
+      }
+
+      window.external.invoke(JSON.stringify({
+        SelectInstallDir: {
+          callback_name: 'diagnosticsFolderCallback'
+        }
+      }))
+    },
+
+    // Progress milestones are read out by screen readers through live regions in App,
+    // and shown as native notifications while the window is in the background
+    watch_announcements: function () {
+      var app = this
+      stream_ajax('/api/events', function (line) {
+        if (!line.hasOwnProperty('Announcement')) {
+          return
+        }
+
+        var announcement = line.Announcement
+        if (announcement.urgent) {
+          app.urgent_announcement = announcement.message
+        } else {
+          app.announcement = announcement.message
+        }
+
+        if (has_external_interface && !document.hasFocus()) {
+          window.external.invoke(JSON.stringify({
+            Notify: {
+              title: app.attrs.name,
+              message: announcement.message
+            }
+          }))
+        }
+      }, function () {}, function () {})
+    },
+
+    // Debug builds of the installer reload their configuration as it is edited
+    watch_config: function () {
+      var app = this
+      stream_ajax('/api/config-changes', function () {
+        if (app.$route.name !== 'install') {
+          console.log('Configuration changed, reloading')
+          window.location.reload()
+        }
+      }, function () {}, function () {})
+    },
+
     ajax: ajax,
     stream_ajax: stream_ajax
   }
 }).$mount('#app')
 
+app.watch_config()
+app.watch_announcements()
+
 console.log("Vue started")
@@ -1,6 +1,9 @@
 module.exports = {
+  publicPath: './',
   devServer: {
     proxy: 'http://127.0.0.1:3000'
   },
-  filenameHashing: false
+  filenameHashing: false,
+  // Adds subresource integrity hashes to injected scripts and styles
+  integrity: true
 }